@@ -0,0 +1,48 @@
+//! Batch generation alongside interactive traffic
+//!
+//! [`UniversalBedrockClient::generate_batch`](crate::UniversalBedrockClient::generate_batch)
+//! sends many independent requests through the same pool, retry policy, and
+//! rate limits as interactive ones. Batch items share their own concurrency
+//! limit, kept below the pool size, so an offline evaluation run cannot take
+//! every client away from interactive requests.
+
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+/// Batch generation settings
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct BatchConfig {
+    /// Batch requests in flight at once, across all batches
+    ///
+    /// Capped at one less than the pool size, so at least one client is
+    /// always left for interactive requests.
+    #[validate(range(min = 1, max = 100))]
+    pub max_concurrency: usize,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self { max_concurrency: 2 }
+    }
+}
+
+impl BatchConfig {
+    /// Requests in flight at once with a pool of `pool_size` clients
+    pub fn effective_concurrency(&self, pool_size: usize) -> usize {
+        self.max_concurrency.min(pool_size.saturating_sub(1)).max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concurrency_leaves_room_for_interactive_requests() {
+        let config = BatchConfig { max_concurrency: 8 };
+        assert_eq!(config.effective_concurrency(5), 4);
+        assert_eq!(config.effective_concurrency(20), 8);
+        assert_eq!(config.effective_concurrency(1), 1);
+    }
+}
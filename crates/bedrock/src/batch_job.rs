@@ -0,0 +1,1085 @@
+//! Asynchronous batch inference jobs
+//!
+//! For large offline workloads Bedrock runs model invocation jobs at half
+//! the on-demand price: requests are written to S3 as JSONL, a job is
+//! submitted, and results appear next to the input once the job finishes,
+//! usually within hours. [`BatchJobManager`] does all three through a
+//! [`BatchJobStorage`] for the files and a [`BatchJobControl`] for the job,
+//! and streams results back as [`GenerationResponse`]s.
+//!
+//! With the `batch-jobs` feature, [`S3BatchStorage`] and
+//! [`HttpBatchJobControl`] talk to AWS directly. Jobs accept Anthropic
+//! models and text, tool use, and tool result content.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::{Stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info};
+
+use crate::config::GenerationConfig;
+use crate::error::{BedrockError, Result};
+use crate::message::{
+    GenerationRequest, GenerationResponse, MessageRole, TokenUsage, UniversalMessage,
+};
+use crate::tools::{ToolDefinition, ToolUse};
+
+/// Price of batch inference relative to on-demand
+pub const BATCH_PRICE_FACTOR: f64 = 0.5;
+
+/// Body version Anthropic models on Bedrock expect
+const ANTHROPIC_VERSION: &str = "bedrock-2023-05-31";
+
+/// Output tokens allowed when a request sets no limit; batch jobs need one
+const DEFAULT_MAX_TOKENS: usize = 4096;
+
+/// Where and how batch jobs run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchJobConfig {
+    /// IAM role Bedrock assumes to read input and write output
+    pub role_arn: String,
+    /// Bucket for job input and output
+    pub bucket: String,
+    /// Key prefix under which each job gets its own directory
+    #[serde(default)]
+    pub prefix: String,
+    /// How often [`BatchJobManager::wait`] checks on a job
+    #[serde(default = "default_poll_interval_seconds")]
+    pub poll_interval_seconds: u64,
+    /// Hours after which Bedrock stops an unfinished job; Bedrock's default
+    /// when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_hours: Option<u32>,
+}
+
+const fn default_poll_interval_seconds() -> u64 {
+    60
+}
+
+impl BatchJobConfig {
+    /// Run jobs as `role_arn` with files in `bucket`
+    pub fn new(role_arn: impl Into<String>, bucket: impl Into<String>) -> Self {
+        Self {
+            role_arn: role_arn.into(),
+            bucket: bucket.into(),
+            prefix: String::new(),
+            poll_interval_seconds: default_poll_interval_seconds(),
+            timeout_hours: None,
+        }
+    }
+
+    /// Keep job files under `prefix`
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Check on running jobs every `interval`
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval_seconds = interval.as_secs().max(1);
+        self
+    }
+
+    /// Stop jobs that have not finished after `hours`
+    pub fn with_timeout_hours(mut self, hours: u32) -> Self {
+        self.timeout_hours = Some(hours);
+        self
+    }
+
+    fn key(&self, path: &str) -> String {
+        match self.prefix.trim_matches('/') {
+            "" => path.to_string(),
+            prefix => format!("{prefix}/{path}"),
+        }
+    }
+
+    fn uri(&self, key: &str) -> String {
+        format!("s3://{}/{key}", self.bucket)
+    }
+}
+
+/// Lifecycle of a batch job, as Bedrock reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchJobStatus {
+    /// Accepted, not yet validated
+    Submitted,
+    /// Input being validated
+    Validating,
+    /// Waiting for capacity
+    Scheduled,
+    /// Records being processed
+    InProgress,
+    /// Every record processed
+    Completed,
+    /// Finished with some records failed
+    PartiallyCompleted,
+    /// The job failed
+    Failed,
+    /// Being stopped on request
+    Stopping,
+    /// Stopped on request
+    Stopped,
+    /// Timed out before finishing
+    Expired,
+}
+
+impl BatchJobStatus {
+    /// Whether the job will not change any more
+    pub const fn is_finished(self) -> bool {
+        matches!(
+            self,
+            Self::Completed
+                | Self::PartiallyCompleted
+                | Self::Failed
+                | Self::Stopped
+                | Self::Expired
+        )
+    }
+
+    /// Whether the job finished with results to read
+    pub const fn has_results(self) -> bool {
+        matches!(self, Self::Completed | Self::PartiallyCompleted)
+    }
+}
+
+impl std::fmt::Display for BatchJobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// A job's status and Bedrock's explanation of it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchJobState {
+    /// Where the job is in its lifecycle
+    pub status: BatchJobStatus,
+    /// Why the job failed or stopped, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Everything Bedrock needs to start a job
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchJobSpec {
+    /// Job name, unique in the account
+    pub job_name: String,
+    /// Model every record is sent to
+    pub model: String,
+    /// IAM role Bedrock assumes
+    pub role_arn: String,
+    /// S3 URI of the input JSONL
+    pub input_uri: String,
+    /// S3 URI of the directory results are written under
+    pub output_uri: String,
+    /// Hours after which an unfinished job is stopped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_hours: Option<u32>,
+}
+
+/// A submitted job
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchJob {
+    /// ARN identifying the job
+    pub job_arn: String,
+    /// What the job was started with
+    pub spec: BatchJobSpec,
+    /// Records in the job, one per request
+    pub record_count: usize,
+}
+
+impl BatchJob {
+    /// The ID at the end of the job ARN, which names its output directory
+    pub fn job_id(&self) -> &str {
+        self.job_arn.rsplit('/').next().unwrap_or(&self.job_arn)
+    }
+}
+
+/// The result of one request in a job
+#[derive(Debug)]
+pub struct BatchJobRecord {
+    /// Position of the request in the submitted batch
+    pub index: usize,
+    /// The model's response, or why the record failed
+    pub response: Result<GenerationResponse>,
+}
+
+/// Starts batch jobs and reports their status
+#[async_trait]
+pub trait BatchJobControl: Send + Sync {
+    /// Start a job, returning its ARN
+    async fn create_job(&self, spec: &BatchJobSpec) -> Result<String>;
+
+    /// Where the job with `job_arn` is in its lifecycle
+    async fn job_state(&self, job_arn: &str) -> Result<BatchJobState>;
+}
+
+/// Holds job input and output files, addressed by key within a bucket
+#[async_trait]
+pub trait BatchJobStorage: Send + Sync {
+    /// Write `body` to `key`
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<()>;
+
+    /// Read the file at `key`
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+
+    /// Keys of every file under `prefix`
+    async fn list(&self, prefix: &str) -> Result<Vec<String>>;
+}
+
+/// Submits batch jobs, waits for them, and reads their results
+#[derive(Clone)]
+pub struct BatchJobManager {
+    control: Arc<dyn BatchJobControl>,
+    storage: Arc<dyn BatchJobStorage>,
+    config: BatchJobConfig,
+}
+
+impl BatchJobManager {
+    /// Run jobs through `control` with files in `storage`
+    pub fn new(
+        control: Arc<dyn BatchJobControl>,
+        storage: Arc<dyn BatchJobStorage>,
+        config: BatchJobConfig,
+    ) -> Self {
+        Self {
+            control,
+            storage,
+            config,
+        }
+    }
+
+    /// Run jobs on AWS with credentials and region from `sdk_config`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `sdk_config` has no region or credentials.
+    #[cfg(feature = "batch-jobs")]
+    pub fn from_sdk_config(
+        sdk_config: &aws_config::SdkConfig,
+        config: BatchJobConfig,
+    ) -> Result<Self> {
+        let storage = S3BatchStorage::new(aws_sdk_s3::Client::new(sdk_config), &config.bucket);
+        Ok(Self::new(
+            Arc::new(HttpBatchJobControl::from_sdk_config(sdk_config)?),
+            Arc::new(storage),
+            config,
+        ))
+    }
+
+    /// Upload `requests` and start a job named `job_name` on them
+    ///
+    /// Every request must use the same Anthropic model. Bedrock rejects jobs
+    /// with fewer records than its per-model minimum, typically 100.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BedrockError::InvalidInput`] if `requests` is empty, mixes
+    /// models, or has content batch jobs cannot carry, and an error if the
+    /// upload or job creation fails.
+    pub async fn submit(&self, job_name: &str, requests: &[GenerationRequest]) -> Result<BatchJob> {
+        let (model, body) = encode_records(requests)?;
+        let input_key = self.config.key(&format!("{job_name}/input.jsonl"));
+        let output_key = self.config.key(&format!("{job_name}/output/"));
+        self.storage.put(&input_key, body).await?;
+
+        let spec = BatchJobSpec {
+            job_name: job_name.to_string(),
+            model,
+            role_arn: self.config.role_arn.clone(),
+            input_uri: self.config.uri(&input_key),
+            output_uri: self.config.uri(&output_key),
+            timeout_hours: self.config.timeout_hours,
+        };
+        let job_arn = self.control.create_job(&spec).await?;
+        info!(
+            "Submitted batch job {} with {} records to {}",
+            job_arn,
+            requests.len(),
+            spec.model
+        );
+        Ok(BatchJob {
+            job_arn,
+            spec,
+            record_count: requests.len(),
+        })
+    }
+
+    /// Where `job` is in its lifecycle
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the status cannot be fetched.
+    pub async fn state(&self, job: &BatchJob) -> Result<BatchJobState> {
+        self.control.job_state(&job.job_arn).await
+    }
+
+    /// Wait until `job` finishes
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BedrockError::RequestFailed`] if the job failed, stopped, or
+    /// expired, and an error if its status cannot be fetched.
+    pub async fn wait(&self, job: &BatchJob) -> Result<BatchJobState> {
+        let interval = Duration::from_secs(self.config.poll_interval_seconds.max(1));
+        loop {
+            let state = self.state(job).await?;
+            if state.status.has_results() {
+                return Ok(state);
+            }
+            if state.status.is_finished() {
+                return Err(BedrockError::RequestFailed(format!(
+                    "Batch job {} {}: {}",
+                    job.job_arn,
+                    state.status,
+                    state.message.as_deref().unwrap_or("no reason given")
+                )));
+            }
+            debug!("Batch job {} is {}", job.job_arn, state.status);
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Results of a finished `job`, file by file as they are read
+    ///
+    /// Records come in the order Bedrock wrote them; use
+    /// [`BatchJobRecord::index`] to match them to requests.
+    pub fn results<'a>(
+        &'a self,
+        job: &'a BatchJob,
+    ) -> impl Stream<Item = Result<BatchJobRecord>> + Send + 'a {
+        let prefix = self
+            .config
+            .key(&format!("{}/output/{}/", job.spec.job_name, job.job_id()));
+        futures::stream::once(async move { self.storage.list(&prefix).await })
+            .map_ok(move |keys| {
+                futures::stream::iter(keys.into_iter().filter(|key| key.ends_with(".jsonl.out")))
+                    .then(move |key| async move { self.storage.get(&key).await })
+                    .map_ok(move |body| {
+                        futures::stream::iter(decode_records(&body, &job.spec.model))
+                    })
+                    .try_flatten()
+            })
+            .try_flatten()
+    }
+}
+
+impl std::fmt::Debug for BatchJobManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BatchJobManager")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Encode `requests` as job input, returning their shared model and the JSONL
+fn encode_records(requests: &[GenerationRequest]) -> Result<(String, Vec<u8>)> {
+    let model = requests
+        .first()
+        .map(|request| request.model.clone())
+        .ok_or_else(|| BedrockError::InvalidInput("A batch job needs requests".to_string()))?;
+    if !model.contains("anthropic.") {
+        return Err(BedrockError::InvalidInput(format!(
+            "Batch jobs support Anthropic models, not {model}"
+        )));
+    }
+
+    let mut body = Vec::new();
+    for (index, request) in requests.iter().enumerate() {
+        if request.model != model {
+            return Err(BedrockError::InvalidInput(format!(
+                "A batch job uses one model, but request {index} uses {} rather than {model}",
+                request.model
+            )));
+        }
+        let record = json!({
+            "recordId": record_id(index),
+            "modelInput": model_input(request)?,
+        });
+        serde_json::to_writer(&mut body, &record)
+            .map_err(|e| BedrockError::InvalidInput(e.to_string()))?;
+        body.push(b'\n');
+    }
+    Ok((model, body))
+}
+
+/// Record IDs are the request's position, as the 11 characters Bedrock expects
+fn record_id(index: usize) -> String {
+    format!("{index:011}")
+}
+
+/// The InvokeModel body of `request` for an Anthropic model
+fn model_input(request: &GenerationRequest) -> Result<serde_json::Value> {
+    let config = request.config.as_ref();
+    let mut system: Vec<&str> = config
+        .and_then(|config| config.system_prompt.as_deref())
+        .into_iter()
+        .collect();
+    let mut messages = Vec::new();
+    let mut tools: Vec<ToolDefinition> = config.map(|c| c.tools.clone()).unwrap_or_default();
+    for message in &request.messages {
+        if message.role == MessageRole::System {
+            system.push(&message.content);
+        } else {
+            messages.push(anthropic_message(message)?);
+            tools.extend(message.tools());
+        }
+    }
+
+    let mut input = json!({
+        "anthropic_version": ANTHROPIC_VERSION,
+        "max_tokens": config.and_then(|c| c.max_tokens).unwrap_or(DEFAULT_MAX_TOKENS),
+        "messages": messages,
+    });
+    if !system.is_empty() {
+        input["system"] = json!(system.join("\n\n"));
+    }
+    if let Some(config) = config {
+        add_sampling(&mut input, config);
+    }
+    if !tools.is_empty() {
+        let mut seen = std::collections::HashSet::new();
+        input["tools"] = tools
+            .iter()
+            .filter(|tool| seen.insert(tool.name.clone()))
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "input_schema": tool.input_schema,
+                })
+            })
+            .collect();
+        if let Some(choice) = config.and_then(|c| c.tool_choice.as_ref()) {
+            input["tool_choice"] = json!(choice);
+        }
+    }
+    Ok(input)
+}
+
+fn add_sampling(input: &mut serde_json::Value, config: &GenerationConfig) {
+    if let Some(temperature) = config.temperature {
+        input["temperature"] = json!(temperature);
+    }
+    if let Some(top_p) = config.top_p {
+        input["top_p"] = json!(top_p);
+    }
+    if !config.stop_sequences.is_empty() {
+        input["stop_sequences"] = json!(config.stop_sequences);
+    }
+}
+
+fn anthropic_message(message: &UniversalMessage) -> Result<serde_json::Value> {
+    if !message.parts.is_empty() {
+        return Err(BedrockError::InvalidInput(
+            "Batch jobs do not carry images or documents".to_string(),
+        ));
+    }
+    let mut content = Vec::new();
+    if !message.content.is_empty() {
+        content.push(json!({ "type": "text", "text": message.content }));
+    }
+    for tool_use in &message.tool_uses {
+        content.push(json!({
+            "type": "tool_use",
+            "id": tool_use.id,
+            "name": tool_use.name,
+            "input": tool_use.input,
+        }));
+    }
+    for result in &message.tool_results {
+        let text = match &result.content {
+            serde_json::Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        content.push(json!({
+            "type": "tool_result",
+            "tool_use_id": result.tool_use_id,
+            "content": text,
+            "is_error": result.is_error,
+        }));
+    }
+    let role = if message.role == MessageRole::Assistant {
+        "assistant"
+    } else {
+        "user"
+    };
+    Ok(json!({ "role": role, "content": content }))
+}
+
+/// One line of a job's output file
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OutputRecord {
+    record_id: String,
+    #[serde(default)]
+    model_output: Option<ModelOutput>,
+    #[serde(default)]
+    error: Option<RecordError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelOutput {
+    #[serde(default)]
+    content: Vec<OutputBlock>,
+    #[serde(default)]
+    stop_reason: Option<String>,
+    #[serde(default)]
+    usage: Option<OutputUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OutputBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct OutputUsage {
+    input_tokens: usize,
+    output_tokens: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecordError {
+    #[serde(default)]
+    error_code: Option<serde_json::Value>,
+    #[serde(default)]
+    error_message: Option<String>,
+}
+
+/// Decode every line of an output file of a job run on `model`
+fn decode_records(body: &[u8], model: &str) -> Vec<Result<BatchJobRecord>> {
+    body.split(|&byte| byte == b'\n')
+        .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+        .map(|line| decode_record(line, model))
+        .collect()
+}
+
+fn decode_record(line: &[u8], model: &str) -> Result<BatchJobRecord> {
+    let record: OutputRecord = serde_json::from_slice(line)
+        .map_err(|e| BedrockError::InvalidResponse(format!("Invalid batch output line: {e}")))?;
+    let index = record.record_id.parse().map_err(|_| {
+        BedrockError::InvalidResponse(format!("Unknown batch record ID {}", record.record_id))
+    })?;
+
+    let response = match (record.model_output, record.error) {
+        (Some(output), None) => Ok(generation_response(output, model)),
+        (_, Some(error)) => Err(BedrockError::RequestFailed(format!(
+            "Batch record failed ({}): {}",
+            error
+                .error_code
+                .map_or_else(|| "no code".to_string(), |code| code.to_string()),
+            error.error_message.as_deref().unwrap_or("no message")
+        ))),
+        (None, None) => Err(BedrockError::InvalidResponse(
+            "Batch record has neither output nor error".to_string(),
+        )),
+    };
+    Ok(BatchJobRecord { index, response })
+}
+
+fn generation_response(output: ModelOutput, model: &str) -> GenerationResponse {
+    let mut response = GenerationResponse::builder(model);
+    let mut content = String::new();
+    for block in output.content {
+        match block {
+            OutputBlock::Text { text } => content.push_str(&text),
+            OutputBlock::ToolUse { id, name, input } => {
+                response = response.tool_use(ToolUse { id, name, input });
+            }
+            OutputBlock::Other => {}
+        }
+    }
+    if let Some(usage) = output.usage {
+        let cost = crate::calculate_cost(usage.input_tokens, usage.output_tokens, model)
+            * BATCH_PRICE_FACTOR;
+        response = response.usage(TokenUsage::new(
+            usage.input_tokens,
+            usage.output_tokens,
+            model,
+            cost,
+        ));
+    }
+    if let Some(reason) = output.stop_reason {
+        response = response.finish_reason(reason);
+    }
+    response.content(content).build()
+}
+
+/// Batch job files in an S3 bucket
+#[cfg(feature = "batch-jobs")]
+pub struct S3BatchStorage {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+}
+
+#[cfg(feature = "batch-jobs")]
+impl S3BatchStorage {
+    /// Store files in `bucket`
+    pub fn new(client: aws_sdk_s3::Client, bucket: impl Into<String>) -> Self {
+        Self {
+            client,
+            bucket: bucket.into(),
+        }
+    }
+}
+
+#[cfg(feature = "batch-jobs")]
+#[async_trait]
+impl BatchJobStorage for S3BatchStorage {
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type("application/jsonl")
+            .body(body.into())
+            .send()
+            .await
+            .map_err(|e| BedrockError::RequestFailed(format!("S3 put {key} failed: {e}")))?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| BedrockError::RequestFailed(format!("S3 get {key} failed: {e}")))?;
+        let body = object
+            .body
+            .collect()
+            .await
+            .map_err(|e| BedrockError::RequestFailed(format!("S3 read {key} failed: {e}")))?;
+        Ok(body.into_bytes().to_vec())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        let mut token = None;
+        loop {
+            let page = self
+                .client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .prefix(prefix)
+                .set_continuation_token(token)
+                .send()
+                .await
+                .map_err(|e| {
+                    BedrockError::RequestFailed(format!("S3 list {prefix} failed: {e}"))
+                })?;
+            keys.extend(
+                page.contents()
+                    .iter()
+                    .filter_map(|object| object.key().map(str::to_string)),
+            );
+            token = page.next_continuation_token().map(str::to_string);
+            if token.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+}
+
+/// Bedrock's model invocation job API, called over signed HTTPS
+///
+/// The runtime SDK has no job operations, so requests are signed with
+/// SigV4 and sent to the Bedrock control plane directly.
+#[cfg(feature = "batch-jobs")]
+pub struct HttpBatchJobControl {
+    http: reqwest::Client,
+    endpoint: url::Url,
+    region: String,
+    credentials: aws_credential_types::provider::SharedCredentialsProvider,
+}
+
+#[cfg(feature = "batch-jobs")]
+impl HttpBatchJobControl {
+    /// Call the Bedrock endpoint of `region` with `credentials`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BedrockError::Configuration`] if the endpoint URL is invalid.
+    pub fn new(
+        region: impl Into<String>,
+        credentials: aws_credential_types::provider::SharedCredentialsProvider,
+    ) -> Result<Self> {
+        let region = region.into();
+        let endpoint = format!("https://bedrock.{region}.amazonaws.com");
+        Ok(Self {
+            http: reqwest::Client::new(),
+            endpoint: url::Url::parse(&endpoint)
+                .map_err(|e| BedrockError::Configuration(format!("Invalid endpoint: {e}")))?,
+            region,
+            credentials,
+        })
+    }
+
+    /// Call Bedrock with the region and credentials of `sdk_config`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BedrockError::Configuration`] if `sdk_config` has no region
+    /// or credentials.
+    pub fn from_sdk_config(sdk_config: &aws_config::SdkConfig) -> Result<Self> {
+        let region = sdk_config
+            .region()
+            .ok_or_else(|| BedrockError::Configuration("No AWS region configured".to_string()))?;
+        let credentials = sdk_config.credentials_provider().ok_or_else(|| {
+            BedrockError::Configuration("No AWS credentials configured".to_string())
+        })?;
+        Self::new(region.to_string(), credentials)
+    }
+
+    /// Send requests to `endpoint` instead, e.g. a VPC endpoint
+    pub fn with_endpoint(mut self, endpoint: url::Url) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        path: &[&str],
+        body: Option<Vec<u8>>,
+    ) -> Result<serde_json::Value> {
+        use aws_credential_types::provider::ProvideCredentials as _;
+        use aws_sigv4::http_request::{sign, SignableBody, SignableRequest, SigningSettings};
+
+        let mut url = self.endpoint.clone();
+        url.path_segments_mut()
+            .map_err(|()| BedrockError::Configuration("Invalid endpoint".to_string()))?
+            .extend(path);
+        let body = body.unwrap_or_default();
+
+        let credentials = self
+            .credentials
+            .provide_credentials()
+            .await
+            .map_err(|e| BedrockError::Authentication(e.to_string()))?;
+        let identity = credentials.into();
+        let params = aws_sigv4::sign::v4::SigningParams::builder()
+            .identity(&identity)
+            .region(&self.region)
+            .name("bedrock")
+            .time(std::time::SystemTime::now())
+            .settings(SigningSettings::default())
+            .build()
+            .map_err(|e| BedrockError::Internal(e.to_string()))?
+            .into();
+        let headers = [("content-type", "application/json")];
+        let signable = SignableRequest::new(
+            method.as_str(),
+            url.as_str(),
+            headers.into_iter(),
+            SignableBody::Bytes(&body),
+        )
+        .map_err(|e| BedrockError::Internal(e.to_string()))?;
+        let (instructions, _) = sign(signable, &params)
+            .map_err(|e| BedrockError::Internal(e.to_string()))?
+            .into_parts();
+
+        let mut request = self
+            .http
+            .request(method, url.clone())
+            .header("content-type", "application/json")
+            .body(body);
+        for (name, value) in instructions.headers() {
+            request = request.header(name, value);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| BedrockError::RequestFailed(format!("{url} failed: {e}")))?;
+
+        let status = response.status();
+        let value: serde_json::Value = response.json().await.unwrap_or_default();
+        if status.is_success() {
+            return Ok(value);
+        }
+        let message = value["message"]
+            .as_str()
+            .map_or_else(|| status.to_string(), str::to_string);
+        Err(match status.as_u16() {
+            401 => BedrockError::Authentication(message),
+            403 => BedrockError::Authorization(message),
+            429 => BedrockError::RateLimited(message),
+            400..=499 => BedrockError::InvalidInput(message),
+            _ => BedrockError::ServiceError(message),
+        })
+    }
+}
+
+#[cfg(feature = "batch-jobs")]
+#[async_trait]
+impl BatchJobControl for HttpBatchJobControl {
+    async fn create_job(&self, spec: &BatchJobSpec) -> Result<String> {
+        let mut body = json!({
+            "jobName": spec.job_name,
+            "modelId": spec.model,
+            "roleArn": spec.role_arn,
+            "inputDataConfig": {
+                "s3InputDataConfig": { "s3Uri": spec.input_uri, "s3InputFormat": "JSONL" }
+            },
+            "outputDataConfig": { "s3OutputDataConfig": { "s3Uri": spec.output_uri } },
+        });
+        if let Some(hours) = spec.timeout_hours {
+            body["timeoutDurationInHours"] = json!(hours);
+        }
+        let body = serde_json::to_vec(&body).map_err(|e| BedrockError::Internal(e.to_string()))?;
+        let response = self
+            .send(reqwest::Method::POST, &["model-invocation-job"], Some(body))
+            .await?;
+        response["jobArn"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| BedrockError::InvalidResponse("No job ARN in response".to_string()))
+    }
+
+    async fn job_state(&self, job_arn: &str) -> Result<BatchJobState> {
+        let response = self
+            .send(
+                reqwest::Method::GET,
+                &["model-invocation-job", job_arn],
+                None,
+            )
+            .await?;
+        serde_json::from_value(response)
+            .map_err(|e| BedrockError::InvalidResponse(format!("Invalid job status: {e}")))
+    }
+}
+
+#[cfg(feature = "batch-jobs")]
+impl std::fmt::Debug for HttpBatchJobControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpBatchJobControl")
+            .field("endpoint", &self.endpoint.as_str())
+            .field("region", &self.region)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::Mutex;
+
+    use super::*;
+    use crate::DEFAULT_HAIKU_MODEL;
+
+    #[derive(Default)]
+    struct MemoryStorage {
+        files: Mutex<std::collections::BTreeMap<String, Vec<u8>>>,
+    }
+
+    #[async_trait]
+    impl BatchJobStorage for MemoryStorage {
+        async fn put(&self, key: &str, body: Vec<u8>) -> Result<()> {
+            self.files.lock().insert(key.to_string(), body);
+            Ok(())
+        }
+
+        async fn get(&self, key: &str) -> Result<Vec<u8>> {
+            self.files
+                .lock()
+                .get(key)
+                .cloned()
+                .ok_or_else(|| BedrockError::InvalidInput(format!("No file {key}")))
+        }
+
+        async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+            Ok(self
+                .files
+                .lock()
+                .keys()
+                .filter(|key| key.starts_with(prefix))
+                .cloned()
+                .collect())
+        }
+    }
+
+    /// Finishes every job on the second status check
+    #[derive(Default)]
+    struct InstantControl {
+        specs: Mutex<Vec<BatchJobSpec>>,
+        checks: Mutex<u32>,
+    }
+
+    #[async_trait]
+    impl BatchJobControl for InstantControl {
+        async fn create_job(&self, spec: &BatchJobSpec) -> Result<String> {
+            self.specs.lock().push(spec.clone());
+            Ok("arn:aws:bedrock:us-east-1:123:model-invocation-job/abc123".to_string())
+        }
+
+        async fn job_state(&self, _job_arn: &str) -> Result<BatchJobState> {
+            let mut checks = self.checks.lock();
+            *checks += 1;
+            let status = if *checks < 2 {
+                BatchJobStatus::InProgress
+            } else {
+                BatchJobStatus::PartiallyCompleted
+            };
+            Ok(BatchJobState {
+                status,
+                message: None,
+            })
+        }
+    }
+
+    fn request(text: &str) -> GenerationRequest {
+        GenerationRequest::new(DEFAULT_HAIKU_MODEL, vec![UniversalMessage::user(text)])
+    }
+
+    #[test]
+    fn test_records_encode_as_anthropic_bodies() {
+        let config = GenerationConfig {
+            system_prompt: Some("Be brief".to_string()),
+            stop_sequences: vec!["END".to_string()],
+            ..GenerationConfig::default()
+        };
+        let requests = [request("Hi").with_config(config), request("Bye")];
+        let (model, body) = encode_records(&requests).unwrap();
+        assert_eq!(model, DEFAULT_HAIKU_MODEL);
+
+        let lines: Vec<serde_json::Value> = body
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(lines[1]["recordId"], "00000000001");
+        let input = &lines[0]["modelInput"];
+        assert_eq!(input["anthropic_version"], ANTHROPIC_VERSION);
+        assert_eq!(input["system"], "Be brief");
+        assert_eq!(input["stop_sequences"], json!(["END"]));
+        assert_eq!(input["messages"][0]["content"][0]["text"], "Hi");
+        assert!(lines[1]["modelInput"].get("temperature").is_none());
+
+        let mixed = [request("Hi"), GenerationRequest::new("other", Vec::new())];
+        assert!(matches!(
+            encode_records(&mixed),
+            Err(BedrockError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_submit_wait_and_stream_results() {
+        let storage = Arc::new(MemoryStorage::default());
+        let control = Arc::new(InstantControl::default());
+        let config = BatchJobConfig::new("arn:aws:iam::123:role/batch", "evals")
+            .with_prefix("runs")
+            .with_poll_interval(Duration::from_millis(1));
+        let manager = BatchJobManager::new(control.clone(), storage.clone(), config);
+
+        let job = manager
+            .submit("nightly", &[request("Hi"), request("Bye")])
+            .await
+            .unwrap();
+        assert_eq!(job.spec.input_uri, "s3://evals/runs/nightly/input.jsonl");
+        assert_eq!(
+            control.specs.lock()[0].output_uri,
+            "s3://evals/runs/nightly/output/"
+        );
+        assert!(storage
+            .files
+            .lock()
+            .contains_key("runs/nightly/input.jsonl"));
+
+        let output = concat!(
+            r#"{"recordId":"00000000001","modelOutput":{"content":[{"type":"text","text":"Goodbye"}],"stop_reason":"end_turn","usage":{"input_tokens":10,"output_tokens":2}}}"#,
+            "\n",
+            r#"{"recordId":"00000000000","error":{"errorCode":400,"errorMessage":"Too long"}}"#,
+            "\n"
+        );
+        storage
+            .put(
+                "runs/nightly/output/abc123/input.jsonl.out",
+                output.as_bytes().to_vec(),
+            )
+            .await
+            .unwrap();
+        storage
+            .put(
+                "runs/nightly/output/abc123/manifest.json.out",
+                b"{}".to_vec(),
+            )
+            .await
+            .unwrap();
+
+        let state = manager.wait(&job).await.unwrap();
+        assert_eq!(state.status, BatchJobStatus::PartiallyCompleted);
+
+        let records: Vec<BatchJobRecord> = manager.results(&job).try_collect().await.unwrap();
+        assert_eq!(records.len(), 2);
+        let response = records[0].response.as_ref().unwrap();
+        assert_eq!(records[0].index, 1);
+        assert_eq!(response.content, "Goodbye");
+        assert_eq!(response.finish_reason, "end_turn");
+        let on_demand = crate::calculate_cost(10, 2, DEFAULT_HAIKU_MODEL);
+        assert!((response.estimated_cost() - on_demand * BATCH_PRICE_FACTOR).abs() < 1e-12);
+        assert_eq!(records[1].index, 0);
+        assert!(records[1].response.is_err());
+    }
+
+    #[cfg(feature = "batch-jobs")]
+    #[tokio::test]
+    async fn test_http_control_signs_and_parses() {
+        use aws_credential_types::{provider::SharedCredentialsProvider, Credentials};
+        use wiremock::matchers::{header_exists, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/model-invocation-job"))
+            .and(header_exists("authorization"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "jobArn": "arn:job/1" })),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/model-invocation-job/arn:job%2F1"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "status": "Failed", "message": "Bad role" })),
+            )
+            .mount(&server)
+            .await;
+
+        let credentials = Credentials::new("AKID", "secret", None, None, "test");
+        let control =
+            HttpBatchJobControl::new("us-east-1", SharedCredentialsProvider::new(credentials))
+                .unwrap()
+                .with_endpoint(server.uri().parse().unwrap());
+
+        let spec = BatchJobSpec {
+            job_name: "nightly".to_string(),
+            model: DEFAULT_HAIKU_MODEL.to_string(),
+            role_arn: "role".to_string(),
+            input_uri: "s3://b/in.jsonl".to_string(),
+            output_uri: "s3://b/out/".to_string(),
+            timeout_hours: Some(24),
+        };
+        let arn = control.create_job(&spec).await.unwrap();
+        assert_eq!(arn, "arn:job/1");
+        let state = control.job_state(&arn).await.unwrap();
+        assert_eq!(state.status, BatchJobStatus::Failed);
+        assert_eq!(state.message.as_deref(), Some("Bad role"));
+    }
+}
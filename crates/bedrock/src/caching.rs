@@ -0,0 +1,212 @@
+//! Prompt caching with Converse cache points
+//!
+//! Models that support prompt caching, such as Claude 3.5 Haiku and Claude
+//! 3.7 Sonnet, can reuse the processed prefix of a prompt across requests.
+//! A cache point marks where a cacheable prefix ends:
+//! [`GenerationConfig::cache_system_prompt`] places one after the system
+//! prompt and [`GenerationConfig::cache_messages`] after the first messages
+//! of the conversation. Tokens read from and written to the cache are
+//! reported in [`TokenUsage`](crate::TokenUsage). Models without prompt
+//! caching reject requests with cache points.
+//!
+//! A cached prefix expires a few minutes after its last use, so the first
+//! request after a quiet period pays for the whole prompt again.
+//! [`UniversalBedrockClient::warm_prompt_prefix`](crate::UniversalBedrockClient::warm_prompt_prefix)
+//! writes a system prompt to the cache ahead of traffic, and streamed
+//! requests record their time to first token by whether their prefix was
+//! warm, in [`FirstTokenLatency`](crate::FirstTokenLatency).
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+use aws_sdk_bedrockruntime::types::{
+    CachePointBlock, CachePointType, ContentBlock, Message as BedrockMessage, SystemContentBlock,
+};
+
+use parking_lot::Mutex;
+
+use crate::config::GenerationConfig;
+use crate::error::{BedrockError, Result};
+
+/// How long Bedrock keeps a cached prefix after its last use
+pub const PROMPT_CACHE_TTL: Duration = Duration::from_secs(300);
+
+fn cache_point() -> Result<CachePointBlock> {
+    CachePointBlock::builder()
+        .r#type(CachePointType::Default)
+        .build()
+        .map_err(|e| BedrockError::InvalidInput(format!("Invalid cache point: {}", e)))
+}
+
+/// The system blocks for a request: the system prompt, then a cache point if it is cached
+pub(crate) fn system_blocks(
+    config: Option<&GenerationConfig>,
+) -> Result<Option<Vec<SystemContentBlock>>> {
+    let Some(config) = config else {
+        return Ok(None);
+    };
+    let Some(system) = &config.system_prompt else {
+        return Ok(None);
+    };
+    let mut blocks = vec![SystemContentBlock::Text(system.clone())];
+    if config.cache_system_prompt {
+        blocks.push(SystemContentBlock::CachePoint(cache_point()?));
+    }
+    Ok(Some(blocks))
+}
+
+/// End the cached prefix of `messages` with a cache point
+///
+/// A prefix longer than the conversation caches all of it.
+pub(crate) fn mark_cached_prefix(
+    messages: &mut [BedrockMessage],
+    config: Option<&GenerationConfig>,
+) -> Result<()> {
+    let Some(count) = config.and_then(|config| config.cache_messages) else {
+        return Ok(());
+    };
+    let Some(last) = count
+        .min(messages.len())
+        .checked_sub(1)
+        .and_then(|index| messages.get_mut(index))
+    else {
+        return Ok(());
+    };
+    last.content.push(ContentBlock::CachePoint(cache_point()?));
+    Ok(())
+}
+
+/// Tracks which system prompts are in each model's prompt cache
+#[derive(Debug, Default)]
+pub(crate) struct PrefixTracker {
+    last_used: Mutex<HashMap<(String, u64), Instant>>,
+}
+
+impl PrefixTracker {
+    /// Note a request to `model` and report whether its cached prefix was warm
+    ///
+    /// Returns `None` for requests that do not cache their system prompt.
+    pub(crate) fn touch(&self, model: &str, config: Option<&GenerationConfig>) -> Option<bool> {
+        let key = (model.to_string(), prefix_hash(config)?);
+        let now = Instant::now();
+        let mut last_used = self.last_used.lock();
+        last_used.retain(|_, used| now.duration_since(*used) < PROMPT_CACHE_TTL);
+        Some(last_used.insert(key, now).is_some())
+    }
+
+    /// Whether `config`'s system prompt is still in `model`'s prompt cache
+    pub(crate) fn is_warm(&self, model: &str, config: &GenerationConfig) -> bool {
+        let Some(hash) = prefix_hash(Some(config)) else {
+            return false;
+        };
+        self.last_used
+            .lock()
+            .get(&(model.to_string(), hash))
+            .is_some_and(|used| used.elapsed() < PROMPT_CACHE_TTL)
+    }
+}
+
+/// Identity of the cached prefix of a request: its tools and system prompt
+fn prefix_hash(config: Option<&GenerationConfig>) -> Option<u64> {
+    let config = config.filter(|config| config.cache_system_prompt)?;
+    let system = config.system_prompt.as_ref()?;
+    let mut hasher = DefaultHasher::new();
+    for tool in &config.tools {
+        tool.name.hash(&mut hasher);
+        tool.description.hash(&mut hasher);
+        tool.input_schema.to_string().hash(&mut hasher);
+    }
+    system.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::UniversalMessage;
+
+    fn conversation() -> Vec<BedrockMessage> {
+        [
+            "Here is the manual: ...",
+            "Understood.",
+            "How do I reset it?",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(index, text)| {
+            let message = if index % 2 == 0 {
+                UniversalMessage::user(*text)
+            } else {
+                UniversalMessage::assistant(*text)
+            };
+            message.to_bedrock_message().unwrap()
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_system_prompt_cache_point() {
+        let config = GenerationConfig {
+            system_prompt: Some("You are a support agent.".to_string()),
+            ..GenerationConfig::default()
+        };
+        assert_eq!(system_blocks(Some(&config)).unwrap().unwrap().len(), 1);
+
+        let blocks = system_blocks(Some(&config.with_cached_system_prompt()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks[1].is_cache_point());
+        assert!(system_blocks(None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_message_prefix_cache_point() {
+        let cached = |count: Option<usize>| {
+            let mut messages = conversation();
+            let config = GenerationConfig {
+                cache_messages: count,
+                ..GenerationConfig::default()
+            };
+            mark_cached_prefix(&mut messages, Some(&config)).unwrap();
+            messages
+                .iter()
+                .map(|message| message.content.iter().any(ContentBlock::is_cache_point))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(cached(None), [false, false, false]);
+        assert_eq!(cached(Some(0)), [false, false, false]);
+        assert_eq!(cached(Some(2)), [false, true, false]);
+        assert_eq!(cached(Some(10)), [false, false, true]);
+    }
+
+    #[test]
+    fn test_prefix_tracker() {
+        let tracker = PrefixTracker::default();
+        let model = "anthropic.claude-3-5-haiku-20241022-v1:0";
+        let config = GenerationConfig {
+            system_prompt: Some("You are a support bot for ACME routers.".to_string()),
+            ..GenerationConfig::default()
+        }
+        .with_cached_system_prompt();
+
+        assert_eq!(tracker.touch(model, None), None);
+        assert_eq!(
+            tracker.touch(model, Some(&GenerationConfig::default())),
+            None
+        );
+        assert!(!tracker.is_warm(model, &config));
+        assert_eq!(tracker.touch(model, Some(&config)), Some(false));
+        assert!(tracker.is_warm(model, &config));
+        assert_eq!(tracker.touch(model, Some(&config)), Some(true));
+        assert_eq!(tracker.touch("other-model", Some(&config)), Some(false));
+
+        let edited = GenerationConfig {
+            system_prompt: Some("You are a sales bot.".to_string()),
+            ..config
+        };
+        assert!(!tracker.is_warm(model, &edited));
+    }
+}
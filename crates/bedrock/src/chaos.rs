@@ -0,0 +1,305 @@
+//! Fault injection for resilience testing
+//!
+//! When enabled, the injector adds latency, throttling errors, dropped
+//! stream chunks, and simulated pool exhaustion at configurable rates so
+//! that retry, circuit-breaker, and fallback behavior can be exercised
+//! before a real incident does it for you.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use validator::Validate;
+
+use crate::error::{BedrockError, Result};
+
+/// Fault injection configuration
+///
+/// All rates are probabilities in `0.0..=1.0` evaluated per request (or per
+/// chunk for `drop_chunk_rate`). Injection is a no-op unless `enabled` is set.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct ChaosConfig {
+    /// Master switch for fault injection
+    pub enabled: bool,
+
+    /// Probability of adding artificial latency to a request
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub latency_rate: f64,
+
+    /// Minimum injected latency in milliseconds
+    pub latency_min_ms: u64,
+
+    /// Maximum injected latency in milliseconds
+    pub latency_max_ms: u64,
+
+    /// Probability of failing a request with a throttling error
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub throttle_rate: f64,
+
+    /// Probability of silently dropping a stream chunk
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub drop_chunk_rate: f64,
+
+    /// Probability of failing a pool acquisition as if the pool were exhausted
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub pool_exhaustion_rate: f64,
+
+    /// Seed for the fault RNG, for reproducible chaos runs
+    pub seed: Option<u64>,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            latency_rate: 0.0,
+            latency_min_ms: 100,
+            latency_max_ms: 2000,
+            throttle_rate: 0.0,
+            drop_chunk_rate: 0.0,
+            pool_exhaustion_rate: 0.0,
+            seed: None,
+        }
+    }
+}
+
+impl ChaosConfig {
+    /// Create an enabled configuration with every rate at zero
+    pub fn enabled() -> Self {
+        Self {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    /// Set the latency injection rate and range
+    pub fn with_latency(mut self, rate: f64, min: Duration, max: Duration) -> Self {
+        self.latency_rate = rate;
+        self.latency_min_ms = min.as_millis() as u64;
+        self.latency_max_ms = max.as_millis() as u64;
+        self
+    }
+
+    /// Set the throttling error rate
+    pub fn with_throttle_rate(mut self, rate: f64) -> Self {
+        self.throttle_rate = rate;
+        self
+    }
+
+    /// Set the stream chunk drop rate
+    pub fn with_drop_chunk_rate(mut self, rate: f64) -> Self {
+        self.drop_chunk_rate = rate;
+        self
+    }
+
+    /// Set the simulated pool exhaustion rate
+    pub fn with_pool_exhaustion_rate(mut self, rate: f64) -> Self {
+        self.pool_exhaustion_rate = rate;
+        self
+    }
+
+    /// Set the RNG seed
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+}
+
+/// Counts of faults injected so far
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ChaosStats {
+    /// Requests delayed by injected latency
+    pub latency_injected: u64,
+    /// Requests failed with an injected throttling error
+    pub throttles_injected: u64,
+    /// Stream chunks dropped
+    pub chunks_dropped: u64,
+    /// Pool acquisitions failed with injected exhaustion
+    pub pool_exhaustions_injected: u64,
+}
+
+/// Injects faults according to a [`ChaosConfig`]
+pub struct FaultInjector {
+    config: ChaosConfig,
+    rng: Mutex<fastrand::Rng>,
+    latency_injected: AtomicU64,
+    throttles_injected: AtomicU64,
+    chunks_dropped: AtomicU64,
+    pool_exhaustions_injected: AtomicU64,
+}
+
+impl FaultInjector {
+    /// Create an injector from configuration
+    pub fn new(config: ChaosConfig) -> Self {
+        let rng = config
+            .seed
+            .map_or_else(fastrand::Rng::new, fastrand::Rng::with_seed);
+        Self {
+            config,
+            rng: Mutex::new(rng),
+            latency_injected: AtomicU64::new(0),
+            throttles_injected: AtomicU64::new(0),
+            chunks_dropped: AtomicU64::new(0),
+            pool_exhaustions_injected: AtomicU64::new(0),
+        }
+    }
+
+    /// Check whether fault injection is active
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Get the injector configuration
+    pub fn config(&self) -> &ChaosConfig {
+        &self.config
+    }
+
+    /// Apply request-level faults: latency first, then throttling
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BedrockError::RateLimited`] when a throttle is injected.
+    pub async fn before_request(&self) -> Result<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        if let Some(delay) = self.sample_latency() {
+            debug!("Chaos: injecting {:?} latency", delay);
+            self.latency_injected.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(delay).await;
+        }
+
+        if self.roll(self.config.throttle_rate) {
+            debug!("Chaos: injecting throttling error");
+            self.throttles_injected.fetch_add(1, Ordering::Relaxed);
+            return Err(BedrockError::RateLimited(
+                "injected by chaos mode".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Apply pool-level faults
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BedrockError::PoolExhausted`] when exhaustion is injected.
+    pub fn before_acquire(&self) -> Result<()> {
+        if self.config.enabled && self.roll(self.config.pool_exhaustion_rate) {
+            debug!("Chaos: injecting pool exhaustion");
+            self.pool_exhaustions_injected
+                .fetch_add(1, Ordering::Relaxed);
+            return Err(BedrockError::PoolExhausted(
+                "injected by chaos mode".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Decide whether the next stream chunk should be dropped
+    pub fn should_drop_chunk(&self) -> bool {
+        if self.config.enabled && self.roll(self.config.drop_chunk_rate) {
+            self.chunks_dropped.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+
+    /// Get counts of injected faults
+    pub fn stats(&self) -> ChaosStats {
+        ChaosStats {
+            latency_injected: self.latency_injected.load(Ordering::Relaxed),
+            throttles_injected: self.throttles_injected.load(Ordering::Relaxed),
+            chunks_dropped: self.chunks_dropped.load(Ordering::Relaxed),
+            pool_exhaustions_injected: self.pool_exhaustions_injected.load(Ordering::Relaxed),
+        }
+    }
+
+    fn roll(&self, rate: f64) -> bool {
+        rate > 0.0 && self.rng.lock().f64() < rate
+    }
+
+    fn sample_latency(&self) -> Option<Duration> {
+        if !self.roll(self.config.latency_rate) {
+            return None;
+        }
+        let min = self.config.latency_min_ms;
+        let max = self.config.latency_max_ms.max(min);
+        let ms = self.rng.lock().u64(min..=max);
+        Some(Duration::from_millis(ms))
+    }
+}
+
+impl Default for FaultInjector {
+    fn default() -> Self {
+        Self::new(ChaosConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_disabled_injector_is_noop() {
+        let injector = FaultInjector::new(ChaosConfig {
+            throttle_rate: 1.0,
+            pool_exhaustion_rate: 1.0,
+            drop_chunk_rate: 1.0,
+            ..Default::default()
+        });
+
+        assert!(injector.before_request().await.is_ok());
+        assert!(injector.before_acquire().is_ok());
+        assert!(!injector.should_drop_chunk());
+        assert_eq!(injector.stats(), ChaosStats::default());
+    }
+
+    #[tokio::test]
+    async fn test_certain_faults_are_injected() {
+        let injector = FaultInjector::new(
+            ChaosConfig::enabled()
+                .with_throttle_rate(1.0)
+                .with_pool_exhaustion_rate(1.0)
+                .with_drop_chunk_rate(1.0),
+        );
+
+        let err = injector.before_request().await.unwrap_err();
+        assert!(err.is_retryable());
+        assert!(matches!(
+            injector.before_acquire(),
+            Err(BedrockError::PoolExhausted(_))
+        ));
+        assert!(injector.should_drop_chunk());
+
+        let stats = injector.stats();
+        assert_eq!(stats.throttles_injected, 1);
+        assert_eq!(stats.pool_exhaustions_injected, 1);
+        assert_eq!(stats.chunks_dropped, 1);
+    }
+
+    #[test]
+    fn test_seeded_runs_are_reproducible() {
+        let config = ChaosConfig::enabled()
+            .with_drop_chunk_rate(0.5)
+            .with_seed(7);
+        let a = FaultInjector::new(config.clone());
+        let b = FaultInjector::new(config);
+
+        let run_a: Vec<bool> = (0..64).map(|_| a.should_drop_chunk()).collect();
+        let run_b: Vec<bool> = (0..64).map(|_| b.should_drop_chunk()).collect();
+        assert_eq!(run_a, run_b);
+        assert!(run_a.iter().any(|d| *d) && run_a.iter().any(|d| !*d));
+    }
+
+    #[test]
+    fn test_rates_are_validated() {
+        let config = ChaosConfig::enabled().with_throttle_rate(1.5);
+        assert!(config.validate().is_err());
+    }
+}
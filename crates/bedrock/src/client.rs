@@ -1,6 +1,7 @@
 //! High-level Bedrock client interface
 
 use async_trait::async_trait;
+#[cfg(feature = "mock-client")]
 use std::collections::HashMap;
 
 use crate::config::GenerationConfig;
@@ -38,6 +39,7 @@ pub trait BedrockClient: Send + Sync {
 #[cfg(feature = "mock-client")]
 pub struct MockBedrockClient {
     responses: HashMap<String, String>,
+    deterministic: bool,
 }
 
 #[cfg(feature = "mock-client")]
@@ -46,9 +48,55 @@ impl MockBedrockClient {
     pub fn new() -> Self {
         Self {
             responses: HashMap::new(),
+            deterministic: false,
         }
     }
 
+    /// Enable deterministic test mode
+    ///
+    /// Responses, IDs, and timestamps become a pure function of the model,
+    /// messages, system prompt, and seed, so snapshot tests of prompt
+    /// changes stay stable across runs.
+    pub fn deterministic(mut self) -> Self {
+        self.deterministic = true;
+        self
+    }
+
+    fn request_fingerprint(
+        model: &str,
+        messages: &[UniversalMessage],
+        config: Option<&GenerationConfig>,
+    ) -> u64 {
+        // FNV-1a: stable across runs and platforms, unlike `DefaultHasher`
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut feed = |bytes: &[u8]| {
+            for byte in bytes.iter().chain(&[0]) {
+                hash ^= u64::from(*byte);
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+
+        feed(model.as_bytes());
+        for message in messages {
+            feed(format!("{:?}", message.role).as_bytes());
+            feed(message.content.as_bytes());
+            for part in &message.parts {
+                feed(&serde_json::to_vec(part).unwrap_or_default());
+            }
+        }
+        if let Some(config) = config {
+            feed(
+                config
+                    .system_prompt
+                    .as_deref()
+                    .unwrap_or_default()
+                    .as_bytes(),
+            );
+            feed(&config.seed.unwrap_or_default().to_le_bytes());
+        }
+        hash
+    }
+
     /// Add a mock response for a model
     pub fn add_response(&mut self, model: &str, response: &str) {
         self.responses
@@ -62,24 +110,33 @@ impl BedrockClient for MockBedrockClient {
     async fn generate_text(
         &self,
         model: &str,
-        _messages: Vec<UniversalMessage>,
-        _config: Option<GenerationConfig>,
+        messages: Vec<UniversalMessage>,
+        config: Option<GenerationConfig>,
     ) -> Result<GenerationResponse> {
+        if self.deterministic {
+            let fingerprint = Self::request_fingerprint(model, &messages, config.as_ref());
+            let content =
+                self.responses.get(model).cloned().unwrap_or_else(|| {
+                    format!("Mock response from {} [{:016x}]", model, fingerprint)
+                });
+
+            return Ok(GenerationResponse::builder(model)
+                .id(uuid::Uuid::from_u64_pair(
+                    fingerprint,
+                    fingerprint.rotate_left(32),
+                ))
+                .content(content)
+                .timestamp(chrono::DateTime::<chrono::Utc>::UNIX_EPOCH)
+                .build());
+        }
+
         let content = self
             .responses
             .get(model)
             .cloned()
             .unwrap_or_else(|| format!("Mock response from {}", model));
 
-        Ok(GenerationResponse {
-            id: uuid::Uuid::new_v4(),
-            content,
-            model: model.to_string(),
-            usage: None,
-            metadata: HashMap::new(),
-            timestamp: chrono::Utc::now(),
-            finish_reason: "stop".to_string(),
-        })
+        Ok(GenerationResponse::builder(model).content(content).build())
     }
 
     async fn stream_text(
@@ -120,3 +177,40 @@ impl Default for MockBedrockClient {
         Self::new()
     }
 }
+
+#[cfg(all(test, feature = "mock-client"))]
+mod tests {
+    use super::*;
+
+    fn user(content: &str) -> UniversalMessage {
+        UniversalMessage::user(content)
+    }
+
+    #[tokio::test]
+    async fn test_deterministic_mode_is_stable() {
+        let client = MockBedrockClient::new().deterministic();
+        let config = GenerationConfig::deterministic().with_seed(1);
+
+        let a = client
+            .generate_text("mock-model-1", vec![user("hi")], Some(config.clone()))
+            .await
+            .unwrap();
+        let b = client
+            .generate_text("mock-model-1", vec![user("hi")], Some(config))
+            .await
+            .unwrap();
+        assert_eq!(a.id, b.id);
+        assert_eq!(a.content, b.content);
+        assert_eq!(a.timestamp, b.timestamp);
+
+        let c = client
+            .generate_text(
+                "mock-model-1",
+                vec![user("hi")],
+                Some(GenerationConfig::deterministic().with_seed(2)),
+            )
+            .await
+            .unwrap();
+        assert_ne!(a.content, c.content);
+    }
+}
@@ -6,6 +6,14 @@ use aws_sdk_bedrockruntime::config::Region;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
+use crate::batch::BatchConfig;
+use crate::chaos::ChaosConfig;
+use crate::model::ClaudeModel;
+use crate::probe::ProbeConfig;
+use crate::ratelimit::RateLimitConfig;
+use crate::region::FailoverConfig;
+use crate::tools::{ToolChoice, ToolDefinition};
+
 /// Configuration for the Bedrock client
 #[derive(Debug, Clone, Serialize, Validate)]
 pub struct BedrockConfig {
@@ -46,6 +54,40 @@ pub struct BedrockConfig {
 
     /// Enable request/response logging
     pub enable_logging: bool,
+
+    /// Fault injection settings for resilience testing
+    #[validate(nested)]
+    pub chaos: ChaosConfig,
+
+    /// What to do when the requested model lacks a needed feature
+    pub capability_routing: CapabilityRouting,
+
+    /// Regions to fail over between
+    #[validate(nested)]
+    pub failover: FailoverConfig,
+
+    /// Probing of which features each model actually supports
+    #[validate(nested)]
+    pub probes: ProbeConfig,
+
+    /// Client-side requests and tokens per minute by model
+    #[validate(nested)]
+    pub rate_limits: RateLimitConfig,
+
+    /// Concurrency of batch generation
+    #[validate(nested)]
+    pub batch: BatchConfig,
+}
+
+/// Policy for requests the chosen model cannot serve
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CapabilityRouting {
+    /// Fail with [`BedrockError::UnsupportedFeature`](crate::BedrockError::UnsupportedFeature)
+    #[default]
+    Strict,
+    /// Send the request to the cheapest capable model instead
+    Reroute,
 }
 
 impl Default for BedrockConfig {
@@ -61,6 +103,12 @@ impl Default for BedrockConfig {
             max_concurrent_requests: 100,
             enable_metrics: true,
             enable_logging: false,
+            chaos: ChaosConfig::default(),
+            capability_routing: CapabilityRouting::default(),
+            failover: FailoverConfig::default(),
+            probes: ProbeConfig::default(),
+            rate_limits: RateLimitConfig::default(),
+            batch: BatchConfig::default(),
         }
     }
 }
@@ -96,6 +144,42 @@ impl BedrockConfig {
         self
     }
 
+    /// Set the fault injection configuration
+    pub fn with_chaos(mut self, chaos: ChaosConfig) -> Self {
+        self.chaos = chaos;
+        self
+    }
+
+    /// Set the policy for requests the chosen model cannot serve
+    pub fn with_capability_routing(mut self, routing: CapabilityRouting) -> Self {
+        self.capability_routing = routing;
+        self
+    }
+
+    /// Set the regions to fail over between
+    pub fn with_failover(mut self, failover: FailoverConfig) -> Self {
+        self.failover = failover;
+        self
+    }
+
+    /// Set when and which models to probe for supported features
+    pub fn with_probes(mut self, probes: ProbeConfig) -> Self {
+        self.probes = probes;
+        self
+    }
+
+    /// Set the requests and tokens per minute allowed for each model
+    pub fn with_rate_limits(mut self, rate_limits: RateLimitConfig) -> Self {
+        self.rate_limits = rate_limits;
+        self
+    }
+
+    /// Set how many batch requests may be in flight at once
+    pub fn with_batch(mut self, batch: BatchConfig) -> Self {
+        self.batch = batch;
+        self
+    }
+
     /// Create a high-performance configuration
     pub fn high_performance() -> Self {
         Self {
@@ -156,6 +240,44 @@ pub struct GenerationConfig {
 
     /// System prompt
     pub system_prompt: Option<String>,
+
+    /// Sampling seed for reproducible generations
+    ///
+    /// Only forwarded to models that accept a seed; see
+    /// [`ModelCapabilities::supports_seed`](crate::ModelCapabilities::supports_seed).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+
+    /// Tools the model may call
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolDefinition>,
+
+    /// How the model chooses among [`tools`](Self::tools); the model decides when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+
+    /// Guardrail ID or ARN to apply; needs [`guardrail_version`](Self::guardrail_version)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guardrail_identifier: Option<String>,
+
+    /// Guardrail version, e.g. `1` or `DRAFT`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guardrail_version: Option<String>,
+
+    /// Cache the system prompt for reuse by later requests
+    #[serde(default)]
+    pub cache_system_prompt: bool,
+
+    /// Cache this many leading messages of the conversation for reuse by later requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_messages: Option<usize>,
+
+    /// Sequences that end generation when the model produces them
+    ///
+    /// The sequence itself is left out of the response, streamed or not,
+    /// and the finish reason is `stop_sequence`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop_sequences: Vec<String>,
 }
 
 impl Default for GenerationConfig {
@@ -165,6 +287,14 @@ impl Default for GenerationConfig {
             temperature: Some(0.7),
             top_p: Some(0.9),
             system_prompt: None,
+            seed: None,
+            tools: Vec::new(),
+            tool_choice: None,
+            guardrail_identifier: None,
+            guardrail_version: None,
+            cache_system_prompt: false,
+            cache_messages: None,
+            stop_sequences: Vec::new(),
         }
     }
 }
@@ -180,6 +310,14 @@ impl GenerationConfig {
                 "You are an expert programmer. Provide clean, efficient, and well-documented code."
                     .to_string(),
             ),
+            seed: None,
+            tools: Vec::new(),
+            tool_choice: None,
+            guardrail_identifier: None,
+            guardrail_version: None,
+            cache_system_prompt: false,
+            cache_messages: None,
+            stop_sequences: Vec::new(),
         }
     }
 
@@ -192,6 +330,14 @@ impl GenerationConfig {
             system_prompt: Some(
                 "You are a creative writer. Be imaginative and engaging.".to_string(),
             ),
+            seed: None,
+            tools: Vec::new(),
+            tool_choice: None,
+            guardrail_identifier: None,
+            guardrail_version: None,
+            cache_system_prompt: false,
+            cache_messages: None,
+            stop_sequences: Vec::new(),
         }
     }
 
@@ -204,6 +350,14 @@ impl GenerationConfig {
             system_prompt: Some(
                 "You are an expert analyst. Provide thorough, objective analysis.".to_string(),
             ),
+            seed: None,
+            tools: Vec::new(),
+            tool_choice: None,
+            guardrail_identifier: None,
+            guardrail_version: None,
+            cache_system_prompt: false,
+            cache_messages: None,
+            stop_sequences: Vec::new(),
         }
     }
 
@@ -214,8 +368,73 @@ impl GenerationConfig {
             temperature: Some(0.0),
             top_p: Some(1.0),
             system_prompt: None,
+            seed: None,
+            tools: Vec::new(),
+            tool_choice: None,
+            guardrail_identifier: None,
+            guardrail_version: None,
+            cache_system_prompt: false,
+            cache_messages: None,
+            stop_sequences: Vec::new(),
         }
     }
+
+    /// Set the sampling seed
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Offer tools to the model
+    pub fn with_tools(mut self, tools: Vec<ToolDefinition>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    /// Set how the model chooses among the offered tools
+    pub fn with_tool_choice(mut self, choice: ToolChoice) -> Self {
+        self.tool_choice = Some(choice);
+        self
+    }
+
+    /// Apply a guardrail to the request and the model's reply
+    pub fn with_guardrail(
+        mut self,
+        identifier: impl Into<String>,
+        version: impl Into<String>,
+    ) -> Self {
+        self.guardrail_identifier = Some(identifier.into());
+        self.guardrail_version = Some(version.into());
+        self
+    }
+
+    /// Cache the system prompt so requests sharing it are cheaper and faster
+    pub fn with_cached_system_prompt(mut self) -> Self {
+        self.cache_system_prompt = true;
+        self
+    }
+
+    /// Cache the first `count` messages, such as a long document shared up front
+    pub fn with_cached_messages(mut self, count: usize) -> Self {
+        self.cache_messages = Some(count);
+        self
+    }
+
+    /// End generation at any of `sequences`
+    pub fn with_stop_sequences(mut self, sequences: Vec<String>) -> Self {
+        self.stop_sequences = sequences;
+        self
+    }
+
+    /// Provider-specific request fields that the Converse API has no slot for
+    ///
+    /// Returns `None` when there is nothing to forward, including when a seed
+    /// is set but the model is known not to accept one.
+    pub fn additional_model_fields(&self, model: &str) -> Option<serde_json::Value> {
+        let seed = self.seed?;
+        let supported = ClaudeModel::from_id(model).is_none_or(|m| m.capabilities().supports_seed);
+        supported.then(|| serde_json::json!({ "seed": seed }))
+    }
 }
 
 #[cfg(test)]
@@ -248,6 +467,24 @@ mod tests {
         assert_eq!(deterministic_config.temperature, Some(0.0));
     }
 
+    #[test]
+    fn test_seed_forwarded_only_when_supported() {
+        let config = GenerationConfig::deterministic().with_seed(42);
+
+        assert_eq!(
+            config.additional_model_fields("mistral.mistral-large-2402-v1:0"),
+            Some(serde_json::json!({ "seed": 42 }))
+        );
+        assert_eq!(
+            config.additional_model_fields(ClaudeModel::Claude3Haiku.id()),
+            None
+        );
+        assert_eq!(
+            GenerationConfig::default().additional_model_fields("mistral.mistral-large-2402-v1:0"),
+            None
+        );
+    }
+
     #[test]
     fn test_config_builder_pattern() {
         let config = BedrockConfig::default()
@@ -259,4 +496,18 @@ mod tests {
         assert_eq!(config.pool_size, 10);
         assert!(!config.enable_metrics);
     }
+
+    #[test]
+    fn test_failover_config_is_validated() {
+        let config = BedrockConfig::default()
+            .with_failover(FailoverConfig::default().with_failure_threshold(0));
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_chaos_config_is_validated() {
+        let config =
+            BedrockConfig::default().with_chaos(ChaosConfig::enabled().with_drop_chunk_rate(2.0));
+        assert!(config.validate().is_err());
+    }
 }
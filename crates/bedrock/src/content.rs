@@ -0,0 +1,348 @@
+//! Multimodal content blocks for [`UniversalMessage`](crate::UniversalMessage)
+//!
+//! A message's text is followed by any number of [`ContentPart`]s: more text,
+//! images, or documents. Media is sent to Bedrock inline, so a
+//! [`MediaSource::Url`] must be a base64 `data:` URL; fetch remote files and
+//! pass their bytes instead.
+
+use aws_sdk_bedrockruntime::types::{
+    ContentBlock, DocumentBlock, DocumentFormat as AwsDocumentFormat, DocumentSource, ImageBlock,
+    ImageFormat as AwsImageFormat, ImageSource,
+};
+use aws_smithy_types::Blob;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BedrockError, Result};
+
+/// Image encodings accepted by vision models
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    /// PNG
+    Png,
+    /// JPEG
+    Jpeg,
+    /// GIF
+    Gif,
+    /// WebP
+    Webp,
+}
+
+impl ImageFormat {
+    /// The format for a MIME type such as `image/png`
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        match mime_type.trim().to_ascii_lowercase().as_str() {
+            "image/png" => Some(Self::Png),
+            "image/jpeg" | "image/jpg" => Some(Self::Jpeg),
+            "image/gif" => Some(Self::Gif),
+            "image/webp" => Some(Self::Webp),
+            _ => None,
+        }
+    }
+
+    fn to_bedrock(self) -> AwsImageFormat {
+        match self {
+            Self::Png => AwsImageFormat::Png,
+            Self::Jpeg => AwsImageFormat::Jpeg,
+            Self::Gif => AwsImageFormat::Gif,
+            Self::Webp => AwsImageFormat::Webp,
+        }
+    }
+
+    fn from_bedrock(format: &AwsImageFormat) -> Option<Self> {
+        match format {
+            AwsImageFormat::Png => Some(Self::Png),
+            AwsImageFormat::Jpeg => Some(Self::Jpeg),
+            AwsImageFormat::Gif => Some(Self::Gif),
+            AwsImageFormat::Webp => Some(Self::Webp),
+            _ => None,
+        }
+    }
+}
+
+/// Document encodings accepted by the Converse API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentFormat {
+    /// PDF
+    Pdf,
+    /// Comma-separated values
+    Csv,
+    /// Word 97-2003
+    Doc,
+    /// Word
+    Docx,
+    /// Excel 97-2003
+    Xls,
+    /// Excel
+    Xlsx,
+    /// HTML
+    Html,
+    /// Plain text
+    Txt,
+    /// Markdown
+    Md,
+}
+
+impl DocumentFormat {
+    fn to_bedrock(self) -> AwsDocumentFormat {
+        match self {
+            Self::Pdf => AwsDocumentFormat::Pdf,
+            Self::Csv => AwsDocumentFormat::Csv,
+            Self::Doc => AwsDocumentFormat::Doc,
+            Self::Docx => AwsDocumentFormat::Docx,
+            Self::Xls => AwsDocumentFormat::Xls,
+            Self::Xlsx => AwsDocumentFormat::Xlsx,
+            Self::Html => AwsDocumentFormat::Html,
+            Self::Txt => AwsDocumentFormat::Txt,
+            Self::Md => AwsDocumentFormat::Md,
+        }
+    }
+
+    fn from_bedrock(format: &AwsDocumentFormat) -> Option<Self> {
+        match format {
+            AwsDocumentFormat::Pdf => Some(Self::Pdf),
+            AwsDocumentFormat::Csv => Some(Self::Csv),
+            AwsDocumentFormat::Doc => Some(Self::Doc),
+            AwsDocumentFormat::Docx => Some(Self::Docx),
+            AwsDocumentFormat::Xls => Some(Self::Xls),
+            AwsDocumentFormat::Xlsx => Some(Self::Xlsx),
+            AwsDocumentFormat::Html => Some(Self::Html),
+            AwsDocumentFormat::Txt => Some(Self::Txt),
+            AwsDocumentFormat::Md => Some(Self::Md),
+            _ => None,
+        }
+    }
+}
+
+/// Where the bytes of an image or document come from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MediaSource {
+    /// Raw bytes, base64-encoded when serialized
+    Bytes {
+        /// File contents
+        #[serde(with = "base64_bytes")]
+        data: Vec<u8>,
+    },
+    /// A base64 `data:` URL
+    Url {
+        /// The URL
+        url: String,
+    },
+}
+
+impl MediaSource {
+    /// The bytes to send, decoding `data:` URLs
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BedrockError::InvalidInput`] for URLs that are not base64 `data:` URLs.
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        match self {
+            Self::Bytes { data } => Ok(data.clone()),
+            Self::Url { url } => {
+                let (_, data) = parse_data_url(url)?;
+                base64::engine::general_purpose::STANDARD
+                    .decode(data)
+                    .map_err(|e| BedrockError::InvalidInput(format!("Invalid data URL: {}", e)))
+            }
+        }
+    }
+}
+
+/// One block of message content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    /// Text
+    Text {
+        /// The text
+        text: String,
+    },
+    /// An image, for vision-capable models
+    Image {
+        /// Encoding
+        format: ImageFormat,
+        /// Contents
+        source: MediaSource,
+    },
+    /// A document the model can read
+    Document {
+        /// Encoding
+        format: DocumentFormat,
+        /// Name shown to the model
+        name: String,
+        /// Contents
+        source: MediaSource,
+    },
+}
+
+impl ContentPart {
+    /// A text block
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::Text { text: text.into() }
+    }
+
+    /// An image from raw bytes
+    pub fn image(data: impl Into<Vec<u8>>, format: ImageFormat) -> Self {
+        Self::Image {
+            format,
+            source: MediaSource::Bytes { data: data.into() },
+        }
+    }
+
+    /// An image from a base64 `data:` URL, taking the format from its MIME type
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BedrockError::InvalidInput`] if the URL is not a base64
+    /// `data:` URL of a supported image type.
+    pub fn image_url(url: impl Into<String>) -> Result<Self> {
+        let url = url.into();
+        let (mime_type, _) = parse_data_url(&url)?;
+        let format = ImageFormat::from_mime_type(mime_type).ok_or_else(|| {
+            BedrockError::InvalidInput(format!("Unsupported image type: {}", mime_type))
+        })?;
+        Ok(Self::Image {
+            format,
+            source: MediaSource::Url { url },
+        })
+    }
+
+    /// A document from raw bytes
+    pub fn document(
+        name: impl Into<String>,
+        data: impl Into<Vec<u8>>,
+        format: DocumentFormat,
+    ) -> Self {
+        Self::Document {
+            format,
+            name: name.into(),
+            source: MediaSource::Bytes { data: data.into() },
+        }
+    }
+
+    /// Whether this is an image
+    pub fn is_image(&self) -> bool {
+        matches!(self, Self::Image { .. })
+    }
+
+    /// Convert to a Bedrock content block
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BedrockError::InvalidInput`] if the media cannot be decoded
+    /// or the block is incomplete.
+    pub fn to_bedrock(&self) -> Result<ContentBlock> {
+        Ok(match self {
+            Self::Text { text } => ContentBlock::Text(text.clone()),
+            Self::Image { format, source } => ContentBlock::Image(
+                ImageBlock::builder()
+                    .format(format.to_bedrock())
+                    .source(ImageSource::Bytes(Blob::new(source.bytes()?)))
+                    .build()
+                    .map_err(|e| BedrockError::InvalidInput(format!("Invalid image: {}", e)))?,
+            ),
+            Self::Document {
+                format,
+                name,
+                source,
+            } => ContentBlock::Document(
+                DocumentBlock::builder()
+                    .format(format.to_bedrock())
+                    .name(name)
+                    .source(DocumentSource::Bytes(Blob::new(source.bytes()?)))
+                    .build()
+                    .map_err(|e| BedrockError::InvalidInput(format!("Invalid document: {}", e)))?,
+            ),
+        })
+    }
+
+    /// Convert an image or document block from Bedrock; other blocks give `None`
+    pub fn from_bedrock(block: &ContentBlock) -> Option<Self> {
+        match block {
+            ContentBlock::Image(image) => {
+                let format = ImageFormat::from_bedrock(image.format())?;
+                let data = image.source()?.as_bytes().ok()?.as_ref().to_vec();
+                Some(Self::image(data, format))
+            }
+            ContentBlock::Document(document) => {
+                let format = DocumentFormat::from_bedrock(document.format())?;
+                let data = document.source()?.as_bytes().ok()?.as_ref().to_vec();
+                Some(Self::document(document.name(), data, format))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Split a base64 `data:` URL into its MIME type and payload
+fn parse_data_url(url: &str) -> Result<(&str, &str)> {
+    url.strip_prefix("data:")
+        .and_then(|rest| rest.split_once(','))
+        .and_then(|(header, data)| Some((header.strip_suffix(";base64")?, data)))
+        .ok_or_else(|| {
+            BedrockError::InvalidInput(
+                "Only base64 data: URLs can be sent; fetch other URLs and pass the bytes"
+                    .to_string(),
+            )
+        })
+}
+
+mod base64_bytes {
+    use base64::Engine as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIXEL: &[u8] = &[0x89, b'P', b'N', b'G'];
+
+    #[test]
+    fn test_image_url_is_decoded() {
+        let url = format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(PIXEL)
+        );
+        let part = ContentPart::image_url(url).unwrap();
+        let ContentPart::Image { format, source } = &part else {
+            panic!("expected image");
+        };
+        assert_eq!(*format, ImageFormat::Png);
+        assert_eq!(source.bytes().unwrap(), PIXEL);
+        assert!(part.to_bedrock().unwrap().is_image());
+
+        assert!(ContentPart::image_url("https://example.com/cat.png").is_err());
+        assert!(ContentPart::image_url("data:image/tiff;base64,AAAA").is_err());
+    }
+
+    #[test]
+    fn test_document_round_trip() {
+        let part = ContentPart::document("report", b"%PDF-1.7".to_vec(), DocumentFormat::Pdf);
+        let block = part.to_bedrock().unwrap();
+        assert_eq!(ContentPart::from_bedrock(&block), Some(part));
+    }
+
+    #[test]
+    fn test_media_bytes_serialize_as_base64() {
+        let part = ContentPart::image(PIXEL, ImageFormat::Png);
+        let json = serde_json::to_value(&part).unwrap();
+        assert_eq!(json["source"]["data"], "iVBORw==");
+        assert_eq!(serde_json::from_value::<ContentPart>(json).unwrap(), part);
+    }
+}
@@ -0,0 +1,261 @@
+//! Text embeddings through InvokeModel
+//!
+//! [`UniversalBedrockClient::embed`](crate::UniversalBedrockClient::embed)
+//! supports Amazon Titan text embeddings, which take one text per call, and
+//! Cohere Embed, which takes up to [`COHERE_MAX_BATCH`] texts per call.
+//! Longer inputs are split into batches that run concurrently, and each
+//! batch is retried like a generation request.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::error::{BedrockError, Result};
+
+/// Most texts Cohere Embed accepts in one call
+pub const COHERE_MAX_BATCH: usize = 96;
+
+/// One embedded text
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Embedding {
+    /// Position of the text in the request
+    pub index: usize,
+    /// The embedding vector
+    pub vector: Vec<f32>,
+    /// Input tokens, when the model reports them
+    pub input_tokens: Option<usize>,
+}
+
+/// What an embedded text is used for; Cohere embeds these differently
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbeddingInputType {
+    /// A document to be searched
+    #[default]
+    SearchDocument,
+    /// A query to search documents with
+    SearchQuery,
+    /// Text to classify
+    Classification,
+    /// Text to cluster
+    Clustering,
+}
+
+/// Options for an embedding request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmbeddingConfig {
+    /// Vector size, for models that offer several, e.g. 256, 512, or 1024
+    /// for Titan Text Embeddings V2
+    pub dimensions: Option<usize>,
+    /// Whether to scale vectors to unit length; Titan V2 only, which normalizes by default
+    pub normalize: Option<bool>,
+    /// What the texts are used for; Cohere only
+    pub input_type: EmbeddingInputType,
+}
+
+impl EmbeddingConfig {
+    /// Embed queries rather than documents
+    pub fn query() -> Self {
+        Self {
+            input_type: EmbeddingInputType::SearchQuery,
+            ..Default::default()
+        }
+    }
+
+    /// Set the vector size
+    pub fn with_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    /// Set whether vectors are scaled to unit length
+    pub fn with_normalize(mut self, normalize: bool) -> Self {
+        self.normalize = Some(normalize);
+        self
+    }
+}
+
+/// Request and response format of an embedding model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EmbeddingFamily {
+    /// Amazon Titan text embeddings
+    Titan,
+    /// Cohere Embed
+    Cohere,
+}
+
+/// Cohere's `embeddings` field: a list of vectors, or vectors by type when types are requested
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CohereEmbeddings {
+    Floats(Vec<Vec<f32>>),
+    ByType { float: Vec<Vec<f32>> },
+}
+
+impl EmbeddingFamily {
+    /// The family of `model`, which may be a model ID, inference profile, or ARN
+    pub(crate) fn detect(model: &str) -> Result<Self> {
+        if model.contains("titan-embed") {
+            Ok(Self::Titan)
+        } else if model.contains("cohere.embed") {
+            Ok(Self::Cohere)
+        } else {
+            Err(BedrockError::InvalidInput(format!(
+                "{} is not a supported embedding model",
+                model
+            )))
+        }
+    }
+
+    /// Most texts sent in one call
+    pub(crate) fn batch_size(self) -> usize {
+        match self {
+            Self::Titan => 1,
+            Self::Cohere => COHERE_MAX_BATCH,
+        }
+    }
+
+    /// The InvokeModel body embedding `texts`
+    pub(crate) fn request_body(
+        self,
+        texts: &[String],
+        config: &EmbeddingConfig,
+    ) -> Result<Vec<u8>> {
+        let body = match self {
+            Self::Titan => {
+                let [text] = texts else {
+                    return Err(BedrockError::InvalidInput(
+                        "Titan embeds one text per request".to_string(),
+                    ));
+                };
+                let mut body = json!({ "inputText": text });
+                if let Some(dimensions) = config.dimensions {
+                    body["dimensions"] = json!(dimensions);
+                }
+                if let Some(normalize) = config.normalize {
+                    body["normalize"] = json!(normalize);
+                }
+                body
+            }
+            Self::Cohere => {
+                let mut body = json!({ "texts": texts, "input_type": config.input_type });
+                if let Some(dimensions) = config.dimensions {
+                    body["output_dimension"] = json!(dimensions);
+                }
+                body
+            }
+        };
+        serde_json::to_vec(&body).map_err(|e| {
+            BedrockError::InvalidInput(format!("Failed to encode embedding request: {}", e))
+        })
+    }
+
+    /// Vectors and token counts from an InvokeModel response to a request for `count` texts
+    pub(crate) fn parse_response(
+        self,
+        body: &[u8],
+        count: usize,
+    ) -> Result<Vec<(Vec<f32>, Option<usize>)>> {
+        let invalid = |e: serde_json::Error| {
+            BedrockError::InvalidResponse(format!("Unexpected embedding response: {}", e))
+        };
+        let embedded: Vec<(Vec<f32>, Option<usize>)> = match self {
+            Self::Titan => {
+                #[derive(Deserialize)]
+                #[serde(rename_all = "camelCase")]
+                struct TitanResponse {
+                    embedding: Vec<f32>,
+                    input_text_token_count: Option<usize>,
+                }
+                let response: TitanResponse = serde_json::from_slice(body).map_err(invalid)?;
+                vec![(response.embedding, response.input_text_token_count)]
+            }
+            Self::Cohere => {
+                #[derive(Deserialize)]
+                struct CohereResponse {
+                    embeddings: CohereEmbeddings,
+                }
+                let response: CohereResponse = serde_json::from_slice(body).map_err(invalid)?;
+                let (CohereEmbeddings::Floats(vectors)
+                | CohereEmbeddings::ByType { float: vectors }) = response.embeddings;
+                vectors.into_iter().map(|vector| (vector, None)).collect()
+            }
+        };
+
+        if embedded.len() != count {
+            return Err(BedrockError::InvalidResponse(format!(
+                "Expected {} embeddings, got {}",
+                count,
+                embedded.len()
+            )));
+        }
+        Ok(embedded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(items: &[&str]) -> Vec<String> {
+        items.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_detect_family() {
+        assert_eq!(
+            EmbeddingFamily::detect("amazon.titan-embed-text-v2:0").unwrap(),
+            EmbeddingFamily::Titan
+        );
+        assert_eq!(
+            EmbeddingFamily::detect("us.cohere.embed-multilingual-v3").unwrap(),
+            EmbeddingFamily::Cohere
+        );
+        assert!(EmbeddingFamily::detect("anthropic.claude-3-haiku-20240307-v1:0").is_err());
+    }
+
+    #[test]
+    fn test_titan_round_trip() {
+        let config = EmbeddingConfig::default().with_dimensions(256);
+        let body = EmbeddingFamily::Titan
+            .request_body(&texts(&["hello"]), &config)
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, json!({ "inputText": "hello", "dimensions": 256 }));
+        assert!(EmbeddingFamily::Titan
+            .request_body(&texts(&["a", "b"]), &config)
+            .is_err());
+
+        let parsed = EmbeddingFamily::Titan
+            .parse_response(
+                br#"{"embedding": [0.5, -0.5], "inputTextTokenCount": 2}"#,
+                1,
+            )
+            .unwrap();
+        assert_eq!(parsed, vec![(vec![0.5, -0.5], Some(2))]);
+    }
+
+    #[test]
+    fn test_cohere_round_trip() {
+        let body = EmbeddingFamily::Cohere
+            .request_body(&texts(&["a", "b"]), &EmbeddingConfig::query())
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({ "texts": ["a", "b"], "input_type": "search_query" })
+        );
+
+        let parsed = EmbeddingFamily::Cohere
+            .parse_response(br#"{"id": "1", "embeddings": [[1.0], [2.0]]}"#, 2)
+            .unwrap();
+        assert_eq!(parsed, vec![(vec![1.0], None), (vec![2.0], None)]);
+        let parsed = EmbeddingFamily::Cohere
+            .parse_response(br#"{"embeddings": {"float": [[3.0]]}}"#, 1)
+            .unwrap();
+        assert_eq!(parsed, vec![(vec![3.0], None)]);
+        assert!(EmbeddingFamily::Cohere
+            .parse_response(br#"{"embeddings": [[1.0]]}"#, 2)
+            .is_err());
+    }
+}
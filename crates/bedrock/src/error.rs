@@ -2,6 +2,9 @@
 
 use thiserror::Error;
 
+use crate::guardrail::GuardrailIntervention;
+use crate::model::FeatureMismatch;
+
 /// Errors that can occur when using the Bedrock client
 #[derive(Error, Debug)]
 pub enum BedrockError {
@@ -42,8 +45,13 @@ pub enum BedrockError {
     ModelUnavailable(String),
 
     /// Content filtering error
-    #[error("Content filtered: {0}")]
-    ContentFiltered(String),
+    #[error("Content filtered: {reason}")]
+    ContentFiltered {
+        /// Why the content was filtered
+        reason: String,
+        /// What the guardrail did, when a guardrail filtered it
+        intervention: Option<Box<GuardrailIntervention>>,
+    },
 
     /// Token limit exceeded
     #[error("Token limit exceeded: {0}")]
@@ -60,20 +68,32 @@ pub enum BedrockError {
     /// Internal error
     #[error("Internal error: {0}")]
     Internal(String),
+
+    /// The model lacks features the request needs
+    #[error(
+        "Model {model} does not support: {}",
+        .missing.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+    )]
+    UnsupportedFeature {
+        /// Requested model
+        model: String,
+        /// Every feature the model lacks
+        missing: Vec<FeatureMismatch>,
+    },
 }
 
 impl BedrockError {
     /// Check if this error is retryable
     pub fn is_retryable(&self) -> bool {
-        match self {
-            Self::ServiceError(_) => true,
-            Self::RequestFailed(_) => true,
-            Self::Timeout(_) => true,
-            Self::RateLimited(_) => true,
-            Self::ModelUnavailable(_) => true,
-            Self::Internal(_) => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            Self::ServiceError(_)
+                | Self::RequestFailed(_)
+                | Self::Timeout(_)
+                | Self::RateLimited(_)
+                | Self::ModelUnavailable(_)
+                | Self::Internal(_)
+        )
     }
 
     /// Get the error category
@@ -88,11 +108,12 @@ impl BedrockError {
             Self::Timeout(_) => ErrorCategory::Network,
             Self::RateLimited(_) => ErrorCategory::RateLimit,
             Self::ModelUnavailable(_) => ErrorCategory::Server,
-            Self::ContentFiltered(_) => ErrorCategory::Content,
+            Self::ContentFiltered { .. } => ErrorCategory::Content,
             Self::TokenLimitExceeded(_) => ErrorCategory::Resource,
             Self::Authentication(_) => ErrorCategory::Authentication,
             Self::Authorization(_) => ErrorCategory::Authorization,
             Self::Internal(_) => ErrorCategory::Internal,
+            Self::UnsupportedFeature { .. } => ErrorCategory::Client,
         }
     }
 
@@ -108,11 +129,12 @@ impl BedrockError {
             Self::Timeout(_) => 504,
             Self::RateLimited(_) => 429,
             Self::ModelUnavailable(_) => 503,
-            Self::ContentFiltered(_) => 400,
+            Self::ContentFiltered { .. } => 400,
             Self::TokenLimitExceeded(_) => 400,
             Self::Authentication(_) => 401,
             Self::Authorization(_) => 403,
             Self::Internal(_) => 500,
+            Self::UnsupportedFeature { .. } => 400,
         }
     }
 }
@@ -161,7 +183,7 @@ impl ErrorCategory {
 }
 
 /// Result type alias for Bedrock operations
-pub type Result<T> = std::result::Result<T, BedrockError>;
+pub type Result<T, E = BedrockError> = std::result::Result<T, E>;
 
 /// Convert AWS SDK errors to Bedrock errors
 impl From<aws_sdk_bedrockruntime::Error> for BedrockError {
@@ -172,6 +194,15 @@ impl From<aws_sdk_bedrockruntime::Error> for BedrockError {
     }
 }
 
+impl From<GuardrailIntervention> for BedrockError {
+    fn from(intervention: GuardrailIntervention) -> Self {
+        Self::ContentFiltered {
+            reason: intervention.summary(),
+            intervention: Some(Box::new(intervention)),
+        }
+    }
+}
+
 /// Convert standard errors to Bedrock errors
 impl From<anyhow::Error> for BedrockError {
     fn from(error: anyhow::Error) -> Self {
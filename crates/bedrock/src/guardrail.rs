@@ -0,0 +1,353 @@
+//! Guardrails on Converse requests
+//!
+//! A guardrail is applied with
+//! [`GenerationConfig::with_guardrail`](crate::GenerationConfig::with_guardrail).
+//! When it intervenes, generation fails with [`BedrockError::ContentFiltered`]
+//! carrying a [`GuardrailIntervention`] that lists the policies that matched.
+//! Streamed replies are guarded too, but a stream only ends early when the
+//! guardrail steps in; it does not report the intervention.
+
+use aws_sdk_bedrockruntime::types::{
+    GuardrailAssessment, GuardrailConfiguration, GuardrailStreamConfiguration, GuardrailTrace,
+    GuardrailTraceAssessment,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::GenerationConfig;
+use crate::error::{BedrockError, Result};
+
+/// Which side of the exchange a guardrail assessed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardrailSource {
+    /// The prompt sent to the model
+    Input,
+    /// The model's output
+    Output,
+}
+
+/// Kind of guardrail policy that matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardrailPolicy {
+    /// A denied topic
+    Topic,
+    /// A harmful content filter, such as hate or violence
+    Content,
+    /// A custom or managed word list
+    Word,
+    /// Personal information or a custom regex
+    SensitiveInformation,
+    /// A grounding or relevance check
+    ContextualGrounding,
+}
+
+/// One policy match in a guardrail assessment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuardrailFinding {
+    /// Whether the input or output matched
+    pub source: GuardrailSource,
+    /// Kind of policy
+    pub policy: GuardrailPolicy,
+    /// What matched: the topic, filter type, word, entity type, or regex name
+    pub name: String,
+    /// What the guardrail did, e.g. `BLOCKED` or `ANONYMIZED`
+    pub action: String,
+}
+
+/// A guardrail stopping a generation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuardrailIntervention {
+    /// Guardrail that intervened
+    pub guardrail_identifier: String,
+    /// Its version
+    pub guardrail_version: String,
+    /// Text the guardrail returned in place of the model's reply
+    pub message: String,
+    /// Policies that matched, inputs first
+    pub findings: Vec<GuardrailFinding>,
+}
+
+impl GuardrailIntervention {
+    /// Build the intervention from a Converse guardrail trace
+    pub(crate) fn from_trace(
+        config: &GenerationConfig,
+        message: impl Into<String>,
+        trace: Option<&GuardrailTraceAssessment>,
+    ) -> Self {
+        let mut findings = Vec::new();
+        if let Some(trace) = trace {
+            let mut inputs: Vec<_> = trace.input_assessment().into_iter().flatten().collect();
+            inputs.sort_by(|a, b| a.0.cmp(b.0));
+            for (_, assessment) in inputs {
+                findings.extend(assessment_findings(GuardrailSource::Input, assessment));
+            }
+            let mut outputs: Vec<_> = trace.output_assessments().into_iter().flatten().collect();
+            outputs.sort_by(|a, b| a.0.cmp(b.0));
+            for assessment in outputs.into_iter().flat_map(|(_, list)| list) {
+                findings.extend(assessment_findings(GuardrailSource::Output, assessment));
+            }
+        }
+
+        Self {
+            guardrail_identifier: config.guardrail_identifier.clone().unwrap_or_default(),
+            guardrail_version: config.guardrail_version.clone().unwrap_or_default(),
+            message: message.into(),
+            findings,
+        }
+    }
+
+    /// Short description for error messages, e.g. `topic Investing (BLOCKED)`
+    pub fn summary(&self) -> String {
+        if self.findings.is_empty() {
+            return format!("guardrail {} intervened", self.guardrail_identifier);
+        }
+        self.findings
+            .iter()
+            .map(|finding| {
+                let policy = match finding.policy {
+                    GuardrailPolicy::Topic => "topic",
+                    GuardrailPolicy::Content => "content filter",
+                    GuardrailPolicy::Word => "word",
+                    GuardrailPolicy::SensitiveInformation => "sensitive information",
+                    GuardrailPolicy::ContextualGrounding => "contextual grounding",
+                };
+                format!("{} {} ({})", policy, finding.name, finding.action)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Every policy match in `assessment`
+fn assessment_findings(
+    source: GuardrailSource,
+    assessment: &GuardrailAssessment,
+) -> Vec<GuardrailFinding> {
+    let finding = |policy, name: &str, action: &str| GuardrailFinding {
+        source,
+        policy,
+        name: name.to_string(),
+        action: action.to_string(),
+    };
+
+    let mut findings = Vec::new();
+    if let Some(topics) = assessment.topic_policy() {
+        for topic in topics.topics() {
+            findings.push(finding(
+                GuardrailPolicy::Topic,
+                topic.name(),
+                topic.action().as_str(),
+            ));
+        }
+    }
+    if let Some(content) = assessment.content_policy() {
+        for filter in content.filters() {
+            findings.push(finding(
+                GuardrailPolicy::Content,
+                filter.r#type().as_str(),
+                filter.action().as_str(),
+            ));
+        }
+    }
+    if let Some(words) = assessment.word_policy() {
+        for word in words.custom_words() {
+            findings.push(finding(
+                GuardrailPolicy::Word,
+                word.r#match(),
+                word.action().as_str(),
+            ));
+        }
+        for word in words.managed_word_lists() {
+            findings.push(finding(
+                GuardrailPolicy::Word,
+                word.r#match(),
+                word.action().as_str(),
+            ));
+        }
+    }
+    if let Some(sensitive) = assessment.sensitive_information_policy() {
+        for entity in sensitive.pii_entities() {
+            findings.push(finding(
+                GuardrailPolicy::SensitiveInformation,
+                entity.r#type().as_str(),
+                entity.action().as_str(),
+            ));
+        }
+        for regex in sensitive.regexes() {
+            findings.push(finding(
+                GuardrailPolicy::SensitiveInformation,
+                regex.name().unwrap_or("regex"),
+                regex.action().as_str(),
+            ));
+        }
+    }
+    if let Some(grounding) = assessment.contextual_grounding_policy() {
+        for filter in grounding.filters() {
+            findings.push(finding(
+                GuardrailPolicy::ContextualGrounding,
+                filter.r#type().as_str(),
+                filter.action().as_str(),
+            ));
+        }
+    }
+    findings
+}
+
+/// The guardrail to apply to a request, if one is configured
+fn configured(config: Option<&GenerationConfig>) -> Result<Option<(&str, &str)>> {
+    let Some(config) = config else {
+        return Ok(None);
+    };
+    match (
+        config.guardrail_identifier.as_deref(),
+        config.guardrail_version.as_deref(),
+    ) {
+        (Some(identifier), Some(version)) => Ok(Some((identifier, version))),
+        (None, None) => Ok(None),
+        _ => Err(BedrockError::InvalidInput(
+            "Guardrails need both guardrail_identifier and guardrail_version".to_string(),
+        )),
+    }
+}
+
+/// The Converse guardrail configuration for a request, with tracing enabled
+pub(crate) fn guardrail_configuration(
+    config: Option<&GenerationConfig>,
+) -> Result<Option<GuardrailConfiguration>> {
+    let Some((identifier, version)) = configured(config)? else {
+        return Ok(None);
+    };
+    Ok(Some(
+        GuardrailConfiguration::builder()
+            .guardrail_identifier(identifier)
+            .guardrail_version(version)
+            .trace(GuardrailTrace::Enabled)
+            .build(),
+    ))
+}
+
+/// The ConverseStream guardrail configuration for a request, with tracing enabled
+pub(crate) fn guardrail_stream_configuration(
+    config: Option<&GenerationConfig>,
+) -> Result<Option<GuardrailStreamConfiguration>> {
+    let Some((identifier, version)) = configured(config)? else {
+        return Ok(None);
+    };
+    Ok(Some(
+        GuardrailStreamConfiguration::builder()
+            .guardrail_identifier(identifier)
+            .guardrail_version(version)
+            .trace(GuardrailTrace::Enabled)
+            .build(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_bedrockruntime::types::{
+        GuardrailContentFilter, GuardrailContentFilterConfidence, GuardrailContentFilterType,
+        GuardrailContentPolicyAction, GuardrailContentPolicyAssessment, GuardrailTopic,
+        GuardrailTopicPolicyAction, GuardrailTopicPolicyAssessment, GuardrailTopicType,
+    };
+
+    fn guarded() -> GenerationConfig {
+        GenerationConfig::default().with_guardrail("gr-123", "2")
+    }
+
+    #[test]
+    fn test_guardrail_configuration_needs_both_fields() {
+        assert!(guardrail_configuration(None).unwrap().is_none());
+        assert!(guardrail_configuration(Some(&GenerationConfig::default()))
+            .unwrap()
+            .is_none());
+
+        let configuration = guardrail_configuration(Some(&guarded())).unwrap().unwrap();
+        assert_eq!(configuration.guardrail_identifier(), "gr-123");
+        assert_eq!(configuration.guardrail_version(), "2");
+        assert_eq!(configuration.trace(), &GuardrailTrace::Enabled);
+
+        let partial = GenerationConfig {
+            guardrail_identifier: Some("gr-123".to_string()),
+            ..GenerationConfig::default()
+        };
+        assert!(matches!(
+            guardrail_stream_configuration(Some(&partial)),
+            Err(BedrockError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_intervention_lists_findings() {
+        let topic = GuardrailTopic::builder()
+            .name("Investing")
+            .r#type(GuardrailTopicType::Deny)
+            .action(GuardrailTopicPolicyAction::Blocked)
+            .build()
+            .unwrap();
+        let filter = GuardrailContentFilter::builder()
+            .r#type(GuardrailContentFilterType::Violence)
+            .confidence(GuardrailContentFilterConfidence::High)
+            .action(GuardrailContentPolicyAction::Blocked)
+            .build()
+            .unwrap();
+        let trace = GuardrailTraceAssessment::builder()
+            .input_assessment(
+                "gr-123",
+                GuardrailAssessment::builder()
+                    .topic_policy(
+                        GuardrailTopicPolicyAssessment::builder()
+                            .topics(topic)
+                            .build()
+                            .unwrap(),
+                    )
+                    .build(),
+            )
+            .output_assessments(
+                "gr-123",
+                vec![GuardrailAssessment::builder()
+                    .content_policy(
+                        GuardrailContentPolicyAssessment::builder()
+                            .filters(filter)
+                            .build()
+                            .unwrap(),
+                    )
+                    .build()],
+            )
+            .build();
+
+        let intervention = GuardrailIntervention::from_trace(
+            &guarded(),
+            "Sorry, I can't help with that.",
+            Some(&trace),
+        );
+        assert_eq!(intervention.guardrail_identifier, "gr-123");
+        assert_eq!(intervention.message, "Sorry, I can't help with that.");
+        assert_eq!(
+            intervention.findings,
+            vec![
+                GuardrailFinding {
+                    source: GuardrailSource::Input,
+                    policy: GuardrailPolicy::Topic,
+                    name: "Investing".to_string(),
+                    action: "BLOCKED".to_string(),
+                },
+                GuardrailFinding {
+                    source: GuardrailSource::Output,
+                    policy: GuardrailPolicy::Content,
+                    name: "VIOLENCE".to_string(),
+                    action: "BLOCKED".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            intervention.summary(),
+            "topic Investing (BLOCKED), content filter VIOLENCE (BLOCKED)"
+        );
+
+        let error = BedrockError::from(intervention);
+        assert!(!error.is_retryable());
+        assert_eq!(error.status_code(), 400);
+    }
+}
@@ -8,40 +8,73 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Context;
-use async_trait::async_trait;
 use aws_config::BehaviorVersion;
-use aws_sdk_bedrockruntime::types::{ContentBlock, Message as BedrockMessage, SystemContentBlock};
-use aws_sdk_bedrockruntime::{Client as BedrockClient, Config};
+use aws_sdk_bedrockruntime::config::Region;
+use aws_sdk_bedrockruntime::operation::RequestId as _;
+use aws_sdk_bedrockruntime::primitives::Blob;
+use aws_sdk_bedrockruntime::Client as BedrockClient;
 use backoff::{ExponentialBackoff, ExponentialBackoffBuilder};
-use bytes::Bytes;
-use chrono::{DateTime, Utc};
-use futures::Stream;
+use base64::Engine as _;
+use chrono::Utc;
+use futures::future::Either;
+use futures::{Stream, StreamExt, TryStreamExt};
 use parking_lot::RwLock;
-use serde::{Deserialize, Serialize};
-use thiserror::Error;
+use serde::de::DeserializeOwned;
 use tokio::sync::Semaphore;
-use tracing::{debug, error, info, instrument, warn};
+use tracing::{debug, info, instrument, warn};
 use uuid::Uuid;
 
-pub use client::*;
+pub use batch::BatchConfig;
+pub use batch_job::{
+    BatchJob, BatchJobConfig, BatchJobControl, BatchJobManager, BatchJobRecord, BatchJobSpec,
+    BatchJobState, BatchJobStatus, BatchJobStorage, BATCH_PRICE_FACTOR,
+};
+#[cfg(feature = "batch-jobs")]
+pub use batch_job::{HttpBatchJobControl, S3BatchStorage};
+pub use caching::PROMPT_CACHE_TTL;
+pub use chaos::{ChaosConfig, ChaosStats, FaultInjector};
 pub use config::*;
+pub use content::*;
+pub use embedding::{Embedding, EmbeddingConfig, EmbeddingInputType, COHERE_MAX_BATCH};
 pub use error::{BedrockError, ErrorCategory, Result};
+pub use guardrail::{GuardrailFinding, GuardrailIntervention, GuardrailPolicy, GuardrailSource};
 pub use message::*;
 pub use metrics::*;
 pub use model::*;
 pub use pool::*;
+pub use probe::*;
+pub use ratelimit::{ModelRateLimit, RateLimitConfig, RateLimiter};
+pub use region::{FailoverConfig, FailoverEvent, FailoverKind, RegionConfig, RegionRouter};
 pub use retry::*;
 pub use streaming::*;
+pub use structured::{
+    extract_json, parse_structured, repair_prompt, schema_instruction, StructuredResponse,
+};
+pub use tool_runner::{ToolHandler, ToolRunner, DEFAULT_TOOL_PARALLELISM};
+pub use tools::*;
 
-mod client;
+mod batch;
+mod batch_job;
+mod caching;
+mod chaos;
+pub mod client;
 mod config;
+mod content;
+mod embedding;
 mod error;
+mod guardrail;
 mod message;
 mod metrics;
 mod model;
 mod pool;
+mod probe;
+mod ratelimit;
+mod region;
 mod retry;
 mod streaming;
+mod structured;
+mod tool_runner;
+mod tools;
 
 /// Re-export commonly used types
 pub use aws_sdk_bedrockruntime::types::{ContentBlock as AwsContentBlock, Message as AwsMessage};
@@ -58,11 +91,19 @@ pub struct UniversalBedrockClient {
 }
 
 struct BedrockClientInner {
-    clients: Vec<BedrockClient>,
+    /// Client pools, indexed like the router's regions
+    pools: Vec<Vec<BedrockClient>>,
+    router: RegionRouter,
     config: BedrockConfig,
     metrics: Arc<RwLock<BedrockMetrics>>,
     semaphore: Semaphore,
+    /// Limits batch items in flight, across all batches
+    batch_semaphore: Semaphore,
     retry_policy: ExponentialBackoff,
+    chaos: Arc<FaultInjector>,
+    rate_limiter: RateLimiter,
+    registry: RwLock<ModelRegistry>,
+    prefixes: caching::PrefixTracker,
 }
 
 impl UniversalBedrockClient {
@@ -92,19 +133,36 @@ impl UniversalBedrockClient {
             .load()
             .await;
 
-        let mut clients = Vec::with_capacity(config.pool_size);
-        for _ in 0..config.pool_size {
-            let client_config = Config::builder()
-                .region(config.region.clone())
-                .timeout_config(
-                    aws_sdk_bedrockruntime::config::timeout::TimeoutConfig::builder()
-                        .operation_timeout(Duration::from_secs(config.timeout_seconds))
-                        .build(),
-                )
-                .build();
+        let router = RegionRouter::new(&config.failover, config.region.as_ref());
+        let mut pools = Vec::with_capacity(router.regions().len());
+        for region in router.regions() {
+            let pool_size = region.pool_size.unwrap_or(config.pool_size).max(1);
+            let mut clients = Vec::with_capacity(pool_size);
+            for _ in 0..pool_size {
+                let client_config = aws_sdk_bedrockruntime::config::Builder::from(&aws_config)
+                    .region(Region::new(region.region.clone()))
+                    .timeout_config(
+                        aws_sdk_bedrockruntime::config::timeout::TimeoutConfig::builder()
+                            .operation_timeout(Duration::from_secs(config.timeout_seconds))
+                            .build(),
+                    )
+                    .build();
 
-            let client = BedrockClient::from_conf(client_config);
-            clients.push(client);
+                let client = BedrockClient::from_conf(client_config);
+                clients.push(client);
+            }
+            pools.push(clients);
+        }
+        if router.is_multi_region() {
+            info!(
+                "Failover enabled across regions: {}",
+                router
+                    .regions()
+                    .iter()
+                    .map(|r| r.region.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
         }
 
         let retry_policy = ExponentialBackoffBuilder::new()
@@ -114,19 +172,36 @@ impl UniversalBedrockClient {
             .with_multiplier(config.retry_multiplier)
             .build();
 
+        if config.chaos.enabled {
+            warn!("Chaos mode enabled: faults will be injected into requests");
+        }
+
         let pool_size = config.pool_size;
+        let batch_concurrency = config.batch.effective_concurrency(pool_size);
+        let chaos = Arc::new(FaultInjector::new(config.chaos.clone()));
+        let rate_limiter = RateLimiter::new(config.rate_limits.clone());
         let inner = BedrockClientInner {
-            clients,
+            pools,
+            router,
             config,
             metrics: Arc::new(RwLock::new(BedrockMetrics::new())),
             semaphore: Semaphore::new(pool_size),
+            batch_semaphore: Semaphore::new(batch_concurrency),
             retry_policy,
+            chaos,
+            rate_limiter,
+            registry: RwLock::new(ModelRegistry::new()),
+            prefixes: caching::PrefixTracker::default(),
         };
 
         info!("Universal Bedrock client initialized successfully");
-        Ok(Self {
+        let client = Self {
             inner: Arc::new(inner),
-        })
+        };
+        if client.inner.config.probes.enabled {
+            client.spawn_probes();
+        }
+        Ok(client)
     }
 
     /// Generate a text response using the specified model
@@ -141,6 +216,9 @@ impl UniversalBedrockClient {
         messages: Vec<UniversalMessage>,
         config: Option<GenerationConfig>,
     ) -> Result<GenerationResponse> {
+        let features = RequestFeatures::detect(&messages, config.as_ref(), false);
+        let model = &self.negotiate(model, &features)?;
+
         let start = std::time::Instant::now();
         let request_id = Uuid::new_v4();
 
@@ -171,6 +249,179 @@ impl UniversalBedrockClient {
         result
     }
 
+    /// Generate a response, running the tools the model calls until it answers
+    ///
+    /// Each turn's tool calls run through `runner`, and their results are sent
+    /// back with the model's turn for the next request. The returned response
+    /// is the first that calls no tools.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request fails, or
+    /// [`BedrockError::RequestFailed`] if the model still calls tools after
+    /// `max_rounds` rounds of tool calls.
+    #[instrument(skip(self, messages, config, runner), fields(model = %model))]
+    pub async fn generate_with_tools(
+        &self,
+        model: &str,
+        mut messages: Vec<UniversalMessage>,
+        config: Option<GenerationConfig>,
+        runner: &ToolRunner,
+        max_rounds: usize,
+    ) -> Result<GenerationResponse> {
+        for round in 0..=max_rounds {
+            let response = self
+                .generate_text(model, messages.clone(), config.clone())
+                .await?;
+            if !response.requires_tool_use() {
+                return Ok(response);
+            }
+            if round == max_rounds {
+                break;
+            }
+            debug!(
+                "Running {} tool calls in round {}",
+                response.tool_uses.len(),
+                round + 1
+            );
+            let results = runner.run(&response.tool_uses).await;
+            messages.push(response.to_message());
+            messages.push(UniversalMessage::tool_results(results));
+        }
+        Err(BedrockError::RequestFailed(format!(
+            "Model still calling tools after {max_rounds} rounds"
+        )))
+    }
+
+    /// Generate a reply matching `schema` and parse it as `T`
+    ///
+    /// The schema is added to the system prompt. A reply that fails to parse
+    /// as `T` is answered with the error and the model asked again, at most
+    /// `max_repairs` times.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request fails, or
+    /// [`BedrockError::InvalidResponse`] if no reply parses.
+    #[instrument(skip(self, messages, config, schema), fields(model = %model))]
+    pub async fn generate_json<T: DeserializeOwned>(
+        &self,
+        model: &str,
+        mut messages: Vec<UniversalMessage>,
+        config: Option<GenerationConfig>,
+        schema: &serde_json::Value,
+        max_repairs: usize,
+    ) -> Result<StructuredResponse<T>> {
+        let mut config = config.unwrap_or_default();
+        let instruction = structured::schema_instruction(schema);
+        config.system_prompt = Some(match config.system_prompt.take() {
+            Some(prompt) => format!("{prompt}\n\n{instruction}"),
+            None => instruction,
+        });
+
+        let mut last_error = None;
+        for attempt in 0..=max_repairs {
+            let response = self
+                .generate_text(model, messages.clone(), Some(config.clone()))
+                .await?;
+            match structured::parse_structured(&response.content) {
+                Ok(value) => {
+                    return Ok(StructuredResponse {
+                        value,
+                        response,
+                        attempts: attempt + 1,
+                    })
+                }
+                Err(e) => {
+                    debug!("Structured reply {} did not parse: {}", attempt + 1, e);
+                    messages.push(UniversalMessage::assistant(response.content));
+                    messages.push(UniversalMessage::user(structured::repair_prompt(&e)));
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(BedrockError::InvalidResponse(format!(
+            "No reply matched the schema after {} attempts: {}",
+            max_repairs + 1,
+            last_error.map(|e| e.to_string()).unwrap_or_default()
+        )))
+    }
+
+    /// Generate a reply matching `T`'s JSON Schema and parse it
+    ///
+    /// See [`generate_json`](Self::generate_json).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request fails, or
+    /// [`BedrockError::InvalidResponse`] if no reply parses.
+    #[cfg(feature = "structured")]
+    pub async fn generate_structured<T: DeserializeOwned + schemars::JsonSchema>(
+        &self,
+        model: &str,
+        messages: Vec<UniversalMessage>,
+        config: Option<GenerationConfig>,
+        max_repairs: usize,
+    ) -> Result<StructuredResponse<T>> {
+        let schema = serde_json::to_value(schemars::schema_for!(T))
+            .map_err(|e| BedrockError::Internal(e.to_string()))?;
+        self.generate_json(model, messages, config, &schema, max_repairs)
+            .await
+    }
+
+    /// Generate a response to each of `requests`
+    ///
+    /// Results are in the order of `requests`. Each item is retried on its
+    /// own and a failed item does not stop the others. At most
+    /// [`BatchConfig::max_concurrency`] batch items are in flight at once,
+    /// across all batches, so interactive requests keep part of the pool.
+    #[instrument(skip(self, requests), fields(batch_size = requests.len()))]
+    pub async fn generate_batch(
+        &self,
+        requests: Vec<GenerationRequest>,
+    ) -> Vec<Result<GenerationResponse>> {
+        if requests.is_empty() {
+            return Vec::new();
+        }
+
+        let concurrency = self
+            .inner
+            .config
+            .batch
+            .effective_concurrency(self.inner.config.pool_size);
+        // Built up front so the stream holds no borrowing closure, keeping it `Send`
+        let items: Vec<_> = requests
+            .into_iter()
+            .map(|request| self._generate_batch_item(request))
+            .collect();
+        let results: Vec<_> = futures::stream::iter(items)
+            .buffered(concurrency)
+            .collect()
+            .await;
+
+        let failed = results.iter().filter(|result| result.is_err()).count() as u64;
+        let succeeded = results.len() as u64 - failed;
+        self.inner.metrics.write().record_batch(succeeded, failed);
+        info!(
+            "Batch of {} finished: {} succeeded, {} failed",
+            results.len(),
+            succeeded,
+            failed
+        );
+        results
+    }
+
+    async fn _generate_batch_item(&self, request: GenerationRequest) -> Result<GenerationResponse> {
+        let _permit = self
+            .inner
+            .batch_semaphore
+            .acquire()
+            .await
+            .map_err(|e| BedrockError::PoolExhausted(e.to_string()))?;
+        self.generate_text(&request.model, request.messages, request.config)
+            .await
+    }
+
     async fn _generate_text_with_retry(
         &self,
         model: &str,
@@ -183,12 +434,22 @@ impl UniversalBedrockClient {
                 .await
         };
 
-        backoff::future::retry(self.inner.retry_policy.clone(), operation)
-            .await
-            .map_err(|e| BedrockError::RequestFailed(format!("All retries exhausted: {}", e)))
-            .context("Failed to generate text after retries")
+        match backoff::future::retry(self.inner.retry_policy.clone(), operation).await {
+            // Keep guardrail interventions and client-side throttling typed
+            // so callers can inspect them
+            Err(e @ (BedrockError::ContentFiltered { .. } | BedrockError::RateLimited(_))) => {
+                Err(e)
+            }
+            result => result
+                .map_err(|e| BedrockError::RequestFailed(format!("All retries exhausted: {e}"))),
+        }
     }
 
+    #[instrument(
+        name = "bedrock.converse",
+        skip(self, messages, config),
+        fields(aws_request_id = tracing::field::Empty)
+    )]
     async fn _generate_text_once(
         &self,
         model: &str,
@@ -196,21 +457,40 @@ impl UniversalBedrockClient {
         config: &Option<GenerationConfig>,
         request_id: Uuid,
     ) -> Result<GenerationResponse, backoff::Error<BedrockError>> {
+        self.inner
+            .chaos
+            .before_acquire()
+            .map_err(backoff::Error::permanent)?;
+
+        let reserved = self
+            .inner
+            .rate_limiter
+            .estimate_tokens(messages, config.as_ref());
+        self.wait_for_rate_limit(model, reserved)
+            .await
+            .map_err(backoff::Error::permanent)?;
+
         let _permit =
             self.inner.semaphore.acquire().await.map_err(|e| {
                 backoff::Error::permanent(BedrockError::PoolExhausted(e.to_string()))
             })?;
 
-        // Get a client from the pool
-        let client_index = request_id.as_u128() as usize % self.inner.clients.len();
-        let client = &self.inner.clients[client_index];
+        // Get a client from the pool of the region serving this model
+        let region_index = self
+            .select_region(model)
+            .map_err(backoff::Error::permanent)?;
+        let clients = &self.inner.pools[region_index];
+        let client_index = request_id.as_u128() as usize % clients.len();
+        let client = &clients[client_index];
 
         // Convert messages to Bedrock format
-        let bedrock_messages = messages
+        let mut bedrock_messages = messages
             .iter()
             .map(|msg| msg.to_bedrock_message())
             .collect::<Result<Vec<_>, _>>()
             .map_err(|e| backoff::Error::permanent(BedrockError::InvalidInput(e.to_string())))?;
+        caching::mark_cached_prefix(&mut bedrock_messages, config.as_ref())
+            .map_err(backoff::Error::permanent)?;
 
         // Build the request
         let mut request = client
@@ -224,66 +504,312 @@ impl UniversalBedrockClient {
                 .set_max_tokens(config.max_tokens.map(|t| t as i32))
                 .set_temperature(config.temperature)
                 .set_top_p(config.top_p)
+                .set_stop_sequences(
+                    (!config.stop_sequences.is_empty()).then(|| config.stop_sequences.clone()),
+                )
                 .build();
             request = request.inference_config(inference_config);
 
-            if let Some(system) = &config.system_prompt {
-                let system_block = SystemContentBlock::Text(system.clone());
-                request = request.system(vec![system_block]);
+            request = request.set_system(
+                caching::system_blocks(Some(config)).map_err(backoff::Error::permanent)?,
+            );
+
+            if let Some(fields) = config.additional_model_fields(model) {
+                request = request.additional_model_request_fields(json_to_document(fields));
             }
         }
 
+        let tool_config = request_tool_configuration(messages, config.as_ref())
+            .map_err(backoff::Error::permanent)?;
+        request = request.set_tool_config(tool_config);
+        let guardrail_config = guardrail::guardrail_configuration(config.as_ref())
+            .map_err(backoff::Error::permanent)?;
+        request = request.set_guardrail_config(guardrail_config);
+
         debug!("Sending request {} to model {}", request_id, model);
 
+        self.inner.chaos.before_request().await.map_err(|e| {
+            if e.is_retryable() {
+                backoff::Error::transient(e)
+            } else {
+                backoff::Error::permanent(e)
+            }
+        })?;
+
         // Execute the request
         let response = request.send().await.map_err(|e| {
             warn!("Request {} failed: {}", request_id, e);
-            if e.as_service_error().is_some() {
-                backoff::Error::transient(BedrockError::ServiceError(e.to_string()))
-            } else {
-                backoff::Error::permanent(BedrockError::RequestFailed(e.to_string()))
-            }
+            let regional = region::is_regional_failure(&e, |e| {
+                e.is_internal_server_exception()
+                    || e.is_service_unavailable_exception()
+                    || e.is_model_timeout_exception()
+            });
+            self.send_failure(
+                region_index,
+                regional,
+                e.as_service_error().is_some(),
+                e.to_string(),
+            )
         })?;
+        self.inner.router.record_success(region_index);
+        self.inner.prefixes.touch(model, config.as_ref());
+        record_aws_request_id(response.request_id());
 
         debug!("Request {} completed successfully", request_id);
 
         // Parse response
-        let content = response
+        let message = response
             .output()
             .as_ref()
-            .and_then(|output| output.as_message())
-            .and_then(|msg| msg.content().first())
-            .and_then(|block| block.as_text())
+            .and_then(|output| output.as_message().ok())
             .ok_or_else(|| {
                 backoff::Error::permanent(BedrockError::InvalidResponse(
-                    "No text content in response".to_string(),
+                    "No message in response".to_string(),
                 ))
             })?;
+        let message =
+            UniversalMessage::from_bedrock_message(message).map_err(backoff::Error::permanent)?;
 
-        let usage = response.usage().map(|u| TokenUsage {
-            input_tokens: u.input_tokens() as usize,
-            output_tokens: u.output_tokens() as usize,
-            total_tokens: u.total_tokens() as usize,
-            estimated_cost: calculate_cost(
-                u.input_tokens() as usize,
-                u.output_tokens() as usize,
-                model,
-            ),
-            model: model.to_string(),
+        if matches!(
+            response.stop_reason(),
+            aws_sdk_bedrockruntime::types::StopReason::GuardrailIntervened
+        ) {
+            let intervention = GuardrailIntervention::from_trace(
+                config.as_ref().unwrap_or(&GenerationConfig::default()),
+                message.content,
+                response.trace().and_then(|trace| trace.guardrail()),
+            );
+            warn!(
+                "Guardrail {} intervened in request {}",
+                intervention.guardrail_identifier, request_id
+            );
+            return Err(backoff::Error::permanent(intervention.into()));
+        }
+
+        let usage = response.usage().map(|u| {
+            let cache_read = u.cache_read_input_tokens().unwrap_or(0) as usize;
+            let cache_write = u.cache_write_input_tokens().unwrap_or(0) as usize;
+            TokenUsage {
+                input_tokens: u.input_tokens() as usize,
+                output_tokens: u.output_tokens() as usize,
+                total_tokens: u.total_tokens() as usize,
+                estimated_cost: calculate_cost(
+                    u.input_tokens() as usize,
+                    u.output_tokens() as usize,
+                    model,
+                ) + calculate_cache_cost(cache_read, cache_write, model),
+                model: model.to_string(),
+                cache_read_input_tokens: cache_read,
+                cache_write_input_tokens: cache_write,
+            }
         });
+        self.inner.rate_limiter.settle(
+            model,
+            reserved,
+            usage.as_ref().map_or(reserved, |u| {
+                u32::try_from(u.total_tokens).unwrap_or(u32::MAX)
+            }),
+        );
+
+        let mut generated = GenerationResponse::builder(model)
+            .id(request_id)
+            .content(message.content)
+            .finish_reason(response.stop_reason().as_str());
+        for tool_use in message.tool_uses {
+            generated = generated.tool_use(tool_use);
+        }
+        if let Some(usage) = usage {
+            generated = generated.usage(usage);
+        }
+        Ok(generated.build())
+    }
+
+    /// Embed `texts` with a Titan or Cohere embedding model
+    ///
+    /// Returns one embedding per text, in order. See
+    /// [`embed_with_config`](Self::embed_with_config).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `model` is not a supported embedding model or a
+    /// batch fails after retries.
+    pub async fn embed(&self, model: &str, texts: &[String]) -> Result<Vec<Embedding>> {
+        self.embed_with_config(model, texts, &EmbeddingConfig::default())
+            .await
+    }
+
+    /// Embed `texts` with a Titan or Cohere embedding model and the given options
+    ///
+    /// Texts are sent in batches as large as the model accepts, several
+    /// batches at a time, and each batch is retried on its own.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `model` is not a supported embedding model or a
+    /// batch fails after retries.
+    #[instrument(skip(self, texts, config), fields(model = %model, text_count = texts.len()))]
+    pub async fn embed_with_config(
+        &self,
+        model: &str,
+        texts: &[String],
+        config: &EmbeddingConfig,
+    ) -> Result<Vec<Embedding>> {
+        let family = embedding::EmbeddingFamily::detect(model)?;
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let start = std::time::Instant::now();
+        {
+            let mut metrics = self.inner.metrics.write();
+            metrics.total_requests += 1;
+            metrics.active_requests += 1;
+        }
+
+        let batch_size = family.batch_size();
+        // Built up front so the stream holds no borrowing closure, keeping it `Send`
+        let batches: Vec<_> = texts
+            .chunks(batch_size)
+            .enumerate()
+            .map(|(batch, chunk)| {
+                self._embed_batch_with_retry(model, family, chunk, config, batch * batch_size)
+            })
+            .collect();
+        let result: Result<Vec<Embedding>> = futures::stream::iter(batches)
+            .buffered(self.inner.config.pool_size.max(1))
+            .try_concat()
+            .await;
+
+        {
+            let mut metrics = self.inner.metrics.write();
+            metrics.active_requests -= 1;
+            match &result {
+                Ok(embeddings) => {
+                    metrics.successful_requests += 1;
+                    metrics.total_input_tokens += embeddings
+                        .iter()
+                        .filter_map(|embedding| embedding.input_tokens)
+                        .sum::<usize>() as u64;
+                }
+                Err(_) => metrics.failed_requests += 1,
+            }
+            metrics.total_latency_ms += start.elapsed().as_millis() as u64;
+        }
+
+        result
+    }
 
-        Ok(GenerationResponse {
-            id: request_id,
-            content: content.to_string(),
-            model: model.to_string(),
-            usage,
-            metadata: HashMap::new(),
-            timestamp: Utc::now(),
-            finish_reason: response
-                .stop_reason()
-                .map(|r| r.as_str().to_string())
-                .unwrap_or_else(|| "unknown".to_string()),
-        })
+    async fn _embed_batch_with_retry(
+        &self,
+        model: &str,
+        family: embedding::EmbeddingFamily,
+        texts: &[String],
+        config: &EmbeddingConfig,
+        offset: usize,
+    ) -> Result<Vec<Embedding>> {
+        let operation = || async { self._embed_batch_once(model, family, texts, config).await };
+        let embedded = backoff::future::retry(self.inner.retry_policy.clone(), operation).await?;
+        Ok(embedded
+            .into_iter()
+            .enumerate()
+            .map(|(index, (vector, input_tokens))| Embedding {
+                index: offset + index,
+                vector,
+                input_tokens,
+            })
+            .collect())
+    }
+
+    async fn _embed_batch_once(
+        &self,
+        model: &str,
+        family: embedding::EmbeddingFamily,
+        texts: &[String],
+        config: &EmbeddingConfig,
+    ) -> Result<Vec<(Vec<f32>, Option<usize>)>, backoff::Error<BedrockError>> {
+        self.inner
+            .chaos
+            .before_acquire()
+            .map_err(backoff::Error::permanent)?;
+
+        let tokens = texts.iter().map(|text| text.len() / 4).sum::<usize>();
+        self.wait_for_rate_limit(model, u32::try_from(tokens).unwrap_or(u32::MAX))
+            .await
+            .map_err(backoff::Error::permanent)?;
+
+        let _permit =
+            self.inner.semaphore.acquire().await.map_err(|e| {
+                backoff::Error::permanent(BedrockError::PoolExhausted(e.to_string()))
+            })?;
+
+        let region_index = self
+            .select_region(model)
+            .map_err(backoff::Error::permanent)?;
+        let clients = &self.inner.pools[region_index];
+        let client = &clients[Uuid::new_v4().as_u128() as usize % clients.len()];
+
+        let body = family
+            .request_body(texts, config)
+            .map_err(backoff::Error::permanent)?;
+
+        self.inner.chaos.before_request().await.map_err(|e| {
+            if e.is_retryable() {
+                backoff::Error::transient(e)
+            } else {
+                backoff::Error::permanent(e)
+            }
+        })?;
+
+        let response = client
+            .invoke_model()
+            .model_id(model)
+            .content_type("application/json")
+            .accept("application/json")
+            .body(Blob::new(body))
+            .send()
+            .await
+            .map_err(|e| {
+                warn!("Embedding request to {} failed: {}", model, e);
+                let regional = region::is_regional_failure(&e, |e| {
+                    e.is_internal_server_exception()
+                        || e.is_service_unavailable_exception()
+                        || e.is_model_timeout_exception()
+                });
+                self.send_failure(
+                    region_index,
+                    regional,
+                    e.as_service_error().is_some(),
+                    e.to_string(),
+                )
+            })?;
+        self.inner.router.record_success(region_index);
+
+        family
+            .parse_response(response.body().as_ref(), texts.len())
+            .map_err(backoff::Error::permanent)
+    }
+
+    /// Record a failed send against its region and decide whether to retry it
+    ///
+    /// Service errors are retried, and so are regional failures when another
+    /// region can take over.
+    fn send_failure(
+        &self,
+        region_index: usize,
+        regional: bool,
+        service_error: bool,
+        message: String,
+    ) -> backoff::Error<BedrockError> {
+        if regional {
+            self.inner.router.record_failure(region_index);
+        }
+        if service_error {
+            backoff::Error::transient(BedrockError::ServiceError(message))
+        } else if regional && self.inner.router.is_multi_region() {
+            backoff::Error::transient(BedrockError::RequestFailed(message))
+        } else {
+            backoff::Error::permanent(BedrockError::RequestFailed(message))
+        }
     }
 
     /// Stream a text response using the specified model
@@ -297,6 +823,44 @@ impl UniversalBedrockClient {
         messages: Vec<UniversalMessage>,
         config: Option<GenerationConfig>,
     ) -> Result<impl Stream<Item = Result<StreamChunk>>> {
+        if !self.supports_streaming(model) {
+            warn!(
+                "{} cannot stream, falling back to a non-streaming request",
+                model
+            );
+            let response = self.generate_text(model, messages, config).await?;
+            return Ok(Either::Right(futures::stream::iter(
+                probe::non_streaming_chunks(response),
+            )));
+        }
+
+        let features = RequestFeatures::detect(&messages, config.as_ref(), true);
+        let model = self.negotiate(model, &features)?;
+        Ok(Either::Left(
+            self.start_stream(&model, messages, config).await?,
+        ))
+    }
+
+    /// Send a streaming request to `model` as is
+    #[instrument(
+        name = "bedrock.converse_stream",
+        skip(self, messages, config),
+        fields(aws_request_id = tracing::field::Empty)
+    )]
+    async fn start_stream(
+        &self,
+        model: &str,
+        messages: Vec<UniversalMessage>,
+        config: Option<GenerationConfig>,
+    ) -> Result<impl Stream<Item = Result<StreamChunk>>> {
+        self.inner.chaos.before_acquire()?;
+        let reserved = self
+            .inner
+            .rate_limiter
+            .estimate_tokens(&messages, config.as_ref());
+        self.wait_for_rate_limit(model, reserved).await?;
+        self.inner.chaos.before_request().await?;
+
         let _permit = self
             .inner
             .semaphore
@@ -304,17 +868,20 @@ impl UniversalBedrockClient {
             .await
             .context("Failed to acquire semaphore permit")?;
 
+        let region_index = self.select_region(model)?;
+        let clients = &self.inner.pools[region_index];
         let client_index = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)?
             .as_nanos() as usize
-            % self.inner.clients.len();
-        let client = &self.inner.clients[client_index];
+            % clients.len();
+        let client = &clients[client_index];
 
         // Convert messages to Bedrock format
-        let bedrock_messages = messages
+        let mut bedrock_messages = messages
             .iter()
             .map(|msg| msg.to_bedrock_message())
             .collect::<Result<Vec<_>, _>>()?;
+        caching::mark_cached_prefix(&mut bedrock_messages, config.as_ref())?;
 
         // Build the request
         let mut request = client
@@ -328,21 +895,316 @@ impl UniversalBedrockClient {
                 .set_max_tokens(config.max_tokens.map(|t| t as i32))
                 .set_temperature(config.temperature)
                 .set_top_p(config.top_p)
+                .set_stop_sequences(
+                    (!config.stop_sequences.is_empty()).then(|| config.stop_sequences.clone()),
+                )
                 .build();
             request = request.inference_config(inference_config);
 
-            if let Some(system) = &config.system_prompt {
-                let system_block = SystemContentBlock::Text(system.clone());
-                request = request.system(vec![system_block]);
+            request = request.set_system(caching::system_blocks(Some(config))?);
+
+            if let Some(fields) = config.additional_model_fields(model) {
+                request = request.additional_model_request_fields(json_to_document(fields));
             }
         }
+        request = request.set_tool_config(request_tool_configuration(&messages, config.as_ref())?);
+        request = request
+            .set_guardrail_config(guardrail::guardrail_stream_configuration(config.as_ref())?);
 
-        let response = request
-            .send()
+        let sent_at = std::time::Instant::now();
+        let response = request.send().await;
+        match &response {
+            Ok(_) => self.inner.router.record_success(region_index),
+            Err(e) => {
+                let regional = region::is_regional_failure(e, |e| {
+                    e.is_internal_server_exception()
+                        || e.is_service_unavailable_exception()
+                        || e.is_model_timeout_exception()
+                });
+                if regional {
+                    self.inner.router.record_failure(region_index);
+                }
+            }
+        }
+        let response = response.context("Failed to start streaming request")?;
+        record_aws_request_id(response.request_id());
+        let warm_prefix = self
+            .inner
+            .prefixes
+            .touch(model, config.as_ref())
+            .unwrap_or(false);
+
+        let chaos = Arc::clone(&self.inner.chaos);
+        let metrics = Arc::clone(&self.inner.metrics);
+        let first_token_model = model.to_string();
+        let mut first_token = true;
+        let stream = StreamingResponse::new(text_deltas(response.stream), model.to_string())
+            .inspect(move |chunk| {
+                if first_token && chunk.is_ok() {
+                    first_token = false;
+                    metrics.write().record_first_token(
+                        &first_token_model,
+                        sent_at.elapsed().as_millis() as u64,
+                        warm_prefix,
+                    );
+                }
+            })
+            .filter(move |chunk| {
+                let drop = chunk.is_ok() && chaos.should_drop_chunk();
+                futures::future::ready(!drop)
+            });
+        let stop_sequences = config
+            .map(|config| config.stop_sequences)
+            .unwrap_or_default();
+        Ok(StopSequenceDetector::new(stop_sequences).apply(stream))
+    }
+
+    /// Write `config`'s system prompt to `model`'s prompt cache ahead of traffic
+    ///
+    /// Sends a one-token request with the system prompt (and tools) cached,
+    /// so the first real request reads the prefix from the cache instead of
+    /// processing it. The cache lasts [`PROMPT_CACHE_TTL`] after its last
+    /// use; call this again before then to keep an idle bot warm. Returns
+    /// `false` without sending anything if the prefix is already warm.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `config` has no system prompt or the request fails,
+    /// including when the model does not support prompt caching.
+    pub async fn warm_prompt_prefix(&self, model: &str, config: &GenerationConfig) -> Result<bool> {
+        if config.system_prompt.is_none() {
+            return Err(BedrockError::InvalidInput(
+                "Prompt prefix warming needs a system prompt".to_string(),
+            ));
+        }
+        let config = GenerationConfig {
+            max_tokens: Some(1),
+            cache_messages: None,
+            stop_sequences: Vec::new(),
+            ..config.clone()
+        }
+        .with_cached_system_prompt();
+        if self.inner.prefixes.is_warm(model, &config) {
+            debug!("Prompt prefix for {} is already warm", model);
+            return Ok(false);
+        }
+
+        let response = self
+            .generate_text(
+                model,
+                vec![UniversalMessage::user("Reply with OK.")],
+                Some(config),
+            )
+            .await?;
+        info!(
+            "Warmed prompt prefix for {}: {} tokens written to the cache",
+            model,
+            response
+                .usage
+                .as_ref()
+                .map_or(0, |usage| usage.cache_write_input_tokens)
+        );
+        Ok(true)
+    }
+
+    /// Wait until `model`'s rate limits have room for a request, recording throttling in metrics
+    async fn wait_for_rate_limit(&self, model: &str, tokens: u32) -> Result<()> {
+        match self.inner.rate_limiter.acquire(model, tokens).await {
+            Ok(waited) if waited.is_zero() => Ok(()),
+            Ok(waited) => {
+                self.inner
+                    .metrics
+                    .write()
+                    .record_throttle(model, waited.as_millis() as u64);
+                Ok(())
+            }
+            Err(e) => {
+                warn!("Rate limiter rejected request to {}: {}", model, e);
+                self.inner.metrics.write().record_rate_limited(model);
+                Err(e)
+            }
+        }
+    }
+
+    /// Choose the region for a request, recording the choice in metrics
+    fn select_region(&self, model: &str) -> Result<usize> {
+        let (index, event) = self.inner.router.select(model)?;
+        let mut metrics = self.inner.metrics.write();
+        metrics.record_region(&self.inner.router.regions()[index].region);
+        if let Some(event) = event {
+            metrics.record_failover(event);
+        }
+        Ok(index)
+    }
+
+    /// Check a request against the model's capabilities before sending it
+    fn negotiate(&self, model: &str, features: &RequestFeatures) -> Result<String> {
+        self.inner
+            .registry
+            .read()
+            .negotiate(model, features, self.inner.config.capability_routing)
+    }
+
+    /// Whether `model` streams, as far as declared and probed capabilities tell
+    fn supports_streaming(&self, model: &str) -> bool {
+        self.inner
+            .registry
+            .read()
+            .get(model)
+            .is_none_or(|info| info.effective_capabilities().supports_streaming)
+    }
+
+    /// Probe which features `model` actually supports and record the results
+    ///
+    /// Each feature is checked with a one-token request. Probes that fail for
+    /// reasons unrelated to the feature, such as throttling, are inconclusive
+    /// and leave earlier results in place.
+    pub async fn probe_capabilities(&self, model: &str) -> ProbedCapabilities {
+        let config = GenerationConfig {
+            max_tokens: Some(1),
+            temperature: Some(0.0),
+            top_p: None,
+            system_prompt: None,
+            seed: None,
+            tools: Vec::new(),
+            tool_choice: None,
+            guardrail_identifier: None,
+            guardrail_version: None,
+            cache_system_prompt: false,
+            cache_messages: None,
+            stop_sequences: Vec::new(),
+        };
+        let prompt = "Reply with OK.";
+
+        let streaming = match self
+            .start_stream(
+                model,
+                vec![UniversalMessage::user(prompt)],
+                Some(config.clone()),
+            )
             .await
-            .context("Failed to start streaming request")?;
+        {
+            Ok(stream) => match Box::pin(stream).next().await {
+                Some(Err(e)) => probe::verdict(&e),
+                _ => Some(true),
+            },
+            Err(e) => probe::verdict(&e),
+        };
 
-        Ok(StreamingResponse::new(response.stream, model.to_string()))
+        let tool = ToolDefinition::new(
+            "probe",
+            "Does nothing",
+            serde_json::json!({ "type": "object", "properties": {} }),
+        );
+        let tools = self
+            .probe_request(
+                model,
+                vec![UniversalMessage::user(prompt)],
+                config.clone().with_tools(vec![tool]),
+            )
+            .await;
+
+        let image = base64::engine::general_purpose::STANDARD
+            .decode(probe::PROBE_IMAGE)
+            .unwrap_or_default();
+        let vision = self
+            .probe_request(
+                model,
+                vec![UniversalMessage::user_with_image(
+                    prompt,
+                    image,
+                    ImageFormat::Png,
+                )],
+                config,
+            )
+            .await;
+
+        let probed = ProbedCapabilities {
+            streaming,
+            tools,
+            vision,
+            probed_at: Some(Utc::now()),
+        };
+        info!(
+            "Probed {}: streaming {:?}, tools {:?}, vision {:?}",
+            model, probed.streaming, probed.tools, probed.vision
+        );
+        if !self.inner.registry.write().record_probe(model, &probed) {
+            debug!("{} is not registered; probe results not recorded", model);
+        }
+        probed
+    }
+
+    /// Probe the configured models, or every available registered model
+    pub async fn probe_models(&self) -> HashMap<String, ProbedCapabilities> {
+        let models: Vec<String> = if self.inner.config.probes.models.is_empty() {
+            self.inner
+                .registry
+                .read()
+                .list_available()
+                .into_iter()
+                .map(|info| info.id.clone())
+                .collect()
+        } else {
+            self.inner.config.probes.models.clone()
+        };
+
+        let mut results = HashMap::with_capacity(models.len());
+        for model in models {
+            let probed = self.probe_capabilities(&model).await;
+            results.insert(model, probed);
+        }
+        results
+    }
+
+    /// Probe results recorded for `model`
+    pub fn probed_capabilities(&self, model: &str) -> Option<ProbedCapabilities> {
+        self.inner
+            .registry
+            .read()
+            .get(model)
+            .map(|info| info.probed.clone())
+    }
+
+    /// One probe request, sent without retries
+    async fn probe_request(
+        &self,
+        model: &str,
+        messages: Vec<UniversalMessage>,
+        config: GenerationConfig,
+    ) -> Option<bool> {
+        match self
+            ._generate_text_once(model, &messages, &Some(config), Uuid::new_v4())
+            .await
+        {
+            Ok(_) => Some(true),
+            Err(backoff::Error::Permanent(e) | backoff::Error::Transient { err: e, .. }) => {
+                probe::verdict(&e)
+            }
+        }
+    }
+
+    /// Probe in the background, once or every `interval_seconds`, until the client is dropped
+    fn spawn_probes(&self) {
+        let inner = Arc::downgrade(&self.inner);
+        let interval = self
+            .inner
+            .config
+            .probes
+            .interval_seconds
+            .map(Duration::from_secs);
+        tokio::spawn(async move {
+            loop {
+                let Some(inner) = inner.upgrade() else {
+                    break;
+                };
+                Self { inner }.probe_models().await;
+                let Some(interval) = interval else {
+                    break;
+                };
+                tokio::time::sleep(interval).await;
+            }
+        });
     }
 
     /// Get current client metrics
@@ -355,6 +1217,11 @@ impl UniversalBedrockClient {
         &self.inner.config
     }
 
+    /// Get counts of faults injected by chaos mode
+    pub fn chaos_stats(&self) -> ChaosStats {
+        self.inner.chaos.stats()
+    }
+
     /// Health check for the client
     ///
     /// # Errors
@@ -364,17 +1231,21 @@ impl UniversalBedrockClient {
         let start = std::time::Instant::now();
 
         // Try a simple request to check connectivity
-        let test_message = UniversalMessage {
-            role: MessageRole::User,
-            content: "Hello".to_string(),
-            metadata: HashMap::new(),
-        };
+        let test_message = UniversalMessage::user("Hello");
 
         let config = GenerationConfig {
             max_tokens: Some(1),
             temperature: Some(0.0),
             top_p: None,
             system_prompt: None,
+            seed: None,
+            tools: Vec::new(),
+            tool_choice: None,
+            guardrail_identifier: None,
+            guardrail_version: None,
+            cache_system_prompt: false,
+            cache_messages: None,
+            stop_sequences: Vec::new(),
         };
 
         match self
@@ -397,19 +1268,92 @@ impl UniversalBedrockClient {
     }
 }
 
+/// Tool configuration for a request, from the generation config and message metadata
+fn request_tool_configuration(
+    messages: &[UniversalMessage],
+    config: Option<&GenerationConfig>,
+) -> Result<Option<aws_sdk_bedrockruntime::types::ToolConfiguration>> {
+    let mut tools = config.map(|c| c.tools.clone()).unwrap_or_default();
+    tools.extend(messages.iter().flat_map(UniversalMessage::tools));
+    tools::tool_configuration(&tools, config.and_then(|c| c.tool_choice.as_ref()))
+}
+
+/// Convert a JSON value into the document type used for model-specific fields
+fn json_to_document(value: serde_json::Value) -> aws_smithy_types::Document {
+    use aws_smithy_types::{Document, Number};
+
+    match value {
+        serde_json::Value::Null => Document::Null,
+        serde_json::Value::Bool(b) => Document::Bool(b),
+        serde_json::Value::Number(n) => Document::Number(if let Some(u) = n.as_u64() {
+            Number::PosInt(u)
+        } else if let Some(i) = n.as_i64() {
+            Number::NegInt(i)
+        } else {
+            Number::Float(n.as_f64().unwrap_or_default())
+        }),
+        serde_json::Value::String(s) => Document::String(s),
+        serde_json::Value::Array(items) => {
+            Document::Array(items.into_iter().map(json_to_document).collect())
+        }
+        serde_json::Value::Object(map) => Document::Object(
+            map.into_iter()
+                .map(|(k, v)| (k, json_to_document(v)))
+                .collect(),
+        ),
+    }
+}
+
+/// Convert a document, such as a tool call's input, into a JSON value
+fn document_to_json(document: &aws_smithy_types::Document) -> serde_json::Value {
+    use aws_smithy_types::{Document, Number};
+
+    match document {
+        Document::Null => serde_json::Value::Null,
+        Document::Bool(b) => serde_json::Value::Bool(*b),
+        Document::Number(Number::PosInt(u)) => serde_json::Value::from(*u),
+        Document::Number(Number::NegInt(i)) => serde_json::Value::from(*i),
+        Document::Number(Number::Float(f)) => serde_json::Number::from_f64(*f)
+            .map_or(serde_json::Value::Null, serde_json::Value::Number),
+        Document::String(s) => serde_json::Value::String(s.clone()),
+        Document::Array(items) => items.iter().map(document_to_json).collect(),
+        Document::Object(map) => serde_json::Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), document_to_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+/// Record the ID Bedrock assigned to a request on the current span
+fn record_aws_request_id(request_id: Option<&str>) {
+    if let Some(request_id) = request_id {
+        tracing::Span::current().record("aws_request_id", request_id);
+    }
+}
+
 /// Calculate estimated cost for token usage
-fn calculate_cost(input_tokens: usize, output_tokens: usize, model: &str) -> f64 {
+fn token_rates(model: &str) -> (f64, f64) {
     // Cost per 1K tokens (example rates, update with actual pricing)
-    let (input_rate, output_rate) = match model {
+    match model {
         m if m.contains("claude-3-opus") => (0.015, 0.075),
         m if m.contains("claude-3-5-sonnet") => (0.003, 0.015),
         m if m.contains("claude-3-haiku") => (0.00025, 0.00125),
         _ => (0.001, 0.002), // Default rates
-    };
+    }
+}
 
+pub(crate) fn calculate_cost(input_tokens: usize, output_tokens: usize, model: &str) -> f64 {
+    let (input_rate, output_rate) = token_rates(model);
     (input_tokens as f64 / 1000.0 * input_rate) + (output_tokens as f64 / 1000.0 * output_rate)
 }
 
+/// Cost of prompt-cache traffic: reads bill at a tenth of the input rate, writes at 125%
+fn calculate_cache_cost(read_tokens: usize, write_tokens: usize, model: &str) -> f64 {
+    let (input_rate, _) = token_rates(model);
+    (read_tokens as f64 * 0.1 + write_tokens as f64 * 1.25) / 1000.0 * input_rate
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -419,6 +1363,10 @@ mod tests {
         let cost = calculate_cost(1000, 500, "anthropic.claude-3-5-sonnet-20241022-v2:0");
         assert!(cost > 0.0);
         assert!(cost < 1.0); // Reasonable bounds
+
+        let model = "anthropic.claude-3-5-sonnet-20241022-v2:0";
+        let cached = calculate_cache_cost(1000, 0, model);
+        assert!((cached - calculate_cost(100, 0, model)).abs() < 1e-12);
     }
 
     #[tokio::test]
@@ -429,13 +1377,53 @@ mod tests {
         assert!(config.pool_size > 0);
     }
 
+    #[tokio::test]
+    async fn test_batch_keeps_order_and_counts_failures() {
+        let config = BedrockConfig::default()
+            .with_chaos(ChaosConfig::enabled().with_pool_exhaustion_rate(1.0));
+        let client = UniversalBedrockClient::with_config(config).await.unwrap();
+        let request =
+            |model: &str| GenerationRequest::new(model, vec![UniversalMessage::user("Hi")]);
+
+        let results = client
+            .generate_batch(vec![
+                request(DEFAULT_HAIKU_MODEL),
+                request(DEFAULT_CLAUDE_MODEL),
+            ])
+            .await;
+
+        assert_eq!(results.len(), 2);
+        assert!(results
+            .iter()
+            .all(|result| matches!(result, Err(BedrockError::RequestFailed(_)))));
+        let metrics = client.metrics();
+        assert_eq!(metrics.total_batches, 1);
+        assert_eq!(metrics.failed_batch_items, 2);
+        assert_eq!(metrics.failed_requests, 2);
+        assert!(client.generate_batch(Vec::new()).await.is_empty());
+    }
+
     #[test]
-    fn test_message_conversion() {
-        let msg = UniversalMessage {
-            role: MessageRole::User,
-            content: "Test message".to_string(),
-            metadata: HashMap::new(),
+    fn test_json_to_document() {
+        use aws_smithy_types::{Document, Number};
+
+        let doc = json_to_document(serde_json::json!({ "seed": 7, "bias": -1 }));
+        let Document::Object(map) = doc else {
+            panic!("expected object");
         };
+        assert_eq!(map["seed"], Document::Number(Number::PosInt(7)));
+        assert_eq!(map["bias"], Document::Number(Number::NegInt(-1)));
+    }
+
+    #[test]
+    fn test_document_to_json_round_trip() {
+        let value = serde_json::json!({ "city": "Paris", "days": [1, -2], "strict": true });
+        assert_eq!(document_to_json(&json_to_document(value.clone())), value);
+    }
+
+    #[test]
+    fn test_message_conversion() {
+        let msg = UniversalMessage::user("Test message");
 
         let bedrock_msg = msg.to_bedrock_message().unwrap();
         // Verify the conversion worked
@@ -7,17 +7,31 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::config::GenerationConfig;
+use crate::content::{ContentPart, DocumentFormat, ImageFormat};
 use crate::error::{BedrockError, Result};
+use crate::model::TOOLS_METADATA_KEY;
+use crate::tools::{ToolDefinition, ToolResult, ToolUse};
 
 /// Universal message format for the bot
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct UniversalMessage {
     /// Message role (user, assistant, system)
     pub role: MessageRole,
     /// Message content
     pub content: String,
+    /// Images, documents, and further text sent after [`content`](Self::content)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<ContentPart>,
     /// Optional metadata
     pub metadata: HashMap<String, serde_json::Value>,
+    /// Tool calls requested by the assistant in this turn
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_uses: Vec<ToolUse>,
+    /// Results of earlier tool calls, sent in a user turn
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_results: Vec<ToolResult>,
 }
 
 /// Message role enumeration
@@ -38,7 +52,10 @@ impl UniversalMessage {
         Self {
             role: MessageRole::User,
             content: content.into(),
+            parts: Vec::new(),
             metadata: HashMap::new(),
+            tool_uses: Vec::new(),
+            tool_results: Vec::new(),
         }
     }
 
@@ -47,7 +64,10 @@ impl UniversalMessage {
         Self {
             role: MessageRole::Assistant,
             content: content.into(),
+            parts: Vec::new(),
             metadata: HashMap::new(),
+            tool_uses: Vec::new(),
+            tool_results: Vec::new(),
         }
     }
 
@@ -56,10 +76,72 @@ impl UniversalMessage {
         Self {
             role: MessageRole::System,
             content: content.into(),
+            parts: Vec::new(),
             metadata: HashMap::new(),
+            tool_uses: Vec::new(),
+            tool_results: Vec::new(),
         }
     }
 
+    /// Create a user message asking about an image
+    pub fn user_with_image(
+        content: impl Into<String>,
+        image: impl Into<Vec<u8>>,
+        format: ImageFormat,
+    ) -> Self {
+        Self::user(content).with_image(image, format)
+    }
+
+    /// Attach an image
+    pub fn with_image(self, image: impl Into<Vec<u8>>, format: ImageFormat) -> Self {
+        self.with_part(ContentPart::image(image, format))
+    }
+
+    /// Attach a document
+    pub fn with_document(
+        self,
+        name: impl Into<String>,
+        data: impl Into<Vec<u8>>,
+        format: DocumentFormat,
+    ) -> Self {
+        self.with_part(ContentPart::document(name, data, format))
+    }
+
+    /// Append a content block
+    pub fn with_part(mut self, part: ContentPart) -> Self {
+        self.parts.push(part);
+        self
+    }
+
+    /// Whether the message carries an image
+    pub fn has_images(&self) -> bool {
+        self.parts.iter().any(ContentPart::is_image)
+    }
+
+    /// Create a user turn answering the assistant's tool calls
+    pub fn tool_results(results: Vec<ToolResult>) -> Self {
+        Self {
+            tool_results: results,
+            ..Self::user("")
+        }
+    }
+
+    /// Offer tools to the model with this message
+    ///
+    /// The definitions are stored under [`TOOLS_METADATA_KEY`] and sent along
+    /// with [`GenerationConfig::tools`](crate::GenerationConfig::tools).
+    pub fn with_tools(self, tools: &[ToolDefinition]) -> Self {
+        self.with_metadata(TOOLS_METADATA_KEY, serde_json::json!(tools))
+    }
+
+    /// Tools offered through [`with_tools`](Self::with_tools)
+    pub fn tools(&self) -> Vec<ToolDefinition> {
+        self.metadata
+            .get(TOOLS_METADATA_KEY)
+            .and_then(|tools| serde_json::from_value(tools.clone()).ok())
+            .unwrap_or_default()
+    }
+
     /// Add metadata to the message
     pub fn with_metadata(mut self, key: impl Into<String>, value: serde_json::Value) -> Self {
         self.metadata.insert(key.into(), value);
@@ -68,7 +150,22 @@ impl UniversalMessage {
 
     /// Convert to AWS Bedrock message format
     pub fn to_bedrock_message(&self) -> Result<BedrockMessage> {
-        let content = ContentBlock::Text(self.content.clone());
+        // Bedrock rejects empty text blocks, so turns with other content carry none
+        let mut content = Vec::new();
+        if !self.content.is_empty()
+            || (self.parts.is_empty() && self.tool_uses.is_empty() && self.tool_results.is_empty())
+        {
+            content.push(ContentBlock::Text(self.content.clone()));
+        }
+        for part in &self.parts {
+            content.push(part.to_bedrock()?);
+        }
+        for tool_use in &self.tool_uses {
+            content.push(ContentBlock::ToolUse(tool_use.to_bedrock()?));
+        }
+        for result in &self.tool_results {
+            content.push(ContentBlock::ToolResult(result.to_bedrock()?));
+        }
 
         let role = match self.role {
             MessageRole::User => aws_sdk_bedrockruntime::types::ConversationRole::User,
@@ -80,11 +177,11 @@ impl UniversalMessage {
             }
         };
 
-        Ok(BedrockMessage::builder()
+        BedrockMessage::builder()
             .role(role)
-            .content(content)
+            .set_content(Some(content))
             .build()
-            .map_err(|e| BedrockError::InvalidInput(format!("Failed to build message: {}", e)))?)
+            .map_err(|e| BedrockError::InvalidInput(format!("Failed to build message: {e}")))
     }
 
     /// Create from AWS Bedrock message
@@ -99,22 +196,72 @@ impl UniversalMessage {
             }
         };
 
-        let content = message
+        let content: String = message
+            .content()
+            .iter()
+            .filter_map(|block| block.as_text().ok())
+            .map(String::as_str)
+            .collect();
+        let tool_uses: Vec<ToolUse> = message
+            .content()
+            .iter()
+            .filter_map(|block| block.as_tool_use().ok())
+            .map(ToolUse::from_bedrock)
+            .collect();
+        let parts: Vec<ContentPart> = message
             .content()
-            .first()
-            .and_then(|block| block.as_text().ok())
-            .ok_or_else(|| BedrockError::InvalidResponse("No text content found".to_string()))?;
+            .iter()
+            .filter_map(ContentPart::from_bedrock)
+            .collect();
+        if content.is_empty() && parts.is_empty() && tool_uses.is_empty() {
+            return Err(BedrockError::InvalidResponse(
+                "No text, media, or tool use content found".to_string(),
+            ));
+        }
 
         Ok(Self {
             role,
-            content: content.to_string(),
+            content,
+            parts,
             metadata: HashMap::new(),
+            tool_uses,
+            tool_results: Vec::new(),
         })
     }
 }
 
+/// One text generation request, as sent in a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationRequest {
+    /// Model to generate with
+    pub model: String,
+    /// Conversation to respond to
+    pub messages: Vec<UniversalMessage>,
+    /// Generation settings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<GenerationConfig>,
+}
+
+impl GenerationRequest {
+    /// Create a request to `model` with default generation settings
+    pub fn new(model: impl Into<String>, messages: Vec<UniversalMessage>) -> Self {
+        Self {
+            model: model.into(),
+            messages,
+            config: None,
+        }
+    }
+
+    /// Set the generation settings
+    pub fn with_config(mut self, config: GenerationConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+}
+
 /// Response from text generation
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct GenerationResponse {
     /// Unique response ID
     pub id: Uuid,
@@ -130,14 +277,46 @@ pub struct GenerationResponse {
     pub timestamp: DateTime<Utc>,
     /// Reason the generation finished
     pub finish_reason: String,
+    /// Tool calls the model requested
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_uses: Vec<ToolUse>,
 }
 
 impl GenerationResponse {
+    /// Start building a response generated by `model`
+    pub fn builder(model: impl Into<String>) -> GenerationResponseBuilder {
+        GenerationResponseBuilder {
+            response: Self {
+                id: Uuid::new_v4(),
+                content: String::new(),
+                model: model.into(),
+                usage: None,
+                metadata: HashMap::new(),
+                timestamp: Utc::now(),
+                finish_reason: "stop".to_string(),
+                tool_uses: Vec::new(),
+            },
+        }
+    }
+
     /// Check if the response was truncated due to token limits
     pub fn is_truncated(&self) -> bool {
         self.finish_reason == "max_tokens" || self.finish_reason == "length"
     }
 
+    /// Check if the model stopped to wait for tool results
+    pub fn requires_tool_use(&self) -> bool {
+        !self.tool_uses.is_empty()
+    }
+
+    /// The assistant turn to append to the conversation before sending tool results
+    pub fn to_message(&self) -> UniversalMessage {
+        UniversalMessage {
+            tool_uses: self.tool_uses.clone(),
+            ..UniversalMessage::assistant(self.content.clone())
+        }
+    }
+
     /// Check if the response was stopped by content filtering
     pub fn is_content_filtered(&self) -> bool {
         self.finish_reason == "content_filter"
@@ -154,8 +333,67 @@ impl GenerationResponse {
     }
 }
 
+/// Builder for [`GenerationResponse`]
+///
+/// Fields left unset default to a random ID, the current time, empty
+/// content, and a `stop` finish reason.
+#[derive(Debug, Clone)]
+pub struct GenerationResponseBuilder {
+    response: GenerationResponse,
+}
+
+impl GenerationResponseBuilder {
+    /// Set the response ID
+    pub fn id(mut self, id: Uuid) -> Self {
+        self.response.id = id;
+        self
+    }
+
+    /// Set the generated content
+    pub fn content(mut self, content: impl Into<String>) -> Self {
+        self.response.content = content.into();
+        self
+    }
+
+    /// Add a tool call requested by the model
+    pub fn tool_use(mut self, tool_use: ToolUse) -> Self {
+        self.response.tool_uses.push(tool_use);
+        self
+    }
+
+    /// Set token usage
+    pub fn usage(mut self, usage: TokenUsage) -> Self {
+        self.response.usage = Some(usage);
+        self
+    }
+
+    /// Add a metadata entry
+    pub fn metadata(mut self, key: impl Into<String>, value: serde_json::Value) -> Self {
+        self.response.metadata.insert(key.into(), value);
+        self
+    }
+
+    /// Set the response timestamp
+    pub fn timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.response.timestamp = timestamp;
+        self
+    }
+
+    /// Set the finish reason
+    pub fn finish_reason(mut self, reason: impl Into<String>) -> Self {
+        self.response.finish_reason = reason.into();
+        self
+    }
+
+    /// Finish building
+    pub fn build(self) -> GenerationResponse {
+        self.response
+    }
+}
+
 /// Token usage information
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct TokenUsage {
     /// Input tokens
     pub input_tokens: usize,
@@ -167,6 +405,12 @@ pub struct TokenUsage {
     pub estimated_cost: f64,
     /// Model identifier
     pub model: String,
+    /// Input tokens served from the prompt cache
+    #[serde(default)]
+    pub cache_read_input_tokens: usize,
+    /// Input tokens written to the prompt cache
+    #[serde(default)]
+    pub cache_write_input_tokens: usize,
 }
 
 impl TokenUsage {
@@ -183,12 +427,15 @@ impl TokenUsage {
             total_tokens: input_tokens + output_tokens,
             estimated_cost,
             model: model.into(),
+            cache_read_input_tokens: 0,
+            cache_write_input_tokens: 0,
         }
     }
 }
 
 /// Stream chunk for streaming responses
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct StreamChunk {
     /// Chunk ID
     pub id: Uuid,
@@ -232,6 +479,7 @@ impl StreamChunk {
 
 /// Conversation context for multi-turn interactions
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct ConversationContext {
     /// Conversation ID
     pub id: String,
@@ -336,6 +584,62 @@ mod tests {
         assert_eq!(converted_back.content, "Test message");
     }
 
+    #[test]
+    fn test_tool_turns_conversion() {
+        let tool_use = ToolUse {
+            id: "call-1".to_string(),
+            name: "get_weather".to_string(),
+            input: serde_json::json!({ "city": "Paris" }),
+        };
+        let response = GenerationResponse::builder("test-model")
+            .tool_use(tool_use.clone())
+            .finish_reason("tool_use")
+            .build();
+        assert!(response.requires_tool_use());
+
+        let assistant = response.to_message().to_bedrock_message().unwrap();
+        assert_eq!(assistant.content().len(), 1);
+        assert!(assistant.content()[0].is_tool_use());
+        let converted_back = UniversalMessage::from_bedrock_message(&assistant).unwrap();
+        assert_eq!(converted_back.tool_uses, vec![tool_use]);
+
+        let results = UniversalMessage::tool_results(vec![ToolResult::success(
+            "call-1",
+            serde_json::json!({ "temp_c": 21 }),
+        )]);
+        let user = results.to_bedrock_message().unwrap();
+        assert_eq!(user.content().len(), 1);
+        assert!(user.content()[0].is_tool_result());
+    }
+
+    #[test]
+    fn test_image_message_conversion() {
+        let msg = UniversalMessage::user_with_image(
+            "What is this?",
+            b"\x89PNG".to_vec(),
+            ImageFormat::Png,
+        )
+        .with_document("notes", b"# Notes".to_vec(), DocumentFormat::Md);
+        assert!(msg.has_images());
+
+        let bedrock_msg = msg.to_bedrock_message().unwrap();
+        assert_eq!(bedrock_msg.content().len(), 3);
+        assert!(bedrock_msg.content()[1].is_image());
+        assert!(bedrock_msg.content()[2].is_document());
+
+        let converted_back = UniversalMessage::from_bedrock_message(&bedrock_msg).unwrap();
+        assert_eq!(converted_back.content, "What is this?");
+        assert_eq!(converted_back.parts, msg.parts);
+    }
+
+    #[test]
+    fn test_message_tools_metadata() {
+        let tool = ToolDefinition::new("search", "Search the web", serde_json::json!({}));
+        let msg = UniversalMessage::user("Find it").with_tools(std::slice::from_ref(&tool));
+        assert_eq!(msg.tools(), vec![tool]);
+        assert!(UniversalMessage::user("Hi").tools().is_empty());
+    }
+
     #[test]
     fn test_system_message_conversion_error() {
         let system_msg = UniversalMessage::system("System prompt");
@@ -346,15 +650,10 @@ mod tests {
     #[test]
     fn test_generation_response() {
         let usage = TokenUsage::new(100, 50, "test-model", 0.01);
-        let response = GenerationResponse {
-            id: Uuid::new_v4(),
-            content: "Generated text".to_string(),
-            model: "test-model".to_string(),
-            usage: Some(usage),
-            metadata: HashMap::new(),
-            timestamp: Utc::now(),
-            finish_reason: "stop".to_string(),
-        };
+        let response = GenerationResponse::builder("test-model")
+            .content("Generated text")
+            .usage(usage)
+            .build();
 
         assert_eq!(response.total_tokens(), 150);
         assert_eq!(response.estimated_cost(), 0.01);
@@ -362,6 +661,20 @@ mod tests {
         assert!(!response.is_content_filtered());
     }
 
+    #[test]
+    fn test_generation_response_builder_defaults() {
+        let response = GenerationResponse::builder("test-model")
+            .finish_reason("max_tokens")
+            .metadata("region", serde_json::json!("us-east-1"))
+            .build();
+
+        assert_eq!(response.model, "test-model");
+        assert!(response.content.is_empty());
+        assert!(response.usage.is_none());
+        assert!(response.is_truncated());
+        assert_eq!(response.metadata["region"], "us-east-1");
+    }
+
     #[test]
     fn test_conversation_context() {
         let mut context = ConversationContext::new("test-conversation");
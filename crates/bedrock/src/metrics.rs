@@ -6,6 +6,11 @@ use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
+use crate::region::FailoverEvent;
+
+/// Failover events kept in [`BedrockMetrics::failover_events`]
+const MAX_FAILOVER_EVENTS: usize = 100;
+
 /// Comprehensive metrics for the Bedrock client
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BedrockMetrics {
@@ -29,6 +34,28 @@ pub struct BedrockMetrics {
     pub requests_by_model: HashMap<String, u64>,
     /// Error counts by type
     pub errors_by_type: HashMap<String, u64>,
+    /// Request counts by region
+    pub requests_by_region: HashMap<String, u64>,
+    /// Total traffic shifts between regions
+    pub total_failovers: u64,
+    /// Most recent traffic shifts between regions, oldest first
+    pub failover_events: Vec<FailoverEvent>,
+    /// Requests held back by the client-side rate limiter
+    pub throttled_requests: u64,
+    /// Requests failed because the rate limiter had no room in time
+    pub rate_limited_requests: u64,
+    /// Total time requests were held back by the rate limiter, in milliseconds
+    pub throttle_wait_ms: u64,
+    /// Held back and failed request counts by model
+    pub throttled_by_model: HashMap<String, u64>,
+    /// Time to first streamed token by model
+    pub first_token_by_model: HashMap<String, FirstTokenLatency>,
+    /// Batches sent with `generate_batch`
+    pub total_batches: u64,
+    /// Batch items that succeeded
+    pub successful_batch_items: u64,
+    /// Batch items that failed after retries
+    pub failed_batch_items: u64,
     /// Metrics collection start time
     pub start_time: DateTime<Utc>,
     /// Last updated time
@@ -50,6 +77,17 @@ impl BedrockMetrics {
             total_cost: 0.0,
             requests_by_model: HashMap::new(),
             errors_by_type: HashMap::new(),
+            requests_by_region: HashMap::new(),
+            total_failovers: 0,
+            failover_events: Vec::new(),
+            throttled_requests: 0,
+            rate_limited_requests: 0,
+            throttle_wait_ms: 0,
+            throttled_by_model: HashMap::new(),
+            first_token_by_model: HashMap::new(),
+            total_batches: 0,
+            successful_batch_items: 0,
+            failed_batch_items: 0,
             start_time: now,
             last_updated: now,
         }
@@ -142,6 +180,65 @@ impl BedrockMetrics {
         self.last_updated = Utc::now();
     }
 
+    /// Record a request sent to `region`
+    pub fn record_region(&mut self, region: &str) {
+        *self
+            .requests_by_region
+            .entry(region.to_string())
+            .or_insert(0) += 1;
+    }
+
+    /// Record a traffic shift between regions
+    pub fn record_failover(&mut self, event: FailoverEvent) {
+        self.total_failovers += 1;
+        if self.failover_events.len() == MAX_FAILOVER_EVENTS {
+            self.failover_events.remove(0);
+        }
+        self.failover_events.push(event);
+        self.last_updated = Utc::now();
+    }
+
+    /// Record a request to `model` that the rate limiter held back for `wait_ms`
+    pub fn record_throttle(&mut self, model: &str, wait_ms: u64) {
+        self.throttled_requests += 1;
+        self.throttle_wait_ms += wait_ms;
+        *self
+            .throttled_by_model
+            .entry(model.to_string())
+            .or_insert(0) += 1;
+        self.last_updated = Utc::now();
+    }
+
+    /// Record a request to `model` that the rate limiter failed
+    pub fn record_rate_limited(&mut self, model: &str) {
+        self.rate_limited_requests += 1;
+        *self
+            .throttled_by_model
+            .entry(model.to_string())
+            .or_insert(0) += 1;
+        self.last_updated = Utc::now();
+    }
+
+    /// Record the time to the first streamed token of a request to `model`
+    ///
+    /// `warm_prefix` is whether the request's system prompt was already in
+    /// the model's prompt cache.
+    pub fn record_first_token(&mut self, model: &str, latency_ms: u64, warm_prefix: bool) {
+        self.first_token_by_model
+            .entry(model.to_string())
+            .or_default()
+            .record(latency_ms, warm_prefix);
+        self.last_updated = Utc::now();
+    }
+
+    /// Record a finished batch of `succeeded` and `failed` items
+    pub fn record_batch(&mut self, succeeded: u64, failed: u64) {
+        self.total_batches += 1;
+        self.successful_batch_items += succeeded;
+        self.failed_batch_items += failed;
+        self.last_updated = Utc::now();
+    }
+
     /// Get the most frequently used model
     pub fn most_used_model(&self) -> Option<(&String, &u64)> {
         self.requests_by_model
@@ -185,6 +282,48 @@ impl Default for BedrockMetrics {
     }
 }
 
+/// Time-to-first-token totals for one model, split by prompt cache state
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FirstTokenLatency {
+    /// Streamed requests whose system prompt was already cached
+    pub warm_requests: u64,
+    /// Total time to first token of warm requests, in milliseconds
+    pub warm_total_ms: u64,
+    /// Streamed requests that processed their whole prompt
+    pub cold_requests: u64,
+    /// Total time to first token of cold requests, in milliseconds
+    pub cold_total_ms: u64,
+}
+
+impl FirstTokenLatency {
+    fn record(&mut self, latency_ms: u64, warm_prefix: bool) {
+        if warm_prefix {
+            self.warm_requests += 1;
+            self.warm_total_ms += latency_ms;
+        } else {
+            self.cold_requests += 1;
+            self.cold_total_ms += latency_ms;
+        }
+    }
+
+    /// Average time to first token with a cached prefix, in milliseconds
+    pub fn average_warm_ms(&self) -> Option<f64> {
+        (self.warm_requests > 0).then(|| self.warm_total_ms as f64 / self.warm_requests as f64)
+    }
+
+    /// Average time to first token without a cached prefix, in milliseconds
+    pub fn average_cold_ms(&self) -> Option<f64> {
+        (self.cold_requests > 0).then(|| self.cold_total_ms as f64 / self.cold_requests as f64)
+    }
+
+    /// How much sooner the first token arrives with a cached prefix, in milliseconds
+    ///
+    /// `None` until there are both warm and cold requests to compare.
+    pub fn improvement_ms(&self) -> Option<f64> {
+        Some(self.average_cold_ms()? - self.average_warm_ms()?)
+    }
+}
+
 /// Summary of key metrics for monitoring
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetricsSummary {
@@ -348,6 +487,30 @@ mod tests {
         assert_eq!(metrics.total_requests, 2);
         assert_eq!(metrics.failed_requests, 1);
         assert_eq!(metrics.success_rate(), 50.0);
+
+        metrics.record_throttle("test-model", 150);
+        metrics.record_rate_limited("test-model");
+        assert_eq!(metrics.throttled_requests, 1);
+        assert_eq!(metrics.rate_limited_requests, 1);
+        assert_eq!(metrics.throttle_wait_ms, 150);
+        assert_eq!(metrics.throttled_by_model["test-model"], 2);
+    }
+
+    #[test]
+    fn test_first_token_improvement() {
+        let mut metrics = BedrockMetrics::new();
+        metrics.record_first_token("test-model", 900, false);
+        assert_eq!(
+            metrics.first_token_by_model["test-model"].improvement_ms(),
+            None
+        );
+
+        metrics.record_first_token("test-model", 1100, false);
+        metrics.record_first_token("test-model", 300, true);
+        let latency = &metrics.first_token_by_model["test-model"];
+        assert_eq!(latency.average_cold_ms(), Some(1000.0));
+        assert_eq!(latency.average_warm_ms(), Some(300.0));
+        assert_eq!(latency.improvement_ms(), Some(700.0));
     }
 
     #[test]
@@ -378,6 +541,26 @@ mod tests {
         assert_eq!(summary.total_cost, 0.025);
     }
 
+    #[test]
+    fn test_failover_events_are_bounded() {
+        use crate::region::FailoverKind;
+
+        let mut metrics = BedrockMetrics::new();
+        for i in 0..=MAX_FAILOVER_EVENTS {
+            metrics.record_failover(FailoverEvent {
+                kind: FailoverKind::Failover,
+                model: format!("model{i}"),
+                from_region: "us-east-1".to_string(),
+                to_region: "us-west-2".to_string(),
+                timestamp: Utc::now(),
+            });
+        }
+
+        assert_eq!(metrics.total_failovers, MAX_FAILOVER_EVENTS as u64 + 1);
+        assert_eq!(metrics.failover_events.len(), MAX_FAILOVER_EVENTS);
+        assert_eq!(metrics.failover_events[0].model, "model1");
+    }
+
     #[test]
     fn test_most_used_model() {
         let mut metrics = BedrockMetrics::new();
@@ -3,6 +3,17 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::config::{CapabilityRouting, GenerationConfig};
+use crate::error::{BedrockError, Result};
+use crate::message::UniversalMessage;
+use crate::probe::ProbedCapabilities;
+
+/// Message metadata key listing images attached to a message
+pub const IMAGES_METADATA_KEY: &str = "images";
+
+/// Message metadata key listing tools offered to the model
+pub const TOOLS_METADATA_KEY: &str = "tools";
+
 /// Supported Claude models on Bedrock
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ClaudeModel {
@@ -44,6 +55,8 @@ impl ClaudeModel {
                 context_window: 200_000,
                 supports_vision: true,
                 supports_function_calling: true,
+                supports_seed: false,
+                supports_streaming: true,
                 input_cost_per_1k_tokens: 0.003,
                 output_cost_per_1k_tokens: 0.015,
                 description: "Most capable model for complex reasoning and analysis".to_string(),
@@ -53,6 +66,8 @@ impl ClaudeModel {
                 context_window: 200_000,
                 supports_vision: true,
                 supports_function_calling: true,
+                supports_seed: false,
+                supports_streaming: true,
                 input_cost_per_1k_tokens: 0.015,
                 output_cost_per_1k_tokens: 0.075,
                 description: "Most powerful model for complex tasks".to_string(),
@@ -62,6 +77,8 @@ impl ClaudeModel {
                 context_window: 200_000,
                 supports_vision: true,
                 supports_function_calling: false,
+                supports_seed: false,
+                supports_streaming: true,
                 input_cost_per_1k_tokens: 0.00025,
                 output_cost_per_1k_tokens: 0.00125,
                 description: "Fastest and most cost-effective model".to_string(),
@@ -115,6 +132,12 @@ pub struct ModelCapabilities {
     pub supports_vision: bool,
     /// Whether the model supports function calling
     pub supports_function_calling: bool,
+    /// Whether the model accepts a sampling seed
+    #[serde(default)]
+    pub supports_seed: bool,
+    /// Whether the model can stream its output
+    #[serde(default = "default_true")]
+    pub supports_streaming: bool,
     /// Input cost per 1K tokens in USD
     pub input_cost_per_1k_tokens: f64,
     /// Output cost per 1K tokens in USD
@@ -123,6 +146,108 @@ pub struct ModelCapabilities {
     pub description: String,
 }
 
+fn default_true() -> bool {
+    true
+}
+
+impl ModelCapabilities {
+    /// Every requested feature this model lacks
+    pub fn unsupported(&self, features: &RequestFeatures) -> Vec<FeatureMismatch> {
+        let mut missing = Vec::new();
+        if features.vision && !self.supports_vision {
+            missing.push(FeatureMismatch::Vision);
+        }
+        if features.tools && !self.supports_function_calling {
+            missing.push(FeatureMismatch::Tools);
+        }
+        if features.streaming && !self.supports_streaming {
+            missing.push(FeatureMismatch::Streaming);
+        }
+        if let Some(requested) = features.max_tokens.filter(|t| *t > self.max_tokens) {
+            missing.push(FeatureMismatch::MaxTokens {
+                requested,
+                limit: self.max_tokens,
+            });
+        }
+        missing
+    }
+}
+
+/// Features a request needs from the model
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestFeatures {
+    /// Image content is present
+    pub vision: bool,
+    /// Tools are offered to the model
+    pub tools: bool,
+    /// Output is streamed
+    pub streaming: bool,
+    /// Requested generation limit
+    pub max_tokens: Option<usize>,
+}
+
+impl RequestFeatures {
+    /// Derive the required features from a request
+    ///
+    /// Vision and tool use are detected from the
+    /// [`IMAGES_METADATA_KEY`] and [`TOOLS_METADATA_KEY`] message metadata;
+    /// image content parts and tools in the generation config also count.
+    pub fn detect(
+        messages: &[UniversalMessage],
+        config: Option<&GenerationConfig>,
+        streaming: bool,
+    ) -> Self {
+        let has = |key: &str| {
+            messages.iter().any(|m| {
+                m.metadata.get(key).is_some_and(|value| match value {
+                    serde_json::Value::Array(items) => !items.is_empty(),
+                    serde_json::Value::Null | serde_json::Value::Bool(false) => false,
+                    _ => true,
+                })
+            })
+        };
+
+        Self {
+            vision: has(IMAGES_METADATA_KEY) || messages.iter().any(UniversalMessage::has_images),
+            tools: has(TOOLS_METADATA_KEY) || config.is_some_and(|c| !c.tools.is_empty()),
+            streaming,
+            max_tokens: config.and_then(|c| c.max_tokens),
+        }
+    }
+}
+
+/// A requested feature the model does not support
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "feature", rename_all = "snake_case")]
+pub enum FeatureMismatch {
+    /// Image input
+    Vision,
+    /// Tool use
+    Tools,
+    /// Streaming output
+    Streaming,
+    /// Generation limit above the model's maximum
+    MaxTokens {
+        /// Tokens requested
+        requested: usize,
+        /// Model maximum
+        limit: usize,
+    },
+}
+
+impl std::fmt::Display for FeatureMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Vision => write!(f, "vision"),
+            Self::Tools => write!(f, "tools"),
+            Self::Streaming => write!(f, "streaming"),
+            Self::MaxTokens { requested, limit } => {
+                write!(f, "max_tokens {} (limit {})", requested, limit)
+            }
+        }
+    }
+}
+
 /// Task types for model recommendation
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TaskType {
@@ -163,6 +288,16 @@ pub struct ModelInfo {
     pub version: String,
     /// Provider (e.g., "anthropic")
     pub provider: String,
+    /// Results of capability probes, which override [`capabilities`](Self::capabilities)
+    #[serde(default)]
+    pub probed: ProbedCapabilities,
+}
+
+impl ModelInfo {
+    /// Declared capabilities corrected by probe results
+    pub fn effective_capabilities(&self) -> ModelCapabilities {
+        self.probed.apply(&self.capabilities)
+    }
 }
 
 impl ModelRegistry {
@@ -181,6 +316,7 @@ impl ModelRegistry {
                 available: true,
                 version: "1.0".to_string(),
                 provider: "anthropic".to_string(),
+                probed: ProbedCapabilities::default(),
             };
             registry.models.insert(model.id().to_string(), info);
         }
@@ -210,6 +346,15 @@ impl ModelRegistry {
         }
     }
 
+    /// Record probe results for a model, returning `false` if it is not registered
+    pub fn record_probe(&mut self, id: &str, probed: &ProbedCapabilities) -> bool {
+        let Some(model) = self.models.get_mut(id) else {
+            return false;
+        };
+        model.probed.merge(probed);
+        true
+    }
+
     /// Get models that support a specific capability
     pub fn models_with_capability(&self, capability: ModelCapability) -> Vec<&ModelInfo> {
         self.models
@@ -218,10 +363,62 @@ impl ModelRegistry {
             .collect()
     }
 
+    /// Pick the model to send a request to
+    ///
+    /// Returns `model` if it supports every requested feature. Otherwise,
+    /// with [`CapabilityRouting::Reroute`], the cheapest available model
+    /// that does is chosen instead. Models missing from the registry are
+    /// passed through unchecked.
+    pub fn negotiate(
+        &self,
+        model: &str,
+        features: &RequestFeatures,
+        routing: CapabilityRouting,
+    ) -> Result<String> {
+        let Some(info) = self.get(model) else {
+            return Ok(model.to_string());
+        };
+        let missing = info.effective_capabilities().unsupported(features);
+        if missing.is_empty() {
+            return Ok(model.to_string());
+        }
+
+        if routing == CapabilityRouting::Reroute {
+            let mut capable: Vec<&ModelInfo> = self
+                .list_available()
+                .into_iter()
+                .filter(|m| m.effective_capabilities().unsupported(features).is_empty())
+                .collect();
+            capable.sort_by(|a, b| {
+                a.capabilities
+                    .input_cost_per_1k_tokens
+                    .total_cmp(&b.capabilities.input_cost_per_1k_tokens)
+                    .then_with(|| a.id.cmp(&b.id))
+            });
+            if let Some(replacement) = capable.first() {
+                tracing::info!(
+                    "Rerouting request from {} to {} ({} unsupported)",
+                    model,
+                    replacement.id,
+                    missing.len()
+                );
+                return Ok(replacement.id.clone());
+            }
+        }
+
+        Err(BedrockError::UnsupportedFeature {
+            model: model.to_string(),
+            missing,
+        })
+    }
+
     fn supports_capability(&self, model: &ModelInfo, capability: ModelCapability) -> bool {
         match capability {
-            ModelCapability::Vision => model.capabilities.supports_vision,
-            ModelCapability::FunctionCalling => model.capabilities.supports_function_calling,
+            ModelCapability::Vision => model.effective_capabilities().supports_vision,
+            ModelCapability::FunctionCalling => {
+                model.effective_capabilities().supports_function_calling
+            }
+            ModelCapability::Seed => model.capabilities.supports_seed,
             ModelCapability::LargeContext => model.capabilities.context_window >= 100_000,
             ModelCapability::LowCost => {
                 model.capabilities.input_cost_per_1k_tokens < 0.001
@@ -238,6 +435,8 @@ pub enum ModelCapability {
     Vision,
     /// Function calling support
     FunctionCalling,
+    /// Reproducible sampling via a seed
+    Seed,
     /// Large context window (>100k tokens)
     LargeContext,
     /// Low cost (< $0.001 input, < $0.002 output per 1k tokens)
@@ -303,6 +502,107 @@ mod tests {
         assert!(sonnet_info.capabilities.supports_vision);
     }
 
+    #[test]
+    fn test_negotiation_reports_every_mismatch() {
+        let registry = ModelRegistry::new();
+        let features = RequestFeatures {
+            tools: true,
+            max_tokens: Some(500_000),
+            ..RequestFeatures::default()
+        };
+
+        let err = registry
+            .negotiate(
+                ClaudeModel::Claude3Haiku.id(),
+                &features,
+                CapabilityRouting::Strict,
+            )
+            .unwrap_err();
+        match &err {
+            BedrockError::UnsupportedFeature { missing, .. } => {
+                assert_eq!(missing.len(), 2);
+                assert_eq!(missing[0], FeatureMismatch::Tools);
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+        assert!(err.to_string().contains("tools, max_tokens 500000"));
+        assert!(!err.is_retryable());
+    }
+
+    #[test]
+    fn test_negotiation_reroutes_when_allowed() {
+        let registry = ModelRegistry::new();
+        let features = RequestFeatures {
+            tools: true,
+            ..RequestFeatures::default()
+        };
+
+        let model = registry
+            .negotiate(
+                ClaudeModel::Claude3Haiku.id(),
+                &features,
+                CapabilityRouting::Reroute,
+            )
+            .unwrap();
+        assert_eq!(model, ClaudeModel::Claude35Sonnet.id());
+
+        let unknown = registry
+            .negotiate("custom-model", &features, CapabilityRouting::Strict)
+            .unwrap();
+        assert_eq!(unknown, "custom-model");
+    }
+
+    #[test]
+    fn test_negotiation_uses_probe_results() {
+        let mut registry = ModelRegistry::new();
+        let probed = ProbedCapabilities {
+            tools: Some(false),
+            ..ProbedCapabilities::default()
+        };
+        assert!(registry.record_probe(ClaudeModel::Claude35Sonnet.id(), &probed));
+        assert!(!registry.record_probe("custom-model", &probed));
+
+        let features = RequestFeatures {
+            tools: true,
+            ..RequestFeatures::default()
+        };
+        let model = registry
+            .negotiate(
+                ClaudeModel::Claude3Haiku.id(),
+                &features,
+                CapabilityRouting::Reroute,
+            )
+            .unwrap();
+        assert_eq!(model, ClaudeModel::Claude3Opus.id());
+        assert!(registry
+            .negotiate(
+                ClaudeModel::Claude35Sonnet.id(),
+                &features,
+                CapabilityRouting::Strict,
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_feature_detection() {
+        let messages = vec![UniversalMessage::user("What is this?")
+            .with_metadata(
+                IMAGES_METADATA_KEY,
+                serde_json::json!(["s3://bucket/cat.png"]),
+            )
+            .with_metadata(TOOLS_METADATA_KEY, serde_json::json!([]))];
+        let config = GenerationConfig {
+            max_tokens: Some(100),
+            ..GenerationConfig::default()
+        };
+
+        let features = RequestFeatures::detect(&messages, Some(&config), true);
+        assert!(features.vision);
+        assert!(!features.tools);
+        assert!(features.streaming);
+        assert_eq!(features.max_tokens, Some(100));
+    }
+
     #[test]
     fn test_capability_filtering() {
         let registry = ModelRegistry::new();
@@ -0,0 +1,207 @@
+//! Capability probes
+//!
+//! [`ModelCapabilities`] describe what a model supports on paper. Probes send
+//! tiny requests to find out what actually works for this account and
+//! region, and the results are recorded in the [`ModelRegistry`](crate::ModelRegistry)
+//! where they override the declared capabilities. Requests then degrade
+//! before they are sent: streaming falls back to a single non-streaming
+//! chunk, and tool or image requests are rerouted or rejected according to
+//! [`CapabilityRouting`](crate::CapabilityRouting).
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::error::{BedrockError, Result};
+use crate::message::{GenerationResponse, StreamChunk, TokenUsage};
+use crate::model::ModelCapabilities;
+
+/// Stream chunk metadata key naming the feature that was degraded to serve the request
+pub const DEGRADED_METADATA_KEY: &str = "degraded";
+
+/// A 1x1 transparent PNG, sent by the vision probe
+pub(crate) const PROBE_IMAGE: &str =
+    "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA60e6kgAAAABJRU5ErkJggg==";
+
+/// When and what to probe
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct ProbeConfig {
+    /// Probe once the client is created
+    pub enabled: bool,
+
+    /// Probe again this often; probes only once when unset
+    #[validate(range(min = 60))]
+    pub interval_seconds: Option<u64>,
+
+    /// Models to probe; every available registered model when empty
+    pub models: Vec<String>,
+}
+
+/// A feature checked by a probe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbedFeature {
+    /// Streaming output
+    Streaming,
+    /// Tool use
+    Tools,
+    /// Image input
+    Vision,
+}
+
+/// Probe results for one model
+///
+/// `None` means the feature was not probed or the probe was inconclusive,
+/// for example because of throttling; the declared capability then applies.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbedCapabilities {
+    /// Whether streaming works
+    pub streaming: Option<bool>,
+    /// Whether tool use works
+    pub tools: Option<bool>,
+    /// Whether image input works
+    pub vision: Option<bool>,
+    /// When the model was last probed
+    pub probed_at: Option<DateTime<Utc>>,
+}
+
+impl ProbedCapabilities {
+    /// The result for one feature
+    pub fn get(&self, feature: ProbedFeature) -> Option<bool> {
+        match feature {
+            ProbedFeature::Streaming => self.streaming,
+            ProbedFeature::Tools => self.tools,
+            ProbedFeature::Vision => self.vision,
+        }
+    }
+
+    /// Record the result for one feature; inconclusive results keep the previous one
+    pub fn set(&mut self, feature: ProbedFeature, supported: Option<bool>) {
+        let slot = match feature {
+            ProbedFeature::Streaming => &mut self.streaming,
+            ProbedFeature::Tools => &mut self.tools,
+            ProbedFeature::Vision => &mut self.vision,
+        };
+        if supported.is_some() {
+            *slot = supported;
+        }
+    }
+
+    /// Fold newer results into these, keeping earlier conclusive ones
+    pub fn merge(&mut self, newer: &Self) {
+        for feature in [
+            ProbedFeature::Streaming,
+            ProbedFeature::Tools,
+            ProbedFeature::Vision,
+        ] {
+            self.set(feature, newer.get(feature));
+        }
+        self.probed_at = newer.probed_at.or(self.probed_at);
+    }
+
+    /// `declared` with probed results taking precedence
+    pub fn apply(&self, declared: &ModelCapabilities) -> ModelCapabilities {
+        ModelCapabilities {
+            supports_streaming: self.streaming.unwrap_or(declared.supports_streaming),
+            supports_function_calling: self.tools.unwrap_or(declared.supports_function_calling),
+            supports_vision: self.vision.unwrap_or(declared.supports_vision),
+            ..declared.clone()
+        }
+    }
+}
+
+/// Interpret a probe request's error
+///
+/// The service rejecting the request as invalid means the feature is
+/// unsupported; anything else, such as throttling or a network error, says
+/// nothing about the feature.
+pub(crate) fn verdict(error: &BedrockError) -> Option<bool> {
+    let message = error.to_string();
+    let rejected = matches!(
+        error,
+        BedrockError::ServiceError(_) | BedrockError::RequestFailed(_)
+    ) && (message.contains("ValidationException")
+        || message.to_ascii_lowercase().contains("not support"));
+    rejected.then_some(false)
+}
+
+/// Serve a non-streaming response as a stream: one content chunk marked as
+/// degraded, then the final chunk
+pub(crate) fn non_streaming_chunks(response: GenerationResponse) -> Vec<Result<StreamChunk>> {
+    let mut chunk = StreamChunk::content(response.content);
+    chunk.metadata.insert(
+        DEGRADED_METADATA_KEY.to_string(),
+        serde_json::json!(ProbedFeature::Streaming),
+    );
+    let usage = response
+        .usage
+        .unwrap_or_else(|| TokenUsage::new(0, 0, response.model, 0.0));
+    vec![Ok(chunk), Ok(StreamChunk::final_chunk(usage))]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ClaudeModel;
+
+    #[test]
+    fn test_probed_results_override_declared() {
+        let declared = ClaudeModel::Claude3Haiku.capabilities();
+        let probed = ProbedCapabilities {
+            streaming: Some(false),
+            ..ProbedCapabilities::default()
+        };
+        let effective = probed.apply(&declared);
+        assert!(!effective.supports_streaming);
+        assert_eq!(effective.supports_vision, declared.supports_vision);
+    }
+
+    #[test]
+    fn test_merge_keeps_conclusive_results() {
+        let mut known = ProbedCapabilities {
+            tools: Some(true),
+            ..ProbedCapabilities::default()
+        };
+        known.merge(&ProbedCapabilities {
+            streaming: Some(false),
+            probed_at: Some(Utc::now()),
+            ..ProbedCapabilities::default()
+        });
+        assert_eq!(known.tools, Some(true));
+        assert_eq!(known.streaming, Some(false));
+        assert!(known.probed_at.is_some());
+    }
+
+    #[test]
+    fn test_non_streaming_chunks() {
+        let response = GenerationResponse::builder("model")
+            .content("Hello")
+            .build();
+        let chunks = non_streaming_chunks(response);
+        assert_eq!(chunks.len(), 2);
+
+        let content = chunks[0].as_ref().unwrap();
+        assert_eq!(content.content, "Hello");
+        assert_eq!(content.metadata[DEGRADED_METADATA_KEY], "streaming");
+        let last = chunks[1].as_ref().unwrap();
+        assert!(last.is_final);
+        assert_eq!(last.usage.as_ref().unwrap().model, "model");
+    }
+
+    #[test]
+    fn test_verdict() {
+        let rejected = BedrockError::ServiceError(
+            "ValidationException: This model doesn't support tool use.".to_string(),
+        );
+        assert_eq!(verdict(&rejected), Some(false));
+        assert_eq!(
+            verdict(&BedrockError::RateLimited("slow down".to_string())),
+            None
+        );
+        assert_eq!(
+            verdict(&BedrockError::Timeout("no answer".to_string())),
+            None
+        );
+    }
+}
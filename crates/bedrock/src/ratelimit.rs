@@ -0,0 +1,346 @@
+//! Client-side rate limiting per model
+//!
+//! Bedrock enforces requests-per-minute and tokens-per-minute quotas per
+//! model, and a request that exceeds them is throttled only after it has
+//! taken a connection and a retry. The [`RateLimiter`] keeps a token bucket
+//! for each limit and holds requests back before they are sent. Like
+//! Bedrock, it reserves the prompt plus `max_tokens` up front and gives back
+//! what the response did not use; streamed requests keep their reservation.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::debug;
+use validator::Validate;
+
+use crate::config::GenerationConfig;
+use crate::error::{BedrockError, Result};
+use crate::message::UniversalMessage;
+
+/// Per-minute quotas for one model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelRateLimit {
+    /// Requests per minute; unlimited when unset
+    pub requests_per_minute: Option<u32>,
+    /// Input plus output tokens per minute; unlimited when unset
+    pub tokens_per_minute: Option<u32>,
+}
+
+impl ModelRateLimit {
+    /// Limit both requests and tokens per minute
+    pub fn new(requests_per_minute: u32, tokens_per_minute: u32) -> Self {
+        Self {
+            requests_per_minute: Some(requests_per_minute),
+            tokens_per_minute: Some(tokens_per_minute),
+        }
+    }
+}
+
+/// Rate limiting configuration
+///
+/// Limiting is a no-op unless `enabled` is set. Models without an entry in
+/// `models` use `default_limit`, or are not limited when that is unset.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Master switch for rate limiting
+    pub enabled: bool,
+
+    /// Limits for models without their own entry
+    pub default_limit: Option<ModelRateLimit>,
+
+    /// Limits by model ID
+    pub models: HashMap<String, ModelRateLimit>,
+
+    /// Longest a request may wait for capacity before failing, in milliseconds
+    pub max_wait_ms: u64,
+
+    /// Output tokens to reserve for requests that do not set `max_tokens`
+    #[validate(range(min = 1))]
+    pub default_max_tokens: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            default_limit: None,
+            models: HashMap::new(),
+            max_wait_ms: 30_000,
+            default_max_tokens: 4096,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Create an enabled configuration with no limits yet
+    pub fn enabled() -> Self {
+        Self {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    /// Set the limits for models without their own entry
+    pub fn with_default_limit(mut self, limit: ModelRateLimit) -> Self {
+        self.default_limit = Some(limit);
+        self
+    }
+
+    /// Set the limits for `model`
+    pub fn with_model_limit(mut self, model: impl Into<String>, limit: ModelRateLimit) -> Self {
+        self.models.insert(model.into(), limit);
+        self
+    }
+
+    /// Set how long a request may wait for capacity
+    pub fn with_max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait_ms = max_wait.as_millis() as u64;
+        self
+    }
+
+    /// The limits that apply to `model`, if any
+    pub fn limit_for(&self, model: &str) -> Option<ModelRateLimit> {
+        if !self.enabled {
+            return None;
+        }
+        self.models.get(model).copied().or(self.default_limit)
+    }
+}
+
+/// A per-minute allowance that refills continuously
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    available: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn new(per_minute: u32, now: Instant) -> Self {
+        let capacity = f64::from(per_minute.max(1));
+        Self {
+            capacity,
+            available: capacity,
+            refilled: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.available = (self.available + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.refilled = now;
+    }
+
+    /// Time until `amount` is available; amounts above capacity wait for a full bucket
+    fn wait_for(&self, amount: f64) -> Duration {
+        let missing = amount.min(self.capacity) - self.available;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing * 60.0 / self.capacity)
+        }
+    }
+}
+
+/// Request and token buckets of one model
+#[derive(Debug)]
+struct ModelBuckets {
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+}
+
+/// Holds requests back until the model's quotas have room for them
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<String, ModelBuckets>>,
+}
+
+impl RateLimiter {
+    /// Create a limiter from configuration
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the limiter configuration
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    /// Reserve one request and `tokens` tokens for `model`, waiting for room
+    ///
+    /// Returns how long the request was held back.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BedrockError::RateLimited`] when room would not free up
+    /// within [`RateLimitConfig::max_wait_ms`].
+    pub async fn acquire(&self, model: &str, tokens: u32) -> Result<Duration> {
+        let Some(limit) = self.config.limit_for(model) else {
+            return Ok(Duration::ZERO);
+        };
+        let max_wait = Duration::from_millis(self.config.max_wait_ms);
+        let mut waited = Duration::ZERO;
+
+        loop {
+            let wait = {
+                let now = Instant::now();
+                let mut buckets = self.buckets.lock();
+                let buckets = buckets
+                    .entry(model.to_string())
+                    .or_insert_with(|| ModelBuckets {
+                        requests: limit.requests_per_minute.map(|rpm| Bucket::new(rpm, now)),
+                        tokens: limit.tokens_per_minute.map(|tpm| Bucket::new(tpm, now)),
+                    });
+
+                let mut wait = Duration::ZERO;
+                if let Some(bucket) = &mut buckets.requests {
+                    bucket.refill(now);
+                    wait = wait.max(bucket.wait_for(1.0));
+                }
+                if let Some(bucket) = &mut buckets.tokens {
+                    bucket.refill(now);
+                    wait = wait.max(bucket.wait_for(f64::from(tokens)));
+                }
+
+                if wait.is_zero() {
+                    if let Some(bucket) = &mut buckets.requests {
+                        bucket.available -= 1.0;
+                    }
+                    if let Some(bucket) = &mut buckets.tokens {
+                        bucket.available -= f64::from(tokens).min(bucket.capacity);
+                    }
+                    return Ok(waited);
+                }
+                wait
+            };
+
+            if waited + wait > max_wait {
+                return Err(BedrockError::RateLimited(format!(
+                    "{} is over its rate limit; room frees up in {:?}",
+                    model, wait
+                )));
+            }
+            debug!("Holding back request to {} for {:?}", model, wait);
+            tokio::time::sleep(wait).await;
+            waited += wait;
+        }
+    }
+
+    /// Correct the token reservation for `model` once actual usage is known
+    ///
+    /// Unused tokens are returned to the bucket; tokens beyond the reservation
+    /// are taken from it.
+    pub fn settle(&self, model: &str, reserved: u32, used: u32) {
+        let mut buckets = self.buckets.lock();
+        let Some(bucket) = buckets
+            .get_mut(model)
+            .and_then(|buckets| buckets.tokens.as_mut())
+        else {
+            return;
+        };
+        bucket.refill(Instant::now());
+        let reserved = f64::from(reserved).min(bucket.capacity);
+        bucket.available = (bucket.available + reserved - f64::from(used)).min(bucket.capacity);
+    }
+
+    /// Tokens to reserve for a request: about four characters per prompt token plus `max_tokens`
+    pub(crate) fn estimate_tokens(
+        &self,
+        messages: &[UniversalMessage],
+        config: Option<&GenerationConfig>,
+    ) -> u32 {
+        let prompt_chars: usize = messages
+            .iter()
+            .map(|message| message.content.len())
+            .chain(
+                config
+                    .and_then(|c| c.system_prompt.as_ref())
+                    .map(String::len),
+            )
+            .sum();
+        let output = config
+            .and_then(|c| c.max_tokens)
+            .map_or(self.config.default_max_tokens, |t| {
+                u32::try_from(t).unwrap_or(u32::MAX)
+            });
+        u32::try_from(prompt_chars / 4)
+            .unwrap_or(u32::MAX)
+            .saturating_add(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(limit: ModelRateLimit) -> RateLimiter {
+        RateLimiter::new(
+            RateLimitConfig::enabled()
+                .with_model_limit("limited", limit)
+                .with_max_wait(Duration::ZERO),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_disabled_limiter_never_waits() {
+        let limiter = RateLimiter::new(
+            RateLimitConfig::default().with_default_limit(ModelRateLimit::new(1, 1)),
+        );
+        for _ in 0..5 {
+            assert_eq!(limiter.acquire("any", 1000).await.unwrap(), Duration::ZERO);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_requests_per_minute() {
+        let limiter = limiter(ModelRateLimit {
+            requests_per_minute: Some(2),
+            tokens_per_minute: None,
+        });
+        limiter.acquire("limited", 0).await.unwrap();
+        limiter.acquire("limited", 0).await.unwrap();
+        assert!(matches!(
+            limiter.acquire("limited", 0).await,
+            Err(BedrockError::RateLimited(_))
+        ));
+        // Other models are not limited
+        limiter.acquire("unlimited", 0).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tokens_are_settled_against_usage() {
+        let limiter = limiter(ModelRateLimit {
+            requests_per_minute: None,
+            tokens_per_minute: Some(1000),
+        });
+        limiter.acquire("limited", 800).await.unwrap();
+        assert!(limiter.acquire("limited", 800).await.is_err());
+
+        // The first request used less than it reserved
+        limiter.settle("limited", 800, 200);
+        limiter.acquire("limited", 800).await.unwrap();
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        let limiter = RateLimiter::new(RateLimitConfig::enabled());
+        let messages = vec![UniversalMessage::user("a".repeat(400))];
+        assert_eq!(limiter.estimate_tokens(&messages, None), 100 + 4096);
+
+        let config = GenerationConfig {
+            max_tokens: Some(50),
+            system_prompt: Some("b".repeat(40)),
+            ..GenerationConfig::default()
+        };
+        assert_eq!(limiter.estimate_tokens(&messages, Some(&config)), 110 + 50);
+    }
+}
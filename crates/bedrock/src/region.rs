@@ -0,0 +1,387 @@
+//! Multi-region failover
+//!
+//! Regions are tried in their configured order. After `failure_threshold`
+//! consecutive regional errors or timeouts a region is marked down and
+//! traffic shifts to the next region that serves the requested model or
+//! inference profile. Once `recovery_seconds` have passed the region is
+//! tried again, and traffic shifts back as soon as a request succeeds.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use aws_sdk_bedrockruntime::error::SdkError;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use validator::Validate;
+
+use crate::error::{BedrockError, Result};
+
+/// Failover configuration
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct FailoverConfig {
+    /// Regions in order of preference; empty uses only the client's region
+    pub regions: Vec<RegionConfig>,
+
+    /// Consecutive regional failures that mark a region down
+    #[validate(range(min = 1, max = 100))]
+    pub failure_threshold: u32,
+
+    /// How long a region stays down before it is tried again
+    #[validate(range(min = 1, max = 3600))]
+    pub recovery_seconds: u64,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        Self {
+            regions: Vec::new(),
+            failure_threshold: 3,
+            recovery_seconds: 60,
+        }
+    }
+}
+
+impl FailoverConfig {
+    /// Fail over between `regions`, in order of preference
+    pub fn new(regions: Vec<RegionConfig>) -> Self {
+        Self {
+            regions,
+            ..Default::default()
+        }
+    }
+
+    /// Set the consecutive failures that mark a region down
+    pub fn with_failure_threshold(mut self, threshold: u32) -> Self {
+        self.failure_threshold = threshold;
+        self
+    }
+
+    /// Set how long a region stays down before it is tried again
+    pub fn with_recovery(mut self, recovery: Duration) -> Self {
+        self.recovery_seconds = recovery.as_secs();
+        self
+    }
+}
+
+/// A region and the client pool serving it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegionConfig {
+    /// AWS region name
+    pub region: String,
+
+    /// Clients in this region's pool; defaults to the client's `pool_size`
+    #[serde(default)]
+    pub pool_size: Option<usize>,
+
+    /// Models and inference profiles enabled in this region; empty allows any
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+impl RegionConfig {
+    /// A region serving every model
+    pub fn new(region: impl Into<String>) -> Self {
+        Self {
+            region: region.into(),
+            pool_size: None,
+            models: Vec::new(),
+        }
+    }
+
+    /// Set the size of this region's client pool
+    pub fn with_pool_size(mut self, size: usize) -> Self {
+        self.pool_size = Some(size);
+        self
+    }
+
+    /// Restrict the region to the given models and inference profiles
+    pub fn with_models(mut self, models: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.models = models.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Whether requests for `model` can be sent to this region
+    ///
+    /// Model ARNs only resolve in the region they name, and geographic
+    /// inference profiles (`us.`, `eu.`, `apac.`, ...) only in their
+    /// geography.
+    pub fn serves(&self, model: &str) -> bool {
+        if !self.models.is_empty() {
+            return self.models.iter().any(|m| m == model);
+        }
+        if model.starts_with("arn:") {
+            return model.split(':').nth(3) == Some(self.region.as_str());
+        }
+        match model.split_once('.').map(|(prefix, _)| prefix) {
+            Some("us") => self.region.starts_with("us-") && !self.region.starts_with("us-gov-"),
+            Some("us-gov") => self.region.starts_with("us-gov-"),
+            Some("eu") => self.region.starts_with("eu-"),
+            Some("apac") => self.region.starts_with("ap-"),
+            Some("ca") => self.region.starts_with("ca-"),
+            Some("jp") => self.region == "ap-northeast-1" || self.region == "ap-northeast-3",
+            _ => true,
+        }
+    }
+}
+
+/// Direction of a traffic shift
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailoverKind {
+    /// Traffic moved away from a failing region
+    Failover,
+    /// Traffic returned to a preferred region after recovery
+    Failback,
+}
+
+/// A shift of a model's traffic from one region to another
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailoverEvent {
+    /// Direction of the shift
+    pub kind: FailoverKind,
+    /// Model or inference profile whose traffic moved
+    pub model: String,
+    /// Region traffic moved away from
+    pub from_region: String,
+    /// Region traffic moved to
+    pub to_region: String,
+    /// When the shift happened
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct RegionHealth {
+    consecutive_failures: u32,
+    down_since: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct RouterState {
+    health: Vec<RegionHealth>,
+    current: HashMap<String, usize>,
+}
+
+/// Chooses the region for each request and tracks regional health
+#[derive(Debug)]
+pub struct RegionRouter {
+    regions: Vec<RegionConfig>,
+    failure_threshold: u32,
+    recovery: Duration,
+    state: Mutex<RouterState>,
+}
+
+impl RegionRouter {
+    /// Create a router over the failover regions, or `default_region` alone
+    pub fn new(config: &FailoverConfig, default_region: &str) -> Self {
+        let regions = if config.regions.is_empty() {
+            vec![RegionConfig::new(default_region)]
+        } else {
+            config.regions.clone()
+        };
+        let state = RouterState {
+            health: regions.iter().map(|_| RegionHealth::default()).collect(),
+            current: HashMap::new(),
+        };
+        Self {
+            regions,
+            failure_threshold: config.failure_threshold.max(1),
+            recovery: Duration::from_secs(config.recovery_seconds),
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Regions in order of preference
+    pub fn regions(&self) -> &[RegionConfig] {
+        &self.regions
+    }
+
+    /// Whether there is more than one region to fail over between
+    pub fn is_multi_region(&self) -> bool {
+        self.regions.len() > 1
+    }
+
+    /// Whether a region is currently marked down
+    pub fn is_down(&self, index: usize) -> bool {
+        self.state
+            .lock()
+            .health
+            .get(index)
+            .is_some_and(|h| h.down_since.is_some())
+    }
+
+    /// Choose the region for a request to `model`
+    ///
+    /// Returns the region's index and, when the model's traffic moved to a
+    /// different region than its previous request, the failover event.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BedrockError::ModelUnavailable`] if no configured region
+    /// serves the model.
+    pub fn select(&self, model: &str) -> Result<(usize, Option<FailoverEvent>)> {
+        self.select_at(model, Instant::now())
+    }
+
+    /// Record a successful request, bringing a recovered region back up
+    pub fn record_success(&self, index: usize) {
+        let mut state = self.state.lock();
+        if let Some(health) = state.health.get_mut(index) {
+            health.consecutive_failures = 0;
+            if health.down_since.take().is_some() {
+                info!("Region {} recovered", self.regions[index].region);
+            }
+        }
+    }
+
+    /// Record a regional error or timeout
+    pub fn record_failure(&self, index: usize) {
+        self.record_failure_at(index, Instant::now());
+    }
+
+    fn select_at(&self, model: &str, now: Instant) -> Result<(usize, Option<FailoverEvent>)> {
+        let serving: Vec<usize> = (0..self.regions.len())
+            .filter(|&i| self.regions[i].serves(model))
+            .collect();
+        let Some(&first) = serving.first() else {
+            return Err(BedrockError::ModelUnavailable(format!(
+                "{model} is not available in any configured region"
+            )));
+        };
+
+        let mut state = self.state.lock();
+        let chosen = serving
+            .iter()
+            .copied()
+            .find(|&i| {
+                state.health[i]
+                    .down_since
+                    .is_none_or(|since| now.duration_since(since) >= self.recovery)
+            })
+            .unwrap_or(first);
+
+        let previous = state.current.insert(model.to_string(), chosen);
+        let event = previous
+            .filter(|&previous| previous != chosen)
+            .map(|previous| FailoverEvent {
+                kind: if chosen > previous {
+                    FailoverKind::Failover
+                } else {
+                    FailoverKind::Failback
+                },
+                model: model.to_string(),
+                from_region: self.regions[previous].region.clone(),
+                to_region: self.regions[chosen].region.clone(),
+                timestamp: Utc::now(),
+            });
+        if let Some(event) = &event {
+            warn!(
+                "Shifting {} traffic from {} to {} ({:?})",
+                event.model, event.from_region, event.to_region, event.kind
+            );
+        }
+
+        Ok((chosen, event))
+    }
+
+    fn record_failure_at(&self, index: usize, now: Instant) {
+        let mut state = self.state.lock();
+        let Some(health) = state.health.get_mut(index) else {
+            return;
+        };
+        health.consecutive_failures += 1;
+        if health.consecutive_failures >= self.failure_threshold {
+            if health.down_since.is_none() {
+                warn!(
+                    "Region {} marked down after {} consecutive failures",
+                    self.regions[index].region, health.consecutive_failures
+                );
+            }
+            health.down_since = Some(now);
+        }
+    }
+}
+
+/// Whether a failed SDK call points at a regional problem
+///
+/// Timeouts and dispatch failures always do; service errors do when
+/// `service_unavailable` says so.
+pub(crate) fn is_regional_failure<E, R>(
+    error: &SdkError<E, R>,
+    service_unavailable: impl FnOnce(&E) -> bool,
+) -> bool {
+    match error {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) => true,
+        SdkError::ServiceError(context) => service_unavailable(context.err()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router() -> RegionRouter {
+        let config = FailoverConfig::new(vec![
+            RegionConfig::new("us-east-1"),
+            RegionConfig::new("us-west-2"),
+            RegionConfig::new("eu-west-1").with_pool_size(2),
+        ])
+        .with_failure_threshold(2)
+        .with_recovery(Duration::from_secs(30));
+        RegionRouter::new(&config, "us-east-1")
+    }
+
+    #[test]
+    fn test_region_serves_profiles_and_arns() {
+        let us = RegionConfig::new("us-west-2");
+        let eu = RegionConfig::new("eu-west-1");
+        assert!(us.serves("us.anthropic.claude-3-haiku-20240307-v1:0"));
+        assert!(!eu.serves("us.anthropic.claude-3-haiku-20240307-v1:0"));
+        assert!(eu.serves("anthropic.claude-3-haiku-20240307-v1:0"));
+        assert!(us.serves("arn:aws:bedrock:us-west-2:123456789012:provisioned-model/abc"));
+        assert!(!eu.serves("arn:aws:bedrock:us-west-2:123456789012:provisioned-model/abc"));
+
+        let restricted = RegionConfig::new("us-east-1").with_models(["amazon.titan-text-express"]);
+        assert!(!restricted.serves("anthropic.claude-3-haiku-20240307-v1:0"));
+    }
+
+    #[test]
+    fn test_failover_and_failback() {
+        let router = router();
+        let model = "anthropic.claude-3-haiku-20240307-v1:0";
+        let start = Instant::now();
+
+        assert_eq!(router.select_at(model, start).unwrap(), (0, None));
+
+        router.record_failure_at(0, start);
+        assert_eq!(router.select_at(model, start).unwrap().0, 0);
+        router.record_failure_at(0, start);
+        assert!(router.is_down(0));
+
+        let (region, event) = router.select_at(model, start).unwrap();
+        assert_eq!(region, 1);
+        let event = event.unwrap();
+        assert_eq!(event.kind, FailoverKind::Failover);
+        assert_eq!(event.from_region, "us-east-1");
+        assert_eq!(event.to_region, "us-west-2");
+        assert_eq!(router.select_at(model, start).unwrap(), (1, None));
+
+        let later = start + Duration::from_secs(30);
+        let (region, event) = router.select_at(model, later).unwrap();
+        assert_eq!(region, 0);
+        assert_eq!(event.unwrap().kind, FailoverKind::Failback);
+        router.record_success(0);
+        assert!(!router.is_down(0));
+    }
+
+    #[test]
+    fn test_profile_skips_regions_outside_its_geography() {
+        let router = router();
+        let profile = "eu.anthropic.claude-3-haiku-20240307-v1:0";
+        assert_eq!(router.select(profile).unwrap().0, 2);
+        assert!(router.select("apac.anthropic.claude-3-haiku").is_err());
+    }
+}
@@ -194,9 +194,9 @@ impl RetryExecutor {
     }
 
     /// Execute an operation with retry logic
-    pub async fn execute<F, Fut, T>(&self, operation: F) -> Result<T, BedrockError>
+    pub async fn execute<F, Fut, T>(&self, mut operation: F) -> Result<T, BedrockError>
     where
-        F: Fn() -> Fut,
+        F: FnMut() -> Fut,
         Fut: std::future::Future<Output = Result<T, BedrockError>>,
     {
         let mut attempt = 0;
@@ -1,20 +1,46 @@
 //! Streaming response handling for Bedrock client
 
+use std::collections::{HashMap, VecDeque};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
-use chrono::Utc;
+use aws_sdk_bedrockruntime::primitives::event_stream::EventReceiver;
+use aws_sdk_bedrockruntime::types::error::ConverseStreamOutputError;
+use aws_sdk_bedrockruntime::types::{ContentBlockDelta, ConverseStreamOutput};
 use futures::{Stream, StreamExt};
-use uuid::Uuid;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 
 use crate::error::{BedrockError, Result};
 use crate::message::{StreamChunk, TokenUsage};
 
-/// Streaming response wrapper (simplified for compilation)
+/// The text deltas of a Converse stream, ending with the stream or its first error
+pub(crate) fn text_deltas(
+    receiver: EventReceiver<ConverseStreamOutput, ConverseStreamOutputError>,
+) -> impl Stream<Item = Result<String>> + Send + 'static {
+    futures::stream::unfold(Some(receiver), |receiver| async move {
+        let mut receiver = receiver?;
+        loop {
+            match receiver.recv().await {
+                Ok(Some(ConverseStreamOutput::ContentBlockDelta(event))) => {
+                    if let Some(ContentBlockDelta::Text(text)) = event.delta {
+                        return Some((Ok(text), Some(receiver)));
+                    }
+                }
+                Ok(Some(_)) => {}
+                Ok(None) => return None,
+                Err(e) => return Some((Err(BedrockError::ServiceError(e.to_string())), None)),
+            }
+        }
+    })
+}
+
+/// Stream of text chunks from a Bedrock response
 pub struct StreamingResponse {
     inner: Pin<Box<dyn Stream<Item = Result<String>> + Send>>,
     model: String,
-    buffer: String,
     finished: bool,
 }
 
@@ -24,7 +50,6 @@ impl StreamingResponse {
         Self {
             inner: Box::pin(stream),
             model,
-            buffer: String::new(),
             finished: false,
         }
     }
@@ -180,7 +205,7 @@ impl StreamBuffer {
 
     /// Check if the stream is complete
     pub fn is_complete(&self) -> bool {
-        self.chunks.last().map_or(false, |chunk| chunk.is_final)
+        self.chunks.last().is_some_and(|chunk| chunk.is_final)
     }
 
     /// Clear the buffer
@@ -192,11 +217,719 @@ impl StreamBuffer {
     }
 }
 
+/// Output pacing for streams delivered to consumers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PacingConfig {
+    /// Maximum tokens per second emitted to the consumer; unpaced if unset
+    pub max_tokens_per_second: Option<f64>,
+    /// Tokens that may be emitted at once before pacing kicks in
+    pub burst_tokens: usize,
+    /// Maximum concurrent streams per conversation
+    pub max_streams_per_conversation: usize,
+}
+
+impl Default for PacingConfig {
+    fn default() -> Self {
+        Self {
+            max_tokens_per_second: None,
+            burst_tokens: 32,
+            max_streams_per_conversation: 2,
+        }
+    }
+}
+
+impl PacingConfig {
+    /// Pace a chunk stream according to this configuration
+    ///
+    /// Content chunks larger than the burst are split so output arrives
+    /// evenly instead of in bursts. Final chunks and errors pass through
+    /// without delay.
+    pub fn pace<S>(&self, stream: S) -> Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>
+    where
+        S: Stream<Item = Result<StreamChunk>> + Send + 'static,
+    {
+        let Some(rate) = self.max_tokens_per_second.filter(|rate| *rate > 0.0) else {
+            return Box::pin(stream);
+        };
+        let burst = self.burst_tokens.max(1);
+        let state = (
+            Box::pin(stream),
+            TokenBucket::new(rate, burst),
+            VecDeque::<StreamChunk>::new(),
+        );
+
+        Box::pin(futures::stream::unfold(
+            state,
+            move |(mut inner, mut bucket, mut pending)| async move {
+                let item = match pending.pop_front() {
+                    Some(chunk) => Ok(chunk),
+                    None => match inner.next().await? {
+                        Ok(chunk) => {
+                            pending.extend(split_chunk(chunk, burst));
+                            Ok(pending.pop_front()?)
+                        }
+                        Err(e) => Err(e),
+                    },
+                };
+
+                if let Ok(chunk) = &item {
+                    let wait = bucket.take(estimate_tokens(&chunk.content));
+                    if !wait.is_zero() {
+                        tokio::time::sleep(wait).await;
+                    }
+                }
+                Some((item, (inner, bucket, pending)))
+            },
+        ))
+    }
+}
+
+/// Token bucket that lets consumption run into debt and reports the wait
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    #[allow(clippy::cast_precision_loss)]
+    fn new(rate: f64, capacity: usize) -> Self {
+        Self {
+            rate,
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            last: Instant::now(),
+        }
+    }
+
+    /// Consume tokens, returning how long to wait before emitting
+    #[allow(clippy::cast_precision_loss)]
+    fn take(&mut self, tokens: usize) -> Duration {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.capacity);
+        self.last = now;
+
+        self.tokens -= tokens as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+/// Rough token estimate for pacing: one token per four bytes
+fn estimate_tokens(text: &str) -> usize {
+    if text.is_empty() {
+        0
+    } else {
+        (text.len() / 4).max(1)
+    }
+}
+
+/// Split a content chunk into pieces of at most `max_tokens` tokens
+fn split_chunk(chunk: StreamChunk, max_tokens: usize) -> Vec<StreamChunk> {
+    let max_bytes = max_tokens * 4;
+    if chunk.is_final || chunk.content.len() <= max_bytes {
+        return vec![chunk];
+    }
+
+    let mut pieces = Vec::new();
+    let mut rest = chunk.content.as_str();
+    while !rest.is_empty() {
+        let mut end = max_bytes.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end += 1;
+        }
+        let (piece, tail) = rest.split_at(end);
+        let mut split = StreamChunk::content(piece);
+        split.metadata.clone_from(&chunk.metadata);
+        pieces.push(split);
+        rest = tail;
+    }
+    pieces
+}
+
+/// Chunk metadata key holding why generation ended, e.g. `stop_sequence`
+pub const FINISH_REASON_METADATA_KEY: &str = "finish_reason";
+
+/// Chunk metadata key holding the stop sequence that ended generation
+pub const STOP_SEQUENCE_METADATA_KEY: &str = "stop_sequence";
+
+/// Ends a stream at the first configured stop sequence
+///
+/// Text that could be the start of a stop sequence is held back until the
+/// next chunk shows whether it is, so a sequence split across chunks is
+/// never partly emitted. Once a sequence is found, the content before it
+/// is emitted, later content is dropped, and the final chunk carries
+/// [`FINISH_REASON_METADATA_KEY`] set to `stop_sequence` along with
+/// [`STOP_SEQUENCE_METADATA_KEY`], as a non-streaming response reports it.
+#[derive(Debug, Clone, Default)]
+pub struct StopSequenceDetector {
+    sequences: Vec<String>,
+    held: String,
+    stopped: Option<String>,
+    finished: bool,
+}
+
+impl StopSequenceDetector {
+    /// Create a detector for `sequences`; empty sequences are ignored
+    pub fn new(sequences: Vec<String>) -> Self {
+        Self {
+            sequences: sequences.into_iter().filter(|s| !s.is_empty()).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// The stop sequence that ended the stream, if one did
+    pub fn stopped_at(&self) -> Option<&str> {
+        self.stopped.as_deref()
+    }
+
+    /// Feed a chunk from the model, returning the chunks to emit
+    pub fn push(&mut self, chunk: StreamChunk) -> Vec<StreamChunk> {
+        if self.finished {
+            return Vec::new();
+        }
+        if chunk.is_final {
+            let mut chunk = chunk;
+            // The final chunk's text is scanned like any other, then released
+            // ahead of it so the final chunk only carries usage and metadata
+            let content = std::mem::take(&mut chunk.content);
+            if self.stopped.is_none() {
+                self.held.push_str(&content);
+                if let Some(at) = self.find_stop() {
+                    self.held.truncate(at);
+                }
+            }
+            let mut emitted: Vec<StreamChunk> = self.flush().into_iter().collect();
+            emitted.push(self.finish_chunk(chunk));
+            return emitted;
+        }
+        if self.stopped.is_some() {
+            return Vec::new();
+        }
+
+        self.held.push_str(&chunk.content);
+        let emit = match self.find_stop() {
+            Some(at) => {
+                let before = self.held[..at].to_string();
+                self.held.clear();
+                before
+            }
+            None => {
+                let keep = self.partial_match_start();
+                self.held.drain(..keep).collect()
+            }
+        };
+        if emit.is_empty() {
+            return Vec::new();
+        }
+        let mut piece = StreamChunk::content(emit);
+        piece.metadata = chunk.metadata;
+        vec![piece]
+    }
+
+    /// Signal the end of the model's stream, returning the chunks to emit
+    ///
+    /// Held-back text is released, and a stream that stopped at a sequence
+    /// but never received a final chunk gets one.
+    pub fn finish(&mut self) -> Vec<StreamChunk> {
+        if self.finished {
+            return Vec::new();
+        }
+        let mut emitted: Vec<StreamChunk> = self.flush().into_iter().collect();
+        if self.stopped.is_some() {
+            let mut last = StreamChunk::content(String::new());
+            last.is_final = true;
+            emitted.push(self.finish_chunk(last));
+        }
+        self.finished = true;
+        emitted
+    }
+
+    /// Apply the detector to a chunk stream
+    ///
+    /// Without stop sequences the stream is returned unchanged.
+    pub fn apply<S>(self, stream: S) -> Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>
+    where
+        S: Stream<Item = Result<StreamChunk>> + Send + 'static,
+    {
+        if self.sequences.is_empty() {
+            return Box::pin(stream);
+        }
+        let state = (Box::pin(stream), self, VecDeque::<StreamChunk>::new());
+
+        Box::pin(futures::stream::unfold(
+            state,
+            |(mut inner, mut detector, mut pending)| async move {
+                loop {
+                    if let Some(chunk) = pending.pop_front() {
+                        return Some((Ok(chunk), (inner, detector, pending)));
+                    }
+                    match inner.next().await {
+                        Some(Ok(chunk)) => pending.extend(detector.push(chunk)),
+                        Some(Err(e)) => return Some((Err(e), (inner, detector, pending))),
+                        None if detector.finished => return None,
+                        None => pending.extend(detector.finish()),
+                    }
+                }
+            },
+        ))
+    }
+
+    /// Byte offset of the earliest stop sequence in the held text, recording
+    /// the sequence as the one that ended the stream
+    fn find_stop(&mut self) -> Option<usize> {
+        let (at, sequence) = self
+            .sequences
+            .iter()
+            .filter_map(|sequence| self.held.find(sequence.as_str()).map(|at| (at, sequence)))
+            .min_by_key(|(at, _)| *at)?;
+        self.stopped = Some(sequence.clone());
+        Some(at)
+    }
+
+    /// Byte offset of the held text's longest suffix that starts a stop sequence
+    fn partial_match_start(&self) -> usize {
+        self.held
+            .char_indices()
+            .map(|(at, _)| at)
+            .find(|&at| {
+                let tail = &self.held[at..];
+                self.sequences
+                    .iter()
+                    .any(|sequence| sequence.starts_with(tail))
+            })
+            .unwrap_or(self.held.len())
+    }
+
+    fn flush(&mut self) -> Option<StreamChunk> {
+        (!self.held.is_empty()).then(|| StreamChunk::content(std::mem::take(&mut self.held)))
+    }
+
+    fn finish_chunk(&mut self, mut chunk: StreamChunk) -> StreamChunk {
+        self.finished = true;
+        if let Some(sequence) = &self.stopped {
+            chunk.metadata.insert(
+                FINISH_REASON_METADATA_KEY.to_string(),
+                serde_json::json!("stop_sequence"),
+            );
+            chunk.metadata.insert(
+                STOP_SEQUENCE_METADATA_KEY.to_string(),
+                serde_json::json!(sequence),
+            );
+        }
+        chunk
+    }
+}
+
+/// Per-conversation limit on concurrent streams
+#[derive(Debug, Clone)]
+pub struct StreamLimiter {
+    max_per_conversation: usize,
+    active: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl StreamLimiter {
+    /// Create a limiter allowing `max_per_conversation` concurrent streams
+    pub fn new(max_per_conversation: usize) -> Self {
+        Self {
+            max_per_conversation: max_per_conversation.max(1),
+            active: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Reserve a stream slot for a conversation
+    ///
+    /// The slot is released when the returned permit is dropped.
+    pub fn acquire(&self, conversation_id: &str) -> Result<StreamPermit> {
+        let mut active = self.active.lock();
+        let count = active.entry(conversation_id.to_string()).or_insert(0);
+        if *count >= self.max_per_conversation {
+            return Err(BedrockError::RateLimited(format!(
+                "Conversation {conversation_id} already has {count} active stream(s)"
+            )));
+        }
+        *count += 1;
+
+        Ok(StreamPermit {
+            conversation_id: conversation_id.to_string(),
+            active: self.active.clone(),
+        })
+    }
+
+    /// Number of active streams for a conversation
+    pub fn active(&self, conversation_id: &str) -> usize {
+        self.active
+            .lock()
+            .get(conversation_id)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// A reserved stream slot, released on drop
+#[derive(Debug)]
+pub struct StreamPermit {
+    conversation_id: String,
+    active: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl Drop for StreamPermit {
+    fn drop(&mut self) {
+        let mut active = self.active.lock();
+        if let Some(count) = active.get_mut(&self.conversation_id) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.conversation_id);
+            }
+        }
+    }
+}
+
+/// Chunk metadata key marking the chunk that replays a late subscriber's missed content
+pub const CATCH_UP_METADATA_KEY: &str = "catch_up";
+
+#[derive(Debug, Default)]
+struct FanoutState {
+    chunks: Vec<StreamChunk>,
+    prefix: String,
+    finished: bool,
+    error: Option<String>,
+    subscribers: usize,
+}
+
+#[derive(Debug, Default)]
+struct FanoutShared {
+    state: Mutex<FanoutState>,
+    notify: tokio::sync::Notify,
+}
+
+/// Broadcasts one streaming response to any number of consumers
+///
+/// The source is read once, at its own pace, and every chunk is kept until
+/// the fan-out is dropped. Each subscriber reads that log from its own
+/// position, so a slow consumer (a TTS synthesizer, say) never holds back a
+/// fast one (the WebSocket client). A subscriber that joins after content
+/// has arrived first receives one chunk holding the accumulated prefix,
+/// tagged with [`CATCH_UP_METADATA_KEY`], then the live chunks.
+#[derive(Debug, Clone)]
+pub struct StreamFanout {
+    shared: Arc<FanoutShared>,
+}
+
+impl StreamFanout {
+    /// Start reading `stream` in a background task
+    pub fn spawn<S>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<StreamChunk>> + Send + 'static,
+    {
+        let shared = Arc::new(FanoutShared::default());
+        let producer = shared.clone();
+        tokio::spawn(async move {
+            let mut stream = Box::pin(stream);
+            while let Some(item) = stream.next().await {
+                let failed = {
+                    let mut state = producer.state.lock();
+                    match item {
+                        Ok(chunk) => {
+                            state.prefix.push_str(&chunk.content);
+                            state.chunks.push(chunk);
+                            false
+                        }
+                        Err(e) => {
+                            state.error = Some(e.to_string());
+                            true
+                        }
+                    }
+                };
+                producer.notify.notify_waiters();
+                if failed {
+                    break;
+                }
+            }
+            producer.state.lock().finished = true;
+            producer.notify.notify_waiters();
+        });
+        Self { shared }
+    }
+
+    /// A new consumer of the response, starting with whatever it missed
+    pub fn subscribe(&self) -> Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>> {
+        let (catch_up, position) = {
+            let mut state = self.shared.state.lock();
+            state.subscribers += 1;
+            let position = state.chunks.len();
+            let catch_up = (!state.prefix.is_empty()).then(|| {
+                let mut chunk = StreamChunk::content(state.prefix.clone());
+                chunk
+                    .metadata
+                    .insert(CATCH_UP_METADATA_KEY.to_string(), serde_json::json!(true));
+                chunk
+            });
+            (catch_up, position)
+        };
+
+        let subscriber = FanoutSubscriber {
+            shared: self.shared.clone(),
+            position,
+            done: false,
+        };
+        let live = futures::stream::unfold(subscriber, |mut subscriber| async move {
+            let item = subscriber.next_chunk().await?;
+            Some((item, subscriber))
+        });
+        Box::pin(futures::stream::iter(catch_up.map(Ok)).chain(live))
+    }
+
+    /// Content received from the source so far
+    pub fn prefix(&self) -> String {
+        self.shared.state.lock().prefix.clone()
+    }
+
+    /// Whether the source has ended
+    pub fn is_finished(&self) -> bool {
+        self.shared.state.lock().finished
+    }
+
+    /// Number of subscribers currently attached
+    pub fn subscriber_count(&self) -> usize {
+        self.shared.state.lock().subscribers
+    }
+}
+
+/// One consumer's read position in a [`StreamFanout`]
+struct FanoutSubscriber {
+    shared: Arc<FanoutShared>,
+    position: usize,
+    done: bool,
+}
+
+impl FanoutSubscriber {
+    async fn next_chunk(&mut self) -> Option<Result<StreamChunk>> {
+        if self.done {
+            return None;
+        }
+        loop {
+            // Register for wakeups before checking, so a chunk pushed in
+            // between is not missed
+            let notified = self.shared.notify.notified();
+            {
+                let state = self.shared.state.lock();
+                if let Some(chunk) = state.chunks.get(self.position) {
+                    self.position += 1;
+                    return Some(Ok(chunk.clone()));
+                }
+                if let Some(error) = &state.error {
+                    self.done = true;
+                    return Some(Err(BedrockError::RequestFailed(error.clone())));
+                }
+                if state.finished {
+                    return None;
+                }
+            }
+            notified.await;
+        }
+    }
+}
+
+impl Drop for FanoutSubscriber {
+    fn drop(&mut self) {
+        self.shared.state.lock().subscribers -= 1;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use futures::stream;
 
+    #[tokio::test]
+    async fn test_pacing_caps_throughput() {
+        let config = PacingConfig {
+            max_tokens_per_second: Some(1000.0),
+            burst_tokens: 5,
+            ..PacingConfig::default()
+        };
+        // 40 tokens in one burst, then the final chunk
+        let chunks = vec![
+            Ok(StreamChunk::content("x".repeat(160))),
+            Ok(StreamChunk::final_chunk(TokenUsage::new(
+                1, 40, "test", 0.0,
+            ))),
+        ];
+
+        let start = Instant::now();
+        let paced: Vec<_> = config.pace(stream::iter(chunks)).collect().await;
+
+        assert_eq!(paced.len(), 9);
+        assert!(paced[..8]
+            .iter()
+            .all(|chunk| chunk.as_ref().unwrap().content.len() == 20));
+        assert!(paced[8].as_ref().unwrap().is_final);
+        // 5 tokens of burst are free; the remaining 35 take 35ms at 1000/s
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[tokio::test]
+    async fn test_unpaced_passthrough() {
+        let chunks = vec![Ok(StreamChunk::content("x".repeat(1000)))];
+        let paced: Vec<_> = PacingConfig::default()
+            .pace(stream::iter(chunks))
+            .collect()
+            .await;
+        assert_eq!(paced.len(), 1);
+    }
+
+    #[test]
+    fn test_split_respects_char_boundaries() {
+        let pieces = split_chunk(StreamChunk::content("é".repeat(10)), 1);
+        assert!(pieces.len() > 1);
+        let joined: String = pieces.iter().map(|p| p.content.as_str()).collect();
+        assert_eq!(joined, "é".repeat(10));
+    }
+
+    #[tokio::test]
+    async fn test_stop_sequence_split_across_chunks() {
+        let chunks = vec![
+            Ok(StreamChunk::content("Answer: 42\nEN")),
+            Ok(StreamChunk::content("D more text")),
+            Ok(StreamChunk::content(" still more")),
+            Ok(StreamChunk::final_chunk(TokenUsage::new(
+                10, 8, "test", 0.0,
+            ))),
+        ];
+        let detector = StopSequenceDetector::new(vec!["\nEND".to_string()]);
+        let chunks: Vec<StreamChunk> = detector
+            .apply(stream::iter(chunks))
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        let text: String = chunks.iter().map(|c| c.content.as_str()).collect();
+        assert_eq!(text, "Answer: 42");
+        let last = chunks.last().unwrap();
+        assert!(last.is_final && last.usage.is_some());
+        assert_eq!(last.metadata[FINISH_REASON_METADATA_KEY], "stop_sequence");
+        assert_eq!(last.metadata[STOP_SEQUENCE_METADATA_KEY], "\nEND");
+    }
+
+    #[test]
+    fn test_stop_sequence_in_final_chunk() {
+        let mut detector = StopSequenceDetector::new(vec!["\nEND".to_string()]);
+        assert_eq!(
+            detector.push(StreamChunk::content("Answer: 42\nE"))[0].content,
+            "Answer: 42"
+        );
+
+        let mut last = StreamChunk::final_chunk(TokenUsage::new(10, 8, "test", 0.0));
+        last.content = "ND trailing text".to_string();
+        let end = detector.push(last);
+
+        assert_eq!(end.len(), 1);
+        assert!(end[0].is_final && end[0].content.is_empty());
+        assert_eq!(end[0].metadata[FINISH_REASON_METADATA_KEY], "stop_sequence");
+        assert_eq!(detector.stopped_at(), Some("\nEND"));
+
+        // Text before a sequence that only appears in the final chunk is kept
+        let mut detector = StopSequenceDetector::new(vec!["STOP".to_string()]);
+        let mut last = StreamChunk::final_chunk(TokenUsage::new(1, 1, "test", 0.0));
+        last.content = "done STOP ignored".to_string();
+        let end = detector.push(last);
+
+        assert_eq!(end[0].content, "done ");
+        assert!(end[1].is_final && end[1].content.is_empty());
+        assert_eq!(end[1].metadata[STOP_SEQUENCE_METADATA_KEY], "STOP");
+    }
+
+    #[test]
+    fn test_held_back_text_is_released_without_a_match() {
+        let mut detector = StopSequenceDetector::new(vec!["STOP".to_string()]);
+        assert_eq!(
+            detector.push(StreamChunk::content("go ST"))[0].content,
+            "go "
+        );
+        assert_eq!(detector.push(StreamChunk::content("é"))[0].content, "STé");
+        assert!(detector.push(StreamChunk::content("S")).is_empty());
+
+        let end = detector.push(StreamChunk::final_chunk(TokenUsage::new(1, 1, "test", 0.0)));
+        assert_eq!(end[0].content, "S");
+        assert!(!end[1].metadata.contains_key(FINISH_REASON_METADATA_KEY));
+        assert!(detector.stopped_at().is_none());
+
+        // A stream cut off after the stop sequence still ends with a final chunk
+        let mut detector = StopSequenceDetector::new(vec!["STOP".to_string()]);
+        assert_eq!(
+            detector.push(StreamChunk::content("okSTOP"))[0].content,
+            "ok"
+        );
+        let end = detector.finish();
+        assert!(end[0].is_final);
+        assert_eq!(detector.stopped_at(), Some("STOP"));
+    }
+
+    #[test]
+    fn test_stream_limiter() {
+        let limiter = StreamLimiter::new(1);
+        let permit = limiter.acquire("conv").unwrap();
+        assert!(limiter.acquire("conv").is_err());
+        assert!(limiter.acquire("other").is_ok());
+        assert_eq!(limiter.active("conv"), 1);
+
+        drop(permit);
+        assert_eq!(limiter.active("conv"), 0);
+        assert!(limiter.acquire("conv").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_fanout_catches_up_late_subscribers() {
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        let fanout = StreamFanout::spawn(rx);
+        let mut early = fanout.subscribe();
+
+        tx.unbounded_send(Ok(StreamChunk::content("Hello")))
+            .unwrap();
+        assert_eq!(early.next().await.unwrap().unwrap().content, "Hello");
+
+        let late = fanout.subscribe();
+        assert_eq!(fanout.subscriber_count(), 2);
+        tx.unbounded_send(Ok(StreamChunk::content(" world")))
+            .unwrap();
+        tx.unbounded_send(Ok(StreamChunk::final_chunk(TokenUsage::new(
+            10, 5, "test", 0.0,
+        ))))
+        .unwrap();
+        drop(tx);
+
+        let late: Vec<StreamChunk> = late.map(|chunk| chunk.unwrap()).collect().await;
+        assert_eq!(late[0].content, "Hello");
+        assert_eq!(late[0].metadata[CATCH_UP_METADATA_KEY], true);
+        assert_eq!(late[1].content, " world");
+        assert!(late[2].is_final);
+
+        let rest: Vec<StreamChunk> = early.map(|chunk| chunk.unwrap()).collect().await;
+        assert_eq!(rest.len(), 2);
+        assert_eq!(fanout.prefix(), "Hello world");
+        assert!(fanout.is_finished());
+        assert_eq!(fanout.subscriber_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fanout_delivers_errors_to_every_subscriber() {
+        let fanout = StreamFanout::spawn(stream::iter(vec![
+            Ok(StreamChunk::content("partial")),
+            Err(BedrockError::Timeout("stream stalled".into())),
+        ]));
+        for subscriber in [fanout.subscribe(), fanout.subscribe()] {
+            let items: Vec<Result<StreamChunk>> = subscriber.collect().await;
+            assert!(items.last().unwrap().is_err());
+        }
+    }
+
     #[test]
     fn test_stream_buffer() {
         let mut buffer = StreamBuffer::new();
@@ -218,34 +951,19 @@ mod tests {
 
     #[tokio::test]
     async fn test_stream_processor() {
-        let chunks = vec![
-            Ok(StreamChunk::content("Hello")),
-            Ok(StreamChunk::content(" world")),
-            Ok(StreamChunk::final_chunk(TokenUsage::new(
-                10, 5, "test", 0.001,
-            ))),
-        ];
-
-        let mock_stream = stream::iter(chunks);
-        let streaming_response = StreamingResponse::new(
-            mock_stream.map(|chunk| {
-                Ok(ConverseStreamOutput::ContentBlockDelta(
-                    aws_sdk_bedrockruntime::types::ContentBlockDeltaEvent::builder().build(),
-                ))
-            }),
-            "test-model".to_string(),
-        );
+        let mock_stream = stream::iter(vec![Ok("Hello".to_string()), Ok(" world".to_string())]);
+        let streaming_response = StreamingResponse::new(mock_stream, "test-model".to_string());
 
-        let mut content = String::new();
+        let content = parking_lot::Mutex::new(String::new());
         let processor = StreamProcessor::new(|chunk: StreamChunk| {
             if !chunk.is_final {
-                content.push_str(&chunk.content);
+                content.lock().push_str(&chunk.content);
             }
             Ok(())
         });
 
-        // This would work with a proper mock implementation
-        // let usage = processor.process(streaming_response).await.unwrap();
-        // assert_eq!(content, "Hello world");
+        // The stream ends without a final chunk carrying usage
+        assert!(processor.process(streaming_response).await.is_err());
+        assert_eq!(*content.lock(), "Hello world");
     }
 }
@@ -0,0 +1,85 @@
+//! Structured output
+//!
+//! [`UniversalBedrockClient::generate_json`](crate::UniversalBedrockClient::generate_json)
+//! adds a JSON Schema to the system prompt, reads the JSON value out of the
+//! reply, and deserializes it. A reply that does not parse or does not match
+//! the target type is sent back to the model with the error, up to a repair
+//! limit. With the `structured` feature,
+//! [`generate_structured`](crate::UniversalBedrockClient::generate_structured)
+//! derives the schema from the target type.
+
+use serde::de::DeserializeOwned;
+
+/// A value parsed from a model reply, with the reply it came from
+#[derive(Debug, Clone)]
+pub struct StructuredResponse<T> {
+    /// The parsed value
+    pub value: T,
+    /// The reply that parsed
+    pub response: crate::GenerationResponse,
+    /// Requests made, including the first
+    pub attempts: usize,
+}
+
+/// System prompt text asking for a reply matching `schema`
+pub fn schema_instruction(schema: &serde_json::Value) -> String {
+    format!(
+        "Reply with a single JSON value that matches this JSON Schema, and nothing else: \
+         no prose and no code fences.\n\n{schema}"
+    )
+}
+
+/// Follow-up turn asking the model to fix a reply that failed with `error`
+pub fn repair_prompt(error: &serde_json::Error) -> String {
+    format!(
+        "That reply is not valid for the schema: {error}. \
+         Reply again with only the corrected JSON value."
+    )
+}
+
+/// The JSON object or array in `text`, without surrounding prose or code fences
+pub fn extract_json(text: &str) -> &str {
+    let start = text.find(['{', '[']);
+    let end = text.rfind(['}', ']']);
+    match (start, end) {
+        (Some(start), Some(end)) if start <= end => &text[start..=end],
+        _ => text.trim(),
+    }
+}
+
+/// Parse the JSON value in a model reply as `T`
+///
+/// # Errors
+///
+/// Returns the parse or deserialization error, naming the offending field.
+pub fn parse_structured<T: DeserializeOwned>(text: &str) -> serde_json::Result<T> {
+    serde_json::from_str(extract_json(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, serde::Deserialize, PartialEq)]
+    struct Todo {
+        title: String,
+        done: bool,
+    }
+
+    #[test]
+    fn test_replies_parse_through_fences_and_prose() {
+        let fenced = "Here you go:\n```json\n{\"title\": \"Ship\", \"done\": false}\n```";
+        assert_eq!(
+            parse_structured::<Todo>(fenced).unwrap(),
+            Todo {
+                title: "Ship".to_string(),
+                done: false,
+            }
+        );
+        assert_eq!(extract_json("[1, 2]"), "[1, 2]");
+        assert_eq!(extract_json("  none  "), "none");
+
+        let error = parse_structured::<Todo>("{\"title\": \"Ship\"}").unwrap_err();
+        assert!(repair_prompt(&error).contains("missing field `done`"));
+    }
+}
@@ -0,0 +1,283 @@
+//! Running the tool calls of a turn concurrently
+//!
+//! A model may request several tool calls in one turn. [`ToolRunner`] runs
+//! them through a [`ToolHandler`], up to a parallelism limit at a time,
+//! holding back calls to a tool until the calls it
+//! [depends on](ToolDefinition::depends_on) have finished. Results come back
+//! in the order the model requested the calls, however the calls finished,
+//! so the follow-up request is the same from run to run.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::stream::{FuturesUnordered, StreamExt};
+use tracing::debug;
+
+use crate::error::{BedrockError, Result};
+use crate::tools::{ToolDefinition, ToolResult, ToolUse};
+
+/// Tool calls run at once unless set with [`ToolRunner::with_max_parallel`]
+pub const DEFAULT_TOOL_PARALLELISM: usize = 4;
+
+/// Executes tool calls requested by the model
+#[async_trait]
+pub trait ToolHandler: Send + Sync {
+    /// Run `call` and return its output
+    ///
+    /// An error is sent to the model as a failed [`ToolResult`].
+    async fn call(&self, call: &ToolUse) -> Result<serde_json::Value>;
+}
+
+/// Runs a turn's tool calls concurrently in dependency order
+#[derive(Clone)]
+pub struct ToolRunner {
+    handler: Arc<dyn ToolHandler>,
+    dependencies: HashMap<String, Vec<String>>,
+    max_parallel: usize,
+}
+
+impl ToolRunner {
+    /// Run calls to `tools` with `handler`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BedrockError::InvalidInput`] if the tools' dependencies form
+    /// a cycle.
+    pub fn new(handler: Arc<dyn ToolHandler>, tools: &[ToolDefinition]) -> Result<Self> {
+        let dependencies: HashMap<String, Vec<String>> = tools
+            .iter()
+            .filter(|tool| !tool.depends_on.is_empty())
+            .map(|tool| (tool.name.clone(), tool.depends_on.clone()))
+            .collect();
+        if let Some(tool) = find_cycle(&dependencies) {
+            return Err(BedrockError::InvalidInput(format!(
+                "Tool {tool} depends on itself through other tools"
+            )));
+        }
+        Ok(Self {
+            handler,
+            dependencies,
+            max_parallel: DEFAULT_TOOL_PARALLELISM,
+        })
+    }
+
+    /// Run at most `max_parallel` calls at once
+    pub fn with_max_parallel(mut self, max_parallel: usize) -> Self {
+        self.max_parallel = max_parallel.max(1);
+        self
+    }
+
+    /// Run `calls` and return one result per call, in the same order
+    ///
+    /// A call whose dependency failed is not run and fails as well.
+    pub async fn run(&self, calls: &[ToolUse]) -> Vec<ToolResult> {
+        let waits_on: Vec<Vec<usize>> = calls
+            .iter()
+            .enumerate()
+            .map(|(i, call)| self.waits_on(calls, i, call))
+            .collect();
+        let mut results: Vec<Option<ToolResult>> = vec![None; calls.len()];
+        let mut started = vec![false; calls.len()];
+        let mut running = FuturesUnordered::new();
+
+        loop {
+            let mut progressed = true;
+            while progressed {
+                progressed = false;
+                for (i, call) in calls.iter().enumerate() {
+                    if started[i] || !waits_on[i].iter().all(|&j| results[j].is_some()) {
+                        continue;
+                    }
+                    if let Some(&failed) = waits_on[i]
+                        .iter()
+                        .find(|&&j| results[j].as_ref().is_some_and(|r| r.is_error))
+                    {
+                        debug!(
+                            "Skipping tool call {} because {} failed",
+                            call.id, calls[failed].id
+                        );
+                        started[i] = true;
+                        results[i] = Some(ToolResult::error(
+                            &call.id,
+                            format!("Not run because {} failed", calls[failed].name),
+                        ));
+                        progressed = true;
+                    } else if running.len() < self.max_parallel {
+                        started[i] = true;
+                        running.push(async move { (i, self.handler.call(call).await) });
+                    }
+                }
+            }
+
+            let Some((i, output)) = running.next().await else {
+                break;
+            };
+            results[i] = Some(match output {
+                Ok(content) => ToolResult::success(&calls[i].id, content),
+                Err(e) => ToolResult::error(&calls[i].id, e.to_string()),
+            });
+        }
+
+        results.into_iter().flatten().collect()
+    }
+
+    /// Indices of the calls that must finish before call `i` starts
+    fn waits_on(&self, calls: &[ToolUse], i: usize, call: &ToolUse) -> Vec<usize> {
+        let Some(dependencies) = self.dependencies.get(&call.name) else {
+            return Vec::new();
+        };
+        calls
+            .iter()
+            .enumerate()
+            .filter(|&(j, other)| {
+                dependencies.contains(&other.name) && (j < i || other.name != call.name)
+            })
+            .map(|(j, _)| j)
+            .collect()
+    }
+}
+
+impl std::fmt::Debug for ToolRunner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ToolRunner")
+            .field("dependencies", &self.dependencies)
+            .field("max_parallel", &self.max_parallel)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A tool on a dependency cycle through other tools, if there is one
+fn find_cycle(dependencies: &HashMap<String, Vec<String>>) -> Option<String> {
+    fn visit<'a>(
+        tool: &'a str,
+        dependencies: &'a HashMap<String, Vec<String>>,
+        path: &mut Vec<&'a str>,
+        done: &mut Vec<&'a str>,
+    ) -> bool {
+        if done.contains(&tool) {
+            return false;
+        }
+        if path.contains(&tool) {
+            return true;
+        }
+        path.push(tool);
+        let cyclic = dependencies.get(tool).is_some_and(|deps| {
+            deps.iter()
+                .filter(|dep| dep.as_str() != tool)
+                .any(|dep| visit(dep, dependencies, path, done))
+        });
+        path.pop();
+        done.push(tool);
+        cyclic
+    }
+
+    let mut done = Vec::new();
+    dependencies
+        .keys()
+        .find(|tool| visit(tool, dependencies, &mut Vec::new(), &mut done))
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use parking_lot::Mutex;
+
+    use super::*;
+
+    /// Sleeps for the call's `ms` input, failing tools named `broken`
+    #[derive(Default)]
+    struct Recorder {
+        active: AtomicUsize,
+        peak: AtomicUsize,
+        finished: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl ToolHandler for Recorder {
+        async fn call(&self, call: &ToolUse) -> Result<serde_json::Value> {
+            let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(active, Ordering::SeqCst);
+            let ms = call.input["ms"].as_u64().unwrap_or(0);
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            self.finished.lock().push(call.id.clone());
+            if call.name == "broken" {
+                return Err(BedrockError::ServiceError("tool down".to_string()));
+            }
+            Ok(serde_json::json!(call.id))
+        }
+    }
+
+    fn call(id: &str, name: &str, ms: u64) -> ToolUse {
+        ToolUse {
+            id: id.to_string(),
+            name: name.to_string(),
+            input: serde_json::json!({ "ms": ms }),
+        }
+    }
+
+    fn tool(name: &str) -> ToolDefinition {
+        ToolDefinition::new(name, name, serde_json::json!({ "type": "object" }))
+    }
+
+    #[tokio::test]
+    async fn test_independent_calls_run_concurrently_in_order() {
+        let recorder = Arc::new(Recorder::default());
+        let runner = ToolRunner::new(recorder.clone(), &[])
+            .unwrap()
+            .with_max_parallel(2);
+        let calls = [
+            call("a", "search", 30),
+            call("b", "search", 10),
+            call("c", "search", 0),
+        ];
+
+        let results = runner.run(&calls).await;
+
+        let ids: Vec<_> = results.iter().map(|r| r.tool_use_id.as_str()).collect();
+        assert_eq!(ids, ["a", "b", "c"]);
+        assert_eq!(recorder.peak.load(Ordering::SeqCst), 2);
+        assert_eq!(*recorder.finished.lock(), ["b", "c", "a"]);
+    }
+
+    #[tokio::test]
+    async fn test_dependencies_hold_back_and_skip_calls() {
+        let recorder = Arc::new(Recorder::default());
+        let tools = [
+            tool("book").depends_on("search"),
+            tool("notify").depends_on("broken"),
+        ];
+        let runner = ToolRunner::new(recorder.clone(), &tools).unwrap();
+        let calls = [
+            call("book-1", "book", 0),
+            call("search-1", "search", 20),
+            call("notify-1", "notify", 0),
+            call("broken-1", "broken", 0),
+        ];
+
+        let results = runner.run(&calls).await;
+
+        let finished = recorder.finished.lock().clone();
+        let position = |id: &str| finished.iter().position(|done| done == id).unwrap();
+        assert!(position("search-1") < position("book-1"));
+        assert!(!finished.contains(&"notify-1".to_string()));
+        assert!(!results[0].is_error);
+        assert!(results[2].is_error);
+        assert_eq!(results[2].content, "Not run because broken failed");
+    }
+
+    #[test]
+    fn test_dependency_cycles_are_rejected() {
+        let handler = Arc::new(Recorder::default());
+        let cyclic = [tool("a").depends_on("b"), tool("b").depends_on("a")];
+        assert!(matches!(
+            ToolRunner::new(handler.clone(), &cyclic),
+            Err(BedrockError::InvalidInput(_))
+        ));
+        assert!(ToolRunner::new(handler, &[tool("a").depends_on("a")]).is_ok());
+    }
+}
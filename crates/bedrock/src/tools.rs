@@ -0,0 +1,263 @@
+//! Tool use (function calling) through the Converse API
+//!
+//! Tools are offered with [`GenerationConfig::tools`](crate::GenerationConfig::tools)
+//! or per message with [`UniversalMessage::with_tools`](crate::UniversalMessage::with_tools).
+//! Tool calls requested by the model come back as [`ToolUse`] blocks on the
+//! [`GenerationResponse`](crate::GenerationResponse); answers are sent back
+//! with [`UniversalMessage::tool_results`](crate::UniversalMessage::tool_results).
+
+use aws_sdk_bedrockruntime::types::{
+    AnyToolChoice, AutoToolChoice, SpecificToolChoice, Tool, ToolChoice as AwsToolChoice,
+    ToolConfiguration, ToolInputSchema, ToolResultBlock, ToolResultContentBlock, ToolResultStatus,
+    ToolSpecification, ToolUseBlock,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BedrockError, Result};
+use crate::{document_to_json, json_to_document};
+
+/// A tool the model may call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    /// Tool name, unique within a request
+    pub name: String,
+    /// What the tool does, shown to the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// JSON schema of the tool's input object
+    pub input_schema: serde_json::Value,
+    /// Tools whose calls in the same turn must finish before calls to this
+    /// one start; not sent to the model
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+}
+
+impl ToolDefinition {
+    /// Create a tool definition
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        input_schema: serde_json::Value,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: Some(description.into()),
+            input_schema,
+            depends_on: Vec::new(),
+        }
+    }
+
+    /// Run calls to this tool only after calls to `tool` in the same turn
+    ///
+    /// A tool depending on itself runs its calls one at a time, in order.
+    pub fn depends_on(mut self, tool: impl Into<String>) -> Self {
+        self.depends_on.push(tool.into());
+        self
+    }
+
+    fn to_bedrock_tool(&self) -> Result<Tool> {
+        let spec = ToolSpecification::builder()
+            .name(&self.name)
+            .set_description(self.description.clone())
+            .input_schema(ToolInputSchema::Json(json_to_document(
+                self.input_schema.clone(),
+            )))
+            .build()
+            .map_err(|e| {
+                BedrockError::InvalidInput(format!("Invalid tool {}: {}", self.name, e))
+            })?;
+        Ok(Tool::ToolSpec(spec))
+    }
+}
+
+/// How the model should choose among the offered tools
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolChoice {
+    /// The model decides whether to call a tool
+    #[default]
+    Auto,
+    /// The model must call one of the tools
+    Any,
+    /// The model must call the named tool
+    Tool {
+        /// Tool name
+        name: String,
+    },
+}
+
+impl ToolChoice {
+    fn to_bedrock(&self) -> Result<AwsToolChoice> {
+        Ok(match self {
+            Self::Auto => AwsToolChoice::Auto(AutoToolChoice::builder().build()),
+            Self::Any => AwsToolChoice::Any(AnyToolChoice::builder().build()),
+            Self::Tool { name } => AwsToolChoice::Tool(
+                SpecificToolChoice::builder()
+                    .name(name)
+                    .build()
+                    .map_err(|e| BedrockError::InvalidInput(e.to_string()))?,
+            ),
+        })
+    }
+}
+
+/// A tool call requested by the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolUse {
+    /// ID to answer with in the matching [`ToolResult`]
+    pub id: String,
+    /// Name of the tool to call
+    pub name: String,
+    /// Input matching the tool's schema
+    pub input: serde_json::Value,
+}
+
+impl ToolUse {
+    pub(crate) fn from_bedrock(block: &ToolUseBlock) -> Self {
+        Self {
+            id: block.tool_use_id().to_string(),
+            name: block.name().to_string(),
+            input: document_to_json(block.input()),
+        }
+    }
+
+    pub(crate) fn to_bedrock(&self) -> Result<ToolUseBlock> {
+        ToolUseBlock::builder()
+            .tool_use_id(&self.id)
+            .name(&self.name)
+            .input(json_to_document(self.input.clone()))
+            .build()
+            .map_err(|e| BedrockError::InvalidInput(format!("Invalid tool use: {}", e)))
+    }
+}
+
+/// The outcome of a tool call, sent back to the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolResult {
+    /// ID of the [`ToolUse`] being answered
+    pub tool_use_id: String,
+    /// Tool output; strings are sent as text, anything else as JSON
+    pub content: serde_json::Value,
+    /// Whether the call failed
+    #[serde(default)]
+    pub is_error: bool,
+}
+
+impl ToolResult {
+    /// A successful result
+    pub fn success(tool_use_id: impl Into<String>, content: serde_json::Value) -> Self {
+        Self {
+            tool_use_id: tool_use_id.into(),
+            content,
+            is_error: false,
+        }
+    }
+
+    /// A failed call, described to the model
+    pub fn error(tool_use_id: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            tool_use_id: tool_use_id.into(),
+            content: serde_json::Value::String(message.into()),
+            is_error: true,
+        }
+    }
+
+    pub(crate) fn to_bedrock(&self) -> Result<ToolResultBlock> {
+        let content = match &self.content {
+            serde_json::Value::String(text) => ToolResultContentBlock::Text(text.clone()),
+            other => ToolResultContentBlock::Json(json_to_document(other.clone())),
+        };
+        let status = if self.is_error {
+            ToolResultStatus::Error
+        } else {
+            ToolResultStatus::Success
+        };
+        ToolResultBlock::builder()
+            .tool_use_id(&self.tool_use_id)
+            .content(content)
+            .status(status)
+            .build()
+            .map_err(|e| BedrockError::InvalidInput(format!("Invalid tool result: {}", e)))
+    }
+}
+
+/// Build the Converse tool configuration, or `None` when no tools are offered
+///
+/// Tools with the same name are sent once; the first definition wins.
+pub(crate) fn tool_configuration(
+    tools: &[ToolDefinition],
+    choice: Option<&ToolChoice>,
+) -> Result<Option<ToolConfiguration>> {
+    if tools.is_empty() {
+        return Ok(None);
+    }
+    let mut seen = std::collections::HashSet::new();
+    let tools = tools
+        .iter()
+        .filter(|tool| seen.insert(tool.name.as_str()))
+        .map(ToolDefinition::to_bedrock_tool)
+        .collect::<Result<Vec<_>>>()?;
+    let choice = choice.map(ToolChoice::to_bedrock).transpose()?;
+    ToolConfiguration::builder()
+        .set_tools(Some(tools))
+        .set_tool_choice(choice)
+        .build()
+        .map(Some)
+        .map_err(|e| BedrockError::InvalidInput(format!("Invalid tool configuration: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weather() -> ToolDefinition {
+        ToolDefinition::new(
+            "get_weather",
+            "Current weather for a city",
+            serde_json::json!({
+                "type": "object",
+                "properties": { "city": { "type": "string" } },
+                "required": ["city"]
+            }),
+        )
+    }
+
+    #[test]
+    fn test_tool_configuration() {
+        assert!(tool_configuration(&[], None).unwrap().is_none());
+
+        let choice = ToolChoice::Tool {
+            name: "get_weather".to_string(),
+        };
+        let config = tool_configuration(&[weather(), weather()], Some(&choice))
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.tools().len(), 1);
+        assert!(config.tool_choice().is_some_and(AwsToolChoice::is_tool));
+    }
+
+    #[test]
+    fn test_tool_use_round_trip() {
+        let tool_use = ToolUse {
+            id: "call-1".to_string(),
+            name: "get_weather".to_string(),
+            input: serde_json::json!({ "city": "Paris" }),
+        };
+        let block = tool_use.to_bedrock().unwrap();
+        assert_eq!(ToolUse::from_bedrock(&block), tool_use);
+    }
+
+    #[test]
+    fn test_tool_result_content() {
+        let block = ToolResult::error("call-1", "city not found")
+            .to_bedrock()
+            .unwrap();
+        assert_eq!(block.status(), Some(&ToolResultStatus::Error));
+        assert!(block.content()[0].is_text());
+
+        let block = ToolResult::success("call-1", serde_json::json!({ "temp_c": 21 }))
+            .to_bedrock()
+            .unwrap();
+        assert!(block.content()[0].is_json());
+    }
+}
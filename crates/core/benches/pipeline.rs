@@ -4,14 +4,45 @@
 //! under various conditions to ensure optimal performance.
 
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::runtime::Runtime;
 
 use universal_bot_core::{
-    context::ContextManager, message::MessageType, BotConfig, Message, MessagePipeline,
+    context::{Context, ContextManager},
+    message::{MessageType, ResponseType},
+    pipeline::{EnrichStage, FormatStage, ProcessStage, RouteStage, SanitizeStage, StaticPipeline},
+    BotConfig, Message, MessagePipeline, PipelineStage, Response,
 };
 
+/// Allocator wrapper that counts bytes allocated, for memory comparisons
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Bytes allocated while running `f`
+fn allocated_during<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    f();
+    ALLOCATED.load(Ordering::Relaxed) - before
+}
+
 /// Benchmark pipeline creation
 fn bench_pipeline_creation(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
@@ -62,6 +93,58 @@ fn bench_message_processing(c: &mut Criterion) {
     group.finish();
 }
 
+/// Benchmark statically composed stages against boxed dynamic stages
+///
+/// `static_tuple` and `dynamic_vec` run the same five built-in stages through
+/// `StaticPipeline`, isolating dispatch and future boxing; `message_pipeline`
+/// adds the dynamic pipeline's middleware for reference.
+fn bench_static_pipeline(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let config = BotConfig::default();
+
+    let message_pipeline = rt.block_on(async { MessagePipeline::new(&config).await.unwrap() });
+    let static_tuple = StaticPipeline::standard(&config);
+    let dynamic_stages: Vec<Box<dyn PipelineStage>> = vec![
+        Box::new(SanitizeStage::new()),
+        Box::new(EnrichStage::new()),
+        Box::new(RouteStage::new()),
+        Box::new(ProcessStage::new(config.clone())),
+        Box::new(FormatStage::new()),
+    ];
+    let dynamic_vec = StaticPipeline::new(dynamic_stages);
+
+    let context = Arc::new(parking_lot::RwLock::new(Context::new("static-bench")));
+    let message = Message::text("Test message content");
+
+    let mut group = c.benchmark_group("static_pipeline");
+
+    group.bench_function("static_tuple", |b| {
+        b.to_async(&rt).iter(|| async {
+            let msg = black_box(message.clone());
+            let _response = static_tuple.process(msg, context.clone()).await.unwrap();
+        });
+    });
+
+    group.bench_function("dynamic_vec", |b| {
+        b.to_async(&rt).iter(|| async {
+            let msg = black_box(message.clone());
+            let _response = dynamic_vec.process(msg, context.clone()).await.unwrap();
+        });
+    });
+
+    group.bench_function("message_pipeline", |b| {
+        b.to_async(&rt).iter(|| async {
+            let msg = black_box(message.clone());
+            let _response = message_pipeline
+                .process(msg, context.clone())
+                .await
+                .unwrap();
+        });
+    });
+
+    group.finish();
+}
+
 /// Benchmark different message types
 fn bench_message_types(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
@@ -245,6 +328,88 @@ fn bench_message_serialization(c: &mut Criterion) {
     group.finish();
 }
 
+/// Benchmark handing a response between stages
+///
+/// Compares the typed `Option<Response>` hand-off used by `PipelineContext`
+/// with the JSON round trip (serialize in the process stage, deserialize and
+/// re-serialize in the format stage, deserialize at the end) it replaced.
+fn bench_response_handoff(c: &mut Criterion) {
+    let mut group = c.benchmark_group("response_handoff");
+
+    let response = Response::text("bench-conversation", "a".repeat(1000));
+
+    group.bench_function("json_round_trip", |b| {
+        b.iter(|| {
+            let value = serde_json::to_value(black_box(response.clone())).unwrap();
+            let mut formatted: Response = serde_json::from_value(value.clone()).unwrap();
+            formatted.response_type = ResponseType::Markdown;
+            let value = serde_json::to_value(formatted).unwrap();
+            let _final: Response = serde_json::from_value(value).unwrap();
+        });
+    });
+
+    group.bench_function("typed", |b| {
+        b.iter(|| {
+            let mut slot = Some(black_box(response.clone()));
+            if let Some(formatted) = slot.as_mut() {
+                formatted.response_type = ResponseType::Markdown;
+            }
+            let _final = slot.unwrap();
+        });
+    });
+
+    group.finish();
+}
+
+/// Benchmark fanning a 100KB message out to concurrent conversations
+///
+/// `shared` clones the message as the pipeline does, sharing the content
+/// buffer; `deep_copy` rebuilds the text per task, which is what cloning a
+/// `String`-backed message used to cost. Allocated bytes for one fan-out are
+/// printed alongside the timings.
+fn bench_large_message_sharing(c: &mut Criterion) {
+    const FAN_OUT: usize = 20;
+
+    let rt = Runtime::new().unwrap();
+    let message = Message::text("a".repeat(100 * 1024));
+
+    let fan_out = |deep_copy: bool| {
+        let message = message.clone();
+        rt.block_on(async move {
+            let mut handles = Vec::with_capacity(FAN_OUT);
+            for i in 0..FAN_OUT {
+                let msg = if deep_copy {
+                    Message::text(message.content.to_string())
+                } else {
+                    message.clone()
+                };
+                handles.push(tokio::spawn(async move {
+                    let mut context = Context::new(format!("fan-out-{i}"));
+                    context.add_message(&msg);
+                    context
+                }));
+            }
+            for handle in handles {
+                black_box(handle.await.unwrap());
+            }
+        });
+    };
+
+    let shared_bytes = allocated_during(|| fan_out(false));
+    let copied_bytes = allocated_during(|| fan_out(true));
+    eprintln!(
+        "large_message_sharing: shared {} KiB, deep copy {} KiB per {FAN_OUT}-way fan-out",
+        shared_bytes / 1024,
+        copied_bytes / 1024
+    );
+
+    let mut group = c.benchmark_group("large_message_sharing");
+    group.sample_size(30);
+    group.bench_function("shared", |b| b.iter(|| fan_out(false)));
+    group.bench_function("deep_copy", |b| b.iter(|| fan_out(true)));
+    group.finish();
+}
+
 /// Benchmark memory usage patterns
 fn bench_memory_patterns(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
@@ -302,9 +467,12 @@ criterion_group!(
     bench_pipeline_creation,
     bench_message_processing,
     bench_message_types,
+    bench_static_pipeline,
     bench_concurrent_processing,
     bench_context_operations,
     bench_message_serialization,
+    bench_response_handoff,
+    bench_large_message_sharing,
     bench_memory_patterns,
 );
 
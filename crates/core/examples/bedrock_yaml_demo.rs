@@ -4,127 +4,33 @@
 //! - AWS Bedrock using Claude Opus 4.1 model
 //! - YAML template generation following PDMT patterns
 
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use anyhow::Result;
+use async_trait::async_trait;
 use aws_config::BehaviorVersion;
 use aws_sdk_bedrockruntime::{primitives::Blob, Client as BedrockClient};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tracing::{debug, info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
-use universal_bot_core::{Bot, BotConfig};
+use universal_bot_core::{
+    context::MessageRole,
+    message::TokenUsage,
+    provider::{Provider, ProviderRequest, ProviderResponse},
+    templates::{TemplateGenerator, YamlTemplate},
+    Bot, BotConfig,
+};
+use validator::Validate;
 
 // Default Claude Opus 4.1 model ID - MUST use inference profile for on-demand
 const DEFAULT_BEDROCK_MODEL_ID: &str = "us.anthropic.claude-opus-4-1-20250805-v1:0";
 
-#[derive(Debug, Serialize, Deserialize)]
-struct TodoItem {
-    id: String,
-    content: String,
-    status: String,
-    priority: String,
-    estimated_hours: f32,
-    dependencies: Vec<String>,
-    tags: Vec<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct TodoTemplate {
-    todos: Vec<TodoItem>,
-    metadata: TodoMetadata,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct TodoMetadata {
-    total_count: usize,
-    generated_at: String,
-    template_version: String,
-    granularity: String,
-    project_name: String,
-    model_used: String,
-}
+/// What to generate, filled in with the project description
+const TODO_PROMPT: &str = r#"Generate a structured todo list in YAML format for the following project:
 
-/// Generate content using Claude Opus 4.1 on AWS Bedrock
-async fn generate_with_bedrock(prompt: &str) -> Result<String> {
-    info!("🚀 Calling AWS Bedrock with Claude Opus 4.1");
-    info!("📍 Model ID: {}", DEFAULT_BEDROCK_MODEL_ID);
-
-    // Initialize AWS SDK
-    let config = aws_config::defaults(BehaviorVersion::latest())
-        .region("us-east-1")
-        .load()
-        .await;
-
-    let client = BedrockClient::new(&config);
-
-    // Format request for Claude Messages API (required for Claude 3+ and Opus 4.1)
-    let request = json!({
-        "anthropic_version": "bedrock-2023-05-31",
-        "max_tokens": 2048,
-        "temperature": 0.1,  // Low temperature for deterministic output (PDMT style)
-        "messages": [
-            {
-                "role": "user",
-                "content": prompt
-            }
-        ]
-    });
-
-    debug!("Request payload: {:?}", request);
-
-    // Make the API call to Bedrock
-    let response = client
-        .invoke_model()
-        .body(Blob::new(request.to_string()))
-        .model_id(DEFAULT_BEDROCK_MODEL_ID)
-        .content_type("application/json")
-        .accept("application/json")
-        .send()
-        .await
-        .map_err(|e| {
-            warn!("Bedrock API call failed: {:?}", e);
-            anyhow::anyhow!("Bedrock API error: {}", e)
-        })?;
-
-    // Parse response
-    let response_bytes = response.body.into_inner();
-    let response_body = String::from_utf8(response_bytes)?;
-    let response_json: serde_json::Value = serde_json::from_str(&response_body)?;
-
-    // Extract content from Claude's response
-    let content = response_json["content"]
-        .as_array()
-        .and_then(|arr| arr.first())
-        .and_then(|c| c["text"].as_str())
-        .ok_or_else(|| anyhow::anyhow!("No content in response"))?;
-
-    // Log token usage if available
-    if let Some(usage) = response_json["usage"].as_object() {
-        info!("📊 Token Usage:");
-        info!(
-            "  Input: {}",
-            usage
-                .get("input_tokens")
-                .and_then(|v| v.as_u64())
-                .unwrap_or(0)
-        );
-        info!(
-            "  Output: {}",
-            usage
-                .get("output_tokens")
-                .and_then(|v| v.as_u64())
-                .unwrap_or(0)
-        );
-    }
-
-    Ok(content.to_string())
-}
-
-/// Generate a YAML todo list template using Claude Opus 4.1
-async fn generate_todo_yaml(project_description: &str) -> Result<TodoTemplate> {
-    let prompt = format!(
-        r#"Generate a structured todo list in YAML format for the following project:
-
-Project: {}
+Project: {{ project }}
 
 Create 5-8 specific, actionable todo items with:
 - Unique IDs (format: todo_X_Y)
@@ -133,10 +39,10 @@ Create 5-8 specific, actionable todo items with:
 - Priority levels (high/medium/low)
 - Estimated hours
 - Dependencies between tasks
-- Relevant tags
-
-Return ONLY valid YAML following this exact structure:
+- Relevant tags"#;
 
+/// The structure every generated todo list must follow
+const TODO_SCHEMA: &str = r#"
 todos:
   - id: "todo_0_0"
     content: "Specific task description"
@@ -153,25 +59,132 @@ metadata:
   granularity: "high"
   project_name: "PROJECT_NAME"
   model_used: "Claude Opus 4.1"
+"#;
 
-Do not include any explanations or markdown formatting. Output only the YAML."#,
-        project_description
-    );
+#[derive(Debug, Serialize, Deserialize)]
+struct TodoItem {
+    id: String,
+    content: String,
+    status: String,
+    priority: String,
+    estimated_hours: f32,
+    dependencies: Vec<String>,
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+struct TodoTemplate {
+    #[validate(length(min = 1))]
+    todos: Vec<TodoItem>,
+    metadata: TodoMetadata,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TodoMetadata {
+    total_count: usize,
+    generated_at: String,
+    template_version: String,
+    granularity: String,
+    project_name: String,
+    model_used: String,
+}
+
+/// Claude on AWS Bedrock through the Messages API
+struct OpusProvider {
+    client: BedrockClient,
+}
 
-    let yaml_content = generate_with_bedrock(&prompt).await?;
+impl OpusProvider {
+    async fn new() -> Self {
+        let config = aws_config::defaults(BehaviorVersion::latest())
+            .region("us-east-1")
+            .load()
+            .await;
+        Self {
+            client: BedrockClient::new(&config),
+        }
+    }
+}
 
-    // Clean up the response (remove any markdown if present)
-    let yaml_content = yaml_content
-        .trim()
-        .trim_start_matches("```yaml")
-        .trim_start_matches("```")
-        .trim_end_matches("```")
-        .trim();
+#[async_trait]
+impl Provider for OpusProvider {
+    fn name(&self) -> &str {
+        "bedrock-opus"
+    }
 
-    // Parse YAML
-    let todo_template: TodoTemplate = serde_yaml::from_str(yaml_content)?;
+    async fn generate(&self, request: ProviderRequest) -> Result<ProviderResponse> {
+        info!("🚀 Calling AWS Bedrock with {}", request.model);
+
+        let messages: Vec<_> = request
+            .messages
+            .iter()
+            .map(|turn| {
+                let role = match turn.role {
+                    MessageRole::Assistant => "assistant",
+                    _ => "user",
+                };
+                json!({ "role": role, "content": turn.content })
+            })
+            .collect();
+        let body = json!({
+            "anthropic_version": "bedrock-2023-05-31",
+            "max_tokens": request.max_tokens,
+            "temperature": request.temperature,
+            "messages": messages,
+        });
+        debug!("Request payload: {:?}", body);
+
+        let response = self
+            .client
+            .invoke_model()
+            .body(Blob::new(body.to_string()))
+            .model_id(&request.model)
+            .content_type("application/json")
+            .accept("application/json")
+            .send()
+            .await
+            .map_err(|e| {
+                warn!("Bedrock API call failed: {:?}", e);
+                anyhow::anyhow!("Bedrock API error: {}", e)
+            })?;
+
+        let response_json: serde_json::Value = serde_json::from_slice(&response.body.into_inner())?;
+        let content = response_json["content"]
+            .as_array()
+            .and_then(|arr| arr.first())
+            .and_then(|c| c["text"].as_str())
+            .ok_or_else(|| anyhow::anyhow!("No content in response"))?;
+        let tokens = |key: &str| response_json["usage"][key].as_u64().unwrap_or(0) as usize;
+
+        Ok(ProviderResponse {
+            content: content.to_string(),
+            usage: TokenUsage::new(
+                tokens("input_tokens"),
+                tokens("output_tokens"),
+                request.model,
+            ),
+            finish_reason: response_json["stop_reason"].as_str().map(str::to_string),
+        })
+    }
+}
 
-    Ok(todo_template)
+/// Generate a YAML todo list template using Claude Opus 4.1
+async fn generate_todo_yaml(
+    generator: &TemplateGenerator,
+    project_description: &str,
+) -> Result<TodoTemplate> {
+    let template = YamlTemplate::new("todo_list", TODO_PROMPT, TODO_SCHEMA);
+    let variables = HashMap::from([("project".to_string(), json!(project_description))]);
+
+    let generated = generator
+        .generate::<TodoTemplate>(&template, &variables)
+        .await?;
+    info!("📊 Token Usage:");
+    info!("  Input: {}", generated.usage.input_tokens);
+    info!("  Output: {}", generated.usage.output_tokens);
+    info!("  Attempts: {}", generated.attempts);
+
+    Ok(generated.value)
 }
 
 #[tokio::main]
@@ -206,6 +219,11 @@ async fn main() -> Result<()> {
         "Develop a mobile app for tracking fitness goals",
     ];
 
+    let generator = TemplateGenerator::new(
+        Arc::new(OpusProvider::new().await),
+        DEFAULT_BEDROCK_MODEL_ID,
+    );
+
     info!("\n🧪 Starting YAML Template Generation Tests:\n");
 
     for (i, project) in test_projects.iter().enumerate() {
@@ -213,7 +231,7 @@ async fn main() -> Result<()> {
         info!("📋 Project {}: {}", i + 1, project);
         info!("═══════════════════════════════════════════════════════");
 
-        match generate_todo_yaml(project).await {
+        match generate_todo_yaml(&generator, project).await {
             Ok(template) => {
                 info!("✅ Successfully generated todo template");
                 info!("📊 Stats:");
@@ -82,10 +82,14 @@ async fn main() -> Result<()> {
                 match query_bedrock(&bedrock_client, input).await {
                     Ok((response, usage)) => {
                         println!("🤖 Claude: {}\n", response);
-                        
+
                         if let Some((input_tokens, output_tokens)) = usage {
-                            println!("📊 Tokens - Input: {}, Output: {}, Total: {}", 
-                                   input_tokens, output_tokens, input_tokens + output_tokens);
+                            println!(
+                                "📊 Tokens - Input: {}, Output: {}, Total: {}",
+                                input_tokens,
+                                output_tokens,
+                                input_tokens + output_tokens
+                            );
                         }
                         println!("─────────────────────────────────────────────\n");
                     }
@@ -183,4 +187,4 @@ mod tests {
         assert!(request_body["messages"].is_array());
         assert_eq!(request_body["messages"][0]["content"], test_prompt);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,18 @@
+//! Deserialize arbitrary JSON as a `Message`, then sanitize and validate it.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use universal_bot_core::{sanitize, Message};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut message) = serde_json::from_slice::<Message>(data) else {
+        return;
+    };
+
+    let _ = message.validate();
+
+    message.content = sanitize::sanitize_content(&message.content).into();
+    sanitize::sanitize_metadata(&mut message.metadata);
+    let _ = message.validate();
+});
@@ -0,0 +1,24 @@
+//! Interpolate an arbitrary template against arbitrary variables.
+//!
+//! Input layout: template bytes, a NUL separator, then a JSON object of
+//! variables. Inputs without a separator are used as the template alone.
+
+#![no_main]
+
+use std::collections::HashMap;
+
+use libfuzzer_sys::fuzz_target;
+use universal_bot_core::prompt::interpolate;
+
+fuzz_target!(|data: &[u8]| {
+    let (template, vars) = match data.iter().position(|b| *b == 0) {
+        Some(split) => (&data[..split], &data[split + 1..]),
+        None => (data, &[][..]),
+    };
+
+    let template = String::from_utf8_lossy(template);
+    let vars: HashMap<String, serde_json::Value> =
+        serde_json::from_slice(vars).unwrap_or_default();
+
+    let _ = interpolate(&template, &vars);
+});
@@ -0,0 +1,23 @@
+//! Sanitize arbitrary text and JSON metadata.
+
+#![no_main]
+
+use std::collections::HashMap;
+
+use libfuzzer_sys::fuzz_target;
+use universal_bot_core::sanitize;
+
+fuzz_target!(|data: &[u8]| {
+    let text = String::from_utf8_lossy(data);
+
+    let once = sanitize::sanitize_content(&text);
+    assert_eq!(sanitize::sanitize_content(&once), once);
+
+    if let Ok(mut metadata) = serde_json::from_slice::<HashMap<String, serde_json::Value>>(data) {
+        sanitize::sanitize_metadata(&mut metadata);
+        assert!(!metadata.keys().any(|k| sanitize::is_sensitive_key(k)));
+        for value in metadata.values() {
+            let _ = sanitize::json_depth(value);
+        }
+    }
+});
@@ -0,0 +1,94 @@
+//! Human approval for side-effecting actions
+//!
+//! Tools that change external systems ask an [`ApprovalGate`] before acting.
+//! The gate approves, denies, or defers the action; deferred actions are
+//! held by the tool under the returned approval ID until someone decides.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// An action awaiting a decision
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApprovalRequest {
+    /// ID the decision is recorded under
+    pub id: Uuid,
+    /// What is being done, e.g. `jira.create_ticket`
+    pub action: String,
+    /// Human-readable summary shown to the approver
+    pub summary: String,
+    /// Conversation that triggered the action
+    pub conversation_id: Option<String>,
+    /// The action's arguments
+    pub details: serde_json::Value,
+}
+
+impl ApprovalRequest {
+    /// Create a request with a fresh ID
+    #[must_use]
+    pub fn new(
+        action: impl Into<String>,
+        summary: impl Into<String>,
+        details: serde_json::Value,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            action: action.into(),
+            summary: summary.into(),
+            conversation_id: None,
+            details,
+        }
+    }
+
+    /// Record the conversation that triggered the action
+    #[must_use]
+    pub fn with_conversation_id(mut self, conversation_id: impl Into<String>) -> Self {
+        self.conversation_id = Some(conversation_id.into());
+        self
+    }
+}
+
+/// A gate's decision
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ApprovalDecision {
+    /// Go ahead now
+    Approved,
+    /// Do not act
+    Denied {
+        /// Why
+        reason: String,
+    },
+    /// Hold the action until someone decides
+    Pending,
+}
+
+/// Decides whether a side-effecting action may run
+#[async_trait]
+pub trait ApprovalGate: Send + Sync {
+    /// Review an action
+    async fn review(&self, request: &ApprovalRequest) -> Result<ApprovalDecision>;
+}
+
+/// Gate that approves everything
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AutoApprove;
+
+#[async_trait]
+impl ApprovalGate for AutoApprove {
+    async fn review(&self, _request: &ApprovalRequest) -> Result<ApprovalDecision> {
+        Ok(ApprovalDecision::Approved)
+    }
+}
+
+/// Gate that holds every action for a human decision
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequireApproval;
+
+#[async_trait]
+impl ApprovalGate for RequireApproval {
+    async fn review(&self, _request: &ApprovalRequest) -> Result<ApprovalDecision> {
+        Ok(ApprovalDecision::Pending)
+    }
+}
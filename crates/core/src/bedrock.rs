@@ -0,0 +1,302 @@
+//! Amazon Bedrock provider
+//!
+//! [`BedrockProvider`] adapts a [`UniversalBedrockClient`] to the
+//! [`Provider`] trait. Bedrock takes system instructions separately and
+//! requires conversations to start with a user turn and alternate roles, so
+//! the system prompt and system notes are sent as the system prompt,
+//! consecutive turns by the same role are joined, and assistant turns before
+//! the first user turn are dropped.
+//!
+//! [`BedrockEmbedder`] adapts the client's Titan and Cohere embeddings to the
+//! [`Embedder`] trait.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::StreamExt as _;
+use tracing::debug;
+use universal_bot_bedrock::{
+    BedrockError, GenerationConfig, MessageRole as BedrockRole, UniversalBedrockClient,
+    UniversalMessage, FINISH_REASON_METADATA_KEY,
+};
+
+use crate::{
+    context::MessageRole,
+    error::Error,
+    message::{CostBreakdown, TokenUsage},
+    provider::{Provider, ProviderRequest, ProviderResponse},
+    streaming::ChunkSink,
+    vector::Embedder,
+};
+
+/// Generates replies with models on Amazon Bedrock
+#[derive(Clone)]
+pub struct BedrockProvider {
+    client: UniversalBedrockClient,
+}
+
+impl std::fmt::Debug for BedrockProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BedrockProvider").finish_non_exhaustive()
+    }
+}
+
+impl BedrockProvider {
+    /// Use an existing client
+    #[must_use]
+    pub fn new(client: UniversalBedrockClient) -> Self {
+        Self { client }
+    }
+
+    /// Create a client from the environment's AWS configuration
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client cannot be configured.
+    pub async fn from_env() -> Result<Self> {
+        let client = UniversalBedrockClient::new()
+            .await
+            .map_err(provider_error)?;
+        Ok(Self::new(client))
+    }
+
+    /// The underlying client
+    #[must_use]
+    pub fn client(&self) -> &UniversalBedrockClient {
+        &self.client
+    }
+}
+
+#[async_trait]
+impl Provider for BedrockProvider {
+    fn name(&self) -> &str {
+        "bedrock"
+    }
+
+    async fn generate(&self, request: ProviderRequest) -> Result<ProviderResponse> {
+        let model = request.model.clone();
+        let (messages, config) = convert(request);
+        debug!("Invoking {} with {} turns", model, messages.len());
+
+        let response = self
+            .client
+            .generate_text(&model, messages, Some(config))
+            .await
+            .map_err(provider_error)?;
+
+        Ok(ProviderResponse {
+            content: response.content,
+            usage: token_usage(response.usage, response.model),
+            finish_reason: Some(response.finish_reason).filter(|reason| !reason.is_empty()),
+        })
+    }
+
+    async fn generate_streaming(
+        &self,
+        request: ProviderRequest,
+        sink: &ChunkSink,
+    ) -> Result<ProviderResponse> {
+        let model = request.model.clone();
+        let (messages, config) = convert(request);
+        debug!("Streaming {} with {} turns", model, messages.len());
+
+        let stream = self
+            .client
+            .stream_text(&model, messages, Some(config))
+            .await
+            .map_err(provider_error)?;
+        let mut stream = std::pin::pin!(stream);
+        let mut content = String::new();
+        let mut usage = None;
+        let mut finish_reason = None;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(provider_error)?;
+            if chunk.is_final {
+                usage = chunk.usage;
+                finish_reason = chunk
+                    .metadata
+                    .get(FINISH_REASON_METADATA_KEY)
+                    .and_then(serde_json::Value::as_str)
+                    .map(str::to_string);
+            } else {
+                content.push_str(&chunk.content);
+                sink.send(chunk.content).await;
+            }
+        }
+        Ok(ProviderResponse {
+            content,
+            usage: token_usage(usage, model),
+            finish_reason,
+        })
+    }
+}
+
+/// Keep Bedrock's own cost figure rather than re-estimating it
+fn token_usage(usage: Option<universal_bot_bedrock::TokenUsage>, model: String) -> TokenUsage {
+    match usage {
+        Some(usage) => TokenUsage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            total_tokens: usage.total_tokens,
+            estimated_cost: usage.estimated_cost,
+            model: usage.model,
+            cost: CostBreakdown::inference(usage.estimated_cost),
+        },
+        None => TokenUsage::new(0, 0, model),
+    }
+}
+
+/// Embeds text with Titan or Cohere embedding models on Amazon Bedrock
+#[derive(Clone)]
+pub struct BedrockEmbedder {
+    client: UniversalBedrockClient,
+    model: String,
+}
+
+impl std::fmt::Debug for BedrockEmbedder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BedrockEmbedder")
+            .field("model", &self.model)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BedrockEmbedder {
+    /// Embed with `model`, e.g. `amazon.titan-embed-text-v2:0`
+    #[must_use]
+    pub fn new(client: UniversalBedrockClient, model: impl Into<String>) -> Self {
+        Self {
+            client,
+            model: model.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Embedder for BedrockEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let embeddings = self
+            .client
+            .embed(&self.model, texts)
+            .await
+            .map_err(provider_error)?;
+        Ok(embeddings
+            .into_iter()
+            .map(|embedding| embedding.vector)
+            .collect())
+    }
+}
+
+/// Bedrock's form of `request`: alternating turns starting with the user, and the system prompt
+fn convert(request: ProviderRequest) -> (Vec<UniversalMessage>, GenerationConfig) {
+    let mut system: Vec<String> = request.system_prompt.into_iter().collect();
+    let mut messages: Vec<UniversalMessage> = Vec::new();
+
+    for turn in request.messages {
+        let role = match turn.role {
+            MessageRole::System => {
+                system.push(turn.content);
+                continue;
+            }
+            MessageRole::User => BedrockRole::User,
+            MessageRole::Assistant if messages.is_empty() => continue,
+            MessageRole::Assistant => BedrockRole::Assistant,
+        };
+        if let Some(last) = messages.last_mut().filter(|last| last.role == role) {
+            last.content.push_str("\n\n");
+            last.content.push_str(&turn.content);
+        } else if role == BedrockRole::User {
+            messages.push(UniversalMessage::user(turn.content));
+        } else {
+            messages.push(UniversalMessage::assistant(turn.content));
+        }
+    }
+
+    let config = GenerationConfig {
+        max_tokens: Some(request.max_tokens),
+        temperature: Some(request.temperature),
+        system_prompt: (!system.is_empty()).then(|| system.join("\n\n")),
+        ..GenerationConfig::default()
+    };
+    (messages, config)
+}
+
+/// Map a Bedrock error onto the core error whose retry semantics match
+#[allow(clippy::needless_pass_by_value)] // Passed to `map_err`
+fn provider_error(error: BedrockError) -> anyhow::Error {
+    let message = error.to_string();
+    match error {
+        BedrockError::RateLimited(_) => Error::RateLimit,
+        BedrockError::RequestFailed(_) | BedrockError::PoolExhausted(_) => Error::Network(message),
+        BedrockError::Configuration(_) => Error::Configuration(message),
+        BedrockError::Authentication(_) => Error::Authentication(message),
+        BedrockError::Authorization(_) => Error::Authorization(message),
+        BedrockError::InvalidInput(_)
+        | BedrockError::ContentFiltered { .. }
+        | BedrockError::TokenLimitExceeded(_)
+        | BedrockError::UnsupportedFeature { .. } => Error::InvalidInput(message),
+        _ => Error::Provider(message),
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ProviderMessage;
+
+    #[test]
+    fn test_convert_alternates_turns() {
+        let request = ProviderRequest {
+            model: "anthropic.claude-haiku".to_string(),
+            system_prompt: Some("Be brief".to_string()),
+            messages: vec![
+                ProviderMessage::new(MessageRole::Assistant, "Welcome back"),
+                ProviderMessage::new(MessageRole::System, "The user prefers French"),
+                ProviderMessage::new(MessageRole::User, "Bonjour"),
+                ProviderMessage::new(MessageRole::User, "Ça va ?"),
+                ProviderMessage::new(MessageRole::Assistant, "Oui"),
+            ],
+            temperature: 0.2,
+            max_tokens: 512,
+            provider: None,
+        };
+
+        let (messages, config) = convert(request);
+        assert_eq!(
+            config.system_prompt.as_deref(),
+            Some("Be brief\n\nThe user prefers French")
+        );
+        assert_eq!(config.max_tokens, Some(512));
+        let turns: Vec<(BedrockRole, &str)> = messages
+            .iter()
+            .map(|m| (m.role, m.content.as_str()))
+            .collect();
+        assert_eq!(
+            turns,
+            [
+                (BedrockRole::User, "Bonjour\n\nÇa va ?"),
+                (BedrockRole::Assistant, "Oui"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors_keep_retry_semantics() {
+        let retryable = |error: BedrockError| {
+            provider_error(error)
+                .downcast_ref::<Error>()
+                .is_some_and(Error::is_retryable)
+        };
+        assert!(retryable(BedrockError::RateLimited("slow down".into())));
+        assert!(retryable(BedrockError::ModelUnavailable("busy".into())));
+        assert!(!retryable(BedrockError::ContentFiltered {
+            reason: "no".into(),
+            intervention: None,
+        }));
+        assert!(!retryable(BedrockError::Authentication("expired".into())));
+    }
+}
@@ -13,6 +13,7 @@
 //!
 //! ```bash
 //! universal-bot-cli
+//! universal-bot-cli --preflight [--generate]
 //! ```
 //!
 //! # Prerequisites
@@ -25,12 +26,19 @@ use aws_config::BehaviorVersion;
 use aws_sdk_bedrockruntime::{primitives::Blob, Client as BedrockClient};
 use serde_json::json;
 use std::io::{self, Write};
+use universal_bot_core::{Bot, BotConfig, PreflightOptions};
 
 /// Claude Opus 4.1 inference profile for AWS Bedrock
 const CLAUDE_OPUS_4_1: &str = "us.anthropic.claude-opus-4-1-20250805-v1:0";
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--preflight") {
+        let generate = args.iter().any(|arg| arg == "--generate");
+        return run_preflight(generate).await;
+    }
+
     // Initialize AWS SDK
     let aws_config = aws_config::defaults(BehaviorVersion::latest())
         .region("us-east-1")
@@ -84,19 +92,23 @@ async fn main() -> Result<()> {
                     Ok((response, usage)) => {
                         println!("✅\n");
                         println!("🤖 Claude: {}\n", response);
-                        
+
                         if let Some((input_tokens, output_tokens)) = usage {
                             let total = input_tokens + output_tokens;
                             let cost = estimate_cost(input_tokens, output_tokens);
-                            println!("📊 Tokens: {} in, {} out, {} total | Cost: ~${:.4}", 
-                                   input_tokens, output_tokens, total, cost);
+                            println!(
+                                "📊 Tokens: {} in, {} out, {} total | Cost: ~${:.4}",
+                                input_tokens, output_tokens, total, cost
+                            );
                         }
                         println!("─────────────────────────────────────────────\n");
                     }
                     Err(e) => {
                         println!("❌");
                         eprintln!("Error: {}\n", e);
-                        println!("💡 Make sure you have AWS credentials configured and Bedrock access.");
+                        println!(
+                            "💡 Make sure you have AWS credentials configured and Bedrock access."
+                        );
                         println!("   Run: aws configure\n");
                     }
                 }
@@ -118,6 +130,18 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Run the bot's startup self-test and exit non-zero if any check fails
+async fn run_preflight(generate: bool) -> Result<()> {
+    let bot = Bot::new(BotConfig::from_env()?).await?;
+    let report = bot.preflight(PreflightOptions { generate }).await;
+
+    print!("{report}");
+    if !report.is_ok() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
 /// Show help information
 fn show_help() {
     println!("\n📚 Universal Bot CLI Help");
@@ -125,6 +149,7 @@ fn show_help() {
     println!("Commands:");
     println!("  help, /help, ?     - Show this help");
     println!("  quit, exit, q      - Exit the CLI");
+    println!("  (run with --preflight to check configuration and exit)");
     println!("  <your question>    - Ask Claude anything");
     println!();
     println!("Examples:");
@@ -137,9 +162,9 @@ fn show_help() {
 /// Estimate cost based on token usage (Claude Opus 4.1 pricing)
 fn estimate_cost(input_tokens: u64, output_tokens: u64) -> f64 {
     // Claude Opus 4.1 pricing (approximate)
-    let input_rate = 0.000015;  // $15 per 1M input tokens
+    let input_rate = 0.000015; // $15 per 1M input tokens
     let output_rate = 0.000075; // $75 per 1M output tokens
-    
+
     (input_tokens as f64 * input_rate) + (output_tokens as f64 * output_rate)
 }
 
@@ -188,4 +213,4 @@ async fn query_bedrock(
     };
 
     Ok((content, usage))
-}
\ No newline at end of file
+}
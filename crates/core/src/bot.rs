@@ -6,15 +6,46 @@
 use std::sync::Arc;
 
 use anyhow::{Context as _, Result};
+use futures::Stream;
 use parking_lot::RwLock;
 use tracing::{debug, info, instrument, warn};
 
 use crate::{
+    botfile::Botfile,
+    branch::{self, BranchComparison, BranchSide, BranchVariant},
+    cluster::TaskSupervisor,
+    completion::CompletionFn,
     config::BotConfig,
-    context::ContextManager,
-    message::{Message, Response},
-    pipeline::MessagePipeline,
-    plugin::PluginRegistry,
+    context::{Context, ContextManager},
+    degraded::{DegradedMode, DegradedReason},
+    diagnostics::{DebugBundle, DiagnosticsRecorder},
+    diff::{ResponseDiffer, RESPONSE_DIFF_METADATA_KEY},
+    encryption::KeyProvider,
+    expiry::{ExpiryNotifier, ExpiryWatcher},
+    governor::{CostGovernor, SpendAlertHook},
+    introspection::BotDescriptor,
+    job::{Job, JobId, JobManager, MemoryJobStore},
+    message::{Message, Response, TokenUsage},
+    overflow::{InputOverflow, INPUT_OVERFLOW_METADATA_KEY},
+    pipeline::{FormatStage, PipelineContext},
+    plugin::{PluginRegistry, PluginResponse},
+    preflight::{self, CheckStatus, PreflightOptions, PreflightReport},
+    prompt_registry::PromptRegistry,
+    prompt_template::PromptTemplate,
+    provider::Provider,
+    provisioned::ProvisionedThroughputManager,
+    retrieval::Retriever,
+    selection::BUDGET_PRESSURE_METADATA_KEY,
+    session::{SessionManager, SessionStore},
+    shedding::LoadShedder,
+    slo::{LatencyHistogram, SloAlertHook, SloTracker},
+    streaming::{ChunkSink, StreamChunk},
+    telemetry::{Feature, Telemetry},
+    tools::{CalculatorPlugin, FxRates, StaticFxRates, UnitConverterPlugin},
+    transcript::Transcript,
+    usage::UsageTimeline,
+    versioning::{ConfigVersion, ConfigVersionRegistry, CONFIG_VERSION_METADATA_KEY},
+    webhook::{WebhookManager, WebhookRegistration},
 };
 
 /// The main Bot struct that handles all AI interactions
@@ -27,10 +58,24 @@ use crate::{
 #[derive(Clone)]
 pub struct Bot {
     config: Arc<BotConfig>,
-    pipeline: Arc<MessagePipeline>,
+    versions: Arc<ConfigVersionRegistry>,
     context_manager: Arc<ContextManager>,
-    plugin_registry: Arc<RwLock<PluginRegistry>>,
+    plugin_registry: Arc<RwLock<Arc<PluginRegistry>>>,
     metrics: Arc<BotMetrics>,
+    webhooks: Option<Arc<WebhookManager>>,
+    jobs: Arc<JobManager>,
+    degraded: Arc<DegradedMode>,
+    diagnostics: Arc<DiagnosticsRecorder>,
+    telemetry: Arc<Telemetry>,
+    differ: Arc<ResponseDiffer>,
+    overflow: Arc<InputOverflow>,
+    slo: Arc<SloTracker>,
+    shedder: Arc<LoadShedder>,
+    expiry: Arc<ExpiryWatcher>,
+    sessions: Arc<SessionManager>,
+    governor: Arc<CostGovernor>,
+    supervisor: Option<Arc<TaskSupervisor>>,
+    prompts: Arc<PromptRegistry>,
 }
 
 impl Bot {
@@ -53,36 +98,135 @@ impl Bot {
     /// # Ok(())
     /// # }
     /// ```
-    #[instrument(skip(config))]
     pub async fn new(config: BotConfig) -> Result<Self> {
+        // Construction is a large future with every feature enabled, so keep it
+        // off the caller's stack
+        Box::pin(BotBuilder::new().config(config).build()).await
+    }
+
+    /// Create a Bot from a YAML or TOML botfile
+    ///
+    /// See [`crate::botfile`] for the format. Use [`BotBuilder::botfile`] to
+    /// also register custom plugins the botfile refers to.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the botfile cannot be read or is invalid, or a
+    /// plugin it lists is not registered.
+    pub async fn from_botfile(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let botfile = Botfile::load(path)?;
+        BotBuilder::new().botfile(&botfile)?.build().await
+    }
+
+    /// The bot with the builder's configuration and the components the
+    /// pipeline, context store, and background tasks are created with
+    #[instrument(skip(builder))]
+    async fn create(builder: &BotBuilder) -> Result<Self> {
         info!("Initializing Universal Bot v{}", crate::VERSION);
+        let config = builder.config.clone();
+        let supervisor = builder.supervisor.clone();
 
         // Validate configuration
         config.validate().context("Invalid bot configuration")?;
 
         // Initialize components
-        let pipeline = MessagePipeline::new(&config)
-            .await
-            .context("Failed to create message pipeline")?;
-
-        let context_manager = ContextManager::new(config.context_config.clone())
-            .await
-            .context("Failed to create context manager")?;
+        let versions = ConfigVersionRegistry::with_components(
+            config.clone(),
+            builder.provider.clone(),
+            builder.retriever.clone(),
+        )
+        .await
+        .context("Failed to create message pipeline")?;
+
+        let context_manager = match builder.key_provider.clone() {
+            Some(provider) => {
+                ContextManager::with_key_provider(config.context_config.clone(), provider).await
+            }
+            None => ContextManager::new(config.context_config.clone()).await,
+        }
+        .context("Failed to create context manager")?
+        .with_fingerprint(config.fingerprint());
 
-        let plugin_registry = PluginRegistry::new();
+        let plugin_registry =
+            PluginRegistry::new().with_strict_responses(config.plugin_config.strict_responses);
 
         let metrics = BotMetrics::new();
 
+        let degraded = DegradedMode::new(config.degraded_mode.clone());
+
+        let overflow = InputOverflow::new(config.input_overflow.clone());
+
+        let telemetry = Arc::new(Telemetry::new(&config.telemetry));
+        #[cfg(feature = "telemetry")]
+        if telemetry.is_enabled() {
+            if let Some(endpoint) = &config.telemetry.endpoint {
+                let sink = crate::telemetry::HttpTelemetrySink::new(endpoint.clone())?;
+                telemetry
+                    .clone()
+                    .spawn_reporter(Arc::new(sink), config.telemetry.interval);
+            }
+        }
+
+        let shedder = LoadShedder::new(config.load_shedding.clone());
+
+        let context_manager = Arc::new(context_manager);
+        let expiry = Arc::new(ExpiryWatcher::new(
+            config.expiry_notifications.clone(),
+            context_manager.clone(),
+            config.context_config.context_ttl,
+        ));
+        if expiry.is_enabled() {
+            match &supervisor {
+                // Notices must go out once, not once per replica
+                Some(supervisor) => {
+                    let expiry = expiry.clone();
+                    supervisor
+                        .spawn_singleton("expiry-checker", move || expiry.clone().run_checker());
+                }
+                None => {
+                    expiry.clone().spawn_checker();
+                }
+            }
+        }
+
+        let slo = Arc::new(SloTracker::new(config.slo.clone()));
+        if slo.is_enabled() {
+            slo.clone().spawn_evaluator();
+        }
+
+        let sessions = SessionManager::new(config.sessions.clone());
+
+        let governor = CostGovernor::new(config.cost_governor.clone());
+
         let bot = Self {
             config: Arc::new(config),
-            pipeline: Arc::new(pipeline),
-            context_manager: Arc::new(context_manager),
-            plugin_registry: Arc::new(RwLock::new(plugin_registry)),
+            versions: Arc::new(versions),
+            context_manager,
+            plugin_registry: Arc::new(RwLock::new(Arc::new(plugin_registry))),
             metrics: Arc::new(metrics),
+            webhooks: None,
+            jobs: Arc::new(JobManager::new(Arc::new(MemoryJobStore::new()))),
+            degraded: Arc::new(degraded),
+            diagnostics: Arc::new(DiagnosticsRecorder::new()),
+            telemetry,
+            differ: Arc::new(ResponseDiffer::new()),
+            overflow: Arc::new(overflow),
+            slo,
+            shedder: Arc::new(shedder),
+            expiry,
+            sessions: Arc::new(sessions),
+            governor: Arc::new(governor),
+            supervisor,
+            prompts: Arc::new(PromptRegistry::new()),
         };
 
         // Load default plugins
-        bot.load_default_plugins();
+        let fx_rates = builder
+            .fx_rates
+            .clone()
+            .unwrap_or_else(|| Arc::new(StaticFxRates::default()));
+        bot.load_default_plugins(fx_rates)
+            .context("Failed to load default plugins")?;
 
         info!("Bot initialized successfully");
         Ok(bot)
@@ -108,33 +252,110 @@ impl Bot {
     /// # Ok(())
     /// # }
     /// ```
-    #[allow(clippy::future_not_send)]
     #[instrument(skip(self, message), fields(message_id = %message.id))]
     pub async fn process(&self, message: Message) -> Result<Response> {
         let start = std::time::Instant::now();
         self.metrics.increment_requests();
+        self.telemetry.record_feature(Feature::Process);
 
         debug!("Processing message: {:?}", message.message_type);
+        let sensitive = message.flags.sensitive;
+
+        // Reject low-priority work early rather than let everything time out
+        let _permit = match self.shedder.admit(&message) {
+            Ok(permit) => permit,
+            Err(overloaded) => {
+                let response = overloaded.into_response(message.conversation_id);
+                self.record_outcome(&response, start.elapsed(), sensitive);
+                return Ok(response);
+            }
+        };
+
+        // Don't call a provider that is known to be unavailable
+        if let Some(reason) = self.degraded.check() {
+            debug!("Serving degraded response: {:?}", reason);
+            let response = self.degraded.respond(message, reason)?;
+            self.record_outcome(&response, start.elapsed(), sensitive);
+            return Ok(response);
+        }
+
+        // Don't spend past the conversation's, the user's, or the bot's budget
+        self.governor
+            .check(&message.conversation_id, &message.user_id)?;
 
         // Get or create context
+        let waited = std::time::Instant::now();
         let context = self
             .context_manager
             .get_or_create(&message.conversation_id)
             .await
             .context("Failed to get conversation context")?;
+        self.shedder.record_wait(waited.elapsed());
 
-        // Apply plugins pre-processing
-        let message = self.apply_plugins_pre(message).await?;
+        // Close a session that has run its course, starting afresh from its wrap-up
+        self.wrap_up_session(&context).await?;
 
-        // Process through pipeline
-        let response = self
-            .pipeline
-            .process(message, context.clone())
-            .await
-            .context("Pipeline processing failed")?;
+        // Truncate, split, or summarize input over the length limit
+        let overflow = self.overflow.apply(message).await;
+        overflow.record(&mut context.write());
+        let overflow_report = overflow.report.map(|report| serde_json::json!(report));
+
+        // Apply plugins pre-processing
+        let mut message = self.apply_plugins_pre(overflow.message).await?;
+
+        // Let routing downgrade the model as the budget runs out
+        self.mark_budget_pressure(&mut message);
+
+        // Process through the active configuration version's pipeline
+        let version = self.versions.active();
+        let routed = self.pipeline_context(&version, &message, &context);
+        let prompt = routed.prompt.clone();
+        let response = match version.pipeline().process_with(routed).await {
+            Ok(response) => {
+                self.degraded.record_success();
+                response.with_metadata(CONFIG_VERSION_METADATA_KEY, serde_json::json!(version.id()))
+            }
+            Err(e) => {
+                version.record(start.elapsed(), true);
+                self.record_prompt(prompt.as_deref(), start.elapsed(), None);
+                self.record_pipeline_error(&e, &message.conversation_id, sensitive);
+                if self.degraded.record_failure(&e) {
+                    let response = self
+                        .degraded
+                        .respond(message, DegradedReason::CircuitOpen)?;
+                    self.record_outcome(&response, start.elapsed(), sensitive);
+                    return Ok(response);
+                }
+                let user_errors = &version.config().pipeline_config.user_errors;
+                if user_errors.enabled {
+                    let locale = context.read().user.locale.clone();
+                    let response = FormatStage::from_config(user_errors).error_response(
+                        &message,
+                        &e,
+                        locale.as_deref(),
+                    );
+                    self.record_outcome(&response, start.elapsed(), sensitive);
+                    return Ok(response);
+                }
+                self.slo.record(start.elapsed(), true);
+                return Err(e.context("Pipeline processing failed"));
+            }
+        };
 
         // Apply plugins post-processing
-        let response = self.apply_plugins_post(response).await?;
+        let mut response = self.apply_plugins_post(response).await?;
+        if let Some(report) = overflow_report {
+            response
+                .metadata
+                .insert(INPUT_OVERFLOW_METADATA_KEY.to_string(), report);
+        }
+
+        // Record the exchange so later turns are answered in context
+        if response.error.is_none() {
+            let mut ctx = context.write();
+            ctx.add_message(&message);
+            ctx.add_response(&response);
+        }
 
         // Update context
         self.context_manager
@@ -144,7 +365,744 @@ impl Bot {
 
         // Record metrics
         let duration = start.elapsed();
+        version.record(duration, response.error.is_some());
+        self.record_prompt(prompt.as_deref(), duration, Some(&response));
+        self.record_outcome(&response, duration, sensitive);
+        if let Some(usage) = &response.usage {
+            self.governor
+                .record(
+                    &message.conversation_id,
+                    &message.user_id,
+                    usage.estimated_cost,
+                )
+                .await;
+        }
+
+        debug!("Message processed in {:?}", duration);
+        Ok(response)
+    }
+
+    /// Process a message, streaming the reply as it is generated
+    ///
+    /// The message goes through the same steps as [`process`](Self::process);
+    /// see [`streaming`](crate::streaming) for the chunks yielded. Dropping
+    /// the stream cancels processing, and the cancelled exchange is not
+    /// recorded in the conversation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    pub fn process_stream(
+        &self,
+        message: Message,
+    ) -> impl Stream<Item = StreamChunk> + Send + Unpin + 'static {
+        self.telemetry.record_feature(Feature::Stream);
+        let (sink, mut chunks) = ChunkSink::channel();
+        let bot = self.clone();
+
+        tokio::spawn(async move {
+            let reader = sink.clone();
+            tokio::select! {
+                outcome = sink.clone().scope(bot.process(message)) => {
+                    sink.finish(&outcome).await;
+                }
+                () = reader.closed() => debug!("Stream reader gone, cancelling processing"),
+            }
+        });
+
+        futures::stream::poll_fn(move |cx| chunks.poll_recv(cx))
+    }
+
+    /// Re-run the conversation's last user message under the bot's configuration and `variant`
+    ///
+    /// Both runs use detached copies of the context, so the canonical history
+    /// is unchanged until [`choose_branch`](Self::choose_branch) commits one
+    /// of the responses. Responses are tagged with
+    /// [`BRANCH_METADATA_KEY`](crate::branch::BRANCH_METADATA_KEY) for
+    /// side-by-side display.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the conversation has no user message or either run fails.
+    pub async fn compare_branch(
+        &self,
+        conversation_id: &str,
+        variant: BranchVariant,
+    ) -> Result<BranchComparison> {
+        self.telemetry.record_feature(Feature::BranchCompare);
+        let context = self
+            .context_manager
+            .get_or_create(conversation_id)
+            .await
+            .context("Failed to get conversation context")?;
+        let (base, message, replaced_message_id) = branch::branch_point(&context.read())?;
+
+        let id = uuid::Uuid::new_v4();
+        let version = self.versions.active();
+        let (baseline, variant_response) = futures::future::try_join(
+            version
+                .pipeline()
+                .process(message.clone(), Arc::new(RwLock::new(base.clone()))),
+            version
+                .pipeline()
+                .process_with(variant.pipeline_context(&message, base)),
+        )
+        .await
+        .context("Branch comparison failed")?;
+
+        Ok(BranchComparison {
+            id,
+            conversation_id: conversation_id.to_string(),
+            replaced_message_id,
+            message,
+            variant,
+            baseline: branch::tag(baseline, id, BranchSide::Baseline),
+            variant_response: branch::tag(variant_response, id, BranchSide::Variant),
+        })
+    }
+
+    /// Commit one side of a comparison to the conversation history
+    ///
+    /// Replaces the last user message and everything after it with the
+    /// re-run message and the chosen response.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the conversation changed since the comparison was
+    /// made or the context cannot be saved.
+    pub async fn choose_branch(
+        &self,
+        comparison: &BranchComparison,
+        side: BranchSide,
+    ) -> Result<Response> {
+        let response = comparison.response(side).clone();
+        self.replace_last_exchange(
+            &comparison.conversation_id,
+            comparison.replaced_message_id,
+            &comparison.message,
+            &response,
+        )
+        .await?;
+        Ok(response)
+    }
+
+    /// Answer the conversation's last user message again, replacing the previous answer
+    ///
+    /// The new response carries a [`ResponseDiff`](crate::diff::ResponseDiff)
+    /// against the replaced answer under [`RESPONSE_DIFF_METADATA_KEY`], so a
+    /// UI can highlight what changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the conversation has no user message, processing
+    /// fails, or the conversation changed while regenerating.
+    pub async fn regenerate(&self, conversation_id: &str) -> Result<Response> {
+        let context = self
+            .context_manager
+            .get_or_create(conversation_id)
+            .await
+            .context("Failed to get conversation context")?;
+        let (base, message, replaced_message_id, previous) = {
+            let ctx = context.read();
+            let previous = ctx
+                .history
+                .back()
+                .filter(|entry| entry.role == crate::context::MessageRole::Assistant)
+                .map(|entry| entry.content.to_string());
+            let (base, message, replaced) = branch::branch_point(&ctx)?;
+            drop(ctx);
+            (base, message, replaced, previous)
+        };
+
+        let pipeline = self.versions.active().pipeline().clone();
+        let mut response = pipeline
+            .process(message.clone(), Arc::new(RwLock::new(base)))
+            .await
+            .context("Regeneration failed")?;
+        if let Some(previous) = previous {
+            let diff = self.differ.diff(&previous, &response.content).await?;
+            response = response.with_metadata(RESPONSE_DIFF_METADATA_KEY, serde_json::json!(diff));
+        }
+
+        self.replace_last_exchange(conversation_id, replaced_message_id, &message, &response)
+            .await?;
+        Ok(response)
+    }
+
+    /// Register a plugin with the bot
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if plugin registration fails.
+    pub fn register_plugin<P>(&self, plugin: P) -> Result<()>
+    where
+        P: crate::plugin::Plugin + 'static,
+    {
+        Arc::make_mut(&mut self.plugin_registry.write()).register(Box::new(plugin))?;
+        Ok(())
+    }
+
+    /// Invoke a tool provided by a registered plugin
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no plugin named `plugin_name` provides tools or
+    /// the plugin fails.
+    pub async fn invoke_tool(
+        &self,
+        plugin_name: &str,
+        data: serde_json::Value,
+    ) -> Result<PluginResponse> {
+        self.telemetry.record_feature(Feature::Tool);
+        self.plugins()
+            .invoke_tool(plugin_name, data, std::collections::HashMap::new())
+            .await
+    }
+
+    /// Run startup self-tests and report the outcome of each
+    ///
+    /// Checks configuration validity, credential resolution, context store
+    /// connectivity, and model routing. With [`PreflightOptions::generate`]
+    /// set, one tiny message is also sent through the pipeline, outside any
+    /// stored conversation. Failures are reported, never returned as errors.
+    #[instrument(skip(self))]
+    pub async fn preflight(&self, options: PreflightOptions) -> PreflightReport {
+        self.telemetry.record_feature(Feature::Preflight);
+        let mut report = PreflightReport::default();
+
+        let started = std::time::Instant::now();
+        let outcome = match self.config.validate() {
+            Ok(()) => (CheckStatus::Pass, "Configuration is valid".to_string()),
+            Err(e) => (CheckStatus::Fail, e.to_string()),
+        };
+        report.record("config", started, outcome);
+
+        let started = std::time::Instant::now();
+        report.record("credentials", started, preflight::resolve_credentials());
+
+        let started = std::time::Instant::now();
+        let outcome = match self.context_manager.probe().await {
+            Ok(()) => (CheckStatus::Pass, "Context store is reachable".to_string()),
+            Err(e) => (CheckStatus::Fail, format!("Context store unreachable: {e}")),
+        };
+        report.record("store", started, outcome);
+
+        let started = std::time::Instant::now();
+        let routed = self
+            .config
+            .pipeline_config
+            .enabled_stages
+            .iter()
+            .any(|stage| stage == "process");
+        let outcome = if routed {
+            (
+                CheckStatus::Pass,
+                format!("Model {} is served by the process stage", self.config.model),
+            )
+        } else {
+            (
+                CheckStatus::Fail,
+                "No process stage is enabled, so no model will be called".to_string(),
+            )
+        };
+        report.record("model", started, outcome);
+
+        if options.generate {
+            let started = std::time::Instant::now();
+            let probe = Message::text("ping").with_conversation_id("preflight");
+            let context = Arc::new(RwLock::new(Context::new("preflight")));
+            let pipeline = self.versions.active().pipeline().clone();
+            let outcome = match pipeline.process(probe, context).await {
+                Ok(response) => match response.error {
+                    Some(error) => (CheckStatus::Fail, error.message),
+                    None if response.content.trim().is_empty() => (
+                        CheckStatus::Warn,
+                        "Generation returned no content".to_string(),
+                    ),
+                    None => (CheckStatus::Pass, "Generation succeeded".to_string()),
+                },
+                Err(e) => (CheckStatus::Fail, format!("Generation failed: {e}")),
+            };
+            report.record("generation", started, outcome);
+        }
+
+        info!("Preflight finished with status {:?}", report.status());
+        report
+    }
+
+    /// Process a message in the background and return a job ID to poll
+    ///
+    /// A response carrying an error fails the job with that error's message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the job cannot be persisted.
+    pub async fn submit_job(&self, message: Message) -> Result<JobId> {
+        self.telemetry.record_feature(Feature::Job);
+        let bot = self.clone();
+        let conversation_id = message.conversation_id.clone();
+        self.jobs
+            .submit(conversation_id, move |progress| async move {
+                progress.report(0.0, Some("processing"), None).await?;
+                let response = bot.process(message).await?;
+                match response.error {
+                    Some(error) => Err(crate::error::Error::Pipeline(error.message).into()),
+                    None => Ok(response),
+                }
+            })
+            .await
+    }
+
+    /// Current status and result of a job
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the job store fails.
+    pub async fn get_job(&self, id: JobId) -> Result<Option<Job>> {
+        self.jobs.get(id).await
+    }
+
+    /// Request cancellation of a running job
+    #[must_use]
+    pub fn cancel_job(&self, id: JobId) -> bool {
+        self.jobs.cancel(id)
+    }
+
+    /// Background job manager, e.g. to subscribe to completion events
+    #[must_use]
+    pub fn jobs(&self) -> &Arc<JobManager> {
+        &self.jobs
+    }
+
+    /// Register a callback URL for asynchronous events in a conversation
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bot was built without webhooks or the
+    /// registration is invalid.
+    pub fn register_webhook(
+        &self,
+        conversation_id: impl Into<String>,
+        registration: WebhookRegistration,
+    ) -> Result<()> {
+        let webhooks = self.webhooks.as_ref().ok_or_else(|| {
+            crate::error::Error::Configuration("Webhooks are not enabled for this bot".into())
+        })?;
+        self.telemetry.record_feature(Feature::Webhook);
+        webhooks.register(conversation_id, registration)
+    }
+
+    /// Degraded-mode state, e.g. to drain queued messages after recovery
+    #[must_use]
+    pub fn degraded_mode(&self) -> &Arc<DegradedMode> {
+        &self.degraded
+    }
+
+    /// Provisioned throughput routing and commitment utilization, if configured
+    #[must_use]
+    pub fn provisioned_throughput(&self) -> Option<Arc<ProvisionedThroughputManager>> {
+        self.versions
+            .active()
+            .pipeline()
+            .provisioned_throughput()
+            .cloned()
+    }
+
+    /// Configuration versions, e.g. to compare per-version metrics
+    #[must_use]
+    pub fn config_versions(&self) -> &Arc<ConfigVersionRegistry> {
+        &self.versions
+    }
+
+    /// Register a candidate configuration for prompts, routing, and stages
+    ///
+    /// The candidate serves no traffic until activated with
+    /// [`activate_config_version`](Self::activate_config_version). Settings
+    /// outside the pipeline, such as context storage, plugins, and budgets,
+    /// stay as the bot was created with.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration is invalid.
+    pub async fn register_config_version(&self, config: BotConfig) -> Result<u32> {
+        self.versions.register(config).await
+    }
+
+    /// Switch all new requests to a registered configuration version
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no such version is registered.
+    pub fn activate_config_version(&self, id: u32) -> Result<()> {
+        self.versions.activate(id)?;
+        Ok(())
+    }
+
+    /// Switch back to the previously active configuration version
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no other version has been active.
+    pub fn rollback_config_version(&self) -> Result<u32> {
+        Ok(self.versions.rollback()?.id())
+    }
+
+    /// Usage telemetry, e.g. to turn reporting on or off at runtime
+    #[must_use]
+    pub fn telemetry(&self) -> &Arc<Telemetry> {
+        &self.telemetry
+    }
+
+    /// Latency objectives, e.g. to check error budgets or add alert hooks
+    #[must_use]
+    pub fn slo(&self) -> &Arc<SloTracker> {
+        &self.slo
+    }
+
+    /// Admission control, e.g. to report pool waits or export saturation
+    #[must_use]
+    pub fn load_shedder(&self) -> &Arc<LoadShedder> {
+        &self.shedder
+    }
+
+    /// Expiry notices, e.g. to add a channel adapter as a notifier
+    #[must_use]
+    pub fn expiry(&self) -> &Arc<ExpiryWatcher> {
+        &self.expiry
+    }
+
+    /// Versioned prompts and experiments, e.g. to register a prompt version
+    /// or compare per-version metrics
+    ///
+    /// See [`PromptTemplateConfig::registry_prompt`](crate::config::PromptTemplateConfig::registry_prompt).
+    #[must_use]
+    pub fn prompt_registry(&self) -> &Arc<PromptRegistry> {
+        &self.prompts
+    }
+
+    /// Coordinator of singleton tasks across replicas, if one was set
+    #[must_use]
+    pub const fn task_supervisor(&self) -> Option<&Arc<TaskSupervisor>> {
+        self.supervisor.as_ref()
+    }
+
+    /// Spend limits, e.g. to check what a user has spent or reset a budget
+    #[must_use]
+    pub fn cost_governor(&self) -> &Arc<CostGovernor> {
+        &self.governor
+    }
+
+    /// Session wrap-ups, e.g. to list a conversation's past sessions
+    #[must_use]
+    pub fn sessions(&self) -> &Arc<SessionManager> {
+        &self.sessions
+    }
+
+    /// Keep a conversation the user chose to continue, restarting its TTL
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the conversation does not exist or has expired,
+    /// or persisting it fails.
+    pub async fn renew_conversation(&self, conversation_id: &str) -> Result<()> {
+        self.context_manager.renew(conversation_id).await
+    }
+
+    /// A copy of a conversation's context, e.g. for the user to download
+    /// before it expires
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the conversation does not exist or has expired.
+    pub async fn export_conversation(&self, conversation_id: &str) -> Result<Context> {
+        self.context_manager.export(conversation_id).await
+    }
+
+    /// A conversation as a [`Transcript`], e.g. to archive it as JSONL
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the conversation does not exist or has expired.
+    pub async fn export_transcript(&self, conversation_id: &str) -> Result<Transcript> {
+        self.context_manager
+            .export_transcript(conversation_id)
+            .await
+    }
+
+    /// Restore a conversation from a [`Transcript`], replacing any with its ID
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if persisting the conversation fails.
+    pub async fn import_transcript(&self, transcript: Transcript) -> Result<()> {
+        self.context_manager
+            .import_transcript(transcript)
+            .await
+            .map(drop)
+    }
+
+    /// Process a message and parse the reply as `T`
+    ///
+    /// `T`'s JSON Schema is sent with the message under
+    /// [`OUTPUT_SCHEMA_METADATA_KEY`](crate::structured::OUTPUT_SCHEMA_METADATA_KEY).
+    /// A reply that does not match the schema or parse as `T` is answered,
+    /// in the same conversation, with the error and a request to correct
+    /// it, at most `max_repairs` times.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if processing fails or the bot answers with an
+    /// error response, or [`Error::Serialization`](crate::error::Error::Serialization)
+    /// if no reply parses.
+    #[cfg(feature = "schema")]
+    pub async fn process_structured<T>(
+        &self,
+        message: Message,
+        max_repairs: usize,
+    ) -> Result<crate::structured::StructuredResponse<T>>
+    where
+        T: serde::de::DeserializeOwned + schemars::JsonSchema,
+    {
+        use crate::structured::{self, StructuredResponse, OUTPUT_SCHEMA_METADATA_KEY};
+
+        let schema = serde_json::to_value(schemars::schema_for!(T))?;
+        let mut message = message.with_metadata(OUTPUT_SCHEMA_METADATA_KEY, schema.clone());
+        let mut last_error = None;
+        for attempt in 0..=max_repairs {
+            let response = self.process(message.clone()).await?;
+            if let Some(error) = &response.error {
+                return Err(crate::error::Error::Pipeline(error.message.clone()).into());
+            }
+            match structured::parse_structured(&response.content, &schema) {
+                Ok(value) => {
+                    return Ok(StructuredResponse {
+                        value,
+                        response,
+                        attempts: attempt + 1,
+                    })
+                }
+                Err(e) => {
+                    debug!("Structured reply {} did not parse: {}", attempt + 1, e);
+                    let metadata = std::mem::take(&mut message.metadata);
+                    message = Message::text(structured::repair_prompt(&e))
+                        .with_conversation_id(message.conversation_id)
+                        .with_user_id(message.user_id)
+                        .with_parent(response.id);
+                    message.metadata = metadata;
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(crate::error::Error::Serialization(format!(
+            "No reply matched the schema after {} attempts: {}",
+            max_repairs + 1,
+            last_error.map(|e| e.to_string()).unwrap_or_default()
+        ))
+        .into())
+    }
+
+    /// Tokens and cost per turn of a conversation, with running totals
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the conversation does not exist or has expired.
+    pub async fn usage_timeline(&self, conversation_id: &str) -> Result<UsageTimeline> {
+        let context = self.context_manager.export(conversation_id).await?;
+        Ok(UsageTimeline::from_context(&context))
+    }
+
+    /// Webhook delivery, if enabled
+    #[must_use]
+    pub fn webhooks(&self) -> Option<&Arc<WebhookManager>> {
+        self.webhooks.as_ref()
+    }
+
+    /// Redacted diagnostics for attaching to a support ticket
+    ///
+    /// Contains a configuration snapshot, recent execution traces and errors
+    /// for the conversation, and a metrics snapshot. Traces are only
+    /// recorded while [`PipelineConfig::attach_trace`](crate::config::PipelineConfig::attach_trace)
+    /// is enabled, or for requests sampled by
+    /// [`PipelineConfig::trace_sampling`](crate::config::PipelineConfig::trace_sampling).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration cannot be serialized.
+    pub fn debug_bundle(&self, conversation_id: &str) -> Result<DebugBundle> {
+        self.telemetry.record_feature(Feature::DebugBundle);
+        DebugBundle::new(
+            conversation_id,
+            &self.config,
+            &self.metrics,
+            &self.diagnostics,
+        )
+    }
+
+    /// What the bot is configured to do: pipeline stages, plugins, tools,
+    /// and model routing under the active configuration version
+    #[must_use]
+    pub fn describe(&self) -> BotDescriptor {
+        let version = self.versions.active();
+        BotDescriptor::new(
+            version.id(),
+            version.config(),
+            version.pipeline().stage_names(),
+            &self.plugins(),
+        )
+    }
+
+    /// Get the current bot configuration
+    #[must_use]
+    pub fn config(&self) -> &BotConfig {
+        &self.config
+    }
+
+    /// Pipeline context for `message`, carrying the registry prompt version
+    /// assigned to the conversation if the configuration names one
+    fn pipeline_context(
+        &self,
+        version: &ConfigVersion,
+        message: &Message,
+        context: &Arc<RwLock<Context>>,
+    ) -> PipelineContext {
+        let prompt = version
+            .config()
+            .pipeline_config
+            .prompt_templates
+            .registry_prompt
+            .as_deref()
+            .and_then(|name| {
+                let prompt = self.prompts.assign(name, &message.conversation_id);
+                if prompt.is_none() {
+                    warn!(
+                        "Prompt {} is not registered, using the configured system prompt",
+                        name
+                    );
+                }
+                prompt
+            });
+        PipelineContext::new(message.clone(), context.clone()).with_prompt(prompt)
+    }
+
+    /// Record a request's outcome against its prompt version; no response means it failed
+    fn record_prompt(
+        &self,
+        prompt: Option<&PromptTemplate>,
+        latency: std::time::Duration,
+        response: Option<&Response>,
+    ) {
+        if let Some(prompt) = prompt {
+            let failed = response.is_none_or(Response::is_error);
+            let tokens = response
+                .and_then(|response| response.usage.as_ref())
+                .map_or(0, |usage| usage.total_tokens);
+            self.prompts.record(prompt, latency, failed, tokens);
+        }
+    }
+
+    /// Get metrics for monitoring
+    #[must_use]
+    pub fn metrics(&self) -> &BotMetrics {
+        &self.metrics
+    }
+
+    // Private helper methods
+
+    /// Swap the last exchange for `message` and `response` if its user message is still `replaced`
+    async fn replace_last_exchange(
+        &self,
+        conversation_id: &str,
+        replaced: Option<uuid::Uuid>,
+        message: &Message,
+        response: &Response,
+    ) -> Result<()> {
+        let context = self
+            .context_manager
+            .get_or_create(conversation_id)
+            .await
+            .context("Failed to get conversation context")?;
+
+        {
+            let mut ctx = context.write();
+            let last_user = ctx
+                .history
+                .iter()
+                .rev()
+                .find(|message| message.role == crate::context::MessageRole::User)
+                .map(|message| message.message_id);
+            if last_user != Some(replaced) {
+                return Err(crate::error::Error::Validation(format!(
+                    "Conversation {conversation_id} changed since its last answer was re-run"
+                ))
+                .into());
+            }
+            ctx.undo_last_exchange();
+            ctx.add_message(message);
+            ctx.add_response(response);
+        }
+
+        self.context_manager
+            .update(conversation_id, context)
+            .await
+            .context("Failed to update context")?;
+        Ok(())
+    }
+
+    /// Close the conversation's session if it is over, replacing its history
+    /// with the wrap-up
+    async fn wrap_up_session(&self, context: &Arc<RwLock<Context>>) -> Result<()> {
+        self.sessions
+            .wrap_up(context)
+            .await
+            .context("Failed to close session")?;
+        Ok(())
+    }
+
+    /// Tag `message` with the budget pressure, if any, for routing to act on
+    fn mark_budget_pressure(&self, message: &mut Message) {
+        match self
+            .config
+            .budget
+            .pressure(self.metrics.tokens_total(), self.metrics.cost_total())
+        {
+            Some(pressure) => message.metadata.insert(
+                BUDGET_PRESSURE_METADATA_KEY.to_string(),
+                serde_json::json!(pressure),
+            ),
+            None => message.metadata.remove(BUDGET_PRESSURE_METADATA_KEY),
+        };
+    }
+
+    fn record_pipeline_error(&self, error: &anyhow::Error, conversation_id: &str, sensitive: bool) {
+        let retryable = error
+            .chain()
+            .filter_map(|cause| cause.downcast_ref::<crate::error::Error>())
+            .any(crate::error::Error::is_retryable);
+        self.telemetry.record_error(error);
+        self.diagnostics.record_error(
+            conversation_id,
+            "pipeline_error",
+            &format!("{error:#}"),
+            retryable,
+            sensitive,
+        );
+    }
+
+    fn record_outcome(&self, response: &Response, duration: std::time::Duration, sensitive: bool) {
         self.metrics.record_response_time(duration);
+        self.slo.record(duration, response.error.is_some());
+        self.diagnostics.record_response(response, sensitive);
+
+        if let Some(usage) = &response.usage {
+            self.metrics.record_usage(usage);
+            let budget = &self.config.budget;
+            if budget.stops_at_limit()
+                && budget.is_exhausted(self.metrics.tokens_total(), self.metrics.cost_total())
+            {
+                warn!("Budget exhausted, switching to degraded mode");
+                self.degraded.set_budget_exhausted(true);
+            }
+        }
 
         if response.error.is_some() {
             self.metrics.increment_errors();
@@ -152,88 +1110,232 @@ impl Bot {
         } else {
             self.metrics.increment_success();
         }
+    }
 
-        debug!("Message processed in {:?}", duration);
-        Ok(response)
+    fn load_default_plugins(&self, fx_rates: Arc<dyn FxRates>) -> Result<()> {
+        debug!("Loading default plugins");
+        let plugin_config = &self.config.plugin_config;
+        if !plugin_config.enable_plugins || !plugin_config.builtin_tools {
+            return Ok(());
+        }
+
+        let mut guard = self.plugin_registry.write();
+        let registry = Arc::make_mut(&mut guard);
+        registry.register(Box::new(CalculatorPlugin::new()))?;
+        registry.register(Box::new(UnitConverterPlugin::with_fx_rates(fx_rates)))?;
+        drop(guard);
+        Ok(())
+    }
+
+    /// The registered plugins, cloned out of the lock so they can be used
+    /// across `.await`
+    fn plugins(&self) -> Arc<PluginRegistry> {
+        Arc::clone(&self.plugin_registry.read())
+    }
+
+    async fn apply_plugins_pre(&self, message: Message) -> Result<Message> {
+        self.plugins().apply_pre_processing(message).await
+    }
+
+    async fn apply_plugins_post(&self, response: Response) -> Result<Response> {
+        self.plugins().apply_post_processing(response).await
+    }
+}
+
+/// Builder for creating Bot instances with custom configuration
+pub struct BotBuilder {
+    config: BotConfig,
+    plugins: Vec<Box<dyn crate::plugin::Plugin>>,
+    webhooks: Option<Arc<WebhookManager>>,
+    fx_rates: Option<Arc<dyn FxRates>>,
+    plugin_configs: Option<Vec<(String, crate::plugin::PluginConfig)>>,
+    differ: Option<Arc<ResponseDiffer>>,
+    overflow_summarizer: Option<CompletionFn>,
+    session_summarizer: Option<CompletionFn>,
+    session_store: Option<Arc<dyn SessionStore>>,
+    key_provider: Option<Arc<dyn KeyProvider>>,
+    provider: Option<Arc<dyn Provider>>,
+    retriever: Option<Arc<Retriever>>,
+    slo_hooks: Vec<Arc<dyn SloAlertHook>>,
+    spend_hooks: Vec<Arc<dyn SpendAlertHook>>,
+    expiry_notifiers: Vec<Arc<dyn ExpiryNotifier>>,
+    supervisor: Option<Arc<TaskSupervisor>>,
+    prompts: Option<Arc<PromptRegistry>>,
+}
+
+impl BotBuilder {
+    /// Create a new builder with default configuration
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            config: BotConfig::default(),
+            plugins: Vec::new(),
+            webhooks: None,
+            fx_rates: None,
+            plugin_configs: None,
+            differ: None,
+            overflow_summarizer: None,
+            session_summarizer: None,
+            session_store: None,
+            key_provider: None,
+            provider: None,
+            retriever: None,
+            slo_hooks: Vec::new(),
+            spend_hooks: Vec::new(),
+            expiry_notifiers: Vec::new(),
+            supervisor: None,
+            prompts: None,
+        }
+    }
+
+    /// Set the bot configuration
+    #[must_use]
+    pub fn config(mut self, config: BotConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Use the configuration and plugin settings of a botfile
+    ///
+    /// Registered plugins the botfile does not list as enabled are removed
+    /// when the bot is built.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the botfile does not describe a valid configuration.
+    pub fn botfile(mut self, botfile: &Botfile) -> Result<Self> {
+        self.config = botfile.config()?;
+        self.plugin_configs = botfile.plugin_configs();
+        Ok(self)
+    }
+
+    /// Add a plugin to be registered on initialization
+    #[must_use]
+    pub fn plugin<P>(mut self, plugin: P) -> Self
+    where
+        P: crate::plugin::Plugin + 'static,
+    {
+        self.plugins.push(Box::new(plugin));
+        self
+    }
+
+    /// Deliver asynchronous events to per-conversation callbacks
+    #[must_use]
+    pub fn webhooks(mut self, webhooks: Arc<WebhookManager>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Compare regenerated answers with a custom differ, e.g. one using embeddings
+    #[must_use]
+    pub fn response_differ(mut self, differ: ResponseDiffer) -> Self {
+        self.differ = Some(Arc::new(differ));
+        self
+    }
+
+    /// Summarize overlong input for tenants whose overflow policy is to summarize
+    ///
+    /// Without a summarizer those tenants' input is truncated instead. See
+    /// [`OverflowPolicy::Summarize`](crate::config::OverflowPolicy::Summarize).
+    #[must_use]
+    pub fn overflow_summarizer(mut self, summarizer: CompletionFn) -> Self {
+        self.overflow_summarizer = Some(summarizer);
+        self
     }
 
-    /// Register a plugin with the bot
-    ///
-    /// # Errors
+    /// Write session wrap-ups with a completion function
     ///
-    /// Returns an error if plugin registration fails.
-    pub fn register_plugin<P>(&self, plugin: P) -> Result<()>
-    where
-        P: crate::plugin::Plugin + 'static,
-    {
-        self.plugin_registry.write().register(Box::new(plugin))?;
-        Ok(())
+    /// Without a summarizer a wrap-up lists the user's requests. See
+    /// [`SessionConfig`](crate::config::SessionConfig).
+    #[must_use]
+    pub fn session_summarizer(mut self, summarizer: CompletionFn) -> Self {
+        self.session_summarizer = Some(summarizer);
+        self
     }
 
-    /// Get the current bot configuration
+    /// Keep closed sessions in `store` instead of in memory
     #[must_use]
-    pub fn config(&self) -> &BotConfig {
-        &self.config
+    pub fn session_store(mut self, store: Arc<dyn SessionStore>) -> Self {
+        self.session_store = Some(store);
+        self
     }
 
-    /// Get metrics for monitoring
+    /// Wrap context encryption keys with `provider` instead of AWS KMS
+    ///
+    /// See [`EncryptionConfig`](crate::config::EncryptionConfig).
     #[must_use]
-    pub fn metrics(&self) -> &BotMetrics {
-        &self.metrics
+    pub fn key_provider(mut self, provider: Arc<dyn KeyProvider>) -> Self {
+        self.key_provider = Some(provider);
+        self
     }
 
-    // Private helper methods
+    /// Generate replies with `provider`, e.g. `bedrock::BedrockProvider`
+    ///
+    /// Without a provider the bot answers with placeholder text.
+    #[must_use]
+    pub fn provider(mut self, provider: Arc<dyn Provider>) -> Self {
+        self.provider = Some(provider);
+        self
+    }
 
-    #[allow(clippy::unused_self)]
-    fn load_default_plugins(&self) {
-        debug!("Loading default plugins");
-        // Load built-in plugins based on configuration
-        // This would load various default plugins
+    /// Look up documents for the `retrieve` pipeline stage with `retriever`
+    ///
+    /// See [`RetrieveStage`](crate::retrieval::RetrieveStage).
+    #[must_use]
+    pub fn retriever(mut self, retriever: Arc<Retriever>) -> Self {
+        self.retriever = Some(retriever);
+        self
     }
 
-    #[allow(clippy::future_not_send, clippy::await_holding_lock)]
-    async fn apply_plugins_pre(&self, message: Message) -> Result<Message> {
-        let registry = self.plugin_registry.read();
-        registry.apply_pre_processing(message).await
+    /// Notify `hook` when a latency objective's error budget burns too fast
+    ///
+    /// See [`SloConfig`](crate::config::SloConfig).
+    #[must_use]
+    pub fn slo_alert_hook(mut self, hook: Arc<dyn SloAlertHook>) -> Self {
+        self.slo_hooks.push(hook);
+        self
     }
 
-    #[allow(clippy::future_not_send, clippy::await_holding_lock)]
-    async fn apply_plugins_post(&self, response: Response) -> Result<Response> {
-        let registry = self.plugin_registry.read();
-        registry.apply_post_processing(response).await
+    /// Notify `hook` when spend crosses an alert threshold of a budget
+    ///
+    /// See [`CostGovernorConfig`](crate::config::CostGovernorConfig).
+    #[must_use]
+    pub fn spend_alert_hook(mut self, hook: Arc<dyn SpendAlertHook>) -> Self {
+        self.spend_hooks.push(hook);
+        self
     }
-}
 
-/// Builder for creating Bot instances with custom configuration
-pub struct BotBuilder {
-    config: BotConfig,
-    plugins: Vec<Box<dyn crate::plugin::Plugin>>,
-}
+    /// Tell users through `notifier` when their conversations are about to expire
+    ///
+    /// Webhooks, if set, are notified as well. See
+    /// [`ExpiryNotificationConfig`](crate::config::ExpiryNotificationConfig).
+    #[must_use]
+    pub fn expiry_notifier(mut self, notifier: Arc<dyn ExpiryNotifier>) -> Self {
+        self.expiry_notifiers.push(notifier);
+        self
+    }
 
-impl BotBuilder {
-    /// Create a new builder with default configuration
+    /// Run singleton background tasks, such as the expiry checker, through
+    /// `supervisor` so only one replica runs each
+    ///
+    /// See [`cluster`](crate::cluster).
     #[must_use]
-    pub fn new() -> Self {
-        Self {
-            config: BotConfig::default(),
-            plugins: Vec::new(),
-        }
+    pub fn task_supervisor(mut self, supervisor: Arc<TaskSupervisor>) -> Self {
+        self.supervisor = Some(supervisor);
+        self
     }
 
-    /// Set the bot configuration
+    /// Share `registry` of versioned prompts, e.g. between bots or with an admin API
     #[must_use]
-    pub fn config(mut self, config: BotConfig) -> Self {
-        self.config = config;
+    pub fn prompt_registry(mut self, registry: Arc<PromptRegistry>) -> Self {
+        self.prompts = Some(registry);
         self
     }
 
-    /// Add a plugin to be registered on initialization
+    /// Exchange rates for the built-in unit converter tool
     #[must_use]
-    pub fn plugin<P>(mut self, plugin: P) -> Self
-    where
-        P: crate::plugin::Plugin + 'static,
-    {
-        self.plugins.push(Box::new(plugin));
+    pub fn fx_rates(mut self, fx_rates: Arc<dyn FxRates>) -> Self {
+        self.fx_rates = Some(fx_rates);
         self
     }
 
@@ -243,11 +1345,72 @@ impl BotBuilder {
     ///
     /// Returns an error if bot creation fails.
     pub async fn build(self) -> Result<Bot> {
-        let bot = Bot::new(self.config).await?;
+        let mut bot = Bot::create(&self).await?;
+        if let Some(webhooks) = self.webhooks {
+            bot.jobs = Arc::new(
+                JobManager::new(Arc::new(MemoryJobStore::new())).with_webhooks(webhooks.clone()),
+            );
+            bot.expiry.add_notifier(webhooks.clone());
+            bot.webhooks = Some(webhooks);
+        }
+        if let Some(differ) = self.differ {
+            bot.differ = differ;
+        }
+        if let Some(prompts) = self.prompts {
+            bot.prompts = prompts;
+        }
+        for hook in self.slo_hooks {
+            bot.slo.add_hook(hook);
+        }
+        for hook in self.spend_hooks {
+            bot.governor.add_hook(hook);
+        }
+        for notifier in self.expiry_notifiers {
+            bot.expiry.add_notifier(notifier);
+        }
+        if let Some(summarizer) = self.overflow_summarizer {
+            bot.overflow = Arc::new(
+                InputOverflow::new(bot.config.input_overflow.clone()).with_summarizer(summarizer),
+            );
+        }
+        if self.session_summarizer.is_some() || self.session_store.is_some() {
+            let mut sessions = SessionManager::new(bot.config.sessions.clone());
+            if let Some(summarizer) = self.session_summarizer {
+                sessions = sessions.with_summarizer(summarizer);
+            }
+            if let Some(store) = self.session_store {
+                sessions = sessions.with_store(store);
+            }
+            bot.sessions = Arc::new(sessions);
+        }
 
         for plugin in self.plugins {
-            let mut registry = bot.plugin_registry.write();
-            registry.register(plugin)?;
+            Arc::make_mut(&mut bot.plugin_registry.write()).register(plugin)?;
+        }
+
+        if let Some(plugin_configs) = self.plugin_configs {
+            // Take the registry out so no lock is held across the awaits below
+            let mut taken = std::mem::take(&mut *bot.plugin_registry.write());
+            let registry = Arc::make_mut(&mut taken);
+            let unlisted: Vec<String> = registry
+                .list()
+                .into_iter()
+                .map(|metadata| metadata.name)
+                .filter(|name| !plugin_configs.iter().any(|(listed, _)| listed == name))
+                .collect();
+            for name in unlisted {
+                registry.unregister(&name).await?;
+            }
+            for (name, config) in plugin_configs {
+                if registry.get(&name).is_none() {
+                    return Err(crate::error::Error::Configuration(format!(
+                        "Botfile plugin '{name}' is not registered"
+                    ))
+                    .into());
+                }
+                registry.configure(&name, config)?;
+            }
+            *bot.plugin_registry.write() = taken;
         }
 
         Ok(bot)
@@ -267,6 +1430,9 @@ pub struct BotMetrics {
     success_total: Arc<RwLock<u64>>,
     errors_total: Arc<RwLock<u64>>,
     response_times: Arc<RwLock<Vec<std::time::Duration>>>,
+    response_histogram: Arc<RwLock<LatencyHistogram>>,
+    tokens_total: Arc<RwLock<u64>>,
+    cost_total: Arc<RwLock<f64>>,
 }
 
 impl BotMetrics {
@@ -276,6 +1442,9 @@ impl BotMetrics {
             success_total: Arc::new(RwLock::new(0)),
             errors_total: Arc::new(RwLock::new(0)),
             response_times: Arc::new(RwLock::new(Vec::new())),
+            response_histogram: Arc::new(RwLock::new(LatencyHistogram::default())),
+            tokens_total: Arc::new(RwLock::new(0)),
+            cost_total: Arc::new(RwLock::new(0.0)),
         }
     }
 
@@ -291,7 +1460,13 @@ impl BotMetrics {
         *self.errors_total.write() += 1;
     }
 
+    fn record_usage(&self, usage: &TokenUsage) {
+        *self.tokens_total.write() += usage.total_tokens as u64;
+        *self.cost_total.write() += usage.estimated_cost;
+    }
+
     fn record_response_time(&self, duration: std::time::Duration) {
+        self.response_histogram.write().record(duration);
         let mut times = self.response_times.write();
         times.push(duration);
         // Keep only last 1000 response times
@@ -318,6 +1493,24 @@ impl BotMetrics {
         *self.errors_total.read()
     }
 
+    /// Get the total tokens reported by responses
+    #[must_use]
+    pub fn tokens_total(&self) -> u64 {
+        *self.tokens_total.read()
+    }
+
+    /// Get the total estimated cost reported by responses, in USD
+    #[must_use]
+    pub fn cost_total(&self) -> f64 {
+        *self.cost_total.read()
+    }
+
+    /// Get the response times since the bot started, bucketed
+    #[must_use]
+    pub fn response_time_histogram(&self) -> LatencyHistogram {
+        self.response_histogram.read().clone()
+    }
+
     /// Get the average response time
     #[must_use]
     #[allow(clippy::cast_possible_truncation)]
@@ -348,6 +1541,7 @@ impl BotMetrics {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::prompt_registry::PROMPT_VERSION_METADATA_KEY;
 
     #[tokio::test]
     async fn test_bot_creation() {
@@ -356,12 +1550,702 @@ mod tests {
         assert!(bot.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_describe_reports_stages_and_tools() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        let descriptor = bot.describe();
+        assert_eq!(
+            descriptor.stages,
+            bot.config().pipeline_config.enabled_stages
+        );
+        assert!(descriptor.tool("calculate").is_some());
+    }
+
     #[tokio::test]
     async fn test_bot_builder() {
         let bot = BotBuilder::new().config(BotConfig::default()).build().await;
         assert!(bot.is_ok());
     }
 
+    struct HistoryProvider;
+
+    #[async_trait::async_trait]
+    impl Provider for HistoryProvider {
+        fn name(&self) -> &str {
+            "history"
+        }
+
+        async fn generate(
+            &self,
+            request: crate::provider::ProviderRequest,
+        ) -> Result<crate::provider::ProviderResponse> {
+            Ok(crate::provider::ProviderResponse {
+                content: format!("Seen {} turns", request.messages.len()),
+                usage: TokenUsage::new(20, 10, request.model),
+                finish_reason: None,
+            })
+        }
+    }
+
+    struct SplitProvider;
+
+    #[async_trait::async_trait]
+    impl Provider for SplitProvider {
+        fn name(&self) -> &str {
+            "split"
+        }
+
+        async fn generate(
+            &self,
+            request: crate::provider::ProviderRequest,
+        ) -> Result<crate::provider::ProviderResponse> {
+            Ok(crate::provider::ProviderResponse {
+                content: "Hello there".to_string(),
+                usage: TokenUsage::new(5, 2, request.model),
+                finish_reason: None,
+            })
+        }
+
+        async fn generate_streaming(
+            &self,
+            request: crate::provider::ProviderRequest,
+            sink: &ChunkSink,
+        ) -> Result<crate::provider::ProviderResponse> {
+            sink.send("Hello").await;
+            sink.send(" there").await;
+            self.generate(request).await
+        }
+    }
+
+    struct RejectingProvider;
+
+    #[async_trait::async_trait]
+    impl Provider for RejectingProvider {
+        fn name(&self) -> &str {
+            "rejecting"
+        }
+
+        async fn generate(
+            &self,
+            _request: crate::provider::ProviderRequest,
+        ) -> Result<crate::provider::ProviderResponse> {
+            Err(
+                crate::error::Error::InvalidInput("prompt of 210000 tokens at 10.0.0.3".into())
+                    .into(),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_errors_become_user_facing_responses() {
+        let mut config = BotConfig::default();
+        config.pipeline_config.user_errors.enabled = true;
+        let bot = BotBuilder::new()
+            .config(config)
+            .provider(Arc::new(RejectingProvider))
+            .build()
+            .await
+            .unwrap();
+
+        let response = bot.process(Message::text("Hello")).await.unwrap();
+        let error = response.error.unwrap();
+        assert_eq!(error.code, "invalid_request");
+        assert_eq!(error.hint.as_deref(), Some("Try rephrasing it."));
+        assert!(!response.content.contains("10.0.0.3"));
+        assert_eq!(bot.metrics().errors_total(), 1);
+    }
+
+    /// Answers with prose first, then with JSON once asked to repair it
+    #[cfg(feature = "schema")]
+    struct RepairedProvider;
+
+    #[cfg(feature = "schema")]
+    #[async_trait::async_trait]
+    impl Provider for RepairedProvider {
+        fn name(&self) -> &str {
+            "repaired"
+        }
+
+        async fn generate(
+            &self,
+            request: crate::provider::ProviderRequest,
+        ) -> Result<crate::provider::ProviderResponse> {
+            assert!(request.system_prompt.unwrap().contains("JSON Schema"));
+            let asked_to_repair = request
+                .messages
+                .last()
+                .is_some_and(|turn| turn.content.contains("not valid for the schema"));
+            let content = if asked_to_repair {
+                "```json\n{\"city\": \"Paris\", \"days\": 3}\n```"
+            } else {
+                "Paris for three days sounds lovely!"
+            };
+            Ok(crate::provider::ProviderResponse {
+                content: content.to_string(),
+                usage: TokenUsage::new(5, 2, request.model),
+                finish_reason: None,
+            })
+        }
+    }
+
+    #[cfg(feature = "schema")]
+    #[tokio::test]
+    async fn test_process_structured_repairs_replies() {
+        #[derive(serde::Deserialize, schemars::JsonSchema)]
+        struct Trip {
+            city: String,
+            days: u32,
+        }
+
+        let bot = BotBuilder::new()
+            .provider(Arc::new(RepairedProvider))
+            .build()
+            .await
+            .unwrap();
+
+        let trip = bot
+            .process_structured::<Trip>(Message::text("Plan a trip"), 2)
+            .await
+            .unwrap();
+        assert_eq!((trip.value.city.as_str(), trip.value.days), ("Paris", 3));
+        assert_eq!(trip.attempts, 2);
+        assert!(bot
+            .process_structured::<Trip>(Message::text("Plan a trip"), 0)
+            .await
+            .is_err());
+    }
+
+    /// Answers with JSON only if the schema in the system prompt defines every property
+    #[cfg(feature = "schema")]
+    struct SchemaFieldsProvider;
+
+    #[cfg(feature = "schema")]
+    #[async_trait::async_trait]
+    impl Provider for SchemaFieldsProvider {
+        fn name(&self) -> &str {
+            "schema-fields"
+        }
+
+        async fn generate(
+            &self,
+            request: crate::provider::ProviderRequest,
+        ) -> Result<crate::provider::ProviderResponse> {
+            let system_prompt = request.system_prompt.unwrap_or_default();
+            let content = if ["author", "token_count"]
+                .iter()
+                .all(|field| system_prompt.contains(&format!("\"{field}\":{{")))
+            {
+                "{\"author\": \"Jane\", \"token_count\": 42}"
+            } else {
+                "I don't know which fields you want."
+            };
+            Ok(crate::provider::ProviderResponse {
+                content: content.to_string(),
+                usage: TokenUsage::new(5, 2, request.model),
+                finish_reason: None,
+            })
+        }
+    }
+
+    #[cfg(feature = "schema")]
+    #[tokio::test]
+    async fn test_process_structured_schema_survives_sanitization() {
+        #[derive(serde::Deserialize, schemars::JsonSchema)]
+        struct Review {
+            author: String,
+            token_count: u32,
+        }
+
+        let bot = BotBuilder::new()
+            .provider(Arc::new(SchemaFieldsProvider))
+            .build()
+            .await
+            .unwrap();
+
+        let review = bot
+            .process_structured::<Review>(Message::text("Summarize the review"), 0)
+            .await
+            .unwrap();
+        assert_eq!(review.value.author, "Jane");
+        assert_eq!(review.value.token_count, 42);
+    }
+
+    #[tokio::test]
+    async fn test_process_stream_yields_deltas_then_response() {
+        use futures::StreamExt as _;
+
+        let bot = BotBuilder::new()
+            .provider(Arc::new(SplitProvider))
+            .build()
+            .await
+            .unwrap();
+        let chunks: Vec<StreamChunk> = bot
+            .process_stream(Message::text("Hi").with_conversation_id("streamed"))
+            .collect()
+            .await;
+
+        let deltas: Vec<&str> = chunks
+            .iter()
+            .filter_map(|chunk| match chunk {
+                StreamChunk::Delta { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(deltas, ["Hello", " there"]);
+        let Some(StreamChunk::Done { response }) = chunks.last() else {
+            panic!("stream did not finish: {chunks:?}");
+        };
+        assert_eq!(response.content, "Hello there");
+        assert_eq!(
+            bot.export_conversation("streamed")
+                .await
+                .unwrap()
+                .history
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_provider_answers_in_context() {
+        let bot = BotBuilder::new()
+            .provider(Arc::new(HistoryProvider))
+            .build()
+            .await
+            .unwrap();
+
+        let first = bot
+            .process(Message::text("Hello").with_conversation_id("conv"))
+            .await
+            .unwrap();
+        assert_eq!(first.content, "Seen 1 turns");
+        assert_eq!(first.usage.unwrap().total_tokens, 30);
+
+        let second = bot
+            .process(Message::text("And again").with_conversation_id("conv"))
+            .await
+            .unwrap();
+        assert_eq!(second.content, "Seen 3 turns");
+        assert_eq!(bot.metrics().tokens_total(), 60);
+    }
+
+    #[tokio::test]
+    async fn test_idle_session_is_wrapped_up() {
+        let mut config = BotConfig::default();
+        config.sessions.enabled = true;
+        let bot = BotBuilder::new()
+            .config(config)
+            .provider(Arc::new(HistoryProvider))
+            .build()
+            .await
+            .unwrap();
+
+        bot.process(Message::text("Hello").with_conversation_id("conv"))
+            .await
+            .unwrap();
+        let context = bot.context_manager.get_or_create("conv").await.unwrap();
+        context.write().metadata.last_activity -= chrono::Duration::hours(1);
+
+        let next = bot
+            .process(Message::text("I'm back").with_conversation_id("conv"))
+            .await
+            .unwrap();
+        assert_eq!(next.content, "Seen 2 turns");
+        let records = bot.sessions().store().list("conv").await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].summary, "The user asked: Hello");
+    }
+
+    #[tokio::test]
+    async fn test_cost_governor_stops_spent_conversations() {
+        let mut config = BotConfig::default();
+        config.cost_governor.per_conversation_usd = Some(0.000_01);
+        let bot = BotBuilder::new()
+            .config(config)
+            .provider(Arc::new(HistoryProvider))
+            .build()
+            .await
+            .unwrap();
+
+        bot.process(Message::text("Hello").with_conversation_id("conv"))
+            .await
+            .unwrap();
+        let error = bot
+            .process(Message::text("Again").with_conversation_id("conv"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<crate::error::Error>(),
+            Some(crate::error::Error::BudgetExceeded { .. })
+        ));
+        bot.process(Message::text("Hi").with_conversation_id("other"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_config_version_activation_and_rollback() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        let candidate = BotConfig {
+            system_prompt: Some("Answer in one sentence.".into()),
+            ..BotConfig::default()
+        };
+        let id = bot.register_config_version(candidate).await.unwrap();
+
+        bot.activate_config_version(id).unwrap();
+        let response = bot.process(Message::text("Hello")).await.unwrap();
+        assert_eq!(response.metadata[CONFIG_VERSION_METADATA_KEY], id);
+
+        assert_eq!(bot.rollback_config_version().unwrap(), 1);
+        let response = bot.process(Message::text("Hello")).await.unwrap();
+        assert_eq!(response.metadata[CONFIG_VERSION_METADATA_KEY], 1);
+
+        let metrics = bot.config_versions().metrics();
+        assert_eq!(metrics[0].requests, 1);
+        assert_eq!(metrics[1].requests, 1);
+    }
+
+    /// Answers with the system prompt it was given
+    struct SystemPromptProvider;
+
+    #[async_trait::async_trait]
+    impl Provider for SystemPromptProvider {
+        fn name(&self) -> &str {
+            "system-prompt"
+        }
+
+        async fn generate(
+            &self,
+            request: crate::provider::ProviderRequest,
+        ) -> Result<crate::provider::ProviderResponse> {
+            Ok(crate::provider::ProviderResponse {
+                content: request.system_prompt.unwrap_or_default(),
+                usage: TokenUsage::new(20, 10, request.model),
+                finish_reason: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_prompt_experiment() {
+        let mut config = BotConfig::default();
+        config.pipeline_config.prompt_templates.registry_prompt = Some("greeter".into());
+        let bot = BotBuilder::new()
+            .config(config)
+            .provider(Arc::new(SystemPromptProvider))
+            .build()
+            .await
+            .unwrap();
+        let prompts = bot.prompt_registry();
+        prompts
+            .register(PromptTemplate::new("greeter", "Say hi."))
+            .unwrap();
+        prompts
+            .register(PromptTemplate::new("greeter", "Say hello.").with_version("2"))
+            .unwrap();
+        prompts
+            .start_experiment(
+                "greeter",
+                vec![
+                    crate::prompt_registry::ExperimentArm::new("1", 1),
+                    crate::prompt_registry::ExperimentArm::new("2", 1),
+                ],
+            )
+            .unwrap();
+
+        for i in 0..6 {
+            let conversation = format!("conv-{i}");
+            let assigned = prompts.assign("greeter", &conversation).unwrap();
+            let response = bot
+                .process(Message::text("Hi").with_conversation_id(&conversation))
+                .await
+                .unwrap();
+            assert_eq!(response.content, assigned.source);
+            assert_eq!(
+                response.metadata[PROMPT_VERSION_METADATA_KEY],
+                assigned.id()
+            );
+        }
+
+        let metrics = prompts.metrics();
+        assert_eq!(metrics.iter().map(|m| m.requests).sum::<u64>(), 6);
+        assert_eq!(metrics.iter().map(|m| m.tokens).sum::<u64>(), 180);
+    }
+
+    #[tokio::test]
+    async fn test_bot_from_botfile() {
+        let path = std::env::temp_dir().join(format!("botfile-{}.yaml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "model: anthropic.claude-haiku\nplugins:\n  - name: calculator\n",
+        )
+        .unwrap();
+        let bot = Box::pin(Bot::from_botfile(&path)).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let plugins = bot.plugins().list();
+        assert_eq!(plugins.len(), 1);
+        assert_eq!(plugins[0].name, "calculator");
+
+        let botfile = Botfile::parse(
+            "model: anthropic.claude-haiku\nplugins:\n  - name: weather\n",
+            crate::botfile::BotfileFormat::Yaml,
+        )
+        .unwrap();
+        let result = BotBuilder::new().botfile(&botfile).unwrap().build().await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_branch_compare_and_choose() {
+        let mut config = BotConfig::default();
+        config.pipeline_config.attach_trace = true;
+        let bot = Bot::new(config).await.unwrap();
+        let context = bot.context_manager.get_or_create("conv").await.unwrap();
+        {
+            let mut ctx = context.write();
+            ctx.add_message(&Message::text("hello"));
+            ctx.add_response(&Response::text("conv", "hi"));
+        }
+
+        let variant = BranchVariant::new().with_model("anthropic.claude-haiku");
+        let comparison = bot.compare_branch("conv", variant).await.unwrap();
+        assert_eq!(comparison.baseline.metadata["branch"], "baseline");
+        assert_eq!(comparison.variant_response.metadata["branch"], "variant");
+        let trace = crate::pipeline::ExecutionTrace::from_response(&comparison.variant_response);
+        assert_eq!(trace.unwrap().model, "anthropic.claude-haiku");
+        assert_eq!(context.read().history[1].content, "hi");
+
+        let chosen = bot
+            .choose_branch(&comparison, BranchSide::Variant)
+            .await
+            .unwrap();
+        let context = bot.context_manager.get_or_create("conv").await.unwrap();
+        let history = context.read().history.clone();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].message_id, Some(chosen.id));
+
+        assert!(bot
+            .choose_branch(&comparison, BranchSide::Baseline)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_branch_variant_system_prompt_reaches_provider() {
+        let config = BotConfig {
+            system_prompt: Some("Be helpful.".into()),
+            ..BotConfig::default()
+        };
+        let bot = BotBuilder::new()
+            .config(config)
+            .provider(Arc::new(SystemPromptProvider))
+            .build()
+            .await
+            .unwrap();
+        let context = bot.context_manager.get_or_create("conv").await.unwrap();
+        {
+            let mut ctx = context.write();
+            ctx.add_message(&Message::text("hello"));
+            ctx.add_response(&Response::text("conv", "hi"));
+        }
+
+        let variant = BranchVariant::new().with_system_prompt("Be terse.");
+        let comparison = bot.compare_branch("conv", variant).await.unwrap();
+        assert_eq!(comparison.baseline.content, "Be helpful.");
+        assert_eq!(comparison.variant_response.content, "Be terse.");
+    }
+
+    #[tokio::test]
+    async fn test_regenerate_attaches_diff() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        assert!(bot.regenerate("regen").await.is_err());
+
+        let context = bot.context_manager.get_or_create("regen").await.unwrap();
+        {
+            let mut ctx = context.write();
+            ctx.add_message(&Message::text("hello"));
+            ctx.add_response(&Response::text("regen", "An answer that will be replaced."));
+        }
+
+        let response = bot.regenerate("regen").await.unwrap();
+        let diff: crate::diff::ResponseDiff =
+            serde_json::from_value(response.metadata[RESPONSE_DIFF_METADATA_KEY].clone()).unwrap();
+        assert!(!diff.is_identical());
+
+        let context = bot.context_manager.get_or_create("regen").await.unwrap();
+        let history = context.read().history.clone();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].message_id, Some(response.id));
+    }
+
+    #[tokio::test]
+    async fn test_overlong_input_follows_overflow_policy() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        let long = Message::text("word ".repeat(30_000));
+        assert!(bot.process(long.clone()).await.is_err());
+
+        let config = BotConfig::builder()
+            .model("anthropic.claude-opus-4-1")
+            .input_overflow(crate::config::InputOverflowConfig {
+                default_policy: crate::config::OverflowPolicy::Truncate,
+                ..crate::config::InputOverflowConfig::default()
+            })
+            .build()
+            .unwrap();
+        let bot = Bot::new(config).await.unwrap();
+        let response = bot.process(long).await.unwrap();
+        assert_eq!(
+            response.metadata[INPUT_OVERFLOW_METADATA_KEY]["applied"],
+            "truncate"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_preflight() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+
+        let report = bot.preflight(PreflightOptions::default()).await;
+        assert!(report.is_ok());
+        assert_eq!(report.check("store").unwrap().status, CheckStatus::Pass);
+        assert!(report.check("generation").is_none());
+
+        let report = bot.preflight(PreflightOptions { generate: true }).await;
+        assert_eq!(
+            report.check("generation").unwrap().status,
+            CheckStatus::Pass
+        );
+        assert_eq!(bot.metrics().requests_total(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_preflight_without_process_stage() {
+        let mut config = BotConfig::default();
+        config
+            .pipeline_config
+            .enabled_stages
+            .retain(|stage| stage != "process");
+        let bot = Bot::new(config).await.unwrap();
+
+        let report = bot.preflight(PreflightOptions::default()).await;
+        assert_eq!(report.check("model").unwrap().status, CheckStatus::Fail);
+        assert!(!report.is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_submit_job() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        let mut events = bot.jobs().subscribe();
+
+        let id = bot.submit_job(Message::text("Hello")).await.unwrap();
+        loop {
+            if let crate::job::JobEvent::Finished { id: done, .. } = events.recv().await.unwrap() {
+                if done == id {
+                    break;
+                }
+            }
+        }
+
+        let job = bot.get_job(id).await.unwrap().unwrap();
+        assert_eq!(job.status, crate::job::JobStatus::Succeeded);
+        assert!(job.response.is_some());
+        assert!(!bot.cancel_job(id));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_failed_job_is_not_reported_as_succeeded() {
+        let mut config = BotConfig::default();
+        config.pipeline_config.user_errors.enabled = true;
+        let bot = BotBuilder::new()
+            .config(config)
+            .provider(Arc::new(RejectingProvider))
+            .build()
+            .await
+            .unwrap();
+        let mut events = bot.jobs().subscribe();
+
+        let id = bot.submit_job(Message::text("Hello")).await.unwrap();
+        loop {
+            if let crate::job::JobEvent::Finished { id: done, .. } = events.recv().await.unwrap() {
+                if done == id {
+                    break;
+                }
+            }
+        }
+
+        let job = bot.get_job(id).await.unwrap().unwrap();
+        assert_eq!(job.status, crate::job::JobStatus::Failed);
+        assert!(job.response.is_none());
+        assert!(job.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_degraded_mode_serves_busy_response() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        bot.degraded_mode().set_budget_exhausted(true);
+
+        let response = bot.process(Message::text("Hello")).await.unwrap();
+        let error = response.error.unwrap();
+        assert_eq!(error.code, "service_busy");
+        assert!(error.retry_after.is_some());
+        assert_eq!(bot.metrics().errors_total(), 1);
+
+        bot.degraded_mode().set_budget_exhausted(false);
+        let response = bot.process(Message::text("Hello")).await.unwrap();
+        assert!(response.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_overload_sheds_background_requests() {
+        let mut config = BotConfig::default();
+        config.load_shedding.enabled = true;
+        let bot = Bot::new(config).await.unwrap();
+        bot.load_shedder()
+            .record_wait(std::time::Duration::from_secs(1));
+
+        let background = Message::text("Hello").with_metadata(
+            crate::shedding::PRIORITY_METADATA_KEY,
+            serde_json::json!("background"),
+        );
+        let response = bot.process(background).await.unwrap();
+        let error = response.error.unwrap();
+        assert_eq!(error.code, "overloaded");
+        assert_eq!(error.retry_after, Some(5));
+        assert_eq!(bot.load_shedder().shed_total(), 1);
+
+        let mut urgent = Message::text("Hello");
+        urgent.flags.urgent = true;
+        let response = bot.process(urgent).await.unwrap();
+        assert!(response.error.is_none());
+        assert_eq!(bot.load_shedder().in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_debug_bundle() {
+        let mut config = BotConfig::default();
+        config.pipeline_config.attach_trace = true;
+        let bot = Bot::new(config).await.unwrap();
+
+        bot.process(Message::text("Hello").with_conversation_id("support"))
+            .await
+            .unwrap();
+        bot.degraded_mode().set_budget_exhausted(true);
+        bot.process(Message::text("Hello").with_conversation_id("support"))
+            .await
+            .unwrap();
+
+        let bundle = bot.debug_bundle("support").unwrap();
+        assert_eq!(bundle.traces.len(), 1);
+        assert_eq!(bundle.errors[0].code, "service_busy");
+        assert_eq!(bundle.metrics.requests_total, 2);
+        assert_eq!(bundle.config["model"], "anthropic.claude-opus-4-1");
+        assert!(bundle
+            .to_json()
+            .unwrap()
+            .contains("\"conversation_id\": \"support\""));
+        assert!(bot.debug_bundle("other").unwrap().errors.is_empty());
+    }
+
     #[test]
     fn test_metrics() {
         let metrics = BotMetrics::new();
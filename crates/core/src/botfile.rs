@@ -0,0 +1,420 @@
+//! Declarative bot definitions
+//!
+//! A botfile describes a whole bot in one YAML or TOML document, so a
+//! deployment can be reproduced from a single checked-in file:
+//!
+//! ```yaml
+//! version: 1
+//! profile: cost_saver
+//! model: anthropic.claude-haiku
+//! system_prompt: "{{ support }}"
+//! prompts:
+//!   support: You are a concise support assistant.
+//! routing:
+//!   tenant_models:
+//!     acme: anthropic.claude-opus-4-1
+//! stages: [sanitize, route, process, format]
+//! plugins:
+//!   - name: calculator
+//! budgets:
+//!   max_tokens: 5000000
+//! channels:
+//!   - kind: irc
+//!     settings:
+//!       channels: ["#support"]
+//!     rate:
+//!       per_destination: {messages: 1, per: 1s}
+//! ```
+//!
+//! Keys other than the sections of [`Botfile`] are [`BotConfig`] settings
+//! and are merged over the chosen profile as in [`BotConfig::from_value`].
+//! `system_prompt` may refer to named `prompts` with `{{name}}`
+//! placeholders. Channel adapters need connectors supplied by the
+//! application, so [`Botfile::channels`] is validated here but the adapters
+//! are created by the caller.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use anyhow::{Context as _, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    config::{merge_json, BotConfig, BudgetConfig, ModelSelectionConfig},
+    error::Error,
+    irc::IrcConfig,
+    plugin::PluginConfig,
+    prompt,
+    shaping::RateShape,
+};
+
+/// Newest botfile format this crate understands
+pub const BOTFILE_VERSION: u32 = 1;
+
+/// Serialization format of a botfile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotfileFormat {
+    /// YAML, for `.yaml` and `.yml` files
+    Yaml,
+    /// TOML, for `.toml` files
+    Toml,
+}
+
+impl BotfileFormat {
+    /// The format implied by a file's extension
+    #[must_use]
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "yaml" | "yml" => Some(Self::Yaml),
+            "toml" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+}
+
+/// A plugin to keep registered, and its settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginSpec {
+    /// Registered plugin name, e.g. `calculator`
+    pub name: String,
+
+    /// Whether the plugin stays registered
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// Settings passed to the plugin's `initialize`
+    #[serde(default)]
+    pub settings: HashMap<String, serde_json::Value>,
+}
+
+/// Channel adapters a botfile can declare
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelKind {
+    /// Microsoft Teams
+    Teams,
+    /// WhatsApp Business
+    Whatsapp,
+    /// Matrix
+    Matrix,
+    /// IRC
+    Irc,
+    /// Email
+    Email,
+}
+
+/// A channel the bot is deployed to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelSpec {
+    /// Adapter serving the channel
+    pub kind: ChannelKind,
+
+    /// Adapter settings
+    #[serde(default = "empty_object")]
+    pub settings: serde_json::Value,
+
+    /// Outbound rate limits and coalescing, for the adapter's
+    /// `with_rate_shape`
+    #[serde(default)]
+    pub rate: RateShape,
+}
+
+impl ChannelSpec {
+    /// Deserialize the settings into the adapter's configuration type
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Configuration`] if the settings do not match `T`.
+    pub fn settings_as<T: DeserializeOwned>(&self) -> Result<T> {
+        serde_json::from_value(self.settings.clone()).map_err(|e| {
+            Error::Configuration(format!("Invalid {:?} channel settings: {e}", self.kind)).into()
+        })
+    }
+}
+
+/// A parsed botfile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Botfile {
+    /// Format version, at most [`BOTFILE_VERSION`]
+    #[serde(default = "default_version")]
+    pub version: u32,
+
+    /// Named prompts that `system_prompt` can refer to
+    #[serde(default)]
+    pub prompts: BTreeMap<String, String>,
+
+    /// Model routing rules, replacing [`BotConfig::model_selection`]
+    #[serde(default)]
+    pub routing: Option<ModelSelectionConfig>,
+
+    /// Pipeline stages to run, in order
+    #[serde(default)]
+    pub stages: Option<Vec<String>>,
+
+    /// Plugins to keep registered; all registered plugins stay if unset
+    #[serde(default)]
+    pub plugins: Option<Vec<PluginSpec>>,
+
+    /// Usage limits, replacing [`BotConfig::budget`]
+    #[serde(default)]
+    pub budgets: Option<BudgetConfig>,
+
+    /// Channels the bot is deployed to
+    #[serde(default)]
+    pub channels: Vec<ChannelSpec>,
+
+    /// Remaining [`BotConfig`] settings
+    #[serde(flatten)]
+    pub settings: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Botfile {
+    /// Read and validate a botfile, choosing the format by extension
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, has an unknown
+    /// extension, or is not a valid botfile.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let format = BotfileFormat::from_path(path).ok_or_else(|| {
+            Error::Configuration(format!(
+                "Botfile {} must have a .yaml, .yml, or .toml extension",
+                path.display()
+            ))
+        })?;
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read botfile {}", path.display()))?;
+        Self::parse(&text, format).with_context(|| format!("Invalid botfile {}", path.display()))
+    }
+
+    /// Parse and validate a botfile
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Configuration`] if the document does not parse, has
+    /// an unsupported version, or describes an invalid bot.
+    pub fn parse(text: &str, format: BotfileFormat) -> Result<Self> {
+        let botfile: Self = match format {
+            BotfileFormat::Yaml => serde_yaml::from_str(text)
+                .map_err(|e| Error::Configuration(format!("Invalid YAML: {e}")))?,
+            BotfileFormat::Toml => toml::from_str(text)
+                .map_err(|e| Error::Configuration(format!("Invalid TOML: {e}")))?,
+        };
+        botfile.validate()?;
+        Ok(botfile)
+    }
+
+    /// Check everything that can be checked without constructing the bot
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the first problem found.
+    pub fn validate(&self) -> Result<()> {
+        if self.version == 0 || self.version > BOTFILE_VERSION {
+            return Err(Error::Configuration(format!(
+                "Unsupported botfile version {}; this build supports up to {BOTFILE_VERSION}",
+                self.version
+            ))
+            .into());
+        }
+        if let Some(plugins) = &self.plugins {
+            let mut seen = std::collections::HashSet::new();
+            if let Some(spec) = plugins.iter().find(|spec| !seen.insert(&spec.name)) {
+                return Err(Error::Configuration(format!(
+                    "Plugin '{}' is listed more than once",
+                    spec.name
+                ))
+                .into());
+            }
+        }
+        for channel in &self.channels {
+            match channel.kind {
+                ChannelKind::Irc => {
+                    channel.settings_as::<IrcConfig>()?;
+                }
+                _ if !channel.settings.is_object() => {
+                    return Err(Error::Configuration(format!(
+                        "{:?} channel settings must be a table",
+                        channel.kind
+                    ))
+                    .into());
+                }
+                _ => {}
+            }
+        }
+        self.config()?;
+        Ok(())
+    }
+
+    /// The bot configuration the botfile describes
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a prompt reference is unknown or the resulting
+    /// configuration is invalid.
+    pub fn config(&self) -> Result<BotConfig> {
+        let mut document = serde_json::Value::Object(self.settings.clone());
+        let mut sections = serde_json::Map::new();
+        if let Some(routing) = &self.routing {
+            sections.insert("model_selection".into(), to_value(routing)?);
+        }
+        if let Some(budgets) = &self.budgets {
+            sections.insert("budget".into(), to_value(budgets)?);
+        }
+        if let Some(stages) = &self.stages {
+            sections.insert(
+                "pipeline_config".into(),
+                serde_json::json!({ "enabled_stages": stages }),
+            );
+        }
+        if let Some(serde_json::Value::String(template)) = document.get("system_prompt") {
+            let vars = self
+                .prompts
+                .iter()
+                .map(|(name, text)| (name.clone(), serde_json::Value::String(text.clone())))
+                .collect::<HashMap<_, _>>();
+            let system_prompt = prompt::interpolate(template, &vars)?;
+            sections.insert("system_prompt".into(), system_prompt.into());
+        }
+        merge_json(&mut document, serde_json::Value::Object(sections));
+        BotConfig::from_value(document)
+    }
+
+    /// Settings for each plugin to keep registered, or `None` to keep all
+    #[must_use]
+    pub fn plugin_configs(&self) -> Option<Vec<(String, PluginConfig)>> {
+        let plugins = self.plugins.as_ref()?;
+        Some(
+            plugins
+                .iter()
+                .filter(|spec| spec.enabled)
+                .map(|spec| {
+                    let config = PluginConfig {
+                        settings: spec.settings.clone(),
+                        ..PluginConfig::default()
+                    };
+                    (spec.name.clone(), config)
+                })
+                .collect(),
+        )
+    }
+}
+
+const fn default_version() -> u32 {
+    BOTFILE_VERSION
+}
+
+const fn default_enabled() -> bool {
+    true
+}
+
+fn empty_object() -> serde_json::Value {
+    serde_json::Value::Object(serde_json::Map::new())
+}
+
+fn to_value<T: Serialize>(value: &T) -> Result<serde_json::Value> {
+    serde_json::to_value(value).map_err(|e| Error::Serialization(e.to_string()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YAML: &str = r##"
+version: 1
+profile: cost_saver
+model: anthropic.claude-haiku
+system_prompt: "{{ support }} Answer in English."
+prompts:
+  support: You are a concise support assistant.
+routing:
+  tenant_models:
+    acme: anthropic.claude-opus-4-1
+stages: [sanitize, route, process, format]
+plugins:
+  - name: calculator
+    settings:
+      precision: 4
+budgets:
+  max_tokens: 5000
+channels:
+  - kind: irc
+    settings:
+      channels: ["#support"]
+    rate:
+      per_destination: {messages: 1, per: 1s}
+      coalesce_window: 2s
+"##;
+
+    #[test]
+    fn test_yaml_botfile_builds_config() {
+        let botfile = Botfile::parse(YAML, BotfileFormat::Yaml).unwrap();
+        let config = botfile.config().unwrap();
+
+        assert_eq!(config.model, "anthropic.claude-haiku");
+        assert_eq!(
+            config.system_prompt.as_deref(),
+            Some("You are a concise support assistant. Answer in English.")
+        );
+        assert_eq!(
+            config.model_selection.tenant_models["acme"],
+            "anthropic.claude-opus-4-1"
+        );
+        assert_eq!(
+            config.pipeline_config.enabled_stages,
+            ["sanitize", "route", "process", "format"]
+        );
+        assert_eq!(config.budget.max_tokens, Some(5000));
+        assert_eq!(
+            config.max_tokens,
+            crate::config::ConfigProfile::CostSaver.config().max_tokens
+        );
+
+        let plugins = botfile.plugin_configs().unwrap();
+        assert_eq!(plugins[0].0, "calculator");
+        assert_eq!(plugins[0].1.settings["precision"], 4);
+
+        let irc: IrcConfig = botfile.channels[0].settings_as().unwrap();
+        assert_eq!(irc.channels, ["#support"]);
+        let rate = &botfile.channels[0].rate;
+        assert_eq!(
+            rate.per_destination,
+            Some(crate::shaping::Rate::per_second(1))
+        );
+        assert_eq!(rate.coalesce_window, std::time::Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_toml_botfile() {
+        let toml = r#"
+model = "anthropic.claude-haiku"
+temperature = 0.2
+
+[[plugins]]
+name = "unit_converter"
+enabled = false
+"#;
+        let botfile = Botfile::parse(toml, BotfileFormat::Toml).unwrap();
+        assert!((botfile.config().unwrap().temperature - 0.2).abs() < f32::EPSILON);
+        assert!(botfile.plugin_configs().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_botfiles_are_rejected() {
+        let cases = [
+            "version: 2\nmodel: anthropic.claude-haiku",
+            "model: not-a-model",
+            "model: anthropic.claude-haiku\nsystem_prompt: \"{{ missing }}\"",
+            "model: anthropic.claude-haiku\nplugins: [{name: a}, {name: a}]",
+            "model: anthropic.claude-haiku\nchannels: [{kind: teams, settings: 1}]",
+        ];
+        for case in cases {
+            assert!(
+                Botfile::parse(case, BotfileFormat::Yaml).is_err(),
+                "accepted: {case}"
+            );
+        }
+        assert!(Botfile::load("bot.json").is_err());
+    }
+}
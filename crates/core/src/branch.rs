@@ -0,0 +1,200 @@
+//! Branch-and-compare for prompt experimentation
+//!
+//! [`Bot::compare_branch`](crate::Bot::compare_branch) re-runs the last user
+//! message of a conversation twice, once under the bot's configuration and
+//! once under a [`BranchVariant`], each against a detached copy of the
+//! context. The canonical history is only changed when one of the responses
+//! is committed with [`Bot::choose_branch`](crate::Bot::choose_branch).
+
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    context::{Context, MessageRole},
+    error::{Error, Result},
+    message::{Message, Response},
+    pipeline::PipelineContext,
+    selection::MODEL_OVERRIDE_METADATA_KEY,
+};
+
+/// Response metadata key tagging which side of a comparison produced it
+pub const BRANCH_METADATA_KEY: &str = "branch";
+
+/// Response metadata key holding the comparison ID
+pub const BRANCH_ID_METADATA_KEY: &str = "branch_id";
+
+/// Alternative configuration to compare against the bot's own
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BranchVariant {
+    /// System prompt to use instead of the configured one
+    pub system_prompt: Option<String>,
+    /// Model to use instead of the selected one
+    pub model: Option<String>,
+}
+
+impl BranchVariant {
+    /// A variant identical to the bot's configuration
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a different system prompt
+    ///
+    /// The prompt is handed to the process stage as
+    /// [`PipelineContext::system_prompt`](crate::pipeline::PipelineContext::system_prompt).
+    #[must_use]
+    pub fn with_system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.system_prompt = Some(prompt.into());
+        self
+    }
+
+    /// Use a different model
+    ///
+    /// The model is sent as a request override, so it must pass
+    /// [`ModelSelectionConfig::allows`](crate::config::ModelSelectionConfig::allows).
+    #[must_use]
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Copy of `message` carrying this variant's model override
+    #[must_use]
+    pub fn apply(&self, message: &Message) -> Message {
+        let mut message = message.clone();
+        if let Some(model) = &self.model {
+            message = message.with_metadata(MODEL_OVERRIDE_METADATA_KEY, serde_json::json!(model));
+        }
+        message
+    }
+
+    /// Pipeline context for running `message` under this variant against `context`
+    #[must_use]
+    pub fn pipeline_context(&self, message: &Message, context: Context) -> PipelineContext {
+        PipelineContext::new(self.apply(message), Arc::new(RwLock::new(context)))
+            .with_system_prompt(self.system_prompt.clone())
+    }
+}
+
+/// Side of a branch comparison
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BranchSide {
+    /// The bot's own configuration
+    Baseline,
+    /// The [`BranchVariant`]
+    Variant,
+}
+
+/// Two responses to the same message, for side-by-side display
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BranchComparison {
+    /// Comparison ID, also set on both responses
+    pub id: Uuid,
+    /// Conversation the message was taken from
+    pub conversation_id: String,
+    /// History entry the comparison re-ran, checked again when committing
+    pub replaced_message_id: Option<Uuid>,
+    /// The re-run user message
+    pub message: Message,
+    /// Variant the second response was produced with
+    pub variant: BranchVariant,
+    /// Response under the bot's configuration
+    pub baseline: Response,
+    /// Response under the variant
+    pub variant_response: Response,
+}
+
+impl BranchComparison {
+    /// The response for one side
+    #[must_use]
+    pub fn response(&self, side: BranchSide) -> &Response {
+        match side {
+            BranchSide::Baseline => &self.baseline,
+            BranchSide::Variant => &self.variant_response,
+        }
+    }
+}
+
+/// The branch point of a conversation: its last user message and the history before it
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] if the history holds no user message.
+pub(crate) fn branch_point(context: &Context) -> Result<(Context, Message, Option<Uuid>)> {
+    let last = context
+        .history
+        .iter()
+        .rev()
+        .find(|message| message.role == MessageRole::User)
+        .ok_or_else(|| {
+            Error::InvalidInput(format!(
+                "Conversation {} has no user message to branch from",
+                context.id
+            ))
+        })?;
+
+    let mut message = Message::text(last.content.clone()).with_conversation_id(context.id.clone());
+    if let Some(user_id) = &context.user.id {
+        message = message.with_user_id(user_id.clone());
+    }
+    let replaced = last.message_id;
+
+    let mut base = context.clone();
+    base.undo_last_exchange();
+    Ok((base, message, replaced))
+}
+
+/// Tag a response with its side of the comparison
+pub(crate) fn tag(response: Response, id: Uuid, side: BranchSide) -> Response {
+    response
+        .with_metadata(BRANCH_METADATA_KEY, serde_json::json!(side))
+        .with_metadata(BRANCH_ID_METADATA_KEY, serde_json::json!(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_branch_point_drops_last_exchange() {
+        let mut context = Context::new("conv");
+        context.add_message(&Message::text("first"));
+        context.add_response(&Response::text("conv", "one"));
+        context.add_message(&Message::text("second"));
+        context.add_response(&Response::text("conv", "two"));
+
+        let (base, message, replaced) = branch_point(&context).unwrap();
+        assert_eq!(base.history.len(), 2);
+        assert_eq!(&*message.content, "second");
+        assert_eq!(message.conversation_id, "conv");
+        assert_eq!(replaced, context.history[2].message_id);
+        assert_eq!(context.history.len(), 4);
+
+        assert!(branch_point(&Context::new("empty")).is_err());
+    }
+
+    #[test]
+    fn test_variant_sets_overrides() {
+        let message = Message::text("hi");
+        let variant = BranchVariant::new()
+            .with_model("anthropic.claude-haiku")
+            .with_system_prompt("Be terse.");
+
+        let applied = variant.apply(&message);
+        assert_eq!(
+            applied.metadata[MODEL_OVERRIDE_METADATA_KEY],
+            "anthropic.claude-haiku"
+        );
+        assert_eq!(applied.metadata.len(), 1);
+        assert!(BranchVariant::new().apply(&message).metadata.is_empty());
+
+        let ctx = variant.pipeline_context(&message, Context::new("conv"));
+        assert_eq!(ctx.system_prompt.as_deref(), Some("Be terse."));
+        assert_eq!(ctx.message.metadata, applied.metadata);
+    }
+}
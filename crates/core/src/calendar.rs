@@ -0,0 +1,923 @@
+//! Calendar scheduling tools for Google Calendar and Microsoft 365
+//!
+//! [`CalendarPlugin`] exposes `check_availability` and `create_event` tools
+//! backed by a [`CalendarProvider`]. Times given without an offset are read
+//! in the user's time zone, taken from the tool call or else from the
+//! [`UserContext`] passed along in [`CalendarPlugin::tool_metadata`].
+//!
+//! Booking takes two calls. The first returns a confirmation prompt and ID
+//! for the model to put to the user; only a second call carrying that ID
+//! creates the event. Unconfirmed bookings expire after
+//! [`CONFIRMATION_TTL_MINUTES`].
+//!
+//! [`GoogleCalendar`] and [`MicrosoftCalendar`] authenticate per user with
+//! OAuth refresh tokens kept in a [`SecretsProvider`]; see [`OAuthTokens`].
+//!
+//! Available with the `calendar` feature.
+
+use std::fmt::Write as _;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, LocalResult, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    context::{Context, UserContext},
+    error::Error,
+    plugin::{
+        Capability, CapabilityType, Permission, Plugin, PluginRequest, PluginResponse, RequestType,
+    },
+    secrets::SecretsProvider,
+};
+
+/// Plugin request metadata key naming the user a tool call acts for
+pub const USER_METADATA_KEY: &str = "user_id";
+
+/// Plugin request metadata key holding the user's IANA time zone
+pub const TIMEZONE_METADATA_KEY: &str = "timezone";
+
+/// Minutes an unconfirmed booking is kept
+pub const CONFIRMATION_TTL_MINUTES: i64 = 10;
+
+/// Slot length used when an availability check does not give one
+const DEFAULT_SLOT_MINUTES: i64 = 30;
+
+/// A busy period
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interval {
+    /// Start
+    pub start: DateTime<Utc>,
+    /// End
+    pub end: DateTime<Utc>,
+}
+
+/// An event to create
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventDraft {
+    /// Title
+    pub title: String,
+    /// Start
+    pub start: DateTime<Utc>,
+    /// End
+    pub end: DateTime<Utc>,
+    /// Time zone the event is shown in
+    pub timezone: Tz,
+    /// Attendee email addresses
+    pub attendees: Vec<String>,
+}
+
+impl EventDraft {
+    /// Local start and end as `YYYY-MM-DDTHH:MM:SS` in the event's time zone
+    #[must_use]
+    pub fn local_times(&self) -> (String, String) {
+        let format = |at: DateTime<Utc>| {
+            at.with_timezone(&self.timezone)
+                .format("%Y-%m-%dT%H:%M:%S")
+                .to_string()
+        };
+        (format(self.start), format(self.end))
+    }
+
+    fn prompt(&self) -> String {
+        let start = self.start.with_timezone(&self.timezone);
+        let end = self.end.with_timezone(&self.timezone);
+        let mut prompt = format!(
+            "Book \"{}\" on {} from {} to {} ({})",
+            self.title,
+            start.format("%a %-d %b %Y"),
+            start.format("%H:%M"),
+            end.format("%H:%M"),
+            self.timezone
+        );
+        if !self.attendees.is_empty() {
+            let _ = write!(prompt, " with {}", self.attendees.join(", "));
+        }
+        prompt.push('?');
+        prompt
+    }
+}
+
+/// A created event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreatedEvent {
+    /// Provider event ID
+    pub id: String,
+    /// Link to the event
+    pub link: Option<String>,
+}
+
+/// Reads and writes a user's primary calendar
+#[async_trait]
+pub trait CalendarProvider: Send + Sync {
+    /// Provider name, used as the plugin name
+    fn name(&self) -> &str;
+
+    /// Busy periods overlapping a window
+    async fn busy(
+        &self,
+        user_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Interval>>;
+
+    /// Create an event and invite its attendees
+    async fn create_event(&self, user_id: &str, draft: &EventDraft) -> Result<CreatedEvent>;
+}
+
+/// Parse a tool time argument
+///
+/// RFC 3339 times keep their offset; times without one are local to `tz`.
+/// Times repeated by a DST change resolve to the earlier instant.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] if the time cannot be parsed or falls in
+/// a DST gap.
+pub fn parse_time(value: &str, tz: Tz) -> Result<DateTime<Utc>> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    let naive = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M"))
+        .map_err(|e| Error::InvalidInput(format!("Invalid time {value}: {e}")))?;
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(at) | LocalResult::Ambiguous(at, _) => Ok(at.with_timezone(&Utc)),
+        LocalResult::None => {
+            Err(Error::InvalidInput(format!("{value} does not exist in {tz}")).into())
+        }
+    }
+}
+
+/// Free slots of at least `min_length` within a window
+#[must_use]
+pub fn free_slots(
+    busy: &[Interval],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    min_length: chrono::Duration,
+) -> Vec<Interval> {
+    let mut busy = busy.to_vec();
+    busy.sort_by_key(|interval| interval.start);
+
+    let mut free = Vec::new();
+    let mut cursor = start;
+    for interval in busy {
+        if interval.start > cursor && interval.start - cursor >= min_length {
+            free.push(Interval {
+                start: cursor,
+                end: interval.start.min(end),
+            });
+        }
+        cursor = cursor.max(interval.end);
+        if cursor >= end {
+            return free;
+        }
+    }
+    if end - cursor >= min_length {
+        free.push(Interval { start: cursor, end });
+    }
+    free
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "tool", rename_all = "snake_case")]
+enum ToolCall {
+    CheckAvailability {
+        start: String,
+        end: String,
+        #[serde(default)]
+        timezone: Option<String>,
+        #[serde(default)]
+        duration_minutes: Option<i64>,
+    },
+    CreateEvent {
+        title: String,
+        start: String,
+        end: String,
+        #[serde(default)]
+        timezone: Option<String>,
+        #[serde(default)]
+        attendees: Vec<String>,
+        #[serde(default)]
+        confirmation_id: Option<Uuid>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct PendingBooking {
+    user_id: String,
+    draft: EventDraft,
+    expires_at: DateTime<Utc>,
+}
+
+/// Tool plugin for checking availability and booking events
+///
+/// Invoked with [`RequestType::InvokeTool`], request metadata from
+/// [`CalendarPlugin::tool_metadata`], and data of the form
+/// `{"tool": "check_availability", "start": "2024-05-02T09:00",
+/// "end": "2024-05-02T17:00", "duration_minutes": 30}` or
+/// `{"tool": "create_event", "title": "Sync", "start": "...", "end": "...",
+/// "attendees": ["bo@example.com"], "confirmation_id": "..."}`.
+pub struct CalendarPlugin {
+    provider: Arc<dyn CalendarProvider>,
+    pending: DashMap<Uuid, PendingBooking>,
+}
+
+impl CalendarPlugin {
+    /// Create the plugin
+    #[must_use]
+    pub fn new(provider: Arc<dyn CalendarProvider>) -> Self {
+        Self {
+            provider,
+            pending: DashMap::new(),
+        }
+    }
+
+    /// Request metadata identifying the user and their time zone
+    #[must_use]
+    pub fn tool_metadata(
+        context: &Context,
+    ) -> std::collections::HashMap<String, serde_json::Value> {
+        let mut metadata = std::collections::HashMap::new();
+        let UserContext { id, timezone, .. } = &context.user;
+        if let Some(id) = id {
+            metadata.insert(USER_METADATA_KEY.to_string(), serde_json::json!(id));
+        }
+        if let Some(timezone) = timezone {
+            metadata.insert(
+                TIMEZONE_METADATA_KEY.to_string(),
+                serde_json::json!(timezone),
+            );
+        }
+        metadata
+    }
+
+    async fn call(
+        &self,
+        user_id: &str,
+        default_tz: Option<&str>,
+        call: ToolCall,
+    ) -> Result<serde_json::Value> {
+        match call {
+            ToolCall::CheckAvailability {
+                start,
+                end,
+                timezone,
+                duration_minutes,
+            } => {
+                let tz = resolve_timezone(timezone.as_deref(), default_tz)?;
+                let (start, end) = (parse_time(&start, tz)?, parse_time(&end, tz)?);
+                let min_length =
+                    chrono::Duration::minutes(duration_minutes.unwrap_or(DEFAULT_SLOT_MINUTES));
+                self.check_availability(user_id, tz, start, end, min_length)
+                    .await
+            }
+            ToolCall::CreateEvent {
+                confirmation_id: Some(confirmation_id),
+                ..
+            } => self.book(user_id, confirmation_id).await,
+            ToolCall::CreateEvent {
+                title,
+                start,
+                end,
+                timezone,
+                attendees,
+                confirmation_id: None,
+            } => {
+                let tz = resolve_timezone(timezone.as_deref(), default_tz)?;
+                let draft = EventDraft {
+                    title,
+                    start: parse_time(&start, tz)?,
+                    end: parse_time(&end, tz)?,
+                    timezone: tz,
+                    attendees,
+                };
+                self.propose(user_id, draft)
+            }
+        }
+    }
+
+    /// Free and busy intervals between `start` and `end`, in `tz`
+    async fn check_availability(
+        &self,
+        user_id: &str,
+        tz: Tz,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        min_length: chrono::Duration,
+    ) -> Result<serde_json::Value> {
+        let busy = self.provider.busy(user_id, start, end).await?;
+        let local = |intervals: Vec<Interval>| -> Vec<serde_json::Value> {
+            intervals
+                .into_iter()
+                .map(|i| {
+                    serde_json::json!({
+                        "start": i.start.with_timezone(&tz).to_rfc3339(),
+                        "end": i.end.with_timezone(&tz).to_rfc3339(),
+                    })
+                })
+                .collect()
+        };
+        Ok(serde_json::json!({
+            "timezone": tz.name(),
+            "free": local(free_slots(&busy, start, end, min_length)),
+            "busy": local(busy),
+        }))
+    }
+
+    /// Create the event of a confirmed booking
+    async fn book(&self, user_id: &str, confirmation_id: Uuid) -> Result<serde_json::Value> {
+        let booking = self
+            .pending
+            .remove(&confirmation_id)
+            .map(|(_, booking)| booking)
+            .filter(|b| b.user_id == user_id && b.expires_at > Utc::now())
+            .ok_or_else(|| {
+                Error::InvalidInput(format!(
+                    "Confirmation {confirmation_id} is unknown or expired"
+                ))
+            })?;
+        let event = self.provider.create_event(user_id, &booking.draft).await?;
+        info!(
+            "Booked {} event {} for {}",
+            self.provider.name(),
+            event.id,
+            user_id
+        );
+        Ok(serde_json::json!({ "status": "booked", "event": event }))
+    }
+
+    /// Hold `draft` for confirmation and return the prompt to confirm it
+    fn propose(&self, user_id: &str, draft: EventDraft) -> Result<serde_json::Value> {
+        if draft.end <= draft.start {
+            return Err(Error::InvalidInput("Event ends before it starts".to_string()).into());
+        }
+
+        let now = Utc::now();
+        self.pending.retain(|_, booking| booking.expires_at > now);
+        let confirmation_id = Uuid::new_v4();
+        let prompt = draft.prompt();
+        self.pending.insert(
+            confirmation_id,
+            PendingBooking {
+                user_id: user_id.to_string(),
+                draft,
+                expires_at: now + chrono::Duration::minutes(CONFIRMATION_TTL_MINUTES),
+            },
+        );
+        Ok(serde_json::json!({
+            "status": "confirmation_required",
+            "confirmation_id": confirmation_id,
+            "prompt": prompt,
+        }))
+    }
+}
+
+/// The time zone named in a tool call, else the user's, else UTC
+fn resolve_timezone(explicit: Option<&str>, default: Option<&str>) -> Result<Tz> {
+    explicit.or(default).map_or_else(
+        || Ok(Tz::UTC),
+        |name| {
+            name.parse()
+                .map_err(|e| Error::InvalidInput(format!("Invalid time zone {name}: {e}")).into())
+        },
+    )
+}
+
+#[async_trait]
+impl Plugin for CalendarPlugin {
+    fn name(&self) -> &str {
+        self.provider.name()
+    }
+
+    fn version(&self) -> &str {
+        "1.0.0"
+    }
+
+    fn description(&self) -> &'static str {
+        "Checks availability and books calendar events"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        let tool = |name: &str, description: &str| Capability {
+            name: name.to_string(),
+            capability_type: CapabilityType::ToolProvider,
+            description: description.to_string(),
+            required_permissions: vec![Permission::NetworkAccess],
+        };
+        vec![
+            tool("check_availability", "List free and busy times in a window"),
+            tool(
+                "create_event",
+                "Book an event; the first call returns a prompt the user must confirm",
+            ),
+        ]
+    }
+
+    async fn process(&self, request: PluginRequest) -> Result<PluginResponse> {
+        if !matches!(request.request_type, RequestType::InvokeTool) {
+            return Ok(PluginResponse::error(
+                request.id,
+                "Unsupported request type",
+            ));
+        }
+        let metadata = |key: &str| request.metadata.get(key).and_then(|v| v.as_str());
+        let Some(user_id) = metadata(USER_METADATA_KEY).map(str::to_string) else {
+            return Ok(PluginResponse::error(
+                request.id,
+                "No user for calendar access",
+            ));
+        };
+        let default_tz = metadata(TIMEZONE_METADATA_KEY).map(str::to_string);
+        let call = match serde_json::from_value::<ToolCall>(request.data) {
+            Ok(call) => call,
+            Err(e) => return Ok(PluginResponse::error(request.id, e)),
+        };
+
+        Ok(
+            match self.call(&user_id, default_tz.as_deref(), call).await {
+                Ok(data) => PluginResponse::success(request.id, data),
+                Err(e) => PluginResponse::error(request.id, e),
+            },
+        )
+    }
+}
+
+/// Per-user OAuth access tokens, refreshed from tokens in a [`SecretsProvider`]
+///
+/// Reads `{provider}/client_id`, `{provider}/client_secret`, and
+/// `{provider}/{user_id}/refresh_token`. Rotated refresh tokens are written
+/// back, and access tokens are cached until a minute before they expire.
+pub struct OAuthTokens {
+    provider: String,
+    token_url: String,
+    scope: Option<String>,
+    secrets: Arc<dyn SecretsProvider>,
+    client: reqwest::Client,
+    cache: DashMap<String, (String, Instant)>,
+}
+
+impl OAuthTokens {
+    /// Create a token source for `provider` using its token endpoint
+    #[must_use]
+    pub fn new(
+        provider: impl Into<String>,
+        token_url: impl Into<String>,
+        secrets: Arc<dyn SecretsProvider>,
+    ) -> Self {
+        Self {
+            provider: provider.into(),
+            token_url: token_url.into(),
+            scope: None,
+            secrets,
+            client: reqwest::Client::new(),
+            cache: DashMap::new(),
+        }
+    }
+
+    /// Request `scope` when refreshing
+    #[must_use]
+    pub fn with_scope(mut self, scope: impl Into<String>) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+
+    async fn secret(&self, name: &str) -> Result<String> {
+        let name = format!("{}/{name}", self.provider);
+        self.secrets
+            .get(&name)
+            .await?
+            .ok_or_else(|| Error::Authorization(format!("Missing secret {name}")).into())
+    }
+
+    /// An access token for a user
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Authorization`] if the user has not connected their
+    /// calendar, or [`Error::Authentication`] if the refresh is rejected.
+    pub async fn access_token(&self, user_id: &str) -> Result<String> {
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            access_token: String,
+            expires_in: u64,
+            refresh_token: Option<String>,
+        }
+
+        if let Some(entry) = self.cache.get(user_id) {
+            if Instant::now() < entry.1 {
+                return Ok(entry.0.clone());
+            }
+        }
+
+        let refresh_token = self.secret(&format!("{user_id}/refresh_token")).await?;
+        let client_id = self.secret("client_id").await?;
+        let client_secret = self.secret("client_secret").await?;
+        let mut form = vec![
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token.as_str()),
+            ("client_id", client_id.as_str()),
+            ("client_secret", client_secret.as_str()),
+        ];
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope));
+        }
+        let response: TokenResponse = self
+            .client
+            .post(&self.token_url)
+            .form(&form)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| {
+                Error::Authentication(format!("{} token refresh failed: {e}", self.provider))
+            })?
+            .json()
+            .await
+            .map_err(|e| Error::Authentication(format!("Invalid token response: {e}")))?;
+
+        if let Some(rotated) = response.refresh_token.filter(|t| *t != refresh_token) {
+            self.secrets
+                .put(
+                    &format!("{}/{user_id}/refresh_token", self.provider),
+                    &rotated,
+                )
+                .await?;
+        }
+        let expires = Instant::now() + Duration::from_secs(response.expires_in.saturating_sub(60));
+        self.cache.insert(
+            user_id.to_string(),
+            (response.access_token.clone(), expires),
+        );
+        Ok(response.access_token)
+    }
+}
+
+async fn send_json(request: reqwest::RequestBuilder, what: &str) -> Result<serde_json::Value> {
+    Ok(request
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| Error::Network(format!("{what} failed: {e}")))?
+        .json()
+        .await
+        .map_err(|e| Error::Network(format!("Invalid {what} response: {e}")))?)
+}
+
+fn parse_utc(value: &serde_json::Value) -> Option<DateTime<Utc>> {
+    let value = value.as_str()?;
+    DateTime::parse_from_rfc3339(value)
+        .map(|at| at.with_timezone(&Utc))
+        .ok()
+        // Graph returns UTC times without an offset
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+                .ok()
+                .map(|naive| naive.and_utc())
+        })
+}
+
+/// Google Calendar, using each user's primary calendar
+pub struct GoogleCalendar {
+    tokens: OAuthTokens,
+    client: reqwest::Client,
+}
+
+impl GoogleCalendar {
+    const API_URL: &'static str = "https://www.googleapis.com/calendar/v3";
+
+    /// Create a provider reading `google/...` secrets
+    #[must_use]
+    pub fn new(secrets: Arc<dyn SecretsProvider>) -> Self {
+        Self {
+            tokens: OAuthTokens::new("google", "https://oauth2.googleapis.com/token", secrets),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl CalendarProvider for GoogleCalendar {
+    fn name(&self) -> &str {
+        "google_calendar"
+    }
+
+    async fn busy(
+        &self,
+        user_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Interval>> {
+        let token = self.tokens.access_token(user_id).await?;
+        let body = send_json(
+            self.client
+                .post(format!("{}/freeBusy", Self::API_URL))
+                .bearer_auth(token)
+                .json(&serde_json::json!({
+                    "timeMin": start.to_rfc3339(),
+                    "timeMax": end.to_rfc3339(),
+                    "items": [{ "id": "primary" }],
+                })),
+            "Google free/busy query",
+        )
+        .await?;
+        Ok(body["calendars"]["primary"]["busy"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|slot| {
+                Some(Interval {
+                    start: parse_utc(&slot["start"])?,
+                    end: parse_utc(&slot["end"])?,
+                })
+            })
+            .collect())
+    }
+
+    async fn create_event(&self, user_id: &str, draft: &EventDraft) -> Result<CreatedEvent> {
+        let token = self.tokens.access_token(user_id).await?;
+        let (start, end) = draft.local_times();
+        let attendees: Vec<_> = draft
+            .attendees
+            .iter()
+            .map(|email| serde_json::json!({ "email": email }))
+            .collect();
+        let body = send_json(
+            self.client
+                .post(format!(
+                    "{}/calendars/primary/events?sendUpdates=all",
+                    Self::API_URL
+                ))
+                .bearer_auth(token)
+                .json(&serde_json::json!({
+                    "summary": draft.title,
+                    "start": { "dateTime": start, "timeZone": draft.timezone.name() },
+                    "end": { "dateTime": end, "timeZone": draft.timezone.name() },
+                    "attendees": attendees,
+                })),
+            "Google event creation",
+        )
+        .await?;
+        Ok(CreatedEvent {
+            id: body["id"].as_str().unwrap_or_default().to_string(),
+            link: body["htmlLink"].as_str().map(str::to_string),
+        })
+    }
+}
+
+/// Microsoft 365 calendars through Microsoft Graph
+pub struct MicrosoftCalendar {
+    tokens: OAuthTokens,
+    client: reqwest::Client,
+}
+
+impl MicrosoftCalendar {
+    const API_URL: &'static str = "https://graph.microsoft.com/v1.0";
+
+    /// Create a provider reading `microsoft/...` secrets
+    #[must_use]
+    pub fn new(secrets: Arc<dyn SecretsProvider>) -> Self {
+        Self {
+            tokens: OAuthTokens::new(
+                "microsoft",
+                "https://login.microsoftonline.com/common/oauth2/v2.0/token",
+                secrets,
+            )
+            .with_scope("offline_access Calendars.ReadWrite"),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl CalendarProvider for MicrosoftCalendar {
+    fn name(&self) -> &str {
+        "microsoft_calendar"
+    }
+
+    async fn busy(
+        &self,
+        user_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Interval>> {
+        let token = self.tokens.access_token(user_id).await?;
+        let body = send_json(
+            self.client
+                .get(format!("{}/me/calendarView", Self::API_URL))
+                .bearer_auth(token)
+                .header("Prefer", "outlook.timezone=\"UTC\"")
+                .query(&[
+                    ("startDateTime", start.to_rfc3339()),
+                    ("endDateTime", end.to_rfc3339()),
+                    ("$select", "start,end,showAs".to_string()),
+                ]),
+            "Graph calendar view",
+        )
+        .await?;
+        Ok(body["value"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|event| event["showAs"] != "free")
+            .filter_map(|event| {
+                Some(Interval {
+                    start: parse_utc(&event["start"]["dateTime"])?,
+                    end: parse_utc(&event["end"]["dateTime"])?,
+                })
+            })
+            .collect())
+    }
+
+    async fn create_event(&self, user_id: &str, draft: &EventDraft) -> Result<CreatedEvent> {
+        let token = self.tokens.access_token(user_id).await?;
+        let (start, end) = draft.local_times();
+        let attendees: Vec<_> = draft
+            .attendees
+            .iter()
+            .map(|email| {
+                serde_json::json!({ "emailAddress": { "address": email }, "type": "required" })
+            })
+            .collect();
+        let body = send_json(
+            self.client
+                .post(format!("{}/me/events", Self::API_URL))
+                .bearer_auth(token)
+                .json(&serde_json::json!({
+                    "subject": draft.title,
+                    "start": { "dateTime": start, "timeZone": draft.timezone.name() },
+                    "end": { "dateTime": end, "timeZone": draft.timezone.name() },
+                    "attendees": attendees,
+                })),
+            "Graph event creation",
+        )
+        .await?;
+        Ok(CreatedEvent {
+            id: body["id"].as_str().unwrap_or_default().to_string(),
+            link: body["webLink"].as_str().map(str::to_string),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    #[derive(Default)]
+    struct FakeCalendar {
+        booked: Mutex<Vec<EventDraft>>,
+    }
+
+    #[async_trait]
+    impl CalendarProvider for FakeCalendar {
+        fn name(&self) -> &str {
+            "fake_calendar"
+        }
+
+        async fn busy(
+            &self,
+            _user_id: &str,
+            start: DateTime<Utc>,
+            _end: DateTime<Utc>,
+        ) -> Result<Vec<Interval>> {
+            Ok(vec![Interval {
+                start: start + chrono::Duration::hours(1),
+                end: start + chrono::Duration::hours(2),
+            }])
+        }
+
+        async fn create_event(&self, _user_id: &str, draft: &EventDraft) -> Result<CreatedEvent> {
+            self.booked.lock().push(draft.clone());
+            Ok(CreatedEvent {
+                id: "evt-1".to_string(),
+                link: None,
+            })
+        }
+    }
+
+    fn invoke(data: serde_json::Value) -> PluginRequest {
+        let mut context = Context::new("conv");
+        context.user.id = Some("ana".to_string());
+        context.user.timezone = Some("Europe/Berlin".to_string());
+        PluginRequest {
+            id: "call".to_string(),
+            request_type: RequestType::InvokeTool,
+            data,
+            metadata: CalendarPlugin::tool_metadata(&context),
+        }
+    }
+
+    #[test]
+    fn test_parse_time_uses_user_zone() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        assert_eq!(
+            parse_time("2024-07-01T09:00", berlin).unwrap().to_rfc3339(),
+            "2024-07-01T07:00:00+00:00"
+        );
+        assert_eq!(
+            parse_time("2024-07-01T09:00:00-04:00", berlin)
+                .unwrap()
+                .to_rfc3339(),
+            "2024-07-01T13:00:00+00:00"
+        );
+        assert!(parse_time("2024-03-31T02:30", berlin).is_err());
+        assert!(parse_time("tomorrow", berlin).is_err());
+    }
+
+    #[test]
+    fn test_free_slots() {
+        let at = |h: u32| Utc.with_ymd_and_hms(2024, 7, 1, h, 0, 0).unwrap();
+        let busy = [
+            Interval {
+                start: at(11),
+                end: at(12),
+            },
+            Interval {
+                start: at(9),
+                end: at(10),
+            },
+        ];
+        let free = free_slots(&busy, at(8), at(13), chrono::Duration::minutes(30));
+        assert_eq!(
+            free,
+            vec![
+                Interval {
+                    start: at(8),
+                    end: at(9)
+                },
+                Interval {
+                    start: at(10),
+                    end: at(11)
+                },
+                Interval {
+                    start: at(12),
+                    end: at(13)
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_availability_in_user_zone() {
+        let plugin = CalendarPlugin::new(Arc::new(FakeCalendar::default()));
+        let response = plugin
+            .process(invoke(serde_json::json!({
+                "tool": "check_availability",
+                "start": "2024-07-01T09:00",
+                "end": "2024-07-01T12:00",
+            })))
+            .await
+            .unwrap();
+        assert!(response.success);
+        assert_eq!(response.data["timezone"], "Europe/Berlin");
+        assert_eq!(
+            response.data["busy"][0]["start"],
+            "2024-07-01T10:00:00+02:00"
+        );
+        assert_eq!(
+            response.data["free"][1]["start"],
+            "2024-07-01T11:00:00+02:00"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_booking_requires_confirmation() {
+        let calendar = Arc::new(FakeCalendar::default());
+        let plugin = CalendarPlugin::new(calendar.clone());
+        let event = serde_json::json!({
+            "tool": "create_event",
+            "title": "Design review",
+            "start": "2024-07-02T15:00",
+            "end": "2024-07-02T16:00",
+            "attendees": ["bo@example.com"],
+        });
+
+        let first = plugin.process(invoke(event.clone())).await.unwrap();
+        assert_eq!(first.data["status"], "confirmation_required");
+        assert_eq!(
+            first.data["prompt"],
+            "Book \"Design review\" on Tue 2 Jul 2024 from 15:00 to 16:00 (Europe/Berlin) with bo@example.com?"
+        );
+        assert!(calendar.booked.lock().is_empty());
+
+        let mut confirmed = event;
+        confirmed["confirmation_id"] = first.data["confirmation_id"].clone();
+        let second = plugin.process(invoke(confirmed.clone())).await.unwrap();
+        assert_eq!(second.data["status"], "booked");
+        assert_eq!(
+            calendar.booked.lock()[0].local_times().0,
+            "2024-07-02T15:00:00"
+        );
+
+        let replay = plugin.process(invoke(confirmed)).await.unwrap();
+        assert!(!replay.success);
+    }
+}
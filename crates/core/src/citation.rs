@@ -0,0 +1,336 @@
+//! Attachment citations
+//!
+//! When attachments are turned into text for the model, each chunk keeps a
+//! pointer back to the attachment (and page) it came from. Chunks are shown
+//! to the model with numbered markers such as `[3]`; markers the model
+//! repeats in its answer are resolved into [`Citation`]s and attached to the
+//! [`Response`] metadata under [`CITATIONS_METADATA_KEY`] for UI display.
+
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::{
+    ingest::{
+        DocumentRef, HtmlExtractor, PlainTextExtractor, RawDocument, TextChunker, TextExtractor,
+    },
+    message::{Attachment, Response},
+    pipeline::{PipelineContext, PipelineStage},
+};
+
+/// Response metadata key holding the list of [`Citation`]s
+pub const CITATIONS_METADATA_KEY: &str = "citations";
+
+/// Page separator emitted by PDF text extraction
+const PAGE_BREAK: char = '\x0c';
+
+/// A chunk of attachment text with its origin
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttachmentChunk {
+    /// Marker number shown to the model, starting at 1
+    pub reference: usize,
+    /// Source attachment
+    pub attachment_id: Uuid,
+    /// Source filename
+    pub filename: String,
+    /// 1-based page number, for paginated documents
+    pub page: Option<usize>,
+    /// Chunk text
+    pub text: String,
+}
+
+/// An attachment-level citation for display
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Citation {
+    /// Cited attachment
+    pub attachment_id: Uuid,
+    /// Cited filename
+    pub filename: String,
+    /// Cited page, for paginated documents
+    pub page: Option<usize>,
+    /// Markers in the response that resolved to this citation
+    pub references: Vec<usize>,
+}
+
+/// Extracted attachment text, indexed by marker number
+pub struct AttachmentIndex {
+    chunks: Vec<AttachmentChunk>,
+}
+
+impl AttachmentIndex {
+    /// Extract and chunk every attachment with an inline payload
+    ///
+    /// Attachments without data or without a matching extractor are
+    /// skipped; extraction failures are logged and skipped as well.
+    #[must_use]
+    pub fn build(
+        attachments: &[Attachment],
+        extractors: &[Box<dyn TextExtractor>],
+        chunker: &TextChunker,
+    ) -> Self {
+        let mut chunks = Vec::new();
+
+        for attachment in attachments {
+            let Some(data) = &attachment.data else {
+                continue;
+            };
+            let document = RawDocument {
+                reference: DocumentRef::new(attachment.filename.clone()),
+                content_type: Some(attachment.mime_type.clone()),
+                bytes: data.to_vec(),
+            };
+            let Some(extractor) = extractors.iter().find(|e| e.supports(&document)) else {
+                debug!("No extractor for attachment {}", attachment.filename);
+                continue;
+            };
+            let text = match extractor.extract(&document) {
+                Ok(text) => text,
+                Err(e) => {
+                    warn!("Failed to extract {}: {}", attachment.filename, e);
+                    continue;
+                }
+            };
+
+            let pages: Vec<&str> = text.split(PAGE_BREAK).collect();
+            let paginated = pages.len() > 1;
+            for (index, page) in pages.into_iter().enumerate() {
+                for chunk in chunker.chunk(page) {
+                    chunks.push(AttachmentChunk {
+                        reference: chunks.len() + 1,
+                        attachment_id: attachment.id,
+                        filename: attachment.filename.clone(),
+                        page: paginated.then_some(index + 1),
+                        text: chunk,
+                    });
+                }
+            }
+        }
+
+        Self { chunks }
+    }
+
+    /// Extractors used when none are supplied
+    #[must_use]
+    pub fn default_extractors() -> Vec<Box<dyn TextExtractor>> {
+        vec![
+            Box::new(PlainTextExtractor),
+            Box::new(HtmlExtractor),
+            #[cfg(feature = "ingest")]
+            Box::new(crate::ingest::PdfExtractor),
+        ]
+    }
+
+    /// Indexed chunks, in marker order
+    #[must_use]
+    pub fn chunks(&self) -> &[AttachmentChunk] {
+        &self.chunks
+    }
+
+    /// Whether no attachment text was extracted
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Render the chunks for inclusion in a prompt
+    ///
+    /// Each chunk is prefixed with its marker and source so the model can
+    /// cite it, e.g. `[2] (report.pdf, page 4) ...`.
+    #[must_use]
+    pub fn prompt_context(&self) -> String {
+        self.chunks
+            .iter()
+            .map(|chunk| {
+                let source = chunk.page.map_or_else(
+                    || chunk.filename.clone(),
+                    |page| format!("{}, page {page}", chunk.filename),
+                );
+                format!("[{}] ({source}) {}", chunk.reference, chunk.text)
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// Resolve the markers in `text` into citations, one per attachment page
+    #[must_use]
+    pub fn citations_for(&self, text: &str) -> Vec<Citation> {
+        let mut citations: Vec<Citation> = Vec::new();
+
+        for reference in markers(text) {
+            let Some(chunk) = self.chunks.get(reference - 1) else {
+                continue;
+            };
+            match citations
+                .iter_mut()
+                .find(|c| c.attachment_id == chunk.attachment_id && c.page == chunk.page)
+            {
+                Some(citation) => citation.references.push(reference),
+                None => citations.push(Citation {
+                    attachment_id: chunk.attachment_id,
+                    filename: chunk.filename.clone(),
+                    page: chunk.page,
+                    references: vec![reference],
+                }),
+            }
+        }
+
+        citations
+    }
+
+    /// Add citations for the markers in a response's content to its metadata
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the citations cannot be serialized.
+    pub fn attach_citations(&self, response: &mut Response) -> Result<()> {
+        let citations = self.citations_for(&response.content);
+        if !citations.is_empty() {
+            response.metadata.insert(
+                CITATIONS_METADATA_KEY.to_string(),
+                serde_json::to_value(citations)?,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Distinct `[n]` markers in the text, in ascending order
+fn markers(text: &str) -> BTreeSet<usize> {
+    let mut found = BTreeSet::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('[') {
+        rest = &rest[open + 1..];
+        let Some(close) = rest.find(']') else {
+            break;
+        };
+        if let Ok(n) = rest[..close].trim_start_matches('^').parse::<usize>() {
+            if n > 0 {
+                found.insert(n);
+            }
+        }
+    }
+    found
+}
+
+/// Pipeline stage that attaches citations to the response
+///
+/// Register it after `process` as the `cite` stage.
+pub struct CitationStage {
+    extractors: Vec<Box<dyn TextExtractor>>,
+    chunker: TextChunker,
+}
+
+impl CitationStage {
+    /// Create the stage with the default extractors and chunker
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            extractors: AttachmentIndex::default_extractors(),
+            chunker: TextChunker::default(),
+        }
+    }
+}
+
+impl Default for CitationStage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl PipelineStage for CitationStage {
+    fn name(&self) -> &str {
+        "cite"
+    }
+
+    async fn process(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
+        if ctx.message.attachments.is_empty() {
+            return Ok(ctx);
+        }
+        let Some(response) = ctx.response.as_mut() else {
+            return Ok(ctx);
+        };
+
+        let index =
+            AttachmentIndex::build(&ctx.message.attachments, &self.extractors, &self.chunker);
+        index.attach_citations(response)?;
+        Ok(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(name: &str, mime: &str, body: &str) -> Attachment {
+        Attachment::new(name, mime, 0, format!("file:///{name}")).with_data(body.to_string())
+    }
+
+    #[test]
+    fn test_chunks_keep_their_source() {
+        let attachments = vec![
+            attachment("notes.txt", "text/plain", "alpha beta"),
+            attachment("report.txt", "text/plain", "page one\x0cpage two"),
+            Attachment::new(
+                "remote.txt",
+                "text/plain",
+                10,
+                "https://example.com/remote.txt",
+            ),
+        ];
+        let index = AttachmentIndex::build(
+            &attachments,
+            &AttachmentIndex::default_extractors(),
+            &TextChunker::default(),
+        );
+
+        let chunks = index.chunks();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].filename, "notes.txt");
+        assert_eq!(chunks[0].page, None);
+        assert_eq!(chunks[2].page, Some(2));
+        assert_eq!(chunks[2].reference, 3);
+        assert!(index
+            .prompt_context()
+            .contains("[3] (report.txt, page 2) page two"));
+    }
+
+    #[test]
+    fn test_citations_group_by_page() {
+        let report = attachment("report.txt", "text/plain", "one\x0ctwo");
+        let id = report.id;
+        let index = AttachmentIndex::build(
+            &[report],
+            &AttachmentIndex::default_extractors(),
+            &TextChunker::default(),
+        );
+
+        let mut response =
+            Response::text("conv", "See [2] and [^2], also [1]; ignore [9] and [x].");
+        index.attach_citations(&mut response).unwrap();
+
+        let citations: Vec<Citation> =
+            serde_json::from_value(response.metadata[CITATIONS_METADATA_KEY].clone()).unwrap();
+        assert_eq!(citations.len(), 2);
+        assert_eq!(citations[0].attachment_id, id);
+        assert_eq!(citations[0].page, Some(1));
+        assert_eq!(citations[1].page, Some(2));
+        assert_eq!(citations[1].references, vec![2]);
+    }
+
+    #[test]
+    fn test_uncited_response_has_no_metadata() {
+        let index = AttachmentIndex::build(
+            &[attachment("a.txt", "text/plain", "text")],
+            &AttachmentIndex::default_extractors(),
+            &TextChunker::default(),
+        );
+        let mut response = Response::text("conv", "No references here");
+        index.attach_citations(&mut response).unwrap();
+        assert!(!response.metadata.contains_key(CITATIONS_METADATA_KEY));
+    }
+}
@@ -0,0 +1,604 @@
+//! Cluster coordination for singleton background tasks
+//!
+//! When several replicas run the same bot, background work such as
+//! expiry notices, scheduled ingestion, or archival must run on only one of
+//! them. Replicas share a [`LockBackend`] holding named leases. A
+//! [`TaskSupervisor`] runs each singleton task on the replica that holds
+//! its lease, renews the lease while the task runs, and stops the task as
+//! soon as a renewal fails. If the holder dies, its lease expires and
+//! another replica takes over within one lease TTL.
+//!
+//! [`MemoryLockBackend`] coordinates tasks within one process,
+//! [`RedisLockBackend`] across replicas sharing a Redis server, and with the
+//! `dynamodb` feature, `DynamoDbLockBackend` through a `DynamoDB` table.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::error::Error;
+
+/// Lease duration unless set with [`TaskSupervisor::with_lease_ttl`]
+pub const DEFAULT_LEASE_TTL: Duration = Duration::from_secs(30);
+
+/// Redis connect and command timeout unless set with [`RedisLockBackend::with_timeout`]
+pub const DEFAULT_REDIS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Shared store of named, expiring leases
+#[async_trait]
+pub trait LockBackend: Send + Sync {
+    /// Take or renew the lease `name` for `holder` for `ttl`
+    ///
+    /// Succeeds if the lease is free, expired, or already held by `holder`.
+    /// Returns whether `holder` holds the lease afterwards.
+    async fn try_acquire(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool>;
+
+    /// Give up the lease `name` if `holder` holds it
+    async fn release(&self, name: &str, holder: &str) -> Result<()>;
+}
+
+/// Leases shared by supervisors in one process
+#[derive(Debug, Default)]
+pub struct MemoryLockBackend {
+    leases: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl MemoryLockBackend {
+    /// Create a backend with no leases
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl LockBackend for MemoryLockBackend {
+    async fn try_acquire(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool> {
+        let now = Instant::now();
+        let mut leases = self.leases.lock();
+        let free = leases
+            .get(name)
+            .is_none_or(|(owner, expires)| owner == holder || *expires <= now);
+        if free {
+            leases.insert(name.to_string(), (holder.to_string(), now + ttl));
+        }
+        drop(leases);
+        Ok(free)
+    }
+
+    async fn release(&self, name: &str, holder: &str) -> Result<()> {
+        let mut leases = self.leases.lock();
+        if leases.get(name).is_some_and(|(owner, _)| owner == holder) {
+            leases.remove(name);
+        }
+        drop(leases);
+        Ok(())
+    }
+}
+
+/// Sets the lease to the holder if it is free or already theirs
+const REDIS_ACQUIRE: &str = "local v = redis.call('GET', KEYS[1]) \
+    if v == false or v == ARGV[1] then \
+    redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2]) return 1 end return 0";
+
+/// Deletes the lease only if the holder still has it
+const REDIS_RELEASE: &str = "if redis.call('GET', KEYS[1]) == ARGV[1] then \
+    return redis.call('DEL', KEYS[1]) end return 0";
+
+/// Leases in Redis, taken and released atomically with Lua scripts
+///
+/// Speaks RESP over one lazily opened connection, reconnecting after errors
+/// and timeouts.
+pub struct RedisLockBackend {
+    address: String,
+    password: Option<String>,
+    prefix: String,
+    timeout: Duration,
+    connection: tokio::sync::Mutex<Option<BufStream<TcpStream>>>,
+}
+
+impl std::fmt::Debug for RedisLockBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisLockBackend")
+            .field("address", &self.address)
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RedisLockBackend {
+    /// Use the Redis server at `address`, e.g. `localhost:6379`
+    #[must_use]
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            password: None,
+            prefix: "universal-bot:lock:".to_string(),
+            timeout: DEFAULT_REDIS_TIMEOUT,
+            connection: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Authenticate with `AUTH` after connecting
+    #[must_use]
+    pub fn with_password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Prefix for lease keys, `universal-bot:lock:` by default
+    #[must_use]
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Fail connects and commands that take longer than `timeout`
+    #[must_use]
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Run a command, returning its integer reply if it has one
+    async fn command(&self, args: &[&str]) -> Result<Option<i64>> {
+        let mut connection = self.connection.lock().await;
+        let reply = tokio::time::timeout(self.timeout, self.command_on(&mut connection, args))
+            .await
+            .unwrap_or_else(|_| Err(Error::Timeout(self.timeout).into()));
+        if reply.is_err() {
+            // The stream may hold half a reply; start over next time
+            *connection = None;
+        }
+        reply
+    }
+
+    async fn command_on(
+        &self,
+        connection: &mut Option<BufStream<TcpStream>>,
+        args: &[&str],
+    ) -> Result<Option<i64>> {
+        if connection.is_none() {
+            let stream = TcpStream::connect(&self.address).await.map_err(|e| {
+                Error::Network(format!("Redis connect to {} failed: {e}", self.address))
+            })?;
+            let mut stream = BufStream::new(stream);
+            if let Some(password) = &self.password {
+                round_trip(&mut stream, &["AUTH", password]).await?;
+            }
+            *connection = Some(stream);
+        }
+        let Some(stream) = connection.as_mut() else {
+            unreachable!("connection was just opened");
+        };
+        round_trip(stream, args).await
+    }
+}
+
+async fn round_trip(stream: &mut BufStream<TcpStream>, args: &[&str]) -> Result<Option<i64>> {
+    let io = |e: std::io::Error| Error::Network(format!("Redis I/O failed: {e}"));
+    let mut request = format!("*{}\r\n", args.len());
+    for arg in args {
+        let _ = write!(request, "${}\r\n{arg}\r\n", arg.len());
+    }
+    stream.write_all(request.as_bytes()).await.map_err(io)?;
+    stream.flush().await.map_err(io)?;
+
+    let mut line = String::new();
+    stream.read_line(&mut line).await.map_err(io)?;
+    let (kind, rest) = line.trim_end().split_at(line.len().min(1));
+    match kind {
+        ":" => rest
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::Network(format!("Malformed Redis reply: {line}")).into()),
+        "+" => Ok(None),
+        "-" => Err(Error::Database(format!("Redis error: {rest}")).into()),
+        "$" => {
+            if let Ok(len @ 0..) = rest.parse::<i64>() {
+                // Skip the bulk string and its trailing CRLF
+                let len = usize::try_from(len).unwrap_or_default() + 2;
+                let mut body = vec![0; len];
+                stream.read_exact(&mut body).await.map_err(io)?;
+            }
+            Ok(None)
+        }
+        _ => Err(Error::Network(format!("Unexpected Redis reply: {line}")).into()),
+    }
+}
+
+#[async_trait]
+impl LockBackend for RedisLockBackend {
+    async fn try_acquire(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool> {
+        let key = format!("{}{name}", self.prefix);
+        let ttl_ms = ttl.as_millis().max(1).to_string();
+        let reply = self
+            .command(&["EVAL", REDIS_ACQUIRE, "1", &key, holder, &ttl_ms])
+            .await?;
+        Ok(reply == Some(1))
+    }
+
+    async fn release(&self, name: &str, holder: &str) -> Result<()> {
+        let key = format!("{}{name}", self.prefix);
+        self.command(&["EVAL", REDIS_RELEASE, "1", &key, holder])
+            .await?;
+        Ok(())
+    }
+}
+
+/// Leases as items of a `DynamoDB` table, written with conditional puts
+///
+/// The table's partition key is the string attribute `lock_name`; items
+/// also carry `holder` and `expires_at` in Unix milliseconds. Enable a TTL
+/// on `expires_at` to clean up abandoned leases.
+#[cfg(feature = "dynamodb")]
+pub struct DynamoDbLockBackend {
+    client: aws_sdk_dynamodb::Client,
+    table: String,
+}
+
+#[cfg(feature = "dynamodb")]
+impl DynamoDbLockBackend {
+    /// Keep leases in `table`
+    #[must_use]
+    pub fn new(client: aws_sdk_dynamodb::Client, table: impl Into<String>) -> Self {
+        Self {
+            client,
+            table: table.into(),
+        }
+    }
+
+    /// Create a backend using credentials from the default AWS provider chain
+    pub async fn from_env(table: impl Into<String>) -> Self {
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        Self::new(aws_sdk_dynamodb::Client::new(&config), table)
+    }
+}
+
+#[cfg(feature = "dynamodb")]
+#[async_trait]
+impl LockBackend for DynamoDbLockBackend {
+    async fn try_acquire(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool> {
+        use aws_sdk_dynamodb::types::AttributeValue;
+
+        let now = chrono::Utc::now().timestamp_millis();
+        let expires_at = now + i64::try_from(ttl.as_millis()).unwrap_or(i64::MAX - now);
+        let result = self
+            .client
+            .put_item()
+            .table_name(&self.table)
+            .item("lock_name", AttributeValue::S(name.to_string()))
+            .item("holder", AttributeValue::S(holder.to_string()))
+            .item("expires_at", AttributeValue::N(expires_at.to_string()))
+            .condition_expression(
+                "attribute_not_exists(lock_name) OR holder = :holder OR expires_at < :now",
+            )
+            .expression_attribute_values(":holder", AttributeValue::S(holder.to_string()))
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .send()
+            .await;
+        match result {
+            Ok(_) => Ok(true),
+            Err(e)
+                if e.as_service_error()
+                    .is_some_and(aws_sdk_dynamodb::operation::put_item::PutItemError::is_conditional_check_failed_exception) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(Error::Database(format!("DynamoDB lease {name} failed: {e}")).into()),
+        }
+    }
+
+    async fn release(&self, name: &str, holder: &str) -> Result<()> {
+        use aws_sdk_dynamodb::types::AttributeValue;
+
+        let result = self
+            .client
+            .delete_item()
+            .table_name(&self.table)
+            .key("lock_name", AttributeValue::S(name.to_string()))
+            .condition_expression("holder = :holder")
+            .expression_attribute_values(":holder", AttributeValue::S(holder.to_string()))
+            .send()
+            .await;
+        match result {
+            Err(e)
+                if !e
+                    .as_service_error()
+                    .is_some_and(aws_sdk_dynamodb::operation::delete_item::DeleteItemError::is_conditional_check_failed_exception) =>
+            {
+                Err(Error::Database(format!("DynamoDB release of {name} failed: {e}")).into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Aborts the task when dropped, so an aborted supervisor loop stops its task
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Runs each singleton task on exactly one replica
+///
+/// Dropping the supervisor stops its tasks without releasing their leases,
+/// which then expire; [`shutdown`](Self::shutdown) hands them over at once.
+pub struct TaskSupervisor {
+    backend: Arc<dyn LockBackend>,
+    holder: String,
+    lease_ttl: Duration,
+    loops: Mutex<Vec<(String, JoinHandle<()>)>>,
+}
+
+impl std::fmt::Debug for TaskSupervisor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TaskSupervisor")
+            .field("holder", &self.holder)
+            .field("lease_ttl", &self.lease_ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TaskSupervisor {
+    /// Supervise tasks as a replica with a random ID
+    #[must_use]
+    pub fn new(backend: Arc<dyn LockBackend>) -> Self {
+        Self {
+            backend,
+            holder: uuid::Uuid::new_v4().to_string(),
+            lease_ttl: DEFAULT_LEASE_TTL,
+            loops: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Identify this replica as `holder`, e.g. its pod name
+    #[must_use]
+    pub fn with_holder(mut self, holder: impl Into<String>) -> Self {
+        self.holder = holder.into();
+        self
+    }
+
+    /// Hold leases for `lease_ttl`, renewing them three times per TTL
+    ///
+    /// A replica takes over a dead holder's task at most this long after it died.
+    #[must_use]
+    pub fn with_lease_ttl(mut self, lease_ttl: Duration) -> Self {
+        self.lease_ttl = lease_ttl;
+        self
+    }
+
+    /// This replica's ID
+    #[must_use]
+    pub fn holder(&self) -> &str {
+        &self.holder
+    }
+
+    /// Run `task` while this replica holds the lease `name`
+    ///
+    /// `task` is started when the lease is taken and aborted when it is
+    /// lost; if it finishes while the lease is held, it is started again.
+    /// A renewal that takes longer than a third of the TTL counts as lost,
+    /// so a stalled backend stops the task before another replica can take
+    /// the expired lease. Call from within a Tokio runtime.
+    pub fn spawn_singleton<F, Fut>(&self, name: impl Into<String>, task: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let name = name.into();
+        let backend = self.backend.clone();
+        let holder = self.holder.clone();
+        let ttl = self.lease_ttl;
+        let lease = name.clone();
+        let handle = tokio::spawn(async move {
+            let period = (ttl / 3).max(Duration::from_millis(10));
+            let mut ticker = tokio::time::interval(period);
+            let mut running: Option<AbortOnDrop> = None;
+            loop {
+                ticker.tick().await;
+                // The last renewal started at most one period ago, so giving up
+                // after another period leaves a third of the lease to stop the task
+                let held =
+                    match tokio::time::timeout(period, backend.try_acquire(&lease, &holder, ttl))
+                        .await
+                    {
+                        Ok(Ok(held)) => held,
+                        Ok(Err(e)) => {
+                            warn!("Failed to renew lease {lease}: {e:#}");
+                            false
+                        }
+                        Err(_) => {
+                            warn!("Renewing lease {lease} timed out after {period:?}");
+                            false
+                        }
+                    };
+                match (held, &running) {
+                    (true, None) => {
+                        info!("Replica {holder} took lease {lease}, starting task");
+                        running = Some(AbortOnDrop(tokio::spawn(task())));
+                    }
+                    (true, Some(task_handle)) if task_handle.0.is_finished() => {
+                        debug!("Singleton task {lease} finished, restarting");
+                        running = Some(AbortOnDrop(tokio::spawn(task())));
+                    }
+                    (false, Some(_)) => {
+                        warn!("Replica {holder} lost lease {lease}, stopping task");
+                        running = None;
+                    }
+                    _ => {}
+                }
+            }
+        });
+        self.loops.lock().push((name, handle));
+    }
+
+    /// Stop every supervised task and release their leases
+    ///
+    /// Other replicas take over on their next renewal instead of waiting
+    /// for the leases to expire.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from releasing a lease.
+    pub async fn shutdown(&self) -> Result<()> {
+        let loops = std::mem::take(&mut *self.loops.lock());
+        let mut result = Ok(());
+        for (name, handle) in loops {
+            handle.abort();
+            // Wait for the loop to drop, and with it abort, its task
+            let _ = handle.await;
+            if let Err(e) = self.backend.release(&name, &self.holder).await {
+                warn!("Failed to release lease {name}: {e:#}");
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+}
+
+impl Drop for TaskSupervisor {
+    fn drop(&mut self) {
+        for (_, handle) in self.loops.get_mut().drain(..) {
+            handle.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_leases_expire_and_renew() {
+        let backend = MemoryLockBackend::new();
+        let ttl = Duration::from_millis(50);
+
+        assert!(backend.try_acquire("gc", "a", ttl).await.unwrap());
+        assert!(!backend.try_acquire("gc", "b", ttl).await.unwrap());
+        assert!(backend.try_acquire("gc", "a", ttl).await.unwrap());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(backend.try_acquire("gc", "b", ttl).await.unwrap());
+        backend.release("gc", "a").await.unwrap();
+        assert!(!backend.try_acquire("gc", "a", ttl).await.unwrap());
+        backend.release("gc", "b").await.unwrap();
+        assert!(backend.try_acquire("gc", "a", ttl).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_one_replica_runs_singleton_and_another_takes_over() {
+        let backend: Arc<dyn LockBackend> = Arc::new(MemoryLockBackend::new());
+        let starts = Arc::new([AtomicUsize::new(0), AtomicUsize::new(0)]);
+        let replicas: Vec<_> = (0..2)
+            .map(|i| {
+                let supervisor = TaskSupervisor::new(backend.clone())
+                    .with_holder(format!("replica-{i}"))
+                    .with_lease_ttl(Duration::from_millis(60));
+                let starts = starts.clone();
+                supervisor.spawn_singleton("archival", move || {
+                    starts[i].fetch_add(1, Ordering::SeqCst);
+                    std::future::pending()
+                });
+                supervisor
+            })
+            .collect();
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let counts = || {
+            starts
+                .iter()
+                .map(|s| s.load(Ordering::SeqCst))
+                .collect::<Vec<_>>()
+        };
+        let leader = counts().iter().position(|&n| n == 1).unwrap();
+        assert_eq!(counts().iter().sum::<usize>(), 1);
+
+        replicas[leader].shutdown().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(counts()[1 - leader], 1);
+    }
+
+    #[tokio::test]
+    async fn test_dropping_the_supervisor_stops_its_tasks() {
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let supervisor = TaskSupervisor::new(Arc::new(MemoryLockBackend::new()))
+            .with_lease_ttl(Duration::from_millis(30));
+        let counter = ticks.clone();
+        supervisor.spawn_singleton("archival", move || {
+            let counter = counter.clone();
+            async move {
+                loop {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(ticks.load(Ordering::SeqCst) > 0);
+
+        drop(supervisor);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let stopped_at = ticks.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(ticks.load(Ordering::SeqCst), stopped_at);
+    }
+
+    /// Grants the first lease, then never answers
+    struct StallingBackend(AtomicUsize);
+
+    #[async_trait]
+    impl LockBackend for StallingBackend {
+        async fn try_acquire(&self, _name: &str, _holder: &str, _ttl: Duration) -> Result<bool> {
+            if self.0.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Ok(true);
+            }
+            std::future::pending().await
+        }
+
+        async fn release(&self, _name: &str, _holder: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stalled_renewal_stops_task_before_lease_expires() {
+        let ttl = Duration::from_millis(90);
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let supervisor =
+            TaskSupervisor::new(Arc::new(StallingBackend(AtomicUsize::new(0)))).with_lease_ttl(ttl);
+        let counter = ticks.clone();
+        supervisor.spawn_singleton("archival", move || {
+            let counter = counter.clone();
+            async move {
+                loop {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+            }
+        });
+
+        // The lease is taken at once; the renewal a third of a TTL later stalls
+        // and is abandoned two thirds of a TTL in, before the lease expires
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        let stopped_at = ticks.load(Ordering::SeqCst);
+        assert!(stopped_at > 0);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(ticks.load(Ordering::SeqCst), stopped_at);
+    }
+}
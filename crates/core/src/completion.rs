@@ -0,0 +1,72 @@
+//! Model completions for internal prompts
+//!
+//! Extractors, summarizers, and judges reach a model through a plain
+//! [`CompletionFn`] rather than a full [`Bot`](crate::bot::Bot). Those that
+//! ask for a list of findings have the model answer with a JSON array and
+//! read it back with [`parse_json_array`]; [`extract_json`] finds the JSON
+//! value in any other reply.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use tracing::warn;
+
+/// Completion function taking a prompt and returning the model's reply
+pub type CompletionFn = Arc<dyn Fn(String) -> BoxFuture<'static, Result<String>> + Send + Sync>;
+
+/// Parse the JSON array in a model reply
+///
+/// Tolerates surrounding prose or code fences around the array. A reply
+/// without a parseable array yields an empty list, logging a warning that
+/// names the `task` the reply was for.
+#[must_use]
+pub fn parse_json_array<T: DeserializeOwned>(text: &str, task: &str) -> Vec<T> {
+    let (Some(start), Some(end)) = (text.find('['), text.rfind(']')) else {
+        return Vec::new();
+    };
+    if end < start {
+        return Vec::new();
+    }
+    serde_json::from_str(&text[start..=end]).unwrap_or_else(|e| {
+        warn!("Failed to parse {} response: {}", task, e);
+        Vec::new()
+    })
+}
+
+/// The JSON object or array in a model reply
+///
+/// Strips surrounding prose or code fences; a reply without either bracket
+/// is returned trimmed.
+#[must_use]
+pub fn extract_json(text: &str) -> &str {
+    match (text.find(['{', '[']), text.rfind(['}', ']'])) {
+        (Some(start), Some(end)) if start <= end => &text[start..=end],
+        _ => text.trim(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_json_array() {
+        let fenced: Vec<u32> = parse_json_array("Here:\n```json\n[1, 2, 3]\n```", "test");
+        assert_eq!(fenced, [1, 2, 3]);
+
+        assert!(parse_json_array::<u32>("no array here", "test").is_empty());
+        assert!(parse_json_array::<u32>("] backwards [", "test").is_empty());
+        assert!(parse_json_array::<u32>("[\"not\", \"numbers\"]", "test").is_empty());
+    }
+
+    #[test]
+    fn test_extract_json() {
+        assert_eq!(
+            extract_json("Sure:\n```json\n{\"a\": [1]}\n```"),
+            "{\"a\": [1]}"
+        );
+        assert_eq!(extract_json("  none  "), "none");
+    }
+}
@@ -0,0 +1,232 @@
+//! Payload compression for persisted contexts
+//!
+//! Stores hand serialized contexts to a [`PayloadCodec`], which compresses
+//! them with zstd once they cross the configured size threshold. Every
+//! payload carries a one-byte tag, so payloads written with compression on
+//! stay readable after it is turned off and vice versa.
+
+use std::io::Read;
+
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::config::CompressionConfig;
+use crate::error::{Error, Result};
+
+/// Upper bound on a decompressed payload, in bytes
+pub const MAX_DECOMPRESSED_LEN: u64 = 64 * 1024 * 1024;
+
+const TAG_RAW: u8 = 0;
+const TAG_ZSTD: u8 = 1;
+
+/// Counters describing how much compression has saved
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// Payloads written compressed
+    pub compressed: u64,
+    /// Payloads written uncompressed (below threshold, disabled, or incompressible)
+    pub uncompressed: u64,
+    /// Serialized size of compressed payloads before compression
+    pub bytes_before: u64,
+    /// Size of compressed payloads after compression
+    pub bytes_after: u64,
+}
+
+impl CompressionStats {
+    /// Ratio of original to compressed size across compressed payloads
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn ratio(&self) -> Option<f64> {
+        (self.bytes_after > 0).then(|| self.bytes_before as f64 / self.bytes_after as f64)
+    }
+}
+
+/// Serializes values to tagged, optionally compressed payloads
+#[derive(Debug)]
+pub struct PayloadCodec {
+    config: CompressionConfig,
+    stats: Mutex<CompressionStats>,
+}
+
+impl PayloadCodec {
+    /// Create a codec with the given settings
+    #[must_use]
+    pub fn new(config: CompressionConfig) -> Self {
+        Self {
+            config,
+            stats: Mutex::new(CompressionStats::default()),
+        }
+    }
+
+    /// The settings this codec was created with
+    #[must_use]
+    pub const fn config(&self) -> &CompressionConfig {
+        &self.config
+    }
+
+    /// Serialize a value, compressing it if it is large enough
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Serialization`] if the value cannot be serialized or
+    /// compression fails.
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        let json = serde_json::to_vec(value).map_err(|e| Error::Serialization(e.to_string()))?;
+
+        if self.config.enabled && json.len() >= self.config.threshold_bytes {
+            let compressed = zstd::bulk::compress(&json, self.config.level)
+                .map_err(|e| Error::Serialization(format!("zstd compression failed: {e}")))?;
+            if compressed.len() < json.len() {
+                let mut stats = self.stats.lock();
+                stats.compressed += 1;
+                stats.bytes_before += json.len() as u64;
+                stats.bytes_after += compressed.len() as u64 + 1;
+                drop(stats);
+                return Ok(tagged(TAG_ZSTD, &compressed));
+            }
+        }
+
+        self.stats.lock().uncompressed += 1;
+        Ok(tagged(TAG_RAW, &json))
+    }
+
+    /// Deserialize a payload produced by [`encode`](Self::encode)
+    ///
+    /// Untagged JSON objects are also accepted, so payloads written before
+    /// compression was introduced remain readable.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Serialization`] for unknown tags, corrupt data, or
+    /// payloads that decompress beyond [`MAX_DECOMPRESSED_LEN`].
+    pub fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> Result<T> {
+        match payload.split_first() {
+            Some((&TAG_RAW, body)) => from_json(body),
+            Some((&TAG_ZSTD, body)) => {
+                let decoder = zstd::stream::read::Decoder::new(body)
+                    .map_err(|e| Error::Serialization(format!("zstd init failed: {e}")))?;
+                let mut json = Vec::new();
+                decoder
+                    .take(MAX_DECOMPRESSED_LEN + 1)
+                    .read_to_end(&mut json)
+                    .map_err(|e| Error::Serialization(format!("zstd decompression failed: {e}")))?;
+                if json.len() as u64 > MAX_DECOMPRESSED_LEN {
+                    return Err(Error::Serialization(format!(
+                        "Decompressed payload exceeds {MAX_DECOMPRESSED_LEN} bytes"
+                    )));
+                }
+                from_json(&json)
+            }
+            Some((b'{', _)) => from_json(payload),
+            Some((tag, _)) => Err(Error::Serialization(format!(
+                "Unknown payload encoding tag: {tag}"
+            ))),
+            None => Err(Error::Serialization("Empty payload".to_string())),
+        }
+    }
+
+    /// The JSON body of a payload that was stored uncompressed
+    ///
+    /// Lets backends with a native JSON type store such payloads as JSON.
+    #[must_use]
+    pub fn plain_json(payload: &[u8]) -> Option<&str> {
+        let json = match payload.split_first() {
+            Some((&TAG_RAW, body)) => body,
+            Some((b'{', _)) => payload,
+            _ => return None,
+        };
+        std::str::from_utf8(json).ok()
+    }
+
+    /// Snapshot of the compression counters
+    #[must_use]
+    pub fn stats(&self) -> CompressionStats {
+        *self.stats.lock()
+    }
+}
+
+fn from_json<T: DeserializeOwned>(json: &[u8]) -> Result<T> {
+    serde_json::from_slice(json).map_err(|e| Error::Serialization(e.to_string()))
+}
+
+fn tagged(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(body.len() + 1);
+    payload.push(tag);
+    payload.extend_from_slice(body);
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_small_payloads_stay_raw() {
+        let codec = PayloadCodec::new(CompressionConfig::default());
+        let payload = codec.encode(&json!({ "a": 1 })).unwrap();
+
+        assert_eq!(payload[0], TAG_RAW);
+        assert_eq!(
+            codec.decode::<serde_json::Value>(&payload).unwrap(),
+            json!({ "a": 1 })
+        );
+        assert_eq!(codec.stats().uncompressed, 1);
+        assert_eq!(codec.stats().ratio(), None);
+    }
+
+    #[test]
+    fn test_large_payloads_are_compressed() {
+        let codec = PayloadCodec::new(CompressionConfig::default());
+        let value = json!({ "history": vec!["the same words over and over"; 200] });
+        let payload = codec.encode(&value).unwrap();
+
+        assert_eq!(payload[0], TAG_ZSTD);
+        assert_eq!(codec.decode::<serde_json::Value>(&payload).unwrap(), value);
+
+        let stats = codec.stats();
+        assert_eq!(stats.compressed, 1);
+        assert!(stats.ratio().unwrap() > 5.0);
+    }
+
+    #[test]
+    fn test_decode_is_independent_of_config() {
+        let value = json!({ "text": "x".repeat(4096) });
+        let compressed = PayloadCodec::new(CompressionConfig::default())
+            .encode(&value)
+            .unwrap();
+        let disabled = PayloadCodec::new(CompressionConfig::disabled());
+
+        assert_eq!(
+            disabled.decode::<serde_json::Value>(&compressed).unwrap(),
+            value
+        );
+        assert_eq!(disabled.encode(&value).unwrap()[0], TAG_RAW);
+        assert_eq!(
+            disabled
+                .decode::<serde_json::Value>(br#"{"legacy":true}"#)
+                .unwrap(),
+            json!({ "legacy": true })
+        );
+    }
+
+    #[test]
+    fn test_plain_json() {
+        let codec = PayloadCodec::new(CompressionConfig::default());
+        let raw = codec.encode(&json!({ "a": 1 })).unwrap();
+        assert_eq!(PayloadCodec::plain_json(&raw), Some(r#"{"a":1}"#));
+
+        let compressed = codec.encode(&json!({ "text": "x".repeat(4096) })).unwrap();
+        assert_eq!(PayloadCodec::plain_json(&compressed), None);
+    }
+
+    #[test]
+    fn test_decode_rejects_garbage() {
+        let codec = PayloadCodec::new(CompressionConfig::default());
+        assert!(codec.decode::<serde_json::Value>(&[]).is_err());
+        assert!(codec.decode::<serde_json::Value>(&[7, 1, 2]).is_err());
+        assert!(codec
+            .decode::<serde_json::Value>(&[TAG_ZSTD, 1, 2, 3])
+            .is_err());
+    }
+}
@@ -3,6 +3,7 @@
 //! This module provides configuration structures and builders for the bot,
 //! following the builder pattern for ergonomic configuration.
 
+use std::collections::{BTreeMap, HashMap};
 use std::time::Duration;
 
 use anyhow::{Context as _, Result};
@@ -10,14 +11,21 @@ use serde::{Deserialize, Serialize};
 use validator::{Validate, ValidationError};
 
 use crate::error::Error;
+use crate::template::ConversationTemplate;
+use crate::user_error::UserErrorKind;
 
 /// Main bot configuration
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct BotConfig {
     /// AI model to use for generation
     #[validate(custom(function = "validate_model"))]
     pub model: String,
 
+    /// System prompt sent with every request
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+
     /// Temperature for generation (0.0 to 1.0)
     #[validate(range(min = 0.0, max = 1.0))]
     pub temperature: f32,
@@ -28,6 +36,7 @@ pub struct BotConfig {
 
     /// Request timeout
     #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
     pub timeout: Duration,
 
     /// Number of retries for failed requests
@@ -48,6 +57,57 @@ pub struct BotConfig {
 
     /// Plugin configuration
     pub plugin_config: PluginConfig,
+
+    /// Preset these settings were derived from
+    #[serde(default)]
+    pub profile: ConfigProfile,
+
+    /// Behavior while the provider is unavailable
+    #[serde(default)]
+    pub degraded_mode: DegradedModeConfig,
+
+    /// Per-tenant and per-conversation model selection
+    #[serde(default)]
+    pub model_selection: ModelSelectionConfig,
+
+    /// Provisioned throughput commitments and spillover policy
+    #[serde(default)]
+    pub provisioned_throughput: ProvisionedThroughputConfig,
+
+    /// Opt-in anonymized usage telemetry
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+
+    /// Usage limits after which the bot answers in degraded mode
+    #[serde(default)]
+    pub budget: BudgetConfig,
+
+    /// Per-conversation, per-user, and global spend limits
+    #[serde(default)]
+    pub cost_governor: CostGovernorConfig,
+
+    /// Handling of user input longer than a message may be
+    #[serde(default)]
+    pub input_overflow: InputOverflowConfig,
+
+    /// Latency objectives and burn-rate alerts
+    #[serde(default)]
+    #[validate(nested)]
+    pub slo: SloConfig,
+
+    /// Early rejection of low-priority requests under overload
+    #[serde(default)]
+    #[validate(nested)]
+    pub load_shedding: LoadSheddingConfig,
+
+    /// Notices sent to users before their conversations expire
+    #[serde(default)]
+    #[validate(nested)]
+    pub expiry_notifications: ExpiryNotificationConfig,
+
+    /// Time-boxed sessions closed with a wrap-up summary
+    #[serde(default)]
+    pub sessions: SessionConfig,
 }
 
 impl BotConfig {
@@ -67,30 +127,148 @@ impl BotConfig {
         Ok(())
     }
 
+    /// Fingerprint of the settings that shape assistant turns
+    ///
+    /// Changes to the model or system prompt change the fingerprint, which
+    /// lets the context manager detect conversations started under a
+    /// different configuration. The hash is stable across builds and
+    /// platforms so it can be persisted.
+    #[must_use]
+    pub fn fingerprint(&self) -> String {
+        // FNV-1a, chosen over `DefaultHasher` for its stable output
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let parts = [
+            self.model.as_str(),
+            self.system_prompt.as_deref().unwrap_or(""),
+        ];
+        for byte in parts.join("\0").bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        format!("{hash:016x}")
+    }
+
+    /// Preset for interactive use where response time matters most
+    ///
+    /// Uses a small model and short answers, fails fast instead of retrying,
+    /// and skips the enrichment stage.
+    #[must_use]
+    pub fn low_latency() -> Self {
+        Self {
+            model: "anthropic.claude-haiku".to_string(),
+            max_tokens: 1024,
+            timeout: Duration::from_secs(10),
+            max_retries: 1,
+            context_config: ContextConfig {
+                max_context_tokens: 2048,
+                compression: Some(CompressionConfig::disabled()),
+                ..ContextConfig::default()
+            },
+            pipeline_config: PipelineConfig::without_enrichment(Duration::from_secs(5)),
+            profile: ConfigProfile::LowLatency,
+            ..Self::default()
+        }
+    }
+
+    /// Preset for answers where quality matters more than speed or cost
+    #[must_use]
+    pub fn high_quality() -> Self {
+        Self {
+            model: "anthropic.claude-opus-4-1".to_string(),
+            max_tokens: 8192,
+            timeout: Duration::from_secs(120),
+            max_retries: 5,
+            context_config: ContextConfig {
+                max_context_tokens: 32_000,
+                ..ContextConfig::default()
+            },
+            pipeline_config: PipelineConfig {
+                max_processing_time: Duration::from_secs(60),
+                ..PipelineConfig::default()
+            },
+            profile: ConfigProfile::HighQuality,
+            ..Self::default()
+        }
+    }
+
+    /// Preset that minimizes token spend and storage
+    ///
+    /// Keeps little history in context, so fewer input tokens are billed
+    /// per turn, and always compresses persisted contexts.
+    #[must_use]
+    pub fn cost_saver() -> Self {
+        Self {
+            model: "anthropic.claude-haiku".to_string(),
+            max_tokens: 512,
+            max_retries: 2,
+            enable_cost_tracking: true,
+            context_config: ContextConfig {
+                max_context_tokens: 1024,
+                compression: Some(CompressionConfig::default()),
+                ..ContextConfig::default()
+            },
+            pipeline_config: PipelineConfig::without_enrichment(Duration::from_secs(10)),
+            profile: ConfigProfile::CostSaver,
+            ..Self::default()
+        }
+    }
+
+    /// Build a configuration from a partial document layered over a preset
+    ///
+    /// The document's `profile` key (default `balanced`) selects the preset;
+    /// every other key overrides it, recursively for nested tables. This lets
+    /// config files state only what differs from a profile. Callers reading
+    /// YAML or TOML can convert to [`serde_json::Value`] first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the profile is unknown, the merged document does
+    /// not describe a valid configuration, or validation fails.
+    pub fn from_value(overrides: serde_json::Value) -> Result<Self> {
+        let profile: ConfigProfile = match overrides.get("profile") {
+            Some(profile) => serde_json::from_value(profile.clone())
+                .map_err(|e| Error::Configuration(format!("Invalid profile: {e}")))?,
+            None => ConfigProfile::default(),
+        };
+
+        let mut merged = serde_json::to_value(profile.config())
+            .map_err(|e| Error::Serialization(e.to_string()))?;
+        merge_json(&mut merged, overrides);
+
+        let config: Self = serde_json::from_value(merged)
+            .map_err(|e| Error::Configuration(format!("Invalid configuration: {e}")))?;
+        config.validate()?;
+        Ok(config)
+    }
+
     /// Load configuration from environment variables
     ///
     /// # Errors
     ///
     /// Returns an error if required environment variables are missing.
     pub fn from_env() -> Result<Self> {
-        let model = std::env::var("DEFAULT_MODEL")
-            .unwrap_or_else(|_| "anthropic.claude-opus-4-1".to_string());
+        let base = match std::env::var("BOT_PROFILE") {
+            Ok(profile) => profile.parse::<ConfigProfile>()?.config(),
+            Err(_) => Self::default(),
+        };
+
+        let model = std::env::var("DEFAULT_MODEL").unwrap_or_else(|_| base.model.clone());
 
-        let temperature = std::env::var("TEMPERATURE")
-            .unwrap_or_else(|_| "0.1".to_string())
-            .parse()
-            .context("Invalid TEMPERATURE value")?;
+        let temperature = match std::env::var("TEMPERATURE") {
+            Ok(value) => value.parse().context("Invalid TEMPERATURE value")?,
+            Err(_) => base.temperature,
+        };
 
-        let max_tokens = std::env::var("MAX_TOKENS")
-            .unwrap_or_else(|_| "2048".to_string())
-            .parse()
-            .context("Invalid MAX_TOKENS value")?;
+        let max_tokens = match std::env::var("MAX_TOKENS") {
+            Ok(value) => value.parse().context("Invalid MAX_TOKENS value")?,
+            Err(_) => base.max_tokens,
+        };
 
         Ok(Self {
             model,
             temperature,
             max_tokens,
-            ..Default::default()
+            ..base
         })
     }
 }
@@ -99,6 +277,7 @@ impl Default for BotConfig {
     fn default() -> Self {
         Self {
             model: "anthropic.claude-opus-4-1".to_string(),
+            system_prompt: None,
             temperature: 0.1,
             max_tokens: 2048,
             timeout: Duration::from_secs(30),
@@ -108,11 +287,62 @@ impl Default for BotConfig {
             context_config: ContextConfig::default(),
             pipeline_config: PipelineConfig::default(),
             plugin_config: PluginConfig::default(),
+            profile: ConfigProfile::Balanced,
+            degraded_mode: DegradedModeConfig::default(),
+            model_selection: ModelSelectionConfig::default(),
+            provisioned_throughput: ProvisionedThroughputConfig::default(),
+            telemetry: TelemetryConfig::default(),
+            budget: BudgetConfig::default(),
+            cost_governor: CostGovernorConfig::default(),
+            input_overflow: InputOverflowConfig::default(),
+            slo: SloConfig::default(),
+            load_shedding: LoadSheddingConfig::default(),
+            expiry_notifications: ExpiryNotificationConfig::default(),
+            sessions: SessionConfig::default(),
         }
     }
 }
 
+/// Named presets for [`BotConfig`]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigProfile {
+    /// The general-purpose defaults
+    #[default]
+    Balanced,
+    /// Fast model, short answers, no enrichment
+    LowLatency,
+    /// Most capable model, long answers, generous retries and context
+    HighQuality,
+    /// Cheapest model, small context, compressed persistence
+    CostSaver,
+}
+
+impl ConfigProfile {
+    /// The full configuration this preset stands for
+    #[must_use]
+    pub fn config(self) -> BotConfig {
+        match self {
+            Self::Balanced => BotConfig::default(),
+            Self::LowLatency => BotConfig::low_latency(),
+            Self::HighQuality => BotConfig::high_quality(),
+            Self::CostSaver => BotConfig::cost_saver(),
+        }
+    }
+}
+
+impl std::str::FromStr for ConfigProfile {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(s.replace('-', "_")))
+            .map_err(|_| Error::Configuration(format!("Unknown config profile: {s}")))
+    }
+}
+
 /// Configuration for context management
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct ContextConfig {
     /// Maximum context size in tokens
@@ -121,6 +351,7 @@ pub struct ContextConfig {
 
     /// Context TTL
     #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
     pub context_ttl: Duration,
 
     /// Enable context persistence
@@ -128,6 +359,43 @@ pub struct ContextConfig {
 
     /// Context storage backend
     pub storage_backend: StorageBackend,
+
+    /// How to treat contexts created under a different model or system prompt
+    #[serde(default)]
+    pub stale_context_policy: StaleContextPolicy,
+
+    /// Payload compression; falls back to the storage backend's default when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(nested)]
+    pub compression: Option<CompressionConfig>,
+
+    /// Token budget for pinned messages, which are never trimmed
+    #[serde(default = "default_max_pinned_tokens")]
+    pub max_pinned_tokens: usize,
+
+    /// Encryption of persisted contexts with per-tenant keys
+    #[serde(default)]
+    #[validate(nested)]
+    pub encryption: EncryptionConfig,
+
+    /// Persist changes as an append-only event log instead of full rewrites
+    #[serde(default)]
+    #[validate(nested)]
+    pub event_log: EventLogConfig,
+
+    /// Read-only replica serving listing, summaries, expiry scans, and
+    /// exports, so reporting does not load the primary; only `PostgreSQL`
+    /// replicas are supported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_replica: Option<StorageBackend>,
+
+    /// Conversation templates by ID, see [`crate::template`]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub templates: HashMap<String, ConversationTemplate>,
+}
+
+const fn default_max_pinned_tokens() -> usize {
+    1024
 }
 
 impl Default for ContextConfig {
@@ -137,11 +405,162 @@ impl Default for ContextConfig {
             context_ttl: Duration::from_secs(3600),
             persist_context: false,
             storage_backend: StorageBackend::Memory,
+            stale_context_policy: StaleContextPolicy::default(),
+            compression: None,
+            max_pinned_tokens: default_max_pinned_tokens(),
+            encryption: EncryptionConfig::default(),
+            event_log: EventLogConfig::default(),
+            read_replica: None,
+            templates: HashMap::new(),
+        }
+    }
+}
+
+impl ContextConfig {
+    /// Compression settings in effect for the configured backend
+    #[must_use]
+    pub fn effective_compression(&self) -> CompressionConfig {
+        self.compression
+            .unwrap_or_else(|| self.storage_backend.default_compression())
+    }
+}
+
+/// Compression applied to persisted context payloads
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct CompressionConfig {
+    /// Compress payloads at all
+    pub enabled: bool,
+
+    /// Payloads smaller than this many bytes are stored uncompressed
+    pub threshold_bytes: usize,
+
+    /// zstd compression level
+    #[validate(range(min = 1, max = 22))]
+    pub level: i32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold_bytes: 1024,
+            level: 3,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Configuration that never compresses
+    #[must_use]
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+}
+
+/// Encryption at rest for persisted contexts
+///
+/// Each context is encrypted under a data key wrapped by its tenant's
+/// customer-managed key, so tenants can bring their own KMS key and revoke
+/// access to their conversations by disabling it.
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    /// Encrypt contexts before they are stored
+    pub enabled: bool,
+
+    /// Key for tenants without their own, e.g. a KMS key ARN; contexts of
+    /// such tenants are stored unencrypted when unset
+    pub default_key_id: Option<String>,
+
+    /// Customer-managed key per tenant ID
+    pub tenant_keys: HashMap<String, String>,
+
+    /// How long a data key is reused for writes before a new one is generated
+    #[validate(range(min = 1))]
+    pub data_key_ttl_seconds: u64,
+
+    /// Accept unencrypted contexts of tenants that have a key, while
+    /// migrating a store written before encryption was turned on
+    pub allow_unsealed: bool,
+}
+
+impl Default for EncryptionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            default_key_id: None,
+            tenant_keys: HashMap::new(),
+            data_key_ttl_seconds: 300,
+            allow_unsealed: false,
+        }
+    }
+}
+
+impl EncryptionConfig {
+    /// The key that encrypts `tenant`'s contexts, if any
+    #[must_use]
+    pub fn key_for(&self, tenant: Option<&str>) -> Option<&str> {
+        if !self.enabled {
+            return None;
+        }
+        tenant
+            .and_then(|tenant| self.tenant_keys.get(tenant))
+            .or(self.default_key_id.as_ref())
+            .map(String::as_str)
+    }
+}
+
+/// Event-sourced context persistence
+///
+/// Each update appends the changes since the last write (messages and
+/// responses added, variables set, history trimmed) to an event log instead
+/// of rewriting the whole context, and a full snapshot is written every
+/// `snapshot_interval` events so that loading replays only a short tail. The
+/// log is kept until the context is deleted, for audit and replay. See
+/// [`crate::journal`].
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct EventLogConfig {
+    /// Append events instead of rewriting persisted contexts
+    pub enabled: bool,
+
+    /// Events appended between snapshots
+    #[validate(range(min = 1))]
+    pub snapshot_interval: usize,
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            snapshot_interval: 50,
         }
     }
 }
 
+/// Policy for contexts whose configuration fingerprint no longer matches
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StaleContextPolicy {
+    /// Keep the context unchanged
+    Ignore,
+    /// Keep the history and add a system note about the configuration change
+    #[default]
+    Annotate,
+    /// Clear the conversation history
+    Reset,
+}
+
 /// Storage backend for context persistence
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum StorageBackend {
@@ -164,7 +583,23 @@ pub enum StorageBackend {
     },
 }
 
+impl StorageBackend {
+    /// Default payload compression for this backend
+    ///
+    /// In-process storage gains nothing from compression, and `PostgreSQL`
+    /// compresses large JSONB values itself; other networked and on-disk
+    /// backends compress payloads above the default threshold.
+    #[must_use]
+    pub fn default_compression(&self) -> CompressionConfig {
+        match self {
+            Self::Memory | Self::Postgres { .. } => CompressionConfig::disabled(),
+            Self::Redis { .. } | Self::Sqlite { .. } => CompressionConfig::default(),
+        }
+    }
+}
+
 /// Configuration for the message pipeline
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct PipelineConfig {
     /// Enable message sanitization
@@ -175,10 +610,31 @@ pub struct PipelineConfig {
 
     /// Maximum pipeline processing time
     #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
     pub max_processing_time: Duration,
 
     /// Pipeline stages to enable
     pub enabled_stages: Vec<String>,
+
+    /// Attach an execution trace to every response's metadata
+    #[serde(default)]
+    pub attach_trace: bool,
+
+    /// Which requests get verbose traces
+    #[serde(default)]
+    pub trace_sampling: TraceSamplingConfig,
+
+    /// Documents the `retrieve` stage adds to the prompt
+    #[serde(default)]
+    pub retrieval: RetrievalConfig,
+
+    /// Messages shown instead of internal errors
+    #[serde(default)]
+    pub user_errors: UserErrorConfig,
+
+    /// Rendering of the system prompt as a prompt template
+    #[serde(default)]
+    pub prompt_templates: PromptTemplateConfig,
 }
 
 impl Default for PipelineConfig {
@@ -194,12 +650,872 @@ impl Default for PipelineConfig {
                 "process".to_string(),
                 "format".to_string(),
             ],
+            attach_trace: false,
+            trace_sampling: TraceSamplingConfig::default(),
+            retrieval: RetrievalConfig::default(),
+            user_errors: UserErrorConfig::default(),
+            prompt_templates: PromptTemplateConfig::default(),
+        }
+    }
+}
+
+impl PipelineConfig {
+    /// Default stages minus enrichment, with the given time budget
+    #[must_use]
+    pub fn without_enrichment(max_processing_time: Duration) -> Self {
+        let mut config = Self {
+            enable_enrichment: false,
+            max_processing_time,
+            ..Self::default()
+        };
+        config.enabled_stages.retain(|stage| stage != "enrich");
+        config
+    }
+}
+
+/// Sampling of verbose request traces
+///
+/// Every request logs a one-line summary. Sampled requests also log their
+/// prompt text and stage timings and carry an execution trace, which keeps
+/// log volume and PII exposure proportional to `verbose_rate`.
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TraceSamplingConfig {
+    /// Share of requests (0.0 to 1.0) traced verbosely
+    pub verbose_rate: f64,
+
+    /// Trace every request that fails, regardless of `verbose_rate`
+    pub sample_errors: bool,
+
+    /// Redact PII from prompt text in verbose traces
+    pub redact_prompts: bool,
+}
+
+impl Default for TraceSamplingConfig {
+    fn default() -> Self {
+        Self {
+            verbose_rate: 0.0,
+            sample_errors: true,
+            redact_prompts: true,
+        }
+    }
+}
+
+/// Retrieval for the `retrieve` pipeline stage
+///
+/// See [`RetrieveStage`](crate::retrieval::RetrieveStage).
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetrievalConfig {
+    /// Most documents to retrieve per message
+    pub top_k: usize,
+
+    /// Lowest similarity score a document needs to be used
+    pub min_score: Option<f32>,
+
+    /// Most characters of retrieved text added to the prompt
+    pub max_context_chars: usize,
+}
+
+impl Default for RetrievalConfig {
+    fn default() -> Self {
+        Self {
+            top_k: 4,
+            min_score: None,
+            max_context_chars: 8_000,
+        }
+    }
+}
+
+/// User-facing error messages
+///
+/// See [`ErrorCatalog`](crate::user_error::ErrorCatalog).
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserErrorConfig {
+    /// Answer failed requests with a catalog message instead of an error
+    pub enabled: bool,
+
+    /// Delay, in seconds, suggested after rate limits and outages
+    pub retry_after_seconds: u64,
+
+    /// Messages replacing or adding to the built-in catalog
+    pub messages: Vec<UserErrorMessage>,
+}
+
+impl Default for UserErrorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            retry_after_seconds: 30,
+            messages: Vec::new(),
+        }
+    }
+}
+
+/// System prompts as [`PromptTemplate`](crate::prompt_template::PromptTemplate)s
+///
+/// When enabled, the process stage renders the system prompt with the
+/// conversation's variables before sending it.
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PromptTemplateConfig {
+    /// Render the system prompt as a Handlebars template
+    pub enabled: bool,
+
+    /// Partials system prompts can include with `{{> name}}`
+    pub partials: BTreeMap<String, String>,
+
+    /// Use this prompt from the bot's
+    /// [`PromptRegistry`](crate::prompt_registry::PromptRegistry) as the
+    /// system prompt, in the version assigned to each conversation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub registry_prompt: Option<String>,
+}
+
+/// A catalog message for one kind of error in one language
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserErrorMessage {
+    /// Language subtag, e.g. `de`
+    pub language: String,
+
+    /// Kind of error the message is for
+    pub kind: UserErrorKind,
+
+    /// Explanation of what went wrong
+    pub message: String,
+
+    /// What the user can do about it
+    #[serde(default)]
+    pub hint: Option<String>,
+}
+
+/// What to serve instead of a model response while degraded
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum DegradedAction {
+    /// Propagate the error to the caller
+    Fail,
+    /// Reply with a fixed message
+    Canned {
+        /// Reply text
+        message: String,
+    },
+    /// Hold the message for processing once the provider recovers
+    Queue {
+        /// Acknowledgement sent to the user
+        acknowledgement: String,
+    },
+    /// Reply with a retryable "service busy" error
+    Busy {
+        /// Suggested wait before retrying
+        #[serde(with = "humantime_serde")]
+        #[cfg_attr(feature = "schema", schemars(with = "String"))]
+        retry_after: Duration,
+    },
+}
+
+impl Default for DegradedAction {
+    fn default() -> Self {
+        Self::Busy {
+            retry_after: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Configuration for graceful degradation when the provider is down
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DegradedModeConfig {
+    /// Consecutive provider failures that open the circuit
+    pub failure_threshold: u32,
+
+    /// How long the circuit stays open before a probe request is allowed
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub reset_timeout: Duration,
+
+    /// Action for tenants without an override
+    pub default_action: DegradedAction,
+
+    /// Per-tenant overrides, keyed by tenant ID
+    pub tenant_actions: HashMap<String, DegradedAction>,
+}
+
+impl Default for DegradedModeConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            reset_timeout: Duration::from_secs(30),
+            default_action: DegradedAction::default(),
+            tenant_actions: HashMap::new(),
+        }
+    }
+}
+
+impl DegradedModeConfig {
+    /// The action configured for a tenant
+    #[must_use]
+    pub fn action_for(&self, tenant: Option<&str>) -> &DegradedAction {
+        tenant
+            .and_then(|tenant| self.tenant_actions.get(tenant))
+            .unwrap_or(&self.default_action)
+    }
+}
+
+/// Configuration for choosing the model that serves a message
+///
+/// The model is resolved with the precedence request override >
+/// conversation pin > tenant default > [`BotConfig::model`].
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelSelectionConfig {
+    /// Default model per tenant, keyed by tenant ID
+    pub tenant_models: HashMap<String, String>,
+
+    /// Models that overrides and pins may select; empty allows any known model
+    pub allowed_models: Vec<String>,
+
+    /// Users allowed to run the `/model` command; empty disables it
+    pub authorized_users: Vec<String>,
+}
+
+impl ModelSelectionConfig {
+    /// Whether overrides and pins may select `model`
+    #[must_use]
+    pub fn allows(&self, model: &str) -> bool {
+        if self.allowed_models.is_empty() {
+            validate_model(model).is_ok()
+        } else {
+            self.allowed_models.iter().any(|allowed| allowed == model)
+        }
+    }
+
+    /// Whether `user_id` may pin models with `/model`
+    #[must_use]
+    pub fn can_switch(&self, user_id: &str) -> bool {
+        self.authorized_users.iter().any(|user| user == user_id)
+    }
+}
+
+/// A provisioned-throughput deployment of a base or custom model
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvisionedModelConfig {
+    /// Provisioned model ARN that requests are sent to
+    pub arn: String,
+
+    /// On-demand model the deployment serves, used for spillover
+    pub base_model: String,
+
+    /// Purchased model units
+    pub model_units: u32,
+
+    /// Tokens per minute a single model unit sustains
+    pub tokens_per_minute_per_unit: u64,
+
+    /// End of the commitment term; expired deployments are not routed to
+    #[serde(default)]
+    pub commitment_expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl ProvisionedModelConfig {
+    /// Tokens per minute the deployment sustains
+    #[must_use]
+    pub fn tokens_per_minute(&self) -> u64 {
+        u64::from(self.model_units).saturating_mul(self.tokens_per_minute_per_unit)
+    }
+}
+
+/// Configuration for routing to provisioned throughput
+///
+/// Requests for a base model go to a matching provisioned deployment until
+/// its utilization reaches `spillover_threshold`, then spill over to the
+/// on-demand model.
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProvisionedThroughputConfig {
+    /// Provisioned deployments
+    pub models: Vec<ProvisionedModelConfig>,
+
+    /// Fraction of per-minute capacity (0.0 to 1.0) above which requests spill over
+    pub spillover_threshold: f64,
+}
+
+impl Default for ProvisionedThroughputConfig {
+    fn default() -> Self {
+        Self {
+            models: Vec::new(),
+            spillover_threshold: 0.9,
+        }
+    }
+}
+
+/// Configuration for opt-in anonymized usage telemetry
+///
+/// See [`crate::telemetry`] for what is collected.
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    /// Report usage statistics; off by default
+    pub enabled: bool,
+
+    /// URL reports are sent to with `POST`
+    pub endpoint: Option<String>,
+
+    /// How often reports are sent
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub interval: Duration,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: None,
+            interval: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+/// Latency service level objectives
+///
+/// See [`crate::slo`] for how objectives are evaluated.
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct SloConfig {
+    /// Track latency against the objectives; off by default
+    pub enabled: bool,
+
+    /// How often burn rates are checked
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub evaluation_interval: Duration,
+
+    /// Objectives to track
+    #[validate(nested)]
+    pub objectives: Vec<LatencyObjective>,
+}
+
+impl Default for SloConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            evaluation_interval: Duration::from_secs(60),
+            objectives: vec![LatencyObjective::default()],
+        }
+    }
+}
+
+/// An end-to-end latency objective, e.g. 95% of requests within 3 seconds
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct LatencyObjective {
+    /// Name used in alerts
+    #[validate(length(min = 1))]
+    pub name: String,
+
+    /// Fraction of requests that must finish within `threshold`; 0.95 is a p95 objective
+    #[validate(range(min = 0.5, max = 0.9999))]
+    pub target: f64,
+
+    /// Latency a request must stay within to count as good
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub threshold: Duration,
+
+    /// Period over which the error budget is spent
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub period: Duration,
+
+    /// Count failed requests as bad regardless of their latency
+    pub count_errors: bool,
+
+    /// Fewest requests in a window for its burn rate to be considered
+    pub min_requests: u64,
+
+    /// Multi-window burn-rate alerts
+    #[validate(nested)]
+    pub alerts: Vec<BurnRateAlert>,
+}
+
+impl Default for LatencyObjective {
+    fn default() -> Self {
+        Self {
+            name: "p95-end-to-end".to_string(),
+            target: 0.95,
+            threshold: Duration::from_secs(3),
+            period: Duration::from_secs(30 * 24 * 60 * 60),
+            count_errors: true,
+            min_requests: 10,
+            alerts: BurnRateAlert::defaults(),
         }
     }
 }
 
+/// How urgent an SLO alert is
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertSeverity {
+    /// Wake someone up
+    Page,
+    /// Look at it during working hours
+    Ticket,
+}
+
+/// Alert when the error budget burns too fast over two windows at once
+///
+/// The long window shows the burn is significant, the short one that it is
+/// still happening, so the alert resolves soon after latency recovers.
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate)]
+pub struct BurnRateAlert {
+    /// Urgency
+    pub severity: AlertSeverity,
+
+    /// Window the burn rate must exceed the limit over
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub long_window: Duration,
+
+    /// Recent window that must also exceed it
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub short_window: Duration,
+
+    /// Multiple of the sustainable rate of budget spend; 1.0 spends exactly
+    /// the budget over the period
+    #[validate(range(min = 0.1))]
+    pub burn_rate: f64,
+}
+
+impl BurnRateAlert {
+    /// Page at 2% of a 30-day budget spent in an hour or 5% in six hours;
+    /// open a ticket at 10% in three days
+    #[must_use]
+    pub fn defaults() -> Vec<Self> {
+        const HOUR: u64 = 60 * 60;
+        vec![
+            Self {
+                severity: AlertSeverity::Page,
+                long_window: Duration::from_secs(HOUR),
+                short_window: Duration::from_secs(5 * 60),
+                burn_rate: 14.4,
+            },
+            Self {
+                severity: AlertSeverity::Page,
+                long_window: Duration::from_secs(6 * HOUR),
+                short_window: Duration::from_secs(30 * 60),
+                burn_rate: 6.0,
+            },
+            Self {
+                severity: AlertSeverity::Ticket,
+                long_window: Duration::from_secs(72 * HOUR),
+                short_window: Duration::from_secs(6 * HOUR),
+                burn_rate: 1.0,
+            },
+        ]
+    }
+}
+
+/// How important a request is when the bot is overloaded
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestPriority {
+    /// Batch or automated work that can wait, shed first
+    Background,
+    /// Ordinary traffic
+    #[default]
+    Normal,
+    /// A user waiting on the reply; never shed
+    Interactive,
+}
+
+/// Thresholds for rejecting low-priority requests under overload
+///
+/// Saturation is the larger of the number of messages in flight relative to
+/// `max_in_flight` and the smoothed context store wait relative to
+/// `max_wait`. See [`crate::shedding`] for which priorities are shed when.
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct LoadSheddingConfig {
+    /// Shed requests when saturated; off by default
+    pub enabled: bool,
+
+    /// Messages in flight at which the bot counts as saturated
+    #[validate(range(min = 1))]
+    pub max_in_flight: usize,
+
+    /// Smoothed wait for the context store at which the bot counts as saturated
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub max_wait: Duration,
+
+    /// Saturation at which normal-priority requests are shed as well;
+    /// background requests are shed from 1.0
+    #[validate(range(min = 1.0))]
+    pub normal_saturation: f64,
+
+    /// Suggested wait before retrying, sent with overloaded responses
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub retry_after: Duration,
+
+    /// Priority of requests that do not set one, keyed by tenant ID
+    pub tenant_priorities: HashMap<String, RequestPriority>,
+}
+
+impl Default for LoadSheddingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_in_flight: 64,
+            max_wait: Duration::from_millis(500),
+            normal_saturation: 1.5,
+            retry_after: Duration::from_secs(5),
+            tenant_priorities: HashMap::new(),
+        }
+    }
+}
+
+impl LoadSheddingConfig {
+    /// The priority of a tenant's requests that do not set one
+    #[must_use]
+    pub fn priority_for(&self, tenant: Option<&str>) -> RequestPriority {
+        tenant
+            .and_then(|tenant| self.tenant_priorities.get(tenant))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// When a conversation's session ends
+///
+/// A session closes after `idle_timeout` without messages or once it has
+/// run for `max_duration`; the next message then starts a new one. See
+/// [`crate::session`].
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Close sessions at all; off by default
+    pub enabled: bool,
+
+    /// Inactivity after which a session is closed
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub idle_timeout: Duration,
+
+    /// Longest a session may run; unlimited if unset
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub max_duration: Option<Duration>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            idle_timeout: Duration::from_secs(30 * 60),
+            max_duration: None,
+        }
+    }
+}
+
+/// Notices sent before a conversation's context expires
+///
+/// See [`crate::expiry`] for how notices are delivered.
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct ExpiryNotificationConfig {
+    /// Notify tenants without an override; off by default
+    pub enabled: bool,
+
+    /// How long before expiry the notice is sent
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub lead_time: Duration,
+
+    /// How often contexts are checked
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub check_interval: Duration,
+
+    /// Per-tenant overrides of `enabled`, keyed by tenant ID
+    pub tenants: HashMap<String, bool>,
+}
+
+impl Default for ExpiryNotificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            lead_time: Duration::from_secs(10 * 60),
+            check_interval: Duration::from_secs(60),
+            tenants: HashMap::new(),
+        }
+    }
+}
+
+impl ExpiryNotificationConfig {
+    /// Whether a tenant's users are notified
+    #[must_use]
+    pub fn enabled_for(&self, tenant: Option<&str>) -> bool {
+        tenant
+            .and_then(|tenant| self.tenants.get(tenant))
+            .copied()
+            .unwrap_or(self.enabled)
+    }
+
+    /// Whether any tenant's users are notified
+    #[must_use]
+    pub fn any_enabled(&self) -> bool {
+        self.enabled || self.tenants.values().any(|enabled| *enabled)
+    }
+}
+
+/// Usage limits for the lifetime of a bot
+///
+/// Once a limit is reached, messages are answered as configured in
+/// [`BotConfig::degraded_mode`] with
+/// [`DegradedReason::BudgetExhausted`](crate::degraded::DegradedReason::BudgetExhausted),
+/// unless a [`downgrade`](Self::downgrade) policy keeps serving on cheaper
+/// models.
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BudgetConfig {
+    /// Most tokens to spend; unlimited if unset
+    pub max_tokens: Option<u64>,
+
+    /// Most estimated cost to spend, in USD; unlimited if unset
+    pub max_cost_usd: Option<f64>,
+
+    /// Route to cheaper models as the budget runs out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub downgrade: Option<BudgetDowngradePolicy>,
+}
+
+impl BudgetConfig {
+    /// Whether `tokens` and `cost_usd` spent reach either limit
+    #[must_use]
+    pub fn is_exhausted(&self, tokens: u64, cost_usd: f64) -> bool {
+        self.max_tokens.is_some_and(|max| tokens >= max)
+            || self.max_cost_usd.is_some_and(|max| cost_usd >= max)
+    }
+
+    /// Share of the budget `tokens` and `cost_usd` spend, by the tighter limit
+    ///
+    /// Returns `None` when no limit is set.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn pressure(&self, tokens: u64, cost_usd: f64) -> Option<f64> {
+        let by_tokens = self.max_tokens.map(|max| tokens as f64 / max.max(1) as f64);
+        let by_cost = self
+            .max_cost_usd
+            .map(|max| if max > 0.0 { cost_usd / max } else { 1.0 });
+        match (by_tokens, by_cost) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Whether reaching a limit stops service rather than downgrading it
+    #[must_use]
+    pub fn stops_at_limit(&self) -> bool {
+        !self
+            .downgrade
+            .as_ref()
+            .is_some_and(|policy| !policy.chain.is_empty() && !policy.stop_at_limit)
+    }
+}
+
+/// Estimated spend limits checked before each message reaches the provider
+///
+/// Unlike [`BudgetConfig`], which degrades the whole bot, these limits
+/// reject only the messages whose conversation, user, or the bot as a whole
+/// has spent its allowance, with
+/// [`Error::BudgetExceeded`](crate::error::Error::BudgetExceeded). Spend is
+/// counted from when the bot starts. See
+/// [`CostGovernor`](crate::governor::CostGovernor).
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CostGovernorConfig {
+    /// Most estimated cost per conversation, in USD; unlimited if unset
+    pub per_conversation_usd: Option<f64>,
+
+    /// Most estimated cost per user, in USD; unlimited if unset
+    pub per_user_usd: Option<f64>,
+
+    /// Most estimated cost across all messages, in USD; unlimited if unset
+    pub global_usd: Option<f64>,
+
+    /// Shares of a limit at which spend alerts fire
+    pub alert_thresholds: Vec<f64>,
+}
+
+impl Default for CostGovernorConfig {
+    fn default() -> Self {
+        Self {
+            per_conversation_usd: None,
+            per_user_usd: None,
+            global_usd: None,
+            alert_thresholds: vec![0.8, 1.0],
+        }
+    }
+}
+
+impl CostGovernorConfig {
+    /// Whether any limit is set
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.per_conversation_usd.is_some()
+            || self.per_user_usd.is_some()
+            || self.global_usd.is_some()
+    }
+}
+
+/// Cheaper models to route to as the budget runs out
+///
+/// Each threshold crossed moves routing one step down `chain`, e.g. from
+/// Opus to Sonnet at 80% of the budget and to Haiku at 95%. Models outside
+/// the chain are never changed, and models are never upgraded.
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BudgetDowngradePolicy {
+    /// Models from most to least expensive
+    pub chain: Vec<String>,
+
+    /// Shares of the budget at which routing moves one more step down the chain
+    pub thresholds: Vec<f64>,
+
+    /// Degrade service once a limit is reached instead of serving on the cheapest model
+    pub stop_at_limit: bool,
+}
+
+impl Default for BudgetDowngradePolicy {
+    fn default() -> Self {
+        Self {
+            chain: Vec::new(),
+            thresholds: vec![0.8, 0.95],
+            stop_at_limit: false,
+        }
+    }
+}
+
+impl BudgetDowngradePolicy {
+    /// Downgrade along `chain`, most expensive first, at the default thresholds
+    #[must_use]
+    pub fn new<I, S>(chain: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            chain: chain.into_iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Move down the chain at `thresholds` instead
+    #[must_use]
+    pub fn with_thresholds(mut self, thresholds: Vec<f64>) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
+
+    /// The model to use instead of `model` at `pressure`, if it is downgraded
+    #[must_use]
+    pub fn downgrade(&self, model: &str, pressure: f64) -> Option<&str> {
+        let steps = self
+            .thresholds
+            .iter()
+            .filter(|threshold| pressure >= **threshold)
+            .count();
+        let position = self.chain.iter().position(|m| m == model)?;
+        let target = (position + steps).min(self.chain.len() - 1);
+        (target != position).then(|| self.chain[target].as_str())
+    }
+}
+
+/// What to do with user input longer than [`InputOverflowConfig::max_chars`]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Fail message validation
+    #[default]
+    Reject,
+    /// Keep the beginning and append a notice saying how much was cut
+    Truncate,
+    /// Add the leading parts to the context as separate user turns and answer the last
+    Split,
+    /// Keep the beginning and add a summary of the rest to the context
+    Summarize,
+}
+
+/// Configuration for overlong user input
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputOverflowConfig {
+    /// Longest input handled as is, in characters; capped at
+    /// [`MAX_CONTENT_CHARS`](crate::message::MAX_CONTENT_CHARS)
+    pub max_chars: usize,
+
+    /// Policy for tenants without an override
+    pub default_policy: OverflowPolicy,
+
+    /// Per-tenant overrides, keyed by tenant ID
+    pub tenant_policies: HashMap<String, OverflowPolicy>,
+}
+
+impl Default for InputOverflowConfig {
+    fn default() -> Self {
+        Self {
+            max_chars: crate::message::MAX_CONTENT_CHARS,
+            default_policy: OverflowPolicy::default(),
+            tenant_policies: HashMap::new(),
+        }
+    }
+}
+
+impl InputOverflowConfig {
+    /// The policy configured for a tenant
+    #[must_use]
+    pub fn policy_for(&self, tenant: Option<&str>) -> OverflowPolicy {
+        tenant
+            .and_then(|tenant| self.tenant_policies.get(tenant))
+            .copied()
+            .unwrap_or(self.default_policy)
+    }
+}
+
 /// Configuration for plugins
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PluginConfig {
     /// Enable plugin system
     pub enable_plugins: bool,
@@ -210,8 +1526,16 @@ pub struct PluginConfig {
     /// Plugins to auto-load
     pub auto_load: Vec<String>,
 
+    /// Register the built-in calculator and unit converter tools
+    pub builtin_tools: bool,
+
+    /// Reject plugin responses that do not match the schema the plugin
+    /// declares for the request type
+    pub strict_responses: bool,
+
     /// Plugin timeout
     #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
     pub plugin_timeout: Duration,
 }
 
@@ -221,6 +1545,8 @@ impl Default for PluginConfig {
             enable_plugins: true,
             plugin_dirs: vec!["plugins".to_string()],
             auto_load: Vec::new(),
+            builtin_tools: true,
+            strict_responses: false,
             plugin_timeout: Duration::from_secs(5),
         }
     }
@@ -230,6 +1556,7 @@ impl Default for PluginConfig {
 #[derive(Default)]
 pub struct BotConfigBuilder {
     model: Option<String>,
+    system_prompt: Option<String>,
     temperature: Option<f32>,
     max_tokens: Option<usize>,
     timeout: Option<Duration>,
@@ -239,9 +1566,28 @@ pub struct BotConfigBuilder {
     context_config: Option<ContextConfig>,
     pipeline_config: Option<PipelineConfig>,
     plugin_config: Option<PluginConfig>,
+    profile: Option<ConfigProfile>,
+    degraded_mode: Option<DegradedModeConfig>,
+    model_selection: Option<ModelSelectionConfig>,
+    provisioned_throughput: Option<ProvisionedThroughputConfig>,
+    telemetry: Option<TelemetryConfig>,
+    budget: Option<BudgetConfig>,
+    cost_governor: Option<CostGovernorConfig>,
+    input_overflow: Option<InputOverflowConfig>,
+    slo: Option<SloConfig>,
+    load_shedding: Option<LoadSheddingConfig>,
+    expiry_notifications: Option<ExpiryNotificationConfig>,
+    sessions: Option<SessionConfig>,
 }
 
 impl BotConfigBuilder {
+    /// Start from a preset; explicitly set fields still take precedence
+    #[must_use]
+    pub fn profile(mut self, profile: ConfigProfile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
     /// Set the AI model
     #[must_use]
     pub fn model(mut self, model: impl Into<String>) -> Self {
@@ -249,6 +1595,13 @@ impl BotConfigBuilder {
         self
     }
 
+    /// Set the system prompt
+    #[must_use]
+    pub fn system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.system_prompt = Some(prompt.into());
+        self
+    }
+
     /// Set the temperature
     #[must_use]
     pub fn temperature(mut self, temperature: f32) -> Self {
@@ -312,23 +1665,126 @@ impl BotConfigBuilder {
         self
     }
 
+    /// Set the degraded-mode policy
+    #[must_use]
+    pub fn degraded_mode(mut self, config: DegradedModeConfig) -> Self {
+        self.degraded_mode = Some(config);
+        self
+    }
+
+    /// Set the model selection policy
+    #[must_use]
+    pub fn model_selection(mut self, config: ModelSelectionConfig) -> Self {
+        self.model_selection = Some(config);
+        self
+    }
+
+    /// Set the provisioned throughput commitments
+    #[must_use]
+    pub fn provisioned_throughput(mut self, config: ProvisionedThroughputConfig) -> Self {
+        self.provisioned_throughput = Some(config);
+        self
+    }
+
+    /// Set the usage telemetry policy
+    #[must_use]
+    pub fn telemetry(mut self, config: TelemetryConfig) -> Self {
+        self.telemetry = Some(config);
+        self
+    }
+
+    /// Set the usage limits
+    #[must_use]
+    pub fn budget(mut self, config: BudgetConfig) -> Self {
+        self.budget = Some(config);
+        self
+    }
+
+    /// Set the spend limits enforced by the cost governor
+    #[must_use]
+    pub fn cost_governor(mut self, config: CostGovernorConfig) -> Self {
+        self.cost_governor = Some(config);
+        self
+    }
+
+    /// Set the handling of overlong user input
+    #[must_use]
+    pub fn input_overflow(mut self, config: InputOverflowConfig) -> Self {
+        self.input_overflow = Some(config);
+        self
+    }
+
+    /// Set the latency objectives
+    #[must_use]
+    pub fn slo(mut self, config: SloConfig) -> Self {
+        self.slo = Some(config);
+        self
+    }
+
+    /// Set the load-shedding thresholds
+    #[must_use]
+    pub fn load_shedding(mut self, config: LoadSheddingConfig) -> Self {
+        self.load_shedding = Some(config);
+        self
+    }
+
+    /// Set when users are told their conversations are about to expire
+    #[must_use]
+    pub fn expiry_notifications(mut self, config: ExpiryNotificationConfig) -> Self {
+        self.expiry_notifications = Some(config);
+        self
+    }
+
+    /// Set when sessions are closed and summarized
+    #[must_use]
+    pub fn sessions(mut self, config: SessionConfig) -> Self {
+        self.sessions = Some(config);
+        self
+    }
+
     /// Build the configuration
     ///
     /// # Errors
     ///
     /// Returns an error if required fields are missing or validation fails.
     pub fn build(self) -> Result<BotConfig> {
+        let model = match (self.model, self.profile) {
+            (Some(model), _) => model,
+            (None, Some(profile)) => profile.config().model,
+            (None, None) => return Err(Error::Configuration("model is required".into()).into()),
+        };
+        let base = self.profile.unwrap_or_default().config();
+
         let config = BotConfig {
-            model: self.model.context("model is required")?,
-            temperature: self.temperature.unwrap_or(0.1),
-            max_tokens: self.max_tokens.unwrap_or(2048),
-            timeout: self.timeout.unwrap_or(Duration::from_secs(30)),
-            max_retries: self.max_retries.unwrap_or(3),
-            enable_logging: self.enable_logging.unwrap_or(true),
-            enable_cost_tracking: self.enable_cost_tracking.unwrap_or(true),
-            context_config: self.context_config.unwrap_or_default(),
-            pipeline_config: self.pipeline_config.unwrap_or_default(),
-            plugin_config: self.plugin_config.unwrap_or_default(),
+            model,
+            system_prompt: self.system_prompt.or(base.system_prompt),
+            temperature: self.temperature.unwrap_or(base.temperature),
+            max_tokens: self.max_tokens.unwrap_or(base.max_tokens),
+            timeout: self.timeout.unwrap_or(base.timeout),
+            max_retries: self.max_retries.unwrap_or(base.max_retries),
+            enable_logging: self.enable_logging.unwrap_or(base.enable_logging),
+            enable_cost_tracking: self
+                .enable_cost_tracking
+                .unwrap_or(base.enable_cost_tracking),
+            context_config: self.context_config.unwrap_or(base.context_config),
+            pipeline_config: self.pipeline_config.unwrap_or(base.pipeline_config),
+            plugin_config: self.plugin_config.unwrap_or(base.plugin_config),
+            profile: base.profile,
+            degraded_mode: self.degraded_mode.unwrap_or(base.degraded_mode),
+            model_selection: self.model_selection.unwrap_or(base.model_selection),
+            provisioned_throughput: self
+                .provisioned_throughput
+                .unwrap_or(base.provisioned_throughput),
+            telemetry: self.telemetry.unwrap_or(base.telemetry),
+            budget: self.budget.unwrap_or(base.budget),
+            cost_governor: self.cost_governor.unwrap_or(base.cost_governor),
+            input_overflow: self.input_overflow.unwrap_or(base.input_overflow),
+            slo: self.slo.unwrap_or(base.slo),
+            load_shedding: self.load_shedding.unwrap_or(base.load_shedding),
+            expiry_notifications: self
+                .expiry_notifications
+                .unwrap_or(base.expiry_notifications),
+            sessions: self.sessions.unwrap_or(base.sessions),
         };
 
         config.validate()?;
@@ -336,6 +1792,47 @@ impl BotConfigBuilder {
     }
 }
 
+/// Recursively overlay `overrides` onto `base`, replacing non-object values
+pub(crate) fn merge_json(base: &mut serde_json::Value, overrides: serde_json::Value) {
+    match (base, overrides) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Whether `model` is a Bedrock provisioned, custom, or imported model ARN
+///
+/// These are account-specific, so they are accepted without being listed.
+#[must_use]
+pub fn is_model_arn(model: &str) -> bool {
+    const RESOURCE_TYPES: &[&str] = &["provisioned-model", "custom-model", "imported-model"];
+
+    let parts: Vec<&str> = model.splitn(6, ':').collect();
+    let [arn, partition, service, region, account, resource] = parts[..] else {
+        return false;
+    };
+    let Some((resource_type, id)) = resource.split_once('/') else {
+        return false;
+    };
+    arn == "arn"
+        && partition.starts_with("aws")
+        && service == "bedrock"
+        && !region.is_empty()
+        && account.len() == 12
+        && account.bytes().all(|b| b.is_ascii_digit())
+        && RESOURCE_TYPES.contains(&resource_type)
+        && !id.is_empty()
+}
+
 /// Validate model name
 fn validate_model(model: &str) -> Result<(), ValidationError> {
     const ALLOWED_MODELS: &[&str] = &[
@@ -350,7 +1847,7 @@ fn validate_model(model: &str) -> Result<(), ValidationError> {
         "ai21.j2-mid",
     ];
 
-    if ALLOWED_MODELS.contains(&model) {
+    if ALLOWED_MODELS.contains(&model) || is_model_arn(model) {
         Ok(())
     } else {
         Err(ValidationError::new("invalid_model"))
@@ -408,6 +1905,102 @@ mod tests {
         assert!(config.validate().is_err());
     }
 
+    #[test]
+    fn test_fingerprint_tracks_model_and_prompt() {
+        let base = BotConfig::default();
+        assert_eq!(base.fingerprint(), BotConfig::default().fingerprint());
+
+        let other_model = BotConfig {
+            model: "anthropic.claude-haiku".to_string(),
+            ..Default::default()
+        };
+        assert_ne!(base.fingerprint(), other_model.fingerprint());
+
+        let other_prompt = BotConfig {
+            system_prompt: Some("Be terse".to_string()),
+            ..Default::default()
+        };
+        assert_ne!(base.fingerprint(), other_prompt.fingerprint());
+
+        let other_temperature = BotConfig {
+            temperature: 0.9,
+            ..Default::default()
+        };
+        assert_eq!(base.fingerprint(), other_temperature.fingerprint());
+    }
+
+    #[test]
+    fn test_config_round_trip() {
+        let config = BotConfig::default();
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["timeout"], "30s");
+
+        let back: BotConfig = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(back.timeout, config.timeout);
+        assert_eq!(serde_json::to_value(&back).unwrap(), json);
+    }
+
+    #[test]
+    fn test_presets_are_valid() {
+        for profile in [
+            ConfigProfile::Balanced,
+            ConfigProfile::LowLatency,
+            ConfigProfile::HighQuality,
+            ConfigProfile::CostSaver,
+        ] {
+            let config = profile.config();
+            assert_eq!(config.profile, profile);
+            assert!(config.validate().is_ok(), "{profile:?}");
+        }
+
+        let fast = BotConfig::low_latency();
+        assert!(!fast
+            .pipeline_config
+            .enabled_stages
+            .contains(&"enrich".to_string()));
+        assert!(fast
+            .pipeline_config
+            .enabled_stages
+            .contains(&"process".to_string()));
+        assert!(BotConfig::cost_saver().max_tokens < BotConfig::high_quality().max_tokens);
+    }
+
+    #[test]
+    fn test_builder_profile_defaults() {
+        let config = BotConfig::builder()
+            .profile(ConfigProfile::CostSaver)
+            .max_tokens(256)
+            .build()
+            .unwrap();
+
+        assert_eq!(config.model, "anthropic.claude-haiku");
+        assert_eq!(config.max_tokens, 256);
+        assert_eq!(config.context_config.max_context_tokens, 1024);
+        assert!(BotConfig::builder().build().is_err());
+    }
+
+    #[test]
+    fn test_from_value_layers_over_profile() {
+        let config = BotConfig::from_value(serde_json::json!({
+            "profile": "low_latency",
+            "temperature": 0.4,
+            "context_config": { "persist_context": true }
+        }))
+        .unwrap();
+
+        assert_eq!(config.profile, ConfigProfile::LowLatency);
+        assert_eq!(config.model, "anthropic.claude-haiku");
+        assert!((config.temperature - 0.4).abs() < f32::EPSILON);
+        assert!(config.context_config.persist_context);
+        assert_eq!(config.context_config.max_context_tokens, 2048);
+
+        assert!(BotConfig::from_value(serde_json::json!({ "profile": "turbo" })).is_err());
+        assert_eq!(
+            "cost-saver".parse::<ConfigProfile>().unwrap(),
+            ConfigProfile::CostSaver
+        );
+    }
+
     #[test]
     fn test_from_env() {
         std::env::set_var("DEFAULT_MODEL", "anthropic.claude-opus-4-1");
@@ -422,6 +2015,51 @@ mod tests {
         assert_eq!(config.max_tokens, 4096);
     }
 
+    #[test]
+    fn test_model_arn_validation() {
+        let arn = "arn:aws:bedrock:us-east-1:123456789012:provisioned-model/abc123";
+        assert!(is_model_arn(arn));
+        assert!(is_model_arn(
+            "arn:aws-us-gov:bedrock:us-gov-west-1:123456789012:custom-model/base/xyz"
+        ));
+        assert!(!is_model_arn(
+            "arn:aws:bedrock:us-east-1:123456789012:foundation-model/anthropic.claude-haiku"
+        ));
+        assert!(!is_model_arn(
+            "arn:aws:s3:us-east-1:123456789012:provisioned-model/abc"
+        ));
+        assert!(!is_model_arn(
+            "arn:aws:bedrock:us-east-1:1234:provisioned-model/abc"
+        ));
+        assert!(!is_model_arn("anthropic.claude-haiku"));
+
+        let config = BotConfig::builder().model(arn).build().unwrap();
+        assert_eq!(config.model, arn);
+        assert!(config.model_selection.allows(arn));
+    }
+
+    #[test]
+    fn test_budget_downgrade_policy() {
+        let budget = BudgetConfig {
+            max_tokens: Some(1000),
+            max_cost_usd: Some(10.0),
+            downgrade: Some(BudgetDowngradePolicy::new(["opus", "sonnet", "haiku"])),
+        };
+        let pressure = budget.pressure(500, 8.5).unwrap();
+        assert!((pressure - 0.85).abs() < 1e-9);
+        assert!(BudgetConfig::default().pressure(500, 8.5).is_none());
+        assert!(!budget.stops_at_limit());
+        assert!(BudgetConfig::default().stops_at_limit());
+
+        let policy = budget.downgrade.as_ref().unwrap();
+        assert_eq!(policy.downgrade("opus", 0.5), None);
+        assert_eq!(policy.downgrade("opus", pressure), Some("sonnet"));
+        assert_eq!(policy.downgrade("opus", 0.97), Some("haiku"));
+        assert_eq!(policy.downgrade("sonnet", 1.2), Some("haiku"));
+        assert_eq!(policy.downgrade("haiku", 1.2), None);
+        assert_eq!(policy.downgrade("titan", 1.2), None);
+    }
+
     #[cfg(feature = "property-testing")]
     mod property_tests {
         use super::*;
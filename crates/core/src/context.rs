@@ -3,24 +3,34 @@
 //! This module provides context tracking and management for maintaining
 //! conversation state across multiple interactions.
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use dashmap::DashMap;
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, instrument};
+use tracing::{debug, instrument, warn};
 use uuid::Uuid;
+use validator::Validate;
 
 use crate::{
-    config::{ContextConfig, StorageBackend},
+    compression::{CompressionStats, PayloadCodec},
+    config::{ContextConfig, StaleContextPolicy, StorageBackend},
+    degraded::TENANT_METADATA_KEY,
+    encryption::{ContextEncryption, KeyProvider},
     error::Error,
-    message::{Message, Response},
+    journal::{ContextEventRecord, ContextEventStore, ContextJournal, MemoryEventStore},
+    message::{Content, Message, Response},
+    transcript::Transcript,
 };
 
+/// Keys fetched per store page when scanning for expiring contexts
+const EXPIRY_SCAN_PAGE_SIZE: usize = 100;
+
 /// Conversation context containing state and history
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Context {
@@ -41,6 +51,10 @@ pub struct Context {
 
     /// Token count for the context
     pub token_count: usize,
+
+    /// Snapshots available for rollback, oldest first
+    #[serde(default)]
+    pub checkpoints: Vec<Checkpoint>,
 }
 
 impl Context {
@@ -54,11 +68,20 @@ impl Context {
             variables: HashMap::new(),
             metadata: ContextMetadata::new(),
             token_count: 0,
+            checkpoints: Vec::new(),
         }
     }
 
     /// Add a message to the history
+    ///
+    /// The first tenant ID seen in message metadata is recorded as the
+    /// context's [`tenant`](Self::tenant).
     pub fn add_message(&mut self, message: &Message) {
+        if let Some(tenant) = message.metadata.get(TENANT_METADATA_KEY) {
+            self.variables
+                .entry(TENANT_METADATA_KEY.to_string())
+                .or_insert_with(|| tenant.clone());
+        }
         let context_msg = ContextMessage::from_message(message);
         self.token_count += context_msg.estimated_tokens();
         self.history.push_back(context_msg);
@@ -77,18 +100,110 @@ impl Context {
         if let Some(usage) = &response.usage {
             self.metadata.total_tokens += usage.total_tokens;
             self.metadata.total_cost += usage.estimated_cost;
+            self.metadata.usage.push(TurnUsage {
+                response_id: response.id,
+                timestamp: response.timestamp,
+                input_tokens: usage.input_tokens,
+                output_tokens: usage.output_tokens,
+                cost_usd: usage.estimated_cost,
+                model: usage.model.clone(),
+            });
         }
     }
 
     /// Trim history to fit within token limit
+    ///
+    /// The oldest unpinned messages are dropped first; pinned messages are
+    /// never trimmed.
     pub fn trim_to_token_limit(&mut self, max_tokens: usize) {
-        while self.token_count > max_tokens && !self.history.is_empty() {
-            if let Some(removed) = self.history.pop_front() {
+        while self.token_count > max_tokens {
+            let Some(index) = self.history.iter().position(|message| !message.pinned) else {
+                break;
+            };
+            if let Some(removed) = self.history.remove(index) {
                 self.token_count = self.token_count.saturating_sub(removed.estimated_tokens());
             }
         }
     }
 
+    /// Pin a history message so it is never trimmed and leads the prompt
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if no message has `message_id`, or
+    /// [`Error::InvalidInput`] if pinning it would exceed `max_pinned_tokens`.
+    pub fn pin(&mut self, message_id: Uuid, max_pinned_tokens: usize) -> Result<()> {
+        let pinned_tokens = self.pinned_tokens();
+        let message = self
+            .history
+            .iter_mut()
+            .find(|message| message.message_id == Some(message_id))
+            .ok_or_else(|| Error::NotFound(format!("Message {message_id}")))?;
+        if message.pinned {
+            return Ok(());
+        }
+        let tokens = message.estimated_tokens();
+        if pinned_tokens + tokens > max_pinned_tokens {
+            return Err(Error::InvalidInput(format!(
+                "Pinning message {message_id} needs {tokens} tokens but only {} of {max_pinned_tokens} remain",
+                max_pinned_tokens.saturating_sub(pinned_tokens)
+            ))
+            .into());
+        }
+        message.pinned = true;
+        Ok(())
+    }
+
+    /// Unpin a message, returning whether it was pinned
+    pub fn unpin(&mut self, message_id: Uuid) -> bool {
+        self.history
+            .iter_mut()
+            .find(|message| message.message_id == Some(message_id) && message.pinned)
+            .is_some_and(|message| {
+                message.pinned = false;
+                true
+            })
+    }
+
+    /// Pinned messages, oldest first
+    pub fn pinned(&self) -> impl Iterator<Item = &ContextMessage> {
+        self.history.iter().filter(|message| message.pinned)
+    }
+
+    /// Estimated tokens held by pinned messages
+    #[must_use]
+    pub fn pinned_tokens(&self) -> usize {
+        self.pinned().map(ContextMessage::estimated_tokens).sum()
+    }
+
+    /// History in prompt order: system messages, then pinned messages, then the rest
+    ///
+    /// Each group keeps its chronological order. Provider integrations should
+    /// build prompts from this rather than from [`history`](Self::history).
+    #[must_use]
+    pub fn prompt_history(&self) -> Vec<&ContextMessage> {
+        let system = self
+            .history
+            .iter()
+            .filter(|message| message.role == MessageRole::System);
+        let pinned = self
+            .pinned()
+            .filter(|message| message.role != MessageRole::System);
+        let rest = self
+            .history
+            .iter()
+            .filter(|message| message.role != MessageRole::System && !message.pinned);
+        system.chain(pinned).chain(rest).collect()
+    }
+
+    /// Tenant the conversation belongs to, which selects its encryption key
+    #[must_use]
+    pub fn tenant(&self) -> Option<&str> {
+        self.variables
+            .get(TENANT_METADATA_KEY)
+            .and_then(serde_json::Value::as_str)
+    }
+
     /// Get a variable value
     pub fn get_variable(&self, key: &str) -> Option<&serde_json::Value> {
         self.variables.get(key)
@@ -106,6 +221,105 @@ impl Context {
         self.metadata.message_count = 0;
     }
 
+    /// Snapshot the history and variables under a name
+    ///
+    /// At most [`MAX_CHECKPOINTS`] are kept; the oldest is dropped when the
+    /// limit is exceeded.
+    pub fn checkpoint(&mut self, name: impl Into<String>) -> Uuid {
+        let checkpoint = Checkpoint {
+            id: Uuid::new_v4(),
+            name: name.into(),
+            created_at: Utc::now(),
+            history: self.history.clone(),
+            variables: self.variables.clone(),
+            token_count: self.token_count,
+            message_count: self.metadata.message_count,
+        };
+        let id = checkpoint.id;
+
+        self.checkpoints.push(checkpoint);
+        if self.checkpoints.len() > MAX_CHECKPOINTS {
+            self.checkpoints.remove(0);
+        }
+        id
+    }
+
+    /// Restore the state captured by a checkpoint
+    ///
+    /// The checkpoint itself is kept so it can be rolled back to again;
+    /// checkpoints taken after it are discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no checkpoint has the given ID.
+    pub fn rollback(&mut self, checkpoint_id: Uuid) -> Result<()> {
+        let index = self
+            .checkpoints
+            .iter()
+            .position(|checkpoint| checkpoint.id == checkpoint_id)
+            .ok_or_else(|| Error::NotFound(format!("Checkpoint {checkpoint_id}")))?;
+
+        self.checkpoints.truncate(index + 1);
+        let checkpoint = &self.checkpoints[index];
+        self.history.clone_from(&checkpoint.history);
+        self.variables.clone_from(&checkpoint.variables);
+        self.token_count = checkpoint.token_count;
+        self.metadata.message_count = checkpoint.message_count;
+        self.metadata.last_activity = Utc::now();
+        Ok(())
+    }
+
+    /// Find the most recent checkpoint with the given name
+    #[must_use]
+    pub fn find_checkpoint(&self, name: &str) -> Option<&Checkpoint> {
+        self.checkpoints
+            .iter()
+            .rev()
+            .find(|checkpoint| checkpoint.name == name)
+    }
+
+    /// Append the messages `current` gained since its count was `seen`
+    ///
+    /// For a copy rewritten across an await, such as a migration or session
+    /// wrap-up, so turns recorded on the shared context meanwhile, and the
+    /// usage they reported, are not lost when the copy replaces it.
+    pub(crate) fn keep_messages_since(&mut self, current: &Self, seen: usize) {
+        let added = current.metadata.message_count.saturating_sub(seen);
+        let skip = current.history.len().saturating_sub(added);
+        for message in current.history.iter().skip(skip) {
+            self.token_count += message.estimated_tokens();
+            self.history.push_back(message.clone());
+        }
+        self.metadata.message_count += added;
+        self.metadata.last_activity = self
+            .metadata
+            .last_activity
+            .max(current.metadata.last_activity);
+        self.metadata.total_tokens = current.metadata.total_tokens;
+        self.metadata.total_cost = current.metadata.total_cost;
+        self.metadata.usage.clone_from(&current.metadata.usage);
+    }
+
+    /// Remove the last user message and everything after it
+    ///
+    /// Returns `false` if the history holds no user message.
+    pub fn undo_last_exchange(&mut self) -> bool {
+        let Some(index) = self
+            .history
+            .iter()
+            .rposition(|message| message.role == MessageRole::User)
+        else {
+            return false;
+        };
+
+        for removed in self.history.drain(index..) {
+            self.token_count = self.token_count.saturating_sub(removed.estimated_tokens());
+            self.metadata.message_count = self.metadata.message_count.saturating_sub(1);
+        }
+        self.metadata.last_activity = Utc::now();
+        true
+    }
+
     /// Get the age of the context
     #[must_use]
     pub fn age(&self) -> Duration {
@@ -115,10 +329,27 @@ impl Context {
             .unwrap_or(Duration::ZERO)
     }
 
+    /// When the context expires, counting `ttl` from creation or the last
+    /// [renewal](ContextManager::renew); `None` if that is out of range
+    #[must_use]
+    pub fn expires_at(&self, ttl: Duration) -> Option<DateTime<Utc>> {
+        let since = self.metadata.renewed_at.unwrap_or(self.metadata.created_at);
+        since.checked_add_signed(chrono::Duration::from_std(ttl).ok()?)
+    }
+
     /// Check if the context is expired
     #[must_use]
     pub fn is_expired(&self, ttl: Duration) -> bool {
-        self.age() > ttl
+        self.expires_at(ttl).is_some_and(|at| Utc::now() > at)
+    }
+
+    /// Check whether the context was built under a different configuration
+    ///
+    /// Contexts without history are never stale; contexts predating
+    /// fingerprinting are treated as stale once they have history.
+    #[must_use]
+    pub fn is_stale(&self, fingerprint: &str) -> bool {
+        !self.history.is_empty() && self.metadata.config_hash.as_deref() != Some(fingerprint)
     }
 
     /// Get a summary of the context
@@ -134,17 +365,42 @@ impl Context {
     }
 }
 
-/// A message in the context history
+/// Maximum number of checkpoints kept per context
+pub const MAX_CHECKPOINTS: usize = 16;
+
+/// Named snapshot of a context's history and variables
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Checkpoint ID
+    pub id: Uuid,
+    /// Caller-supplied name, e.g. the agent step about to run
+    pub name: String,
+    /// When the snapshot was taken
+    pub created_at: DateTime<Utc>,
+    /// History at the time of the snapshot
+    pub history: VecDeque<ContextMessage>,
+    /// Variables at the time of the snapshot
+    pub variables: HashMap<String, serde_json::Value>,
+    /// Token count at the time of the snapshot
+    pub token_count: usize,
+    /// Message count at the time of the snapshot
+    pub message_count: usize,
+}
+
+/// A message in the context history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ContextMessage {
     /// Message role
     pub role: MessageRole,
     /// Message content
-    pub content: String,
+    pub content: Content,
     /// Timestamp
     pub timestamp: DateTime<Utc>,
     /// Optional message ID
     pub message_id: Option<Uuid>,
+    /// Kept through trimming and placed near the top of the prompt
+    #[serde(default)]
+    pub pinned: bool,
 }
 
 impl ContextMessage {
@@ -155,6 +411,7 @@ impl ContextMessage {
             content: message.content.clone(),
             timestamp: message.timestamp,
             message_id: Some(message.id),
+            pinned: false,
         }
     }
 
@@ -162,24 +419,26 @@ impl ContextMessage {
     pub fn from_response(response: &Response) -> Self {
         Self {
             role: MessageRole::Assistant,
-            content: response.content.clone(),
+            content: Content::from(&response.content),
             timestamp: response.timestamp,
             message_id: Some(response.id),
+            pinned: false,
         }
     }
 
     /// Create a system message
-    pub fn system(content: impl Into<String>) -> Self {
+    pub fn system(content: impl Into<Content>) -> Self {
         Self {
             role: MessageRole::System,
             content: content.into(),
             timestamp: Utc::now(),
             message_id: None,
+            pinned: false,
         }
     }
 
     /// Estimate token count (rough approximation)
-    const fn estimated_tokens(&self) -> usize {
+    pub(crate) fn estimated_tokens(&self) -> usize {
         // Rough estimate: 1 token per 4 characters
         self.content.len() / 4
     }
@@ -208,6 +467,12 @@ pub struct UserContext {
     pub preferences: HashMap<String, serde_json::Value>,
     /// User attributes
     pub attributes: HashMap<String, String>,
+    /// BCP 47 language tag, e.g. `de-DE`
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// IANA time zone name, e.g. `Europe/Berlin`
+    #[serde(default)]
+    pub timezone: Option<String>,
 }
 
 /// Context metadata
@@ -225,6 +490,18 @@ pub struct ContextMetadata {
     pub total_cost: f64,
     /// Custom tags
     pub tags: Vec<String>,
+    /// Fingerprint of the bot configuration the context was last used with
+    #[serde(default)]
+    pub config_hash: Option<String>,
+    /// When the user last chose to keep the conversation past its expiry
+    #[serde(default)]
+    pub renewed_at: Option<DateTime<Utc>>,
+    /// Sequence number of the last event-log entry this state reflects
+    #[serde(default)]
+    pub event_seq: u64,
+    /// Usage of each response, oldest first, kept when history is trimmed
+    #[serde(default)]
+    pub usage: Vec<TurnUsage>,
 }
 
 impl ContextMetadata {
@@ -237,7 +514,121 @@ impl ContextMetadata {
             total_tokens: 0,
             total_cost: 0.0,
             tags: Vec::new(),
+            config_hash: None,
+            renewed_at: None,
+            event_seq: 0,
+            usage: Vec::new(),
+        }
+    }
+}
+
+/// Token usage and cost of one response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TurnUsage {
+    /// The response
+    pub response_id: Uuid,
+    /// When the response was produced
+    pub timestamp: DateTime<Utc>,
+    /// Input tokens billed
+    pub input_tokens: usize,
+    /// Output tokens generated
+    pub output_tokens: usize,
+    /// Estimated cost in USD
+    pub cost_usd: f64,
+    /// Model that produced the response
+    pub model: String,
+}
+
+/// Hook run on contexts whose configuration fingerprint is out of date
+///
+/// Implementations can annotate, summarize, or reset the history so that
+/// assistant turns produced by a previous model or system prompt don't
+/// confuse the current one.
+#[async_trait::async_trait]
+pub trait ContextMigration: Send + Sync {
+    /// Migrate a stale context to the current fingerprint
+    async fn migrate(&self, context: &mut Context, fingerprint: &str) -> Result<()>;
+}
+
+/// Built-in migration driven by [`StaleContextPolicy`]
+pub struct PolicyMigration {
+    policy: StaleContextPolicy,
+}
+
+impl PolicyMigration {
+    /// Create a migration for the given policy
+    #[must_use]
+    pub const fn new(policy: StaleContextPolicy) -> Self {
+        Self { policy }
+    }
+}
+
+#[async_trait::async_trait]
+impl ContextMigration for PolicyMigration {
+    async fn migrate(&self, context: &mut Context, _fingerprint: &str) -> Result<()> {
+        match self.policy {
+            StaleContextPolicy::Ignore => {}
+            StaleContextPolicy::Annotate => {
+                let note = ContextMessage::system(
+                    "The assistant configuration has changed since this conversation started; \
+                     earlier assistant replies may not reflect current behavior.",
+                );
+                context.token_count += note.estimated_tokens();
+                context.history.push_back(note);
+            }
+            StaleContextPolicy::Reset => context.clear_history(),
+        }
+        Ok(())
+    }
+}
+
+/// Migration that replaces stale history with a model-written summary
+///
+/// The summary is kept as a single system message so the new configuration
+/// keeps the gist of the conversation without the old assistant turns.
+pub struct SummarizingMigration {
+    complete: crate::completion::CompletionFn,
+}
+
+impl SummarizingMigration {
+    /// Create a migration backed by the given completion function
+    #[must_use]
+    pub fn new(complete: crate::completion::CompletionFn) -> Self {
+        Self { complete }
+    }
+
+    fn summary_prompt(context: &Context) -> String {
+        let mut prompt = String::from(
+            "Summarize the following conversation in a few sentences, keeping any facts \
+             the user shared and any open questions.\n\n",
+        );
+        for message in &context.history {
+            let role = match message.role {
+                MessageRole::System => "system",
+                MessageRole::User => "user",
+                MessageRole::Assistant => "assistant",
+            };
+            prompt.push_str(role);
+            prompt.push_str(": ");
+            prompt.push_str(&message.content);
+            prompt.push('\n');
         }
+        prompt
+    }
+}
+
+#[async_trait::async_trait]
+impl ContextMigration for SummarizingMigration {
+    async fn migrate(&self, context: &mut Context, _fingerprint: &str) -> Result<()> {
+        let summary = (self.complete)(Self::summary_prompt(context)).await?;
+        let note = ContextMessage::system(format!(
+            "Summary of the conversation so far: {}",
+            summary.trim()
+        ));
+        context.clear_history();
+        context.token_count = note.estimated_tokens();
+        context.history.push_back(note);
+        Ok(())
     }
 }
 
@@ -246,6 +637,13 @@ pub struct ContextManager {
     config: ContextConfig,
     store: Arc<dyn ContextStore>,
     cache: Arc<DashMap<String, Arc<RwLock<Context>>>>,
+    fingerprint: Option<String>,
+    migration: Arc<dyn ContextMigration>,
+    /// Held while a context is migrated, so each is migrated once
+    migrations: DashMap<String, Arc<tokio::sync::Mutex<()>>>,
+    journal: Option<ContextJournal>,
+    /// Read-only store for reporting reads, see [`ContextConfig::read_replica`]
+    replica: Option<Arc<dyn ContextStore>>,
 }
 
 impl ContextManager {
@@ -253,34 +651,174 @@ impl ContextManager {
     ///
     /// # Errors
     ///
-    /// Returns an error if store initialization fails.
+    /// Returns an error if store initialization fails, or if encryption is
+    /// enabled without the `kms` feature; use
+    /// [`with_key_provider`](Self::with_key_provider) to supply keys otherwise.
     #[instrument(skip(config))]
     pub async fn new(config: ContextConfig) -> Result<Self> {
+        if !config.encryption.enabled {
+            return Self::create(config, None).await;
+        }
+        #[cfg(feature = "kms")]
+        {
+            let provider: Arc<dyn KeyProvider> =
+                Arc::new(crate::encryption::KmsKeyProvider::from_env().await);
+            Self::create(config, Some(provider)).await
+        }
+        #[cfg(not(feature = "kms"))]
+        Err(Error::Configuration(
+            "Context encryption requires the kms feature or a key provider".to_string(),
+        )
+        .into())
+    }
+
+    /// Create a context manager encrypting contexts with keys from `provider`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if store initialization fails.
+    pub async fn with_key_provider(
+        config: ContextConfig,
+        provider: Arc<dyn KeyProvider>,
+    ) -> Result<Self> {
+        Self::create(config, Some(provider)).await
+    }
+
+    async fn create(config: ContextConfig, provider: Option<Arc<dyn KeyProvider>>) -> Result<Self> {
         debug!("Creating context manager with config: {:?}", config);
 
-        let store: Arc<dyn ContextStore> = match &config.storage_backend {
-            StorageBackend::Memory => Arc::new(MemoryContextStore::new()),
-            StorageBackend::Redis { url: _ } => {
-                // Would initialize Redis store here
-                return Err(Error::new("Redis store not yet implemented").into());
-            }
-            StorageBackend::Postgres { url: _ } => {
-                // Would initialize Postgres store here
-                return Err(Error::new("Postgres store not yet implemented").into());
+        let compression = config.effective_compression();
+        compression
+            .validate()
+            .map_err(|e| Error::Configuration(format!("Invalid compression config: {e}")))?;
+        let codec = PayloadCodec::new(compression);
+        config
+            .encryption
+            .validate()
+            .map_err(|e| Error::Configuration(format!("Invalid encryption config: {e}")))?;
+        let provider = provider.filter(|_| config.encryption.enabled);
+        let encryption = || {
+            provider
+                .clone()
+                .map(|provider| ContextEncryption::new(config.encryption.clone(), provider))
+        };
+
+        let store = open_store(&config.storage_backend, codec, encryption()).await?;
+        let replica = match &config.read_replica {
+            Some(backend) => {
+                Some(open_replica(backend, PayloadCodec::new(compression), encryption()).await?)
             }
-            StorageBackend::Sqlite { path: _ } => {
-                // Would initialize SQLite store here
-                return Err(Error::new("SQLite store not yet implemented").into());
+            None => None,
+        };
+
+        let journal = match &config.storage_backend {
+            _ if !config.event_log.enabled => None,
+            StorageBackend::Memory => Some(ContextJournal::new(
+                Arc::new(MemoryEventStore::default()),
+                config.event_log.snapshot_interval,
+            )),
+            _ => {
+                warn!(
+                    "No built-in event store for the configured backend; \
+                     persisting full contexts until one is set with with_event_store"
+                );
+                None
             }
         };
 
+        let migration = Arc::new(PolicyMigration::new(config.stale_context_policy));
         Ok(Self {
             config,
             store,
             cache: Arc::new(DashMap::new()),
+            fingerprint: None,
+            migration,
+            migrations: DashMap::new(),
+            journal,
+            replica,
         })
     }
 
+    /// Persist changes to `events` as an append-only log
+    ///
+    /// Enables event-sourced persistence even if
+    /// [`EventLogConfig::enabled`](crate::config::EventLogConfig::enabled) is
+    /// off; snapshots are still written to the context store. See
+    /// [`crate::journal`].
+    #[must_use]
+    pub fn with_event_store(mut self, events: Arc<dyn ContextEventStore>) -> Self {
+        self.journal = Some(ContextJournal::new(
+            events,
+            self.config.event_log.snapshot_interval,
+        ));
+        self
+    }
+
+    /// Serve listing, summaries, expiry scans, and exports from `replica`
+    ///
+    /// The replica is only read; it must be kept in sync with the primary
+    /// store outside the bot. See [`ContextConfig::read_replica`].
+    #[must_use]
+    pub fn with_read_replica(mut self, replica: Arc<dyn ContextStore>) -> Self {
+        self.replica = Some(replica);
+        self
+    }
+
+    /// The store for reporting reads: the read replica, else the primary
+    fn reporting_store(&self) -> &Arc<dyn ContextStore> {
+        self.replica.as_ref().unwrap_or(&self.store)
+    }
+
+    /// Unexpired contexts for `ids`, in order, from the cache or else the reporting store
+    ///
+    /// Nothing read is cached or migrated, so reporting cannot evict or
+    /// rewrite live conversations.
+    async fn read_for_reporting(&self, ids: &[&str]) -> Result<Vec<Option<Context>>> {
+        let mut found = Vec::with_capacity(ids.len());
+        let mut misses = Vec::new();
+        for (index, id) in ids.iter().enumerate() {
+            let cached = self.cache.get(*id).map(|ctx| ctx.read().clone());
+            if cached.is_none() {
+                misses.push(index);
+            }
+            found.push(cached);
+        }
+
+        if !misses.is_empty() {
+            let keys: Vec<&str> = misses.iter().map(|&index| ids[index]).collect();
+            let loaded = self.reporting_store().get_many(&keys).await?;
+            for (index, snapshot) in misses.into_iter().zip(loaded) {
+                found[index] = match &self.journal {
+                    Some(journal) => journal.view(ids[index], snapshot).await?,
+                    None => snapshot,
+                };
+            }
+        }
+
+        let ttl = self.config.context_ttl;
+        Ok(found
+            .into_iter()
+            .map(|context| context.filter(|context| !context.is_expired(ttl)))
+            .collect())
+    }
+
+    /// Set the configuration fingerprint stamped on contexts
+    ///
+    /// Contexts loaded with a different fingerprint are passed through the
+    /// migration hook on first use.
+    #[must_use]
+    pub fn with_fingerprint(mut self, fingerprint: impl Into<String>) -> Self {
+        self.fingerprint = Some(fingerprint.into());
+        self
+    }
+
+    /// Replace the migration hook used for stale contexts
+    #[must_use]
+    pub fn with_migration(mut self, migration: Arc<dyn ContextMigration>) -> Self {
+        self.migration = migration;
+        self
+    }
+
     /// Get or create a context
     ///
     /// # Errors
@@ -298,35 +836,120 @@ impl ContextManager {
                 self.cache.remove(id);
             } else {
                 debug!("Found context {} in cache", id);
+                self.refresh_if_stale(&ctx).await?;
                 return Ok(ctx);
             }
         }
 
         // Try to load from store
-        if let Some(context) = self.store.get(id).await? {
+        if let Some(context) = self.load(id).await? {
             if !context.is_expired(self.config.context_ttl) {
                 debug!("Loaded context {} from store", id);
-                let ctx = Arc::new(RwLock::new(context));
-                self.cache.insert(id.to_string(), ctx.clone());
+                // Concurrent loads share the first one cached, so it is migrated once
+                let ctx = self
+                    .cache
+                    .entry(id.to_string())
+                    .or_insert_with(|| Arc::new(RwLock::new(context)))
+                    .clone();
+                self.refresh_if_stale(&ctx).await?;
                 return Ok(ctx);
             }
         }
 
-        // Create new context
+        // Create new context, starting a fresh event log
         debug!("Creating new context {}", id);
-        let context = Context::new(id);
+        if let Some(journal) = &self.journal {
+            journal.forget(id).await?;
+        }
+        let mut context = Context::new(id);
+        context.metadata.config_hash.clone_from(&self.fingerprint);
         let ctx = Arc::new(RwLock::new(context));
         self.cache.insert(id.to_string(), ctx.clone());
 
         // Persist if configured
         if self.config.persist_context {
             let context = ctx.read().clone();
-            self.store.set(id, context, self.config.context_ttl).await?;
+            self.persist(id, context).await?;
         }
 
         Ok(ctx)
     }
 
+    /// Load a context from the store, replaying events logged after its snapshot
+    async fn load(&self, id: &str) -> Result<Option<Context>> {
+        let snapshot = self.store.get(id).await?;
+        self.replay_onto(id, snapshot).await
+    }
+
+    async fn replay_onto(&self, id: &str, snapshot: Option<Context>) -> Result<Option<Context>> {
+        match &self.journal {
+            Some(journal) => journal.rebuild(id, snapshot).await,
+            None => Ok(snapshot),
+        }
+    }
+
+    /// Write a context to the store, or log its changes if the event log is enabled
+    async fn persist(&self, id: &str, context: Context) -> Result<()> {
+        let ttl = self.config.context_ttl;
+        match &self.journal {
+            Some(journal) => journal.persist(self.store.as_ref(), id, context, ttl).await,
+            None => self.store.set(id, context, ttl).await,
+        }
+    }
+
+    /// Run the migration hook if the context was built under another configuration
+    ///
+    /// Each context is migrated by one caller at a time; the others wait and
+    /// find it already migrated.
+    async fn refresh_if_stale(&self, ctx: &Arc<RwLock<Context>>) -> Result<()> {
+        let Some(fingerprint) = &self.fingerprint else {
+            return Ok(());
+        };
+
+        let id = {
+            let context = ctx.read();
+            if !context.is_stale(fingerprint) {
+                if context.metadata.config_hash.is_none() {
+                    drop(context);
+                    ctx.write().metadata.config_hash = Some(fingerprint.clone());
+                }
+                return Ok(());
+            }
+            context.id.clone()
+        };
+
+        let lock = self.migrations.entry(id.clone()).or_default().clone();
+        let _migrating = lock.lock().await;
+        let migrated = self.migrate(ctx, fingerprint).await;
+        self.migrations.remove(&id);
+        migrated
+    }
+
+    /// Migrate a stale context, keeping messages added while the hook runs
+    async fn migrate(&self, ctx: &Arc<RwLock<Context>>, fingerprint: &str) -> Result<()> {
+        let mut migrated = {
+            let context = ctx.read();
+            if !context.is_stale(fingerprint) {
+                return Ok(());
+            }
+            context.clone()
+        };
+        let seen = migrated.metadata.message_count;
+
+        debug!(
+            "Context {} is stale ({:?} -> {}), migrating",
+            migrated.id, migrated.metadata.config_hash, fingerprint
+        );
+        self.migration.migrate(&mut migrated, fingerprint).await?;
+
+        let mut context = ctx.write();
+        migrated.keep_messages_since(&context, seen);
+        migrated.metadata.config_hash = Some(fingerprint.to_string());
+        *context = migrated;
+        drop(context);
+        Ok(())
+    }
+
     /// Update a context
     ///
     /// # Errors
@@ -346,12 +969,110 @@ impl ContextManager {
         // Persist if configured
         if self.config.persist_context {
             let ctx = context.read().clone();
-            self.store.set(id, ctx, self.config.context_ttl).await?;
+            self.persist(id, ctx).await?;
         }
 
         Ok(())
     }
 
+    /// Checkpoint a context and persist the snapshot
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the context cannot be loaded or persisted
+    #[instrument(skip(self))]
+    pub async fn checkpoint(&self, id: &str, name: &str) -> Result<Uuid> {
+        let context = self.get_or_create(id).await?;
+        let checkpoint_id = context.write().checkpoint(name);
+        self.update(id, context).await?;
+        Ok(checkpoint_id)
+    }
+
+    /// Roll a context back to a checkpoint and persist the result
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the checkpoint does not exist or persisting fails
+    #[instrument(skip(self))]
+    pub async fn rollback(&self, id: &str, checkpoint_id: Uuid) -> Result<()> {
+        let context = self.get_or_create(id).await?;
+        context.write().rollback(checkpoint_id)?;
+        self.update(id, context).await
+    }
+
+    /// Pin a message so it survives trimming, within the pinned-token budget
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message does not exist, the budget would be
+    /// exceeded, or persisting fails
+    #[instrument(skip(self))]
+    pub async fn pin(&self, id: &str, message_id: Uuid) -> Result<()> {
+        let context = self.get_or_create(id).await?;
+        context
+            .write()
+            .pin(message_id, self.config.max_pinned_tokens)?;
+        self.update(id, context).await
+    }
+
+    /// Unpin a message, returning whether it was pinned
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if persisting fails
+    #[instrument(skip(self))]
+    pub async fn unpin(&self, id: &str, message_id: Uuid) -> Result<bool> {
+        let context = self.get_or_create(id).await?;
+        let unpinned = context.write().unpin(message_id);
+        if unpinned {
+            self.update(id, context).await?;
+        }
+        Ok(unpinned)
+    }
+
+    /// Pinned messages of a context, oldest first
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the context cannot be loaded
+    pub async fn pinned(&self, id: &str) -> Result<Vec<ContextMessage>> {
+        let context = self.get_or_create(id).await?;
+        let pinned = context.read().pinned().cloned().collect();
+        Ok(pinned)
+    }
+
+    /// Start a new conversation from a configured template
+    ///
+    /// The context gets a fresh ID and is set up as described in
+    /// [`ConversationTemplate::apply`](crate::template::ConversationTemplate::apply).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if no template has `template_id`, or an
+    /// error if the template does not fit the pinned-token budget or
+    /// persisting fails
+    #[instrument(skip(self))]
+    pub async fn create_from_template(&self, template_id: &str) -> Result<Arc<RwLock<Context>>> {
+        let template = self
+            .config
+            .templates
+            .get(template_id)
+            .ok_or_else(|| Error::NotFound(format!("Conversation template {template_id}")))?;
+
+        let id = Uuid::new_v4().to_string();
+        let mut context = Context::new(id.as_str());
+        context.metadata.config_hash.clone_from(&self.fingerprint);
+        template.apply(template_id, &mut context, self.config.max_pinned_tokens)?;
+        debug!("Creating context {} from template {}", id, template_id);
+
+        if let Some(journal) = &self.journal {
+            journal.forget(&id).await?;
+        }
+        let ctx = Arc::new(RwLock::new(context));
+        self.update(&id, ctx.clone()).await?;
+        Ok(ctx)
+    }
+
     /// Delete a context
     ///
     /// # Errors
@@ -362,53 +1083,470 @@ impl ContextManager {
         debug!("Deleting context {}", id);
         self.cache.remove(id);
         self.store.delete(id).await?;
+        if let Some(journal) = &self.journal {
+            journal.forget(id).await?;
+        }
         Ok(())
     }
 
-    /// Clear expired contexts
+    /// Fetch several existing contexts at once
+    ///
+    /// Cached contexts are served directly; the rest are loaded from the
+    /// store in a single batch. Unknown and expired IDs are omitted from the
+    /// result, and nothing is created.
     ///
     /// # Errors
     ///
-    /// Returns an error if clearing expired contexts fails
+    /// Returns an error if the store lookup or a stale-context migration fails
     #[instrument(skip(self))]
-    pub async fn clear_expired(&self) -> Result<usize> {
-        let mut removed = 0;
-        let expired_keys: Vec<String> = self
-            .cache
-            .iter()
-            .filter(|entry| entry.value().read().is_expired(self.config.context_ttl))
-            .map(|entry| entry.key().clone())
-            .collect();
-
-        for key in expired_keys {
-            self.cache.remove(&key);
-            self.store.delete(&key).await?;
-            removed += 1;
+    pub async fn get_many(&self, ids: &[&str]) -> Result<HashMap<String, Arc<RwLock<Context>>>> {
+        let mut found = HashMap::with_capacity(ids.len());
+        let mut misses = Vec::new();
+
+        for &id in ids {
+            match self.cache.get(id).map(|entry| entry.clone()) {
+                Some(ctx) if !ctx.read().is_expired(self.config.context_ttl) => {
+                    self.refresh_if_stale(&ctx).await?;
+                    found.insert(id.to_string(), ctx);
+                }
+                _ => misses.push(id),
+            }
         }
 
-        debug!("Removed {} expired contexts", removed);
-        Ok(removed)
-    }
-
-    /// Get statistics about managed contexts
-    #[must_use]
-    pub fn stats(&self) -> ContextStats {
-        let total = self.cache.len();
-        let mut total_tokens = 0;
-        let mut total_messages = 0;
+        if misses.is_empty() {
+            return Ok(found);
+        }
 
-        for entry in self.cache.iter() {
-            let ctx = entry.value().read();
-            total_tokens += ctx.token_count;
-            total_messages += ctx.metadata.message_count;
+        debug!("Loading {} contexts from store", misses.len());
+        let loaded = self.store.get_many(&misses).await?;
+        for (id, context) in misses.into_iter().zip(loaded) {
+            let Some(context) = self.replay_onto(id, context).await? else {
+                continue;
+            };
+            if context.is_expired(self.config.context_ttl) {
+                self.cache.remove(id);
+                continue;
+            }
+            let ctx = self
+                .cache
+                .entry(id.to_string())
+                .or_insert_with(|| Arc::new(RwLock::new(context)))
+                .clone();
+            self.refresh_if_stale(&ctx).await?;
+            found.insert(id.to_string(), ctx);
         }
 
-        ContextStats {
+        Ok(found)
+    }
+
+    /// Delete several contexts at once
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the deletion fails
+    #[instrument(skip(self))]
+    pub async fn delete_many(&self, ids: &[&str]) -> Result<()> {
+        debug!("Deleting {} contexts", ids.len());
+        for id in ids {
+            self.cache.remove(*id);
+        }
+        self.store.delete_many(ids).await?;
+        if let Some(journal) = &self.journal {
+            for id in ids {
+                journal.forget(id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark several contexts as active and refresh their stored TTL
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store update fails
+    #[instrument(skip(self))]
+    pub async fn touch_many(&self, ids: &[&str]) -> Result<()> {
+        let now = Utc::now();
+        for id in ids {
+            if let Some(ctx) = self.cache.get(*id) {
+                ctx.write().metadata.last_activity = now;
+            }
+        }
+
+        if self.config.persist_context {
+            self.store.touch_many(ids, self.config.context_ttl).await?;
+        }
+        Ok(())
+    }
+
+    /// List one page of stored context keys matching `pattern`
+    ///
+    /// Pass the previous page's `next_cursor` to continue; keys are returned
+    /// in ascending order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store listing fails
+    pub async fn list_page(
+        &self,
+        pattern: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<ContextPage> {
+        self.reporting_store()
+            .list_page(pattern, cursor, limit)
+            .await
+    }
+
+    /// Stream all stored context keys matching `pattern`, one page at a time
+    pub fn stream_keys<'a>(
+        &'a self,
+        pattern: &'a str,
+        page_size: usize,
+    ) -> BoxStream<'a, Result<String>> {
+        let pages = stream::try_unfold(
+            Some(None),
+            move |cursor: Option<Option<String>>| async move {
+                let Some(cursor) = cursor else {
+                    return Ok::<_, anyhow::Error>(None);
+                };
+                let page = self
+                    .reporting_store()
+                    .list_page(pattern, cursor.as_deref(), page_size)
+                    .await?;
+                let next = page.next_cursor.map(Some);
+                Ok(Some((page.keys, next)))
+            },
+        );
+
+        pages
+            .map_ok(|keys| stream::iter(keys.into_iter().map(Ok::<_, anyhow::Error>)))
+            .try_flatten()
+            .boxed()
+    }
+
+    /// Stream summaries of all stored contexts matching `pattern`
+    ///
+    /// Each page of keys is resolved with a single batch read, from the cache
+    /// or else the read replica, without caching what is read; contexts that
+    /// expire between listing and loading are skipped.
+    pub fn stream_summaries<'a>(
+        &'a self,
+        pattern: &'a str,
+        page_size: usize,
+    ) -> BoxStream<'a, Result<ContextSummary>> {
+        self.stream_keys(pattern, page_size)
+            .try_chunks(page_size.max(1))
+            .map_err(|e| e.1)
+            .and_then(move |keys| async move {
+                let ids: Vec<&str> = keys.iter().map(String::as_str).collect();
+                let summaries: Vec<Result<ContextSummary>> = self
+                    .read_for_reporting(&ids)
+                    .await?
+                    .iter()
+                    .flatten()
+                    .map(|context| Ok(ContextSummary::from(context)))
+                    .collect();
+                Ok(stream::iter(summaries))
+            })
+            .try_flatten()
+            .boxed()
+    }
+
+    /// Contexts that have not expired but will within `lead_time`
+    ///
+    /// Cached contexts are checked first; with persistence enabled, the rest
+    /// of the store is scanned without loading contexts into the cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store listing or lookup fails
+    pub async fn expiring_within(&self, lead_time: Duration) -> Result<Vec<Context>> {
+        let ttl = self.config.context_ttl;
+        let deadline = Utc::now() + chrono::Duration::from_std(lead_time)?;
+        let expiring = |context: &Context| {
+            !context.is_expired(ttl)
+                && context
+                    .expires_at(ttl)
+                    .is_some_and(|expires_at| expires_at <= deadline)
+        };
+
+        let mut found: Vec<Context> = self
+            .cache
+            .iter()
+            .map(|entry| entry.value().read().clone())
+            .filter(&expiring)
+            .collect();
+        if !self.config.persist_context {
+            return Ok(found);
+        }
+
+        let mut cursor = None;
+        loop {
+            let page = self
+                .reporting_store()
+                .list_page("", cursor.as_deref(), EXPIRY_SCAN_PAGE_SIZE)
+                .await?;
+            let uncached: Vec<&str> = page
+                .keys
+                .iter()
+                .map(String::as_str)
+                .filter(|key| !self.cache.contains_key(*key))
+                .collect();
+            if !uncached.is_empty() {
+                let loaded = self.reporting_store().get_many(&uncached).await?;
+                found.extend(loaded.into_iter().flatten().filter(&expiring));
+            }
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(found),
+            }
+        }
+    }
+
+    /// A copy of a context for export, read from the cache or else the read replica
+    ///
+    /// Unlike [`get_many`](Self::get_many), the context is not cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if the context does not exist or has
+    /// expired, or an error if the store lookup fails
+    #[instrument(skip(self))]
+    pub async fn export(&self, id: &str) -> Result<Context> {
+        self.read_for_reporting(&[id])
+            .await?
+            .pop()
+            .flatten()
+            .ok_or_else(|| Error::NotFound(format!("Context {id}")).into())
+    }
+
+    /// A transcript of a context, e.g. to archive it or move it to another store
+    ///
+    /// Read like an [`export`](Self::export).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if the context does not exist or has
+    /// expired, or an error if the store lookup fails
+    pub async fn export_transcript(&self, id: &str) -> Result<Transcript> {
+        Ok(Transcript::from_context(&self.export(id).await?))
+    }
+
+    /// Store the conversation in a transcript, replacing any context with its ID
+    ///
+    /// Importing counts as a [renewal](Self::renew), so the TTL restarts.
+    /// Change the transcript's ID first to import a copy alongside the original.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if persisting the context fails
+    #[instrument(skip(self, transcript), fields(id = %transcript.conversation.id))]
+    pub async fn import_transcript(&self, transcript: Transcript) -> Result<Arc<RwLock<Context>>> {
+        let mut context = transcript.into_context();
+        context.metadata.renewed_at = Some(Utc::now());
+        let id = context.id.clone();
+        debug!(
+            "Importing context {} with {} turns",
+            id,
+            context.history.len()
+        );
+
+        if let Some(journal) = &self.journal {
+            journal.forget(&id).await?;
+        }
+        let ctx = Arc::new(RwLock::new(context));
+        self.update(&id, ctx.clone()).await?;
+        Ok(ctx)
+    }
+
+    /// Check that the store, and the read replica if any, can be listed
+    ///
+    /// # Errors
+    ///
+    /// Returns the first store's listing error
+    pub async fn probe(&self) -> Result<()> {
+        self.store.list_page("", None, 1).await?;
+        if let Some(replica) = &self.replica {
+            replica
+                .list_page("", None, 1)
+                .await
+                .map_err(|e| e.context("Read replica unreachable"))?;
+        }
+        Ok(())
+    }
+
+    /// Restart a context's TTL, keeping its history
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if the context does not exist or has
+    /// expired, or an error if persisting it fails
+    #[instrument(skip(self))]
+    pub async fn renew(&self, id: &str) -> Result<()> {
+        let context = self
+            .get_many(&[id])
+            .await?
+            .remove(id)
+            .ok_or_else(|| Error::NotFound(format!("Context {id}")))?;
+        context.write().metadata.renewed_at = Some(Utc::now());
+        self.update(id, context).await
+    }
+
+    /// Clear expired contexts
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if clearing expired contexts fails
+    #[instrument(skip(self))]
+    pub async fn clear_expired(&self) -> Result<usize> {
+        let mut removed = 0;
+        let expired_keys: Vec<String> = self
+            .cache
+            .iter()
+            .filter(|entry| entry.value().read().is_expired(self.config.context_ttl))
+            .map(|entry| entry.key().clone())
+            .collect();
+
+        for key in expired_keys {
+            self.cache.remove(&key);
+            self.store.delete(&key).await?;
+            if let Some(journal) = &self.journal {
+                journal.forget(&key).await?;
+            }
+            removed += 1;
+        }
+
+        debug!("Removed {} expired contexts", removed);
+        Ok(removed)
+    }
+
+    /// A context's event log, oldest first
+    ///
+    /// Empty unless event-sourced persistence is enabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event store cannot be read
+    pub async fn event_log(&self, id: &str) -> Result<Vec<ContextEventRecord>> {
+        match &self.journal {
+            Some(journal) => journal.records(id).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Rebuild a context as it was after event `seq` of its log
+    ///
+    /// Returns `None` if nothing was logged for the context by then.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event store cannot be read
+    pub async fn replay(&self, id: &str, seq: u64) -> Result<Option<Context>> {
+        let records = self.event_log(id).await?;
+        Ok(crate::journal::replay(
+            id,
+            None,
+            records.into_iter().take_while(|record| record.seq <= seq),
+        ))
+    }
+
+    /// Get statistics about managed contexts
+    #[must_use]
+    pub fn stats(&self) -> ContextStats {
+        let total = self.cache.len();
+        let mut total_tokens = 0;
+        let mut total_messages = 0;
+
+        for entry in self.cache.iter() {
+            let ctx = entry.value().read();
+            total_tokens += ctx.token_count;
+            total_messages += ctx.metadata.message_count;
+        }
+
+        ContextStats {
             total_contexts: total,
             total_tokens,
             total_messages,
             cache_size: total,
+            compression: self.store.compression_stats(),
+        }
+    }
+}
+
+/// Open the primary store for `backend`
+// Only the feature-gated database backends await while opening
+#[cfg_attr(
+    not(any(feature = "postgres", feature = "sqlite")),
+    allow(clippy::unused_async)
+)]
+async fn open_store(
+    backend: &StorageBackend,
+    codec: PayloadCodec,
+    encryption: Option<ContextEncryption>,
+) -> Result<Arc<dyn ContextStore>> {
+    let store: Arc<dyn ContextStore> = match backend {
+        StorageBackend::Memory => Arc::new(MemoryContextStore::new(codec, encryption)),
+        StorageBackend::Redis { url: _ } => {
+            // Would initialize Redis store here
+            return Err(Error::new("Redis store not yet implemented").into());
+        }
+        #[cfg(feature = "postgres")]
+        StorageBackend::Postgres { url } => {
+            let store =
+                crate::postgres::PostgresContextStore::connect(url, codec, encryption).await?;
+            store.migrate().await?;
+            Arc::new(store)
+        }
+        #[cfg(not(feature = "postgres"))]
+        StorageBackend::Postgres { .. } => {
+            return Err(Error::Configuration(
+                "Postgres storage requires the postgres feature".to_string(),
+            )
+            .into());
+        }
+        #[cfg(feature = "sqlite")]
+        StorageBackend::Sqlite { path } => {
+            let store =
+                Arc::new(crate::sqlite::SqliteContextStore::open(path, codec, encryption).await?);
+            store.spawn_vacuum(crate::sqlite::VACUUM_INTERVAL);
+            store
         }
+        #[cfg(not(feature = "sqlite"))]
+        StorageBackend::Sqlite { .. } => {
+            return Err(Error::Configuration(
+                "SQLite storage requires the sqlite feature".to_string(),
+            )
+            .into());
+        }
+    };
+    Ok(store)
+}
+
+/// Open a read replica for `backend`, without migrating or maintaining it
+#[cfg_attr(not(feature = "postgres"), allow(clippy::unused_async))]
+async fn open_replica(
+    backend: &StorageBackend,
+    codec: PayloadCodec,
+    encryption: Option<ContextEncryption>,
+) -> Result<Arc<dyn ContextStore>> {
+    match backend {
+        #[cfg(feature = "postgres")]
+        StorageBackend::Postgres { url } => Ok(Arc::new(
+            crate::postgres::PostgresContextStore::connect(url, codec, encryption).await?,
+        )),
+        #[cfg(not(feature = "postgres"))]
+        StorageBackend::Postgres { .. } => {
+            let _ = (codec, encryption);
+            Err(Error::Configuration(
+                "Postgres read replicas require the postgres feature".to_string(),
+            )
+            .into())
+        }
+        _ => Err(Error::Configuration(
+            "Read replicas are only supported for Postgres storage".to_string(),
+        )
+        .into()),
     }
 }
 
@@ -426,17 +1564,83 @@ pub trait ContextStore: Send + Sync {
 
     /// List all context keys
     async fn list_keys(&self, pattern: &str) -> Result<Vec<String>>;
+
+    /// List one page of keys containing `pattern`, in ascending order,
+    /// starting after `cursor`
+    ///
+    /// The default sorts the full [`list_keys`](Self::list_keys) result;
+    /// backends with native cursors should override it.
+    async fn list_page(
+        &self,
+        pattern: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<ContextPage> {
+        let mut keys = self.list_keys(pattern).await?;
+        keys.sort_unstable();
+        let start = cursor.map_or(0, |c| keys.partition_point(|k| k.as_str() <= c));
+        let keys: Vec<String> = keys.into_iter().skip(start).take(limit + 1).collect();
+        Ok(ContextPage::from_overfetch(keys, limit))
+    }
+
+    /// Get several contexts, in the same order as `keys`
+    ///
+    /// The default issues one `get` per key; backends with a native batch
+    /// read should override it.
+    async fn get_many(&self, keys: &[&str]) -> Result<Vec<Option<Context>>> {
+        let mut contexts = Vec::with_capacity(keys.len());
+        for key in keys {
+            contexts.push(self.get(key).await?);
+        }
+        Ok(contexts)
+    }
+
+    /// Delete several contexts
+    async fn delete_many(&self, keys: &[&str]) -> Result<()> {
+        for key in keys {
+            self.delete(key).await?;
+        }
+        Ok(())
+    }
+
+    /// Reset the TTL of several contexts, skipping keys that do not exist
+    ///
+    /// The default rewrites each context; backends that can update expiry
+    /// in place should override it.
+    async fn touch_many(&self, keys: &[&str], ttl: Duration) -> Result<()> {
+        for key in keys {
+            if let Some(context) = self.get(key).await? {
+                self.set(key, context, ttl).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compression counters, for stores that encode their payloads
+    fn compression_stats(&self) -> Option<CompressionStats> {
+        None
+    }
 }
 
+/// Encoded payload and its expiry, as held by [`MemoryContextStore`]
+type StoredPayload = (Vec<u8>, DateTime<Utc>);
+
 /// In-memory context store implementation
+///
+/// Contexts are held as encoded and, if configured, encrypted payloads,
+/// exactly as an external backend would store them.
 struct MemoryContextStore {
-    data: Arc<DashMap<String, (Context, DateTime<Utc>)>>,
+    data: Arc<DashMap<String, StoredPayload>>,
+    codec: PayloadCodec,
+    encryption: Option<ContextEncryption>,
 }
 
 impl MemoryContextStore {
-    fn new() -> Self {
+    fn new(codec: PayloadCodec, encryption: Option<ContextEncryption>) -> Self {
         Self {
             data: Arc::new(DashMap::new()),
+            codec,
+            encryption,
         }
     }
 }
@@ -444,12 +1648,24 @@ impl MemoryContextStore {
 #[async_trait::async_trait]
 impl ContextStore for MemoryContextStore {
     async fn get(&self, key: &str) -> Result<Option<Context>> {
-        Ok(self.data.get(key).map(|entry| entry.0.clone()))
+        let Some(payload) = self.data.get(key).map(|entry| entry.0.clone()) else {
+            return Ok(None);
+        };
+        match &self.encryption {
+            Some(encryption) => Ok(Some(
+                encryption.open_context(key, payload, &self.codec).await?,
+            )),
+            None => Ok(Some(self.codec.decode(&payload)?)),
+        }
     }
 
     async fn set(&self, key: &str, context: Context, ttl: Duration) -> Result<()> {
         let expiry = Utc::now() + chrono::Duration::from_std(ttl)?;
-        self.data.insert(key.to_string(), (context, expiry));
+        let mut payload = self.codec.encode(&context)?;
+        if let Some(encryption) = &self.encryption {
+            payload = encryption.seal(context.tenant(), key, payload).await?;
+        }
+        self.data.insert(key.to_string(), (payload, expiry));
         Ok(())
     }
 
@@ -467,6 +1683,97 @@ impl ContextStore for MemoryContextStore {
             .collect();
         Ok(keys)
     }
+
+    fn compression_stats(&self) -> Option<CompressionStats> {
+        Some(self.codec.stats())
+    }
+
+    async fn list_page(
+        &self,
+        pattern: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<ContextPage> {
+        // Keep only the smallest `limit + 1` matching keys rather than
+        // collecting and sorting the whole map
+        let mut smallest = BinaryHeap::with_capacity(limit + 1);
+        for entry in self.data.iter() {
+            let key = entry.key();
+            if !key.contains(pattern) || cursor.is_some_and(|c| key.as_str() <= c) {
+                continue;
+            }
+            if smallest.len() <= limit {
+                smallest.push(key.clone());
+            } else if smallest.peek().is_some_and(|largest| key < largest) {
+                smallest.pop();
+                smallest.push(key.clone());
+            }
+        }
+        Ok(ContextPage::from_overfetch(
+            smallest.into_sorted_vec(),
+            limit,
+        ))
+    }
+
+    async fn touch_many(&self, keys: &[&str], ttl: Duration) -> Result<()> {
+        let expiry = Utc::now() + chrono::Duration::from_std(ttl)?;
+        for key in keys {
+            if let Some(mut entry) = self.data.get_mut(*key) {
+                entry.1 = expiry;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One page of context keys
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContextPage {
+    /// Keys on this page, in ascending order
+    pub keys: Vec<String>,
+    /// Cursor for the next page, or `None` if this is the last one
+    pub next_cursor: Option<String>,
+}
+
+impl ContextPage {
+    /// Build a page from up to `limit + 1` sorted keys, using the extra key
+    /// only to detect whether another page follows
+    pub(crate) fn from_overfetch(mut keys: Vec<String>, limit: usize) -> Self {
+        let next_cursor = if keys.len() > limit {
+            keys.truncate(limit);
+            keys.last().cloned()
+        } else {
+            None
+        };
+        Self { keys, next_cursor }
+    }
+}
+
+/// Lightweight description of a stored context, for listings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextSummary {
+    /// Context ID
+    pub id: String,
+    /// Number of messages exchanged
+    pub message_count: usize,
+    /// Tokens currently held in history
+    pub token_count: usize,
+    /// Creation time
+    pub created_at: DateTime<Utc>,
+    /// Last activity time
+    pub last_activity: DateTime<Utc>,
+}
+
+impl From<&Context> for ContextSummary {
+    fn from(context: &Context) -> Self {
+        Self {
+            id: context.id.clone(),
+            message_count: context.metadata.message_count,
+            token_count: context.token_count,
+            created_at: context.metadata.created_at,
+            last_activity: context.metadata.last_activity,
+        }
+    }
 }
 
 /// Statistics about managed contexts
@@ -480,11 +1787,15 @@ pub struct ContextStats {
     pub total_messages: usize,
     /// Number of contexts in cache
     pub cache_size: usize,
+    /// Payload compression counters, if the store reports them
+    pub compression: Option<CompressionStats>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{CompressionConfig, EncryptionConfig};
+    use crate::encryption::LocalKeyProvider;
 
     #[test]
     fn test_context_creation() {
@@ -542,6 +1853,155 @@ mod tests {
         // Can't easily test actual expiry without mocking time
     }
 
+    #[test]
+    fn test_checkpoint_and_rollback() {
+        let mut context = Context::new("test");
+        context.add_message(&Message::text("first"));
+        context.set_variable("step", serde_json::json!(1));
+        let checkpoint = context.checkpoint("before-step-2");
+
+        context.add_message(&Message::text("second"));
+        context.set_variable("step", serde_json::json!(2));
+        let later = context.checkpoint("after-step-2");
+
+        context.rollback(checkpoint).unwrap();
+        assert_eq!(context.history.len(), 1);
+        assert_eq!(context.metadata.message_count, 1);
+        assert_eq!(context.get_variable("step"), Some(&serde_json::json!(1)));
+        assert!(context.find_checkpoint("before-step-2").is_some());
+        assert!(context.rollback(later).is_err());
+
+        for i in 0..=MAX_CHECKPOINTS {
+            context.checkpoint(format!("cp-{i}"));
+        }
+        assert_eq!(context.checkpoints.len(), MAX_CHECKPOINTS);
+        assert!(context.find_checkpoint("before-step-2").is_none());
+    }
+
+    #[test]
+    fn test_undo_last_exchange() {
+        let mut context = Context::new("test");
+        assert!(!context.undo_last_exchange());
+
+        context.add_message(&Message::text("hello"));
+        context.add_response(&Response::text("test", "hi there"));
+        context.add_message(&Message::text("oops, wrong question"));
+        context.add_response(&Response::text("test", "an answer"));
+
+        assert!(context.undo_last_exchange());
+        assert_eq!(context.history.len(), 2);
+        assert_eq!(context.metadata.message_count, 2);
+        assert_eq!(context.history[1].role, MessageRole::Assistant);
+    }
+
+    #[test]
+    fn test_pinned_messages_survive_trimming() {
+        let mut context = Context::new("test");
+        let important = Message::text("My account number is 12345");
+        context.add_message(&important);
+        for i in 0..10 {
+            context.add_message(&Message::text(format!("Message {i}")));
+        }
+
+        context.pin(important.id, 100).unwrap();
+        context.trim_to_token_limit(10);
+        let system = ContextMessage::system("Be brief");
+        context.token_count += system.estimated_tokens();
+        context.history.push_back(system);
+
+        assert_eq!(context.pinned().count(), 1);
+        assert_eq!(context.history[0].message_id, Some(important.id));
+        let prompt = context.prompt_history();
+        assert_eq!(prompt[0].role, MessageRole::System);
+        assert_eq!(prompt[1].message_id, Some(important.id));
+
+        assert!(context.unpin(important.id));
+        assert!(!context.unpin(important.id));
+        context.trim_to_token_limit(0);
+        assert!(context.history.is_empty());
+    }
+
+    #[test]
+    fn test_pin_respects_budget() {
+        let mut context = Context::new("test");
+        let long = Message::text("a".repeat(400));
+        context.add_message(&long);
+
+        assert!(context.pin(long.id, 50).is_err());
+        assert!(context.pin(Uuid::new_v4(), 50).is_err());
+        context.pin(long.id, 100).unwrap();
+        assert_eq!(context.pinned_tokens(), 100);
+    }
+
+    #[tokio::test]
+    async fn test_contexts_are_encrypted_with_tenant_keys() {
+        let provider = Arc::new(LocalKeyProvider::new().with_key("acme-cmk"));
+        let config = ContextConfig {
+            persist_context: true,
+            encryption: EncryptionConfig {
+                enabled: true,
+                tenant_keys: HashMap::from([("acme".to_string(), "acme-cmk".to_string())]),
+                ..EncryptionConfig::default()
+            },
+            ..ContextConfig::default()
+        };
+        let manager = ContextManager::with_key_provider(config, provider)
+            .await
+            .unwrap();
+
+        let ctx = manager.get_or_create("conv").await.unwrap();
+        ctx.write().add_message(
+            &Message::text("The launch code is 0000")
+                .with_metadata(TENANT_METADATA_KEY, "acme".into()),
+        );
+        assert_eq!(ctx.read().tenant(), Some("acme"));
+        manager.update("conv", ctx).await.unwrap();
+
+        manager.cache.clear();
+        let loaded = manager.get_or_create("conv").await.unwrap();
+        assert_eq!(loaded.read().history.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_pinning_is_persisted() {
+        let config = ContextConfig {
+            persist_context: true,
+            ..ContextConfig::default()
+        };
+        let manager = ContextManager::new(config).await.unwrap();
+        let message = Message::text("Remember this");
+        let ctx = manager.get_or_create("conv").await.unwrap();
+        ctx.write().add_message(&message);
+        manager.update("conv", ctx).await.unwrap();
+
+        manager.pin("conv", message.id).await.unwrap();
+        let stored = manager.store.get("conv").await.unwrap().unwrap();
+        assert!(stored.history[0].pinned);
+        assert_eq!(manager.pinned("conv").await.unwrap().len(), 1);
+
+        assert!(manager.unpin("conv", message.id).await.unwrap());
+        assert!(manager.pinned("conv").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rollback_is_persisted() {
+        let config = ContextConfig {
+            persist_context: true,
+            ..ContextConfig::default()
+        };
+        let manager = ContextManager::new(config).await.unwrap();
+
+        let checkpoint = manager.checkpoint("conv", "start").await.unwrap();
+        let ctx = manager.get_or_create("conv").await.unwrap();
+        ctx.write().add_message(&Message::text("speculative"));
+        manager.update("conv", ctx).await.unwrap();
+
+        manager.rollback("conv", checkpoint).await.unwrap();
+        let stored = manager.store.get("conv").await.unwrap().unwrap();
+        assert!(stored.history.is_empty());
+        assert_eq!(stored.checkpoints.len(), 1);
+    }
+
     #[tokio::test]
     async fn test_context_manager() {
         let config = ContextConfig::default();
@@ -554,9 +2014,125 @@ mod tests {
         assert_eq!(ctx1.read().id, ctx2.read().id);
     }
 
+    #[tokio::test]
+    async fn test_stale_context_is_annotated() {
+        let manager = ContextManager::new(ContextConfig::default())
+            .await
+            .unwrap()
+            .with_fingerprint("v1");
+
+        let ctx = manager.get_or_create("conv").await.unwrap();
+        assert_eq!(ctx.read().metadata.config_hash.as_deref(), Some("v1"));
+        ctx.write().add_message(&Message::text("Hello"));
+
+        let mut stale = ctx.read().clone();
+        stale.metadata.config_hash = Some("v0".to_string());
+        *ctx.write() = stale;
+
+        let ctx = manager.get_or_create("conv").await.unwrap();
+        let context = ctx.read().clone();
+        assert_eq!(context.metadata.config_hash.as_deref(), Some("v1"));
+        assert_eq!(context.history.len(), 2);
+        assert_eq!(context.history[1].role, MessageRole::System);
+    }
+
+    /// Annotates like [`StaleContextPolicy::Annotate`], slowly, counting calls
+    struct SlowMigration {
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl ContextMigration for SlowMigration {
+        async fn migrate(&self, context: &mut Context, fingerprint: &str) -> Result<()> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            PolicyMigration::new(StaleContextPolicy::Annotate)
+                .migrate(context, fingerprint)
+                .await
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_gets_migrate_once_and_keep_new_messages() {
+        let migration = Arc::new(SlowMigration {
+            calls: std::sync::atomic::AtomicUsize::new(0),
+        });
+        let manager = ContextManager::new(ContextConfig::default())
+            .await
+            .unwrap()
+            .with_fingerprint("v2")
+            .with_migration(migration.clone());
+
+        let ctx = manager.get_or_create("conv").await.unwrap();
+        ctx.write().add_message(&Message::text("Hello"));
+        ctx.write().metadata.config_hash = Some("v1".to_string());
+
+        let (first, second, ()) = tokio::join!(
+            manager.get_or_create("conv"),
+            manager.get_or_create("conv"),
+            async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                ctx.write().add_message(&Message::text("Still there?"));
+            }
+        );
+        first.unwrap();
+        second.unwrap();
+
+        assert_eq!(migration.calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+        let context = ctx.read().clone();
+        assert!(!context.is_stale("v2"));
+        assert_eq!(context.metadata.message_count, 2);
+        let roles: Vec<_> = context.history.iter().map(|m| m.role).collect();
+        assert_eq!(
+            roles,
+            [MessageRole::User, MessageRole::System, MessageRole::User]
+        );
+        assert_eq!(context.history[2].content.as_str(), "Still there?");
+    }
+
+    #[tokio::test]
+    async fn test_stale_context_reset_policy() {
+        let config = ContextConfig {
+            stale_context_policy: StaleContextPolicy::Reset,
+            ..ContextConfig::default()
+        };
+        let manager = ContextManager::new(config)
+            .await
+            .unwrap()
+            .with_fingerprint("v2");
+
+        let ctx = manager.get_or_create("conv").await.unwrap();
+        ctx.write().add_message(&Message::text("Hello"));
+        ctx.write().metadata.config_hash = Some("v1".to_string());
+
+        let ctx = manager.get_or_create("conv").await.unwrap();
+        assert!(ctx.read().history.is_empty());
+        assert!(!ctx.read().is_stale("v2"));
+    }
+
+    #[tokio::test]
+    async fn test_summarizing_migration() {
+        let complete: crate::completion::CompletionFn = Arc::new(|prompt: String| {
+            Box::pin(async move {
+                assert!(prompt.contains("user: Hello"));
+                Ok("The user said hello.".to_string())
+            })
+        });
+        let mut context = Context::new("conv");
+        context.add_message(&Message::text("Hello"));
+
+        SummarizingMigration::new(complete)
+            .migrate(&mut context, "v2")
+            .await
+            .unwrap();
+
+        assert_eq!(context.history.len(), 1);
+        assert!(context.history[0].content.ends_with("The user said hello."));
+    }
+
     #[tokio::test]
     async fn test_memory_store() {
-        let store = MemoryContextStore::new();
+        let store = MemoryContextStore::new(PayloadCodec::new(CompressionConfig::default()), None);
         let context = Context::new("test");
 
         store
@@ -572,4 +2148,280 @@ mod tests {
         let deleted = store.get("test").await.unwrap();
         assert!(deleted.is_none());
     }
+
+    #[tokio::test]
+    async fn test_memory_store_compresses_large_contexts() {
+        let config = ContextConfig {
+            persist_context: true,
+            compression: Some(CompressionConfig {
+                threshold_bytes: 512,
+                ..CompressionConfig::default()
+            }),
+            ..ContextConfig::default()
+        };
+        let manager = ContextManager::new(config).await.unwrap();
+
+        let small = manager.get_or_create("small").await.unwrap();
+        manager.update("small", small).await.unwrap();
+
+        let large = manager.get_or_create("large").await.unwrap();
+        for _ in 0..20 {
+            large
+                .write()
+                .add_message(&Message::text("a fairly repetitive message"));
+        }
+        manager.update("large", large.clone()).await.unwrap();
+
+        let stats = manager.stats().compression.unwrap();
+        assert_eq!(stats.compressed, 1);
+        assert!(stats.ratio().unwrap() > 1.0);
+
+        let loaded = manager.store.get("large").await.unwrap().unwrap();
+        assert_eq!(loaded.history.len(), 20);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_compression_config_is_rejected() {
+        let config = ContextConfig {
+            compression: Some(CompressionConfig {
+                level: 99,
+                ..CompressionConfig::default()
+            }),
+            ..ContextConfig::default()
+        };
+        assert!(ContextManager::new(config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bulk_operations() {
+        let config = ContextConfig {
+            persist_context: true,
+            ..ContextConfig::default()
+        };
+        let manager = ContextManager::new(config).await.unwrap();
+
+        for id in ["a", "b", "c"] {
+            manager.get_or_create(id).await.unwrap();
+        }
+        manager.cache.remove("b");
+
+        let found = manager.get_many(&["a", "b", "missing"]).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert!(found.contains_key("b"));
+        assert!(!found.contains_key("missing"));
+        assert!(manager.cache.contains_key("b"));
+
+        manager.touch_many(&["a", "missing"]).await.unwrap();
+        assert!(manager.store.get("missing").await.unwrap().is_none());
+
+        manager.delete_many(&["a", "b"]).await.unwrap();
+        let found = manager.get_many(&["a", "b", "c"]).await.unwrap();
+        assert_eq!(found.keys().collect::<Vec<_>>(), vec!["c"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_page() {
+        let store = MemoryContextStore::new(PayloadCodec::new(CompressionConfig::disabled()), None);
+        for i in 0..5 {
+            let key = format!("conv-{i}");
+            store
+                .set(&key, Context::new(&key), Duration::from_secs(60))
+                .await
+                .unwrap();
+        }
+        store
+            .set("other", Context::new("other"), Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        let first = store.list_page("conv-", None, 2).await.unwrap();
+        assert_eq!(first.keys, vec!["conv-0", "conv-1"]);
+        assert_eq!(first.next_cursor.as_deref(), Some("conv-1"));
+
+        let last = store.list_page("conv-", Some("conv-3"), 2).await.unwrap();
+        assert_eq!(last.keys, vec!["conv-4"]);
+        assert!(last.next_cursor.is_none());
+
+        let exact = store.list_page("conv-", Some("conv-2"), 2).await.unwrap();
+        assert_eq!(exact.keys, vec!["conv-3", "conv-4"]);
+        assert!(exact.next_cursor.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_summaries() {
+        let config = ContextConfig {
+            persist_context: true,
+            ..ContextConfig::default()
+        };
+        let manager = ContextManager::new(config).await.unwrap();
+        for i in 0..7 {
+            manager.get_or_create(&format!("conv-{i}")).await.unwrap();
+        }
+
+        let keys: Vec<String> = manager.stream_keys("conv", 3).try_collect().await.unwrap();
+        assert_eq!(keys.len(), 7);
+
+        let summaries: Vec<ContextSummary> = manager
+            .stream_summaries("conv", 3)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(summaries.len(), 7);
+        assert_eq!(summaries[6].id, "conv-6");
+    }
+
+    #[tokio::test]
+    async fn test_event_log_persistence() {
+        let config = ContextConfig {
+            persist_context: true,
+            event_log: crate::config::EventLogConfig {
+                enabled: true,
+                snapshot_interval: 4,
+            },
+            ..ContextConfig::default()
+        };
+        let manager = ContextManager::new(config).await.unwrap();
+        let ctx = manager.get_or_create("conv").await.unwrap();
+        for i in 0..3 {
+            ctx.write()
+                .add_message(&Message::text(format!("message {i}")));
+            manager.update("conv", ctx.clone()).await.unwrap();
+        }
+
+        // One full state, then a message and metadata event per update
+        let log = manager.event_log("conv").await.unwrap();
+        assert_eq!(log.len(), 7);
+        assert!(matches!(
+            log[0].event,
+            crate::journal::ContextEvent::Replaced { .. }
+        ));
+        assert!(matches!(
+            log[5].event,
+            crate::journal::ContextEvent::MessageAdded { .. }
+        ));
+        let snapshot = manager.store.get("conv").await.unwrap().unwrap();
+        assert_eq!(snapshot.metadata.event_seq, 5);
+        assert_eq!(snapshot.history.len(), 2);
+
+        manager.cache.clear();
+        let reloaded = manager.get_or_create("conv").await.unwrap();
+        assert_eq!(reloaded.read().history.len(), 3);
+        assert_eq!(reloaded.read().metadata.event_seq, 7);
+        assert_eq!(reloaded.read().metadata.message_count, 3);
+
+        let earlier = manager.replay("conv", 3).await.unwrap().unwrap();
+        assert_eq!(earlier.history.len(), 1);
+
+        manager.delete("conv").await.unwrap();
+        assert!(manager.event_log("conv").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reporting_reads_use_replica() {
+        let replica = Arc::new(MemoryContextStore::new(
+            PayloadCodec::new(CompressionConfig::disabled()),
+            None,
+        ));
+        let mut archived = Context::new("archived");
+        archived.add_message(&Message::text("old question"));
+        replica
+            .set("archived", archived, Duration::from_secs(3600))
+            .await
+            .unwrap();
+
+        let config = ContextConfig {
+            persist_context: true,
+            ..ContextConfig::default()
+        };
+        let manager = ContextManager::new(config)
+            .await
+            .unwrap()
+            .with_read_replica(replica);
+        let live = manager.get_or_create("live").await.unwrap();
+        live.write().add_message(&Message::text("new question"));
+        manager.update("live", live).await.unwrap();
+
+        let page = manager.list_page("", None, 10).await.unwrap();
+        assert_eq!(page.keys, ["archived"]);
+        let summaries: Vec<ContextSummary> = manager
+            .stream_summaries("", 10)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert!(manager.probe().await.is_ok());
+
+        // Exports prefer the cache, which is fresher than the replica
+        assert_eq!(manager.export("archived").await.unwrap().history.len(), 1);
+        assert_eq!(manager.export("live").await.unwrap().history.len(), 1);
+        assert!(manager.export("missing").await.is_err());
+
+        // Live reads stay on the primary and reporting reads are not cached
+        assert!(manager.get_many(&["archived"]).await.unwrap().is_empty());
+        assert_eq!(manager.stats().total_contexts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_transcript_moves_between_managers() {
+        let source = ContextManager::new(ContextConfig::default()).await.unwrap();
+        let ctx = source.get_or_create("conv").await.unwrap();
+        ctx.write().add_message(&Message::text("Hello"));
+        ctx.write()
+            .add_response(&Response::text("conv", "Hi!").with_usage(
+                crate::message::TokenUsage::new(8, 2, "anthropic.claude-haiku"),
+            ));
+        source.update("conv", ctx).await.unwrap();
+
+        let jsonl = source
+            .export_transcript("conv")
+            .await
+            .unwrap()
+            .to_jsonl()
+            .unwrap();
+        let target = ContextManager::new(ContextConfig {
+            persist_context: true,
+            ..ContextConfig::default()
+        })
+        .await
+        .unwrap();
+        target
+            .import_transcript(Transcript::from_jsonl(&jsonl).unwrap())
+            .await
+            .unwrap();
+
+        let imported = target.export("conv").await.unwrap();
+        assert_eq!(imported.history.len(), 2);
+        assert_eq!(imported.metadata.total_tokens, 10);
+        assert_eq!(imported.metadata.usage.len(), 1);
+        assert!(imported.metadata.renewed_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_create_from_template() {
+        let template = crate::template::ConversationTemplate::new("Support")
+            .with_system_prompt("You are a support agent.")
+            .with_greeting("Hi! How can I help?");
+        let mut config = ContextConfig {
+            persist_context: true,
+            ..ContextConfig::default()
+        };
+        config.templates.insert("support".to_string(), template);
+        let manager = ContextManager::new(config).await.unwrap();
+
+        let ctx = manager.create_from_template("support").await.unwrap();
+        let id = ctx.read().id.clone();
+        let stored = manager.export(&id).await.unwrap();
+        assert_eq!(stored.history.len(), 1);
+        assert_eq!(stored.history[0].role, MessageRole::Assistant);
+        assert_eq!(
+            crate::template::system_prompt_of(&stored),
+            Some("You are a support agent.")
+        );
+
+        let error = manager.create_from_template("missing").await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::NotFound(_))
+        ));
+    }
 }
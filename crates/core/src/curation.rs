@@ -0,0 +1,554 @@
+//! Fine-tuning dataset curation
+//!
+//! Turns production conversations into a Bedrock model customization
+//! dataset. [`Exchange`]s (a prompt and the bot's reply) are split out of
+//! conversation history, scored with user feedback and judge ratings, and
+//! passed through a [`Curator`]: low-rated, unrated, trivial, and duplicate
+//! exchanges are dropped, personal data is masked with
+//! [`redact_pii`](crate::sanitize::redact_pii), and the rest are written as
+//! JSONL. A [`DatasetSink`] stores the file together with a
+//! [`DatasetManifest`] describing how it was built.
+//!
+//! [`S3DatasetSink`] is available with the `curation` feature.
+
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, instrument};
+use uuid::Uuid;
+
+use crate::{
+    context::{Context, MessageRole},
+    sanitize::redact_pii,
+};
+
+/// One prompt and the bot's reply
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    /// Conversation the exchange came from
+    pub conversation_id: String,
+    /// ID of the reply, for joining feedback
+    pub response_id: Option<Uuid>,
+    /// System prompt in effect
+    pub system: Option<String>,
+    /// User message
+    pub prompt: String,
+    /// Bot reply
+    pub completion: String,
+    /// User feedback, 0.0 (bad) to 1.0 (good)
+    pub feedback_score: Option<f32>,
+    /// Automated judge rating, 0.0 to 1.0
+    pub judge_rating: Option<f32>,
+    /// When the reply was sent
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Exchange {
+    /// Split a conversation into exchanges
+    ///
+    /// Each assistant message is paired with the user message before it;
+    /// the most recent system message becomes the exchange's system prompt.
+    #[must_use]
+    pub fn from_context(context: &Context) -> Vec<Self> {
+        let mut exchanges = Vec::new();
+        let mut system = None;
+        let mut prompt: Option<&str> = None;
+        for message in &context.history {
+            match message.role {
+                MessageRole::System => system = Some(message.content.to_string()),
+                MessageRole::User => prompt = Some(&message.content),
+                MessageRole::Assistant => {
+                    if let Some(prompt) = prompt.take() {
+                        exchanges.push(Self {
+                            conversation_id: context.id.clone(),
+                            response_id: message.message_id,
+                            system: system.clone(),
+                            prompt: prompt.to_string(),
+                            completion: message.content.to_string(),
+                            feedback_score: None,
+                            judge_rating: None,
+                            timestamp: message.timestamp,
+                        });
+                    }
+                }
+            }
+        }
+        exchanges
+    }
+
+    /// Attach a user feedback score
+    #[must_use]
+    pub const fn with_feedback_score(mut self, score: f32) -> Self {
+        self.feedback_score = Some(score);
+        self
+    }
+
+    /// Attach a judge rating
+    #[must_use]
+    pub const fn with_judge_rating(mut self, rating: f32) -> Self {
+        self.judge_rating = Some(rating);
+        self
+    }
+
+    fn fingerprint(&self) -> String {
+        let normalize = |text: &str| {
+            text.split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase()
+        };
+        let mut hasher = Sha256::new();
+        hasher.update(normalize(&self.prompt));
+        hasher.update([0]);
+        hasher.update(normalize(&self.completion));
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// JSONL record layout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatasetFormat {
+    /// `{"system", "messages": [{"role", "content"}]}`, used by Claude models
+    #[default]
+    Conversation,
+    /// `{"prompt", "completion"}`, used by Titan and Llama models
+    PromptCompletion,
+}
+
+impl DatasetFormat {
+    /// Render one exchange as a JSONL record
+    #[must_use]
+    pub fn record(self, exchange: &Exchange) -> serde_json::Value {
+        match self {
+            Self::Conversation => {
+                let mut record = serde_json::json!({
+                    "messages": [
+                        { "role": "user", "content": exchange.prompt },
+                        { "role": "assistant", "content": exchange.completion },
+                    ]
+                });
+                if let Some(system) = &exchange.system {
+                    record["system"] = serde_json::json!(system);
+                }
+                record
+            }
+            Self::PromptCompletion => serde_json::json!({
+                "prompt": exchange.prompt,
+                "completion": exchange.completion,
+            }),
+        }
+    }
+}
+
+/// Selection thresholds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CurationConfig {
+    /// Minimum user feedback score
+    pub min_feedback_score: f32,
+    /// Minimum judge rating
+    pub min_judge_rating: f32,
+    /// Drop exchanges with neither feedback nor a judge rating
+    pub require_rating: bool,
+    /// Drop exchanges whose prompt or reply is shorter than this
+    pub min_chars: usize,
+    /// Keep at most this many examples, best rated first
+    pub max_examples: Option<usize>,
+    /// Mask personal data in prompts and replies
+    pub redact_pii: bool,
+    /// Record layout
+    pub format: DatasetFormat,
+}
+
+impl Default for CurationConfig {
+    fn default() -> Self {
+        Self {
+            min_feedback_score: 0.5,
+            min_judge_rating: 0.7,
+            require_rating: true,
+            min_chars: 8,
+            max_examples: None,
+            redact_pii: true,
+            format: DatasetFormat::default(),
+        }
+    }
+}
+
+/// Why an exchange was left out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rejection {
+    /// Feedback below the threshold
+    LowFeedback,
+    /// Judge rating below the threshold
+    LowJudgeRating,
+    /// No feedback or rating
+    Unrated,
+    /// Prompt or reply too short
+    TooShort,
+    /// Same prompt and reply as an earlier exchange
+    Duplicate,
+    /// Over the example limit
+    OverLimit,
+}
+
+/// A curated dataset
+#[derive(Debug, Clone)]
+pub struct CuratedDataset {
+    /// Selected, redacted exchanges
+    pub examples: Vec<Exchange>,
+    /// Count of exchanges left out, by reason
+    pub rejected: BTreeMap<Rejection, usize>,
+    /// Layout of [`CuratedDataset::to_jsonl`]
+    pub format: DatasetFormat,
+}
+
+impl CuratedDataset {
+    /// Render the dataset as JSONL
+    #[must_use]
+    pub fn to_jsonl(&self) -> String {
+        self.examples
+            .iter()
+            .map(|exchange| self.format.record(exchange).to_string() + "\n")
+            .collect()
+    }
+}
+
+/// Selects and cleans exchanges for fine-tuning
+#[derive(Debug, Clone, Default)]
+pub struct Curator {
+    config: CurationConfig,
+}
+
+impl Curator {
+    /// Create a curator
+    #[must_use]
+    pub const fn new(config: CurationConfig) -> Self {
+        Self { config }
+    }
+
+    fn check(&self, exchange: &Exchange) -> Option<Rejection> {
+        let config = &self.config;
+        if exchange.prompt.trim().chars().count() < config.min_chars
+            || exchange.completion.trim().chars().count() < config.min_chars
+        {
+            return Some(Rejection::TooShort);
+        }
+        if exchange
+            .feedback_score
+            .is_some_and(|score| score < config.min_feedback_score)
+        {
+            return Some(Rejection::LowFeedback);
+        }
+        if exchange
+            .judge_rating
+            .is_some_and(|rating| rating < config.min_judge_rating)
+        {
+            return Some(Rejection::LowJudgeRating);
+        }
+        if config.require_rating
+            && exchange.feedback_score.is_none()
+            && exchange.judge_rating.is_none()
+        {
+            return Some(Rejection::Unrated);
+        }
+        None
+    }
+
+    fn rank(exchange: &Exchange) -> f32 {
+        match (exchange.feedback_score, exchange.judge_rating) {
+            (Some(feedback), Some(judge)) => f32::midpoint(feedback, judge),
+            (Some(score), None) | (None, Some(score)) => score,
+            (None, None) => 0.0,
+        }
+    }
+
+    /// Select, deduplicate, and redact exchanges
+    #[must_use]
+    pub fn curate(&self, exchanges: Vec<Exchange>) -> CuratedDataset {
+        let mut rejected = BTreeMap::new();
+        let mut seen = HashSet::new();
+        let mut examples = Vec::new();
+
+        for exchange in exchanges {
+            let reason = self
+                .check(&exchange)
+                .or_else(|| (!seen.insert(exchange.fingerprint())).then_some(Rejection::Duplicate));
+            match reason {
+                Some(reason) => *rejected.entry(reason).or_insert(0) += 1,
+                None => examples.push(exchange),
+            }
+        }
+
+        if let Some(limit) = self.config.max_examples {
+            examples.sort_by(|a, b| Self::rank(b).total_cmp(&Self::rank(a)));
+            if examples.len() > limit {
+                rejected.insert(Rejection::OverLimit, examples.len() - limit);
+                examples.truncate(limit);
+            }
+        }
+
+        if self.config.redact_pii {
+            for exchange in &mut examples {
+                exchange.prompt = redact_pii(&exchange.prompt);
+                exchange.completion = redact_pii(&exchange.completion);
+                exchange.system = exchange.system.as_deref().map(redact_pii);
+            }
+        }
+
+        CuratedDataset {
+            examples,
+            rejected,
+            format: self.config.format,
+        }
+    }
+}
+
+/// Describes an uploaded dataset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatasetManifest {
+    /// Upload ID
+    pub id: Uuid,
+    /// Where the JSONL was stored
+    pub dataset_uri: String,
+    /// Record layout
+    pub format: DatasetFormat,
+    /// Number of records
+    pub example_count: usize,
+    /// Hex SHA-256 of the JSONL
+    pub sha256: String,
+    /// Count of exchanges left out, by reason
+    pub rejected: BTreeMap<Rejection, usize>,
+    /// Thresholds used
+    pub config: CurationConfig,
+    /// When the dataset was built
+    pub created_at: DateTime<Utc>,
+}
+
+/// Stores dataset files
+#[async_trait]
+pub trait DatasetSink: Send + Sync {
+    /// Store a file under a relative key, returning its URI
+    async fn put(&self, key: &str, content_type: &str, body: Vec<u8>) -> Result<String>;
+}
+
+/// Write a curated dataset and its manifest to a sink
+///
+/// Files are stored as `{id}/train.jsonl` and `{id}/manifest.json`.
+///
+/// # Errors
+///
+/// Returns an error if either upload fails.
+#[instrument(skip(sink, dataset, config), fields(examples = dataset.examples.len()))]
+pub async fn publish(
+    sink: &dyn DatasetSink,
+    dataset: &CuratedDataset,
+    config: &CurationConfig,
+) -> Result<DatasetManifest> {
+    let id = Uuid::new_v4();
+    let jsonl = dataset.to_jsonl();
+    let sha256 = format!("{:x}", Sha256::digest(jsonl.as_bytes()));
+    let dataset_uri = sink
+        .put(
+            &format!("{id}/train.jsonl"),
+            "application/jsonl",
+            jsonl.into_bytes(),
+        )
+        .await?;
+
+    let manifest = DatasetManifest {
+        id,
+        dataset_uri,
+        format: dataset.format,
+        example_count: dataset.examples.len(),
+        sha256,
+        rejected: dataset.rejected.clone(),
+        config: config.clone(),
+        created_at: Utc::now(),
+    };
+    sink.put(
+        &format!("{id}/manifest.json"),
+        "application/json",
+        serde_json::to_vec_pretty(&manifest)?,
+    )
+    .await?;
+
+    info!(
+        "Published {} fine-tuning examples to {}",
+        manifest.example_count, manifest.dataset_uri
+    );
+    Ok(manifest)
+}
+
+/// Dataset files in S3, where Bedrock customization jobs read training data
+#[cfg(feature = "curation")]
+pub struct S3DatasetSink {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+    prefix: String,
+}
+
+#[cfg(feature = "curation")]
+impl S3DatasetSink {
+    /// Create a sink writing under `s3://bucket/prefix`
+    #[must_use]
+    pub fn new(
+        client: aws_sdk_s3::Client,
+        bucket: impl Into<String>,
+        prefix: impl Into<String>,
+    ) -> Self {
+        Self {
+            client,
+            bucket: bucket.into(),
+            prefix: prefix.into(),
+        }
+    }
+}
+
+#[cfg(feature = "curation")]
+#[async_trait]
+impl DatasetSink for S3DatasetSink {
+    async fn put(&self, key: &str, content_type: &str, body: Vec<u8>) -> Result<String> {
+        let key = format!("{}/{key}", self.prefix.trim_end_matches('/'))
+            .trim_start_matches('/')
+            .to_string();
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .content_type(content_type)
+            .body(body.into())
+            .send()
+            .await
+            .map_err(|e| crate::error::Error::Network(format!("S3 put {key} failed: {e}")))?;
+        Ok(format!("s3://{}/{key}", self.bucket))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{Message, Response};
+    use parking_lot::Mutex;
+
+    fn exchange(prompt: &str, completion: &str) -> Exchange {
+        Exchange {
+            conversation_id: "c1".to_string(),
+            response_id: None,
+            system: None,
+            prompt: prompt.to_string(),
+            completion: completion.to_string(),
+            feedback_score: None,
+            judge_rating: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingSink {
+        files: Mutex<Vec<(String, Vec<u8>)>>,
+    }
+
+    #[async_trait]
+    impl DatasetSink for RecordingSink {
+        async fn put(&self, key: &str, _content_type: &str, body: Vec<u8>) -> Result<String> {
+            self.files.lock().push((key.to_string(), body));
+            Ok(format!("mem://{key}"))
+        }
+    }
+
+    #[test]
+    fn test_exchanges_from_context() {
+        let mut context = Context::new("c1");
+        context.add_message(&Message::text("What is our refund window?"));
+        context.add_response(&Response::text("c1", "Thirty days from delivery."));
+        context.add_message(&Message::text("Thanks"));
+
+        let exchanges = Exchange::from_context(&context);
+        assert_eq!(exchanges.len(), 1);
+        assert_eq!(exchanges[0].prompt, "What is our refund window?");
+        assert_eq!(exchanges[0].completion, "Thirty days from delivery.");
+    }
+
+    #[test]
+    fn test_curate_filters_dedups_and_redacts() {
+        let curator = Curator::new(CurationConfig::default());
+        let dataset = curator.curate(vec![
+            exchange(
+                "How do I reset my password?",
+                "Use the link on the sign-in page.",
+            )
+            .with_feedback_score(1.0),
+            exchange(
+                "How do I  reset my password?",
+                "Use the link on the sign-in page.",
+            )
+            .with_judge_rating(0.9),
+            exchange("Where is my order?", "It shipped yesterday.").with_feedback_score(0.0),
+            exchange("Cancel my plan", "Done, you will not be billed again.")
+                .with_judge_rating(0.2),
+            exchange("Is the API down?", "No incidents are reported right now."),
+            exchange("hi", "Hello! How can I help?").with_feedback_score(1.0),
+            exchange(
+                "Email ada@example.com the invoice",
+                "Sent the invoice to ada@example.com.",
+            )
+            .with_judge_rating(0.8),
+        ]);
+
+        assert_eq!(dataset.examples.len(), 2);
+        assert_eq!(
+            dataset.rejected,
+            BTreeMap::from([
+                (Rejection::LowFeedback, 1),
+                (Rejection::LowJudgeRating, 1),
+                (Rejection::Unrated, 1),
+                (Rejection::TooShort, 1),
+                (Rejection::Duplicate, 1),
+            ])
+        );
+        assert_eq!(dataset.examples[1].prompt, "Email [redacted] the invoice");
+    }
+
+    #[tokio::test]
+    async fn test_publish_writes_jsonl_and_manifest() {
+        let config = CurationConfig {
+            max_examples: Some(1),
+            ..CurationConfig::default()
+        };
+        let mut dataset = Curator::new(config.clone()).curate(vec![
+            exchange(
+                "Summarize the release notes",
+                "Three bug fixes and a new API.",
+            )
+            .with_feedback_score(0.6),
+            exchange("Translate 'thank you' to French", "Merci beaucoup.").with_feedback_score(1.0),
+        ]);
+        assert_eq!(dataset.rejected[&Rejection::OverLimit], 1);
+        dataset.examples[0].system = Some("Be brief.".to_string());
+
+        let sink = RecordingSink::default();
+        let manifest = publish(&sink, &dataset, &config).await.unwrap();
+        assert_eq!(manifest.example_count, 1);
+        assert_eq!(
+            manifest.dataset_uri,
+            format!("mem://{}/train.jsonl", manifest.id)
+        );
+
+        let files = sink.files.lock().clone();
+        assert!(files[0].0.ends_with("train.jsonl"));
+        let record: serde_json::Value = serde_json::from_slice(&files[0].1).unwrap();
+        assert_eq!(record["system"], "Be brief.");
+        assert_eq!(
+            record["messages"][0]["content"],
+            "Translate 'thank you' to French"
+        );
+        assert_eq!(record["messages"][1]["role"], "assistant");
+        assert!(files[1].0.ends_with("manifest.json"));
+    }
+}
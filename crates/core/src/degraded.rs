@@ -0,0 +1,319 @@
+//! Graceful degradation while the provider is unavailable
+//!
+//! A circuit breaker tracks consecutive provider failures. While it is open,
+//! or while the token budget is exhausted, [`DegradedMode`] answers messages
+//! according to the tenant's [`DegradedAction`] instead of surfacing hard
+//! errors: a canned reply, a queued-for-later acknowledgement, or a
+//! retryable "service busy" response carrying `retry_after`.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    config::{DegradedAction, DegradedModeConfig},
+    error::Error,
+    message::{Message, Response, ResponseError},
+};
+
+/// Message metadata key identifying the tenant a message belongs to
+pub const TENANT_METADATA_KEY: &str = "tenant_id";
+
+/// Response metadata key set on every degraded response
+pub const DEGRADED_METADATA_KEY: &str = "degraded";
+
+/// Why the bot is degraded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DegradedReason {
+    /// The provider circuit breaker is open
+    CircuitOpen,
+    /// The token or cost budget is spent
+    BudgetExhausted,
+}
+
+/// State of the provider circuit breaker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests flow normally
+    Closed,
+    /// Requests are short-circuited
+    Open,
+    /// One probe request is allowed through
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct Breaker {
+    state: CircuitState,
+    failures: u32,
+    opened_at: Option<Instant>,
+}
+
+/// Degraded-mode policy and state shared by a bot
+#[derive(Debug)]
+pub struct DegradedMode {
+    config: DegradedModeConfig,
+    breaker: Mutex<Breaker>,
+    budget_exhausted: AtomicBool,
+    queue: Mutex<VecDeque<Message>>,
+}
+
+impl DegradedMode {
+    /// Create the policy with a closed circuit
+    #[must_use]
+    pub fn new(config: DegradedModeConfig) -> Self {
+        Self {
+            config,
+            breaker: Mutex::new(Breaker {
+                state: CircuitState::Closed,
+                failures: 0,
+                opened_at: None,
+            }),
+            budget_exhausted: AtomicBool::new(false),
+            queue: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Current circuit state
+    #[must_use]
+    pub fn circuit_state(&self) -> CircuitState {
+        self.breaker.lock().state
+    }
+
+    /// Mark the budget as exhausted or replenished
+    pub fn set_budget_exhausted(&self, exhausted: bool) {
+        self.budget_exhausted.store(exhausted, Ordering::Relaxed);
+    }
+
+    /// Whether a request may reach the provider, and if not, why
+    ///
+    /// An open circuit moves to half-open once the reset timeout has
+    /// elapsed, letting the next request through as a probe.
+    #[must_use]
+    pub fn check(&self) -> Option<DegradedReason> {
+        if self.budget_exhausted.load(Ordering::Relaxed) {
+            return Some(DegradedReason::BudgetExhausted);
+        }
+
+        let mut breaker = self.breaker.lock();
+        if breaker.state == CircuitState::Closed {
+            return None;
+        }
+
+        // Only one probe may proceed per reset interval; a probe that never
+        // reports back is replaced by the next one
+        let cooled = breaker
+            .opened_at
+            .is_some_and(|at| at.elapsed() >= self.config.reset_timeout);
+        if cooled {
+            breaker.state = CircuitState::HalfOpen;
+            breaker.opened_at = Some(Instant::now());
+            drop(breaker);
+            None
+        } else {
+            Some(DegradedReason::CircuitOpen)
+        }
+    }
+
+    /// Record a successful provider call, closing the circuit
+    pub fn record_success(&self) {
+        let mut breaker = self.breaker.lock();
+        if breaker.state != CircuitState::Closed {
+            info!("Provider recovered; closing circuit");
+        }
+        breaker.state = CircuitState::Closed;
+        breaker.failures = 0;
+        breaker.opened_at = None;
+    }
+
+    /// Record a failed call; returns whether the circuit is now open
+    ///
+    /// Only provider-side failures (see [`Error::is_retryable`]) count
+    /// towards opening the circuit.
+    pub fn record_failure(&self, error: &anyhow::Error) -> bool {
+        let provider_failure = error
+            .chain()
+            .filter_map(|cause| cause.downcast_ref::<Error>())
+            .any(Error::is_retryable);
+        let mut breaker = self.breaker.lock();
+        if !provider_failure {
+            // A half-open probe that failed for another reason proved nothing
+            if breaker.state == CircuitState::HalfOpen {
+                breaker.state = CircuitState::Open;
+            }
+            return breaker.state == CircuitState::Open;
+        }
+
+        breaker.failures += 1;
+        let trip = breaker.state == CircuitState::HalfOpen
+            || breaker.failures >= self.config.failure_threshold.max(1);
+        if trip && breaker.state != CircuitState::Open {
+            warn!(
+                "Opening provider circuit after {} failure(s)",
+                breaker.failures
+            );
+            breaker.state = CircuitState::Open;
+            breaker.opened_at = Some(Instant::now());
+        }
+        breaker.state == CircuitState::Open
+    }
+
+    /// Build the response for a message that cannot reach the provider
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tenant's action is [`DegradedAction::Fail`].
+    pub fn respond(&self, message: Message, reason: DegradedReason) -> anyhow::Result<Response> {
+        let tenant = message
+            .metadata
+            .get(TENANT_METADATA_KEY)
+            .and_then(serde_json::Value::as_str);
+
+        let response = match self.config.action_for(tenant) {
+            DegradedAction::Fail => {
+                return Err(match reason {
+                    DegradedReason::CircuitOpen => {
+                        Error::Provider("Provider circuit is open".into())
+                    }
+                    DegradedReason::BudgetExhausted => Error::RateLimit,
+                }
+                .into());
+            }
+            DegradedAction::Canned { message: text } => {
+                Response::text(message.conversation_id.clone(), text.clone())
+            }
+            DegradedAction::Queue { acknowledgement } => {
+                let response =
+                    Response::text(message.conversation_id.clone(), acknowledgement.clone())
+                        .with_metadata("queued", serde_json::json!(true));
+                self.queue.lock().push_back(message);
+                response
+            }
+            DegradedAction::Busy { retry_after } => Response::error(
+                message.conversation_id,
+                ResponseError::new("service_busy", "The service is busy, please retry later")
+                    .retryable(true)
+                    .retry_after(retry_after.as_secs()),
+            ),
+        };
+
+        Ok(response.with_metadata(DEGRADED_METADATA_KEY, serde_json::json!(reason)))
+    }
+
+    /// Number of messages waiting for the provider to recover
+    #[must_use]
+    pub fn queued(&self) -> usize {
+        self.queue.lock().len()
+    }
+
+    /// Remove and return queued messages, oldest first
+    pub fn drain_queue(&self) -> Vec<Message> {
+        self.queue.lock().drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn mode(default_action: DegradedAction) -> DegradedMode {
+        DegradedMode::new(DegradedModeConfig {
+            failure_threshold: 2,
+            reset_timeout: Duration::from_millis(20),
+            default_action,
+            ..DegradedModeConfig::default()
+        })
+    }
+
+    fn provider_error() -> anyhow::Error {
+        anyhow::Error::from(Error::Provider("down".into())).context("Pipeline processing failed")
+    }
+
+    #[test]
+    fn test_circuit_opens_and_recovers() {
+        let degraded = mode(DegradedAction::default());
+        assert!(degraded.check().is_none());
+
+        assert!(!degraded.record_failure(&provider_error()));
+        assert!(degraded.record_failure(&provider_error()));
+        assert_eq!(degraded.check(), Some(DegradedReason::CircuitOpen));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(degraded.check().is_none());
+        assert_eq!(degraded.circuit_state(), CircuitState::HalfOpen);
+        assert_eq!(degraded.check(), Some(DegradedReason::CircuitOpen));
+
+        degraded.record_success();
+        assert_eq!(degraded.circuit_state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_client_errors_do_not_trip() {
+        let degraded = mode(DegradedAction::default());
+        let error = anyhow::Error::from(Error::InvalidInput("bad".into()));
+        for _ in 0..5 {
+            assert!(!degraded.record_failure(&error));
+        }
+        assert_eq!(degraded.circuit_state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_busy_response() {
+        let degraded = mode(DegradedAction::default());
+        degraded.set_budget_exhausted(true);
+        let reason = degraded.check().unwrap();
+        assert_eq!(reason, DegradedReason::BudgetExhausted);
+
+        let response = degraded.respond(Message::text("hi"), reason).unwrap();
+        let error = response.error.unwrap();
+        assert!(error.retryable);
+        assert_eq!(error.retry_after, Some(30));
+        assert_eq!(
+            response.metadata[DEGRADED_METADATA_KEY],
+            serde_json::json!("budget_exhausted")
+        );
+    }
+
+    #[test]
+    fn test_per_tenant_actions() {
+        let mut config = DegradedModeConfig::default();
+        config.tenant_actions.insert(
+            "acme".to_string(),
+            DegradedAction::Queue {
+                acknowledgement: "We'll get back to you".to_string(),
+            },
+        );
+        config
+            .tenant_actions
+            .insert("strict".to_string(), DegradedAction::Fail);
+        let degraded = DegradedMode::new(config);
+
+        let queued = Message::text("later").with_metadata(TENANT_METADATA_KEY, "acme".into());
+        let response = degraded
+            .respond(queued, DegradedReason::CircuitOpen)
+            .unwrap();
+        assert_eq!(response.content, "We'll get back to you");
+        assert_eq!(degraded.queued(), 1);
+        assert_eq!(degraded.drain_queue()[0].content, "later");
+
+        let strict = Message::text("now").with_metadata(TENANT_METADATA_KEY, "strict".into());
+        assert!(degraded
+            .respond(strict, DegradedReason::CircuitOpen)
+            .is_err());
+
+        let canned = mode(DegradedAction::Canned {
+            message: "Back soon".to_string(),
+        });
+        let response = canned
+            .respond(Message::text("hi"), DegradedReason::CircuitOpen)
+            .unwrap();
+        assert_eq!(response.content, "Back soon");
+        assert!(response.error.is_none());
+    }
+}
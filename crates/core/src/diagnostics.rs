@@ -0,0 +1,304 @@
+//! Debug bundles for support tickets
+//!
+//! The bot keeps a short rolling record of execution traces and errors per
+//! conversation. [`Bot::debug_bundle`](crate::Bot::debug_bundle) combines
+//! it with a configuration and metrics snapshot into a [`DebugBundle`] that
+//! is safe to share: credential-like configuration values are dropped,
+//! personal data in error messages is masked with [`redact_pii`], and error
+//! details from messages flagged as sensitive are withheld entirely.
+
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bot::BotMetrics,
+    config::BotConfig,
+    message::Response,
+    pipeline::{ExecutionTrace, TRACE_METADATA_KEY},
+    sanitize::{is_sensitive_key, redact_pii},
+};
+
+/// Traces and errors retained per conversation
+pub const MAX_RECORDS_PER_CONVERSATION: usize = 20;
+
+/// Message substituted for error details of sensitive exchanges
+const WITHHELD: &str = "[withheld: sensitive]";
+
+/// An error observed while serving a conversation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorRecord {
+    /// When the error occurred
+    pub timestamp: DateTime<Utc>,
+    /// Error code, e.g. `service_busy` or `pipeline_error`
+    pub code: String,
+    /// Redacted error message
+    pub message: String,
+    /// Whether a retry may succeed
+    pub retryable: bool,
+}
+
+/// Point-in-time copy of the bot metrics
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    /// Requests received
+    pub requests_total: u64,
+    /// Requests answered without error
+    pub success_total: u64,
+    /// Requests answered with an error
+    pub errors_total: u64,
+    /// Success rate as a percentage
+    pub success_rate: f64,
+    /// Average response time in milliseconds
+    pub average_response_time_ms: Option<u64>,
+}
+
+impl From<&BotMetrics> for MetricsSnapshot {
+    fn from(metrics: &BotMetrics) -> Self {
+        Self {
+            requests_total: metrics.requests_total(),
+            success_total: metrics.success_total(),
+            errors_total: metrics.errors_total(),
+            success_rate: metrics.success_rate(),
+            average_response_time_ms: metrics
+                .average_response_time()
+                .map(|d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX)),
+        }
+    }
+}
+
+/// Redacted diagnostics for one conversation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DebugBundle {
+    /// When the bundle was produced
+    pub generated_at: DateTime<Utc>,
+    /// Library version
+    pub version: String,
+    /// Conversation the bundle describes
+    pub conversation_id: String,
+    /// Redacted bot configuration
+    pub config: serde_json::Value,
+    /// Recent execution traces, oldest first
+    pub traces: Vec<ExecutionTrace>,
+    /// Recent errors, oldest first
+    pub errors: Vec<ErrorRecord>,
+    /// Bot-wide metrics
+    pub metrics: MetricsSnapshot,
+}
+
+impl DebugBundle {
+    /// Assemble a bundle from the bot's state
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration cannot be serialized.
+    pub fn new(
+        conversation_id: impl Into<String>,
+        config: &BotConfig,
+        metrics: &BotMetrics,
+        recorder: &DiagnosticsRecorder,
+    ) -> Result<Self> {
+        let conversation_id = conversation_id.into();
+        let mut config = serde_json::to_value(config)?;
+        redact_json(&mut config);
+        let (traces, errors) = recorder.records(&conversation_id);
+
+        Ok(Self {
+            generated_at: Utc::now(),
+            version: crate::VERSION.to_string(),
+            conversation_id,
+            config,
+            traces,
+            errors,
+            metrics: MetricsSnapshot::from(metrics),
+        })
+    }
+
+    /// Serialize the bundle as pretty-printed JSON
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if serialization fails.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[derive(Debug, Default)]
+struct ConversationLog {
+    traces: VecDeque<ExecutionTrace>,
+    errors: VecDeque<ErrorRecord>,
+}
+
+/// Rolling per-conversation record of traces and errors
+#[derive(Debug, Default)]
+pub struct DiagnosticsRecorder {
+    conversations: DashMap<String, ConversationLog>,
+}
+
+impl DiagnosticsRecorder {
+    /// Create an empty recorder
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the trace and error, if any, carried by a response
+    ///
+    /// Traces are only present when the pipeline attaches them.
+    pub fn record_response(&self, response: &Response, sensitive: bool) {
+        let trace = response
+            .metadata
+            .get(TRACE_METADATA_KEY)
+            .and_then(|value| serde_json::from_value::<ExecutionTrace>(value.clone()).ok());
+        if let Some(trace) = trace {
+            let mut log = self
+                .conversations
+                .entry(response.conversation_id.clone())
+                .or_default();
+            push_bounded(&mut log.traces, trace);
+        }
+
+        if let Some(error) = &response.error {
+            self.record_error(
+                &response.conversation_id,
+                &error.code,
+                &error.message,
+                error.retryable,
+                sensitive || response.flags.sensitive,
+            );
+        }
+    }
+
+    /// Record an error for a conversation
+    ///
+    /// The message is redacted before it is stored, or withheld entirely
+    /// when the exchange is flagged as sensitive.
+    pub fn record_error(
+        &self,
+        conversation_id: &str,
+        code: &str,
+        message: &str,
+        retryable: bool,
+        sensitive: bool,
+    ) {
+        let record = ErrorRecord {
+            timestamp: Utc::now(),
+            code: code.to_string(),
+            message: if sensitive {
+                WITHHELD.to_string()
+            } else {
+                redact_pii(message)
+            },
+            retryable,
+        };
+        let mut log = self
+            .conversations
+            .entry(conversation_id.to_string())
+            .or_default();
+        push_bounded(&mut log.errors, record);
+    }
+
+    /// Recent traces and errors for a conversation, oldest first
+    #[must_use]
+    pub fn records(&self, conversation_id: &str) -> (Vec<ExecutionTrace>, Vec<ErrorRecord>) {
+        self.conversations
+            .get(conversation_id)
+            .map(|log| {
+                (
+                    log.traces.iter().cloned().collect(),
+                    log.errors.iter().cloned().collect(),
+                )
+            })
+            .unwrap_or_default()
+    }
+
+    /// Forget everything recorded for a conversation
+    pub fn clear(&self, conversation_id: &str) {
+        self.conversations.remove(conversation_id);
+    }
+}
+
+fn push_bounded<T>(records: &mut VecDeque<T>, record: T) {
+    if records.len() == MAX_RECORDS_PER_CONVERSATION {
+        records.pop_front();
+    }
+    records.push_back(record);
+}
+
+/// Drop credential-like string values from the configuration snapshot
+///
+/// Only strings are dropped so numeric settings such as `max_tokens`, which
+/// match the sensitive key list, survive. The configuration is operator
+/// supplied, so it is not run through [`redact_pii`].
+fn redact_json(value: &mut serde_json::Value) {
+    let mut stack = vec![value];
+    while let Some(value) = stack.pop() {
+        match value {
+            serde_json::Value::Object(map) => {
+                map.retain(|key, value| !(value.is_string() && is_sensitive_key(key)));
+                stack.extend(map.values_mut());
+            }
+            serde_json::Value::Array(items) => stack.extend(items.iter_mut()),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::ResponseError;
+
+    #[test]
+    fn test_errors_are_redacted_and_bounded() {
+        let recorder = DiagnosticsRecorder::new();
+        recorder.record_error(
+            "conv",
+            "pipeline_error",
+            "Lookup failed for bob@example.com",
+            false,
+            false,
+        );
+        recorder.record_response(
+            &Response::error("conv", ResponseError::new("bad_request", "SSN 123-45-6789")),
+            true,
+        );
+
+        let (_, errors) = recorder.records("conv");
+        assert_eq!(errors[0].message, "Lookup failed for [redacted]");
+        assert_eq!(errors[1].code, "bad_request");
+        assert_eq!(errors[1].message, WITHHELD);
+
+        for _ in 0..MAX_RECORDS_PER_CONVERSATION {
+            recorder.record_error("conv", "x", "y", true, false);
+        }
+        assert_eq!(
+            recorder.records("conv").1.len(),
+            MAX_RECORDS_PER_CONVERSATION
+        );
+        assert!(recorder.records("other").1.is_empty());
+    }
+
+    #[test]
+    fn test_config_snapshot_drops_secrets() {
+        let mut value = serde_json::json!({
+            "model": "us.anthropic.claude-opus-4-1-20250805-v1:0",
+            "max_tokens": 2048,
+            "auth_token": "abc",
+            "nested": [{ "api_key": "k", "region": "us-east-1" }],
+        });
+        redact_json(&mut value);
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "model": "us.anthropic.claude-opus-4-1-20250805-v1:0",
+                "max_tokens": 2048,
+                "nested": [{ "region": "us-east-1" }],
+            })
+        );
+    }
+}
@@ -0,0 +1,395 @@
+//! Structured diffs between an answer and its regeneration
+//!
+//! Both responses are split into sentences and aligned. Sentences present in
+//! both are unchanged; the rest are added or removed. A removed sentence
+//! that closely resembles an added one is reported as changed instead, which
+//! is how a corrected figure or name shows up. Resemblance is measured with
+//! an [`Embedder`] when one is configured, and by word overlap otherwise.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    vector::{DistanceMetric, Embedder},
+};
+
+/// Response metadata key holding the [`ResponseDiff`] against the replaced answer
+pub const RESPONSE_DIFF_METADATA_KEY: &str = "response_diff";
+
+/// How a sentence of the new response relates to the old one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SegmentStatus {
+    /// Also in the old response
+    Unchanged,
+    /// Rewords or corrects an old sentence
+    Changed,
+    /// New content
+    Added,
+}
+
+/// A sentence of the new response, in order, for highlighting
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffSegment {
+    /// Sentence text
+    pub text: String,
+    /// Relation to the old response
+    pub status: SegmentStatus,
+}
+
+/// An old sentence and the new sentence that replaced it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SentenceChange {
+    /// Sentence in the old response
+    pub before: String,
+    /// Sentence in the new response
+    pub after: String,
+    /// Similarity between the two, from 0 to 1
+    pub similarity: f32,
+}
+
+/// Differences between an old and a new response
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResponseDiff {
+    /// Sentences of the new response, each with its status
+    pub segments: Vec<DiffSegment>,
+    /// Sentences only in the new response
+    pub added: Vec<String>,
+    /// Sentences only in the old response
+    pub removed: Vec<String>,
+    /// Old sentences replaced by similar new ones
+    pub changed: Vec<SentenceChange>,
+    /// Sentences in both responses
+    pub unchanged: usize,
+}
+
+impl ResponseDiff {
+    /// Whether both responses say the same thing, sentence for sentence
+    #[must_use]
+    pub fn is_identical(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Computes [`ResponseDiff`]s
+#[derive(Clone)]
+pub struct ResponseDiffer {
+    embedder: Option<Arc<dyn Embedder>>,
+    change_threshold: f32,
+}
+
+impl Default for ResponseDiffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResponseDiffer {
+    /// A differ using word overlap and a change threshold of 0.5
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            embedder: None,
+            change_threshold: 0.5,
+        }
+    }
+
+    /// Measure sentence similarity with embeddings; the threshold becomes 0.8
+    #[must_use]
+    pub fn with_embedder(mut self, embedder: Arc<dyn Embedder>) -> Self {
+        self.embedder = Some(embedder);
+        self.change_threshold = 0.8;
+        self
+    }
+
+    /// Similarity at or above which a removed and an added sentence count as changed
+    #[must_use]
+    pub fn with_change_threshold(mut self, threshold: f32) -> Self {
+        self.change_threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Diff `old` against `new`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the embedder fails or returns the wrong number of vectors.
+    pub async fn diff(&self, old: &str, new: &str) -> Result<ResponseDiff> {
+        let old = split_sentences(old);
+        let new = split_sentences(new);
+        let (old_kept, new_kept) = align(&old, &new);
+
+        let removed: Vec<usize> = (0..old.len()).filter(|i| !old_kept[*i]).collect();
+        let added: Vec<usize> = (0..new.len()).filter(|j| !new_kept[*j]).collect();
+        let similarity = self.similarities(&old, &removed, &new, &added).await?;
+
+        // Pair the most similar sentences first
+        let mut candidates: Vec<(usize, usize, f32)> = similarity
+            .iter()
+            .enumerate()
+            .flat_map(|(r, row)| row.iter().enumerate().map(move |(a, s)| (r, a, *s)))
+            .filter(|(_, _, s)| *s >= self.change_threshold)
+            .collect();
+        candidates.sort_by(|x, y| y.2.total_cmp(&x.2));
+        let (mut paired_old, mut paired_new) = (HashSet::new(), HashSet::new());
+        let mut changed_new = HashSet::new();
+        let mut changed = Vec::new();
+        for (r, a, score) in candidates {
+            if paired_old.contains(&r) || paired_new.contains(&a) {
+                continue;
+            }
+            paired_old.insert(r);
+            paired_new.insert(a);
+            changed_new.insert(added[a]);
+            changed.push(SentenceChange {
+                before: old[removed[r]].clone(),
+                after: new[added[a]].clone(),
+                similarity: score,
+            });
+        }
+
+        let segments = new
+            .iter()
+            .enumerate()
+            .map(|(j, text)| DiffSegment {
+                text: text.clone(),
+                status: if new_kept[j] {
+                    SegmentStatus::Unchanged
+                } else if changed_new.contains(&j) {
+                    SegmentStatus::Changed
+                } else {
+                    SegmentStatus::Added
+                },
+            })
+            .collect();
+
+        Ok(ResponseDiff {
+            segments,
+            added: added
+                .iter()
+                .enumerate()
+                .filter(|(a, _)| !paired_new.contains(a))
+                .map(|(_, j)| new[*j].clone())
+                .collect(),
+            removed: removed
+                .iter()
+                .enumerate()
+                .filter(|(r, _)| !paired_old.contains(r))
+                .map(|(_, i)| old[*i].clone())
+                .collect(),
+            changed,
+            unchanged: new_kept.iter().filter(|kept| **kept).count(),
+        })
+    }
+
+    /// Similarity of every removed sentence to every added one
+    async fn similarities(
+        &self,
+        old: &[String],
+        removed: &[usize],
+        new: &[String],
+        added: &[usize],
+    ) -> Result<Vec<Vec<f32>>> {
+        if removed.is_empty() || added.is_empty() {
+            return Ok(Vec::new());
+        }
+        let Some(embedder) = &self.embedder else {
+            return Ok(removed
+                .iter()
+                .map(|i| {
+                    added
+                        .iter()
+                        .map(|j| word_overlap(&old[*i], &new[*j]))
+                        .collect()
+                })
+                .collect());
+        };
+
+        let texts: Vec<String> = removed
+            .iter()
+            .map(|i| old[*i].clone())
+            .chain(added.iter().map(|j| new[*j].clone()))
+            .collect();
+        let vectors = embedder.embed(&texts).await?;
+        if vectors.len() != texts.len() {
+            return Err(Error::Internal(format!(
+                "Embedder returned {} vectors for {} inputs",
+                vectors.len(),
+                texts.len()
+            ))
+            .into());
+        }
+        let (before, after) = vectors.split_at(removed.len());
+        Ok(before
+            .iter()
+            .map(|b| {
+                after
+                    .iter()
+                    .map(|a| 1.0 - DistanceMetric::Cosine.distance(b, a))
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+/// Split text into trimmed sentences at `.`, `!`, `?` followed by whitespace, and at line breaks
+#[must_use]
+pub fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let boundary = match c {
+            '\n' => Some(i),
+            '.' | '!' | '?' => chars
+                .peek()
+                .filter(|(_, next)| next.is_whitespace())
+                .map(|_| i + c.len_utf8()),
+            _ => None,
+        };
+        if let Some(end) = boundary {
+            push_sentence(&mut sentences, &text[start..end]);
+            start = end;
+        }
+    }
+    push_sentence(&mut sentences, &text[start..]);
+    sentences
+}
+
+fn push_sentence(sentences: &mut Vec<String>, text: &str) {
+    let text = text.trim();
+    if !text.is_empty() {
+        sentences.push(text.to_string());
+    }
+}
+
+/// Mark the sentences on the longest common subsequence of `old` and `new`
+fn align(old: &[String], new: &[String]) -> (Vec<bool>, Vec<bool>) {
+    let (n, m) = (old.len(), new.len());
+    let mut lengths = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut old_kept, mut new_kept) = (vec![false; n], vec![false; m]);
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i] == new[j] {
+            old_kept[i] = true;
+            new_kept[j] = true;
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    (old_kept, new_kept)
+}
+
+/// Jaccard similarity of the lowercase word sets
+fn word_overlap(a: &str, b: &str) -> f32 {
+    let words = |text: &str| -> HashSet<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    };
+    let (a, b) = (words(a), words(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f32 / union as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    #[test]
+    fn test_split_sentences() {
+        assert_eq!(
+            split_sentences("Paris is big. It has 2.1 million people!\nSee you?"),
+            ["Paris is big.", "It has 2.1 million people!", "See you?"]
+        );
+        assert!(split_sentences("  ").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_diff_reports_added_removed_and_changed() {
+        let old = "Paris is the capital of France. It has 2.1 million people. It is sunny.";
+        let new = "Paris is the capital of France. It has 2.2 million people. Visit in spring.";
+        let diff = ResponseDiffer::new().diff(old, new).await.unwrap();
+
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].before, "It has 2.1 million people.");
+        assert_eq!(diff.changed[0].after, "It has 2.2 million people.");
+        assert_eq!(diff.added, ["Visit in spring."]);
+        assert_eq!(diff.removed, ["It is sunny."]);
+        let statuses: Vec<SegmentStatus> = diff.segments.iter().map(|s| s.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                SegmentStatus::Unchanged,
+                SegmentStatus::Changed,
+                SegmentStatus::Added
+            ]
+        );
+
+        assert!(ResponseDiffer::new()
+            .diff(old, old)
+            .await
+            .unwrap()
+            .is_identical());
+    }
+
+    struct TopicEmbedder;
+
+    #[async_trait]
+    impl Embedder for TopicEmbedder {
+        fn model(&self) -> &str {
+            "topic"
+        }
+
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    if text.contains("population") || text.contains("people") {
+                        vec![1.0, 0.0]
+                    } else {
+                        vec![0.0, 1.0]
+                    }
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_diff_uses_embeddings_when_configured() {
+        let differ = ResponseDiffer::new().with_embedder(Arc::new(TopicEmbedder));
+        let diff = differ
+            .diff(
+                "The population is 2.1 million.",
+                "About 2.2 million people live there.",
+            )
+            .await
+            .unwrap();
+        assert_eq!(diff.changed.len(), 1);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+    }
+}
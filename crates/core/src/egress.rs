@@ -0,0 +1,214 @@
+//! Guards for outbound requests to URLs chosen by clients or the model
+//!
+//! Webhook callbacks and web fetches go to addresses the operator does not
+//! control, so they must not reach loopback, private, link-local, or other
+//! reserved addresses such as cloud metadata endpoints. [`check_url`] parses
+//! a URL the way the HTTP client does, so spellings like `http://127.1/`,
+//! `http://2130706433/`, or `http://127.0.0.1\@example.com/` are judged by
+//! the address actually connected to. [`PublicResolver`] applies the same
+//! rule to every address a hostname resolves to, at connect time, so names
+//! pointing at internal addresses are blocked too.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use anyhow::Result;
+use url::{Host, Url};
+
+use crate::error::Error;
+
+/// Parse an `http` or `https` URL, rejecting private and reserved hosts
+///
+/// # Errors
+///
+/// Returns an error if the URL does not parse, is not `http` or `https`,
+/// has no host, or names a private or reserved host.
+pub fn check_url(url: &str) -> Result<Url> {
+    let parsed = parse_http_url(url)?;
+    if is_private_url(&parsed) {
+        return Err(Error::InvalidInput(format!(
+            "URL targets a private or reserved address: {url}"
+        ))
+        .into());
+    }
+    Ok(parsed)
+}
+
+/// Parse an `http` or `https` URL with a host, without checking the host
+///
+/// # Errors
+///
+/// Returns an error if the URL does not parse, is not `http` or `https`, or
+/// has no host.
+pub fn parse_http_url(url: &str) -> Result<Url> {
+    let parsed =
+        Url::parse(url).map_err(|e| Error::InvalidInput(format!("Invalid URL {url}: {e}")))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host().is_none() {
+        return Err(Error::InvalidInput(format!("URL must be http(s): {url}")).into());
+    }
+    Ok(parsed)
+}
+
+/// Whether a parsed URL's host is a loopback, private, link-local, or reserved target
+#[must_use]
+pub fn is_private_url(url: &Url) -> bool {
+    match url.host() {
+        Some(Host::Ipv4(ip)) => is_private_ip(IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => is_private_ip(IpAddr::V6(ip)),
+        Some(Host::Domain(domain)) => is_private_domain(domain),
+        None => true,
+    }
+}
+
+/// Whether a hostname is reserved for local or internal use
+///
+/// Only the name is checked; [`PublicResolver`] checks what it resolves to.
+#[must_use]
+pub fn is_private_domain(domain: &str) -> bool {
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+    domain == "localhost"
+        || [".localhost", ".local", ".internal"]
+            .iter()
+            .any(|suffix| domain.ends_with(suffix))
+}
+
+/// Whether an address is loopback, private, link-local, or otherwise reserved
+#[must_use]
+pub fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_private_ipv4(ip),
+        IpAddr::V6(ip) => ip
+            .to_ipv4_mapped()
+            .or_else(|| nat64_ipv4(ip))
+            .map_or_else(|| is_private_ipv6(ip), is_private_ipv4),
+    }
+}
+
+fn is_private_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // Shared address space, benchmarking, and reserved ranges
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240
+        || (a == 192 && b == 0 && c == 0)
+}
+
+fn is_private_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, link-local, and documentation ranges
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8)
+}
+
+/// The IPv4 address embedded in a NAT64 address (`64:ff9b::/96`)
+fn nat64_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let octets = ip.octets();
+    (octets[..12] == [0, 0x64, 0xff, 0x9b, 0, 0, 0, 0, 0, 0, 0, 0])
+        .then(|| Ipv4Addr::new(octets[12], octets[13], octets[14], octets[15]))
+}
+
+/// DNS resolver that drops private and reserved addresses
+///
+/// A name that resolves only to such addresses fails to resolve, so the
+/// request never connects. IP-literal URLs bypass resolution, so pair this
+/// with [`check_url`].
+#[cfg(any(feature = "webhooks", feature = "web-fetch"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PublicResolver;
+
+#[cfg(any(feature = "webhooks", feature = "web-fetch"))]
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: hyper::client::connect::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs: Vec<std::net::SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| !is_private_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(
+                    format!("{host} resolves only to private or reserved addresses").into(),
+                );
+            }
+            let addrs: reqwest::dns::Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// A client builder that only connects to public addresses
+///
+/// Resolution goes through [`PublicResolver`], and system proxies are not
+/// used, since a proxy would resolve the target on the client's behalf.
+#[cfg(any(feature = "webhooks", feature = "web-fetch"))]
+pub fn public_client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .dns_resolver(std::sync::Arc::new(PublicResolver))
+        .no_proxy()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_url_rejects_private_targets() {
+        for url in [
+            "http://localhost:8080/hook",
+            "http://127.0.0.1/hook",
+            "https://10.1.2.3/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://metadata.google.internal/computeMetadata",
+            "http://user@192.168.0.1:80/hook",
+            "http://[::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+            "http://[64:ff9b::a9fe:a9fe]/hook",
+            "http://0.0.0.0/hook",
+            // Spellings the HTTP client resolves to 127.0.0.1
+            "http://127.0.0.1\\@example.com/",
+            "http://2130706433/",
+            "http://0x7f.1/",
+            "http://127.1/",
+            "http://0177.0.0.1/",
+            "http://LOCALHOST./",
+        ] {
+            assert!(check_url(url).is_err(), "{url} should be rejected");
+        }
+    }
+
+    #[test]
+    fn test_check_url_accepts_public_targets() {
+        for url in [
+            "https://hooks.example.com/cb",
+            "https://93.184.216.34:8443/cb",
+            "https://[2606:2800:220:1::]/cb",
+            "https://user@example.com/cb",
+        ] {
+            assert!(check_url(url).is_ok(), "{url} should be accepted");
+        }
+        for url in ["ftp://example.com/", "file:///etc/passwd", "not a url"] {
+            assert!(check_url(url).is_err(), "{url} should be rejected");
+        }
+    }
+
+    #[cfg(any(feature = "webhooks", feature = "web-fetch"))]
+    #[tokio::test]
+    async fn test_public_resolver_rejects_private_names() {
+        use reqwest::dns::Resolve;
+
+        let name: hyper::client::connect::dns::Name = "localhost".parse().unwrap();
+        assert!(PublicResolver.resolve(name).await.is_err());
+    }
+}
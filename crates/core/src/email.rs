@@ -0,0 +1,918 @@
+//! Email channel adapter
+//!
+//! Inbound mail is parsed from raw RFC 5322 messages, as delivered by an SES
+//! receipt rule into S3 or fetched from an IMAP mailbox. Threads map to
+//! conversations through the `Message-ID`, `In-Reply-To`, and `References`
+//! headers, attachments become message [`Attachment`]s, and replies are sent
+//! as `multipart/alternative` mail with plaintext and HTML bodies and the
+//! headers mail clients need to keep them in the same thread. Replies can
+//! be paced per recipient with a [`RateShape`].
+
+use std::fmt::Write as _;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    bot::Bot,
+    error::Error,
+    ingest::{DocumentRef, HtmlExtractor, RawDocument, TextExtractor},
+    message::{Attachment, Message, Response, CHANNEL_METADATA_KEY},
+    pipeline::FormatStage,
+    shaping::{RateShape, RateShaper},
+};
+
+/// Message metadata key holding the inbound `Message-ID`
+pub const MESSAGE_ID_METADATA_KEY: &str = "email_message_id";
+
+/// Message metadata key holding the inbound subject
+pub const SUBJECT_METADATA_KEY: &str = "email_subject";
+
+/// Deepest multipart nesting that is parsed
+const MAX_MIME_DEPTH: usize = 8;
+
+/// An attachment carried by an inbound email
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailAttachment {
+    /// Declared filename
+    pub filename: String,
+    /// MIME type
+    pub mime_type: String,
+    /// Decoded content
+    pub data: Vec<u8>,
+}
+
+/// A parsed inbound email
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InboundEmail {
+    /// `Message-ID`, without angle brackets
+    pub message_id: String,
+    /// `In-Reply-To`, without angle brackets
+    pub in_reply_to: Option<String>,
+    /// `References`, oldest first, without angle brackets
+    pub references: Vec<String>,
+    /// Sender address
+    pub from: String,
+    /// Recipient addresses
+    pub to: Vec<String>,
+    /// Decoded subject
+    pub subject: String,
+    /// `text/plain` body
+    pub text_body: Option<String>,
+    /// `text/html` body
+    pub html_body: Option<String>,
+    /// Attachments, in message order
+    pub attachments: Vec<EmailAttachment>,
+}
+
+impl InboundEmail {
+    /// Parse a raw RFC 5322 message
+    ///
+    /// Handles folded headers, RFC 2047 encoded words, nested multipart
+    /// bodies, and base64 or quoted-printable transfer encodings.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message has no sender.
+    pub fn parse(raw: &[u8]) -> Result<Self> {
+        let raw = String::from_utf8_lossy(raw).replace("\r\n", "\n");
+        let (headers, body) = split_part(&raw);
+
+        let from = header(&headers, "from")
+            .map(extract_address)
+            .filter(|from| !from.is_empty())
+            .ok_or_else(|| Error::InvalidInput("Email has no sender".into()))?;
+        let message_id = header(&headers, "message-id")
+            .and_then(|value| message_ids(value).into_iter().next())
+            .unwrap_or_else(|| format!("{}@generated", Uuid::new_v4()));
+
+        let mut email = Self {
+            message_id,
+            in_reply_to: header(&headers, "in-reply-to")
+                .and_then(|value| message_ids(value).into_iter().next()),
+            references: header(&headers, "references")
+                .map(message_ids)
+                .unwrap_or_default(),
+            from,
+            to: header(&headers, "to")
+                .map(|value| value.split(',').map(extract_address).collect())
+                .unwrap_or_default(),
+            subject: header(&headers, "subject")
+                .map(decode_words)
+                .unwrap_or_default(),
+            text_body: None,
+            html_body: None,
+            attachments: Vec::new(),
+        };
+        email.collect_part(&headers, body, 0);
+        Ok(email)
+    }
+
+    /// Conversation IDs this message may continue, most specific first
+    fn thread_candidates(&self) -> impl Iterator<Item = &str> {
+        self.in_reply_to
+            .iter()
+            .chain(self.references.iter().rev())
+            .map(String::as_str)
+    }
+
+    /// Body text for the bot, falling back to the HTML body's text
+    #[must_use]
+    pub fn text(&self) -> String {
+        if let Some(text) = &self.text_body {
+            return text.trim().to_string();
+        }
+        let Some(html) = &self.html_body else {
+            return String::new();
+        };
+        let document = RawDocument {
+            reference: DocumentRef::new(format!("email://{}", self.message_id)),
+            content_type: Some("text/html".to_string()),
+            bytes: html.as_bytes().to_vec(),
+        };
+        HtmlExtractor.extract(&document).unwrap_or_default()
+    }
+
+    fn collect_part(&mut self, headers: &[(String, String)], body: &str, depth: usize) {
+        let content_type = header(headers, "content-type").unwrap_or("text/plain");
+        let mime_type = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+
+        if mime_type.starts_with("multipart/") {
+            if depth >= MAX_MIME_DEPTH {
+                warn!("Skipping multipart nested deeper than {}", MAX_MIME_DEPTH);
+                return;
+            }
+            let Some(boundary) = header_param(content_type, "boundary") else {
+                return;
+            };
+            for part in split_multipart(body, &boundary) {
+                let (part_headers, part_body) = split_part(&part);
+                self.collect_part(&part_headers, part_body, depth + 1);
+            }
+            return;
+        }
+
+        let data = decode_transfer(
+            header(headers, "content-transfer-encoding").unwrap_or("7bit"),
+            body,
+        );
+        let disposition = header(headers, "content-disposition").unwrap_or_default();
+        let filename = header_param(disposition, "filename")
+            .or_else(|| header_param(content_type, "name"))
+            .map(|name| decode_words(&name));
+
+        if disposition.to_lowercase().starts_with("attachment") || filename.is_some() {
+            self.attachments.push(EmailAttachment {
+                filename: filename.unwrap_or_else(|| "attachment".to_string()),
+                mime_type,
+                data,
+            });
+        } else if mime_type == "text/plain" && self.text_body.is_none() {
+            self.text_body = Some(String::from_utf8_lossy(&data).into_owned());
+        } else if mime_type == "text/html" && self.html_body.is_none() {
+            self.html_body = Some(String::from_utf8_lossy(&data).into_owned());
+        }
+    }
+}
+
+/// A reply ready to be sent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutboundEmail {
+    /// Sender address
+    pub from: String,
+    /// Recipient address
+    pub to: String,
+    /// Subject, prefixed with `Re:`
+    pub subject: String,
+    /// `Message-ID` of the reply, without angle brackets
+    pub message_id: String,
+    /// `Message-ID` being answered
+    pub in_reply_to: String,
+    /// Thread history, oldest first
+    pub references: Vec<String>,
+    /// Plaintext alternative
+    pub text_body: String,
+    /// HTML alternative
+    pub html_body: String,
+    /// Send time
+    pub date: DateTime<Utc>,
+}
+
+impl OutboundEmail {
+    /// Build the reply to `inbound` carrying the bot's response
+    #[must_use]
+    pub fn reply(inbound: &InboundEmail, response: &Response, from: &str) -> Self {
+        let domain = from.rsplit_once('@').map_or("localhost", |(_, d)| d);
+        let subject = if inbound.subject.to_lowercase().starts_with("re:") {
+            inbound.subject.clone()
+        } else {
+            format!("Re: {}", inbound.subject)
+        };
+        let mut references = inbound.references.clone();
+        references.push(inbound.message_id.clone());
+
+        Self {
+            from: from.to_string(),
+            to: inbound.from.clone(),
+            subject,
+            message_id: format!("{}@{domain}", Uuid::new_v4()),
+            in_reply_to: inbound.message_id.clone(),
+            references,
+            text_body: response.content.clone(),
+            html_body: FormatStage::new().to_html(&response.content),
+            date: Utc::now(),
+        }
+    }
+
+    /// Render the reply as a raw MIME message
+    #[must_use]
+    pub fn to_mime(&self) -> String {
+        let boundary = format!("alt-{}", Uuid::new_v4().simple());
+        let references = self
+            .references
+            .iter()
+            .map(|id| format!("<{id}>"))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut mime = String::new();
+        for (name, value) in [
+            ("From", self.from.clone()),
+            ("To", self.to.clone()),
+            ("Subject", encode_word(&self.subject)),
+            ("Date", self.date.to_rfc2822()),
+            ("Message-ID", format!("<{}>", self.message_id)),
+            ("In-Reply-To", format!("<{}>", self.in_reply_to)),
+            ("References", references),
+            ("MIME-Version", "1.0".to_string()),
+            (
+                "Content-Type",
+                format!("multipart/alternative; boundary=\"{boundary}\""),
+            ),
+        ] {
+            let _ = write!(mime, "{name}: {value}\r\n");
+        }
+        mime.push_str("\r\n");
+
+        for (content_type, body) in [
+            ("text/plain", &self.text_body),
+            ("text/html", &self.html_body),
+        ] {
+            let _ = write!(
+                mime,
+                "--{boundary}\r\nContent-Type: {content_type}; charset=utf-8\r\n\
+                 Content-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+                wrap_base64(body.as_bytes())
+            );
+        }
+        let _ = write!(mime, "--{boundary}--\r\n");
+        mime
+    }
+}
+
+/// A raw message waiting to be processed
+#[derive(Debug, Clone)]
+pub struct RawEmail {
+    /// Source-specific handle used to acknowledge the message
+    pub id: String,
+    /// RFC 5322 bytes
+    pub bytes: Vec<u8>,
+}
+
+/// A mailbox the adapter polls for inbound mail
+///
+/// Implement it over an IMAP client to serve an IMAP mailbox; SES receipt
+/// rules that store mail in S3 are served by [`SesS3MailSource`].
+#[async_trait]
+pub trait MailSource: Send + Sync {
+    /// Messages not yet acknowledged
+    async fn fetch(&self) -> Result<Vec<RawEmail>>;
+
+    /// Mark a message as processed so it is not fetched again
+    async fn ack(&self, id: &str) -> Result<()>;
+}
+
+/// Sends outbound mail
+#[async_trait]
+pub trait MailTransport: Send + Sync {
+    /// Send a reply, returning the provider's message ID
+    async fn send(&self, email: &OutboundEmail) -> Result<String>;
+}
+
+/// Maps email threads to conversation IDs
+#[derive(Debug, Default)]
+pub struct ThreadIndex {
+    conversations: DashMap<String, String>,
+}
+
+impl ThreadIndex {
+    /// Create an empty index
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The conversation an inbound message belongs to
+    ///
+    /// Known `In-Reply-To` and `References` IDs are tried first. Otherwise
+    /// the thread root (the first reference, or the message itself) names
+    /// the conversation, so threads survive a restart of the index.
+    #[must_use]
+    pub fn conversation_for(&self, email: &InboundEmail) -> String {
+        if let Some(conversation) = email
+            .thread_candidates()
+            .find_map(|id| self.conversations.get(id))
+        {
+            return conversation.clone();
+        }
+        let root = email.references.first().unwrap_or(&email.message_id);
+        format!("email:{root}")
+    }
+
+    /// Associate a message ID with a conversation
+    pub fn remember(&self, message_id: impl Into<String>, conversation_id: impl Into<String>) {
+        self.conversations
+            .insert(message_id.into(), conversation_id.into());
+    }
+}
+
+/// Routes email through a [`Bot`]
+pub struct EmailAdapter {
+    bot: Bot,
+    transport: Arc<dyn MailTransport>,
+    threads: ThreadIndex,
+    from_address: String,
+    shaper: Option<RateShaper>,
+}
+
+impl EmailAdapter {
+    /// Create an adapter that replies from `from_address`
+    #[must_use]
+    pub fn new(
+        bot: Bot,
+        transport: Arc<dyn MailTransport>,
+        from_address: impl Into<String>,
+    ) -> Self {
+        Self {
+            bot,
+            transport,
+            threads: ThreadIndex::new(),
+            from_address: from_address.into(),
+            shaper: None,
+        }
+    }
+
+    /// Pace replies to `shape`, queueing per recipient
+    ///
+    /// Sent mail cannot be edited, so the coalesce window is unused.
+    #[must_use]
+    pub fn with_rate_shape(mut self, shape: RateShape) -> Self {
+        self.shaper = Some(RateShaper::new(shape));
+        self
+    }
+
+    /// Thread to conversation mapping
+    #[must_use]
+    pub fn threads(&self) -> &ThreadIndex {
+        &self.threads
+    }
+
+    /// Convert an inbound email into a bot message
+    #[must_use]
+    pub fn to_message(&self, email: &InboundEmail) -> Message {
+        let conversation_id = self.threads.conversation_for(email);
+        let mut message = Message::text(email.text())
+            .with_conversation_id(conversation_id)
+            .with_user_id(email.from.clone())
+            .with_metadata(CHANNEL_METADATA_KEY, serde_json::json!("email"))
+            .with_metadata(MESSAGE_ID_METADATA_KEY, serde_json::json!(email.message_id))
+            .with_metadata(SUBJECT_METADATA_KEY, serde_json::json!(email.subject));
+        for attachment in &email.attachments {
+            message = message.with_attachment(
+                Attachment::new(
+                    attachment.filename.clone(),
+                    attachment.mime_type.clone(),
+                    attachment.data.len(),
+                    format!("email://{}/{}", email.message_id, attachment.filename),
+                )
+                .with_data(attachment.data.clone()),
+            );
+        }
+        message
+    }
+
+    /// Process one raw email and send the reply
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the email cannot be parsed, processing fails, or
+    /// the reply cannot be sent.
+    pub async fn handle(&self, raw: &[u8]) -> Result<OutboundEmail> {
+        let email = InboundEmail::parse(raw)?;
+        let message = self.to_message(&email);
+        let conversation_id = message.conversation_id.clone();
+        self.threads
+            .remember(email.message_id.clone(), conversation_id.clone());
+        debug!(
+            "Email {} mapped to conversation {}",
+            email.message_id, conversation_id
+        );
+
+        let response = self.bot.process(message).await?;
+        let reply = OutboundEmail::reply(&email, &response, &self.from_address);
+        if let Some(shaper) = &self.shaper {
+            shaper.acquire(&reply.to).await;
+        }
+        self.transport.send(&reply).await?;
+        self.threads
+            .remember(reply.message_id.clone(), conversation_id);
+        Ok(reply)
+    }
+
+    /// Process every pending message in a mailbox
+    ///
+    /// Messages that fail are logged and left unacknowledged for the next
+    /// poll. Returns the number of messages answered.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the mailbox cannot be read.
+    pub async fn poll(&self, source: &dyn MailSource) -> Result<usize> {
+        let mut answered = 0;
+        for raw in source.fetch().await? {
+            match self.handle(&raw.bytes).await {
+                Ok(_) => {
+                    source.ack(&raw.id).await?;
+                    answered += 1;
+                }
+                Err(e) => warn!("Failed to process email {}: {:#}", raw.id, e),
+            }
+        }
+        info!("Answered {} email(s)", answered);
+        Ok(answered)
+    }
+}
+
+/// Inbound mail stored in S3 by an SES receipt rule
+///
+/// Acknowledged messages are deleted from the bucket.
+#[cfg(feature = "email")]
+pub struct SesS3MailSource {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+    prefix: String,
+}
+
+#[cfg(feature = "email")]
+impl SesS3MailSource {
+    /// Create a source for mail stored under `s3://bucket/prefix`
+    #[must_use]
+    pub fn new(
+        client: aws_sdk_s3::Client,
+        bucket: impl Into<String>,
+        prefix: impl Into<String>,
+    ) -> Self {
+        Self {
+            client,
+            bucket: bucket.into(),
+            prefix: prefix.into(),
+        }
+    }
+}
+
+#[cfg(feature = "email")]
+#[async_trait]
+impl MailSource for SesS3MailSource {
+    async fn fetch(&self) -> Result<Vec<RawEmail>> {
+        let mut emails = Vec::new();
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(&self.prefix)
+            .into_paginator()
+            .send();
+
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|e| Error::Network(format!("S3 list failed: {e}")))?;
+            for object in page.contents() {
+                let Some(key) = object.key() else { continue };
+                if key.ends_with('/') {
+                    continue;
+                }
+                let bytes = self
+                    .client
+                    .get_object()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .send()
+                    .await
+                    .map_err(|e| Error::Network(format!("S3 get {key} failed: {e}")))?
+                    .body
+                    .collect()
+                    .await
+                    .map_err(|e| Error::Network(format!("S3 read {key} failed: {e}")))?
+                    .into_bytes();
+                emails.push(RawEmail {
+                    id: key.to_string(),
+                    bytes: bytes.to_vec(),
+                });
+            }
+        }
+        Ok(emails)
+    }
+
+    async fn ack(&self, id: &str) -> Result<()> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(id)
+            .send()
+            .await
+            .map_err(|e| Error::Network(format!("S3 delete {id} failed: {e}")))?;
+        Ok(())
+    }
+}
+
+/// Sends replies through SES as raw MIME
+#[cfg(feature = "email")]
+pub struct SesTransport {
+    client: aws_sdk_sesv2::Client,
+}
+
+#[cfg(feature = "email")]
+impl SesTransport {
+    /// Create a transport from an SES client
+    #[must_use]
+    pub fn new(client: aws_sdk_sesv2::Client) -> Self {
+        Self { client }
+    }
+
+    /// Create a transport using credentials from the default AWS provider chain
+    pub async fn from_env() -> Self {
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        Self::new(aws_sdk_sesv2::Client::new(&config))
+    }
+}
+
+#[cfg(feature = "email")]
+#[async_trait]
+impl MailTransport for SesTransport {
+    async fn send(&self, email: &OutboundEmail) -> Result<String> {
+        use aws_sdk_sesv2::types::{EmailContent, RawMessage};
+
+        let raw = RawMessage::builder()
+            .data(aws_sdk_sesv2::primitives::Blob::new(email.to_mime()))
+            .build()
+            .map_err(|e| Error::Internal(format!("Invalid raw message: {e}")))?;
+        let output = self
+            .client
+            .send_email()
+            .destination(
+                aws_sdk_sesv2::types::Destination::builder()
+                    .to_addresses(&email.to)
+                    .build(),
+            )
+            .content(EmailContent::builder().raw(raw).build())
+            .send()
+            .await
+            .map_err(|e| Error::Network(format!("SES send failed: {e}")))?;
+        Ok(output.message_id().unwrap_or_default().to_string())
+    }
+}
+
+/// Split a part into unfolded headers and body
+fn split_part(raw: &str) -> (Vec<(String, String)>, &str) {
+    let (head, body) = raw.split_once("\n\n").unwrap_or((raw, ""));
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in head.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+    (headers, body)
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// A `name=value` parameter of a structured header such as `Content-Type`
+fn header_param(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+fn split_multipart(body: &str, boundary: &str) -> Vec<String> {
+    let delimiter = format!("--{boundary}");
+    let closing = format!("{delimiter}--");
+    let mut parts = Vec::new();
+    let mut current: Option<Vec<&str>> = None;
+
+    for line in body.lines() {
+        let trimmed = line.trim_end();
+        if trimmed == delimiter || trimmed == closing {
+            if let Some(lines) = current.take() {
+                parts.push(lines.join("\n"));
+            }
+            if trimmed == closing {
+                break;
+            }
+            current = Some(Vec::new());
+        } else if let Some(lines) = current.as_mut() {
+            lines.push(line);
+        }
+    }
+    parts
+}
+
+fn decode_transfer(encoding: &str, body: &str) -> Vec<u8> {
+    match encoding.trim().to_lowercase().as_str() {
+        "base64" => {
+            let compact: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+            base64::engine::general_purpose::STANDARD
+                .decode(compact)
+                .unwrap_or_else(|e| {
+                    warn!("Invalid base64 body: {}", e);
+                    Vec::new()
+                })
+        }
+        "quoted-printable" => decode_quoted_printable(body.as_bytes(), false),
+        _ => body.as_bytes().to_vec(),
+    }
+}
+
+/// Decode quoted-printable; `underscores` selects the RFC 2047 `Q` variant
+fn decode_quoted_printable(input: &[u8], underscores: bool) -> Vec<u8> {
+    let hex = |b: u8| char::from(b).to_digit(16);
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        match input[i] {
+            b'=' if input.get(i + 1) == Some(&b'\n') => i += 2,
+            b'=' => {
+                if let (Some(high), Some(low)) = (
+                    input.get(i + 1).and_then(|b| hex(*b)),
+                    input.get(i + 2).and_then(|b| hex(*b)),
+                ) {
+                    out.push(u8::try_from(high * 16 + low).unwrap_or(b'?'));
+                    i += 3;
+                } else {
+                    out.push(b'=');
+                    i += 1;
+                }
+            }
+            b'_' if underscores => {
+                out.push(b' ');
+                i += 1;
+            }
+            byte => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Decode RFC 2047 encoded words, e.g. `=?utf-8?B?...?=`
+fn decode_words(value: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("=?") {
+        let word = &rest[start + 2..];
+        let parsed = word.split_once('?').and_then(|(_, word)| {
+            let (encoding, word) = word.split_once('?')?;
+            let (text, after) = word.split_once("?=")?;
+            let bytes = match encoding {
+                "B" | "b" => base64::engine::general_purpose::STANDARD
+                    .decode(text)
+                    .ok()?,
+                "Q" | "q" => decode_quoted_printable(text.as_bytes(), true),
+                _ => return None,
+            };
+            Some((String::from_utf8_lossy(&bytes).into_owned(), after))
+        });
+        if let Some((text, after)) = parsed {
+            let between = &rest[..start];
+            // Whitespace between adjacent encoded words is not displayed
+            if !between.trim().is_empty() || decoded.is_empty() {
+                decoded.push_str(between);
+            }
+            decoded.push_str(&text);
+            rest = after;
+        } else {
+            decoded.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2..];
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Encode a header value as an RFC 2047 word if it is not plain ASCII
+fn encode_word(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_string()
+    } else {
+        format!(
+            "=?utf-8?B?{}?=",
+            base64::engine::general_purpose::STANDARD.encode(value)
+        )
+    }
+}
+
+fn wrap_base64(data: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD
+        .encode(data)
+        .as_bytes()
+        .chunks(76)
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+/// The address in `Name <addr>` or a bare address
+fn extract_address(value: &str) -> String {
+    match (value.rfind('<'), value.rfind('>')) {
+        (Some(start), Some(end)) if start < end => value[start + 1..end].trim().to_string(),
+        _ => value.trim().to_string(),
+    }
+}
+
+/// Message IDs in a header value, without angle brackets
+fn message_ids(value: &str) -> Vec<String> {
+    value
+        .split_whitespace()
+        .map(|id| id.trim_matches(|c| c == '<' || c == '>' || c == ','))
+        .filter(|id| !id.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BotConfig;
+    use parking_lot::Mutex;
+
+    const INBOUND: &str = "From: Jane Doe <jane@example.com>\r\n\
+To: bot@support.example.com\r\n\
+Subject: =?utf-8?Q?Caf=C3=A9_order?=\r\n\
+Message-ID: <first@example.com>\r\n\
+Content-Type: multipart/mixed;\r\n boundary=\"outer\"\r\n\
+\r\n\
+--outer\r\n\
+Content-Type: multipart/alternative; boundary=\"inner\"\r\n\
+\r\n\
+--inner\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+Where is my order? It was =\r\n\
+placed =E2=82=AC20 ago.\r\n\
+--inner\r\n\
+Content-Type: text/html\r\n\
+\r\n\
+<p>Where is my order?</p>\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: text/plain; name=\"receipt.txt\"\r\n\
+Content-Disposition: attachment; filename=\"receipt.txt\"\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+UmVjZWlwdCAjNDI=\r\n\
+--outer--\r\n";
+
+    #[derive(Default)]
+    struct RecordingTransport {
+        sent: Mutex<Vec<OutboundEmail>>,
+    }
+
+    #[async_trait]
+    impl MailTransport for RecordingTransport {
+        async fn send(&self, email: &OutboundEmail) -> Result<String> {
+            self.sent.lock().push(email.clone());
+            Ok("ses-id".to_string())
+        }
+    }
+
+    #[test]
+    fn test_parse_multipart() {
+        let email = InboundEmail::parse(INBOUND.as_bytes()).unwrap();
+        assert_eq!(email.from, "jane@example.com");
+        assert_eq!(email.to, vec!["bot@support.example.com"]);
+        assert_eq!(email.subject, "Café order");
+        assert_eq!(email.message_id, "first@example.com");
+        assert_eq!(
+            email.text_body.as_deref(),
+            Some("Where is my order? It was placed €20 ago.")
+        );
+        assert_eq!(
+            email.html_body.as_deref(),
+            Some("<p>Where is my order?</p>")
+        );
+        assert_eq!(email.attachments.len(), 1);
+        assert_eq!(email.attachments[0].filename, "receipt.txt");
+        assert_eq!(email.attachments[0].data, b"Receipt #42");
+    }
+
+    #[test]
+    fn test_parse_requires_sender() {
+        assert!(InboundEmail::parse(b"Subject: hi\r\n\r\nbody").is_err());
+    }
+
+    #[test]
+    fn test_thread_mapping() {
+        let threads = ThreadIndex::new();
+        let first = InboundEmail::parse(INBOUND.as_bytes()).unwrap();
+        assert_eq!(threads.conversation_for(&first), "email:first@example.com");
+
+        threads.remember("reply@bot", "conv-1");
+        let follow_up = InboundEmail::parse(
+            b"From: jane@example.com\r\nMessage-ID: <third@example.com>\r\n\
+              In-Reply-To: <reply@bot>\r\nReferences: <first@example.com> <reply@bot>\r\n\r\nThanks",
+        )
+        .unwrap();
+        assert_eq!(threads.conversation_for(&follow_up), "conv-1");
+
+        let forgotten = ThreadIndex::new();
+        assert_eq!(
+            forgotten.conversation_for(&follow_up),
+            "email:first@example.com"
+        );
+    }
+
+    #[test]
+    fn test_reply_headers_and_alternatives() {
+        let inbound = InboundEmail::parse(INBOUND.as_bytes()).unwrap();
+        let response = Response::text("conv", "On its way\n<soon>");
+        let reply = OutboundEmail::reply(&inbound, &response, "bot@support.example.com");
+
+        assert_eq!(reply.to, "jane@example.com");
+        assert_eq!(reply.subject, "Re: Café order");
+        assert_eq!(reply.in_reply_to, "first@example.com");
+        assert_eq!(reply.references, vec!["first@example.com"]);
+        assert!(reply.message_id.ends_with("@support.example.com"));
+        assert!(reply.html_body.contains("&lt;soon&gt;"));
+
+        let mime = reply.to_mime();
+        assert!(mime.contains("In-Reply-To: <first@example.com>\r\n"));
+        assert!(mime.contains("Content-Type: multipart/alternative;"));
+
+        let parsed = InboundEmail::parse(mime.as_bytes()).unwrap();
+        assert_eq!(parsed.subject, "Re: Café order");
+        assert_eq!(parsed.text_body.as_deref(), Some("On its way\n<soon>"));
+        assert!(parsed.html_body.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_adapter_keeps_thread_in_one_conversation() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        let transport = Arc::new(RecordingTransport::default());
+        let adapter = EmailAdapter::new(bot, transport.clone(), "bot@support.example.com");
+
+        let reply = adapter.handle(INBOUND.as_bytes()).await.unwrap();
+        assert_eq!(reply.in_reply_to, "first@example.com");
+        assert_eq!(transport.sent.lock()[0].message_id, reply.message_id);
+
+        let follow_up = format!(
+            "From: jane@example.com\r\nSubject: Re: Caf\u{e9} order\r\n\
+             Message-ID: <third@example.com>\r\nIn-Reply-To: <{}>\r\n\r\nThanks",
+            reply.message_id
+        );
+        let email = InboundEmail::parse(follow_up.as_bytes()).unwrap();
+        let message = adapter.to_message(&email);
+        assert_eq!(message.conversation_id, "email:first@example.com");
+        assert_eq!(message.metadata[CHANNEL_METADATA_KEY], "email");
+        assert_eq!(message.user_id, "jane@example.com");
+
+        let message = adapter.to_message(&InboundEmail::parse(INBOUND.as_bytes()).unwrap());
+        assert_eq!(message.attachments[0].filename, "receipt.txt");
+        assert_eq!(message.attachments[0].size, 11);
+    }
+}
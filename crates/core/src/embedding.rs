@@ -0,0 +1,342 @@
+//! Embedding cache
+//!
+//! [`CachedEmbedder`] wraps an [`Embedder`] and remembers vectors by model
+//! and SHA-256 of the text, so re-ingesting unchanged chunks or re-embedding
+//! repeated messages does not call the model again. Vectors are kept in an
+//! [`EmbeddingCacheStore`]; [`MemoryEmbeddingCache`] is the in-process
+//! backend. Hit and miss counts are available from
+//! [`CachedEmbedder::stats`].
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+use crate::{error::Error, vector::Embedder};
+
+/// Default time a cached vector stays valid
+pub const DEFAULT_EMBEDDING_TTL: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Backend holding cached vectors
+#[async_trait]
+pub trait EmbeddingCacheStore: Send + Sync {
+    /// Vectors for `keys`, in order; `None` where nothing is cached
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<f32>>>>;
+
+    /// Cache vectors for `ttl`
+    async fn put_many(&self, entries: Vec<(String, Vec<f32>)>, ttl: Duration) -> Result<()>;
+}
+
+/// In-process cache, bounded by entry count
+///
+/// When full, expired entries are dropped first, then the oldest.
+#[derive(Debug)]
+pub struct MemoryEmbeddingCache {
+    entries: DashMap<String, (Vec<f32>, Instant, Instant)>,
+    max_entries: usize,
+}
+
+impl MemoryEmbeddingCache {
+    /// Create a cache holding at most `max_entries` vectors
+    #[must_use]
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            entries: DashMap::new(),
+            max_entries: max_entries.max(1),
+        }
+    }
+
+    /// Number of cached vectors, including expired ones not yet dropped
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether nothing is cached
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn make_room(&self) {
+        if self.entries.len() < self.max_entries {
+            return;
+        }
+        let now = Instant::now();
+        self.entries.retain(|_, (_, _, expires)| *expires > now);
+        while self.entries.len() >= self.max_entries {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|entry| entry.value().1)
+                .map(|entry| entry.key().clone());
+            let Some(key) = oldest else {
+                break;
+            };
+            self.entries.remove(&key);
+        }
+    }
+}
+
+impl Default for MemoryEmbeddingCache {
+    fn default() -> Self {
+        Self::new(100_000)
+    }
+}
+
+#[async_trait]
+impl EmbeddingCacheStore for MemoryEmbeddingCache {
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<f32>>>> {
+        let now = Instant::now();
+        Ok(keys
+            .iter()
+            .map(|key| {
+                self.entries
+                    .get(key)
+                    .filter(|entry| entry.value().2 > now)
+                    .map(|entry| entry.value().0.clone())
+            })
+            .collect())
+    }
+
+    async fn put_many(&self, entries: Vec<(String, Vec<f32>)>, ttl: Duration) -> Result<()> {
+        let now = Instant::now();
+        for (key, vector) in entries {
+            if !self.entries.contains_key(&key) {
+                self.make_room();
+            }
+            self.entries.insert(key, (vector, now, now + ttl));
+        }
+        Ok(())
+    }
+}
+
+/// Hit and miss counts of a [`CachedEmbedder`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EmbeddingCacheStats {
+    /// Texts answered from the cache
+    pub hits: u64,
+    /// Texts sent to the model
+    pub misses: u64,
+}
+
+impl EmbeddingCacheStats {
+    /// Share of texts answered from the cache
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn hit_rate(&self) -> Option<f64> {
+        let total = self.hits + self.misses;
+        (total > 0).then(|| self.hits as f64 / total as f64)
+    }
+}
+
+/// An [`Embedder`] that serves repeated texts from a cache
+pub struct CachedEmbedder {
+    inner: Arc<dyn Embedder>,
+    store: Arc<dyn EmbeddingCacheStore>,
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl std::fmt::Debug for CachedEmbedder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CachedEmbedder")
+            .field("model", &self.inner.model())
+            .field("ttl", &self.ttl)
+            .field("stats", &self.stats())
+            .finish_non_exhaustive()
+    }
+}
+
+impl CachedEmbedder {
+    /// Cache `inner`'s vectors in `store`
+    #[must_use]
+    pub fn new(inner: Arc<dyn Embedder>, store: Arc<dyn EmbeddingCacheStore>) -> Self {
+        Self {
+            inner,
+            store,
+            ttl: DEFAULT_EMBEDDING_TTL,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Cache `inner`'s vectors in memory, up to `max_entries`
+    #[must_use]
+    pub fn in_memory(inner: Arc<dyn Embedder>, max_entries: usize) -> Self {
+        Self::new(inner, Arc::new(MemoryEmbeddingCache::new(max_entries)))
+    }
+
+    /// Keep vectors for `ttl` instead of [`DEFAULT_EMBEDDING_TTL`]
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Hits and misses so far
+    #[must_use]
+    pub fn stats(&self) -> EmbeddingCacheStats {
+        EmbeddingCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Cache key for `text` under this embedder's model
+    #[must_use]
+    pub fn key(&self, text: &str) -> String {
+        cache_key(self.inner.model(), text)
+    }
+}
+
+/// Cache key for `text` embedded by `model`
+#[must_use]
+pub fn cache_key(model: &str, text: &str) -> String {
+    format!("{model}:{:x}", Sha256::digest(text.as_bytes()))
+}
+
+#[async_trait]
+impl Embedder for CachedEmbedder {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let keys: Vec<String> = texts.iter().map(|text| self.key(text)).collect();
+        let mut vectors = match self.store.get_many(&keys).await {
+            Ok(cached) => cached,
+            Err(e) => {
+                warn!("Embedding cache lookup failed: {:#}", e);
+                vec![None; texts.len()]
+            }
+        };
+
+        // Embed each distinct missing text once
+        let mut pending: HashMap<&str, Vec<usize>> = HashMap::new();
+        let mut missing: Vec<String> = Vec::new();
+        for (index, vector) in vectors.iter().enumerate() {
+            if vector.is_some() {
+                continue;
+            }
+            let slots = pending.entry(keys[index].as_str()).or_default();
+            if slots.is_empty() {
+                missing.push(texts[index].clone());
+            }
+            slots.push(index);
+        }
+        // A repeat of a text already pending is served without a lookup miss
+        let hits = texts.len() - pending.len();
+        self.hits.fetch_add(hits as u64, Ordering::Relaxed);
+        self.misses
+            .fetch_add(missing.len() as u64, Ordering::Relaxed);
+
+        if !missing.is_empty() {
+            debug!(
+                "Embedding {} of {} texts with {}",
+                missing.len(),
+                texts.len(),
+                self.inner.model()
+            );
+            let embedded = self.inner.embed(&missing).await?;
+            if embedded.len() != missing.len() {
+                return Err(Error::Provider(format!(
+                    "Embedder returned {} vectors for {} inputs",
+                    embedded.len(),
+                    missing.len()
+                ))
+                .into());
+            }
+
+            let mut fresh = Vec::with_capacity(embedded.len());
+            for (text, vector) in missing.iter().zip(embedded) {
+                let key = self.key(text);
+                for &index in &pending[key.as_str()] {
+                    vectors[index] = Some(vector.clone());
+                }
+                fresh.push((key, vector));
+            }
+            if let Err(e) = self.store.put_many(fresh, self.ttl).await {
+                warn!("Failed to cache embeddings: {:#}", e);
+            }
+        }
+
+        Ok(vectors.into_iter().flatten().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    #[derive(Default)]
+    struct CountingEmbedder(Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl Embedder for CountingEmbedder {
+        fn model(&self) -> &str {
+            "counting"
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            self.0.lock().extend(texts.iter().cloned());
+            Ok(texts.iter().map(|t| vec![t.len() as f32]).collect())
+        }
+    }
+
+    fn texts(items: &[&str]) -> Vec<String> {
+        items.iter().map(ToString::to_string).collect()
+    }
+
+    #[tokio::test]
+    async fn test_repeated_texts_are_embedded_once() {
+        let inner = Arc::new(CountingEmbedder::default());
+        let embedder = CachedEmbedder::in_memory(inner.clone(), 100);
+
+        let vectors = embedder.embed(&texts(&["a", "bb", "a"])).await.unwrap();
+        assert_eq!(vectors, vec![vec![1.0], vec![2.0], vec![1.0]]);
+        assert_eq!(*inner.0.lock(), texts(&["a", "bb"]));
+
+        let vectors = embedder.embed(&texts(&["bb", "ccc"])).await.unwrap();
+        assert_eq!(vectors, vec![vec![2.0], vec![3.0]]);
+        assert_eq!(*inner.0.lock(), texts(&["a", "bb", "ccc"]));
+
+        let stats = embedder.stats();
+        assert_eq!(stats, EmbeddingCacheStats { hits: 2, misses: 3 });
+        assert!((stats.hit_rate().unwrap() - 0.4).abs() < f64::EPSILON);
+        assert_ne!(cache_key("counting", "a"), cache_key("other", "a"));
+    }
+
+    #[tokio::test]
+    async fn test_memory_cache_expires_and_evicts() {
+        let cache = MemoryEmbeddingCache::new(2);
+        let keys = texts(&["k1", "k2", "k3"]);
+        cache
+            .put_many(vec![(keys[0].clone(), vec![1.0])], Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(cache.get_many(&keys[..1]).await.unwrap(), vec![None]);
+
+        cache
+            .put_many(
+                vec![(keys[1].clone(), vec![2.0]), (keys[2].clone(), vec![3.0])],
+                Duration::from_secs(60),
+            )
+            .await
+            .unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(
+            cache.get_many(&keys).await.unwrap(),
+            vec![None, Some(vec![2.0]), Some(vec![3.0])]
+        );
+    }
+}
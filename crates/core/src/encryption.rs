@@ -0,0 +1,538 @@
+//! Encryption at rest for persisted contexts
+//!
+//! Contexts are sealed with envelope encryption: the serialized payload is
+//! encrypted with AES-256-GCM under a data key, and the data key is wrapped
+//! by the tenant's customer-managed key through a [`KeyProvider`]. Only the
+//! wrapped data key is stored, so decrypting a context requires access to
+//! the tenant's key. The storage key is bound to the ciphertext as
+//! associated data, so a sealed payload cannot be replayed under another
+//! context ID.
+//!
+//! Sealed payloads start with a versioned magic prefix that no
+//! [`PayloadCodec`] payload begins with. Once a tenant has a key, unsealed
+//! contexts of that tenant are rejected, so a payload planted in the store
+//! cannot bypass encryption; [`EncryptionConfig::allow_unsealed`] accepts
+//! them while an existing store is migrated.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::Result;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::compression::PayloadCodec;
+use crate::config::EncryptionConfig;
+use crate::context::Context;
+use crate::error::Error;
+
+/// Prefix of a sealed payload: a marker, then the envelope format version
+const SEALED_MAGIC: [u8; 5] = [0xE5, b'U', b'B', b'E', 1];
+
+/// Unwrapped data keys kept in memory at most
+const MAX_CACHED_KEYS: usize = 1024;
+
+/// A data key as returned by [`KeyProvider::generate_data_key`]
+#[derive(Clone)]
+pub struct DataKey {
+    /// 256-bit key used to encrypt payloads; never stored
+    pub plaintext: Vec<u8>,
+    /// The key encrypted under the customer-managed key
+    pub wrapped: Vec<u8>,
+}
+
+impl std::fmt::Debug for DataKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DataKey")
+            .field("wrapped", &self.wrapped.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Generates and unwraps data keys under customer-managed keys
+#[async_trait]
+pub trait KeyProvider: Send + Sync {
+    /// Generate a 256-bit data key wrapped by `key_id`
+    async fn generate_data_key(&self, key_id: &str) -> Result<DataKey>;
+
+    /// Unwrap a data key previously generated under `key_id`
+    async fn decrypt_data_key(&self, key_id: &str, wrapped: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// Wraps data keys with master keys held in memory, for tests and development
+#[derive(Default)]
+pub struct LocalKeyProvider {
+    keys: DashMap<String, Vec<u8>>,
+}
+
+impl std::fmt::Debug for LocalKeyProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalKeyProvider")
+            .field("keys", &self.keys.len())
+            .finish()
+    }
+}
+
+impl LocalKeyProvider {
+    /// Create a provider without keys
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a random master key under `key_id`
+    #[must_use]
+    pub fn with_key(self, key_id: impl Into<String>) -> Self {
+        self.keys
+            .insert(key_id.into(), Aes256Gcm::generate_key(OsRng).to_vec());
+        self
+    }
+
+    /// Remove a master key, as disabling a KMS key would
+    pub fn revoke(&self, key_id: &str) -> bool {
+        self.keys.remove(key_id).is_some()
+    }
+
+    fn cipher(&self, key_id: &str) -> Result<Aes256Gcm> {
+        let key = self
+            .keys
+            .get(key_id)
+            .ok_or_else(|| Error::NotFound(format!("Encryption key {key_id}")))?;
+        Ok(cipher(&key)?)
+    }
+}
+
+#[async_trait]
+impl KeyProvider for LocalKeyProvider {
+    async fn generate_data_key(&self, key_id: &str) -> Result<DataKey> {
+        let plaintext = Aes256Gcm::generate_key(OsRng).to_vec();
+        let wrapped = encrypt(&self.cipher(key_id)?, &plaintext, key_id.as_bytes())?;
+        Ok(DataKey { plaintext, wrapped })
+    }
+
+    async fn decrypt_data_key(&self, key_id: &str, wrapped: &[u8]) -> Result<Vec<u8>> {
+        Ok(decrypt(&self.cipher(key_id)?, wrapped, key_id.as_bytes())?)
+    }
+}
+
+/// Data keys from AWS KMS, wrapped by customer-managed keys
+#[cfg(feature = "kms")]
+pub struct KmsKeyProvider {
+    client: aws_sdk_kms::Client,
+}
+
+#[cfg(feature = "kms")]
+impl KmsKeyProvider {
+    /// Create a provider from a KMS client
+    #[must_use]
+    pub fn new(client: aws_sdk_kms::Client) -> Self {
+        Self { client }
+    }
+
+    /// Create a provider using credentials from the default AWS provider chain
+    pub async fn from_env() -> Self {
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        Self::new(aws_sdk_kms::Client::new(&config))
+    }
+}
+
+#[cfg(feature = "kms")]
+#[async_trait]
+impl KeyProvider for KmsKeyProvider {
+    async fn generate_data_key(&self, key_id: &str) -> Result<DataKey> {
+        let output = self
+            .client
+            .generate_data_key()
+            .key_id(key_id)
+            .key_spec(aws_sdk_kms::types::DataKeySpec::Aes256)
+            .send()
+            .await
+            .map_err(|e| {
+                Error::Internal(format!("KMS GenerateDataKey with {key_id} failed: {e}"))
+            })?;
+        let (Some(plaintext), Some(wrapped)) = (output.plaintext(), output.ciphertext_blob())
+        else {
+            return Err(Error::Internal("KMS returned an incomplete data key".to_string()).into());
+        };
+        Ok(DataKey {
+            plaintext: plaintext.as_ref().to_vec(),
+            wrapped: wrapped.as_ref().to_vec(),
+        })
+    }
+
+    async fn decrypt_data_key(&self, key_id: &str, wrapped: &[u8]) -> Result<Vec<u8>> {
+        let output = self
+            .client
+            .decrypt()
+            .key_id(key_id)
+            .ciphertext_blob(aws_sdk_kms::primitives::Blob::new(wrapped))
+            .send()
+            .await
+            .map_err(|e| Error::Internal(format!("KMS Decrypt with {key_id} failed: {e}")))?;
+        let plaintext = output
+            .plaintext()
+            .ok_or_else(|| Error::Internal("KMS returned no plaintext".to_string()))?;
+        Ok(plaintext.as_ref().to_vec())
+    }
+}
+
+/// A sealed payload as stored, after the magic prefix
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    key_id: String,
+    wrapped_key: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+/// Seals and opens context payloads with per-tenant keys
+pub struct ContextEncryption {
+    config: EncryptionConfig,
+    provider: Arc<dyn KeyProvider>,
+    /// Current data key per key ID, reused for writes until it expires
+    write_keys: DashMap<String, (DataKey, Instant)>,
+    /// Unwrapped data keys by wrapped key, with when they were unwrapped
+    read_keys: DashMap<Vec<u8>, (Vec<u8>, Instant)>,
+}
+
+impl std::fmt::Debug for ContextEncryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ContextEncryption")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ContextEncryption {
+    /// Create an encryptor using `provider` for the keys named in `config`
+    #[must_use]
+    pub fn new(config: EncryptionConfig, provider: Arc<dyn KeyProvider>) -> Self {
+        Self {
+            config,
+            provider,
+            write_keys: DashMap::new(),
+            read_keys: DashMap::new(),
+        }
+    }
+
+    /// Encrypt `payload` stored under `key` with `tenant`'s key
+    ///
+    /// Payloads of tenants without a key are returned unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a data key cannot be generated or encryption fails.
+    pub async fn seal(&self, tenant: Option<&str>, key: &str, payload: Vec<u8>) -> Result<Vec<u8>> {
+        let Some(key_id) = self.config.key_for(tenant) else {
+            return Ok(payload);
+        };
+        let data_key = self.write_key(key_id).await?;
+        let ciphertext = encrypt(&cipher(&data_key.plaintext)?, &payload, key.as_bytes())?;
+        let envelope = Envelope {
+            key_id: key_id.to_string(),
+            wrapped_key: data_key.wrapped,
+            ciphertext,
+        };
+        let body =
+            bincode::serialize(&envelope).map_err(|e| Error::Serialization(e.to_string()))?;
+        let mut sealed = Vec::with_capacity(SEALED_MAGIC.len() + body.len());
+        sealed.extend_from_slice(&SEALED_MAGIC);
+        sealed.extend_from_slice(&body);
+        Ok(sealed)
+    }
+
+    /// Whether `payload` was produced by [`seal`](Self::seal)
+    #[must_use]
+    pub fn is_sealed(payload: &[u8]) -> bool {
+        payload.starts_with(&SEALED_MAGIC)
+    }
+
+    /// Decrypt a payload sealed under `key`; unsealed payloads are returned unchanged
+    ///
+    /// Callers must pass the decoded context of an unsealed payload to
+    /// [`check_unsealed`](Self::check_unsealed), or use
+    /// [`open_context`](Self::open_context), which does both.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tenant's key is unavailable or revoked, or
+    /// the payload was tampered with or stored under another key.
+    pub async fn open(&self, key: &str, payload: Vec<u8>) -> Result<Vec<u8>> {
+        let Some(body) = payload.strip_prefix(&SEALED_MAGIC) else {
+            return Ok(payload);
+        };
+        let envelope: Envelope =
+            bincode::deserialize(body).map_err(|e| Error::Serialization(e.to_string()))?;
+        let data_key = self
+            .read_key(&envelope.key_id, &envelope.wrapped_key)
+            .await?;
+        Ok(decrypt(
+            &cipher(&data_key)?,
+            &envelope.ciphertext,
+            key.as_bytes(),
+        )?)
+    }
+
+    /// Decrypt and decode a context stored under `key`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload cannot be opened or decoded, or if it
+    /// was stored unsealed although its tenant has a key.
+    pub async fn open_context(
+        &self,
+        key: &str,
+        payload: Vec<u8>,
+        codec: &PayloadCodec,
+    ) -> Result<Context> {
+        if Self::is_sealed(&payload) {
+            return Ok(codec.decode(&self.open(key, payload).await?)?);
+        }
+        let context = codec.decode(&payload)?;
+        self.check_unsealed(key, &context)?;
+        Ok(context)
+    }
+
+    /// Reject a context that was stored unsealed although its tenant has a key
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Authorization`] unless the tenant has no key or
+    /// [`EncryptionConfig::allow_unsealed`] is set.
+    pub fn check_unsealed(&self, key: &str, context: &Context) -> Result<()> {
+        if self.config.allow_unsealed || self.config.key_for(context.tenant()).is_none() {
+            return Ok(());
+        }
+        Err(Error::Authorization(format!(
+            "Context {key} is stored unencrypted but its tenant requires encryption"
+        ))
+        .into())
+    }
+
+    async fn write_key(&self, key_id: &str) -> Result<DataKey> {
+        let ttl = Duration::from_secs(self.config.data_key_ttl_seconds);
+        if let Some(entry) = self.write_keys.get(key_id) {
+            if entry.1.elapsed() < ttl {
+                return Ok(entry.0.clone());
+            }
+        }
+        let data_key = self.provider.generate_data_key(key_id).await?;
+        self.write_keys
+            .insert(key_id.to_string(), (data_key.clone(), Instant::now()));
+        Ok(data_key)
+    }
+
+    /// Unwrap a data key, reusing it for the data key TTL
+    ///
+    /// Keys are unwrapped again once the TTL passes, so revoking or
+    /// disabling the customer-managed key takes effect within one TTL.
+    async fn read_key(&self, key_id: &str, wrapped: &[u8]) -> Result<Vec<u8>> {
+        let ttl = Duration::from_secs(self.config.data_key_ttl_seconds);
+        if let Some(entry) = self.read_keys.get(wrapped) {
+            if entry.1.elapsed() < ttl {
+                return Ok(entry.0.clone());
+            }
+        }
+        let plaintext = self.provider.decrypt_data_key(key_id, wrapped).await?;
+        if self.read_keys.len() >= MAX_CACHED_KEYS {
+            self.read_keys
+                .retain(|_, (_, unwrapped)| unwrapped.elapsed() < ttl);
+            if self.read_keys.len() >= MAX_CACHED_KEYS {
+                self.read_keys.clear();
+            }
+        }
+        self.read_keys
+            .insert(wrapped.to_vec(), (plaintext.clone(), Instant::now()));
+        Ok(plaintext)
+    }
+}
+
+fn cipher(key: &[u8]) -> crate::error::Result<Aes256Gcm> {
+    Aes256Gcm::new_from_slice(key)
+        .map_err(|_| Error::Internal(format!("Expected a 256-bit key, got {} bytes", key.len())))
+}
+
+/// Encrypt with a random nonce, which is prepended to the ciphertext
+fn encrypt(cipher: &Aes256Gcm, plaintext: &[u8], aad: &[u8]) -> crate::error::Result<Vec<u8>> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| Error::Internal("Encryption failed".to_string()))?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+fn decrypt(cipher: &Aes256Gcm, sealed: &[u8], aad: &[u8]) -> crate::error::Result<Vec<u8>> {
+    const NONCE_LEN: usize = 12;
+    if sealed.len() < NONCE_LEN {
+        return Err(Error::Serialization(
+            "Sealed payload is truncated".to_string(),
+        ));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| Error::Authorization("Payload could not be decrypted".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn acme_encryption(provider: Arc<LocalKeyProvider>) -> ContextEncryption {
+        ContextEncryption::new(
+            EncryptionConfig {
+                enabled: true,
+                tenant_keys: HashMap::from([("acme".to_string(), "acme-cmk".to_string())]),
+                ..EncryptionConfig::default()
+            },
+            provider,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_cached_read_keys_expire_after_revocation() {
+        let provider = Arc::new(LocalKeyProvider::new().with_key("acme-cmk"));
+        let encryption = ContextEncryption::new(
+            EncryptionConfig {
+                enabled: true,
+                tenant_keys: HashMap::from([("acme".to_string(), "acme-cmk".to_string())]),
+                data_key_ttl_seconds: 1,
+                ..EncryptionConfig::default()
+            },
+            provider.clone(),
+        );
+        let sealed = encryption
+            .seal(Some("acme"), "conv", b"payload".to_vec())
+            .await
+            .unwrap();
+        encryption.open("conv", sealed.clone()).await.unwrap();
+
+        // The cached key still opens payloads until its TTL passes
+        provider.revoke("acme-cmk");
+        encryption.open("conv", sealed.clone()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert!(encryption.open("conv", sealed).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tenant_payloads_are_sealed() {
+        let provider = Arc::new(LocalKeyProvider::new().with_key("acme-cmk"));
+        let encryption = acme_encryption(provider.clone());
+
+        let sealed = encryption
+            .seal(Some("acme"), "conv", b"{\"secret\":1}".to_vec())
+            .await
+            .unwrap();
+        assert!(ContextEncryption::is_sealed(&sealed));
+        assert!(!sealed.windows(6).any(|w| w == b"secret"));
+        assert_eq!(
+            encryption.open("conv", sealed.clone()).await.unwrap(),
+            b"{\"secret\":1}"
+        );
+        assert!(encryption.open("other", sealed.clone()).await.is_err());
+
+        // A fresh reader has no cached keys and must unwrap through the provider
+        provider.revoke("acme-cmk");
+        assert!(acme_encryption(provider)
+            .open("conv", sealed)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tenants_without_keys_pass_through() {
+        let encryption = acme_encryption(Arc::new(LocalKeyProvider::new().with_key("acme-cmk")));
+        let payload = b"{\"plain\":true}".to_vec();
+
+        let stored = encryption
+            .seal(Some("globex"), "conv", payload.clone())
+            .await
+            .unwrap();
+        assert_eq!(stored, payload);
+        assert_eq!(encryption.open("conv", stored).await.unwrap(), payload);
+    }
+
+    fn encoded(tenant: Option<&str>) -> Vec<u8> {
+        let mut context = Context::new("conv");
+        if let Some(tenant) = tenant {
+            context.set_variable(crate::degraded::TENANT_METADATA_KEY, tenant.into());
+        }
+        PayloadCodec::new(crate::config::CompressionConfig::default())
+            .encode(&context)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_unsealed_contexts_of_keyed_tenants_are_rejected() {
+        let codec = PayloadCodec::new(crate::config::CompressionConfig::default());
+        let encryption = acme_encryption(Arc::new(LocalKeyProvider::new().with_key("acme-cmk")));
+
+        let planted = encryption
+            .open_context("conv", encoded(Some("acme")), &codec)
+            .await;
+        assert!(planted.is_err());
+        encryption
+            .open_context("conv", encoded(Some("globex")), &codec)
+            .await
+            .unwrap();
+        encryption
+            .open_context("conv", encoded(None), &codec)
+            .await
+            .unwrap();
+
+        let sealed = encryption
+            .seal(Some("acme"), "conv", encoded(Some("acme")))
+            .await
+            .unwrap();
+        let context = encryption
+            .open_context("conv", sealed, &codec)
+            .await
+            .unwrap();
+        assert_eq!(context.tenant(), Some("acme"));
+
+        // Accepted while migrating a store written before encryption was on
+        let migrating = ContextEncryption::new(
+            EncryptionConfig {
+                allow_unsealed: true,
+                ..encryption.config.clone()
+            },
+            Arc::new(LocalKeyProvider::new()),
+        );
+        migrating
+            .open_context("conv", encoded(Some("acme")), &codec)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_payloads_starting_with_the_marker_byte_are_not_sealed() {
+        assert!(!ContextEncryption::is_sealed(&[0xE5, b'{', b'}']));
+        assert!(!ContextEncryption::is_sealed(&SEALED_MAGIC[..4]));
+        assert!(!ContextEncryption::is_sealed(&encoded(Some("acme"))));
+    }
+
+    #[tokio::test]
+    async fn test_data_keys_are_reused_within_ttl() {
+        let encryption = acme_encryption(Arc::new(LocalKeyProvider::new().with_key("acme-cmk")));
+        let first = encryption.write_key("acme-cmk").await.unwrap();
+        let second = encryption.write_key("acme-cmk").await.unwrap();
+        assert_eq!(first.wrapped, second.wrapped);
+    }
+}
@@ -30,6 +30,17 @@ pub enum Error {
     #[error("Plugin error: {0}")]
     Plugin(String),
 
+    /// Plugin response that does not match the schema it declared
+    #[error("Plugin '{plugin}' returned invalid data at {path}: {message}")]
+    InvalidPluginResponse {
+        /// Plugin that responded
+        plugin: String,
+        /// JSON Pointer to the offending value, `/` for the root
+        path: String,
+        /// What is wrong with it
+        message: String,
+    },
+
     /// AI provider error
     #[error("AI provider error: {0}")]
     Provider(String),
@@ -46,6 +57,17 @@ pub enum Error {
     #[error("Rate limit exceeded")]
     RateLimit,
 
+    /// Spend budget error
+    #[error("Budget exceeded for {scope}: spent ${spent_usd:.4} of ${limit_usd:.4}")]
+    BudgetExceeded {
+        /// Budget that was exceeded, e.g. `user alice`
+        scope: String,
+        /// Estimated spend so far, in USD
+        spent_usd: f64,
+        /// The budget's limit, in USD
+        limit_usd: f64,
+    },
+
     /// Authentication error
     #[error("Authentication failed: {0}")]
     Authentication(String),
@@ -166,6 +188,8 @@ impl Error {
             Self::Cache(_) => "E016",
             Self::Initialization(_) => "E017",
             Self::Internal(_) => "E018",
+            Self::BudgetExceeded { .. } => "E019",
+            Self::InvalidPluginResponse { .. } => "E020",
             Self::Other { .. } => "E999",
         }
     }
@@ -179,8 +203,8 @@ impl Error {
             Self::Authorization(_) => 403,
             Self::NotFound(_) => 404,
             Self::Timeout(_) => 408,
-            Self::RateLimit => 429,
-            Self::Network(_) | Self::Provider(_) => 502,
+            Self::RateLimit | Self::BudgetExceeded { .. } => 429,
+            Self::Network(_) | Self::Provider(_) | Self::InvalidPluginResponse { .. } => 502,
             Self::Initialization(_) => 503,
             _ => 500,
         }
@@ -343,6 +367,13 @@ mod tests {
             408
         );
         assert_eq!(Error::RateLimit.http_status_code(), 429);
+        let budget = Error::BudgetExceeded {
+            scope: "global".into(),
+            spent_usd: 10.0,
+            limit_usd: 10.0,
+        };
+        assert_eq!(budget.http_status_code(), 429);
+        assert!(!budget.is_retryable());
         assert_eq!(Error::Internal("500".into()).http_status_code(), 500);
         assert_eq!(Error::Network("net".into()).http_status_code(), 502);
         assert_eq!(Error::Initialization("init".into()).http_status_code(), 503);
@@ -0,0 +1,297 @@
+//! Notices before conversations expire
+//!
+//! A conversation's context expires
+//! [`context_ttl`](crate::config::ContextConfig::context_ttl) after it was
+//! created or last renewed. [`ExpiryWatcher`] checks for
+//! contexts that expire within the configured lead time and sends an
+//! [`ExpiryNotice`] through every registered [`ExpiryNotifier`]: a channel
+//! adapter telling the user directly, or the [`WebhookManager`] posting a
+//! [`WebhookEventKind::ConversationExpiring`] event to the conversation's
+//! callback. The notice offers to continue the conversation, which renews
+//! it, or to export it first.
+//!
+//! Each expiry is announced once per watcher; bots sharing a store each
+//! send their own notices.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+    config::ExpiryNotificationConfig,
+    context::{Context, ContextManager},
+    webhook::{WebhookEvent, WebhookEventKind, WebhookManager},
+};
+
+/// What the user can do about an expiring conversation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpiryAction {
+    /// Keep the conversation, see [`Bot::renew_conversation`](crate::Bot::renew_conversation)
+    Continue,
+    /// Download it first, see [`Bot::export_conversation`](crate::Bot::export_conversation)
+    Export,
+}
+
+/// A conversation about to expire
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpiryNotice {
+    /// The conversation
+    pub conversation_id: String,
+    /// Tenant the conversation belongs to
+    pub tenant_id: Option<String>,
+    /// User to notify, if known
+    pub user_id: Option<String>,
+    /// When the context expires
+    pub expires_at: DateTime<Utc>,
+    /// Actions to offer the user
+    pub actions: Vec<ExpiryAction>,
+}
+
+impl ExpiryNotice {
+    fn new(context: &Context, expires_at: DateTime<Utc>) -> Self {
+        Self {
+            conversation_id: context.id.clone(),
+            tenant_id: context.tenant().map(str::to_string),
+            user_id: context.user.id.clone(),
+            expires_at,
+            actions: vec![ExpiryAction::Continue, ExpiryAction::Export],
+        }
+    }
+}
+
+/// Delivers expiry notices, e.g. through a channel adapter
+#[async_trait]
+pub trait ExpiryNotifier: Send + Sync {
+    /// Tell the user their conversation is about to expire
+    async fn notify(&self, notice: &ExpiryNotice) -> Result<()>;
+}
+
+#[async_trait]
+impl ExpiryNotifier for WebhookManager {
+    async fn notify(&self, notice: &ExpiryNotice) -> Result<()> {
+        let event = WebhookEvent::new(
+            notice.conversation_id.clone(),
+            WebhookEventKind::ConversationExpiring,
+            serde_json::to_value(notice)?,
+        );
+        self.dispatch(event).await?;
+        Ok(())
+    }
+}
+
+/// Finds expiring conversations and notifies their users
+pub struct ExpiryWatcher {
+    config: ExpiryNotificationConfig,
+    contexts: Arc<ContextManager>,
+    ttl: Duration,
+    notifiers: RwLock<Vec<Arc<dyn ExpiryNotifier>>>,
+    /// Expiry time last announced, by conversation
+    notified: DashMap<String, DateTime<Utc>>,
+}
+
+impl std::fmt::Debug for ExpiryWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExpiryWatcher")
+            .field("config", &self.config)
+            .field("notifiers", &self.notifiers.read().len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ExpiryWatcher {
+    /// Create a watcher for contexts living `ttl` in `contexts`
+    #[must_use]
+    pub fn new(
+        config: ExpiryNotificationConfig,
+        contexts: Arc<ContextManager>,
+        ttl: Duration,
+    ) -> Self {
+        Self {
+            config,
+            contexts,
+            ttl,
+            notifiers: RwLock::new(Vec::new()),
+            notified: DashMap::new(),
+        }
+    }
+
+    /// Whether any tenant is notified
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.config.any_enabled()
+    }
+
+    /// Send notices through `notifier` as well
+    pub fn add_notifier(&self, notifier: Arc<dyn ExpiryNotifier>) {
+        self.notifiers.write().push(notifier);
+    }
+
+    /// Notify users of conversations expiring within the lead time
+    ///
+    /// Returns the notices sent. Conversations already announced for the
+    /// same expiry time are skipped; a renewed conversation is announced
+    /// again before its new expiry. Failed deliveries are logged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the contexts cannot be scanned.
+    pub async fn check(&self) -> Result<Vec<ExpiryNotice>> {
+        let now = Utc::now();
+        self.notified.retain(|_, expires_at| *expires_at > now);
+
+        let mut sent = Vec::new();
+        for context in self.contexts.expiring_within(self.config.lead_time).await? {
+            let Some(expires_at) = context.expires_at(self.ttl) else {
+                continue;
+            };
+            if !self.config.enabled_for(context.tenant())
+                || self
+                    .notified
+                    .get(&context.id)
+                    .is_some_and(|notified| *notified == expires_at)
+            {
+                continue;
+            }
+
+            let notice = ExpiryNotice::new(&context, expires_at);
+            let notifiers = self.notifiers.read().clone();
+            for notifier in notifiers {
+                if let Err(e) = notifier.notify(&notice).await {
+                    warn!(
+                        "Failed to send expiry notice for {}: {:#}",
+                        notice.conversation_id, e
+                    );
+                }
+            }
+            debug!(
+                "Conversation {} expires at {}",
+                notice.conversation_id, expires_at
+            );
+            self.notified.insert(context.id, expires_at);
+            sent.push(notice);
+        }
+        Ok(sent)
+    }
+
+    /// Check every [`ExpiryNotificationConfig::check_interval`], forever
+    pub async fn run_checker(self: Arc<Self>) {
+        let period = self.config.check_interval.max(Duration::from_secs(1));
+        let mut ticker = tokio::time::interval(period);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.check().await {
+                warn!("Failed to check for expiring conversations: {:#}", e);
+            }
+        }
+    }
+
+    /// Run [`run_checker`](Self::run_checker) until the returned task is aborted
+    pub fn spawn_checker(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(self.run_checker())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ContextConfig;
+    use crate::degraded::TENANT_METADATA_KEY;
+    use crate::message::Message;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<ExpiryNotice>>);
+
+    #[async_trait]
+    impl ExpiryNotifier for Recorder {
+        async fn notify(&self, notice: &ExpiryNotice) -> Result<()> {
+            self.0.lock().push(notice.clone());
+            Ok(())
+        }
+    }
+
+    async fn recording_watcher(config: ExpiryNotificationConfig) -> (ExpiryWatcher, Arc<Recorder>) {
+        let ttl = Duration::from_secs(60);
+        let contexts = ContextManager::new(ContextConfig {
+            context_ttl: ttl,
+            ..ContextConfig::default()
+        })
+        .await
+        .unwrap();
+        let watcher = ExpiryWatcher::new(config, Arc::new(contexts), ttl);
+        let recorder = Arc::new(Recorder::default());
+        watcher.add_notifier(recorder.clone());
+        (watcher, recorder)
+    }
+
+    async fn start(watcher: &ExpiryWatcher, id: &str, tenant: &str) {
+        let context = watcher.contexts.get_or_create(id).await.unwrap();
+        let message = Message::text("hi").with_metadata(TENANT_METADATA_KEY, tenant.into());
+        context.write().add_message(&message);
+    }
+
+    #[tokio::test]
+    async fn test_notifies_once_per_expiry() {
+        let (watcher, recorder) = recording_watcher(ExpiryNotificationConfig {
+            enabled: true,
+            lead_time: Duration::from_secs(120),
+            ..ExpiryNotificationConfig::default()
+        })
+        .await;
+        start(&watcher, "conv", "acme").await;
+
+        let sent = watcher.check().await.unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].tenant_id.as_deref(), Some("acme"));
+        assert_eq!(
+            sent[0].actions,
+            vec![ExpiryAction::Continue, ExpiryAction::Export]
+        );
+        assert!(watcher.check().await.unwrap().is_empty());
+        assert_eq!(recorder.0.lock().len(), 1);
+
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        watcher.contexts.renew("conv").await.unwrap();
+        let renewed = watcher.check().await.unwrap();
+        assert_eq!(renewed.len(), 1);
+        assert!(renewed[0].expires_at > sent[0].expires_at);
+    }
+
+    #[tokio::test]
+    async fn test_lead_time_and_tenant_overrides() {
+        let (watcher, recorder) = recording_watcher(ExpiryNotificationConfig {
+            enabled: false,
+            lead_time: Duration::from_secs(120),
+            tenants: HashMap::from([("acme".to_string(), true)]),
+            ..ExpiryNotificationConfig::default()
+        })
+        .await;
+        assert!(watcher.is_enabled());
+        start(&watcher, "acme-conv", "acme").await;
+        start(&watcher, "other-conv", "other").await;
+
+        let sent = watcher.check().await.unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].conversation_id, "acme-conv");
+        assert_eq!(recorder.0.lock().len(), 1);
+
+        let (watcher, _) = recording_watcher(ExpiryNotificationConfig {
+            enabled: true,
+            lead_time: Duration::from_secs(1),
+            ..ExpiryNotificationConfig::default()
+        })
+        .await;
+        start(&watcher, "conv", "acme").await;
+        assert!(watcher.check().await.unwrap().is_empty());
+    }
+}
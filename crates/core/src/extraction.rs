@@ -0,0 +1,679 @@
+//! Entity extraction
+//!
+//! The [`ExtractStage`] pulls structured entities (people, organizations,
+//! dates, and amounts) out of each user message and records them in the
+//! message and pipeline metadata under [`ENTITIES_METADATA_KEY`], where later
+//! stages can route on them and analytics can aggregate them.
+//!
+//! Enable it by listing `extract` before `route` in
+//! [`PipelineConfig::enabled_stages`](crate::config::PipelineConfig::enabled_stages);
+//! it then uses the local [`RuleEntityExtractor`]. To use a cheap model
+//! instead, or to also remember the people and organizations in
+//! [graph memory](crate::graph), add an [`ExtractStage`] built with
+//! [`PromptEntityExtractor`] or [`ExtractStage::with_graph`] through
+//! [`MessagePipeline::add_stage`](crate::pipeline::MessagePipeline::add_stage).
+
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::{
+    completion::{parse_json_array, CompletionFn},
+    degraded::TENANT_METADATA_KEY,
+    graph::{GraphMemory, Relation},
+    memory::MemoryProvenance,
+    message::Message,
+    pipeline::{PipelineContext, PipelineStage},
+};
+
+/// Message and pipeline metadata key listing the [`Entity`]s in a message
+pub const ENTITIES_METADATA_KEY: &str = "entities";
+
+/// Kind of extracted entity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntityKind {
+    /// A person
+    Person,
+    /// A company, institution, or other organization
+    Organization,
+    /// A calendar date
+    Date,
+    /// A sum of money
+    Amount,
+}
+
+/// An entity mentioned in a message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entity {
+    /// Entity kind
+    pub kind: EntityKind,
+    /// The entity as written, e.g. `next Friday` or `$1,200`
+    pub text: String,
+    /// Normalized value: an ISO 8601 date for dates, `<number> <currency>`
+    /// for amounts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Extractor confidence (0.0 to 1.0)
+    #[serde(default = "default_confidence")]
+    pub confidence: f32,
+}
+
+const fn default_confidence() -> f32 {
+    0.5
+}
+
+/// Extracts entities from messages
+#[async_trait]
+pub trait EntityExtractor: Send + Sync {
+    /// Extractor name, recorded in provenance
+    fn name(&self) -> &str;
+
+    /// Extract the entities in a message
+    ///
+    /// Relative dates such as "tomorrow" are resolved against the message
+    /// timestamp.
+    async fn extract(&self, message: &Message) -> Result<Vec<Entity>>;
+}
+
+/// A word of a message with surrounding punctuation removed
+struct Word<'a> {
+    text: &'a str,
+    /// Punctuation such as a comma or full stop follows the word
+    ends_clause: bool,
+    /// The word starts a sentence
+    starts_sentence: bool,
+}
+
+/// Pattern-based extractor that runs locally
+///
+/// Finds currency amounts (`$12.50`, `300 EUR`), dates (`2025-03-14`,
+/// `March 14th`, `14 March 2025`, `tomorrow`), organizations (capitalized
+/// names ending in a suffix such as `Inc` or `Bank`), and people (names
+/// after an honorific, or runs of two or more capitalized words).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RuleEntityExtractor;
+
+impl RuleEntityExtractor {
+    const MONTHS: &'static [&'static str] = &[
+        "january",
+        "february",
+        "march",
+        "april",
+        "may",
+        "june",
+        "july",
+        "august",
+        "september",
+        "october",
+        "november",
+        "december",
+    ];
+    const WEEKDAYS: &'static [&'static str] = &[
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+        "Sunday",
+    ];
+    const HONORIFICS: &'static [&'static str] = &["Mr", "Mrs", "Ms", "Mx", "Dr", "Prof"];
+    const ORGANIZATION_SUFFIXES: &'static [&'static str] = &[
+        "Inc",
+        "Corp",
+        "Corporation",
+        "Ltd",
+        "LLC",
+        "GmbH",
+        "PLC",
+        "Co",
+        "Company",
+        "Group",
+        "Bank",
+        "University",
+        "Foundation",
+        "Institute",
+    ];
+    /// Capitalized words that open sentences rather than names
+    const SENTENCE_OPENERS: &'static [&'static str] = &[
+        "A", "An", "And", "But", "Dear", "Hello", "Hey", "Hi", "I", "If", "My", "Our", "Please",
+        "So", "The", "Then", "This", "We", "When",
+    ];
+    const CURRENCY_CODES: &'static [(&'static str, &'static str)] = &[
+        ("usd", "USD"),
+        ("dollars", "USD"),
+        ("eur", "EUR"),
+        ("euros", "EUR"),
+        ("gbp", "GBP"),
+        ("pounds", "GBP"),
+    ];
+    const CURRENCY_SYMBOLS: &'static [(char, &'static str)] =
+        &[('$', "USD"), ('€', "EUR"), ('£', "GBP")];
+
+    fn words(text: &str) -> Vec<Word<'_>> {
+        let mut words = Vec::new();
+        let mut starts_sentence = true;
+        for raw in text.split_whitespace() {
+            let trimmed = raw.trim_start_matches(['(', '"', '\'']);
+            let core = trimmed.trim_end_matches(|c: char| {
+                !c.is_alphanumeric() && !Self::CURRENCY_SYMBOLS.iter().any(|(s, _)| *s == c)
+            });
+            if core.is_empty() {
+                continue;
+            }
+            let trailing = &trimmed[core.len()..];
+            let abbreviation = Self::HONORIFICS.contains(&core);
+            words.push(Word {
+                text: core,
+                ends_clause: !trailing.is_empty() && !abbreviation,
+                starts_sentence,
+            });
+            starts_sentence = !abbreviation && trailing.contains(['.', '!', '?']);
+        }
+        words
+    }
+
+    fn month(word: &str) -> Option<u32> {
+        if !word.starts_with(|c: char| c.is_uppercase()) || word.len() < 3 {
+            return None;
+        }
+        let word = word.to_lowercase();
+        let index = Self::MONTHS
+            .iter()
+            .position(|m| *m == word || (word.len() == 3 && m.starts_with(&word)))?;
+        u32::try_from(index + 1).ok()
+    }
+
+    fn day(word: &str) -> Option<u32> {
+        let digits = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        let suffix = &word[digits.len()..];
+        if !["", "st", "nd", "rd", "th"].contains(&suffix) {
+            return None;
+        }
+        digits.parse().ok().filter(|day| (1..=31).contains(day))
+    }
+
+    fn year(word: &str) -> Option<i32> {
+        if word.len() != 4 {
+            return None;
+        }
+        word.parse()
+            .ok()
+            .filter(|year| (1900..=2200).contains(year))
+    }
+
+    fn number(word: &str) -> Option<f64> {
+        let (digits, scale) = word
+            .strip_suffix(['k', 'K'])
+            .map(|digits| (digits, 1_000.0))
+            .or_else(|| {
+                word.strip_suffix(['m', 'M'])
+                    .map(|digits| (digits, 1_000_000.0))
+            })
+            .unwrap_or((word, 1.0));
+        if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        digits
+            .replace(',', "")
+            .parse::<f64>()
+            .ok()
+            .map(|n| n * scale)
+    }
+
+    fn amount(value: f64, currency: &str) -> String {
+        format!("{value} {currency}")
+    }
+
+    /// An amount starting at `words[0]`, with the number of words it spans
+    fn amount_at(words: &[Word<'_>]) -> Option<(Entity, usize)> {
+        let first = words[0].text;
+        for (symbol, currency) in Self::CURRENCY_SYMBOLS {
+            let value = first
+                .strip_prefix(*symbol)
+                .or_else(|| first.strip_suffix(*symbol))
+                .and_then(Self::number);
+            if let Some(value) = value {
+                return Some((
+                    Entity {
+                        kind: EntityKind::Amount,
+                        text: first.to_string(),
+                        value: Some(Self::amount(value, currency)),
+                        confidence: 0.9,
+                    },
+                    1,
+                ));
+            }
+        }
+        let value = Self::number(first)?;
+        let code = words.get(1)?.text.to_lowercase();
+        let (_, currency) = Self::CURRENCY_CODES.iter().find(|(c, _)| *c == code)?;
+        Some((
+            Entity {
+                kind: EntityKind::Amount,
+                text: format!("{first} {}", words[1].text),
+                value: Some(Self::amount(value, currency)),
+                confidence: 0.9,
+            },
+            2,
+        ))
+    }
+
+    /// A date written as "March 14[, 2025]" or "14 March[ 2025]" starting
+    /// at `words[0]`, with the number of words it spans
+    fn calendar_date_at(words: &[Word<'_>], today: NaiveDate) -> Option<(NaiveDate, usize)> {
+        let first = words[0].text;
+        let second = words.get(1).filter(|_| !words[0].ends_clause)?;
+        let (month, day) = match (Self::month(first), Self::day(second.text)) {
+            (Some(month), Some(day)) => (month, day),
+            _ => (Self::month(second.text)?, Self::day(first)?),
+        };
+        let year = words
+            .get(2)
+            .filter(|w| !w.starts_sentence)
+            .and_then(|w| Self::year(w.text));
+        let date = NaiveDate::from_ymd_opt(year.unwrap_or_else(|| today.year()), month, day)?;
+        Some((date, if year.is_some() { 3 } else { 2 }))
+    }
+
+    /// A date starting at `words[0]`, with the number of words it spans
+    fn date_at(words: &[Word<'_>], today: NaiveDate) -> Option<(Entity, usize)> {
+        let first = words[0].text;
+        let relative = match first.to_lowercase().as_str() {
+            "today" => Some(today),
+            "tomorrow" => today.succ_opt(),
+            "yesterday" => today.pred_opt(),
+            _ => None,
+        };
+        let (date, span) = relative
+            .or_else(|| NaiveDate::parse_from_str(first, "%Y-%m-%d").ok())
+            .map_or_else(
+                || Self::calendar_date_at(words, today),
+                |date| Some((date, 1)),
+            )?;
+        let text = words[..span]
+            .iter()
+            .map(|w| w.text)
+            .collect::<Vec<_>>()
+            .join(" ");
+        Some((
+            Entity {
+                kind: EntityKind::Date,
+                text,
+                value: Some(date.format("%Y-%m-%d").to_string()),
+                confidence: if span == 1 && relative.is_none() {
+                    0.9
+                } else {
+                    0.8
+                },
+            },
+            span,
+        ))
+    }
+
+    fn is_name_word(word: &str) -> bool {
+        word.starts_with(|c: char| c.is_uppercase())
+            && word
+                .chars()
+                .all(|c| c.is_alphabetic() || "'-&.".contains(c))
+            && Self::month(word).is_none()
+            && !Self::WEEKDAYS.contains(&word)
+    }
+
+    /// A person or organization starting at `words[0]`, with the number of
+    /// words it spans
+    fn name_at(words: &[Word<'_>]) -> Option<(Option<Entity>, usize)> {
+        if !Self::is_name_word(words[0].text) {
+            return None;
+        }
+        let mut span = 0;
+        for word in words.iter().take_while(|w| Self::is_name_word(w.text)) {
+            span += 1;
+            if word.ends_clause {
+                break;
+            }
+        }
+        let mut names: Vec<&str> = words[..span].iter().map(|w| w.text).collect();
+
+        let honorific = Self::HONORIFICS.contains(&names[0]);
+        if honorific || (words[0].starts_sentence && Self::SENTENCE_OPENERS.contains(&names[0])) {
+            names.remove(0);
+        }
+        let kind = if names.is_empty() {
+            None
+        } else if honorific {
+            Some((EntityKind::Person, 0.8))
+        } else if names.len() < 2 {
+            None
+        } else if Self::ORGANIZATION_SUFFIXES.contains(&names[names.len() - 1]) {
+            Some((EntityKind::Organization, 0.8))
+        } else {
+            Some((EntityKind::Person, 0.5))
+        };
+        let entity = kind.map(|(kind, confidence)| Entity {
+            kind,
+            text: names.join(" "),
+            value: None,
+            confidence,
+        });
+        Some((entity, span))
+    }
+
+    /// The entities in `text`, resolving relative dates against `today`
+    #[must_use]
+    pub fn extract_from(text: &str, today: NaiveDate) -> Vec<Entity> {
+        let words = Self::words(text);
+        let mut entities: Vec<Entity> = Vec::new();
+        let mut at = 0;
+        while at < words.len() {
+            let rest = &words[at..];
+            let (entity, span) = Self::amount_at(rest)
+                .or_else(|| Self::date_at(rest, today))
+                .map_or_else(
+                    || Self::name_at(rest).unwrap_or((None, 1)),
+                    |(entity, span)| (Some(entity), span),
+                );
+            if let Some(entity) = entity {
+                if !entities
+                    .iter()
+                    .any(|e| e.kind == entity.kind && e.text == entity.text)
+                {
+                    entities.push(entity);
+                }
+            }
+            at += span;
+        }
+        entities
+    }
+}
+
+#[async_trait]
+impl EntityExtractor for RuleEntityExtractor {
+    fn name(&self) -> &str {
+        "rules"
+    }
+
+    async fn extract(&self, message: &Message) -> Result<Vec<Entity>> {
+        Ok(Self::extract_from(
+            &message.content,
+            message.timestamp.date_naive(),
+        ))
+    }
+}
+
+/// Model-backed extractor that sends an extraction prompt to a completion
+/// function, usually a small, cheap model
+pub struct PromptEntityExtractor {
+    complete: CompletionFn,
+}
+
+impl PromptEntityExtractor {
+    /// Create an extractor around a completion function
+    #[must_use]
+    pub fn new(complete: CompletionFn) -> Self {
+        Self { complete }
+    }
+
+    /// Build the extraction prompt for a message
+    #[must_use]
+    pub fn extraction_prompt(message: &Message) -> String {
+        format!(
+            "Extract the people, organizations, dates, and amounts of money mentioned in the \
+             message below. Today is {}.\n\
+             Respond with a JSON array of objects with fields \
+             \"kind\" (\"person\", \"organization\", \"date\", or \"amount\"), \
+             \"text\" (as written in the message), \"value\" (YYYY-MM-DD for dates, \
+             number and ISO currency code such as \"12.5 USD\" for amounts, omitted otherwise), \
+             and \"confidence\" (0.0-1.0). \
+             Respond with [] if there are none.\n\nMessage:\n{}",
+            message.timestamp.date_naive(),
+            message.content
+        )
+    }
+
+    /// Parse the model's extraction response
+    ///
+    /// Tolerates surrounding prose or code fences around the JSON array.
+    #[must_use]
+    pub fn parse_response(text: &str) -> Vec<Entity> {
+        parse_json_array(text, "entity extraction")
+    }
+}
+
+#[async_trait]
+impl EntityExtractor for PromptEntityExtractor {
+    fn name(&self) -> &str {
+        "prompt"
+    }
+
+    async fn extract(&self, message: &Message) -> Result<Vec<Entity>> {
+        let response = (self.complete)(Self::extraction_prompt(message)).await?;
+        Ok(Self::parse_response(&response))
+    }
+}
+
+/// The entities an [`ExtractStage`] recorded in message or pipeline metadata
+#[must_use]
+pub fn entities<S: BuildHasher>(metadata: &HashMap<String, serde_json::Value, S>) -> Vec<Entity> {
+    metadata
+        .get(ENTITIES_METADATA_KEY)
+        .and_then(|value| serde_json::from_value(value.clone()).ok())
+        .unwrap_or_default()
+}
+
+/// Pipeline stage that extracts the entities in the message
+///
+/// Register it before `route` as the `extract` stage. When extraction fails
+/// the message is processed without entities.
+pub struct ExtractStage {
+    extractor: Arc<dyn EntityExtractor>,
+    graph: Option<Arc<GraphMemory>>,
+}
+
+impl std::fmt::Debug for ExtractStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExtractStage")
+            .field("extractor", &self.extractor.name())
+            .field("graph", &self.graph.is_some())
+            .finish()
+    }
+}
+
+impl Default for ExtractStage {
+    fn default() -> Self {
+        Self::new(Arc::new(RuleEntityExtractor))
+    }
+}
+
+impl ExtractStage {
+    /// Create the stage
+    #[must_use]
+    pub fn new(extractor: Arc<dyn EntityExtractor>) -> Self {
+        Self {
+            extractor,
+            graph: None,
+        }
+    }
+
+    /// Also remember the people and organizations mentioned in `graph`
+    ///
+    /// Each is stored as an `is_a` relation, e.g. "Acme Corp is a
+    /// organization", in the message's tenant namespace (the `tenant_id`
+    /// metadata) when set, otherwise its user's, so that the
+    /// [`GraphMemoryStage`](crate::graph::GraphMemoryStage) recognizes them
+    /// in later messages.
+    #[must_use]
+    pub fn with_graph(mut self, graph: Arc<GraphMemory>) -> Self {
+        self.graph = Some(graph);
+        self
+    }
+
+    async fn remember(&self, graph: &GraphMemory, message: &Message, entities: &[Entity]) {
+        let namespace = message
+            .metadata
+            .get(TENANT_METADATA_KEY)
+            .and_then(|v| v.as_str())
+            .unwrap_or(&message.user_id);
+        let now = Utc::now();
+        for entity in entities {
+            let object = match entity.kind {
+                EntityKind::Person => "person",
+                EntityKind::Organization => "organization",
+                EntityKind::Date | EntityKind::Amount => continue,
+            };
+            let relation = Relation {
+                id: Uuid::new_v4(),
+                subject: entity.text.clone(),
+                predicate: "is_a".to_string(),
+                object: object.to_string(),
+                confidence: entity.confidence,
+                provenance: MemoryProvenance {
+                    conversation_id: message.conversation_id.clone(),
+                    message_id: Some(message.id),
+                    extractor: self.extractor.name().to_string(),
+                    extracted_at: now,
+                },
+            };
+            if let Err(e) = graph.backend().put(namespace, relation).await {
+                warn!("Failed to remember entity {}: {:#}", entity.text, e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl PipelineStage for ExtractStage {
+    fn name(&self) -> &str {
+        "extract"
+    }
+
+    async fn process(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
+        if ctx.message.content.trim().is_empty() {
+            return Ok(ctx);
+        }
+
+        match self.extractor.extract(&ctx.message).await {
+            Ok(entities) if entities.is_empty() => {}
+            Ok(entities) => {
+                debug!("Extracted {} entities", entities.len());
+                if let Some(graph) = &self.graph {
+                    self.remember(graph, &ctx.message, &entities).await;
+                }
+                let value = serde_json::to_value(&entities)?;
+                ctx.message
+                    .metadata
+                    .insert(ENTITIES_METADATA_KEY.to_string(), value.clone());
+                ctx.metadata
+                    .insert(ENTITIES_METADATA_KEY.to_string(), value);
+            }
+            Err(e) => warn!("Entity extraction failed, continuing without: {:#}", e),
+        }
+        Ok(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Context;
+    use crate::graph::TriplePattern;
+    use parking_lot::RwLock;
+
+    fn found(entities: &[Entity]) -> Vec<(EntityKind, &str, Option<&str>)> {
+        entities
+            .iter()
+            .map(|e| (e.kind, e.text.as_str(), e.value.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn test_rule_extraction() {
+        let today = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+        let entities = RuleEntityExtractor::extract_from(
+            "Hi, Dr. Ada Lovelace here from Acme Corp. Please wire $1,200.50 to Grace Hopper \
+             by March 14th, and 300 EUR tomorrow. The invoice is dated 2025-02-28.",
+            today,
+        );
+        assert_eq!(
+            found(&entities),
+            [
+                (EntityKind::Person, "Ada Lovelace", None),
+                (EntityKind::Organization, "Acme Corp", None),
+                (EntityKind::Amount, "$1,200.50", Some("1200.5 USD")),
+                (EntityKind::Person, "Grace Hopper", None),
+                (EntityKind::Date, "March 14th", Some("2025-03-14")),
+                (EntityKind::Amount, "300 EUR", Some("300 EUR")),
+                (EntityKind::Date, "tomorrow", Some("2025-03-11")),
+                (EntityKind::Date, "2025-02-28", Some("2025-02-28")),
+            ]
+        );
+
+        let entities = RuleEntityExtractor::extract_from("I paid £5k on 3 June 2024.", today);
+        assert_eq!(
+            found(&entities),
+            [
+                (EntityKind::Amount, "£5k", Some("5000 GBP")),
+                (EntityKind::Date, "3 June 2024", Some("2024-06-03")),
+            ]
+        );
+        assert!(RuleEntityExtractor::extract_from("The weather is nice", today).is_empty());
+    }
+
+    #[test]
+    fn test_parse_prompt_response() {
+        let entities = PromptEntityExtractor::parse_response(
+            "```json\n[{\"kind\":\"organization\",\"text\":\"Initech\"},\
+             {\"kind\":\"date\",\"text\":\"next Friday\",\"value\":\"2025-03-14\",\
+             \"confidence\":0.7}]\n```",
+        );
+        assert_eq!(
+            found(&entities),
+            [
+                (EntityKind::Organization, "Initech", None),
+                (EntityKind::Date, "next Friday", Some("2025-03-14")),
+            ]
+        );
+        assert!((entities[0].confidence - 0.5).abs() < f32::EPSILON);
+        assert!(PromptEntityExtractor::parse_response("nothing here").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stage_records_entities() {
+        let graph = Arc::new(GraphMemory::in_memory());
+        let stage = ExtractStage::default().with_graph(graph.clone());
+        let message = Message::text("Grace Hopper at Acme Corp owes $40");
+        let context = Arc::new(RwLock::new(Context::new(message.conversation_id.clone())));
+        let ctx = stage
+            .process(PipelineContext::new(message, context))
+            .await
+            .unwrap();
+
+        let recorded = entities(&ctx.message.metadata);
+        assert_eq!(recorded.len(), 3);
+        assert_eq!(recorded, entities(&ctx.metadata));
+
+        let user = ctx.message.user_id.as_str();
+        let organizations = graph
+            .query(
+                user,
+                &TriplePattern::object("organization").with_predicate("is_a"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(organizations.len(), 1);
+        assert_eq!(organizations[0].sentence(), "Acme Corp is a organization");
+        let people = graph
+            .query(user, &TriplePattern::object("person"))
+            .await
+            .unwrap();
+        assert_eq!(people[0].subject, "Grace Hopper");
+    }
+}
@@ -0,0 +1,388 @@
+//! Prompt adjustment suggestions mined from low-rated responses
+//!
+//! A [`FeedbackAnalyzer`] picks out [`Exchange`]s whose user feedback or
+//! judge rating is low, groups them by the system prompt that produced them,
+//! and asks a judge model to diagnose each group and propose system-prompt or
+//! routing changes. The output is a [`FeedbackReport`] for prompt engineers
+//! to review; nothing is applied to the bot.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    completion::{parse_json_array, CompletionFn},
+    curation::Exchange,
+    sanitize::redact_pii,
+};
+
+/// Rated exchange count and scored failures for one system prompt
+type PromptGroup<'a> = (usize, Vec<(f32, &'a Exchange)>);
+
+/// Configuration for feedback analysis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeedbackAnalysisConfig {
+    /// Exchanges with a feedback score or judge rating at or below this are failures
+    pub max_score: f32,
+    /// Failures shown to the judge per system prompt
+    pub max_examples: usize,
+    /// Skip system prompts with fewer failures than this
+    pub min_failures: usize,
+    /// Mask personal data before exchanges reach the judge or the report
+    pub redact: bool,
+}
+
+impl Default for FeedbackAnalysisConfig {
+    fn default() -> Self {
+        Self {
+            max_score: 0.4,
+            max_examples: 10,
+            min_failures: 2,
+            redact: true,
+        }
+    }
+}
+
+/// What a suggestion changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdjustmentKind {
+    /// Edit the system prompt
+    SystemPrompt,
+    /// Send some requests to a different model or route
+    Routing,
+}
+
+/// A proposed change, for human review
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptSuggestion {
+    /// What the suggestion changes
+    pub kind: AdjustmentKind,
+    /// Failure pattern the suggestion addresses
+    pub problem: String,
+    /// Proposed change, e.g. text to add to the system prompt
+    pub change: String,
+    /// Judge's confidence, 0.0 to 1.0
+    #[serde(default)]
+    pub confidence: f32,
+    /// Indexes into the group's examples that show the problem
+    #[serde(default)]
+    pub examples: Vec<usize>,
+}
+
+/// Failures and suggestions for one system prompt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptFindings {
+    /// System prompt in effect, if any
+    pub system_prompt: Option<String>,
+    /// Rated exchanges under this prompt
+    pub rated: usize,
+    /// Low-rated exchanges under this prompt
+    pub failures: usize,
+    /// Failures shown to the judge, lowest-rated first
+    pub examples: Vec<Exchange>,
+    /// Judge suggestions
+    pub suggestions: Vec<PromptSuggestion>,
+}
+
+impl PromptFindings {
+    /// Share of rated exchanges that failed
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn failure_rate(&self) -> f32 {
+        if self.rated == 0 {
+            0.0
+        } else {
+            self.failures as f32 / self.rated as f32
+        }
+    }
+}
+
+/// Result of a feedback analysis run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedbackReport {
+    /// When the analysis ran
+    pub generated_at: DateTime<Utc>,
+    /// Exchanges considered
+    pub analyzed: usize,
+    /// Exchanges with a feedback score or judge rating
+    pub rated: usize,
+    /// Low-rated exchanges
+    pub failures: usize,
+    /// Findings per system prompt, most failures first
+    pub findings: Vec<PromptFindings>,
+}
+
+impl FeedbackReport {
+    /// Render the report as Markdown for review
+    #[must_use]
+    pub fn to_markdown(&self) -> String {
+        use std::fmt::Write as _;
+
+        let mut out = format!(
+            "# Feedback analysis\n\n{} of {} rated exchanges were low-rated ({} analyzed).\n",
+            self.failures, self.rated, self.analyzed
+        );
+        for findings in &self.findings {
+            let prompt = findings
+                .system_prompt
+                .as_deref()
+                .unwrap_or("(no system prompt)");
+            let _ = write!(
+                out,
+                "\n## {}\n\n{} failures, {:.0}% of rated exchanges.\n",
+                prompt.lines().next().unwrap_or_default(),
+                findings.failures,
+                findings.failure_rate() * 100.0
+            );
+            for suggestion in &findings.suggestions {
+                let kind = match suggestion.kind {
+                    AdjustmentKind::SystemPrompt => "System prompt",
+                    AdjustmentKind::Routing => "Routing",
+                };
+                let _ = write!(
+                    out,
+                    "\n- **{kind}** ({:.0}% confidence): {}\n  - Change: {}\n",
+                    suggestion.confidence * 100.0,
+                    suggestion.problem,
+                    suggestion.change
+                );
+            }
+        }
+        out
+    }
+}
+
+/// Mines low-rated exchanges for prompt and routing adjustments
+pub struct FeedbackAnalyzer {
+    judge: CompletionFn,
+    config: FeedbackAnalysisConfig,
+}
+
+impl FeedbackAnalyzer {
+    /// Create an analyzer around a judge model completion function
+    #[must_use]
+    pub fn new(judge: CompletionFn) -> Self {
+        Self {
+            judge,
+            config: FeedbackAnalysisConfig::default(),
+        }
+    }
+
+    /// Use the given configuration
+    #[must_use]
+    pub fn with_config(mut self, config: FeedbackAnalysisConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// The lowest of an exchange's feedback score and judge rating
+    #[must_use]
+    pub fn score(exchange: &Exchange) -> Option<f32> {
+        match (exchange.feedback_score, exchange.judge_rating) {
+            (Some(feedback), Some(judge)) => Some(feedback.min(judge)),
+            (score, None) | (None, score) => score,
+        }
+    }
+
+    /// Analyze exchanges and ask the judge for suggestions
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a judge call fails.
+    #[instrument(skip(self, exchanges), fields(exchanges = exchanges.len()))]
+    pub async fn analyze(&self, exchanges: &[Exchange]) -> Result<FeedbackReport> {
+        let mut groups: BTreeMap<Option<&str>, PromptGroup<'_>> = BTreeMap::new();
+        let mut rated = 0;
+        for exchange in exchanges {
+            let Some(score) = Self::score(exchange) else {
+                continue;
+            };
+            rated += 1;
+            let group = groups.entry(exchange.system.as_deref()).or_default();
+            group.0 += 1;
+            if score <= self.config.max_score {
+                group.1.push((score, exchange));
+            }
+        }
+
+        let mut findings = Vec::new();
+        let mut failures = 0;
+        for (system_prompt, (group_rated, mut group_failures)) in groups {
+            failures += group_failures.len();
+            if group_failures.is_empty() || group_failures.len() < self.config.min_failures {
+                continue;
+            }
+            let failure_count = group_failures.len();
+            group_failures.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let examples: Vec<Exchange> = group_failures
+                .into_iter()
+                .take(self.config.max_examples)
+                .map(|(_, exchange)| self.prepare(exchange))
+                .collect();
+            let system_prompt = system_prompt.map(|prompt| self.redact(prompt));
+
+            let prompt = Self::analysis_prompt(system_prompt.as_deref(), &examples);
+            let response = (self.judge)(prompt).await?;
+            let suggestions = Self::parse_response(&response)
+                .into_iter()
+                .map(|mut suggestion| {
+                    suggestion.examples.retain(|&i| i < examples.len());
+                    suggestion.confidence = suggestion.confidence.clamp(0.0, 1.0);
+                    suggestion
+                })
+                .collect();
+
+            findings.push(PromptFindings {
+                system_prompt,
+                rated: group_rated,
+                failures: failure_count,
+                examples,
+                suggestions,
+            });
+        }
+        findings.sort_by_key(|f| Reverse(f.failures));
+
+        Ok(FeedbackReport {
+            generated_at: Utc::now(),
+            analyzed: exchanges.len(),
+            rated,
+            failures,
+            findings,
+        })
+    }
+
+    /// Build the judge prompt for one system prompt's failures
+    #[must_use]
+    pub fn analysis_prompt(system_prompt: Option<&str>, examples: &[Exchange]) -> String {
+        let examples = examples
+            .iter()
+            .enumerate()
+            .map(|(i, exchange)| {
+                format!(
+                    "[{i}] User: {}\nAssistant: {}",
+                    exchange.prompt, exchange.completion
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        format!(
+            "You review a chatbot's low-rated responses to help a prompt engineer improve it. \
+             Identify recurring failure patterns and suggest changes to the system prompt, or \
+             routing changes such as sending certain requests to a different model.\n\
+             Respond with a JSON array of objects with fields \"kind\" (\"system_prompt\" or \
+             \"routing\"), \"problem\", \"change\" (the exact text or rule to add), \
+             \"confidence\" (0.0-1.0), and \"examples\" (indexes of the responses showing the \
+             problem). Respond with [] if there is no clear pattern.\n\n\
+             System prompt:\n{}\n\nLow-rated responses:\n{examples}",
+            system_prompt.unwrap_or("(none)")
+        )
+    }
+
+    /// Parse the judge's response
+    ///
+    /// Tolerates surrounding prose or code fences around the JSON array.
+    #[must_use]
+    pub fn parse_response(text: &str) -> Vec<PromptSuggestion> {
+        parse_json_array(text, "feedback analysis")
+    }
+
+    fn prepare(&self, exchange: &Exchange) -> Exchange {
+        let mut exchange = exchange.clone();
+        exchange.prompt = self.redact(&exchange.prompt);
+        exchange.completion = self.redact(&exchange.completion);
+        exchange.system = exchange.system.as_deref().map(|s| self.redact(s));
+        exchange
+    }
+
+    fn redact(&self, text: &str) -> String {
+        if self.config.redact {
+            redact_pii(text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn exchange(system: &str, prompt: &str, score: f32) -> Exchange {
+        Exchange {
+            conversation_id: "c1".to_string(),
+            response_id: None,
+            system: Some(system.to_string()),
+            prompt: prompt.to_string(),
+            completion: "I cannot help with that.".to_string(),
+            feedback_score: None,
+            judge_rating: None,
+            timestamp: Utc::now(),
+        }
+        .with_feedback_score(score)
+    }
+
+    #[tokio::test]
+    async fn test_analyze_groups_failures_by_system_prompt() {
+        let analyzer = FeedbackAnalyzer::new(Arc::new(|prompt: String| {
+            Box::pin(async move {
+                assert!(prompt.contains("[1] User: Can I get a refund?"));
+                assert!(!prompt.contains("bob@example.com"));
+                assert!(!prompt.contains("Pricing?"));
+                Ok("Here you go:\n```json\n[{\"kind\": \"system_prompt\", \
+                    \"problem\": \"Refuses refund questions\", \
+                    \"change\": \"Answer refund policy questions directly.\", \
+                    \"confidence\": 1.4, \"examples\": [0, 7]}]\n```"
+                    .to_string())
+            })
+        }));
+
+        let exchanges = vec![
+            exchange("Support bot", "Refund for order, mail bob@example.com", 0.1),
+            exchange("Support bot", "Can I get a refund?", 0.2),
+            exchange("Support bot", "Where is my order?", 0.9),
+            exchange("Sales bot", "Pricing?", 0.3),
+            Exchange {
+                feedback_score: None,
+                ..exchange("Support bot", "Unrated", 0.0)
+            },
+        ];
+        let report = analyzer.analyze(&exchanges).await.unwrap();
+
+        assert_eq!(report.analyzed, 5);
+        assert_eq!(report.rated, 4);
+        assert_eq!(report.failures, 3);
+        assert_eq!(report.findings.len(), 1);
+
+        let findings = &report.findings[0];
+        assert_eq!(findings.system_prompt.as_deref(), Some("Support bot"));
+        assert_eq!(findings.failures, 2);
+        assert!((findings.failure_rate() - 2.0 / 3.0).abs() < f32::EPSILON);
+        assert_eq!(
+            findings.examples[0].prompt,
+            "Refund for order, mail [redacted]"
+        );
+        assert_eq!(findings.suggestions[0].kind, AdjustmentKind::SystemPrompt);
+        assert_eq!(findings.suggestions[0].examples, [0]);
+        assert!((findings.suggestions[0].confidence - 1.0).abs() < f32::EPSILON);
+
+        assert!(report
+            .to_markdown()
+            .contains("Answer refund policy questions directly."));
+    }
+
+    #[test]
+    fn test_score_uses_lowest_rating() {
+        let rated = exchange("s", "p", 0.8).with_judge_rating(0.3);
+        assert_eq!(FeedbackAnalyzer::score(&rated), Some(0.3));
+        assert!(FeedbackAnalyzer::parse_response("no json here").is_empty());
+    }
+}
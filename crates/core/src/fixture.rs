@@ -0,0 +1,642 @@
+//! Golden request/response fixtures for plugins
+//!
+//! Plugin authors describe the behaviour of a plugin as YAML: plugin
+//! settings plus a list of cases, each a [`PluginRequest`] and the response
+//! it should produce.
+//!
+//! ```yaml
+//! plugin: unit_converter
+//! cases:
+//!   - name: miles to kilometres
+//!     request:
+//!       type: invoke_tool
+//!       data: { tool: convert, value: "1", from: mi, to: km }
+//!     expect:
+//!       success: true
+//!       data: { result: "1.609344" }
+//!   - name: currency uses the rate source
+//!     mocks:
+//!       fx.rate: [{ ok: "0.9" }]
+//!     request:
+//!       type: invoke_tool
+//!       data: { tool: convert, value: "10", from: USD, to: EUR }
+//!     expect:
+//!       data: { result: "9" }
+//!       calls: [fx.rate]
+//! ```
+//!
+//! [`FixtureSuite::run`] builds a fresh plugin for every case and hands it a
+//! [`MockBroker`] loaded with the case's `mocks`. Plugins reach external
+//! services through traits such as [`FxRates`](crate::tools::FxRates) or
+//! [`TicketBackend`](crate::tickets::TicketBackend); a test implements the
+//! trait over the broker so the fixture controls every reply. Expected data
+//! and metadata match as a subset of the actual response unless the case
+//! sets `exact`, and each mismatch is reported as a [`FixtureDiff`] at a
+//! JSON pointer.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    error::Error,
+    plugin::{Permission, Plugin, PluginConfig, PluginRequest, PluginResponse, RequestType},
+};
+
+/// A scripted reply from a mocked service
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MockReply {
+    /// The call succeeds with this value
+    Ok(Value),
+    /// The call fails with this message
+    Error(String),
+}
+
+/// A call a plugin made through a [`MockBroker`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MockCall {
+    /// Operation name, as used in the fixture's `mocks`
+    pub operation: String,
+    /// Arguments the plugin passed
+    pub args: Value,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    replies: HashMap<String, VecDeque<MockReply>>,
+    calls: Vec<MockCall>,
+}
+
+/// Scripted replies for the services a plugin calls
+///
+/// Each operation answers with its replies in order and then keeps
+/// repeating the last one. Clones share the same script and call log.
+#[derive(Debug, Clone, Default)]
+pub struct MockBroker {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockBroker {
+    /// Create a broker answering each operation with `replies`
+    #[must_use]
+    pub fn new(replies: HashMap<String, Vec<MockReply>>) -> Self {
+        let replies = replies
+            .into_iter()
+            .map(|(operation, replies)| (operation, replies.into()))
+            .collect();
+        Self {
+            state: Arc::new(Mutex::new(MockState {
+                replies,
+                calls: Vec::new(),
+            })),
+        }
+    }
+
+    /// Record a call and return its scripted reply
+    ///
+    /// # Errors
+    ///
+    /// Returns the scripted error, or [`Error::NotFound`] if the fixture has
+    /// no reply for `operation`.
+    pub fn call(&self, operation: &str, args: Value) -> Result<Value> {
+        let reply = {
+            let mut state = self.state.lock();
+            state.calls.push(MockCall {
+                operation: operation.to_string(),
+                args,
+            });
+            state.replies.get_mut(operation).and_then(|queue| {
+                if queue.len() > 1 {
+                    queue.pop_front()
+                } else {
+                    queue.front().cloned()
+                }
+            })
+        };
+        match reply {
+            Some(MockReply::Ok(value)) => Ok(value),
+            Some(MockReply::Error(message)) => Err(Error::Provider(message).into()),
+            None => Err(Error::NotFound(format!("No mocked reply for '{operation}'")).into()),
+        }
+    }
+
+    /// Calls made so far, in order
+    #[must_use]
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.state.lock().calls.clone()
+    }
+}
+
+/// The request a fixture case sends
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureRequest {
+    /// Request ID; defaults to one derived from the case position
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Request type
+    #[serde(rename = "type")]
+    pub request_type: RequestType,
+    /// Request data
+    #[serde(default)]
+    pub data: Value,
+    /// Request metadata
+    #[serde(default)]
+    pub metadata: HashMap<String, Value>,
+}
+
+/// What a fixture case expects back
+///
+/// Unset fields are not checked.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FixtureExpectation {
+    /// Expected success status
+    pub success: Option<bool>,
+    /// Expected response data
+    pub data: Option<Value>,
+    /// Text the error message must contain
+    pub error: Option<String>,
+    /// Expected response metadata entries
+    pub metadata: HashMap<String, Value>,
+    /// Compare data and metadata for equality instead of as a subset
+    pub exact: bool,
+    /// Mocked operations the plugin must call, in order
+    pub calls: Option<Vec<String>>,
+}
+
+/// One golden request/response pair
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureCase {
+    /// Case name, used in reports
+    pub name: String,
+    /// Scripted replies for the case's [`MockBroker`]
+    #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
+    pub mocks: HashMap<String, Vec<MockReply>>,
+    /// Request to send
+    pub request: FixtureRequest,
+    /// Expected response
+    #[serde(default)]
+    pub expect: FixtureExpectation,
+}
+
+/// A plugin's fixture file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureSuite {
+    /// Name the plugin must report, if set
+    #[serde(default)]
+    pub plugin: Option<String>,
+    /// Settings passed to [`Plugin::initialize`]
+    #[serde(default)]
+    pub settings: HashMap<String, Value>,
+    /// Permissions granted to the plugin
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+    /// Cases, run in order
+    pub cases: Vec<FixtureCase>,
+}
+
+impl FixtureSuite {
+    /// Parse a fixture document
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Configuration`] if the YAML is invalid or has no cases.
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        let suite: Self = serde_yaml::from_str(text)
+            .map_err(|e| Error::Configuration(format!("Invalid fixture YAML: {e}")))?;
+        if suite.cases.is_empty() {
+            return Err(Error::Configuration("Fixture has no cases".to_string()).into());
+        }
+        Ok(suite)
+    }
+
+    /// Read a fixture file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a valid fixture.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read fixture {}", path.display()))?;
+        Self::from_yaml(&text).with_context(|| format!("Invalid fixture {}", path.display()))
+    }
+
+    fn config(&self) -> PluginConfig {
+        PluginConfig {
+            settings: self.settings.clone(),
+            permissions: self.permissions.clone(),
+            ..PluginConfig::default()
+        }
+    }
+
+    /// Run every case against a fresh plugin from `make_plugin`
+    ///
+    /// Each plugin is initialized with the suite's settings, sent the case
+    /// request, and shut down. A plugin returning `Err` is compared as an
+    /// error response carrying that message.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Configuration`] if the plugin's name does not match
+    /// the fixture. Case failures are reported, not returned.
+    pub async fn run<F>(&self, mut make_plugin: F) -> Result<FixtureReport>
+    where
+        F: FnMut(MockBroker) -> Box<dyn Plugin>,
+    {
+        let mut report = FixtureReport {
+            plugin: self.plugin.clone().unwrap_or_default(),
+            cases: Vec::with_capacity(self.cases.len()),
+        };
+        for (index, case) in self.cases.iter().enumerate() {
+            let broker = MockBroker::new(case.mocks.clone());
+            let mut plugin = make_plugin(broker.clone());
+            if let Some(expected) = &self.plugin {
+                if plugin.name() != expected {
+                    return Err(Error::Configuration(format!(
+                        "Fixture is for plugin '{expected}', got '{}'",
+                        plugin.name()
+                    ))
+                    .into());
+                }
+            }
+            report.plugin = plugin.name().to_string();
+
+            if let Err(e) = plugin.initialize(self.config()).await {
+                report.cases.push(CaseResult {
+                    name: case.name.clone(),
+                    diffs: vec![FixtureDiff {
+                        path: "initialize".to_string(),
+                        expected: Value::Null,
+                        actual: Some(e.to_string().into()),
+                    }],
+                });
+                continue;
+            }
+            let id = case
+                .request
+                .id
+                .clone()
+                .unwrap_or_else(|| format!("fixture-{}", index + 1));
+            let request = PluginRequest {
+                id: id.clone(),
+                request_type: case.request.request_type.clone(),
+                data: case.request.data.clone(),
+                metadata: case.request.metadata.clone(),
+            };
+            let response = match plugin.process(request).await {
+                Ok(response) => response,
+                Err(e) => PluginResponse::error(id.clone(), e),
+            };
+            if let Err(e) = plugin.shutdown().await {
+                tracing::warn!("Plugin {} failed to shut down: {}", plugin.name(), e);
+            }
+
+            let mut diffs = compare_response(&id, &case.expect, &response);
+            if let Some(expected) = &case.expect.calls {
+                let actual: Vec<String> = broker
+                    .calls()
+                    .into_iter()
+                    .map(|call| call.operation)
+                    .collect();
+                if &actual != expected {
+                    diffs.push(FixtureDiff {
+                        path: "calls".to_string(),
+                        expected: serde_json::json!(expected),
+                        actual: Some(serde_json::json!(actual)),
+                    });
+                }
+            }
+            report.cases.push(CaseResult {
+                name: case.name.clone(),
+                diffs,
+            });
+        }
+        Ok(report)
+    }
+}
+
+fn compare_response(
+    id: &str,
+    expect: &FixtureExpectation,
+    response: &PluginResponse,
+) -> Vec<FixtureDiff> {
+    let mut diffs = Vec::new();
+    if response.id != id {
+        diffs.push(FixtureDiff {
+            path: "/id".to_string(),
+            expected: id.into(),
+            actual: Some(response.id.clone().into()),
+        });
+    }
+    if let Some(success) = expect.success {
+        if response.success != success {
+            diffs.push(FixtureDiff {
+                path: "/success".to_string(),
+                expected: success.into(),
+                actual: Some(response.success.into()),
+            });
+        }
+    }
+    if let Some(fragment) = &expect.error {
+        let matches = response
+            .error
+            .as_deref()
+            .is_some_and(|error| error.contains(fragment.as_str()));
+        if !matches {
+            diffs.push(FixtureDiff {
+                path: "/error".to_string(),
+                expected: fragment.clone().into(),
+                actual: response.error.clone().map(Value::from),
+            });
+        }
+    }
+    if let Some(data) = &expect.data {
+        compare_value(
+            "/data",
+            data,
+            Some(&response.data),
+            expect.exact,
+            &mut diffs,
+        );
+    }
+    if !expect.metadata.is_empty() || expect.exact {
+        let expected = serde_json::to_value(&expect.metadata).unwrap_or_default();
+        let actual = serde_json::to_value(&response.metadata).unwrap_or_default();
+        compare_value(
+            "/metadata",
+            &expected,
+            Some(&actual),
+            expect.exact,
+            &mut diffs,
+        );
+    }
+    diffs
+}
+
+/// Compare `actual` against `expected`, recording mismatches under `path`
+///
+/// Outside exact mode, objects only need the expected keys; arrays and
+/// scalars must match in full.
+fn compare_value(
+    path: &str,
+    expected: &Value,
+    actual: Option<&Value>,
+    exact: bool,
+    diffs: &mut Vec<FixtureDiff>,
+) {
+    match (expected, actual) {
+        (Value::Object(expected), Some(Value::Object(actual))) => {
+            for (key, value) in expected {
+                let child = format!("{path}/{}", escape_pointer(key));
+                compare_value(&child, value, actual.get(key), exact, diffs);
+            }
+            if exact {
+                for (key, value) in actual {
+                    if !expected.contains_key(key) {
+                        diffs.push(FixtureDiff {
+                            path: format!("{path}/{}", escape_pointer(key)),
+                            expected: Value::Null,
+                            actual: Some(value.clone()),
+                        });
+                    }
+                }
+            }
+        }
+        (Value::Array(expected), Some(Value::Array(actual))) if expected.len() == actual.len() => {
+            for (index, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                compare_value(
+                    &format!("{path}/{index}"),
+                    expected,
+                    Some(actual),
+                    exact,
+                    diffs,
+                );
+            }
+        }
+        (expected, actual) if actual != Some(expected) => diffs.push(FixtureDiff {
+            path: path.to_string(),
+            expected: expected.clone(),
+            actual: actual.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// A mismatch between the expected and actual response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixtureDiff {
+    /// JSON pointer into the response, or `calls` / `initialize`
+    pub path: String,
+    /// Expected value
+    pub expected: Value,
+    /// Actual value, `None` if missing
+    pub actual: Option<Value>,
+}
+
+impl fmt::Display for FixtureDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.actual {
+            Some(actual) => write!(
+                f,
+                "{}: expected {}, got {}",
+                self.path, self.expected, actual
+            ),
+            None => write!(f, "{}: expected {}, got nothing", self.path, self.expected),
+        }
+    }
+}
+
+/// Outcome of one fixture case
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaseResult {
+    /// Case name
+    pub name: String,
+    /// Mismatches; empty if the case passed
+    pub diffs: Vec<FixtureDiff>,
+}
+
+impl CaseResult {
+    /// Whether the response matched
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        self.diffs.is_empty()
+    }
+}
+
+/// Outcome of a fixture suite
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureReport {
+    /// Name of the plugin under test
+    pub plugin: String,
+    /// Case outcomes, in fixture order
+    pub cases: Vec<CaseResult>,
+}
+
+impl FixtureReport {
+    /// Whether every case passed
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        self.cases.iter().all(CaseResult::passed)
+    }
+
+    /// Cases that failed
+    pub fn failures(&self) -> impl Iterator<Item = &CaseResult> {
+        self.cases.iter().filter(|case| !case.passed())
+    }
+
+    /// Fail with the full report if any case failed, for use in tests
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Validation`] listing every diff.
+    pub fn ensure_passed(&self) -> Result<()> {
+        if self.passed() {
+            Ok(())
+        } else {
+            Err(Error::Validation(self.to_string()).into())
+        }
+    }
+}
+
+impl fmt::Display for FixtureReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let failed = self.failures().count();
+        write!(
+            f,
+            "{}: {} of {} fixture cases passed",
+            self.plugin,
+            self.cases.len() - failed,
+            self.cases.len()
+        )?;
+        for case in self.failures() {
+            write!(f, "\n  {}", case.name)?;
+            for diff in &case.diffs {
+                write!(f, "\n    {diff}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use async_trait::async_trait;
+    use bigdecimal::BigDecimal;
+
+    use super::*;
+    use crate::tools::{FxRates, UnitConverterPlugin};
+
+    struct MockFx(MockBroker);
+
+    #[async_trait]
+    impl FxRates for MockFx {
+        async fn rate(&self, from: &str, to: &str) -> Result<BigDecimal> {
+            let rate = self
+                .0
+                .call("fx.rate", serde_json::json!({ "from": from, "to": to }))?;
+            Ok(BigDecimal::from_str(rate.as_str().unwrap_or_default())?)
+        }
+    }
+
+    const CONVERTER_FIXTURE: &str = r#"
+plugin: unit_converter
+cases:
+  - name: miles to kilometres
+    request:
+      type: invoke_tool
+      data: { tool: convert, value: "1", from: mi, to: km }
+    expect:
+      success: true
+      data: { result: "1.609344" }
+  - name: currency uses the rate source
+    mocks:
+      fx.rate: [{ ok: "0.9" }]
+    request:
+      type: invoke_tool
+      data: { tool: convert, value: "10", from: USD, to: EUR }
+    expect:
+      data: { result: "9" }
+      calls: [fx.rate]
+  - name: rate source failure
+    mocks:
+      fx.rate: [{ error: "rates unavailable" }]
+    request:
+      type: invoke_tool
+      data: { tool: convert, value: "10", from: USD, to: EUR }
+    expect:
+      success: false
+      error: rates unavailable
+"#;
+
+    #[tokio::test]
+    async fn test_fixture_runs_with_mocked_services() {
+        let suite = FixtureSuite::from_yaml(CONVERTER_FIXTURE).unwrap();
+        let report = suite
+            .run(|broker| Box::new(UnitConverterPlugin::with_fx_rates(Arc::new(MockFx(broker)))))
+            .await
+            .unwrap();
+        report.ensure_passed().unwrap();
+        assert_eq!(report.cases.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_fixture_reports_diffs() {
+        let suite = FixtureSuite::from_yaml(
+            r#"
+plugin: unit_converter
+cases:
+  - name: wrong result
+    request:
+      type: invoke_tool
+      data: { tool: convert, value: "1", from: mi, to: km }
+    expect:
+      data: { from: mi, result: "2" }
+  - name: unsupported
+    request: { type: handle_event }
+    expect: { success: true, calls: [fx.rate] }
+"#,
+        )
+        .unwrap();
+        let report = suite
+            .run(|_| Box::new(UnitConverterPlugin::new()))
+            .await
+            .unwrap();
+
+        assert!(!report.passed());
+        let failures: Vec<_> = report.failures().collect();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].diffs.len(), 1);
+        assert_eq!(failures[0].diffs[0].path, "/data/result");
+        let paths: Vec<_> = failures[1].diffs.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, ["/success", "calls"]);
+        assert!(report.to_string().contains("0 of 2 fixture cases passed"));
+    }
+
+    #[test]
+    fn test_mock_broker_repeats_last_reply() {
+        let broker = MockBroker::new(HashMap::from([(
+            "lookup".to_string(),
+            vec![MockReply::Ok(1.into()), MockReply::Ok(2.into())],
+        )]));
+        let replies: Vec<_> = (0..3)
+            .map(|_| broker.call("lookup", Value::Null).unwrap())
+            .collect();
+        assert_eq!(replies, [1, 2, 2]);
+        assert!(broker.call("missing", Value::Null).is_err());
+        assert_eq!(broker.calls().len(), 4);
+    }
+}
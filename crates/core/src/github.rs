@@ -0,0 +1,851 @@
+//! GitHub App channel adapter
+//!
+//! Issue comments and pull request reviews that mention the app are
+//! answered with a comment on the same issue or pull request. Each issue or
+//! pull request is one conversation, keyed `github:{owner}/{repo}#{number}`.
+//! Comments can be paced per conversation with a [`RateShape`].
+//!
+//! Pull request diffs and repository files are exposed to the model as
+//! tools by [`GitHubToolsPlugin`]. Tool calls go through the plugin
+//! registry, so they only run when the plugin has been granted
+//! [`Permission::NetworkAccess`].
+//!
+//! Deliveries must be authenticated with [`verify_sha256_header`] against
+//! the app's webhook secret before they are passed to the adapter.
+//!
+//! [`verify_sha256_header`]: crate::webhook::verify_sha256_header
+
+use std::fmt::Write as _;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+    bot::Bot,
+    error::Error,
+    message::{Message, MessageType, Response, CHANNEL_METADATA_KEY},
+    plugin::{
+        Capability, CapabilityType, Permission, Plugin, PluginRequest, PluginResponse, RequestType,
+    },
+    shaping::{RateShape, RateShaper},
+};
+
+/// Message metadata key holding the `owner/repo` the message came from
+pub const REPOSITORY_METADATA_KEY: &str = "github_repository";
+
+/// Message metadata key holding the issue or pull request number
+pub const NUMBER_METADATA_KEY: &str = "github_number";
+
+/// Message metadata key set when the conversation is a pull request
+pub const PULL_REQUEST_METADATA_KEY: &str = "github_is_pull_request";
+
+/// Message metadata key holding the app installation ID
+pub const INSTALLATION_METADATA_KEY: &str = "github_installation_id";
+
+/// Diffs and files larger than this are truncated before reaching the model
+pub const MAX_TOOL_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// A GitHub user
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct User {
+    /// Login
+    pub login: String,
+    /// `User` or `Bot`
+    #[serde(default, rename = "type")]
+    pub kind: String,
+}
+
+/// A repository
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Repository {
+    /// `owner/repo`
+    pub full_name: String,
+}
+
+/// The app installation that received the event
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Installation {
+    /// Installation ID
+    pub id: u64,
+}
+
+/// An issue or pull request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Issue {
+    /// Number
+    pub number: u64,
+    /// Title
+    #[serde(default)]
+    pub title: String,
+    /// Present when the issue is a pull request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_request: Option<serde_json::Value>,
+}
+
+/// An issue comment or review
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Comment {
+    /// ID
+    pub id: u64,
+    /// Markdown body; reviews may have none
+    #[serde(default)]
+    pub body: Option<String>,
+    /// Author
+    pub user: User,
+}
+
+/// A webhook event the adapter handles
+#[derive(Debug, Clone)]
+pub enum GitHubEvent {
+    /// `issue_comment` with action `created`, on an issue or pull request
+    IssueComment {
+        /// Repository
+        repository: Repository,
+        /// Installation
+        installation: Option<Installation>,
+        /// Issue or pull request commented on
+        issue: Issue,
+        /// The comment
+        comment: Comment,
+    },
+    /// `pull_request_review` with action `submitted`
+    PullRequestReview {
+        /// Repository
+        repository: Repository,
+        /// Installation
+        installation: Option<Installation>,
+        /// Pull request reviewed, as an issue
+        pull_request: Issue,
+        /// The review
+        review: Comment,
+    },
+}
+
+impl GitHubEvent {
+    /// Parse a delivery from its `X-GitHub-Event` header and body
+    ///
+    /// Returns `None` for events and actions the adapter does not handle.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if a handled event has a malformed body.
+    pub fn parse(event: &str, body: &[u8]) -> Result<Option<Self>> {
+        #[derive(Deserialize)]
+        struct Payload {
+            action: String,
+            repository: Repository,
+            installation: Option<Installation>,
+            issue: Option<Issue>,
+            comment: Option<Comment>,
+            pull_request: Option<Issue>,
+            review: Option<Comment>,
+        }
+
+        if !matches!(event, "issue_comment" | "pull_request_review") {
+            return Ok(None);
+        }
+        let payload: Payload = serde_json::from_slice(body)
+            .map_err(|e| Error::InvalidInput(format!("Malformed {event} payload: {e}")))?;
+        let event =
+            match (event, payload.action.as_str()) {
+                ("issue_comment", "created") => {
+                    payload
+                        .issue
+                        .zip(payload.comment)
+                        .map(|(issue, comment)| Self::IssueComment {
+                            repository: payload.repository,
+                            installation: payload.installation,
+                            issue,
+                            comment,
+                        })
+                }
+                ("pull_request_review", "submitted") => payload
+                    .pull_request
+                    .zip(payload.review)
+                    .map(|(pull_request, review)| Self::PullRequestReview {
+                        repository: payload.repository,
+                        installation: payload.installation,
+                        pull_request,
+                        review,
+                    }),
+                _ => None,
+            };
+        Ok(event)
+    }
+
+    fn parts(&self) -> (&Repository, Option<&Installation>, &Issue, &Comment, bool) {
+        match self {
+            Self::IssueComment {
+                repository,
+                installation,
+                issue,
+                comment,
+            } => (
+                repository,
+                installation.as_ref(),
+                issue,
+                comment,
+                issue.pull_request.is_some(),
+            ),
+            Self::PullRequestReview {
+                repository,
+                installation,
+                pull_request,
+                review,
+            } => (
+                repository,
+                installation.as_ref(),
+                pull_request,
+                review,
+                true,
+            ),
+        }
+    }
+}
+
+/// GitHub REST API operations used by the adapter and tools
+#[async_trait]
+pub trait GitHubApi: Send + Sync {
+    /// Comment on an issue or pull request, returning the comment ID
+    async fn create_comment(
+        &self,
+        installation_id: u64,
+        repository: &str,
+        number: u64,
+        body: &str,
+    ) -> Result<u64>;
+
+    /// Unified diff of a pull request
+    async fn pull_request_diff(
+        &self,
+        installation_id: u64,
+        repository: &str,
+        number: u64,
+    ) -> Result<String>;
+
+    /// Contents of a file at a ref, or the default branch
+    async fn file_contents(
+        &self,
+        installation_id: u64,
+        repository: &str,
+        path: &str,
+        git_ref: Option<&str>,
+    ) -> Result<String>;
+}
+
+/// Render a response as a GitHub comment
+///
+/// An attached [`Embed`](crate::message::Embed) is appended as a heading, a field table, and a link.
+#[must_use]
+pub fn format_comment(response: &Response) -> String {
+    let mut body = response.content.trim().to_string();
+    if let Some(embed) = response.embed() {
+        let _ = write!(body, "\n\n### {}\n", embed.title);
+        if let Some(description) = &embed.description {
+            let _ = write!(body, "\n{description}\n");
+        }
+        if !embed.fields.is_empty() {
+            body.push_str("\n| | |\n|---|---|\n");
+            for field in &embed.fields {
+                let _ = writeln!(
+                    body,
+                    "| **{}** | {} |",
+                    escape_cell(&field.name),
+                    escape_cell(&field.value)
+                );
+            }
+        }
+        if let Some(url) = &embed.url {
+            let _ = write!(body, "\n[{}]({url})\n", embed.title);
+        }
+    }
+    body
+}
+
+fn escape_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Routes GitHub comments and reviews through a [`Bot`]
+pub struct GitHubAdapter {
+    bot: Bot,
+    api: Arc<dyn GitHubApi>,
+    app_slug: String,
+    shaper: Option<RateShaper>,
+}
+
+impl GitHubAdapter {
+    /// Create an adapter for the app whose bot user is `{app_slug}[bot]`
+    #[must_use]
+    pub fn new(bot: Bot, api: Arc<dyn GitHubApi>, app_slug: impl Into<String>) -> Self {
+        Self {
+            bot,
+            api,
+            app_slug: app_slug.into(),
+            shaper: None,
+        }
+    }
+
+    /// Pace comments to `shape`, queueing per issue or pull request
+    ///
+    /// Comments are not edited, so the coalesce window is unused.
+    #[must_use]
+    pub fn with_rate_shape(mut self, shape: RateShape) -> Self {
+        self.shaper = Some(RateShaper::new(shape));
+        self
+    }
+
+    /// Conversation for an issue or pull request
+    #[must_use]
+    pub fn conversation_id(repository: &str, number: u64) -> String {
+        format!("github:{repository}#{number}")
+    }
+
+    /// Convert an event into a bot message
+    ///
+    /// Returns `None` unless a human mentions `@{app_slug}`; the mention is
+    /// removed from the text.
+    #[must_use]
+    pub fn to_message(&self, event: &GitHubEvent) -> Option<Message> {
+        let (repository, installation, issue, comment, is_pull_request) = event.parts();
+        if comment.user.kind == "Bot" {
+            return None;
+        }
+        let mention = format!("@{}", self.app_slug);
+        let body = comment.body.as_deref()?;
+        if !body.to_lowercase().contains(&mention.to_lowercase()) {
+            return None;
+        }
+
+        let text = body
+            .split_whitespace()
+            .filter(|word| !word.eq_ignore_ascii_case(&mention))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let message_type = if text.starts_with('/') {
+            MessageType::Command
+        } else {
+            MessageType::Text
+        };
+        let mut message = Message::with_type(text, message_type)
+            .with_conversation_id(Self::conversation_id(&repository.full_name, issue.number))
+            .with_user_id(comment.user.login.clone())
+            .with_metadata(CHANNEL_METADATA_KEY, serde_json::json!("github"))
+            .with_metadata(
+                REPOSITORY_METADATA_KEY,
+                serde_json::json!(repository.full_name),
+            )
+            .with_metadata(NUMBER_METADATA_KEY, serde_json::json!(issue.number))
+            .with_metadata(
+                PULL_REQUEST_METADATA_KEY,
+                serde_json::json!(is_pull_request),
+            );
+        if let Some(installation) = installation {
+            message = message.with_metadata(
+                INSTALLATION_METADATA_KEY,
+                serde_json::json!(installation.id),
+            );
+        }
+        Some(message)
+    }
+
+    /// Answer a webhook delivery
+    ///
+    /// Returns the ID of the comment posted, or `None` if the delivery was
+    /// not addressed to the app.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload is malformed, the event has no
+    /// installation, processing fails, or the comment cannot be posted.
+    pub async fn handle(&self, event_name: &str, body: &[u8]) -> Result<Option<u64>> {
+        let Some(event) = GitHubEvent::parse(event_name, body)? else {
+            debug!("Ignoring GitHub {} event", event_name);
+            return Ok(None);
+        };
+        let Some(message) = self.to_message(&event) else {
+            return Ok(None);
+        };
+
+        let (repository, installation, issue, _, _) = event.parts();
+        let installation_id = installation
+            .map(|installation| installation.id)
+            .ok_or_else(|| Error::InvalidInput("Event has no app installation".to_string()))?;
+        let response = self.bot.process(message).await?;
+        if let Some(shaper) = &self.shaper {
+            shaper.acquire(&response.conversation_id).await;
+        }
+        let comment_id = self
+            .api
+            .create_comment(
+                installation_id,
+                &repository.full_name,
+                issue.number,
+                &format_comment(&response),
+            )
+            .await?;
+        Ok(Some(comment_id))
+    }
+}
+
+/// Tools for reading pull request diffs and repository files
+///
+/// Invoked with [`RequestType::InvokeTool`] and data of the form
+/// `{"tool": "get_diff", "installation_id": 1, "repository": "o/r", "number": 7}`
+/// or `{"tool": "get_file", "installation_id": 1, "repository": "o/r",
+/// "path": "src/lib.rs", "ref": "main"}`. Output is truncated to
+/// [`MAX_TOOL_OUTPUT_BYTES`].
+pub struct GitHubToolsPlugin {
+    api: Arc<dyn GitHubApi>,
+}
+
+impl GitHubToolsPlugin {
+    /// Create the plugin
+    #[must_use]
+    pub fn new(api: Arc<dyn GitHubApi>) -> Self {
+        Self { api }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "tool", rename_all = "snake_case")]
+enum ToolCall {
+    GetDiff {
+        installation_id: u64,
+        repository: String,
+        number: u64,
+    },
+    GetFile {
+        installation_id: u64,
+        repository: String,
+        path: String,
+        #[serde(default, rename = "ref")]
+        git_ref: Option<String>,
+    },
+}
+
+#[async_trait]
+impl Plugin for GitHubToolsPlugin {
+    fn name(&self) -> &str {
+        "github"
+    }
+
+    fn version(&self) -> &str {
+        "1.0.0"
+    }
+
+    fn description(&self) -> &'static str {
+        "Reads pull request diffs and repository files"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        let tool = |name: &str, description: &str| Capability {
+            name: name.to_string(),
+            capability_type: CapabilityType::ToolProvider,
+            description: description.to_string(),
+            required_permissions: vec![Permission::NetworkAccess],
+        };
+        vec![
+            tool("get_diff", "Fetch the unified diff of a pull request"),
+            tool("get_file", "Fetch a file from a repository"),
+        ]
+    }
+
+    async fn process(&self, request: PluginRequest) -> Result<PluginResponse> {
+        if !matches!(request.request_type, RequestType::InvokeTool) {
+            return Ok(PluginResponse::error(
+                request.id,
+                "Unsupported request type",
+            ));
+        }
+        let call = match serde_json::from_value::<ToolCall>(request.data) {
+            Ok(call) => call,
+            Err(e) => return Ok(PluginResponse::error(request.id, e)),
+        };
+        let output = match call {
+            ToolCall::GetDiff {
+                installation_id,
+                repository,
+                number,
+            } => {
+                self.api
+                    .pull_request_diff(installation_id, &repository, number)
+                    .await
+            }
+            ToolCall::GetFile {
+                installation_id,
+                repository,
+                path,
+                git_ref,
+            } => {
+                self.api
+                    .file_contents(installation_id, &repository, &path, git_ref.as_deref())
+                    .await
+            }
+        };
+        Ok(match output {
+            Ok(text) => {
+                let (text, truncated) = truncate(text, MAX_TOOL_OUTPUT_BYTES);
+                PluginResponse::success(
+                    request.id,
+                    serde_json::json!({ "content": text, "truncated": truncated }),
+                )
+            }
+            Err(e) => PluginResponse::error(request.id, e),
+        })
+    }
+}
+
+fn truncate(mut text: String, max_bytes: usize) -> (String, bool) {
+    if text.len() <= max_bytes {
+        return (text, false);
+    }
+    let mut cut = max_bytes;
+    while !text.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    text.truncate(cut);
+    (text, true)
+}
+
+/// GitHub App client authenticating as an installation
+///
+/// Signs an app JWT with the app's private key, exchanges it for
+/// installation tokens, and caches each token until shortly before it
+/// expires.
+#[cfg(feature = "github")]
+pub struct GitHubAppClient {
+    client: reqwest::Client,
+    app_id: String,
+    key: jsonwebtoken::EncodingKey,
+    tokens: dashmap::DashMap<u64, (String, std::time::Instant)>,
+}
+
+#[cfg(feature = "github")]
+impl GitHubAppClient {
+    const API_URL: &'static str = "https://api.github.com";
+
+    /// Create a client from the app ID and its PEM private key
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Configuration`] if the key is not a valid RSA PEM key.
+    pub fn new(app_id: impl Into<String>, private_key_pem: &[u8]) -> Result<Self> {
+        let key = jsonwebtoken::EncodingKey::from_rsa_pem(private_key_pem)
+            .map_err(|e| Error::Configuration(format!("Invalid GitHub App key: {e}")))?;
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("universal-bot/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(|e| Error::Initialization(format!("HTTP client setup failed: {e}")))?;
+        Ok(Self {
+            client,
+            app_id: app_id.into(),
+            key,
+            tokens: dashmap::DashMap::new(),
+        })
+    }
+
+    async fn token(&self, installation_id: u64) -> Result<String> {
+        #[derive(Serialize)]
+        struct Claims<'a> {
+            iat: i64,
+            exp: i64,
+            iss: &'a str,
+        }
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            token: String,
+        }
+
+        if let Some(entry) = self.tokens.get(&installation_id) {
+            if std::time::Instant::now() < entry.1 {
+                return Ok(entry.0.clone());
+            }
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let claims = Claims {
+            iat: now - 60,
+            exp: now + 540,
+            iss: &self.app_id,
+        };
+        let jwt = jsonwebtoken::encode(
+            &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256),
+            &claims,
+            &self.key,
+        )
+        .map_err(|e| Error::Authentication(format!("Failed to sign GitHub App JWT: {e}")))?;
+
+        let response: TokenResponse = self
+            .request(
+                self.client.post(format!(
+                    "{}/app/installations/{installation_id}/access_tokens",
+                    Self::API_URL
+                )),
+                &jwt,
+            )
+            .await?
+            .json()
+            .await
+            .map_err(|e| Error::Authentication(format!("Invalid installation token: {e}")))?;
+
+        // Installation tokens last an hour
+        let expires = std::time::Instant::now() + std::time::Duration::from_secs(50 * 60);
+        self.tokens
+            .insert(installation_id, (response.token.clone(), expires));
+        Ok(response.token)
+    }
+
+    async fn request(
+        &self,
+        request: reqwest::RequestBuilder,
+        token: &str,
+    ) -> Result<reqwest::Response> {
+        Ok(request
+            .bearer_auth(token)
+            .header("X-GitHub-Api-Version", "2022-11-28")
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| Error::Network(format!("GitHub API request failed: {e}")))?)
+    }
+
+    async fn text(&self, installation_id: u64, url: String, accept: &str) -> Result<String> {
+        let token = self.token(installation_id).await?;
+        Ok(self
+            .request(self.client.get(url).header("Accept", accept), &token)
+            .await?
+            .text()
+            .await
+            .map_err(|e| Error::Network(format!("GitHub API request failed: {e}")))?)
+    }
+}
+
+#[cfg(feature = "github")]
+#[async_trait]
+impl GitHubApi for GitHubAppClient {
+    async fn create_comment(
+        &self,
+        installation_id: u64,
+        repository: &str,
+        number: u64,
+        body: &str,
+    ) -> Result<u64> {
+        #[derive(Deserialize)]
+        struct Created {
+            id: u64,
+        }
+
+        let token = self.token(installation_id).await?;
+        let url = format!(
+            "{}/repos/{repository}/issues/{number}/comments",
+            Self::API_URL
+        );
+        let created: Created = self
+            .request(
+                self.client
+                    .post(url)
+                    .header("Accept", "application/vnd.github+json")
+                    .json(&serde_json::json!({ "body": body })),
+                &token,
+            )
+            .await?
+            .json()
+            .await
+            .map_err(|e| Error::Network(format!("Invalid comment response: {e}")))?;
+        Ok(created.id)
+    }
+
+    async fn pull_request_diff(
+        &self,
+        installation_id: u64,
+        repository: &str,
+        number: u64,
+    ) -> Result<String> {
+        let url = format!("{}/repos/{repository}/pulls/{number}", Self::API_URL);
+        self.text(installation_id, url, "application/vnd.github.diff")
+            .await
+    }
+
+    async fn file_contents(
+        &self,
+        installation_id: u64,
+        repository: &str,
+        path: &str,
+        git_ref: Option<&str>,
+    ) -> Result<String> {
+        let mut url = format!(
+            "{}/repos/{repository}/contents/{}",
+            Self::API_URL,
+            path.trim_start_matches('/')
+        );
+        if let Some(git_ref) = git_ref {
+            let _ = write!(url, "?ref={git_ref}");
+        }
+        self.text(installation_id, url, "application/vnd.github.raw")
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BotConfig;
+    use crate::message::Embed;
+    use parking_lot::Mutex;
+
+    #[derive(Default)]
+    struct RecordingApi {
+        comments: Mutex<Vec<(String, u64, String)>>,
+    }
+
+    #[async_trait]
+    impl GitHubApi for RecordingApi {
+        async fn create_comment(
+            &self,
+            _installation_id: u64,
+            repository: &str,
+            number: u64,
+            body: &str,
+        ) -> Result<u64> {
+            self.comments
+                .lock()
+                .push((repository.to_string(), number, body.to_string()));
+            Ok(99)
+        }
+
+        async fn pull_request_diff(&self, _: u64, _: &str, _: u64) -> Result<String> {
+            Ok(format!(
+                "diff --git a/x b/x\n{}",
+                "+é".repeat(MAX_TOOL_OUTPUT_BYTES)
+            ))
+        }
+
+        async fn file_contents(
+            &self,
+            _: u64,
+            _: &str,
+            path: &str,
+            git_ref: Option<&str>,
+        ) -> Result<String> {
+            Ok(format!("{path}@{}", git_ref.unwrap_or("HEAD")))
+        }
+    }
+
+    fn comment_payload(body: &str, user_type: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "action": "created",
+            "repository": { "full_name": "paiml/universal-bot" },
+            "installation": { "id": 42 },
+            "issue": { "number": 17, "title": "Flaky test", "pull_request": { "url": "..." } },
+            "comment": { "id": 5, "body": body, "user": { "login": "ana", "type": user_type } },
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_comment_is_answered_on_the_issue() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        let api = Arc::new(RecordingApi::default());
+        let adapter = GitHubAdapter::new(bot, api.clone(), "helper");
+
+        let payload = comment_payload("@Helper why does this fail?", "User");
+        let event = GitHubEvent::parse("issue_comment", &payload)
+            .unwrap()
+            .unwrap();
+        let message = adapter.to_message(&event).unwrap();
+        assert_eq!(message.content, "why does this fail?");
+        assert_eq!(message.conversation_id, "github:paiml/universal-bot#17");
+        assert_eq!(message.metadata[INSTALLATION_METADATA_KEY], 42);
+        assert_eq!(message.metadata[PULL_REQUEST_METADATA_KEY], true);
+
+        assert_eq!(
+            adapter.handle("issue_comment", &payload).await.unwrap(),
+            Some(99)
+        );
+        let comments = api.comments.lock().clone();
+        assert_eq!(comments[0].0, "paiml/universal-bot");
+        assert_eq!(comments[0].1, 17);
+
+        let unaddressed = comment_payload("looks good", "User");
+        assert_eq!(
+            adapter.handle("issue_comment", &unaddressed).await.unwrap(),
+            None
+        );
+        let from_bot = comment_payload("@helper ping", "Bot");
+        assert_eq!(
+            adapter.handle("issue_comment", &from_bot).await.unwrap(),
+            None
+        );
+        assert_eq!(adapter.handle("push", b"{}").await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_review_event() {
+        let payload = serde_json::to_vec(&serde_json::json!({
+            "action": "submitted",
+            "repository": { "full_name": "o/r" },
+            "pull_request": { "number": 3 },
+            "review": { "id": 1, "body": null, "user": { "login": "ana", "type": "User" } },
+        }))
+        .unwrap();
+        let event = GitHubEvent::parse("pull_request_review", &payload)
+            .unwrap()
+            .unwrap();
+        assert!(matches!(event, GitHubEvent::PullRequestReview { .. }));
+        assert!(GitHubEvent::parse("pull_request_review", b"not json").is_err());
+    }
+
+    #[test]
+    fn test_format_comment_renders_embed() {
+        let response = Response::text("c", "Two checks failed.").with_embed(
+            Embed::new("CI summary")
+                .with_field("lint", "ok")
+                .with_field("test | unit", "failed")
+                .with_url("https://ci/1"),
+        );
+        let body = format_comment(&response);
+        assert!(body.starts_with("Two checks failed.\n\n### CI summary\n"));
+        assert!(body.contains("| **test \\| unit** | failed |"));
+        assert!(body.ends_with("[CI summary](https://ci/1)\n"));
+    }
+
+    #[tokio::test]
+    async fn test_tools_plugin() {
+        let plugin = GitHubToolsPlugin::new(Arc::new(RecordingApi::default()));
+        let invoke = |data| PluginRequest {
+            id: "t".to_string(),
+            request_type: RequestType::InvokeTool,
+            data,
+            metadata: std::collections::HashMap::new(),
+        };
+
+        let diff = plugin
+            .process(invoke(serde_json::json!({
+                "tool": "get_diff", "installation_id": 1, "repository": "o/r", "number": 3,
+            })))
+            .await
+            .unwrap();
+        assert!(diff.success);
+        assert_eq!(diff.data["truncated"], true);
+        assert!(diff.data["content"].as_str().unwrap().len() <= MAX_TOOL_OUTPUT_BYTES);
+
+        let file = plugin
+            .process(invoke(serde_json::json!({
+                "tool": "get_file", "installation_id": 1, "repository": "o/r",
+                "path": "src/lib.rs", "ref": "main",
+            })))
+            .await
+            .unwrap();
+        assert_eq!(file.data["content"], "src/lib.rs@main");
+
+        let unknown = plugin
+            .process(invoke(serde_json::json!({ "tool": "delete_repo" })))
+            .await
+            .unwrap();
+        assert!(!unknown.success);
+    }
+}
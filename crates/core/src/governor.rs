@@ -0,0 +1,272 @@
+//! Per-conversation, per-user, and global spend limits
+//!
+//! The [`CostGovernor`] adds up the estimated cost of every response by
+//! conversation, by user, and overall. Before a message is dispatched to the
+//! provider, [`check`](CostGovernor::check) rejects it with
+//! [`Error::BudgetExceeded`] if any of its budgets is spent. Each time spend
+//! crosses one of [`CostGovernorConfig::alert_thresholds`], e.g. 80% and
+//! 100% of a limit, a [`SpendAlert`] is logged and passed to every
+//! registered [`SpendAlertHook`].
+
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{config::CostGovernorConfig, error::Error};
+
+/// What a budget applies to
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum BudgetScope {
+    /// One conversation
+    Conversation(String),
+    /// One user, across their conversations
+    User(String),
+    /// Every message the bot handles
+    Global,
+}
+
+impl fmt::Display for BudgetScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Conversation(id) => write!(f, "conversation {id}"),
+            Self::User(id) => write!(f, "user {id}"),
+            Self::Global => f.write_str("global budget"),
+        }
+    }
+}
+
+/// Spend crossing an alert threshold of a budget
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpendAlert {
+    /// Budget that crossed the threshold
+    pub scope: BudgetScope,
+    /// Share of the limit crossed, e.g. 0.8
+    pub threshold: f64,
+    /// Estimated spend so far, in USD
+    pub spent_usd: f64,
+    /// The budget's limit, in USD
+    pub limit_usd: f64,
+    /// When the threshold was crossed
+    pub at: DateTime<Utc>,
+}
+
+/// Receives spend alerts as thresholds are crossed
+#[async_trait]
+pub trait SpendAlertHook: Send + Sync {
+    /// Handle an alert
+    async fn on_alert(&self, alert: &SpendAlert) -> Result<()>;
+}
+
+/// Enforces [`CostGovernorConfig`] spend limits
+pub struct CostGovernor {
+    config: CostGovernorConfig,
+    spend: DashMap<BudgetScope, f64>,
+    hooks: RwLock<Vec<Arc<dyn SpendAlertHook>>>,
+}
+
+impl fmt::Debug for CostGovernor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CostGovernor")
+            .field("config", &self.config)
+            .field("hooks", &self.hooks.read().len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl CostGovernor {
+    /// Create a governor for the configured limits
+    #[must_use]
+    pub fn new(config: CostGovernorConfig) -> Self {
+        Self {
+            config,
+            spend: DashMap::new(),
+            hooks: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Whether any limit is set
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.config.is_enabled()
+    }
+
+    /// Pass alerts to `hook` as well
+    pub fn add_hook(&self, hook: Arc<dyn SpendAlertHook>) {
+        self.hooks.write().push(hook);
+    }
+
+    /// The limit of `scope`, in USD, if it has one
+    #[must_use]
+    pub const fn limit(&self, scope: &BudgetScope) -> Option<f64> {
+        match scope {
+            BudgetScope::Conversation(_) => self.config.per_conversation_usd,
+            BudgetScope::User(_) => self.config.per_user_usd,
+            BudgetScope::Global => self.config.global_usd,
+        }
+    }
+
+    /// Estimated spend counted against `scope`, in USD
+    #[must_use]
+    pub fn spent(&self, scope: &BudgetScope) -> f64 {
+        self.spend.get(scope).map_or(0.0, |spent| *spent)
+    }
+
+    /// Start counting `scope` from zero again, e.g. at the start of a billing period
+    pub fn reset(&self, scope: &BudgetScope) {
+        self.spend.remove(scope);
+    }
+
+    /// Check that a message in `conversation_id` from `user_id` may be dispatched
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BudgetExceeded`] for the first of the conversation,
+    /// user, and global budgets that is spent.
+    pub fn check(&self, conversation_id: &str, user_id: &str) -> Result<()> {
+        for scope in scopes(conversation_id, user_id) {
+            let Some(limit) = self.limit(&scope) else {
+                continue;
+            };
+            let spent = self.spent(&scope);
+            if spent >= limit {
+                return Err(Error::BudgetExceeded {
+                    scope: scope.to_string(),
+                    spent_usd: spent,
+                    limit_usd: limit,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Count `cost_usd` against the message's budgets, returning the alerts it raised
+    ///
+    /// Alerts are logged and passed to every hook before returning.
+    pub async fn record(
+        &self,
+        conversation_id: &str,
+        user_id: &str,
+        cost_usd: f64,
+    ) -> Vec<SpendAlert> {
+        if !self.is_enabled() || cost_usd <= 0.0 {
+            return Vec::new();
+        }
+
+        let now = Utc::now();
+        let mut alerts = Vec::new();
+        for scope in scopes(conversation_id, user_id) {
+            let Some(limit) = self.limit(&scope) else {
+                continue;
+            };
+            let (before, after) = {
+                let mut spent = self.spend.entry(scope.clone()).or_insert(0.0);
+                let before = *spent;
+                *spent += cost_usd;
+                (before, *spent)
+            };
+            for &threshold in &self.config.alert_thresholds {
+                let at = threshold * limit;
+                if before < at && after >= at {
+                    alerts.push(SpendAlert {
+                        scope: scope.clone(),
+                        threshold,
+                        spent_usd: after,
+                        limit_usd: limit,
+                        at: now,
+                    });
+                }
+            }
+        }
+
+        let hooks = self.hooks.read().clone();
+        for alert in &alerts {
+            warn!(
+                "Spend for {} crossed {:.0}% of ${:.2}",
+                alert.scope,
+                alert.threshold * 100.0,
+                alert.limit_usd
+            );
+            for hook in &hooks {
+                if let Err(e) = hook.on_alert(alert).await {
+                    warn!("Spend alert hook failed for {}: {}", alert.scope, e);
+                }
+            }
+        }
+        alerts
+    }
+}
+
+fn scopes(conversation_id: &str, user_id: &str) -> [BudgetScope; 3] {
+    [
+        BudgetScope::Conversation(conversation_id.to_string()),
+        BudgetScope::User(user_id.to_string()),
+        BudgetScope::Global,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    #[tokio::test]
+    async fn test_alerts_fire_once_per_threshold() {
+        struct Recorder(Mutex<Vec<SpendAlert>>);
+
+        #[async_trait]
+        impl SpendAlertHook for Recorder {
+            async fn on_alert(&self, alert: &SpendAlert) -> Result<()> {
+                self.0.lock().push(alert.clone());
+                Ok(())
+            }
+        }
+
+        let governor = CostGovernor::new(CostGovernorConfig {
+            per_user_usd: Some(1.0),
+            ..CostGovernorConfig::default()
+        });
+        let recorder = Arc::new(Recorder(Mutex::new(Vec::new())));
+        governor.add_hook(recorder.clone());
+
+        assert!(governor.record("c1", "alice", 0.5).await.is_empty());
+        let alerts = governor.record("c2", "alice", 0.35).await;
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].scope, BudgetScope::User("alice".into()));
+        assert!((alerts[0].threshold - 0.8).abs() < f64::EPSILON);
+        assert!(governor.record("c2", "alice", 0.05).await.is_empty());
+        assert_eq!(governor.record("c3", "alice", 0.2).await.len(), 1);
+        assert_eq!(recorder.0.lock().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_check_rejects_spent_budgets() {
+        let governor = CostGovernor::new(CostGovernorConfig {
+            per_conversation_usd: Some(0.1),
+            global_usd: Some(1.0),
+            ..CostGovernorConfig::default()
+        });
+        governor.check("c1", "alice").unwrap();
+
+        governor.record("c1", "alice", 0.1).await;
+        let error = governor.check("c1", "bob").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::BudgetExceeded { scope, .. }) if scope == "conversation c1"
+        ));
+        governor.check("c2", "alice").unwrap();
+
+        governor.record("c2", "bob", 0.95).await;
+        assert!(governor.check("c3", "carol").is_err());
+        governor.reset(&BudgetScope::Global);
+        governor.check("c3", "carol").unwrap();
+    }
+}
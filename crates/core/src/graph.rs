@@ -0,0 +1,762 @@
+//! Knowledge graph memory
+//!
+//! Complements [`memory`](crate::memory), which keeps free-text facts about a
+//! single user, with relations between named entities ("Bo reports to Ana",
+//! "Ana works at Acme") extracted from conversations. Relations are stored
+//! as triples in a pluggable [`GraphBackend`] under a namespace, usually the
+//! tenant, and can be queried by pattern or by the entities a message
+//! mentions, which answers relational questions such as "who reports to
+//! Ana?" that word-overlap recall misses.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+use uuid::Uuid;
+
+use crate::{
+    completion::{parse_json_array, CompletionFn},
+    context::{Context, ContextMessage, MessageRole},
+    degraded::TENANT_METADATA_KEY,
+    memory::{ExtractionMarker, MemoryProvenance},
+    pipeline::{PipelineContext, PipelineStage},
+};
+
+/// Pipeline metadata key holding the related [`Relation`]s
+pub const GRAPH_FACTS_METADATA_KEY: &str = "graph_facts";
+
+/// Pipeline metadata key holding the rendered relations prompt section
+pub const GRAPH_PROMPT_METADATA_KEY: &str = "graph_prompt";
+
+/// Marker tracking how far into a conversation relation extraction has run
+const EXTRACTED_AT: ExtractionMarker = ExtractionMarker("graph_extracted_at");
+
+/// Normalize an entity name for matching
+#[must_use]
+pub fn entity_key(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// A stored relation between two entities
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Relation {
+    /// Unique ID
+    pub id: Uuid,
+    /// Subject entity name
+    pub subject: String,
+    /// Relation in snake case, e.g. `reports_to`
+    pub predicate: String,
+    /// Object entity name
+    pub object: String,
+    /// Extraction confidence (0.0-1.0)
+    pub confidence: f32,
+    /// Where the relation came from
+    pub provenance: MemoryProvenance,
+}
+
+impl Relation {
+    /// Whether this relation states the same triple as another
+    #[must_use]
+    pub fn same_triple(&self, other: &Self) -> bool {
+        self.predicate == other.predicate
+            && entity_key(&self.subject) == entity_key(&other.subject)
+            && entity_key(&self.object) == entity_key(&other.object)
+    }
+
+    /// Render as a sentence, e.g. "Bo reports to Ana"
+    #[must_use]
+    pub fn sentence(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.subject,
+            self.predicate.replace('_', " "),
+            self.object
+        )
+    }
+}
+
+/// A relation proposed by an extractor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RelationCandidate {
+    /// Subject entity name
+    pub subject: String,
+    /// Relation, e.g. `reports_to`
+    pub predicate: String,
+    /// Object entity name
+    pub object: String,
+    /// Extraction confidence
+    #[serde(default = "default_confidence")]
+    pub confidence: f32,
+    /// Message the relation was extracted from
+    #[serde(default)]
+    pub message_id: Option<Uuid>,
+}
+
+const fn default_confidence() -> f32 {
+    0.7
+}
+
+/// Pattern over triples; `None` matches anything
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TriplePattern {
+    /// Subject entity name
+    pub subject: Option<String>,
+    /// Relation
+    pub predicate: Option<String>,
+    /// Object entity name
+    pub object: Option<String>,
+}
+
+impl TriplePattern {
+    /// Relations with the given subject
+    #[must_use]
+    pub fn subject(name: impl Into<String>) -> Self {
+        Self {
+            subject: Some(name.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Relations with the given object
+    #[must_use]
+    pub fn object(name: impl Into<String>) -> Self {
+        Self {
+            object: Some(name.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Restrict to a relation
+    #[must_use]
+    pub fn with_predicate(mut self, predicate: impl Into<String>) -> Self {
+        self.predicate = Some(predicate.into());
+        self
+    }
+
+    /// Whether a relation matches
+    #[must_use]
+    pub fn matches(&self, relation: &Relation) -> bool {
+        self.subject
+            .as_ref()
+            .is_none_or(|s| entity_key(s) == entity_key(&relation.subject))
+            && self
+                .predicate
+                .as_ref()
+                .is_none_or(|p| *p == relation.predicate)
+            && self
+                .object
+                .as_ref()
+                .is_none_or(|o| entity_key(o) == entity_key(&relation.object))
+    }
+}
+
+/// Storage backend for relations
+#[async_trait]
+pub trait GraphBackend: Send + Sync {
+    /// Store a relation, replacing any relation with the same triple
+    async fn put(&self, namespace: &str, relation: Relation) -> Result<()>;
+
+    /// Relations matching a pattern
+    async fn query(&self, namespace: &str, pattern: &TriplePattern) -> Result<Vec<Relation>>;
+
+    /// Names of all entities in a namespace
+    async fn entities(&self, namespace: &str) -> Result<Vec<String>>;
+
+    /// Remove every relation in a namespace
+    async fn clear(&self, namespace: &str) -> Result<()>;
+}
+
+/// In-memory graph backend
+#[derive(Default)]
+pub struct InMemoryGraphBackend {
+    relations: DashMap<String, Vec<Relation>>,
+}
+
+impl InMemoryGraphBackend {
+    /// Create an empty backend
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl GraphBackend for InMemoryGraphBackend {
+    async fn put(&self, namespace: &str, relation: Relation) -> Result<()> {
+        let mut relations = self.relations.entry(namespace.to_string()).or_default();
+        relations.retain(|existing| !existing.same_triple(&relation));
+        relations.push(relation);
+        drop(relations);
+        Ok(())
+    }
+
+    async fn query(&self, namespace: &str, pattern: &TriplePattern) -> Result<Vec<Relation>> {
+        Ok(self
+            .relations
+            .get(namespace)
+            .map(|relations| {
+                relations
+                    .iter()
+                    .filter(|r| pattern.matches(r))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn entities(&self, namespace: &str) -> Result<Vec<String>> {
+        let mut seen = HashSet::new();
+        Ok(self
+            .relations
+            .get(namespace)
+            .map(|relations| {
+                relations
+                    .iter()
+                    .flat_map(|r| [r.subject.clone(), r.object.clone()])
+                    .filter(|name| seen.insert(entity_key(name)))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn clear(&self, namespace: &str) -> Result<()> {
+        self.relations.remove(namespace);
+        Ok(())
+    }
+}
+
+/// Extracts relations from conversation messages
+#[async_trait]
+pub trait RelationExtractor: Send + Sync {
+    /// Extractor name, recorded in provenance
+    fn name(&self) -> &str;
+
+    /// Extract candidate relations from user messages
+    async fn extract(&self, messages: &[ContextMessage]) -> Result<Vec<RelationCandidate>>;
+}
+
+/// Pattern-based extractor for common organizational phrasing
+///
+/// Matches sentences such as "Bo Chen reports to Ana" where both sides are
+/// capitalized names. "I" is recorded as the entity `User`.
+pub struct RuleBasedRelationExtractor;
+
+impl RuleBasedRelationExtractor {
+    const RULES: &'static [(&'static str, &'static str)] = &[
+        (" reports to ", "reports_to"),
+        (" report to ", "reports_to"),
+        (" works at ", "works_at"),
+        (" work at ", "works_at"),
+        (" works for ", "works_at"),
+        (" work for ", "works_at"),
+        (" manages ", "manages"),
+        (" manage ", "manages"),
+        (" leads ", "leads"),
+        (" lead ", "leads"),
+        (" is married to ", "married_to"),
+        (" lives in ", "lives_in"),
+        (" is part of ", "part_of"),
+    ];
+
+    fn is_name_word(word: &str) -> bool {
+        word.starts_with(|c: char| c.is_uppercase())
+    }
+
+    fn name_before(text: &str) -> Option<String> {
+        let mut words: Vec<&str> = text
+            .split_whitespace()
+            .rev()
+            .take_while(|w| Self::is_name_word(w))
+            .collect();
+        words.reverse();
+        match words.as_slice() {
+            [] => None,
+            ["I"] => Some("User".to_string()),
+            // "Today Bo reports to..." has no separator; keep the nearest name
+            _ => Some(words.join(" ")),
+        }
+    }
+
+    fn name_after(text: &str) -> Option<String> {
+        let mut words = Vec::new();
+        for word in text.split_whitespace() {
+            let name = word.trim_end_matches(|c: char| !c.is_alphanumeric());
+            if !Self::is_name_word(name) {
+                break;
+            }
+            words.push(name);
+            // "Acme, Bo and Cy" ends the name at the comma
+            if name.len() < word.len() {
+                break;
+            }
+        }
+        (!words.is_empty()).then(|| words.join(" "))
+    }
+
+    fn extract_from(message: &ContextMessage) -> Vec<RelationCandidate> {
+        let mut candidates = Vec::new();
+        for sentence in message.content.split(['.', '!', '?', ';', '\n']) {
+            let padded = format!(" {sentence} ");
+            for (pattern, predicate) in Self::RULES {
+                let Some(at) = padded.find(pattern) else {
+                    continue;
+                };
+                let (Some(subject), Some(object)) = (
+                    Self::name_before(&padded[..at]),
+                    Self::name_after(&padded[at + pattern.len()..]),
+                ) else {
+                    continue;
+                };
+                candidates.push(RelationCandidate {
+                    subject,
+                    predicate: (*predicate).to_string(),
+                    object,
+                    confidence: default_confidence(),
+                    message_id: message.message_id,
+                });
+            }
+        }
+        candidates
+    }
+}
+
+#[async_trait]
+impl RelationExtractor for RuleBasedRelationExtractor {
+    fn name(&self) -> &str {
+        "rules"
+    }
+
+    async fn extract(&self, messages: &[ContextMessage]) -> Result<Vec<RelationCandidate>> {
+        Ok(messages
+            .iter()
+            .filter(|m| m.role == MessageRole::User)
+            .flat_map(Self::extract_from)
+            .collect())
+    }
+}
+
+/// Model-backed extractor that sends an extraction prompt to a completion function
+pub struct PromptRelationExtractor {
+    complete: CompletionFn,
+}
+
+impl PromptRelationExtractor {
+    /// Create an extractor around a completion function
+    #[must_use]
+    pub fn new(complete: CompletionFn) -> Self {
+        Self { complete }
+    }
+
+    /// Build the extraction prompt for a set of messages
+    #[must_use]
+    pub fn extraction_prompt(messages: &[ContextMessage]) -> String {
+        let transcript = messages
+            .iter()
+            .filter(|m| m.role == MessageRole::User)
+            .map(|m| format!("- {}", m.content))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        format!(
+            "Extract relationships between named people, teams, organizations, and places \
+             from the messages below. Use \"User\" for the person writing.\n\
+             Respond with a JSON array of objects with fields \"subject\", \
+             \"predicate\" (snake_case, e.g. \"reports_to\", \"works_at\", \"manages\"), \
+             \"object\", and \"confidence\" (0.0-1.0). \
+             Respond with [] if there are none.\n\nMessages:\n{transcript}"
+        )
+    }
+
+    /// Parse the model's extraction response
+    ///
+    /// Tolerates surrounding prose or code fences around the JSON array.
+    #[must_use]
+    pub fn parse_response(text: &str) -> Vec<RelationCandidate> {
+        parse_json_array(text, "relation extraction")
+    }
+}
+
+#[async_trait]
+impl RelationExtractor for PromptRelationExtractor {
+    fn name(&self) -> &str {
+        "prompt"
+    }
+
+    async fn extract(&self, messages: &[ContextMessage]) -> Result<Vec<RelationCandidate>> {
+        let response = (self.complete)(Self::extraction_prompt(messages)).await?;
+        Ok(Self::parse_response(&response))
+    }
+}
+
+/// Configuration for graph memory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphMemoryConfig {
+    /// Run extraction after this many new messages in a conversation
+    pub extraction_interval: usize,
+    /// Maximum relations injected into a conversation
+    pub max_facts: usize,
+    /// Discard candidates below this confidence
+    pub min_confidence: f32,
+}
+
+impl Default for GraphMemoryConfig {
+    fn default() -> Self {
+        Self {
+            extraction_interval: 6,
+            max_facts: 10,
+            min_confidence: 0.5,
+        }
+    }
+}
+
+/// Coordinates relation extraction and graph queries
+pub struct GraphMemory {
+    backend: Arc<dyn GraphBackend>,
+    extractor: Arc<dyn RelationExtractor>,
+    config: GraphMemoryConfig,
+}
+
+impl GraphMemory {
+    /// Create a new graph memory
+    #[must_use]
+    pub fn new(
+        backend: Arc<dyn GraphBackend>,
+        extractor: Arc<dyn RelationExtractor>,
+        config: GraphMemoryConfig,
+    ) -> Self {
+        Self {
+            backend,
+            extractor,
+            config,
+        }
+    }
+
+    /// Create a graph memory with an in-memory backend and the rule-based extractor
+    #[must_use]
+    pub fn in_memory() -> Self {
+        Self::new(
+            Arc::new(InMemoryGraphBackend::new()),
+            Arc::new(RuleBasedRelationExtractor),
+            GraphMemoryConfig::default(),
+        )
+    }
+
+    /// Get the underlying backend
+    #[must_use]
+    pub fn backend(&self) -> &Arc<dyn GraphBackend> {
+        &self.backend
+    }
+
+    /// Extract relations from a conversation if enough new messages have arrived
+    ///
+    /// Returns the number of relations stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if extraction or storage fails.
+    #[instrument(skip(self, context), fields(context_id = %context.id))]
+    pub async fn observe(&self, namespace: &str, context: &mut Context) -> Result<usize> {
+        let Some(recent) = EXTRACTED_AT.pending(context, self.config.extraction_interval) else {
+            return Ok(0);
+        };
+        let stored = self
+            .extract_and_store(namespace, &context.id, &recent)
+            .await?;
+
+        EXTRACTED_AT.advance(context);
+        Ok(stored)
+    }
+
+    /// Run extraction over the given messages and store the results
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if extraction or storage fails.
+    pub async fn extract_and_store(
+        &self,
+        namespace: &str,
+        conversation_id: &str,
+        messages: &[ContextMessage],
+    ) -> Result<usize> {
+        let candidates = self.extractor.extract(messages).await?;
+        let now = Utc::now();
+        let mut stored = 0;
+
+        for candidate in candidates {
+            if candidate.confidence < self.config.min_confidence
+                || candidate.subject.trim().is_empty()
+                || candidate.object.trim().is_empty()
+                || candidate.predicate.trim().is_empty()
+            {
+                continue;
+            }
+            self.backend
+                .put(
+                    namespace,
+                    Relation {
+                        id: Uuid::new_v4(),
+                        subject: candidate.subject.trim().to_string(),
+                        predicate: candidate.predicate.trim().to_lowercase().replace(' ', "_"),
+                        object: candidate.object.trim().to_string(),
+                        confidence: candidate.confidence,
+                        provenance: MemoryProvenance {
+                            conversation_id: conversation_id.to_string(),
+                            message_id: candidate.message_id,
+                            extractor: self.extractor.name().to_string(),
+                            extracted_at: now,
+                        },
+                    },
+                )
+                .await?;
+            stored += 1;
+        }
+
+        debug!("Stored {} relations in {}", stored, namespace);
+        Ok(stored)
+    }
+
+    /// Relations matching a pattern
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backend cannot be read.
+    pub async fn query(&self, namespace: &str, pattern: &TriplePattern) -> Result<Vec<Relation>> {
+        self.backend.query(namespace, pattern).await
+    }
+
+    /// Known entities mentioned in a text
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backend cannot be read.
+    pub async fn mentioned_entities(&self, namespace: &str, text: &str) -> Result<Vec<String>> {
+        let text = format!(" {} ", entity_key(text));
+        Ok(self
+            .backend
+            .entities(namespace)
+            .await?
+            .into_iter()
+            .filter(|name| {
+                let key = entity_key(name);
+                text.match_indices(&key).any(|(at, _)| {
+                    let before = text[..at].chars().next_back();
+                    let after = text[at + key.len()..].chars().next();
+                    !before.is_some_and(char::is_alphanumeric)
+                        && !after.is_some_and(char::is_alphanumeric)
+                })
+            })
+            .collect())
+    }
+
+    /// Relations touching the entities mentioned in a text, most confident first
+    ///
+    /// Includes relations where a mentioned entity is the subject or the
+    /// object, so "who reports to Ana?" finds "Bo reports to Ana".
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backend cannot be read.
+    pub async fn related(&self, namespace: &str, text: &str) -> Result<Vec<Relation>> {
+        let mut related: Vec<Relation> = Vec::new();
+        for entity in self.mentioned_entities(namespace, text).await? {
+            for pattern in [
+                TriplePattern::subject(entity.clone()),
+                TriplePattern::object(entity),
+            ] {
+                for relation in self.backend.query(namespace, &pattern).await? {
+                    if !related.iter().any(|r| r.id == relation.id) {
+                        related.push(relation);
+                    }
+                }
+            }
+        }
+        related.sort_by(|a, b| {
+            b.confidence
+                .total_cmp(&a.confidence)
+                .then_with(|| b.provenance.extracted_at.cmp(&a.provenance.extracted_at))
+        });
+        related.truncate(self.config.max_facts);
+        Ok(related)
+    }
+
+    /// Render relations as a system prompt section
+    #[must_use]
+    pub fn render(relations: &[Relation]) -> Option<String> {
+        if relations.is_empty() {
+            return None;
+        }
+        let lines = relations
+            .iter()
+            .map(|r| format!("- {}", r.sentence()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Some(format!("Known relationships:\n{lines}"))
+    }
+}
+
+/// Pipeline stage that injects related relations and schedules extraction
+///
+/// The namespace is the message's tenant (the `tenant_id` metadata) when
+/// set, otherwise its user. Adds the related relations under
+/// [`GRAPH_FACTS_METADATA_KEY`] and the rendered prompt section under
+/// [`GRAPH_PROMPT_METADATA_KEY`].
+pub struct GraphMemoryStage {
+    graph: Arc<GraphMemory>,
+}
+
+impl GraphMemoryStage {
+    /// Create a new graph memory stage
+    #[must_use]
+    pub fn new(graph: Arc<GraphMemory>) -> Self {
+        Self { graph }
+    }
+}
+
+#[async_trait]
+impl PipelineStage for GraphMemoryStage {
+    fn name(&self) -> &str {
+        "graph_memory"
+    }
+
+    async fn process(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
+        let namespace = ctx
+            .message
+            .metadata
+            .get(TENANT_METADATA_KEY)
+            .and_then(|v| v.as_str())
+            .map_or_else(|| ctx.message.user_id.clone(), str::to_string);
+        let related = self.graph.related(&namespace, &ctx.message.content).await?;
+
+        if let Some(prompt) = GraphMemory::render(&related) {
+            ctx.metadata.insert(
+                GRAPH_PROMPT_METADATA_KEY.to_string(),
+                serde_json::json!(prompt),
+            );
+        }
+        ctx.metadata.insert(
+            GRAPH_FACTS_METADATA_KEY.to_string(),
+            serde_json::to_value(&related)?,
+        );
+
+        let mut snapshot = ctx.context.read().clone();
+        self.graph.observe(&namespace, &mut snapshot).await?;
+        EXTRACTED_AT.copy_back(&snapshot, &ctx);
+
+        Ok(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+
+    fn user_message(content: &str) -> ContextMessage {
+        ContextMessage::from_message(&Message::text(content))
+    }
+
+    #[tokio::test]
+    async fn test_rule_based_extraction() {
+        let candidates = RuleBasedRelationExtractor
+            .extract(&[user_message(
+                "Bo Chen reports to Ana. I work at Acme Corp, and the team lives in Lisbon.",
+            )])
+            .await
+            .unwrap();
+        let triples: Vec<_> = candidates
+            .iter()
+            .map(|c| (c.subject.as_str(), c.predicate.as_str(), c.object.as_str()))
+            .collect();
+        assert_eq!(
+            triples,
+            [
+                ("Bo Chen", "reports_to", "Ana"),
+                ("User", "works_at", "Acme Corp")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_related_answers_reverse_queries() {
+        let graph = GraphMemory::in_memory();
+        graph
+            .extract_and_store(
+                "acme",
+                "c1",
+                &[user_message(
+                    "Bo reports to Ana. Cy reports to Ana. Ana reports to Dee. Ed works at Initech.",
+                )],
+            )
+            .await
+            .unwrap();
+
+        let related = graph.related("acme", "Who reports to ana?").await.unwrap();
+        let mut sentences: Vec<_> = related.iter().map(Relation::sentence).collect();
+        sentences.sort();
+        assert_eq!(
+            sentences,
+            [
+                "Ana reports to Dee",
+                "Bo reports to Ana",
+                "Cy reports to Ana"
+            ]
+        );
+
+        let reports = graph
+            .query(
+                "acme",
+                &TriplePattern::object("Ana").with_predicate("reports_to"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(reports.len(), 2);
+        assert!(graph.related("other", "ana").await.unwrap().is_empty());
+        assert!(graph.related("acme", "banana").await.unwrap().is_empty());
+
+        let prompt = GraphMemory::render(&related).unwrap();
+        assert!(prompt.starts_with("Known relationships:\n- "));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_triples_replace() {
+        let graph = GraphMemory::in_memory();
+        for conversation in ["c1", "c2"] {
+            graph
+                .extract_and_store("acme", conversation, &[user_message("Bo reports to Ana")])
+                .await
+                .unwrap();
+        }
+        let relations = graph
+            .query("acme", &TriplePattern::default())
+            .await
+            .unwrap();
+        assert_eq!(relations.len(), 1);
+        assert_eq!(relations[0].provenance.conversation_id, "c2");
+    }
+
+    #[tokio::test]
+    async fn test_prompt_extractor_parses_response() {
+        let extractor = PromptRelationExtractor::new(Arc::new(|prompt: String| {
+            Box::pin(async move {
+                assert!(prompt.contains("Dee runs the platform team"));
+                Ok("```json\n[{\"subject\":\"Dee\",\"predicate\":\"leads\",\"object\":\"Platform\",\"confidence\":0.9}]\n```".to_string())
+            })
+        }));
+
+        let candidates = extractor
+            .extract(&[user_message("Dee runs the platform team")])
+            .await
+            .unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].object, "Platform");
+        assert!(PromptRelationExtractor::parse_response("none").is_empty());
+    }
+}
@@ -0,0 +1,379 @@
+//! Signed inbound webhooks with replay protection
+//!
+//! Channel adapters that accept webhook calls must not let a spoofed or
+//! replayed request inject messages into a conversation. Senders sign each
+//! request with a secret shared per channel, putting an
+//! [`INBOUND_SIGNATURE_HEADER`] of the form
+//! `t=<unix seconds>,n=<nonce>,v1=<hex HMAC-SHA256>` on it, computed over
+//! `"{t}.{n}.{body}"` (see [`sign_inbound`]).
+//!
+//! The [`InboundVerifier`] rejects requests with a missing or wrong
+//! signature, a timestamp outside the tolerance, or a nonce it has already
+//! seen within the tolerance window. Every decision is kept in a bounded
+//! audit log for review.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dashmap::{mapref::entry::Entry, DashMap};
+use hmac::{Hmac, Mac};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::warn;
+
+use crate::{error::Error, webhook::decode_hex};
+
+/// Header carrying an inbound request's signature
+pub const INBOUND_SIGNATURE_HEADER: &str = "X-UniversalBot-Inbound-Signature";
+
+/// Default allowed clock skew between sender and bot
+pub const DEFAULT_SIGNATURE_TOLERANCE: Duration = Duration::from_secs(300);
+
+/// Most decisions kept in the audit log
+pub const MAX_AUDIT_ENTRIES: usize = 1024;
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn mac(secret: &str, timestamp: i64, nonce: &str, body: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .unwrap_or_else(|_| unreachable!("HMAC accepts keys of any length"));
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(nonce.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+/// Compute the [`INBOUND_SIGNATURE_HEADER`] value for a request
+///
+/// `nonce` must be unique per request, e.g. a UUID; it may not contain
+/// commas or dots.
+#[must_use]
+pub fn sign_inbound(secret: &str, timestamp: i64, nonce: &str, body: &[u8]) -> String {
+    let digest = mac(secret, timestamp, nonce, body).finalize().into_bytes();
+    let hex = digest.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{b:02x}");
+        hex
+    });
+    format!("t={timestamp},n={nonce},v1={hex}")
+}
+
+/// Why an inbound request was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InboundRejection {
+    /// No secret is configured for the channel
+    UnknownChannel,
+    /// The signature header is missing or malformed
+    Malformed,
+    /// The signature does not match the body
+    BadSignature,
+    /// The timestamp is outside the tolerance
+    Stale,
+    /// The nonce was already used
+    Replayed,
+}
+
+impl InboundRejection {
+    /// Human-readable reason
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::UnknownChannel => "no secret is configured for the channel",
+            Self::Malformed => "the signature header is missing or malformed",
+            Self::BadSignature => "the signature does not match",
+            Self::Stale => "the timestamp is outside the allowed skew",
+            Self::Replayed => "the nonce was already used",
+        }
+    }
+}
+
+/// One verification decision
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InboundAuditEntry {
+    /// When the request was checked
+    pub at: DateTime<Utc>,
+    /// Channel the request claimed to come from
+    pub channel: String,
+    /// Nonce of the request, if the header carried one
+    pub nonce: Option<String>,
+    /// Why the request was rejected; `None` if it was accepted
+    pub rejection: Option<InboundRejection>,
+}
+
+/// Counts of verification decisions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InboundStats {
+    /// Requests accepted
+    pub accepted: u64,
+    /// Requests rejected
+    pub rejected: u64,
+}
+
+/// Verifies inbound webhook signatures and rejects replays
+pub struct InboundVerifier {
+    secrets: HashMap<String, String>,
+    tolerance: Duration,
+    /// Seen nonces by channel and nonce, with the unix time they may be forgotten
+    nonces: DashMap<(String, String), i64>,
+    audit: Mutex<VecDeque<InboundAuditEntry>>,
+    stats: Mutex<InboundStats>,
+}
+
+impl std::fmt::Debug for InboundVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InboundVerifier")
+            .field("channels", &self.secrets.keys().collect::<Vec<_>>())
+            .field("tolerance", &self.tolerance)
+            .field("stats", &self.stats())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for InboundVerifier {
+    fn default() -> Self {
+        Self::new(DEFAULT_SIGNATURE_TOLERANCE)
+    }
+}
+
+impl InboundVerifier {
+    /// Create a verifier accepting timestamps up to `tolerance` from now
+    #[must_use]
+    pub fn new(tolerance: Duration) -> Self {
+        Self {
+            secrets: HashMap::new(),
+            tolerance,
+            nonces: DashMap::new(),
+            audit: Mutex::new(VecDeque::new()),
+            stats: Mutex::new(InboundStats::default()),
+        }
+    }
+
+    /// Accept requests from `channel` signed with `secret`
+    #[must_use]
+    pub fn with_secret(mut self, channel: impl Into<String>, secret: impl Into<String>) -> Self {
+        self.secrets.insert(channel.into(), secret.into());
+        self
+    }
+
+    /// Check a request from `channel` with signature `header` and `body`
+    ///
+    /// An accepted nonce is remembered for twice the tolerance, covering
+    /// every timestamp that could still be accepted with it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Authentication`] if the request is rejected.
+    pub fn verify(&self, channel: &str, header: Option<&str>, body: &[u8]) -> Result<()> {
+        let now = Utc::now();
+        let (nonce, outcome) = self.check(channel, header, body, now);
+        self.record(InboundAuditEntry {
+            at: now,
+            channel: channel.to_string(),
+            nonce,
+            rejection: outcome.err(),
+        });
+        outcome.map_err(|rejection| {
+            warn!("Rejected {} webhook: {}", channel, rejection.as_str());
+            Error::Authentication(format!(
+                "Rejected {channel} webhook: {}",
+                rejection.as_str()
+            ))
+            .into()
+        })
+    }
+
+    fn check(
+        &self,
+        channel: &str,
+        header: Option<&str>,
+        body: &[u8],
+        now: DateTime<Utc>,
+    ) -> (Option<String>, std::result::Result<(), InboundRejection>) {
+        let mut timestamp = None;
+        let mut nonce = None;
+        let mut signature = None;
+        for part in header.unwrap_or_default().split(',') {
+            match part.trim().split_once('=') {
+                Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+                // A dot would let the nonce and body shift within the signed string
+                Some(("n", value)) if !value.is_empty() && !value.contains('.') => {
+                    nonce = Some(value.to_string());
+                }
+                Some(("v1", value)) => signature = decode_hex(value),
+                _ => {}
+            }
+        }
+
+        let Some(secret) = self.secrets.get(channel) else {
+            return (nonce, Err(InboundRejection::UnknownChannel));
+        };
+        let (Some(timestamp), Some(nonce_value), Some(signature)) =
+            (timestamp, nonce.as_deref(), signature)
+        else {
+            return (nonce, Err(InboundRejection::Malformed));
+        };
+        if mac(secret, timestamp, nonce_value, body)
+            .verify_slice(&signature)
+            .is_err()
+        {
+            return (nonce, Err(InboundRejection::BadSignature));
+        }
+        if now.timestamp().abs_diff(timestamp) > self.tolerance.as_secs() {
+            return (nonce, Err(InboundRejection::Stale));
+        }
+
+        // Only signed requests reach the cache, so it cannot be flooded
+        let window = i64::try_from(self.tolerance.as_secs()).unwrap_or(i64::MAX / 2);
+        self.nonces
+            .retain(|_, forget_at| *forget_at > now.timestamp());
+        // Check and record the nonce under one shard lock so concurrent replays cannot both pass
+        match self
+            .nonces
+            .entry((channel.to_string(), nonce_value.to_string()))
+        {
+            Entry::Occupied(_) => (nonce, Err(InboundRejection::Replayed)),
+            Entry::Vacant(entry) => {
+                entry.insert(now.timestamp().saturating_add(window.saturating_mul(2)));
+                (nonce, Ok(()))
+            }
+        }
+    }
+
+    fn record(&self, entry: InboundAuditEntry) {
+        {
+            let mut stats = self.stats.lock();
+            if entry.rejection.is_some() {
+                stats.rejected += 1;
+            } else {
+                stats.accepted += 1;
+            }
+        }
+        let mut audit = self.audit.lock();
+        if audit.len() == MAX_AUDIT_ENTRIES {
+            audit.pop_front();
+        }
+        audit.push_back(entry);
+    }
+
+    /// Recent decisions, oldest first
+    #[must_use]
+    pub fn audit_log(&self) -> Vec<InboundAuditEntry> {
+        self.audit.lock().iter().cloned().collect()
+    }
+
+    /// Decisions so far
+    #[must_use]
+    pub fn stats(&self) -> InboundStats {
+        *self.stats.lock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verifier() -> InboundVerifier {
+        InboundVerifier::default().with_secret("whatsapp", "s3cret")
+    }
+
+    fn rejection(verifier: &InboundVerifier) -> Option<InboundRejection> {
+        verifier.audit_log().last().unwrap().rejection
+    }
+
+    #[test]
+    fn test_signed_request_is_accepted_once() {
+        let verifier = verifier();
+        let body = br#"{"text":"hi"}"#;
+        let header = sign_inbound("s3cret", Utc::now().timestamp(), "nonce-1", body);
+
+        verifier.verify("whatsapp", Some(&header), body).unwrap();
+        let error = verifier
+            .verify("whatsapp", Some(&header), body)
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::Authentication(_))
+        ));
+        assert_eq!(rejection(&verifier), Some(InboundRejection::Replayed));
+
+        let fresh = sign_inbound("s3cret", Utc::now().timestamp(), "nonce-2", body);
+        verifier.verify("whatsapp", Some(&fresh), body).unwrap();
+        assert_eq!(
+            verifier.stats(),
+            InboundStats {
+                accepted: 2,
+                rejected: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_spoofed_requests_are_rejected() {
+        let verifier = verifier();
+        let body = b"payload";
+        let now = Utc::now().timestamp();
+
+        let cases = [
+            (
+                "teams",
+                Some(sign_inbound("s3cret", now, "a", body)),
+                InboundRejection::UnknownChannel,
+            ),
+            ("whatsapp", None, InboundRejection::Malformed),
+            (
+                "whatsapp",
+                Some(format!("t={now},v1=00")),
+                InboundRejection::Malformed,
+            ),
+            (
+                "whatsapp",
+                Some(sign_inbound("s3cret", now, "e.f", body)),
+                InboundRejection::Malformed,
+            ),
+            (
+                "whatsapp",
+                Some(sign_inbound("guess", now, "b", body)),
+                InboundRejection::BadSignature,
+            ),
+            (
+                "whatsapp",
+                Some(sign_inbound("s3cret", now - 3600, "c", body)),
+                InboundRejection::Stale,
+            ),
+        ];
+        for (channel, header, expected) in cases {
+            assert!(verifier.verify(channel, header.as_deref(), body).is_err());
+            assert_eq!(rejection(&verifier), Some(expected));
+        }
+
+        // A tampered body fails even with a valid header
+        let header = sign_inbound("s3cret", now, "d", body);
+        assert!(verifier
+            .verify("whatsapp", Some(&header), b"tampered")
+            .is_err());
+        assert_eq!(rejection(&verifier), Some(InboundRejection::BadSignature));
+        assert_eq!(verifier.stats().accepted, 0);
+    }
+
+    #[test]
+    fn test_concurrent_replays_accept_once() {
+        let verifier = verifier();
+        let body = b"payload";
+        let header = sign_inbound("s3cret", Utc::now().timestamp(), "race", body);
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| verifier.verify("whatsapp", Some(&header), body));
+            }
+        });
+        let accepted = verifier.stats().accepted;
+        assert_eq!(accepted, 1);
+        assert_eq!(verifier.stats().rejected, 7);
+    }
+}
@@ -0,0 +1,965 @@
+//! Document ingestion into vector stores
+//!
+//! This module crawls document sources (S3 prefixes, web pages), extracts
+//! readable text, chunks and embeds it, and upserts the chunks into a
+//! [`VectorStore`]. Sync is incremental: documents whose `ETag` or
+//! last-modified timestamp has not changed since the previous run are skipped.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, instrument, warn};
+
+use crate::{
+    error::Error,
+    vector::{Embedder, VectorRecord, VectorStore},
+};
+
+/// A document discovered in a source listing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentRef {
+    /// Stable document URI (`s3://bucket/key` or `https://...`)
+    pub uri: String,
+    /// Entity tag reported by the source
+    pub etag: Option<String>,
+    /// Last modification time reported by the source
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+impl DocumentRef {
+    /// Create a new document reference
+    #[must_use]
+    pub fn new(uri: impl Into<String>) -> Self {
+        Self {
+            uri: uri.into(),
+            etag: None,
+            last_modified: None,
+        }
+    }
+
+    /// Version marker used for change detection
+    ///
+    /// Prefers the `ETag` and falls back to the last-modified timestamp.
+    /// Documents without either are always re-ingested.
+    #[must_use]
+    pub fn version(&self) -> Option<String> {
+        self.etag
+            .clone()
+            .or_else(|| self.last_modified.map(|t| t.to_rfc3339()))
+    }
+}
+
+/// Raw document content fetched from a source
+#[derive(Debug, Clone)]
+pub struct RawDocument {
+    /// Document reference
+    pub reference: DocumentRef,
+    /// MIME type, if known
+    pub content_type: Option<String>,
+    /// Raw bytes
+    pub bytes: Vec<u8>,
+}
+
+/// A source of documents to ingest
+#[async_trait]
+pub trait DocumentSource: Send + Sync {
+    /// Source name used in logs and record metadata
+    fn name(&self) -> &str;
+
+    /// List the documents currently available
+    async fn list(&self) -> Result<Vec<DocumentRef>>;
+
+    /// Fetch a document's content
+    async fn fetch(&self, reference: &DocumentRef) -> Result<RawDocument>;
+}
+
+/// Extracts plain text from raw document bytes
+pub trait TextExtractor: Send + Sync {
+    /// Check whether this extractor handles the document
+    fn supports(&self, document: &RawDocument) -> bool;
+
+    /// Extract text from the document
+    fn extract(&self, document: &RawDocument) -> Result<String>;
+}
+
+/// Plain text and markdown extractor
+pub struct PlainTextExtractor;
+
+impl TextExtractor for PlainTextExtractor {
+    fn supports(&self, document: &RawDocument) -> bool {
+        document
+            .content_type
+            .as_deref()
+            .is_some_and(|ct| ct.starts_with("text/plain") || ct.starts_with("text/markdown"))
+            || has_extension(&document.reference.uri, &["txt", "md", "markdown"])
+    }
+
+    fn extract(&self, document: &RawDocument) -> Result<String> {
+        Ok(String::from_utf8_lossy(&document.bytes).into_owned())
+    }
+}
+
+/// Readability-style HTML extractor
+///
+/// Drops scripts, styles, and page chrome (navigation, headers, footers,
+/// sidebars), prefers `<article>` or `<main>` content when present, and
+/// collapses the remaining markup into paragraphs of text.
+pub struct HtmlExtractor;
+
+impl HtmlExtractor {
+    const SKIPPED_TAGS: &'static [&'static str] = &[
+        "script", "style", "noscript", "nav", "header", "footer", "aside", "form", "svg",
+    ];
+    const BLOCK_TAGS: &'static [&'static str] = &[
+        "p",
+        "div",
+        "br",
+        "li",
+        "tr",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "section",
+        "article",
+        "blockquote",
+        "pre",
+        "table",
+    ];
+
+    /// Extract readable text from an HTML string
+    #[must_use]
+    pub fn extract_html(html: &str) -> String {
+        let body = Self::main_content(html).unwrap_or(html);
+        let mut text = String::with_capacity(body.len() / 2);
+        let mut skip_depth = 0usize;
+        let mut rest = body;
+
+        while let Some(start) = rest.find('<') {
+            if skip_depth == 0 {
+                text.push_str(&rest[..start]);
+            }
+            let Some(end) = rest[start..].find('>') else {
+                break;
+            };
+            let tag = &rest[start + 1..start + end];
+            rest = &rest[start + end + 1..];
+
+            let closing = tag.starts_with('/');
+            let name = tag
+                .trim_start_matches('/')
+                .split(|c: char| c.is_whitespace() || c == '/')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+
+            if Self::SKIPPED_TAGS.contains(&name.as_str()) && !tag.ends_with('/') {
+                if closing {
+                    skip_depth = skip_depth.saturating_sub(1);
+                } else {
+                    skip_depth += 1;
+                }
+            } else if skip_depth == 0 && Self::BLOCK_TAGS.contains(&name.as_str()) {
+                text.push('\n');
+            }
+        }
+        if skip_depth == 0 {
+            text.push_str(rest);
+        }
+
+        decode_entities(&text)
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn main_content(html: &str) -> Option<&str> {
+        let lower = html.to_ascii_lowercase();
+        ["article", "main", "body"].iter().find_map(|tag| {
+            let open = lower.find(&format!("<{tag}"))?;
+            let content_start = open + lower[open..].find('>')? + 1;
+            let close = lower[content_start..]
+                .rfind(&format!("</{tag}"))
+                .map_or(html.len(), |i| content_start + i);
+            Some(&html[content_start..close])
+        })
+    }
+}
+
+impl TextExtractor for HtmlExtractor {
+    fn supports(&self, document: &RawDocument) -> bool {
+        document
+            .content_type
+            .as_deref()
+            .is_some_and(|ct| ct.starts_with("text/html") || ct.starts_with("application/xhtml"))
+            || has_extension(&document.reference.uri, &["html", "htm"])
+    }
+
+    fn extract(&self, document: &RawDocument) -> Result<String> {
+        Ok(Self::extract_html(&String::from_utf8_lossy(
+            &document.bytes,
+        )))
+    }
+}
+
+/// PDF text extractor
+#[cfg(feature = "ingest")]
+pub struct PdfExtractor;
+
+#[cfg(feature = "ingest")]
+impl TextExtractor for PdfExtractor {
+    fn supports(&self, document: &RawDocument) -> bool {
+        document.content_type.as_deref() == Some("application/pdf")
+            || has_extension(&document.reference.uri, &["pdf"])
+    }
+
+    fn extract(&self, document: &RawDocument) -> Result<String> {
+        pdf_extract::extract_text_from_mem(&document.bytes).map_err(|e| {
+            Error::InvalidInput(format!(
+                "Failed to extract PDF text from {}: {e}",
+                document.reference.uri
+            ))
+            .into()
+        })
+    }
+}
+
+fn has_extension(uri: &str, extensions: &[&str]) -> bool {
+    let path = uri.split(['?', '#']).next().unwrap_or(uri);
+    path.rsplit_once('.')
+        .is_some_and(|(_, ext)| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+pub(crate) fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Splits text into overlapping chunks on word boundaries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextChunker {
+    /// Target chunk size in characters
+    pub chunk_size: usize,
+    /// Characters of overlap carried into the next chunk
+    pub overlap: usize,
+}
+
+impl Default for TextChunker {
+    fn default() -> Self {
+        Self {
+            chunk_size: 1000,
+            overlap: 200,
+        }
+    }
+}
+
+impl TextChunker {
+    /// Create a new chunker
+    #[must_use]
+    pub fn new(chunk_size: usize, overlap: usize) -> Self {
+        Self {
+            chunk_size: chunk_size.max(1),
+            overlap: overlap.min(chunk_size / 2),
+        }
+    }
+
+    /// Split text into chunks
+    #[must_use]
+    pub fn chunk(&self, text: &str) -> Vec<String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let mut chunks = Vec::new();
+        let mut start = 0;
+
+        while start < words.len() {
+            let mut end = start;
+            let mut len = 0;
+            while end < words.len() && (end == start || len + words[end].len() < self.chunk_size) {
+                len += words[end].len() + 1;
+                end += 1;
+            }
+            chunks.push(words[start..end].join(" "));
+            if end == words.len() {
+                break;
+            }
+
+            // Step back over trailing words to build the overlap
+            let mut next = end;
+            let mut carried = 0;
+            while next > start + 1 && carried + words[next - 1].len() < self.overlap {
+                carried += words[next - 1].len() + 1;
+                next -= 1;
+            }
+            start = next;
+        }
+
+        chunks
+    }
+}
+
+/// Configuration for an ingestion pipeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestConfig {
+    /// Chunking strategy
+    pub chunker: TextChunker,
+    /// Number of chunks embedded per request
+    pub embed_batch_size: usize,
+    /// Interval between scheduled syncs
+    #[serde(with = "humantime_serde")]
+    pub sync_interval: Duration,
+    /// Remove chunks of documents no longer present in the source
+    pub delete_missing: bool,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            chunker: TextChunker::default(),
+            embed_batch_size: 32,
+            sync_interval: Duration::from_secs(3600),
+            delete_missing: true,
+        }
+    }
+}
+
+/// Outcome of a single sync run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IngestReport {
+    /// Documents listed by the source
+    pub documents_seen: usize,
+    /// Documents (re-)ingested
+    pub documents_updated: usize,
+    /// Documents skipped because they were unchanged
+    pub documents_skipped: usize,
+    /// Documents removed because they disappeared from the source
+    pub documents_removed: usize,
+    /// Chunks upserted into the vector store
+    pub chunks_upserted: usize,
+    /// Per-document failures (URI and error message)
+    pub errors: Vec<(String, String)>,
+}
+
+/// Per-document sync state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SyncEntry {
+    version: Option<String>,
+    chunk_count: usize,
+}
+
+/// Pipeline that syncs a document source into a vector store
+///
+/// Changed documents are re-chunked in full; wrap the embedder in a
+/// [`CachedEmbedder`](crate::embedding::CachedEmbedder) so chunks whose text
+/// did not change are not embedded again.
+pub struct IngestionPipeline {
+    source: Arc<dyn DocumentSource>,
+    embedder: Arc<dyn Embedder>,
+    store: Arc<dyn VectorStore>,
+    extractors: Vec<Box<dyn TextExtractor>>,
+    config: IngestConfig,
+    state: RwLock<HashMap<String, SyncEntry>>,
+}
+
+impl IngestionPipeline {
+    /// Create a new ingestion pipeline with the default extractors
+    #[must_use]
+    pub fn new(
+        source: Arc<dyn DocumentSource>,
+        embedder: Arc<dyn Embedder>,
+        store: Arc<dyn VectorStore>,
+        config: IngestConfig,
+    ) -> Self {
+        #[cfg_attr(not(feature = "ingest"), allow(unused_mut))]
+        let mut extractors: Vec<Box<dyn TextExtractor>> =
+            vec![Box::new(HtmlExtractor), Box::new(PlainTextExtractor)];
+        #[cfg(feature = "ingest")]
+        extractors.push(Box::new(PdfExtractor));
+
+        Self {
+            source,
+            embedder,
+            store,
+            extractors,
+            config,
+            state: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Register an additional text extractor, tried before the defaults
+    pub fn add_extractor(&mut self, extractor: Box<dyn TextExtractor>) {
+        self.extractors.insert(0, extractor);
+    }
+
+    /// Run one incremental sync
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if listing the source fails. Failures on individual
+    /// documents are recorded in the report instead.
+    #[instrument(skip(self), fields(source = self.source.name()))]
+    pub async fn sync(&self) -> Result<IngestReport> {
+        let documents = self.source.list().await?;
+        let mut report = IngestReport {
+            documents_seen: documents.len(),
+            ..IngestReport::default()
+        };
+
+        for document in &documents {
+            let unchanged = {
+                let state = self.state.read();
+                let version = document.version();
+                version.is_some() && state.get(&document.uri).map(|e| &e.version) == Some(&version)
+            };
+            if unchanged {
+                report.documents_skipped += 1;
+                continue;
+            }
+
+            match self.ingest_document(document).await {
+                Ok(chunks) => {
+                    report.documents_updated += 1;
+                    report.chunks_upserted += chunks;
+                }
+                Err(e) => {
+                    warn!("Failed to ingest {}: {}", document.uri, e);
+                    report.errors.push((document.uri.clone(), e.to_string()));
+                }
+            }
+        }
+
+        if self.config.delete_missing {
+            report.documents_removed = self.remove_missing(&documents).await?;
+        }
+
+        info!(
+            "Ingestion sync complete: {} updated, {} skipped, {} removed",
+            report.documents_updated, report.documents_skipped, report.documents_removed
+        );
+        Ok(report)
+    }
+
+    /// Run [`IngestionPipeline::sync`] on the configured interval until the task is aborted
+    pub fn spawn_scheduled(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.sync_interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.sync().await {
+                    warn!("Scheduled ingestion sync failed: {}", e);
+                }
+            }
+        })
+    }
+
+    async fn ingest_document(&self, document: &DocumentRef) -> Result<usize> {
+        let raw = self.source.fetch(document).await?;
+        let extractor = self
+            .extractors
+            .iter()
+            .find(|e| e.supports(&raw))
+            .ok_or_else(|| {
+                Error::InvalidInput(format!(
+                    "No text extractor for {} ({:?})",
+                    document.uri, raw.content_type
+                ))
+            })?;
+
+        let text = extractor.extract(&raw)?;
+        let chunks = self.config.chunker.chunk(&text);
+        debug!("Extracted {} chunks from {}", chunks.len(), document.uri);
+
+        let mut records = Vec::with_capacity(chunks.len());
+        for (batch_index, batch) in chunks
+            .chunks(self.config.embed_batch_size.max(1))
+            .enumerate()
+        {
+            let vectors = self.embedder.embed(batch).await?;
+            if vectors.len() != batch.len() {
+                return Err(Error::Provider(format!(
+                    "Embedder returned {} vectors for {} inputs",
+                    vectors.len(),
+                    batch.len()
+                ))
+                .into());
+            }
+            for (offset, (content, vector)) in batch.iter().zip(vectors).enumerate() {
+                let index = batch_index * self.config.embed_batch_size.max(1) + offset;
+                records.push(
+                    VectorRecord::new(chunk_id(&document.uri, index), vector, content.clone())
+                        .with_metadata("source", serde_json::json!(self.source.name()))
+                        .with_metadata("uri", serde_json::json!(document.uri))
+                        .with_metadata("chunk_index", serde_json::json!(index))
+                        .with_metadata("embedding_model", serde_json::json!(self.embedder.model())),
+                );
+            }
+        }
+
+        let chunk_count = records.len();
+        self.store.upsert(records).await?;
+
+        // Drop trailing chunks left over from a longer previous version
+        let previous = self
+            .state
+            .read()
+            .get(&document.uri)
+            .map_or(0, |e| e.chunk_count);
+        if previous > chunk_count {
+            let stale: Vec<String> = (chunk_count..previous)
+                .map(|i| chunk_id(&document.uri, i))
+                .collect();
+            self.store.delete(&stale).await?;
+        }
+
+        self.state.write().insert(
+            document.uri.clone(),
+            SyncEntry {
+                version: document.version(),
+                chunk_count,
+            },
+        );
+        Ok(chunk_count)
+    }
+
+    async fn remove_missing(&self, documents: &[DocumentRef]) -> Result<usize> {
+        let missing: Vec<(String, usize)> = {
+            let state = self.state.read();
+            state
+                .iter()
+                .filter(|(uri, _)| !documents.iter().any(|d| &d.uri == *uri))
+                .map(|(uri, entry)| (uri.clone(), entry.chunk_count))
+                .collect()
+        };
+
+        for (uri, chunk_count) in &missing {
+            let ids: Vec<String> = (0..*chunk_count).map(|i| chunk_id(uri, i)).collect();
+            self.store.delete(&ids).await?;
+            self.state.write().remove(uri);
+        }
+        Ok(missing.len())
+    }
+}
+
+fn chunk_id(uri: &str, index: usize) -> String {
+    format!("{uri}#{index}")
+}
+
+/// Largest document a [`WebSource`] downloads by default, in bytes
+#[cfg(feature = "ingest")]
+pub const DEFAULT_MAX_DOCUMENT_BYTES: usize = 16 * 1024 * 1024;
+
+/// Static list of web pages
+///
+/// Bodies are streamed and documents larger than the size limit are
+/// skipped, so a large or hostile URL cannot exhaust memory during a sync.
+#[cfg(feature = "ingest")]
+pub struct WebSource {
+    urls: Vec<String>,
+    client: reqwest::Client,
+    max_bytes: usize,
+}
+
+#[cfg(feature = "ingest")]
+impl WebSource {
+    /// Create a source for the given URLs
+    #[must_use]
+    pub fn new(urls: Vec<String>) -> Self {
+        Self {
+            urls,
+            client: reqwest::Client::new(),
+            max_bytes: DEFAULT_MAX_DOCUMENT_BYTES,
+        }
+    }
+
+    /// Skip documents larger than `max_bytes`
+    #[must_use]
+    pub const fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    fn too_large(&self, reference: &DocumentRef) -> Error {
+        Error::InvalidInput(format!(
+            "{} is larger than {} bytes, skipping",
+            reference.uri, self.max_bytes
+        ))
+    }
+
+    fn header(response: &reqwest::Response, name: reqwest::header::HeaderName) -> Option<String> {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(ToString::to_string)
+    }
+}
+
+#[cfg(feature = "ingest")]
+#[async_trait]
+impl DocumentSource for WebSource {
+    fn name(&self) -> &str {
+        "web"
+    }
+
+    async fn list(&self) -> Result<Vec<DocumentRef>> {
+        let mut documents = Vec::with_capacity(self.urls.len());
+        for url in &self.urls {
+            let mut reference = DocumentRef::new(url.clone());
+            match self.client.head(url).send().await {
+                Ok(response) => {
+                    reference.etag = Self::header(&response, reqwest::header::ETAG);
+                    reference.last_modified =
+                        Self::header(&response, reqwest::header::LAST_MODIFIED)
+                            .and_then(|v| DateTime::parse_from_rfc2822(&v).ok())
+                            .map(|t| t.with_timezone(&Utc));
+                }
+                Err(e) => debug!("HEAD {} failed, will refetch: {}", url, e),
+            }
+            documents.push(reference);
+        }
+        Ok(documents)
+    }
+
+    async fn fetch(&self, reference: &DocumentRef) -> Result<RawDocument> {
+        let mut response = self
+            .client
+            .get(&reference.uri)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| Error::Network(format!("Failed to fetch {}: {e}", reference.uri)))?;
+        if response
+            .content_length()
+            .is_some_and(|len| len > self.max_bytes as u64)
+        {
+            return Err(self.too_large(reference).into());
+        }
+        let content_type = Self::header(&response, reqwest::header::CONTENT_TYPE);
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| Error::Network(format!("Failed to read {}: {e}", reference.uri)))?
+        {
+            if chunk.len() > self.max_bytes - bytes.len() {
+                return Err(self.too_large(reference).into());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        Ok(RawDocument {
+            reference: reference.clone(),
+            content_type,
+            bytes,
+        })
+    }
+}
+
+/// Objects under an S3 prefix
+#[cfg(feature = "ingest")]
+pub struct S3Source {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+    prefix: String,
+}
+
+#[cfg(feature = "ingest")]
+impl S3Source {
+    /// Create a source for `s3://bucket/prefix`
+    #[must_use]
+    pub fn new(
+        client: aws_sdk_s3::Client,
+        bucket: impl Into<String>,
+        prefix: impl Into<String>,
+    ) -> Self {
+        Self {
+            client,
+            bucket: bucket.into(),
+            prefix: prefix.into(),
+        }
+    }
+
+    /// Create a source using credentials from the default AWS provider chain
+    pub async fn from_env(bucket: impl Into<String>, prefix: impl Into<String>) -> Self {
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        Self::new(aws_sdk_s3::Client::new(&config), bucket, prefix)
+    }
+
+    fn key<'a>(&self, uri: &'a str) -> &'a str {
+        uri.strip_prefix(&format!("s3://{}/", self.bucket))
+            .unwrap_or(uri)
+    }
+}
+
+#[cfg(feature = "ingest")]
+#[async_trait]
+impl DocumentSource for S3Source {
+    fn name(&self) -> &str {
+        "s3"
+    }
+
+    async fn list(&self) -> Result<Vec<DocumentRef>> {
+        let mut documents = Vec::new();
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(&self.prefix)
+            .into_paginator()
+            .send();
+
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|e| Error::Network(format!("S3 list failed: {e}")))?;
+            for object in page.contents() {
+                let Some(key) = object.key() else { continue };
+                if key.ends_with('/') {
+                    continue;
+                }
+                documents.push(DocumentRef {
+                    uri: format!("s3://{}/{key}", self.bucket),
+                    etag: object.e_tag().map(ToString::to_string),
+                    last_modified: object
+                        .last_modified()
+                        .and_then(|t| DateTime::from_timestamp(t.secs(), t.subsec_nanos())),
+                });
+            }
+        }
+        Ok(documents)
+    }
+
+    async fn fetch(&self, reference: &DocumentRef) -> Result<RawDocument> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.key(&reference.uri))
+            .send()
+            .await
+            .map_err(|e| Error::Network(format!("S3 get {} failed: {e}", reference.uri)))?;
+        let content_type = object.content_type().map(ToString::to_string);
+        let bytes = object
+            .body
+            .collect()
+            .await
+            .map_err(|e| Error::Network(format!("S3 read {} failed: {e}", reference.uri)))?
+            .into_bytes();
+
+        Ok(RawDocument {
+            reference: reference.clone(),
+            content_type,
+            bytes: bytes.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::HnswIndex;
+
+    struct StaticSource {
+        documents: RwLock<Vec<(DocumentRef, &'static str)>>,
+    }
+
+    #[async_trait]
+    impl DocumentSource for StaticSource {
+        fn name(&self) -> &str {
+            "static"
+        }
+
+        async fn list(&self) -> Result<Vec<DocumentRef>> {
+            Ok(self
+                .documents
+                .read()
+                .iter()
+                .map(|(r, _)| r.clone())
+                .collect())
+        }
+
+        async fn fetch(&self, reference: &DocumentRef) -> Result<RawDocument> {
+            let body = self
+                .documents
+                .read()
+                .iter()
+                .find(|(r, _)| r.uri == reference.uri)
+                .map(|(_, body)| *body)
+                .ok_or_else(|| Error::NotFound(reference.uri.clone()))?;
+            Ok(RawDocument {
+                reference: reference.clone(),
+                content_type: None,
+                bytes: body.as_bytes().to_vec(),
+            })
+        }
+    }
+
+    struct LengthEmbedder;
+
+    #[async_trait]
+    impl Embedder for LengthEmbedder {
+        fn model(&self) -> &str {
+            "length"
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts.iter().map(|t| vec![t.len() as f32, 1.0]).collect())
+        }
+    }
+
+    fn document(uri: &str, etag: &str) -> DocumentRef {
+        DocumentRef {
+            uri: uri.to_string(),
+            etag: Some(etag.to_string()),
+            last_modified: None,
+        }
+    }
+
+    #[test]
+    fn test_html_extraction() {
+        let html = r"<html><head><title>T</title><script>var x = 1;</script></head>
+            <body><nav>Home | About</nav><article><h1>Title</h1><p>First &amp; second.</p>
+            <p>Third</p></article><footer>Copyright</footer></body></html>";
+        let text = HtmlExtractor::extract_html(html);
+        assert_eq!(text, "Title\nFirst & second.\nThird");
+    }
+
+    #[test]
+    fn test_chunker_overlap() {
+        let chunker = TextChunker::new(20, 8);
+        let chunks = chunker.chunk("one two three four five six seven eight nine ten");
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.len() <= 20));
+        assert!(chunks[1].starts_with(chunks[0].rsplit(' ').next().unwrap()));
+        assert!(TextChunker::default().chunk("").is_empty());
+    }
+
+    #[test]
+    fn test_extension_detection() {
+        assert!(has_extension(
+            "https://example.com/page.HTML?x=1",
+            &["html"]
+        ));
+        assert!(!has_extension("s3://bucket/readme", &["md"]));
+    }
+
+    #[tokio::test]
+    async fn test_incremental_sync() {
+        let source = Arc::new(StaticSource {
+            documents: RwLock::new(vec![
+                (document("s3://docs/a.txt", "v1"), "alpha document"),
+                (document("s3://docs/b.html", "v1"), "<p>beta</p>"),
+            ]),
+        });
+        let store = Arc::new(HnswIndex::default());
+        let pipeline = IngestionPipeline::new(
+            source.clone(),
+            Arc::new(LengthEmbedder),
+            store.clone(),
+            IngestConfig::default(),
+        );
+
+        let first = pipeline.sync().await.unwrap();
+        assert_eq!(first.documents_updated, 2);
+        assert_eq!(first.chunks_upserted, 2);
+        assert_eq!(store.len().await.unwrap(), 2);
+
+        let second = pipeline.sync().await.unwrap();
+        assert_eq!(second.documents_skipped, 2);
+        assert_eq!(second.documents_updated, 0);
+
+        source.documents.write()[0] = (document("s3://docs/a.txt", "v2"), "alpha v2");
+        source.documents.write().remove(1);
+        let third = pipeline.sync().await.unwrap();
+        assert_eq!(third.documents_updated, 1);
+        assert_eq!(third.documents_removed, 1);
+        assert_eq!(store.len().await.unwrap(), 1);
+
+        let record = store.get("s3://docs/a.txt#0").await.unwrap().unwrap();
+        assert_eq!(record.content, "alpha v2");
+        assert_eq!(record.metadata["source"], serde_json::json!("static"));
+    }
+
+    #[cfg(feature = "ingest")]
+    #[tokio::test]
+    async fn test_oversize_web_documents_are_skipped() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]).into_owned();
+                let body = "x".repeat(64 * 1024);
+                // The streamed page has no Content-Length, so only the read limit stops it
+                let response = if request.starts_with("GET /streamed") {
+                    format!("HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n{body}")
+                } else if request.starts_with("GET /large") {
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{body}",
+                        body.len()
+                    )
+                } else {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nsmall".to_string()
+                };
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let source = Arc::new(
+            WebSource::new(
+                ["large.txt", "streamed.txt", "small.txt"]
+                    .iter()
+                    .map(|path| format!("http://127.0.0.1:{port}/{path}"))
+                    .collect(),
+            )
+            .with_max_bytes(1024),
+        );
+        let pipeline = IngestionPipeline::new(
+            source,
+            Arc::new(LengthEmbedder),
+            Arc::new(HnswIndex::default()),
+            IngestConfig::default(),
+        );
+
+        let report = pipeline.sync().await.unwrap();
+        assert_eq!(report.documents_updated, 1);
+        assert_eq!(report.errors.len(), 2);
+        assert!(report
+            .errors
+            .iter()
+            .all(|(_, error)| error.contains("larger than 1024 bytes")));
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_document_is_reported() {
+        let source = Arc::new(StaticSource {
+            documents: RwLock::new(vec![(document("s3://docs/image.png", "v1"), "binary")]),
+        });
+        let pipeline = IngestionPipeline::new(
+            source,
+            Arc::new(LengthEmbedder),
+            Arc::new(HnswIndex::default()),
+            IngestConfig::default(),
+        );
+
+        let report = pipeline.sync().await.unwrap();
+        assert_eq!(report.documents_updated, 0);
+        assert_eq!(report.errors.len(), 1);
+    }
+}
@@ -0,0 +1,180 @@
+//! A structured description of a running bot
+//!
+//! [`Bot::describe`](crate::Bot::describe) reports what the bot would do with
+//! the next message: the active configuration version's pipeline stages in
+//! order, the registered plugins with their capabilities and permissions,
+//! the tools those plugins offer, and how models are chosen. Admin UIs can
+//! render it directly, and it is the first thing to check when a message is
+//! not handled as expected.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{BotConfig, BudgetDowngradePolicy, ProvisionedModelConfig},
+    plugin::{Capability, CapabilityType, Permission, Plugin, PluginMetadata, PluginRegistry},
+};
+
+/// Everything a bot is configured to do
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BotDescriptor {
+    /// ID of the active configuration version
+    pub config_version: u32,
+    /// Pipeline stages, in the order messages pass through them
+    pub stages: Vec<String>,
+    /// Registered plugins, by name
+    pub plugins: Vec<PluginDescriptor>,
+    /// Tools offered by plugins, by plugin and tool name
+    pub tools: Vec<ToolDescriptor>,
+    /// Models and the rules choosing between them
+    pub models: ModelRouting,
+}
+
+/// A registered plugin
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginDescriptor {
+    /// Name, version, and description
+    #[serde(flatten)]
+    pub metadata: PluginMetadata,
+    /// What the plugin can do
+    pub capabilities: Vec<Capability>,
+    /// What the plugin is allowed to do
+    pub permissions: Vec<Permission>,
+}
+
+/// A tool a plugin offers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolDescriptor {
+    /// Tool name, the capability name
+    pub name: String,
+    /// Plugin to invoke the tool on
+    pub plugin: String,
+    /// What the tool does
+    pub description: String,
+    /// JSON Schema of the request data, if the plugin publishes one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<serde_json::Value>,
+}
+
+/// How the model for a message is chosen
+///
+/// Unless a user has pinned a model, a message goes to its tenant's model or
+/// `default`, moves down the budget downgrade chain as the budget runs out,
+/// and is sent to a provisioned deployment of that model while it has
+/// capacity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelRouting {
+    /// Model used when no other rule applies
+    pub default: String,
+    /// Default model per tenant
+    pub tenant_models: BTreeMap<String, String>,
+    /// Models that overrides and pins may select; empty allows any known model
+    pub allowed_models: Vec<String>,
+    /// Cheaper models to move to as the budget runs out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget_downgrade: Option<BudgetDowngradePolicy>,
+    /// Provisioned deployments requests are routed to
+    pub provisioned: Vec<ProvisionedModelConfig>,
+}
+
+impl BotDescriptor {
+    /// Describe a bot running `config` through `stages` with `plugins`
+    #[must_use]
+    pub fn new(
+        config_version: u32,
+        config: &BotConfig,
+        stages: Vec<String>,
+        plugins: &PluginRegistry,
+    ) -> Self {
+        let mut registered: Vec<&dyn Plugin> = plugins.plugins().collect();
+        registered.sort_by(|a, b| a.name().cmp(b.name()));
+
+        let tools = registered
+            .iter()
+            .flat_map(|plugin| {
+                plugin
+                    .capabilities()
+                    .into_iter()
+                    .filter(|capability| {
+                        matches!(capability.capability_type, CapabilityType::ToolProvider)
+                    })
+                    .map(|capability| ToolDescriptor {
+                        schema: plugin.tool_schema(&capability.name),
+                        name: capability.name,
+                        plugin: plugin.name().to_string(),
+                        description: capability.description,
+                    })
+            })
+            .collect();
+        let plugins = registered
+            .iter()
+            .map(|plugin| PluginDescriptor {
+                metadata: plugin.metadata(),
+                capabilities: plugin.capabilities(),
+                permissions: plugins.permissions(plugin.name()).to_vec(),
+            })
+            .collect();
+
+        Self {
+            config_version,
+            stages,
+            plugins,
+            tools,
+            models: ModelRouting {
+                default: config.model.clone(),
+                tenant_models: config
+                    .model_selection
+                    .tenant_models
+                    .iter()
+                    .map(|(tenant, model)| (tenant.clone(), model.clone()))
+                    .collect(),
+                allowed_models: config.model_selection.allowed_models.clone(),
+                budget_downgrade: config.budget.downgrade.clone(),
+                provisioned: config.provisioned_throughput.models.clone(),
+            },
+        }
+    }
+
+    /// The tool named `name`, if any plugin offers it
+    #[must_use]
+    pub fn tool(&self, name: &str) -> Option<&ToolDescriptor> {
+        self.tools.iter().find(|tool| tool.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{CalculatorPlugin, UnitConverterPlugin};
+
+    #[test]
+    fn test_descriptor_lists_plugins_and_tools() {
+        let mut registry = PluginRegistry::new();
+        registry
+            .register(Box::new(UnitConverterPlugin::new()))
+            .unwrap();
+        registry
+            .register(Box::new(CalculatorPlugin::new()))
+            .unwrap();
+        let config = BotConfig::default();
+
+        let descriptor = BotDescriptor::new(1, &config, vec!["sanitize".to_string()], &registry);
+
+        let names: Vec<_> = descriptor
+            .plugins
+            .iter()
+            .map(|plugin| plugin.metadata.name.as_str())
+            .collect();
+        assert_eq!(names, ["calculator", "unit_converter"]);
+        assert_eq!(descriptor.plugins[0].permissions, [Permission::All]);
+
+        let convert = descriptor.tool("convert").unwrap();
+        assert_eq!(convert.plugin, "unit_converter");
+        assert_eq!(
+            convert.schema.as_ref().unwrap()["required"],
+            serde_json::json!(["tool", "value", "from", "to"])
+        );
+        assert_eq!(descriptor.models.default, config.model);
+    }
+}
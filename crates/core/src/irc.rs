@@ -0,0 +1,623 @@
+//! IRC channel adapter
+//!
+//! Channels map to one conversation each and private messages to one
+//! conversation per nick. In channels the bot only answers messages that
+//! match the channel's [`Activation`] rule; private messages are always
+//! answered.
+//!
+//! IRC lines are limited to 512 bytes and servers disconnect clients that
+//! send too fast, so responses are split into lines that fit with
+//! [`split_message`] and paced by [`FloodControl`]; a [`RateShape`] can pace
+//! messages per channel or nick on top of that. The connection speaks the
+//! protocol directly over any async stream, so TLS is a matter of passing a
+//! TLS stream to [`IrcAdapter::run`].
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tracing::{debug, info, warn};
+
+use crate::{
+    bot::Bot,
+    error::Error,
+    message::{Message, MessageType, CHANNEL_METADATA_KEY},
+    shaping::{RateShape, RateShaper},
+};
+
+/// Maximum length of an IRC line, including the trailing CRLF
+pub const MAX_LINE_BYTES: usize = 512;
+
+/// Bytes reserved for the `:nick!user@host ` prefix servers add when relaying
+const HOSTMASK_ALLOWANCE: usize = 100;
+
+/// How the bot is addressed in a channel
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Activation {
+    /// Messages starting with the prefix, e.g. `!ask what is rust?`
+    Prefix(String),
+    /// Messages starting with the bot's nick, e.g. `bot: what is rust?`
+    #[default]
+    Mention,
+    /// Every message
+    Always,
+}
+
+/// Connection and behaviour settings
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IrcConfig {
+    /// Network name, used in conversation IDs
+    pub network: String,
+    /// Server host
+    pub server: String,
+    /// Server port
+    pub port: u16,
+    /// Nick
+    pub nick: String,
+    /// Password to `IDENTIFY` with `NickServ` after connecting
+    pub nickserv_password: Option<String>,
+    /// Channels to join
+    pub channels: Vec<String>,
+    /// Activation for channels without an override
+    pub default_activation: Activation,
+    /// Per-channel activation, keyed by channel name
+    pub activation: HashMap<String, Activation>,
+    /// Lines sent back to back before pacing starts
+    pub flood_burst: u32,
+    /// Pause between lines once the burst is spent
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub flood_interval: Duration,
+    /// Most lines sent for one response; the rest is dropped
+    pub max_response_lines: usize,
+}
+
+impl Default for IrcConfig {
+    fn default() -> Self {
+        Self {
+            network: "libera".to_string(),
+            server: "irc.libera.chat".to_string(),
+            port: 6667,
+            nick: "universal-bot".to_string(),
+            nickserv_password: None,
+            channels: Vec::new(),
+            default_activation: Activation::default(),
+            activation: HashMap::new(),
+            flood_burst: 4,
+            flood_interval: Duration::from_secs(2),
+            max_response_lines: 10,
+        }
+    }
+}
+
+impl IrcConfig {
+    /// The activation rule for a channel
+    #[must_use]
+    pub fn activation_for(&self, channel: &str) -> &Activation {
+        self.activation
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(channel))
+            .map_or(&self.default_activation, |(_, activation)| activation)
+    }
+}
+
+/// A parsed IRC protocol line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrcMessage {
+    /// Source, e.g. `nick!user@host`
+    pub prefix: Option<String>,
+    /// Command or numeric reply, e.g. `PRIVMSG` or `001`
+    pub command: String,
+    /// Parameters, with the trailing parameter last
+    pub params: Vec<String>,
+}
+
+impl IrcMessage {
+    /// Parse a line, without its CRLF
+    ///
+    /// `IRCv3` message tags are skipped. Returns `None` for empty lines.
+    #[must_use]
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+        if rest.starts_with('@') {
+            rest = rest.split_once(' ')?.1;
+        }
+        let prefix = match rest.strip_prefix(':') {
+            Some(stripped) => {
+                let (prefix, remainder) = stripped.split_once(' ')?;
+                rest = remainder;
+                Some(prefix.to_string())
+            }
+            None => None,
+        };
+
+        let (middle, trailing) = match rest.split_once(" :") {
+            Some((middle, trailing)) => (middle, Some(trailing)),
+            None => (rest, None),
+        };
+        let mut words = middle.split_whitespace();
+        let command = words.next()?.to_ascii_uppercase();
+        let mut params: Vec<String> = words.map(str::to_string).collect();
+        params.extend(trailing.map(str::to_string));
+        Some(Self {
+            prefix,
+            command,
+            params,
+        })
+    }
+
+    /// Nick of the source, if the source is a user
+    #[must_use]
+    pub fn nick(&self) -> Option<&str> {
+        self.prefix
+            .as_deref()?
+            .split_once('!')
+            .map(|(nick, _)| nick)
+    }
+}
+
+/// Build a `PRIVMSG` line, without CRLF
+#[must_use]
+pub fn privmsg(target: &str, text: &str) -> String {
+    format!("PRIVMSG {target} :{text}")
+}
+
+/// Split a response into lines that fit in a `PRIVMSG` to `target`
+///
+/// Newlines start a new line and blank lines are dropped. Long lines wrap
+/// at spaces where possible and never split a UTF-8 character.
+#[must_use]
+pub fn split_message(target: &str, text: &str) -> Vec<String> {
+    let budget = MAX_LINE_BYTES - 2 - HOSTMASK_ALLOWANCE - privmsg(target, "").len();
+    let mut lines = Vec::new();
+    for line in text
+        .lines()
+        .map(str::trim_end)
+        .filter(|l| !l.trim().is_empty())
+    {
+        let mut rest = line;
+        while rest.len() > budget {
+            let mut cut = budget;
+            while !rest.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            if let Some(space) = rest[..cut].rfind(' ').filter(|&i| i > 0) {
+                cut = space;
+            }
+            lines.push(rest[..cut].to_string());
+            rest = rest[cut..].trim_start();
+        }
+        if !rest.is_empty() {
+            lines.push(rest.to_string());
+        }
+    }
+    lines
+}
+
+/// Token-bucket pacing for outgoing lines
+///
+/// Allows `burst` lines back to back, then one line per `interval`, the
+/// way most servers meter clients.
+#[derive(Debug)]
+pub struct FloodControl {
+    burst: u32,
+    interval: Duration,
+    tokens: f64,
+    last: Instant,
+}
+
+impl FloodControl {
+    /// Create a full bucket
+    #[must_use]
+    pub fn new(burst: u32, interval: Duration) -> Self {
+        Self {
+            burst,
+            interval,
+            tokens: f64::from(burst),
+            last: Instant::now(),
+        }
+    }
+
+    /// Take a slot for one line at `now`, returning how long to wait first
+    pub fn reserve(&mut self, now: Instant) -> Duration {
+        let refill = now.saturating_duration_since(self.last).as_secs_f64()
+            / self.interval.as_secs_f64().max(f64::EPSILON);
+        self.tokens = (self.tokens + refill).min(f64::from(self.burst));
+        self.last = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            self.interval.mul_f64(-self.tokens)
+        }
+    }
+}
+
+/// Routes IRC messages through a [`Bot`]
+pub struct IrcAdapter {
+    bot: Bot,
+    config: IrcConfig,
+    shaper: Option<RateShaper>,
+}
+
+impl IrcAdapter {
+    /// Create an adapter
+    #[must_use]
+    pub fn new(bot: Bot, config: IrcConfig) -> Self {
+        Self {
+            bot,
+            config,
+            shaper: None,
+        }
+    }
+
+    /// Pace messages to `shape`, queueing per channel or nick
+    ///
+    /// Lines cannot be edited, so the coalesce window is unused.
+    #[must_use]
+    pub fn with_rate_shape(mut self, shape: RateShape) -> Self {
+        self.shaper = Some(RateShaper::new(shape));
+        self
+    }
+
+    /// Conversation for a channel or, for private messages, a nick
+    #[must_use]
+    pub fn conversation_id(&self, target: &str) -> String {
+        format!("irc:{}:{}", self.config.network, target.to_lowercase())
+    }
+
+    /// Lines that register the connection
+    #[must_use]
+    pub fn registration(&self) -> Vec<String> {
+        vec![
+            format!("NICK {}", self.config.nick),
+            format!("USER {} 0 * :{}", self.config.nick, self.config.nick),
+        ]
+    }
+
+    /// Lines sent once the server welcomes the bot
+    #[must_use]
+    pub fn on_welcome(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(password) = &self.config.nickserv_password {
+            lines.push(privmsg(
+                "NickServ",
+                &format!("IDENTIFY {} {password}", self.config.nick),
+            ));
+        }
+        lines.extend(self.config.channels.iter().map(|c| format!("JOIN {c}")));
+        lines
+    }
+
+    /// Convert a `PRIVMSG` into a bot message, applying activation rules
+    ///
+    /// Returns the message and the target to reply to, or `None` if the
+    /// line is not addressed to the bot.
+    #[must_use]
+    pub fn to_message(&self, line: &IrcMessage) -> Option<(Message, String)> {
+        if line.command != "PRIVMSG" {
+            return None;
+        }
+        let nick = line.nick()?;
+        let [target, text] = line.params.as_slice() else {
+            return None;
+        };
+        let is_channel = target.starts_with(['#', '&']);
+
+        let text = if is_channel {
+            self.activated_text(target, text)?
+        } else {
+            text.as_str()
+        };
+        // CTCP, e.g. ACTION or VERSION
+        if text.is_empty() || text.starts_with('\u{1}') {
+            return None;
+        }
+
+        let reply_to = if is_channel { target } else { nick };
+        let message_type = if text.starts_with('/') {
+            MessageType::Command
+        } else {
+            MessageType::Text
+        };
+        let message = Message::with_type(text, message_type)
+            .with_conversation_id(self.conversation_id(reply_to))
+            .with_user_id(nick)
+            .with_metadata(CHANNEL_METADATA_KEY, serde_json::json!("irc"));
+        Some((message, reply_to.to_string()))
+    }
+
+    fn activated_text<'a>(&self, channel: &str, text: &'a str) -> Option<&'a str> {
+        match self.config.activation_for(channel) {
+            Activation::Always => Some(text.trim()),
+            Activation::Prefix(prefix) => text.strip_prefix(prefix.as_str()).map(str::trim),
+            Activation::Mention => {
+                let nick = &self.config.nick;
+                let head = text.get(..nick.len())?;
+                let rest = &text[nick.len()..];
+                (head.eq_ignore_ascii_case(nick) && rest.starts_with([':', ',']))
+                    .then(|| rest[1..].trim())
+            }
+        }
+    }
+
+    /// Lines to send in response to a server line
+    ///
+    /// Answers `PING`, identifies and joins on welcome, retries with an
+    /// underscore when the nick is taken, and replies to messages addressed
+    /// to the bot. Channel replies address the sender by nick.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if processing a message fails.
+    pub async fn handle(&mut self, line: &IrcMessage) -> Result<Vec<String>> {
+        match line.command.as_str() {
+            "PING" => Ok(vec![format!(
+                "PONG :{}",
+                line.params.last().map_or("", String::as_str)
+            )]),
+            "001" => Ok(self.on_welcome()),
+            "433" => {
+                self.config.nick.push('_');
+                warn!("Nick in use, retrying as {}", self.config.nick);
+                Ok(vec![format!("NICK {}", self.config.nick)])
+            }
+            "PRIVMSG" => {
+                let Some((message, reply_to)) = self.to_message(line) else {
+                    return Ok(Vec::new());
+                };
+                let sender = message.user_id.clone();
+                let response = self.bot.process(message).await?;
+                let text = if reply_to.starts_with(['#', '&']) {
+                    format!("{sender}: {}", response.content)
+                } else {
+                    response.content
+                };
+
+                let mut lines = split_message(&reply_to, &text);
+                if lines.len() > self.config.max_response_lines {
+                    debug!(
+                        "Truncating {}-line response to {}",
+                        lines.len(),
+                        self.config.max_response_lines
+                    );
+                    lines.truncate(self.config.max_response_lines);
+                }
+                Ok(lines.iter().map(|l| privmsg(&reply_to, l)).collect())
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Connect over plain TCP and serve until the server closes the connection
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails or breaks.
+    pub async fn connect(self) -> Result<()> {
+        let address = format!("{}:{}", self.config.server, self.config.port);
+        let stream = tokio::net::TcpStream::connect(&address)
+            .await
+            .map_err(|e| Error::Network(format!("Failed to connect to {address}: {e}")))?;
+        info!("Connected to {}", address);
+        self.run(stream).await
+    }
+
+    /// Serve over an established stream until it closes
+    ///
+    /// Failed messages are logged and skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading from or writing to the stream fails.
+    pub async fn run<S>(mut self, stream: S) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader).lines();
+        let mut flood = FloodControl::new(self.config.flood_burst, self.config.flood_interval);
+
+        for line in self.registration() {
+            send_line(&mut writer, &mut flood, &line).await?;
+        }
+        while let Some(raw) = reader
+            .next_line()
+            .await
+            .map_err(|e| Error::Network(format!("IRC read failed: {e}")))?
+        {
+            let Some(line) = IrcMessage::parse(&raw) else {
+                continue;
+            };
+            let outgoing = match self.handle(&line).await {
+                Ok(outgoing) => outgoing,
+                Err(e) => {
+                    warn!("Failed to answer IRC message: {:#}", e);
+                    continue;
+                }
+            };
+            for line in outgoing {
+                self.pace(&line).await;
+                send_line(&mut writer, &mut flood, &line).await?;
+            }
+        }
+        info!("IRC connection closed");
+        Ok(())
+    }
+
+    async fn pace(&self, line: &str) {
+        let Some(shaper) = &self.shaper else {
+            return;
+        };
+        let Some(line) = IrcMessage::parse(line).filter(|line| line.command == "PRIVMSG") else {
+            return;
+        };
+        if let Some(target) = line.params.first() {
+            shaper.acquire(&target.to_lowercase()).await;
+        }
+    }
+}
+
+async fn send_line<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    flood: &mut FloodControl,
+    line: &str,
+) -> Result<()> {
+    // PONG must not wait behind queued output or the server drops us
+    if !line.starts_with("PONG") {
+        let wait = flood.reserve(Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+    writer
+        .write_all(format!("{line}\r\n").as_bytes())
+        .await
+        .map_err(|e| Error::Network(format!("IRC write failed: {e}")))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BotConfig;
+
+    fn config() -> IrcConfig {
+        IrcConfig {
+            nick: "helper".to_string(),
+            nickserv_password: Some("hunter2".to_string()),
+            channels: vec!["#rust".to_string()],
+            activation: HashMap::from([("#help".to_string(), Activation::Prefix("!".to_string()))]),
+            ..IrcConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let line = IrcMessage::parse(
+            "@time=2024-01-01T00:00:00Z :ana!~ana@host PRIVMSG #rust :helper: hi there\r\n",
+        )
+        .unwrap();
+        assert_eq!(line.command, "PRIVMSG");
+        assert_eq!(line.nick(), Some("ana"));
+        assert_eq!(line.params, vec!["#rust", "helper: hi there"]);
+
+        let ping = IrcMessage::parse("PING :irc.example.org").unwrap();
+        assert_eq!(ping.prefix, None);
+        assert_eq!(ping.params, vec!["irc.example.org"]);
+        assert_eq!(
+            IrcMessage::parse(":server.example 001 helper :Welcome")
+                .unwrap()
+                .nick(),
+            None
+        );
+        assert!(IrcMessage::parse("").is_none());
+    }
+
+    #[test]
+    fn test_split_message_fits_line_limit() {
+        let text = format!("short\n\n{}\nlast", "word ".repeat(200) + &"é".repeat(300));
+        let lines = split_message("#rust", &text);
+        assert_eq!(lines[0], "short");
+        assert_eq!(lines.last().unwrap(), "last");
+        for line in &lines {
+            assert!(privmsg("#rust", line).len() + 2 + HOSTMASK_ALLOWANCE <= MAX_LINE_BYTES);
+        }
+        assert_eq!(lines.concat().chars().filter(|c| *c == 'é').count(), 300);
+    }
+
+    #[test]
+    fn test_flood_control() {
+        let start = Instant::now();
+        let mut flood = FloodControl::new(2, Duration::from_secs(2));
+        assert_eq!(flood.reserve(start), Duration::ZERO);
+        assert_eq!(flood.reserve(start), Duration::ZERO);
+        assert_eq!(flood.reserve(start), Duration::from_secs(2));
+        assert_eq!(flood.reserve(start), Duration::from_secs(4));
+        assert_eq!(
+            flood.reserve(start + Duration::from_secs(10)),
+            Duration::ZERO
+        );
+    }
+
+    #[tokio::test]
+    async fn test_activation_rules() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        let adapter = IrcAdapter::new(bot, config());
+        let msg = |target: &str, text: &str| IrcMessage {
+            prefix: Some("Ana!~ana@host".to_string()),
+            command: "PRIVMSG".to_string(),
+            params: vec![target.to_string(), text.to_string()],
+        };
+
+        let (message, reply_to) = adapter
+            .to_message(&msg("#rust", "Helper, what is a trait?"))
+            .unwrap();
+        assert_eq!(message.content, "what is a trait?");
+        assert_eq!(message.conversation_id, "irc:libera:#rust");
+        assert_eq!(reply_to, "#rust");
+        assert!(adapter
+            .to_message(&msg("#rust", "helpers are nice"))
+            .is_none());
+
+        assert!(adapter.to_message(&msg("#help", "helper: hi")).is_none());
+        let (message, _) = adapter.to_message(&msg("#HELP", "!ping")).unwrap();
+        assert_eq!(message.content, "ping");
+
+        let (message, reply_to) = adapter.to_message(&msg("helper", "hello")).unwrap();
+        assert_eq!(message.conversation_id, "irc:libera:ana");
+        assert_eq!(reply_to, "Ana");
+        assert!(adapter
+            .to_message(&msg("helper", "\u{1}VERSION\u{1}"))
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_handle_protocol_lines() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        let mut adapter = IrcAdapter::new(bot, config());
+
+        let ping = IrcMessage::parse("PING :abc").unwrap();
+        assert_eq!(adapter.handle(&ping).await.unwrap(), vec!["PONG :abc"]);
+
+        let welcome = IrcMessage::parse(":srv 001 helper :Welcome").unwrap();
+        assert_eq!(
+            adapter.handle(&welcome).await.unwrap(),
+            vec!["PRIVMSG NickServ :IDENTIFY helper hunter2", "JOIN #rust"]
+        );
+
+        let taken = IrcMessage::parse(":srv 433 * helper :Nickname is already in use").unwrap();
+        assert_eq!(adapter.handle(&taken).await.unwrap(), vec!["NICK helper_"]);
+
+        let question = IrcMessage::parse(":ana!~ana@host PRIVMSG #rust :helper_: hello").unwrap();
+        let reply = adapter.handle(&question).await.unwrap();
+        assert!(reply[0].starts_with("PRIVMSG #rust :ana: "));
+    }
+
+    #[tokio::test]
+    async fn test_rate_shape_paces_each_target() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        let adapter = IrcAdapter::new(bot, config()).with_rate_shape(RateShape {
+            per_destination: Some(crate::shaping::Rate {
+                messages: 1,
+                per: Duration::from_millis(100),
+            }),
+            ..RateShape::default()
+        });
+
+        let started = Instant::now();
+        adapter.pace("PRIVMSG #rust :one").await;
+        adapter.pace("PRIVMSG #go :two").await;
+        adapter.pace("PONG :abc").await;
+        assert!(started.elapsed() < Duration::from_millis(50));
+
+        adapter.pace("PRIVMSG #Rust :three").await;
+        assert!(started.elapsed() >= Duration::from_millis(90));
+    }
+}
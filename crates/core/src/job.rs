@@ -0,0 +1,533 @@
+//! Long-running background jobs
+//!
+//! Agent runs can take minutes, far longer than an HTTP caller should be
+//! held open. [`JobManager::submit`] starts the work in the background and
+//! returns a [`JobId`] immediately; callers then poll [`JobManager::get`],
+//! subscribe to [`JobEvent`]s, or receive a webhook when the job finishes.
+//! Every status and progress change is written to a [`JobStore`].
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, watch};
+use tracing::{debug, instrument, warn};
+use uuid::Uuid;
+
+use crate::{
+    message::Response,
+    webhook::{WebhookEvent, WebhookEventKind, WebhookManager},
+};
+
+/// Identifier of a submitted job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct JobId(pub Uuid);
+
+impl JobId {
+    /// Generate a new random ID
+    #[must_use]
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl Default for JobId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Display for JobId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::str::FromStr for JobId {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(s).map(Self)
+    }
+}
+
+/// Lifecycle state of a job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Accepted but not yet started
+    Queued,
+    /// Currently executing
+    Running,
+    /// Finished with a response
+    Succeeded,
+    /// Finished with an error
+    Failed,
+    /// Stopped at the caller's request
+    Cancelled,
+}
+
+impl JobStatus {
+    /// Whether the job has stopped for good
+    #[must_use]
+    pub const fn is_terminal(self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// Snapshot of a job's state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    /// Job ID
+    pub id: JobId,
+    /// Conversation the job runs in
+    pub conversation_id: String,
+    /// Current state
+    pub status: JobStatus,
+    /// Completion estimate between 0.0 and 1.0
+    pub progress: f32,
+    /// Latest progress note, e.g. the step being run
+    pub note: Option<String>,
+    /// Partial output produced so far
+    pub partial: Option<String>,
+    /// Final response, once succeeded
+    pub response: Option<Response>,
+    /// Failure reason, once failed
+    pub error: Option<String>,
+    /// When the job was submitted
+    pub created_at: DateTime<Utc>,
+    /// When the job last changed
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Job {
+    fn new(id: JobId, conversation_id: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id,
+            conversation_id,
+            status: JobStatus::Queued,
+            progress: 0.0,
+            note: None,
+            partial: None,
+            response: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// Notification of a job state change
+#[derive(Debug, Clone)]
+pub enum JobEvent {
+    /// The job reported progress
+    Progress {
+        /// Job ID
+        id: JobId,
+        /// Completion estimate
+        progress: f32,
+    },
+    /// The job reached a terminal state
+    Finished {
+        /// Job ID
+        id: JobId,
+        /// Terminal status
+        status: JobStatus,
+    },
+}
+
+/// Persistent storage for job state
+#[async_trait]
+pub trait JobStore: Send + Sync {
+    /// Insert or replace a job
+    async fn save(&self, job: &Job) -> Result<()>;
+
+    /// Load a job by ID
+    async fn load(&self, id: JobId) -> Result<Option<Job>>;
+}
+
+/// How long [`MemoryJobStore`] keeps finished jobs unless configured otherwise
+pub const DEFAULT_JOB_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// In-memory job store
+///
+/// Finished jobs are kept for a retention period after their last change,
+/// then evicted the next time a job finishes.
+#[derive(Debug)]
+pub struct MemoryJobStore {
+    jobs: DashMap<JobId, Job>,
+    retention: Duration,
+}
+
+impl MemoryJobStore {
+    /// Create an empty store keeping finished jobs for
+    /// [`DEFAULT_JOB_RETENTION`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep finished jobs for `retention` instead
+    #[must_use]
+    pub const fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Whether `job` finished longer than the retention period ago
+    fn is_expired(&self, job: &Job) -> bool {
+        job.status.is_terminal()
+            && chrono::Duration::from_std(self.retention)
+                .is_ok_and(|retention| job.updated_at + retention < Utc::now())
+    }
+}
+
+impl Default for MemoryJobStore {
+    fn default() -> Self {
+        Self {
+            jobs: DashMap::new(),
+            retention: DEFAULT_JOB_RETENTION,
+        }
+    }
+}
+
+#[async_trait]
+impl JobStore for MemoryJobStore {
+    async fn save(&self, job: &Job) -> Result<()> {
+        if job.status.is_terminal() {
+            self.jobs.retain(|_, stored| !self.is_expired(stored));
+        }
+        self.jobs.insert(job.id, job.clone());
+        Ok(())
+    }
+
+    async fn load(&self, id: JobId) -> Result<Option<Job>> {
+        Ok(self
+            .jobs
+            .get(&id)
+            .filter(|entry| !self.is_expired(entry))
+            .map(|entry| entry.clone()))
+    }
+}
+
+/// Handle given to a running job for reporting progress
+#[derive(Clone)]
+pub struct JobProgress {
+    id: JobId,
+    store: Arc<dyn JobStore>,
+    events: broadcast::Sender<JobEvent>,
+    cancelled: watch::Receiver<bool>,
+}
+
+impl JobProgress {
+    /// Record progress and an optional partial result
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the job store fails.
+    pub async fn report(
+        &self,
+        progress: f32,
+        note: Option<&str>,
+        partial: Option<&str>,
+    ) -> Result<()> {
+        let progress = progress.clamp(0.0, 1.0);
+        update(&*self.store, self.id, |job| {
+            job.progress = progress;
+            if let Some(note) = note {
+                job.note = Some(note.to_string());
+            }
+            if let Some(partial) = partial {
+                job.partial = Some(partial.to_string());
+            }
+        })
+        .await?;
+
+        let _ = self.events.send(JobEvent::Progress {
+            id: self.id,
+            progress,
+        });
+        Ok(())
+    }
+
+    /// Whether cancellation has been requested
+    ///
+    /// Work that checks this between steps stops sooner than work that is
+    /// only interrupted at its next await point.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+}
+
+/// Runs jobs in the background and tracks their state
+pub struct JobManager {
+    store: Arc<dyn JobStore>,
+    events: broadcast::Sender<JobEvent>,
+    running: Arc<DashMap<JobId, watch::Sender<bool>>>,
+    webhooks: Option<Arc<WebhookManager>>,
+}
+
+impl JobManager {
+    /// Create a manager persisting to `store`
+    #[must_use]
+    pub fn new(store: Arc<dyn JobStore>) -> Self {
+        let (events, _) = broadcast::channel(256);
+        Self {
+            store,
+            events,
+            running: Arc::new(DashMap::new()),
+            webhooks: None,
+        }
+    }
+
+    /// Send a [`WebhookEventKind::JobCompleted`] event when jobs finish
+    #[must_use]
+    pub fn with_webhooks(mut self, webhooks: Arc<WebhookManager>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Receive progress and completion events for all jobs
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.events.subscribe()
+    }
+
+    /// Start `task` in the background and return its ID
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the job cannot be persisted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    #[instrument(skip(self, task))]
+    pub async fn submit<F, Fut>(&self, conversation_id: String, task: F) -> Result<JobId>
+    where
+        F: FnOnce(JobProgress) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Response>> + Send + 'static,
+    {
+        let id = JobId::new();
+        let job = Job::new(id, conversation_id.clone());
+        self.store.save(&job).await?;
+
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+        self.running.insert(id, cancel_tx);
+
+        let progress = JobProgress {
+            id,
+            store: self.store.clone(),
+            events: self.events.clone(),
+            cancelled: cancel_rx.clone(),
+        };
+        let store = self.store.clone();
+        let events = self.events.clone();
+        let running = self.running.clone();
+        let webhooks = self.webhooks.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = update(&*store, id, |job| job.status = JobStatus::Running).await {
+                warn!("Failed to mark job {} running: {}", id, e);
+            }
+
+            let outcome = tokio::select! {
+                result = task(progress) => Some(result),
+                () = cancelled(cancel_rx) => None,
+            };
+            running.remove(&id);
+
+            let status = match &outcome {
+                Some(Ok(_)) => JobStatus::Succeeded,
+                Some(Err(_)) => JobStatus::Failed,
+                None => JobStatus::Cancelled,
+            };
+            let saved = update(&*store, id, |job| {
+                job.status = status;
+                match outcome {
+                    Some(Ok(response)) => {
+                        job.progress = 1.0;
+                        job.response = Some(response);
+                    }
+                    Some(Err(e)) => job.error = Some(e.to_string()),
+                    None => {}
+                }
+            })
+            .await;
+            if let Err(e) = saved {
+                warn!("Failed to persist job {} result: {}", id, e);
+            }
+
+            debug!("Job {} finished with status {:?}", id, status);
+            let _ = events.send(JobEvent::Finished { id, status });
+
+            if let Some(webhooks) = webhooks {
+                let event = WebhookEvent::new(
+                    conversation_id,
+                    WebhookEventKind::JobCompleted,
+                    serde_json::json!({ "job_id": id, "status": status }),
+                );
+                if let Err(e) = webhooks.dispatch(event).await {
+                    warn!("Failed to send completion webhook for job {}: {}", id, e);
+                }
+            }
+        });
+
+        Ok(id)
+    }
+
+    /// Current state of a job
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the job store fails.
+    pub async fn get(&self, id: JobId) -> Result<Option<Job>> {
+        self.store.load(id).await
+    }
+
+    /// Request cancellation of a running job
+    ///
+    /// Returns `false` if the job is unknown or already finished.
+    #[must_use]
+    pub fn cancel(&self, id: JobId) -> bool {
+        self.running
+            .get(&id)
+            .is_some_and(|sender| sender.send(true).is_ok())
+    }
+}
+
+async fn update(store: &dyn JobStore, id: JobId, apply: impl FnOnce(&mut Job)) -> Result<()> {
+    let Some(mut job) = store.load(id).await? else {
+        return Ok(());
+    };
+    apply(&mut job);
+    job.updated_at = Utc::now();
+    store.save(&job).await
+}
+
+async fn cancelled(mut rx: watch::Receiver<bool>) {
+    while !*rx.borrow() {
+        if rx.changed().await.is_err() {
+            // Sender dropped without cancelling; never resolve
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn manager() -> JobManager {
+        JobManager::new(Arc::new(MemoryJobStore::new()))
+    }
+
+    async fn wait_finished(events: &mut broadcast::Receiver<JobEvent>, id: JobId) -> JobStatus {
+        loop {
+            if let JobEvent::Finished { id: done, status } = events.recv().await.unwrap() {
+                if done == id {
+                    return status;
+                }
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_job_runs_to_completion() {
+        let jobs = manager();
+        let mut events = jobs.subscribe();
+
+        let id = jobs
+            .submit("conv".to_string(), |progress| async move {
+                progress
+                    .report(0.5, Some("halfway"), Some("partial"))
+                    .await?;
+                Ok(Response::text("conv", "done"))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(wait_finished(&mut events, id).await, JobStatus::Succeeded);
+        let job = jobs.get(id).await.unwrap().unwrap();
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert_eq!(job.note.as_deref(), Some("halfway"));
+        assert_eq!(job.partial.as_deref(), Some("partial"));
+        assert!((job.progress - 1.0).abs() < f32::EPSILON);
+        assert_eq!(job.response.unwrap().content, "done");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_job_failure_is_recorded() {
+        let jobs = manager();
+        let mut events = jobs.subscribe();
+
+        let id = jobs
+            .submit("conv".to_string(), |_| async {
+                Err(anyhow::anyhow!("model unavailable"))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(wait_finished(&mut events, id).await, JobStatus::Failed);
+        let job = jobs.get(id).await.unwrap().unwrap();
+        assert_eq!(job.error.as_deref(), Some("model unavailable"));
+        assert!(!jobs.cancel(id));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_job_cancellation() {
+        let jobs = manager();
+        let mut events = jobs.subscribe();
+
+        let id = jobs
+            .submit("conv".to_string(), |_| async {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok(Response::text("conv", "too late"))
+            })
+            .await
+            .unwrap();
+
+        assert!(jobs.cancel(id));
+        assert_eq!(wait_finished(&mut events, id).await, JobStatus::Cancelled);
+        let job = jobs.get(id).await.unwrap().unwrap();
+        assert!(job.status.is_terminal());
+        assert!(job.response.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_evicts_finished_jobs() {
+        let store = MemoryJobStore::new().with_retention(Duration::ZERO);
+        let running = Job::new(JobId::new(), "conv".to_string());
+        store.save(&running).await.unwrap();
+
+        let mut finished = Job::new(JobId::new(), "conv".to_string());
+        finished.status = JobStatus::Succeeded;
+        finished.updated_at = Utc::now() - chrono::Duration::seconds(1);
+        store.save(&finished).await.unwrap();
+
+        assert!(store.load(finished.id).await.unwrap().is_none());
+        assert!(store.load(running.id).await.unwrap().is_some());
+
+        let mut later = Job::new(JobId::new(), "conv".to_string());
+        later.status = JobStatus::Failed;
+        store.save(&later).await.unwrap();
+        assert!(!store.jobs.contains_key(&finished.id));
+        assert!(store.jobs.contains_key(&running.id));
+    }
+
+    #[test]
+    fn test_job_id_round_trip() {
+        let id = JobId::new();
+        assert_eq!(id.to_string().parse::<JobId>().unwrap(), id);
+        assert_eq!(serde_json::to_string(&id).unwrap(), format!("\"{id}\""));
+    }
+}
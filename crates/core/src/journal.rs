@@ -0,0 +1,523 @@
+//! Event-sourced context persistence
+//!
+//! With [`EventLogConfig::enabled`](crate::config::EventLogConfig::enabled),
+//! [`ContextManager`](crate::context::ContextManager) does not rewrite the
+//! whole context on every update. It diffs the context against the state it
+//! last persisted and appends the difference to a [`ContextEventStore`] as
+//! [`ContextEvent`]s: messages and responses added, variables set, history
+//! trimmed. Every
+//! [`snapshot_interval`](crate::config::EventLogConfig::snapshot_interval)
+//! events, and whenever the context's expiry moves, the full context is
+//! written to the [`ContextStore`] as a snapshot that records the sequence
+//! number it reflects, so loading replays only the events after it.
+//!
+//! The log starts with the context's initial state and is kept until the
+//! context is deleted, so any earlier state can be rebuilt for audit or
+//! replay. Changes no other event describes, such as a rollback, an undo, or
+//! pinning a message, are logged as a [`ContextEvent::Replaced`] carrying the
+//! full state.
+//!
+//! A context is expected to have one writer; concurrent updates through the
+//! same manager are serialized per context.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::context::{
+    Context, ContextMessage, ContextMetadata, ContextStore, MessageRole, UserContext,
+};
+
+/// One change to a context
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContextEvent {
+    /// A user or system message was appended to the history
+    MessageAdded {
+        /// The message
+        message: ContextMessage,
+    },
+    /// An assistant response was appended to the history
+    ResponseAdded {
+        /// The response
+        message: ContextMessage,
+    },
+    /// A session variable was set
+    VariableSet {
+        /// Variable name
+        key: String,
+        /// New value
+        value: serde_json::Value,
+    },
+    /// A session variable was removed
+    VariableRemoved {
+        /// Variable name
+        key: String,
+    },
+    /// The oldest history entries were dropped to fit the token limit
+    HistoryTrimmed {
+        /// Entries dropped
+        count: usize,
+    },
+    /// Counters, timestamps, or tags changed
+    MetadataUpdated {
+        /// The new metadata
+        metadata: ContextMetadata,
+    },
+    /// User information changed
+    UserUpdated {
+        /// The new user information
+        user: UserContext,
+    },
+    /// The full state, logged first and for changes no other event describes
+    Replaced {
+        /// The new state
+        context: Box<Context>,
+    },
+}
+
+impl ContextEvent {
+    /// The events that turn `before` into `after`
+    ///
+    /// Returns no events if nothing changed, and a single
+    /// [`Replaced`](Self::Replaced) if the history was changed other than
+    /// by trimming its oldest entries and appending new ones.
+    #[must_use]
+    pub fn diff(before: &Context, after: &Context) -> Vec<Self> {
+        let replaced = || {
+            vec![Self::Replaced {
+                context: Box::new(after.clone()),
+            }]
+        };
+        let same_checkpoints = before
+            .checkpoints
+            .iter()
+            .map(|checkpoint| checkpoint.id)
+            .eq(after.checkpoints.iter().map(|checkpoint| checkpoint.id));
+        if before.id != after.id || !same_checkpoints {
+            return replaced();
+        }
+
+        let Some(trimmed) = (0..before.history.len().max(1)).find(|&trimmed| {
+            let kept = before.history.len() - trimmed;
+            after.history.len() >= kept
+                && before
+                    .history
+                    .range(trimmed..)
+                    .eq(after.history.range(..kept))
+        }) else {
+            return replaced();
+        };
+
+        let mut events = Vec::new();
+        if trimmed > 0 {
+            events.push(Self::HistoryTrimmed { count: trimmed });
+        }
+        let kept = before.history.len() - trimmed;
+        events.extend(after.history.range(kept..).map(|message| {
+            let message = message.clone();
+            if message.role == MessageRole::Assistant {
+                Self::ResponseAdded { message }
+            } else {
+                Self::MessageAdded { message }
+            }
+        }));
+
+        let mut set: Vec<(&String, &serde_json::Value)> = after
+            .variables
+            .iter()
+            .filter(|(key, value)| before.variables.get(*key) != Some(*value))
+            .collect();
+        set.sort_unstable_by_key(|(key, _)| *key);
+        events.extend(set.into_iter().map(|(key, value)| Self::VariableSet {
+            key: key.clone(),
+            value: value.clone(),
+        }));
+        let mut removed: Vec<&String> = before
+            .variables
+            .keys()
+            .filter(|key| !after.variables.contains_key(*key))
+            .collect();
+        removed.sort_unstable();
+        events.extend(
+            removed
+                .into_iter()
+                .map(|key| Self::VariableRemoved { key: key.clone() }),
+        );
+
+        if json(&before.user) != json(&after.user) {
+            events.push(Self::UserUpdated {
+                user: after.user.clone(),
+            });
+        }
+        let mut metadata = after.metadata.clone();
+        metadata.event_seq = before.metadata.event_seq;
+        if json(&before.metadata) != json(&metadata) {
+            events.push(Self::MetadataUpdated { metadata });
+        }
+
+        // The events must rebuild `after` exactly; log the full state if not
+        let mut rebuilt = before.clone();
+        for event in &events {
+            event.clone().apply(&mut rebuilt);
+        }
+        rebuilt.metadata.event_seq = after.metadata.event_seq;
+        if json(&rebuilt) != json(after) {
+            return replaced();
+        }
+        events
+    }
+
+    /// Apply the change to `context`
+    pub fn apply(self, context: &mut Context) {
+        match self {
+            Self::MessageAdded { message } | Self::ResponseAdded { message } => {
+                context.token_count += message.estimated_tokens();
+                context.history.push_back(message);
+            }
+            Self::VariableSet { key, value } => {
+                context.variables.insert(key, value);
+            }
+            Self::VariableRemoved { key } => {
+                context.variables.remove(&key);
+            }
+            Self::HistoryTrimmed { count } => {
+                let count = count.min(context.history.len());
+                for removed in context.history.drain(..count) {
+                    context.token_count = context
+                        .token_count
+                        .saturating_sub(removed.estimated_tokens());
+                }
+            }
+            Self::MetadataUpdated { metadata } => {
+                let seq = context.metadata.event_seq;
+                context.metadata = metadata;
+                context.metadata.event_seq = seq;
+            }
+            Self::UserUpdated { user } => context.user = user,
+            Self::Replaced {
+                context: replacement,
+            } => *context = *replacement,
+        }
+    }
+}
+
+fn json<T: Serialize>(value: &T) -> Option<serde_json::Value> {
+    serde_json::to_value(value).ok()
+}
+
+/// A logged [`ContextEvent`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextEventRecord {
+    /// Position in the context's log, starting at 1
+    pub seq: u64,
+    /// When the change was persisted
+    pub at: DateTime<Utc>,
+    /// The change
+    pub event: ContextEvent,
+}
+
+impl ContextEventRecord {
+    /// Apply the event to `context` and mark the context as reflecting it
+    pub fn apply(self, context: &mut Context) {
+        self.event.apply(context);
+        context.metadata.event_seq = self.seq;
+    }
+}
+
+/// Rebuild context `id` by applying `records` to `snapshot`
+///
+/// Without a snapshot the records must start with the context's first
+/// event. Returns `None` if there is neither a snapshot nor a record.
+#[must_use]
+pub fn replay(
+    id: &str,
+    snapshot: Option<Context>,
+    records: impl IntoIterator<Item = ContextEventRecord>,
+) -> Option<Context> {
+    let mut context = snapshot;
+    for record in records {
+        record.apply(context.get_or_insert_with(|| Context::new(id)));
+    }
+    context
+}
+
+/// Append-only storage for context event logs
+///
+/// Records hold message text; stores outside the process should protect
+/// them as [`ContextStore`] backends protect contexts.
+#[async_trait]
+pub trait ContextEventStore: Send + Sync {
+    /// Append records, in sequence order, to a context's log
+    async fn append(&self, key: &str, records: &[ContextEventRecord]) -> Result<()>;
+
+    /// The records of a context's log after sequence number `after`, oldest first
+    async fn read(&self, key: &str, after: u64) -> Result<Vec<ContextEventRecord>>;
+
+    /// Delete a context's log
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// In-memory event store, used with the memory storage backend
+#[derive(Debug, Default)]
+pub(crate) struct MemoryEventStore {
+    logs: DashMap<String, Vec<ContextEventRecord>>,
+}
+
+#[async_trait]
+impl ContextEventStore for MemoryEventStore {
+    async fn append(&self, key: &str, records: &[ContextEventRecord]) -> Result<()> {
+        self.logs
+            .entry(key.to_string())
+            .or_default()
+            .extend_from_slice(records);
+        Ok(())
+    }
+
+    async fn read(&self, key: &str, after: u64) -> Result<Vec<ContextEventRecord>> {
+        Ok(self.logs.get(key).map_or_else(Vec::new, |log| {
+            log[log.partition_point(|record| record.seq <= after)..].to_vec()
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.logs.remove(key);
+        Ok(())
+    }
+}
+
+/// A context as of its last logged event
+#[derive(Debug)]
+struct Logged {
+    context: Context,
+    since_snapshot: usize,
+}
+
+/// Event log bookkeeping for a [`ContextManager`](crate::context::ContextManager)
+pub(crate) struct ContextJournal {
+    events: Arc<dyn ContextEventStore>,
+    snapshot_interval: usize,
+    logged: DashMap<String, Arc<Mutex<Option<Logged>>>>,
+}
+
+impl ContextJournal {
+    pub(crate) fn new(events: Arc<dyn ContextEventStore>, snapshot_interval: usize) -> Self {
+        Self {
+            events,
+            snapshot_interval: snapshot_interval.max(1),
+            logged: DashMap::new(),
+        }
+    }
+
+    fn slot(&self, id: &str) -> Arc<Mutex<Option<Logged>>> {
+        self.logged.entry(id.to_string()).or_default().clone()
+    }
+
+    /// Bring a stored snapshot up to date with the events logged after it
+    pub(crate) async fn rebuild(
+        &self,
+        id: &str,
+        snapshot: Option<Context>,
+    ) -> Result<Option<Context>> {
+        let slot = self.slot(id);
+        let mut logged = slot.lock().await;
+        let after = snapshot
+            .as_ref()
+            .map_or(0, |snapshot| snapshot.metadata.event_seq);
+        let records = self.events.read(id, after).await?;
+        let since_snapshot = records.len();
+        let context = replay(id, snapshot, records);
+        *logged = context.clone().map(|context| Logged {
+            context,
+            since_snapshot,
+        });
+        drop(logged);
+        Ok(context)
+    }
+
+    /// Like [`rebuild`](Self::rebuild), for reads that will not be persisted
+    ///
+    /// The bookkeeping for the next [`persist`](Self::persist) is left as is.
+    pub(crate) async fn view(
+        &self,
+        id: &str,
+        snapshot: Option<Context>,
+    ) -> Result<Option<Context>> {
+        let after = snapshot
+            .as_ref()
+            .map_or(0, |snapshot| snapshot.metadata.event_seq);
+        let records = self.events.read(id, after).await?;
+        Ok(replay(id, snapshot, records))
+    }
+
+    /// Log the changes since the context was last persisted, writing a snapshot when due
+    pub(crate) async fn persist(
+        &self,
+        store: &dyn ContextStore,
+        id: &str,
+        mut context: Context,
+        ttl: Duration,
+    ) -> Result<()> {
+        let slot = self.slot(id);
+        let mut logged = slot.lock().await;
+        let (events, seq, since_snapshot) = match logged.as_ref() {
+            Some(previous) => (
+                ContextEvent::diff(&previous.context, &context),
+                previous.context.metadata.event_seq,
+                previous.since_snapshot,
+            ),
+            None => (
+                vec![ContextEvent::Replaced {
+                    context: Box::new(context.clone()),
+                }],
+                context.metadata.event_seq,
+                0,
+            ),
+        };
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let at = Utc::now();
+        let records: Vec<ContextEventRecord> = (seq + 1..)
+            .zip(events)
+            .map(|(seq, event)| ContextEventRecord { seq, at, event })
+            .collect();
+        self.events.append(id, &records).await?;
+        context.metadata.event_seq = records.last().map_or(seq, |record| record.seq);
+
+        // The snapshot's stored TTL must outlast the context
+        let expiry_unchanged = matches!(
+            logged.as_ref(),
+            Some(previous) if previous.context.expires_at(ttl) == context.expires_at(ttl)
+        );
+        let mut since_snapshot = since_snapshot + records.len();
+        if !expiry_unchanged || since_snapshot >= self.snapshot_interval {
+            store.set(id, context.clone(), ttl).await?;
+            since_snapshot = 0;
+        }
+        *logged = Some(Logged {
+            context,
+            since_snapshot,
+        });
+        drop(logged);
+        Ok(())
+    }
+
+    /// A context's full log
+    pub(crate) async fn records(&self, id: &str) -> Result<Vec<ContextEventRecord>> {
+        self.events.read(id, 0).await
+    }
+
+    /// Drop a context's log
+    pub(crate) async fn forget(&self, id: &str) -> Result<()> {
+        self.logged.remove(id);
+        self.events.delete(id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{Message, Response};
+
+    fn kinds(events: &[ContextEvent]) -> Vec<&'static str> {
+        events
+            .iter()
+            .map(|event| match event {
+                ContextEvent::MessageAdded { .. } => "message_added",
+                ContextEvent::ResponseAdded { .. } => "response_added",
+                ContextEvent::VariableSet { .. } => "variable_set",
+                ContextEvent::VariableRemoved { .. } => "variable_removed",
+                ContextEvent::HistoryTrimmed { .. } => "history_trimmed",
+                ContextEvent::MetadataUpdated { .. } => "metadata_updated",
+                ContextEvent::UserUpdated { .. } => "user_updated",
+                ContextEvent::Replaced { .. } => "replaced",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_appends_and_rebuilds() {
+        let mut before = Context::new("conv");
+        before.add_message(&Message::text("a".repeat(400)));
+        before.add_message(&Message::text("Hi"));
+        before.set_variable("stale", serde_json::json!(1));
+
+        let mut after = before.clone();
+        after.add_message(&Message::text("What is the plan?"));
+        after.add_response(&Response::text("conv", "Ship on Friday."));
+        after.set_variable("topic", serde_json::json!("launch"));
+        after.variables.remove("stale");
+        after.trim_to_token_limit(50);
+
+        let events = ContextEvent::diff(&before, &after);
+        assert_eq!(
+            kinds(&events),
+            [
+                "history_trimmed",
+                "message_added",
+                "response_added",
+                "variable_set",
+                "variable_removed",
+                "metadata_updated",
+            ]
+        );
+
+        let mut rebuilt = before.clone();
+        for event in events {
+            event.apply(&mut rebuilt);
+        }
+        assert_eq!(json(&rebuilt), json(&after));
+        assert!(ContextEvent::diff(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn test_diff_replaces_other_changes() {
+        let mut before = Context::new("conv");
+        before.add_message(&Message::text("first"));
+        before.add_response(&Response::text("conv", "one"));
+
+        let mut after = before.clone();
+        after.undo_last_exchange();
+        after.add_message(&Message::text("second"));
+        assert_eq!(kinds(&ContextEvent::diff(&before, &after)), ["replaced"]);
+    }
+
+    #[test]
+    fn test_replay_from_log() {
+        let mut context = Context::new("conv");
+        let mut records = vec![ContextEventRecord {
+            seq: 1,
+            at: Utc::now(),
+            event: ContextEvent::Replaced {
+                context: Box::new(context.clone()),
+            },
+        }];
+        let before = context.clone();
+        context.add_message(&Message::text("hello"));
+        records.extend(
+            ContextEvent::diff(&before, &context)
+                .into_iter()
+                .zip(2..)
+                .map(|(event, seq)| ContextEventRecord {
+                    seq,
+                    at: Utc::now(),
+                    event,
+                }),
+        );
+
+        let rebuilt = replay("conv", None, records.clone()).unwrap();
+        assert_eq!(rebuilt.history.len(), 1);
+        assert_eq!(rebuilt.metadata.event_seq, 3);
+        let initial = replay("conv", None, records.into_iter().take(1)).unwrap();
+        assert!(initial.history.is_empty());
+        assert!(replay("conv", None, Vec::new()).is_none());
+    }
+}
@@ -0,0 +1,398 @@
+//! JSON Schema checks for values crossing a trust boundary
+//!
+//! Covers the subset of JSON Schema that plugins and derived schemas use:
+//! `type`, `enum`, `const`, `properties`, `required`,
+//! `additionalProperties`, `items`, `anyOf`, `oneOf`, length, size and
+//! range limits, and local `$ref`s into `definitions` or `$defs`. Other
+//! keywords are ignored, so a schema never rejects more than it says.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Where and why a value does not match its schema
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaViolation {
+    /// JSON Pointer to the offending value, e.g. `/items/0/id`; empty for the root
+    pub path: String,
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{path}: {}", self.message)
+    }
+}
+
+/// Check `value` against `schema`
+///
+/// # Errors
+///
+/// Returns the first violation found, depth first, or a violation naming a
+/// `$ref` that leads back to itself without descending into the value.
+pub fn validate(schema: &Value, value: &Value) -> Result<(), SchemaViolation> {
+    check(schema, schema, value, "", &[])
+}
+
+fn violation(path: &str, message: impl Into<String>) -> SchemaViolation {
+    SchemaViolation {
+        path: path.to_string(),
+        message: message.into(),
+    }
+}
+
+/// `refs` are the references followed to reach `schema` from the schema
+/// applied to `value`; following one again would never end.
+fn check(
+    root: &Value,
+    schema: &Value,
+    value: &Value,
+    path: &str,
+    refs: &[&str],
+) -> Result<(), SchemaViolation> {
+    let schema = match schema {
+        Value::Bool(false) => return Err(violation(path, "no value is allowed here")),
+        Value::Object(schema) => schema,
+        _ => return Ok(()),
+    };
+
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        if refs.contains(&reference) {
+            return Err(violation(
+                path,
+                format!("$ref {reference} refers to itself"),
+            ));
+        }
+        let target = reference
+            .strip_prefix('#')
+            .and_then(|pointer| root.pointer(pointer))
+            .ok_or_else(|| violation(path, format!("unresolvable $ref {reference}")))?;
+        check(root, target, value, path, &[refs, &[reference]].concat())?;
+    }
+
+    if let Some(expected) = schema.get("type") {
+        let matches = match expected {
+            Value::String(name) => is_type(value, name),
+            Value::Array(names) => names
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|name| is_type(value, name)),
+            _ => true,
+        };
+        if !matches {
+            return Err(violation(
+                path,
+                format!("expected {expected}, found {}", type_name(value)),
+            ));
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            return Err(violation(
+                path,
+                format!("{value} is not one of {}", Value::from(allowed.clone())),
+            ));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            return Err(violation(
+                path,
+                format!("expected {expected}, found {value}"),
+            ));
+        }
+    }
+
+    check_alternatives(root, schema, value, path, refs)?;
+
+    match value {
+        Value::Object(object) => check_object(root, schema, object, path),
+        Value::Array(items) => {
+            check_limit(schema, "minItems", "maxItems", items.len(), "items", path)?;
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(root, item_schema, item, &format!("{path}/{i}"), &[])?;
+                }
+            }
+            Ok(())
+        }
+        Value::String(text) => check_limit(
+            schema,
+            "minLength",
+            "maxLength",
+            text.chars().count(),
+            "characters",
+            path,
+        ),
+        Value::Number(number) => check_range(schema, number.as_f64().unwrap_or_default(), path),
+        Value::Null | Value::Bool(_) => Ok(()),
+    }
+}
+
+/// `anyOf` needs at least one matching schema, `oneOf` exactly one
+fn check_alternatives(
+    root: &Value,
+    schema: &serde_json::Map<String, Value>,
+    value: &Value,
+    path: &str,
+    refs: &[&str],
+) -> Result<(), SchemaViolation> {
+    let matching = |options: &[Value]| {
+        options
+            .iter()
+            .filter(|option| check(root, option, value, path, refs).is_ok())
+            .count()
+    };
+    if let Some(options) = schema.get("anyOf").and_then(Value::as_array) {
+        if matching(options) == 0 {
+            return Err(violation(path, "matches none of the anyOf schemas"));
+        }
+    }
+    if let Some(options) = schema.get("oneOf").and_then(Value::as_array) {
+        match matching(options) {
+            1 => {}
+            0 => return Err(violation(path, "matches none of the oneOf schemas")),
+            n => {
+                return Err(violation(
+                    path,
+                    format!("matches {n} of the oneOf schemas, not exactly one"),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_object(
+    root: &Value,
+    schema: &serde_json::Map<String, Value>,
+    object: &serde_json::Map<String, Value>,
+    path: &str,
+) -> Result<(), SchemaViolation> {
+    if let Some(required) = schema.get("required").and_then(Value::as_array) {
+        if let Some(missing) = required
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|field| !object.contains_key(*field))
+        {
+            return Err(violation(
+                path,
+                format!("missing required field `{missing}`"),
+            ));
+        }
+    }
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (field, value) in object {
+        let field_path = format!("{path}/{}", field.replace('~', "~0").replace('/', "~1"));
+        match properties.and_then(|properties| properties.get(field)) {
+            Some(field_schema) => check(root, field_schema, value, &field_path, &[])?,
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    return Err(violation(&field_path, "unexpected field"));
+                }
+                Some(additional) => check(root, additional, value, &field_path, &[])?,
+                None => {}
+            },
+        }
+    }
+    Ok(())
+}
+
+fn check_limit(
+    schema: &serde_json::Map<String, Value>,
+    min_keyword: &str,
+    max_keyword: &str,
+    len: usize,
+    unit: &str,
+    path: &str,
+) -> Result<(), SchemaViolation> {
+    let limit = |keyword| {
+        schema
+            .get(keyword)
+            .and_then(Value::as_u64)
+            .and_then(|limit| usize::try_from(limit).ok())
+    };
+    if let Some(min) = limit(min_keyword).filter(|min| len < *min) {
+        return Err(violation(path, format!("{len} {unit}, fewer than {min}")));
+    }
+    if let Some(max) = limit(max_keyword).filter(|max| len > *max) {
+        return Err(violation(path, format!("{len} {unit}, more than {max}")));
+    }
+    Ok(())
+}
+
+fn check_range(
+    schema: &serde_json::Map<String, Value>,
+    number: f64,
+    path: &str,
+) -> Result<(), SchemaViolation> {
+    if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64) {
+        if number < minimum {
+            return Err(violation(
+                path,
+                format!("{number} is below the minimum {minimum}"),
+            ));
+        }
+    }
+    if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64) {
+        if number > maximum {
+            return Err(violation(
+                path,
+                format!("{number} is above the maximum {maximum}"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn is_type(value: &Value, name: &str) -> bool {
+    match name {
+        "integer" => value.as_i64().is_some() || value.as_u64().is_some(),
+        "number" => value.is_number(),
+        other => type_name(value) == other,
+    }
+}
+
+const fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_violations_name_the_offending_path() {
+        let schema = json!({
+            "type": "object",
+            "required": ["items"],
+            "properties": {
+                "items": { "type": "array", "items": { "$ref": "#/definitions/Item" } }
+            },
+            "definitions": {
+                "Item": {
+                    "type": "object",
+                    "required": ["id"],
+                    "properties": {
+                        "id": { "type": "integer", "minimum": 1 },
+                        "state": { "enum": ["open", "closed"] }
+                    },
+                    "additionalProperties": false
+                }
+            }
+        });
+
+        assert!(validate(&schema, &json!({"items": [{"id": 1, "state": "open"}]})).is_ok());
+
+        let wrong_type = validate(&schema, &json!({"items": [{"id": 1}, {"id": "2"}]}));
+        assert_eq!(wrong_type.unwrap_err().path, "/items/1/id");
+
+        let extra = validate(&schema, &json!({"items": [{"id": 1, "owner": "me"}]}));
+        assert_eq!(
+            extra.unwrap_err().to_string(),
+            "/items/0/owner: unexpected field"
+        );
+
+        let missing = validate(&schema, &json!({})).unwrap_err();
+        assert_eq!(missing.to_string(), "/: missing required field `items`");
+    }
+
+    #[test]
+    fn test_one_of_requires_exactly_one_match() {
+        let schema = json!({
+            "oneOf": [
+                { "type": "integer" },
+                { "type": "number", "minimum": 10 }
+            ]
+        });
+
+        assert!(validate(&schema, &json!(3)).is_ok());
+        assert!(validate(&schema, &json!(10.5)).is_ok());
+        assert_eq!(
+            validate(&schema, &json!(12)).unwrap_err().message,
+            "matches 2 of the oneOf schemas, not exactly one"
+        );
+        assert!(validate(&schema, &json!("12")).is_err());
+    }
+
+    #[test]
+    fn test_const_enum_and_bounds() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "kind": { "const": "ticket" },
+                "priority": { "enum": ["low", "high"] },
+                "score": { "type": "number", "minimum": 0, "maximum": 1 },
+                "title": { "type": "string", "minLength": 1, "maxLength": 5 },
+                "tags": { "type": "array", "maxItems": 1 }
+            }
+        });
+
+        let valid = json!({"kind": "ticket", "priority": "low", "score": 1, "title": "Bug"});
+        assert!(validate(&schema, &valid).is_ok());
+        for (invalid, path) in [
+            (json!({"kind": "task"}), "/kind"),
+            (json!({"priority": "urgent"}), "/priority"),
+            (json!({"score": -0.5}), "/score"),
+            (json!({"score": 1.5}), "/score"),
+            (json!({"title": ""}), "/title"),
+            (json!({"title": "Broken"}), "/title"),
+            (json!({"tags": ["a", "b"]}), "/tags"),
+        ] {
+            assert_eq!(validate(&schema, &invalid).unwrap_err().path, path);
+        }
+    }
+
+    #[test]
+    fn test_ref_cycles_are_violations() {
+        let root = json!({ "$ref": "#" });
+        let error = validate(&root, &json!(1)).unwrap_err();
+        assert_eq!(error.message, "$ref # refers to itself");
+
+        let looped = json!({
+            "$ref": "#/definitions/A",
+            "definitions": {
+                "A": { "$ref": "#/definitions/B" },
+                "B": { "anyOf": [{ "$ref": "#/definitions/A" }] }
+            }
+        });
+        assert!(validate(&looped, &json!(1)).is_err());
+
+        let tree = json!({
+            "$ref": "#/definitions/Node",
+            "definitions": {
+                "Node": {
+                    "type": "object",
+                    "properties": {
+                        "children": { "type": "array", "items": { "$ref": "#/definitions/Node" } }
+                    }
+                }
+            }
+        });
+        let nested = json!({"children": [{"children": [{"children": []}]}]});
+        assert!(validate(&tree, &nested).is_ok());
+        assert_eq!(
+            validate(&tree, &json!({"children": [{"children": [1]}]}))
+                .unwrap_err()
+                .path,
+            "/children/0/children/0"
+        );
+    }
+}
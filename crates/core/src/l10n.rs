@@ -0,0 +1,508 @@
+//! Localization of user-facing text
+//!
+//! Each user's locale and time zone live in [`UserContext`]. A [`Localizer`]
+//! resolves them into a [`Locale`] and uses it to pick a Fluent message
+//! catalog, format dates in the user's local time, and format numbers with
+//! the right separators. Templates rendered through [`Localizer::render`]
+//! get the same treatment for their variables.
+//!
+//! Available with the `l10n` feature.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Timelike, Utc};
+use chrono_tz::Tz;
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource, FluentValue};
+use unic_langid::LanguageIdentifier;
+
+use crate::context::UserContext;
+use crate::error::{Error, Result};
+use crate::prompt;
+
+/// Locale used when a user has none, or an unparseable one
+pub const DEFAULT_LOCALE: &str = "en-US";
+
+/// Messages shipped with the crate; deployments can add or override them
+const BUILTIN_CATALOGS: &[(&str, &str)] = &[
+    (
+        "en",
+        "greeting-morning = Good morning\n\
+         greeting-afternoon = Good afternoon\n\
+         greeting-evening = Good evening\n\
+         greeting-night = Hello\n",
+    ),
+    (
+        "de",
+        "greeting-morning = Guten Morgen\n\
+         greeting-afternoon = Guten Tag\n\
+         greeting-evening = Guten Abend\n\
+         greeting-night = Hallo\n",
+    ),
+    (
+        "es",
+        "greeting-morning = Buenos días\n\
+         greeting-afternoon = Buenas tardes\n\
+         greeting-evening = Buenas noches\n\
+         greeting-night = Hola\n",
+    ),
+    (
+        "fr",
+        "greeting-morning = Bonjour\n\
+         greeting-afternoon = Bonjour\n\
+         greeting-evening = Bonsoir\n\
+         greeting-night = Bonsoir\n",
+    ),
+];
+
+/// A resolved language and time zone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    /// Language identifier
+    pub language: LanguageIdentifier,
+    /// Time zone for displaying dates
+    pub timezone: Tz,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self {
+            language: DEFAULT_LOCALE.parse().unwrap_or_default(),
+            timezone: Tz::UTC,
+        }
+    }
+}
+
+impl Locale {
+    /// Parse a language tag and time zone name
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if either value is not recognized.
+    pub fn new(language: &str, timezone: &str) -> Result<Self> {
+        Ok(Self {
+            language: language
+                .parse()
+                .map_err(|e| Error::InvalidInput(format!("Invalid locale {language}: {e}")))?,
+            timezone: timezone
+                .parse()
+                .map_err(|e| Error::InvalidInput(format!("Invalid time zone {timezone}: {e}")))?,
+        })
+    }
+
+    /// Resolve a user's settings, falling back to defaults for missing or
+    /// invalid values
+    #[must_use]
+    pub fn for_user(user: &UserContext) -> Self {
+        let default = Self::default();
+        Self {
+            language: user
+                .locale
+                .as_deref()
+                .and_then(|tag| tag.parse().ok())
+                .unwrap_or(default.language),
+            timezone: user
+                .timezone
+                .as_deref()
+                .and_then(|tz| tz.parse().ok())
+                .unwrap_or(default.timezone),
+        }
+    }
+
+    fn lang(&self) -> &str {
+        self.language.language.as_str()
+    }
+
+    fn region(&self) -> Option<&str> {
+        self.language
+            .region
+            .as_ref()
+            .map(unic_langid::subtags::Region::as_str)
+    }
+
+    /// Decimal and grouping separators
+    fn separators(&self) -> (char, char) {
+        match self.lang() {
+            "de" | "es" | "it" | "nl" | "pt" | "id" | "tr" => (',', '.'),
+            "fr" | "pl" | "cs" | "sv" | "nb" | "fi" | "ru" | "uk" => (',', '\u{202f}'),
+            _ => ('.', ','),
+        }
+    }
+
+    fn date_pattern(&self) -> &'static str {
+        match (self.lang(), self.region()) {
+            ("en", Some("US") | None) => "%m/%d/%Y %-I:%M %p",
+            ("en" | "es" | "fr" | "it" | "pt", _) => "%d/%m/%Y %H:%M",
+            ("de" | "pl" | "ru" | "cs" | "fi" | "nb", _) => "%d.%m.%Y %H:%M",
+            _ => "%Y-%m-%d %H:%M",
+        }
+    }
+
+    /// Format a timestamp in this locale's time zone and date order
+    #[must_use]
+    pub fn format_datetime(&self, at: DateTime<Utc>) -> String {
+        at.with_timezone(&self.timezone)
+            .format(self.date_pattern())
+            .to_string()
+    }
+
+    /// Format a number with this locale's separators and fixed decimals
+    #[must_use]
+    pub fn format_number(&self, value: f64, decimals: usize) -> String {
+        let (decimal, group) = self.separators();
+        let fixed = format!("{:.*}", decimals, value.abs());
+        let (int_part, frac_part) = fixed.split_once('.').unwrap_or((&fixed, ""));
+
+        let mut out = String::with_capacity(fixed.len() + int_part.len() / 3 + 1);
+        if value.is_sign_negative() && fixed.bytes().any(|b| b.is_ascii_digit() && b != b'0') {
+            out.push('-');
+        }
+        for (i, digit) in int_part.chars().enumerate() {
+            if i > 0 && (int_part.len() - i) % 3 == 0 {
+                out.push(group);
+            }
+            out.push(digit);
+        }
+        if !frac_part.is_empty() {
+            out.push(decimal);
+            out.push_str(frac_part);
+        }
+        out
+    }
+}
+
+/// Time-of-day bucket used for greetings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DayPeriod {
+    /// 05:00 to 11:59
+    Morning,
+    /// 12:00 to 16:59
+    Afternoon,
+    /// 17:00 to 21:59
+    Evening,
+    /// 22:00 to 04:59
+    Night,
+}
+
+impl DayPeriod {
+    /// The period containing `at` in the locale's time zone
+    #[must_use]
+    pub fn at(at: DateTime<Utc>, locale: &Locale) -> Self {
+        match at.with_timezone(&locale.timezone).hour() {
+            5..=11 => Self::Morning,
+            12..=16 => Self::Afternoon,
+            17..=21 => Self::Evening,
+            _ => Self::Night,
+        }
+    }
+
+    const fn message_id(self) -> &'static str {
+        match self {
+            Self::Morning => "greeting-morning",
+            Self::Afternoon => "greeting-afternoon",
+            Self::Evening => "greeting-evening",
+            Self::Night => "greeting-night",
+        }
+    }
+}
+
+/// Fluent message catalogs keyed by language
+pub struct Localizer {
+    bundles: HashMap<LanguageIdentifier, FluentBundle<FluentResource>>,
+    fallback: LanguageIdentifier,
+}
+
+impl std::fmt::Debug for Localizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Localizer")
+            .field("languages", &self.bundles.keys().collect::<Vec<_>>())
+            .field("fallback", &self.fallback)
+            .finish()
+    }
+}
+
+impl Default for Localizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Localizer {
+    /// Create a localizer with the built-in catalogs, falling back to English
+    ///
+    /// # Panics
+    ///
+    /// Panics if a built-in catalog fails to parse, which the test suite
+    /// rules out.
+    #[must_use]
+    pub fn new() -> Self {
+        let mut localizer = Self {
+            bundles: HashMap::new(),
+            fallback: "en".parse().unwrap_or_default(),
+        };
+        for (language, source) in BUILTIN_CATALOGS {
+            localizer
+                .add_catalog(language, source)
+                .expect("built-in catalogs are valid");
+        }
+        localizer
+    }
+
+    /// Add Fluent (`.ftl`) messages for a language
+    ///
+    /// Messages for a language already present are added to its catalog;
+    /// redefining an existing message replaces it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the tag or the FTL source is invalid.
+    pub fn add_catalog(&mut self, language: &str, source: &str) -> Result<()> {
+        let langid: LanguageIdentifier = language
+            .parse()
+            .map_err(|e| Error::InvalidInput(format!("Invalid locale {language}: {e}")))?;
+        let resource = FluentResource::try_new(source.to_string()).map_err(|(_, errors)| {
+            Error::InvalidInput(format!("Invalid catalog for {language}: {errors:?}"))
+        })?;
+
+        let bundle = self.bundles.entry(langid.clone()).or_insert_with(|| {
+            let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+            bundle.set_use_isolating(false);
+            bundle
+        });
+        bundle.add_resource_overriding(resource);
+        Ok(())
+    }
+
+    /// Bundle for the closest available language: exact tag, then bare
+    /// language, then the fallback
+    fn bundle(&self, language: &LanguageIdentifier) -> Option<&FluentBundle<FluentResource>> {
+        self.bundles
+            .get(language)
+            .or_else(|| {
+                self.bundles
+                    .iter()
+                    .find(|(id, _)| id.language == language.language && id.region.is_none())
+                    .map(|(_, bundle)| bundle)
+            })
+            .or_else(|| {
+                self.bundles
+                    .iter()
+                    .find(|(id, _)| id.language == language.language)
+                    .map(|(_, bundle)| bundle)
+            })
+            .or_else(|| self.bundles.get(&self.fallback))
+    }
+
+    /// Format a catalog message
+    ///
+    /// Numeric arguments are passed to Fluent as numbers so plural rules
+    /// apply; everything else is passed as text.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if no catalog defines `id`, or
+    /// [`Error::InvalidInput`] if formatting reports errors.
+    pub fn message(
+        &self,
+        locale: &Locale,
+        id: &str,
+        args: &HashMap<String, serde_json::Value>,
+    ) -> Result<String> {
+        let found = self
+            .bundle(&locale.language)
+            .and_then(|bundle| bundle.get_message(id).map(|m| (bundle, m)))
+            .or_else(|| {
+                let bundle = self.bundles.get(&self.fallback)?;
+                bundle.get_message(id).map(|m| (bundle, m))
+            });
+        let (bundle, message) =
+            found.ok_or_else(|| Error::NotFound(format!("Localized message {id}")))?;
+        let pattern = message
+            .value()
+            .ok_or_else(|| Error::NotFound(format!("Localized message {id} has no value")))?;
+
+        let mut fluent_args = FluentArgs::new();
+        for (key, value) in args {
+            let value = match value {
+                serde_json::Value::Number(n) => FluentValue::from(n.as_f64().unwrap_or_default()),
+                serde_json::Value::String(s) => FluentValue::from(s.clone()),
+                other => FluentValue::from(other.to_string()),
+            };
+            fluent_args.set(key.clone(), value);
+        }
+
+        let mut errors = Vec::new();
+        let text = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+        if !errors.is_empty() {
+            return Err(Error::InvalidInput(format!(
+                "Failed to format {id}: {errors:?}"
+            )));
+        }
+        Ok(text.into_owned())
+    }
+
+    /// Greeting appropriate for the current time of day in the user's zone
+    #[must_use]
+    pub fn greeting(&self, locale: &Locale, now: DateTime<Utc>) -> String {
+        let id = DayPeriod::at(now, locale).message_id();
+        self.message(locale, id, &HashMap::new())
+            .unwrap_or_else(|_| "Hello".to_string())
+    }
+
+    /// Interpolate a `{{name}}` template with localized variable values
+    ///
+    /// Numbers are formatted with the locale's separators (integers without
+    /// decimals, others with two), and RFC 3339 timestamps are converted to
+    /// the user's time zone and date format. See [`prompt::interpolate`] for
+    /// the template syntax.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`prompt::interpolate`].
+    pub fn render(
+        &self,
+        locale: &Locale,
+        template: &str,
+        vars: &HashMap<String, serde_json::Value>,
+    ) -> Result<String> {
+        let localized: HashMap<String, serde_json::Value> = vars
+            .iter()
+            .map(|(key, value)| (key.clone(), localize_value(locale, value)))
+            .collect();
+        prompt::interpolate(template, &localized)
+    }
+}
+
+fn localize_value(locale: &Locale, value: &serde_json::Value) -> serde_json::Value {
+    let text = match value {
+        serde_json::Value::Number(n) if n.is_f64() => {
+            locale.format_number(n.as_f64().unwrap_or_default(), 2)
+        }
+        serde_json::Value::Number(n) => locale.format_number(n.as_f64().unwrap_or_default(), 0),
+        serde_json::Value::String(s) => match DateTime::parse_from_rfc3339(s) {
+            Ok(at) => locale.format_datetime(at.with_timezone(&Utc)),
+            Err(_) => return value.clone(),
+        },
+        _ => return value.clone(),
+    };
+    serde_json::Value::String(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn locale(language: &str, timezone: &str) -> Locale {
+        Locale::new(language, timezone).unwrap()
+    }
+
+    #[test]
+    fn test_locale_for_user_falls_back() {
+        let user = UserContext {
+            locale: Some("de-DE".to_string()),
+            timezone: Some("Not/AZone".to_string()),
+            ..UserContext::default()
+        };
+        let resolved = Locale::for_user(&user);
+        assert_eq!(resolved.language.to_string(), "de-DE");
+        assert_eq!(resolved.timezone, Tz::UTC);
+
+        assert_eq!(Locale::for_user(&UserContext::default()), Locale::default());
+    }
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(
+            locale("en-US", "UTC").format_number(1_234_567.891, 2),
+            "1,234,567.89"
+        );
+        assert_eq!(locale("de-DE", "UTC").format_number(1_234.5, 1), "1.234,5");
+        assert_eq!(
+            locale("fr-FR", "UTC").format_number(-1_000.0, 0),
+            "-1\u{202f}000"
+        );
+        assert_eq!(locale("en-US", "UTC").format_number(-0.001, 2), "0.00");
+        assert_eq!(locale("en-US", "UTC").format_number(999.0, 0), "999");
+    }
+
+    #[test]
+    fn test_format_datetime_uses_timezone() {
+        let at = Utc.with_ymd_and_hms(2025, 3, 14, 23, 30, 0).unwrap();
+        assert_eq!(
+            locale("en-US", "America/New_York").format_datetime(at),
+            "03/14/2025 7:30 PM"
+        );
+        assert_eq!(
+            locale("de-DE", "Europe/Berlin").format_datetime(at),
+            "15.03.2025 00:30"
+        );
+    }
+
+    #[test]
+    fn test_greeting_by_local_time() {
+        let localizer = Localizer::new();
+        let at = Utc.with_ymd_and_hms(2025, 3, 14, 8, 0, 0).unwrap();
+
+        assert_eq!(
+            localizer.greeting(&locale("en-GB", "Europe/London"), at),
+            "Good morning"
+        );
+        assert_eq!(
+            localizer.greeting(&locale("es-MX", "America/Mexico_City"), at),
+            "Hola"
+        );
+        assert_eq!(
+            localizer.greeting(&locale("de-AT", "Asia/Tokyo"), at),
+            "Guten Abend"
+        );
+        assert_eq!(
+            localizer.greeting(&locale("ja-JP", "UTC"), at),
+            "Good morning"
+        );
+    }
+
+    #[test]
+    fn test_custom_catalog_with_plurals() {
+        let mut localizer = Localizer::new();
+        localizer
+            .add_catalog(
+                "en",
+                "unread = { $count ->\n    [one] You have one message\n   *[other] You have { $count } messages\n}\n",
+            )
+            .unwrap();
+
+        let en = Locale::default();
+        let one = HashMap::from([("count".to_string(), serde_json::json!(1))]);
+        let many = HashMap::from([("count".to_string(), serde_json::json!(5))]);
+        assert_eq!(
+            localizer.message(&en, "unread", &one).unwrap(),
+            "You have one message"
+        );
+        assert_eq!(
+            localizer.message(&en, "unread", &many).unwrap(),
+            "You have 5 messages"
+        );
+        assert!(localizer.message(&en, "missing", &one).is_err());
+        assert!(localizer.add_catalog("en", "= broken").is_err());
+    }
+
+    #[test]
+    fn test_render_localizes_variables() {
+        let localizer = Localizer::new();
+        let vars = HashMap::from([
+            ("total".to_string(), serde_json::json!(1234.5)),
+            ("count".to_string(), serde_json::json!(1200)),
+            ("due".to_string(), serde_json::json!("2025-03-14T23:30:00Z")),
+        ]);
+
+        let text = localizer
+            .render(
+                &locale("de-DE", "Europe/Berlin"),
+                "{{count}} Artikel, {{total}} EUR, fällig {{due}}",
+                &vars,
+            )
+            .unwrap();
+        assert_eq!(text, "1.200 Artikel, 1.234,50 EUR, fällig 15.03.2025 00:30");
+    }
+}
@@ -20,6 +20,14 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! # API stability
+//!
+//! Public structs and enums that are likely to grow are `#[non_exhaustive]`:
+//! construct them through their constructors and `with_*` methods rather than
+//! struct literals, and include a wildcard arm when matching on them. Code
+//! that imports from [`v1`] keeps compiling across 1.x releases even if items
+//! move between modules.
 
 #![deny(missing_docs, rust_2018_idioms, clippy::all)]
 #![warn(clippy::pedantic, clippy::nursery)]
@@ -31,25 +39,216 @@
     clippy::struct_excessive_bools,
     clippy::cast_precision_loss,
     clippy::unnecessary_literal_bound,
-    clippy::significant_drop_in_scrutinee
+    clippy::significant_drop_in_scrutinee,
+    clippy::duration_suboptimal_units
 )]
 
+pub mod approval;
+#[cfg(feature = "bedrock")]
+pub mod bedrock;
 pub mod bot;
+pub mod botfile;
+pub mod branch;
+#[cfg(feature = "calendar")]
+pub mod calendar;
+pub mod citation;
+pub mod cluster;
+pub mod completion;
+pub mod compression;
 pub mod config;
 pub mod context;
+pub mod curation;
+pub mod degraded;
+pub mod diagnostics;
+pub mod diff;
+pub mod egress;
+pub mod email;
+pub mod embedding;
+pub mod encryption;
 pub mod error;
+pub mod expiry;
+pub mod extraction;
+pub mod feedback;
+pub mod fixture;
+pub mod github;
+pub mod governor;
+pub mod graph;
+pub mod inbound;
+pub mod ingest;
+pub mod introspection;
+pub mod irc;
+pub mod job;
+pub mod journal;
+pub mod json_schema;
+#[cfg(feature = "l10n")]
+pub mod l10n;
+pub mod matrix;
+pub mod memory;
 pub mod message;
+#[cfg(feature = "metrics-prometheus")]
+pub mod metrics;
+pub mod overflow;
 pub mod pipeline;
 pub mod plugin;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod preflight;
+pub mod prompt;
+pub mod prompt_registry;
+pub mod prompt_template;
+pub mod provider;
+pub mod provisioned;
+pub mod retrieval;
+pub mod review;
+pub mod sanitize;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod secrets;
+pub mod selection;
+pub mod session;
+pub mod shaping;
+pub mod shedding;
+pub mod slo;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod streaming;
+pub mod structured;
+pub mod teams;
+pub mod telemetry;
+pub mod template;
+pub mod templates;
+pub mod tickets;
+pub mod tools;
+pub mod trace_context;
+pub mod transcript;
+pub mod usage;
+pub mod user_error;
+pub mod vector;
+pub mod versioning;
+pub mod webfetch;
+pub mod webhook;
+pub mod whatsapp;
 
 // Re-exports
 pub use bot::{Bot, BotBuilder};
-pub use config::{BotConfig, BotConfigBuilder};
+pub use config::{BotConfig, BotConfigBuilder, ConfigProfile};
 pub use context::{Context, ContextManager, ContextStore};
 pub use error::{Error, Result};
-pub use message::{Message, MessageType, Response};
+pub use message::{Content, Message, MessageType, Response};
 pub use pipeline::{MessagePipeline, PipelineStage};
 pub use plugin::{Plugin, PluginRegistry};
+pub use preflight::{PreflightOptions, PreflightReport};
+pub use vector::{HnswIndex, MetadataFilter, VectorStore};
+
+/// The 1.x public API surface
+///
+/// Items re-exported here are covered by semver for the lifetime of the 1.x
+/// series. A future major version adds a `v2` module alongside this one, so
+/// downstream crates can migrate one import at a time.
+pub mod v1 {
+    pub use crate::bot::{Bot, BotBuilder, BotMetrics};
+    pub use crate::botfile::{Botfile, BotfileFormat, ChannelKind, ChannelSpec, PluginSpec};
+    pub use crate::branch::{BranchComparison, BranchSide, BranchVariant};
+    pub use crate::cluster::{LockBackend, MemoryLockBackend, RedisLockBackend, TaskSupervisor};
+    pub use crate::config::{
+        AlertSeverity, BotConfig, BotConfigBuilder, BudgetConfig, BudgetDowngradePolicy,
+        BurnRateAlert, ConfigProfile, ContextConfig, CostGovernorConfig, DegradedAction,
+        DegradedModeConfig, EncryptionConfig, EventLogConfig, ExpiryNotificationConfig,
+        InputOverflowConfig, LatencyObjective, LoadSheddingConfig, ModelSelectionConfig,
+        OverflowPolicy, PipelineConfig, PluginConfig, PromptTemplateConfig, ProvisionedModelConfig,
+        ProvisionedThroughputConfig, RequestPriority, RetrievalConfig, SessionConfig, SloConfig,
+        StorageBackend, TelemetryConfig, TraceSamplingConfig, UserErrorConfig, UserErrorMessage,
+    };
+    pub use crate::context::{Checkpoint, Context, ContextManager, ContextStore};
+    pub use crate::diff::{
+        DiffSegment, ResponseDiff, ResponseDiffer, SegmentStatus, SentenceChange,
+        RESPONSE_DIFF_METADATA_KEY,
+    };
+    pub use crate::embedding::{
+        CachedEmbedder, EmbeddingCacheStats, EmbeddingCacheStore, MemoryEmbeddingCache,
+    };
+    pub use crate::encryption::{ContextEncryption, DataKey, KeyProvider, LocalKeyProvider};
+    pub use crate::error::{Error, Result};
+    pub use crate::expiry::{ExpiryAction, ExpiryNotice, ExpiryNotifier, ExpiryWatcher};
+    pub use crate::extraction::{
+        Entity, EntityExtractor, EntityKind, ExtractStage, PromptEntityExtractor,
+        RuleEntityExtractor, ENTITIES_METADATA_KEY,
+    };
+    pub use crate::fixture::{
+        CaseResult, FixtureCase, FixtureDiff, FixtureExpectation, FixtureReport, FixtureRequest,
+        FixtureSuite, MockBroker, MockCall, MockReply,
+    };
+    pub use crate::governor::{BudgetScope, CostGovernor, SpendAlert, SpendAlertHook};
+    pub use crate::inbound::{
+        sign_inbound, InboundAuditEntry, InboundRejection, InboundStats, InboundVerifier,
+        INBOUND_SIGNATURE_HEADER,
+    };
+    pub use crate::introspection::{BotDescriptor, ModelRouting, PluginDescriptor, ToolDescriptor};
+    pub use crate::journal::{ContextEvent, ContextEventRecord, ContextEventStore};
+    pub use crate::json_schema::SchemaViolation;
+    pub use crate::message::{
+        Attachment, Content, CostBreakdown, Embed, EmbedField, Message, MessageFlags, MessageType,
+        Response, ResponseError, ResponseFlags, ResponseType, Suggestion, SuggestionAction,
+        TokenUsage, ToolCost,
+    };
+    pub use crate::overflow::{InputOverflow, OverflowReport, INPUT_OVERFLOW_METADATA_KEY};
+    pub use crate::pipeline::{
+        ExecutionTrace, MessagePipeline, PipelineStage, StageTrace, TraceSampler, TRACE_LOG_TARGET,
+        TRACE_METADATA_KEY,
+    };
+    pub use crate::plugin::{Plugin, PluginRegistry};
+    pub use crate::preflight::{PreflightOptions, PreflightReport};
+    pub use crate::prompt_registry::{
+        ExperimentArm, PromptMetrics, PromptRegistry, PROMPT_VERSION_METADATA_KEY,
+    };
+    pub use crate::prompt_template::PromptTemplate;
+    pub use crate::provider::{
+        Provider, ProviderMessage, ProviderRegistry, ProviderRequest, ProviderResponse,
+        PROVIDER_METADATA_KEY,
+    };
+    pub use crate::provisioned::{
+        Capacity, CapacityRoute, CommitmentUtilization, ProvisionedThroughputManager,
+    };
+    pub use crate::retrieval::{
+        RetrieveStage, RetrievedDocument, Retriever, RETRIEVED_DOCUMENTS_METADATA_KEY,
+    };
+    pub use crate::review::{
+        QualityBucket, ReviewItem, ReviewQueue, ReviewSampler, ReviewSamplingConfig, ReviewVerdict,
+        SampleReason, SamplingRule,
+    };
+    pub use crate::selection::{
+        BudgetDowngrade, ModelPin, ModelSelection, ModelSource, BUDGET_DOWNGRADE_METADATA_KEY,
+        BUDGET_PRESSURE_METADATA_KEY,
+    };
+    pub use crate::session::{
+        MemorySessionStore, Session, SessionEndReason, SessionManager, SessionRecord, SessionStore,
+        SESSION_VARIABLE,
+    };
+    pub use crate::shaping::{Delivery, Rate, RateShape, RateShaper};
+    pub use crate::shedding::{
+        LoadPermit, LoadShedder, Overloaded, PRIORITY_METADATA_KEY, SHED_METADATA_KEY,
+    };
+    pub use crate::slo::{
+        AlertState, LatencyHistogram, SloAlert, SloAlertHook, SloStatus, SloTracker,
+    };
+    pub use crate::streaming::{ChunkSink, StreamChunk, STREAM_BUFFER};
+    pub use crate::structured::{StructuredResponse, OUTPUT_SCHEMA_METADATA_KEY};
+    pub use crate::telemetry::{Feature, Telemetry, TelemetryReport, TelemetrySink};
+    pub use crate::template::{
+        ConversationTemplate, FLOWS_VARIABLE, SUGGESTED_PROMPTS_VARIABLE, SYSTEM_PROMPT_VARIABLE,
+        TEMPLATE_VARIABLE,
+    };
+    pub use crate::templates::{GeneratedTemplate, TemplateGenerator, YamlTemplate};
+    pub use crate::trace_context::{
+        TraceContext, TRACEPARENT_METADATA_KEY, TRACESTATE_METADATA_KEY,
+    };
+    pub use crate::transcript::{Transcript, TranscriptHeader, TranscriptTurn};
+    pub use crate::usage::{UsagePoint, UsageTimeline};
+    pub use crate::user_error::{ErrorCatalog, UserErrorKind, UserFacingError};
+    pub use crate::versioning::{
+        ConfigVersion, ConfigVersionRegistry, VersionMetrics, CONFIG_VERSION_METADATA_KEY,
+    };
+}
 
 /// Library version
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -57,6 +256,8 @@ pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 /// Initialize the library with default settings
 ///
 /// This function sets up logging, tracing, and other global configurations.
+/// With the `otel` feature and `OTEL_EXPORTER_OTLP_ENDPOINT` set, spans are
+/// also exported over OTLP, as with [`init_with_otlp`].
 ///
 /// # Errors
 ///
@@ -73,6 +274,11 @@ pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub fn init() -> Result<()> {
     use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+    #[cfg(feature = "otel")]
+    if let Some(config) = trace_context::OtlpConfig::from_env() {
+        return init_with_otlp(&config);
+    }
+
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
@@ -85,6 +291,44 @@ pub fn init() -> Result<()> {
     Ok(())
 }
 
+/// Initialize the library, exporting spans to an OTLP collector
+///
+/// Sets up logging like [`init`] and sends spans, including those continued
+/// from a message's [`trace_context`], to `config.endpoint`. Must be called
+/// within a Tokio runtime; call [`shutdown_tracing`] before exiting to flush
+/// pending spans.
+///
+/// # Errors
+///
+/// Returns an error if the exporter cannot be built or a global subscriber
+/// is already installed.
+#[cfg(feature = "otel")]
+pub fn init_with_otlp(config: &trace_context::OtlpConfig) -> Result<()> {
+    use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
+        )
+        .with(tracing_subscriber::fmt::layer())
+        .with(config.layer()?)
+        .try_init()
+        .map_err(|e| Error::Initialization(e.to_string()))?;
+
+    tracing::info!(
+        "Universal Bot Core v{} initialized, exporting spans to {}",
+        VERSION,
+        config.endpoint
+    );
+    Ok(())
+}
+
+/// Flush and stop OTLP span export
+#[cfg(feature = "otel")]
+pub fn shutdown_tracing() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
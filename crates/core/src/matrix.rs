@@ -0,0 +1,610 @@
+//! Matrix channel adapter
+//!
+//! Rooms map to conversations through a [`RoomMap`]. In direct chats every
+//! message is answered; in rooms with more members the bot only answers
+//! when mentioned, by `m.mentions`, user ID, or display name. Streamed
+//! output is shown by sending a message and then editing it in place
+//! (`m.replace`) as chunks arrive, at most once per edit interval. With a
+//! [`RateShape`], sends and edits are paced to the homeserver's limits and
+//! [`MatrixAdapter::send_chunk`] merges rapid chunks into edits.
+//!
+//! The protocol side is behind the [`MatrixSender`] trait. With the
+//! `matrix` feature, [`SdkClient`] implements it on `matrix-sdk` with
+//! end-to-end encryption: events in encrypted rooms are decrypted before
+//! they reach the adapter and replies are encrypted on send.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use futures::{Stream, StreamExt};
+use tracing::debug;
+
+use crate::{
+    bot::Bot,
+    message::{Message, MessageType, CHANNEL_METADATA_KEY},
+    shaping::{Delivery, RateShape, RateShaper},
+};
+
+/// Message metadata key recording whether the room is end-to-end encrypted
+pub const ENCRYPTED_METADATA_KEY: &str = "matrix_encrypted";
+
+/// Default minimum time between edits of a streamed message
+pub const DEFAULT_EDIT_INTERVAL: Duration = Duration::from_millis(750);
+
+/// A text message received in a room, already decrypted
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoomEvent {
+    /// Room ID, e.g. `!abc:example.org`
+    pub room_id: String,
+    /// Event ID
+    pub event_id: String,
+    /// Sender's user ID
+    pub sender: String,
+    /// Plain-text body
+    pub body: String,
+    /// Users listed in `m.mentions`
+    pub mentioned_user_ids: Vec<String>,
+    /// Joined members, including the bot
+    pub member_count: u64,
+    /// Whether the room is end-to-end encrypted
+    pub encrypted: bool,
+}
+
+/// Sends and edits messages in rooms
+#[async_trait]
+pub trait MatrixSender: Send + Sync {
+    /// Send a Markdown message, returning its event ID
+    async fn send(&self, room_id: &str, body: &str) -> Result<String>;
+
+    /// Replace the content of a message the bot sent
+    async fn edit(&self, room_id: &str, event_id: &str, body: &str) -> Result<()>;
+}
+
+/// Room to conversation mapping
+///
+/// Rooms map to `matrix:{room_id}` unless linked to another conversation,
+/// for example to share context between a room and its successor after a
+/// room upgrade.
+#[derive(Debug, Default)]
+pub struct RoomMap {
+    links: DashMap<String, String>,
+}
+
+impl RoomMap {
+    /// Create an empty mapping
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Conversation a room belongs to
+    #[must_use]
+    pub fn conversation_for(&self, room_id: &str) -> String {
+        self.links
+            .get(room_id)
+            .map_or_else(|| format!("matrix:{room_id}"), |entry| entry.clone())
+    }
+
+    /// Route a room into an existing conversation
+    pub fn link(&self, room_id: impl Into<String>, conversation_id: impl Into<String>) {
+        self.links.insert(room_id.into(), conversation_id.into());
+    }
+}
+
+/// Routes Matrix room messages through a [`Bot`]
+pub struct MatrixAdapter {
+    bot: Bot,
+    sender: Arc<dyn MatrixSender>,
+    rooms: RoomMap,
+    user_id: String,
+    display_name: Option<String>,
+    edit_interval: Duration,
+    shaper: Option<RateShaper>,
+}
+
+impl MatrixAdapter {
+    /// Create an adapter for the bot account `user_id`
+    #[must_use]
+    pub fn new(bot: Bot, sender: Arc<dyn MatrixSender>, user_id: impl Into<String>) -> Self {
+        Self {
+            bot,
+            sender,
+            rooms: RoomMap::new(),
+            user_id: user_id.into(),
+            display_name: None,
+            edit_interval: DEFAULT_EDIT_INTERVAL,
+            shaper: None,
+        }
+    }
+
+    /// Also treat the bot's display name as a mention
+    #[must_use]
+    pub fn with_display_name(mut self, display_name: impl Into<String>) -> Self {
+        self.display_name = Some(display_name.into());
+        self
+    }
+
+    /// Minimum time between edits of a streamed message
+    #[must_use]
+    pub const fn with_edit_interval(mut self, edit_interval: Duration) -> Self {
+        self.edit_interval = edit_interval;
+        self
+    }
+
+    /// Pace sends and edits to `shape`, queueing per room
+    #[must_use]
+    pub fn with_rate_shape(mut self, shape: RateShape) -> Self {
+        self.shaper = Some(RateShaper::new(shape));
+        self
+    }
+
+    /// Room to conversation mapping
+    #[must_use]
+    pub fn rooms(&self) -> &RoomMap {
+        &self.rooms
+    }
+
+    /// Whether the bot should answer an event
+    ///
+    /// The bot ignores its own messages, answers everything in direct
+    /// chats, and answers only mentions in larger rooms.
+    #[must_use]
+    pub fn should_respond(&self, event: &RoomEvent) -> bool {
+        if event.sender == self.user_id {
+            return false;
+        }
+        event.member_count <= 2 || self.is_mentioned(event)
+    }
+
+    fn is_mentioned(&self, event: &RoomEvent) -> bool {
+        if event.mentioned_user_ids.contains(&self.user_id) {
+            return true;
+        }
+        let body = event.body.to_lowercase();
+        body.contains(&self.user_id.to_lowercase())
+            || self
+                .display_name
+                .as_ref()
+                .is_some_and(|name| body.contains(&name.to_lowercase()))
+    }
+
+    /// Remove a leading mention of the bot, e.g. `Helper: ` or `@helper:example.org `
+    fn strip_mention<'a>(&self, body: &'a str) -> &'a str {
+        let names = std::iter::once(self.user_id.as_str()).chain(self.display_name.as_deref());
+        for name in names {
+            if let Some(prefix) = body.get(..name.len()) {
+                if prefix.eq_ignore_ascii_case(name) {
+                    return body[name.len()..].trim_start_matches([':', ',', ' ']);
+                }
+            }
+        }
+        body
+    }
+
+    /// Convert a room event into a bot message
+    #[must_use]
+    pub fn to_message(&self, event: &RoomEvent) -> Message {
+        let text = self.strip_mention(event.body.trim());
+        let message_type = if text.starts_with('/') {
+            MessageType::Command
+        } else {
+            MessageType::Text
+        };
+        Message::with_type(text, message_type)
+            .with_conversation_id(self.rooms.conversation_for(&event.room_id))
+            .with_user_id(event.sender.clone())
+            .with_metadata(CHANNEL_METADATA_KEY, serde_json::json!("matrix"))
+            .with_metadata(ENCRYPTED_METADATA_KEY, serde_json::json!(event.encrypted))
+    }
+
+    /// Answer a room event if it is addressed to the bot
+    ///
+    /// Returns the event ID of the reply, or `None` if the event was ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if processing fails or the reply cannot be sent.
+    pub async fn handle(&self, event: &RoomEvent) -> Result<Option<String>> {
+        if !self.should_respond(event) {
+            debug!(
+                "Ignoring {} in {}: not addressed to the bot",
+                event.event_id, event.room_id
+            );
+            return Ok(None);
+        }
+        let response = self.bot.process(self.to_message(event)).await?;
+        self.pace(&event.room_id).await;
+        let event_id = self.sender.send(&event.room_id, &response.content).await?;
+        Ok(Some(event_id))
+    }
+
+    /// Post part of a reply, returning the event ID of the message showing it
+    ///
+    /// Without a rate shape every chunk is its own message. With one, chunks
+    /// wait their turn, and chunks within the coalesce window of the last
+    /// are appended to it with an edit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if sending or editing fails.
+    pub async fn send_chunk(&self, room_id: &str, chunk: &str) -> Result<String> {
+        let Some(shaper) = &self.shaper else {
+            return self.sender.send(room_id, chunk).await;
+        };
+        shaper
+            .deliver(room_id, chunk, |delivery| async move {
+                match delivery {
+                    Delivery::Send { body } => self.sender.send(room_id, &body).await,
+                    Delivery::Edit { message_id, body } => {
+                        self.sender.edit(room_id, &message_id, &body).await?;
+                        Ok(message_id)
+                    }
+                }
+            })
+            .await
+    }
+
+    async fn pace(&self, room_id: &str) {
+        if let Some(shaper) = &self.shaper {
+            shaper.acquire(room_id).await;
+        }
+    }
+
+    /// Show streamed output as one message that is edited as chunks arrive
+    ///
+    /// The message is sent with the first chunk and edited at most once per
+    /// edit interval; a final edit carries the complete text. Returns the
+    /// complete text.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from the stream, or an error if sending or
+    /// editing fails. Text received before a stream error stays visible.
+    pub async fn stream_reply<S>(&self, room_id: &str, mut chunks: S) -> Result<String>
+    where
+        S: Stream<Item = Result<String>> + Unpin,
+    {
+        let mut text = String::new();
+        let mut shown = 0;
+        let mut message: Option<(String, Instant)> = None;
+
+        while let Some(chunk) = chunks.next().await {
+            text.push_str(&chunk?);
+            if text.trim().is_empty() {
+                continue;
+            }
+            match &mut message {
+                None => {
+                    self.pace(room_id).await;
+                    let event_id = self.sender.send(room_id, &text).await?;
+                    message = Some((event_id, Instant::now()));
+                    shown = text.len();
+                }
+                Some((event_id, last_edit)) if last_edit.elapsed() >= self.edit_interval => {
+                    self.pace(room_id).await;
+                    self.sender.edit(room_id, event_id, &text).await?;
+                    *last_edit = Instant::now();
+                    shown = text.len();
+                }
+                Some(_) => {}
+            }
+        }
+
+        if let Some((event_id, _)) = &message {
+            if shown < text.len() {
+                self.pace(room_id).await;
+                self.sender.edit(room_id, event_id, &text).await?;
+            }
+        }
+        Ok(text)
+    }
+}
+
+/// `matrix-sdk` client with end-to-end encryption
+///
+/// Encryption keys and sync state live in a `SQLite` store so the device
+/// keeps its identity across restarts; the device must be verified from
+/// another session before other users' clients share room keys with it.
+#[cfg(feature = "matrix")]
+pub struct SdkClient {
+    client: matrix_sdk::Client,
+}
+
+#[cfg(feature = "matrix")]
+impl SdkClient {
+    /// Log in with a password, persisting state under `store_path`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the homeserver is unreachable, the store cannot
+    /// be opened, or login fails.
+    pub async fn login(
+        homeserver_url: &str,
+        username: &str,
+        password: &str,
+        store_path: impl AsRef<std::path::Path>,
+    ) -> Result<Self> {
+        use crate::error::Error;
+
+        let client = matrix_sdk::Client::builder()
+            .homeserver_url(homeserver_url)
+            .sqlite_store(store_path, None)
+            .build()
+            .await
+            .map_err(|e| Error::Initialization(format!("Matrix client setup failed: {e}")))?;
+        client
+            .matrix_auth()
+            .login_username(username, password)
+            .initial_device_display_name("universal-bot")
+            .await
+            .map_err(|e| Error::Authentication(format!("Matrix login failed: {e}")))?;
+        Ok(Self { client })
+    }
+
+    /// The bot's user ID
+    #[must_use]
+    pub fn user_id(&self) -> Option<String> {
+        self.client.user_id().map(ToString::to_string)
+    }
+
+    /// Sync forever, passing room messages to the adapter
+    ///
+    /// Invitations are accepted automatically. Sync runs on a spawned task
+    /// and events are handled on the caller's task, one at a time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if syncing stops.
+    pub async fn run(&self, adapter: &MatrixAdapter) -> Result<()> {
+        use matrix_sdk::{
+            config::SyncSettings,
+            room::Room,
+            ruma::events::room::{
+                member::StrippedRoomMemberEvent,
+                message::{MessageType as RumaMessageType, OriginalSyncRoomMessageEvent},
+            },
+        };
+        use tracing::warn;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<RoomEvent>();
+        self.client
+            .add_event_handler(move |event: OriginalSyncRoomMessageEvent, room: Room| {
+                let tx = tx.clone();
+                async move {
+                    let RumaMessageType::Text(text) = event.content.msgtype else {
+                        return;
+                    };
+                    let mentioned_user_ids = event
+                        .content
+                        .mentions
+                        .map(|m| m.user_ids.iter().map(ToString::to_string).collect())
+                        .unwrap_or_default();
+                    let _ = tx.send(RoomEvent {
+                        room_id: room.room_id().to_string(),
+                        event_id: event.event_id.to_string(),
+                        sender: event.sender.to_string(),
+                        body: text.body,
+                        mentioned_user_ids,
+                        member_count: room.joined_members_count(),
+                        encrypted: room.is_encrypted().await.unwrap_or(false),
+                    });
+                }
+            });
+        self.client
+            .add_event_handler(|event: StrippedRoomMemberEvent, room: Room| async move {
+                if event.state_key == room.own_user_id() {
+                    if let Err(e) = room.join().await {
+                        warn!("Failed to join {}: {}", room.room_id(), e);
+                    }
+                }
+            });
+
+        let client = self.client.clone();
+        let sync = tokio::spawn(async move { client.sync(SyncSettings::default()).await });
+        while let Some(event) = rx.recv().await {
+            if let Err(e) = adapter.handle(&event).await {
+                warn!("Failed to answer Matrix event {}: {:#}", event.event_id, e);
+            }
+        }
+        sync.await?
+            .map_err(|e| crate::error::Error::Network(format!("Matrix sync stopped: {e}")))?;
+        Ok(())
+    }
+
+    fn room(&self, room_id: &str) -> Result<matrix_sdk::room::Room> {
+        use crate::error::Error;
+
+        let room_id = matrix_sdk::ruma::RoomId::parse(room_id)
+            .map_err(|e| Error::InvalidInput(format!("Invalid room ID {room_id}: {e}")))?;
+        self.client
+            .get_room(&room_id)
+            .ok_or_else(|| Error::NotFound(format!("Not a member of {room_id}")).into())
+    }
+}
+
+#[cfg(feature = "matrix")]
+#[async_trait]
+impl MatrixSender for SdkClient {
+    async fn send(&self, room_id: &str, body: &str) -> Result<String> {
+        use matrix_sdk::ruma::events::room::message::RoomMessageEventContent;
+
+        let response = self
+            .room(room_id)?
+            .send(RoomMessageEventContent::text_markdown(body))
+            .await
+            .map_err(|e| crate::error::Error::Network(format!("Matrix send failed: {e}")))?;
+        Ok(response.event_id.to_string())
+    }
+
+    async fn edit(&self, room_id: &str, event_id: &str, body: &str) -> Result<()> {
+        use matrix_sdk::ruma::{
+            events::{
+                relation::Replacement,
+                room::message::{Relation, RoomMessageEventContent},
+            },
+            EventId,
+        };
+
+        let event_id = EventId::parse(event_id).map_err(|e| {
+            crate::error::Error::InvalidInput(format!("Invalid event ID {event_id}: {e}"))
+        })?;
+        // Clients without edit support show the fallback body
+        let mut content = RoomMessageEventContent::text_markdown(format!("* {body}"));
+        content.relates_to = Some(Relation::Replacement(Replacement::new(
+            event_id,
+            RoomMessageEventContent::text_markdown(body).into(),
+        )));
+        self.room(room_id)?
+            .send(content)
+            .await
+            .map_err(|e| crate::error::Error::Network(format!("Matrix edit failed: {e}")))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BotConfig;
+    use parking_lot::Mutex;
+
+    #[derive(Default)]
+    struct RecordingSender {
+        sent: Mutex<Vec<String>>,
+        edits: Mutex<Vec<(String, String)>>,
+    }
+
+    #[async_trait]
+    impl MatrixSender for RecordingSender {
+        async fn send(&self, _room_id: &str, body: &str) -> Result<String> {
+            let mut sent = self.sent.lock();
+            sent.push(body.to_string());
+            let id = format!("$event{}", sent.len());
+            drop(sent);
+            Ok(id)
+        }
+
+        async fn edit(&self, _room_id: &str, event_id: &str, body: &str) -> Result<()> {
+            self.edits
+                .lock()
+                .push((event_id.to_string(), body.to_string()));
+            Ok(())
+        }
+    }
+
+    fn event(body: &str, member_count: u64) -> RoomEvent {
+        RoomEvent {
+            room_id: "!room:example.org".to_string(),
+            event_id: "$in".to_string(),
+            sender: "@ana:example.org".to_string(),
+            body: body.to_string(),
+            mentioned_user_ids: Vec::new(),
+            member_count,
+            encrypted: true,
+        }
+    }
+
+    async fn build_adapter(sender: Arc<RecordingSender>) -> MatrixAdapter {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        MatrixAdapter::new(bot, sender, "@helper:example.org").with_display_name("Helper")
+    }
+
+    #[tokio::test]
+    async fn test_mention_activation() {
+        let adapter = build_adapter(Arc::new(RecordingSender::default())).await;
+
+        assert!(adapter.should_respond(&event("hi", 2)));
+        assert!(!adapter.should_respond(&event("hi all", 5)));
+        assert!(adapter.should_respond(&event("helper: what's new?", 5)));
+        let mut mentioned = event("what's new?", 5);
+        mentioned.mentioned_user_ids = vec!["@helper:example.org".to_string()];
+        assert!(adapter.should_respond(&mentioned));
+        let mut own = event("hi", 2);
+        own.sender = "@helper:example.org".to_string();
+        assert!(!adapter.should_respond(&own));
+
+        let message = adapter.to_message(&event("Helper: what's new?", 5));
+        assert_eq!(message.content, "what's new?");
+        assert_eq!(message.conversation_id, "matrix:!room:example.org");
+        assert_eq!(message.metadata[ENCRYPTED_METADATA_KEY], true);
+
+        adapter
+            .rooms()
+            .link("!room:example.org", "matrix:!old:example.org");
+        assert_eq!(
+            adapter.to_message(&event("hi", 2)).conversation_id,
+            "matrix:!old:example.org"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_ignores_unaddressed_messages() {
+        let sender = Arc::new(RecordingSender::default());
+        let adapter = build_adapter(sender.clone()).await;
+
+        assert_eq!(adapter.handle(&event("hi all", 5)).await.unwrap(), None);
+        assert_eq!(
+            adapter.handle(&event("hello", 2)).await.unwrap().as_deref(),
+            Some("$event1")
+        );
+        assert_eq!(sender.sent.lock().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stream_reply_edits_in_place() {
+        let sender = Arc::new(RecordingSender::default());
+        let adapter = build_adapter(sender.clone())
+            .await
+            .with_edit_interval(Duration::ZERO);
+        let chunks = futures::stream::iter(["Hel", "lo, ", "world"].map(|c| Ok(c.to_string())));
+
+        let text = adapter
+            .stream_reply("!room:example.org", chunks)
+            .await
+            .unwrap();
+        assert_eq!(text, "Hello, world");
+        assert_eq!(*sender.sent.lock(), vec!["Hel".to_string()]);
+        assert_eq!(
+            sender.edits.lock().last().unwrap(),
+            &("$event1".to_string(), "Hello, world".to_string())
+        );
+
+        let sender = Arc::new(RecordingSender::default());
+        let adapter = build_adapter(sender.clone())
+            .await
+            .with_edit_interval(Duration::from_secs(3600));
+        let chunks = futures::stream::iter(["a", "b", "c"].map(|c| Ok(c.to_string())));
+        adapter
+            .stream_reply("!room:example.org", chunks)
+            .await
+            .unwrap();
+        assert_eq!(
+            *sender.edits.lock(),
+            vec![("$event1".to_string(), "abc".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_chunk_coalesces_with_rate_shape() {
+        let sender = Arc::new(RecordingSender::default());
+        let adapter = build_adapter(sender.clone())
+            .await
+            .with_rate_shape(RateShape {
+                coalesce_window: Duration::from_secs(60),
+                ..RateShape::default()
+            });
+
+        for chunk in ["Working", " on it", "..."] {
+            let event_id = adapter
+                .send_chunk("!room:example.org", chunk)
+                .await
+                .unwrap();
+            assert_eq!(event_id, "$event1");
+        }
+        assert_eq!(sender.sent.lock().len(), 1);
+        assert_eq!(sender.edits.lock().last().unwrap().1, "Working on it...");
+    }
+}
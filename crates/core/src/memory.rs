@@ -0,0 +1,728 @@
+//! Long-term user memory
+//!
+//! Unlike [`Context`](crate::context::Context), which holds the state of a
+//! single conversation, the memory subsystem keeps durable facts and
+//! preferences about a user ("user's name is Ada", "prefers metric units")
+//! across conversations. Memories are extracted periodically from
+//! conversation history, stored per user with provenance, and injected into
+//! later conversations within a token budget.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+use uuid::Uuid;
+
+use crate::{
+    completion::{parse_json_array, CompletionFn},
+    context::{Context, ContextMessage, MessageRole},
+    pipeline::{PipelineContext, PipelineStage},
+};
+
+/// Kind of remembered information
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryKind {
+    /// A fact about the user
+    Fact,
+    /// A stated preference
+    Preference,
+}
+
+/// Where a memory came from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryProvenance {
+    /// Conversation the memory was extracted from
+    pub conversation_id: String,
+    /// Source message ID, if known
+    pub message_id: Option<Uuid>,
+    /// Extractor that produced the memory
+    pub extractor: String,
+    /// When the memory was extracted
+    pub extracted_at: DateTime<Utc>,
+}
+
+/// A durable memory about a user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryItem {
+    /// Unique memory ID
+    pub id: Uuid,
+    /// User the memory belongs to
+    pub user_id: String,
+    /// Memory kind
+    pub kind: MemoryKind,
+    /// Optional slot key (e.g. `name`); a newer memory with the same key replaces the older one
+    pub key: Option<String>,
+    /// Human-readable memory content
+    pub content: String,
+    /// Extractor confidence (0.0 to 1.0)
+    pub confidence: f32,
+    /// Provenance information
+    pub provenance: MemoryProvenance,
+    /// Last update timestamp
+    pub updated_at: DateTime<Utc>,
+}
+
+impl MemoryItem {
+    /// Estimate token count (rough approximation)
+    #[must_use]
+    pub const fn estimated_tokens(&self) -> usize {
+        // Rough estimate: 1 token per 4 characters
+        self.content.len() / 4 + 1
+    }
+}
+
+/// A memory proposed by an extractor, before it is attributed to a user
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryCandidate {
+    /// Memory kind
+    pub kind: MemoryKind,
+    /// Optional slot key
+    #[serde(default)]
+    pub key: Option<String>,
+    /// Memory content
+    pub content: String,
+    /// Extractor confidence
+    #[serde(default = "default_confidence")]
+    pub confidence: f32,
+    /// Source message ID, if known
+    #[serde(default)]
+    pub message_id: Option<Uuid>,
+}
+
+const fn default_confidence() -> f32 {
+    0.5
+}
+
+/// Storage backend for user memories
+#[async_trait]
+pub trait MemoryStore: Send + Sync {
+    /// Insert a memory, replacing any existing memory with the same user and key
+    async fn put(&self, item: MemoryItem) -> Result<()>;
+
+    /// List all memories for a user
+    async fn list(&self, user_id: &str) -> Result<Vec<MemoryItem>>;
+
+    /// Delete a memory, returning whether it existed
+    async fn delete(&self, user_id: &str, id: Uuid) -> Result<bool>;
+
+    /// Delete all memories for a user
+    async fn clear(&self, user_id: &str) -> Result<()>;
+}
+
+/// In-memory memory store implementation
+#[derive(Default)]
+pub struct InMemoryMemoryStore {
+    data: DashMap<String, Vec<MemoryItem>>,
+}
+
+impl InMemoryMemoryStore {
+    /// Create an empty store
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl MemoryStore for InMemoryMemoryStore {
+    async fn put(&self, item: MemoryItem) -> Result<()> {
+        let mut items = self.data.entry(item.user_id.clone()).or_default();
+        items.retain(|existing| {
+            existing.id != item.id
+                && (item.key.is_none() || existing.key != item.key)
+                && !existing.content.eq_ignore_ascii_case(&item.content)
+        });
+        items.push(item);
+        drop(items);
+        Ok(())
+    }
+
+    async fn list(&self, user_id: &str) -> Result<Vec<MemoryItem>> {
+        Ok(self
+            .data
+            .get(user_id)
+            .map(|items| items.clone())
+            .unwrap_or_default())
+    }
+
+    async fn delete(&self, user_id: &str, id: Uuid) -> Result<bool> {
+        Ok(self.data.get_mut(user_id).is_some_and(|mut items| {
+            let before = items.len();
+            items.retain(|item| item.id != id);
+            items.len() != before
+        }))
+    }
+
+    async fn clear(&self, user_id: &str) -> Result<()> {
+        self.data.remove(user_id);
+        Ok(())
+    }
+}
+
+/// Extracts memory candidates from conversation history
+#[async_trait]
+pub trait MemoryExtractor: Send + Sync {
+    /// Extractor name recorded in provenance
+    fn name(&self) -> &str;
+
+    /// Extract candidates from the given messages
+    async fn extract(&self, messages: &[ContextMessage]) -> Result<Vec<MemoryCandidate>>;
+}
+
+/// Pattern-based extractor for common self-descriptions
+///
+/// Works without a model call and is used as the default extractor.
+pub struct RuleBasedExtractor;
+
+impl RuleBasedExtractor {
+    const RULES: &'static [(&'static str, MemoryKind, Option<&'static str>, &'static str)] = &[
+        (
+            "my name is ",
+            MemoryKind::Fact,
+            Some("name"),
+            "User's name is",
+        ),
+        ("call me ", MemoryKind::Fact, Some("name"), "User's name is"),
+        (
+            "i live in ",
+            MemoryKind::Fact,
+            Some("location"),
+            "User lives in",
+        ),
+        (
+            "i work at ",
+            MemoryKind::Fact,
+            Some("employer"),
+            "User works at",
+        ),
+        (
+            "i work as ",
+            MemoryKind::Fact,
+            Some("occupation"),
+            "User works as",
+        ),
+        ("i prefer ", MemoryKind::Preference, None, "User prefers"),
+        ("i like ", MemoryKind::Preference, None, "User likes"),
+        (
+            "i don't like ",
+            MemoryKind::Preference,
+            None,
+            "User dislikes",
+        ),
+    ];
+
+    fn extract_from(message: &ContextMessage) -> Vec<MemoryCandidate> {
+        // ASCII lowercasing keeps byte offsets aligned with the original content
+        let lower = message.content.to_ascii_lowercase();
+        let mut candidates = Vec::new();
+
+        for (pattern, kind, key, prefix) in Self::RULES {
+            let Some(start) = lower.find(pattern) else {
+                continue;
+            };
+            // Only match at word boundaries ("i like" but not "hi like")
+            if start > 0 && lower[..start].ends_with(char::is_alphanumeric) {
+                continue;
+            }
+            let value_start = start + pattern.len();
+            let value = message.content[value_start..]
+                .split(['.', ',', '!', '?', ';', '\n'])
+                .next()
+                .unwrap_or_default()
+                .trim();
+            if value.is_empty() || value.split_whitespace().count() > 8 {
+                continue;
+            }
+            candidates.push(MemoryCandidate {
+                kind: *kind,
+                key: key.map(ToString::to_string),
+                content: format!("{prefix} {value}"),
+                confidence: 0.7,
+                message_id: message.message_id,
+            });
+        }
+
+        candidates
+    }
+}
+
+#[async_trait]
+impl MemoryExtractor for RuleBasedExtractor {
+    fn name(&self) -> &str {
+        "rules"
+    }
+
+    async fn extract(&self, messages: &[ContextMessage]) -> Result<Vec<MemoryCandidate>> {
+        Ok(messages
+            .iter()
+            .filter(|m| m.role == MessageRole::User)
+            .flat_map(Self::extract_from)
+            .collect())
+    }
+}
+
+/// Model-backed extractor that sends an extraction prompt to a completion function
+pub struct PromptMemoryExtractor {
+    complete: CompletionFn,
+}
+
+impl PromptMemoryExtractor {
+    /// Create an extractor around a completion function
+    #[must_use]
+    pub fn new(complete: CompletionFn) -> Self {
+        Self { complete }
+    }
+
+    /// Build the extraction prompt for a set of messages
+    #[must_use]
+    pub fn extraction_prompt(messages: &[ContextMessage]) -> String {
+        let transcript = messages
+            .iter()
+            .filter(|m| m.role == MessageRole::User)
+            .map(|m| format!("- {}", m.content))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        format!(
+            "Extract durable facts and preferences about the user from the messages below. \
+             Only include information likely to remain true across conversations.\n\
+             Respond with a JSON array of objects with fields \
+             \"kind\" (\"fact\" or \"preference\"), \"key\" (optional slot such as \"name\"), \
+             \"content\" (third-person sentence), and \"confidence\" (0.0-1.0). \
+             Respond with [] if there is nothing to remember.\n\nMessages:\n{transcript}"
+        )
+    }
+
+    /// Parse the model's extraction response
+    ///
+    /// Tolerates surrounding prose or code fences around the JSON array.
+    #[must_use]
+    pub fn parse_response(text: &str) -> Vec<MemoryCandidate> {
+        parse_json_array(text, "memory extraction")
+    }
+}
+
+#[async_trait]
+impl MemoryExtractor for PromptMemoryExtractor {
+    fn name(&self) -> &str {
+        "prompt"
+    }
+
+    async fn extract(&self, messages: &[ContextMessage]) -> Result<Vec<MemoryCandidate>> {
+        let response = (self.complete)(Self::extraction_prompt(messages)).await?;
+        Ok(Self::parse_response(&response))
+    }
+}
+
+/// Configuration for long-term memory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryConfig {
+    /// Run extraction after this many new messages in a conversation
+    pub extraction_interval: usize,
+    /// Maximum tokens of memories injected into a conversation
+    pub injection_token_budget: usize,
+    /// Discard candidates below this confidence
+    pub min_confidence: f32,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            extraction_interval: 6,
+            injection_token_budget: 256,
+            min_confidence: 0.5,
+        }
+    }
+}
+
+/// Marker tracking how far into a conversation memory extraction has run
+const EXTRACTED_AT: ExtractionMarker = ExtractionMarker("memory_extracted_at");
+
+/// Context variable holding the message count at an extractor's last run
+///
+/// Each extractor over conversation history keeps its own marker, so it
+/// only sees the messages that arrived since it last ran.
+pub(crate) struct ExtractionMarker(pub(crate) &'static str);
+
+impl ExtractionMarker {
+    /// Messages added since the last run, once at least `interval` have arrived
+    pub(crate) fn pending(
+        &self,
+        context: &Context,
+        interval: usize,
+    ) -> Option<Vec<ContextMessage>> {
+        let last = context
+            .get_variable(self.0)
+            .and_then(serde_json::Value::as_u64)
+            .and_then(|v| usize::try_from(v).ok())
+            .unwrap_or(0)
+            .min(context.metadata.message_count);
+        let pending = context.metadata.message_count - last;
+        if pending < interval.max(1) {
+            return None;
+        }
+
+        Some(
+            context
+                .history
+                .iter()
+                .rev()
+                .take(pending)
+                .rev()
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Record that extraction has caught up with the conversation
+    pub(crate) fn advance(&self, context: &mut Context) {
+        context.set_variable(self.0, serde_json::json!(context.metadata.message_count));
+    }
+
+    /// Copy the marker from a snapshot back into the pipeline's context
+    ///
+    /// Extraction is async, so stages run it on a snapshot of the context
+    /// rather than holding the context lock across the model call.
+    pub(crate) fn copy_back(&self, snapshot: &Context, ctx: &PipelineContext) {
+        if let Some(marker) = snapshot.get_variable(self.0).cloned() {
+            ctx.context.write().set_variable(self.0, marker);
+        }
+    }
+}
+
+/// Coordinates memory extraction and recall
+pub struct MemoryManager {
+    store: Arc<dyn MemoryStore>,
+    extractor: Arc<dyn MemoryExtractor>,
+    config: MemoryConfig,
+}
+
+impl MemoryManager {
+    /// Create a new memory manager
+    #[must_use]
+    pub fn new(
+        store: Arc<dyn MemoryStore>,
+        extractor: Arc<dyn MemoryExtractor>,
+        config: MemoryConfig,
+    ) -> Self {
+        Self {
+            store,
+            extractor,
+            config,
+        }
+    }
+
+    /// Create a manager with an in-memory store and the rule-based extractor
+    #[must_use]
+    pub fn in_memory() -> Self {
+        Self::new(
+            Arc::new(InMemoryMemoryStore::new()),
+            Arc::new(RuleBasedExtractor),
+            MemoryConfig::default(),
+        )
+    }
+
+    /// Get the underlying store
+    #[must_use]
+    pub fn store(&self) -> &Arc<dyn MemoryStore> {
+        &self.store
+    }
+
+    /// Extract memories from a conversation if enough new messages have arrived
+    ///
+    /// Returns the number of memories stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if extraction or storage fails.
+    #[instrument(skip(self, context), fields(context_id = %context.id))]
+    pub async fn observe(&self, user_id: &str, context: &mut Context) -> Result<usize> {
+        let Some(recent) = EXTRACTED_AT.pending(context, self.config.extraction_interval) else {
+            return Ok(0);
+        };
+        let stored = self
+            .extract_and_store(user_id, &context.id, &recent)
+            .await?;
+
+        EXTRACTED_AT.advance(context);
+        Ok(stored)
+    }
+
+    /// Run extraction over the given messages and store the results
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if extraction or storage fails.
+    pub async fn extract_and_store(
+        &self,
+        user_id: &str,
+        conversation_id: &str,
+        messages: &[ContextMessage],
+    ) -> Result<usize> {
+        let candidates = self.extractor.extract(messages).await?;
+        let now = Utc::now();
+        let mut stored = 0;
+
+        for candidate in candidates {
+            if candidate.confidence < self.config.min_confidence
+                || candidate.content.trim().is_empty()
+            {
+                continue;
+            }
+            self.store
+                .put(MemoryItem {
+                    id: Uuid::new_v4(),
+                    user_id: user_id.to_string(),
+                    kind: candidate.kind,
+                    key: candidate.key,
+                    content: candidate.content,
+                    confidence: candidate.confidence,
+                    provenance: MemoryProvenance {
+                        conversation_id: conversation_id.to_string(),
+                        message_id: candidate.message_id,
+                        extractor: self.extractor.name().to_string(),
+                        extracted_at: now,
+                    },
+                    updated_at: now,
+                })
+                .await?;
+            stored += 1;
+        }
+
+        debug!("Stored {} memories for user {}", stored, user_id);
+        Ok(stored)
+    }
+
+    /// Recall the memories most relevant to a query within the token budget
+    ///
+    /// Memories are ranked by word overlap with the query, then confidence,
+    /// then recency. Slot memories (those with a key) such as the user's name
+    /// get a small boost since they are broadly useful.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store cannot be read.
+    pub async fn recall(&self, user_id: &str, query: &str) -> Result<Vec<MemoryItem>> {
+        let query_words = words(query);
+        let mut scored: Vec<(f32, MemoryItem)> = self
+            .store
+            .list(user_id)
+            .await?
+            .into_iter()
+            .map(|item| {
+                let overlap = words(&item.content).intersection(&query_words).count();
+                #[allow(clippy::cast_precision_loss)]
+                let relevance = overlap as f32 + if item.key.is_some() { 0.5 } else { 0.0 };
+                (item.confidence.mul_add(0.1, relevance), item)
+            })
+            .collect();
+        scored
+            .sort_by(|(a, x), (b, y)| b.total_cmp(a).then_with(|| y.updated_at.cmp(&x.updated_at)));
+
+        let mut budget = self.config.injection_token_budget;
+        Ok(scored
+            .into_iter()
+            .map(|(_, item)| item)
+            .filter(|item| {
+                let tokens = item.estimated_tokens();
+                if tokens <= budget {
+                    budget -= tokens;
+                    true
+                } else {
+                    false
+                }
+            })
+            .collect())
+    }
+
+    /// Render recalled memories as a system prompt section
+    #[must_use]
+    pub fn render(memories: &[MemoryItem]) -> Option<String> {
+        if memories.is_empty() {
+            return None;
+        }
+        let lines = memories
+            .iter()
+            .map(|m| format!("- {}", m.content))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Some(format!("Known information about the user:\n{lines}"))
+    }
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 2)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Pipeline stage that injects recalled memories and schedules extraction
+///
+/// Adds the recalled memories under the `memories` metadata key and the
+/// rendered prompt section under `memory_prompt` for the process stage.
+pub struct MemoryStage {
+    manager: Arc<MemoryManager>,
+}
+
+impl MemoryStage {
+    /// Create a new memory stage
+    #[must_use]
+    pub fn new(manager: Arc<MemoryManager>) -> Self {
+        Self { manager }
+    }
+}
+
+#[async_trait]
+impl PipelineStage for MemoryStage {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    async fn process(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
+        let user_id = ctx.message.user_id.clone();
+        let memories = self.manager.recall(&user_id, &ctx.message.content).await?;
+
+        if let Some(prompt) = MemoryManager::render(&memories) {
+            ctx.metadata
+                .insert("memory_prompt".to_string(), serde_json::json!(prompt));
+        }
+        ctx.metadata
+            .insert("memories".to_string(), serde_json::to_value(&memories)?);
+
+        let mut snapshot = ctx.context.read().clone();
+        self.manager.observe(&user_id, &mut snapshot).await?;
+        EXTRACTED_AT.copy_back(&snapshot, &ctx);
+
+        Ok(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+
+    fn user_message(content: &str) -> ContextMessage {
+        ContextMessage::from_message(&Message::text(content))
+    }
+
+    #[tokio::test]
+    async fn test_rule_based_extraction() {
+        let candidates = RuleBasedExtractor
+            .extract(&[
+                user_message("Hi, my name is Ada Lovelace. I prefer metric units!"),
+                ContextMessage::system("my name is System"),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].key.as_deref(), Some("name"));
+        assert_eq!(candidates[0].content, "User's name is Ada Lovelace");
+        assert_eq!(candidates[1].kind, MemoryKind::Preference);
+        assert_eq!(candidates[1].content, "User prefers metric units");
+    }
+
+    #[tokio::test]
+    async fn test_keyed_memory_replaces_previous() {
+        let manager = MemoryManager::in_memory();
+        manager
+            .extract_and_store("u1", "c1", &[user_message("my name is Ada")])
+            .await
+            .unwrap();
+        manager
+            .extract_and_store("u1", "c2", &[user_message("Actually, call me Grace")])
+            .await
+            .unwrap();
+
+        let memories = manager.store().list("u1").await.unwrap();
+        assert_eq!(memories.len(), 1);
+        assert_eq!(memories[0].content, "User's name is Grace");
+        assert_eq!(memories[0].provenance.conversation_id, "c2");
+        assert!(manager.store().list("u2").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_observe_respects_interval() {
+        let manager = MemoryManager::new(
+            Arc::new(InMemoryMemoryStore::new()),
+            Arc::new(RuleBasedExtractor),
+            MemoryConfig {
+                extraction_interval: 2,
+                ..MemoryConfig::default()
+            },
+        );
+        let mut context = Context::new("conv");
+        context.add_message(&Message::text("I live in Lisbon"));
+        assert_eq!(manager.observe("u1", &mut context).await.unwrap(), 0);
+
+        context.add_message(&Message::text("I like jazz"));
+        assert_eq!(manager.observe("u1", &mut context).await.unwrap(), 2);
+        assert_eq!(manager.observe("u1", &mut context).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_recall_ranks_and_budgets() {
+        let manager = MemoryManager::new(
+            Arc::new(InMemoryMemoryStore::new()),
+            Arc::new(RuleBasedExtractor),
+            MemoryConfig {
+                injection_token_budget: 12,
+                ..MemoryConfig::default()
+            },
+        );
+        manager
+            .extract_and_store(
+                "u1",
+                "c1",
+                &[user_message(
+                    "I like hiking in the mountains. I prefer metric units. I work as a chemist",
+                )],
+            )
+            .await
+            .unwrap();
+
+        let recalled = manager
+            .recall("u1", "convert miles to metric units")
+            .await
+            .unwrap();
+        assert!(!recalled.is_empty());
+        assert_eq!(recalled[0].content, "User prefers metric units");
+        assert!(
+            recalled
+                .iter()
+                .map(MemoryItem::estimated_tokens)
+                .sum::<usize>()
+                <= 12
+        );
+
+        let prompt = MemoryManager::render(&recalled).unwrap();
+        assert!(prompt.contains("- User prefers metric units"));
+        assert!(MemoryManager::render(&[]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_prompt_extractor_parses_response() {
+        let extractor = PromptMemoryExtractor::new(Arc::new(|prompt: String| {
+            Box::pin(async move {
+                assert!(prompt.contains("I am vegetarian"));
+                Ok("Sure:\n```json\n[{\"kind\":\"preference\",\"content\":\"User is vegetarian\",\"confidence\":0.9}]\n```".to_string())
+            })
+        }));
+
+        let candidates = extractor
+            .extract(&[user_message("I am vegetarian")])
+            .await
+            .unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].content, "User is vegetarian");
+        assert!(PromptMemoryExtractor::parse_response("nothing here").is_empty());
+    }
+}
@@ -4,16 +4,40 @@
 //! between the bot and its users, as well as internal message passing.
 
 use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
 
+use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
 use crate::error::{Error, Result};
 
+/// Message metadata key identifying the channel a message arrived on
+pub const CHANNEL_METADATA_KEY: &str = "channel";
+
+/// Response metadata key holding an [`Embed`]
+pub const EMBED_METADATA_KEY: &str = "embed";
+
+/// Maximum length of message content, in characters
+pub const MAX_CONTENT_CHARS: usize = 100_000;
+
+/// Maximum number of top-level metadata entries on a message
+pub const MAX_METADATA_ENTRIES: usize = 256;
+
+/// Maximum length of a metadata key, in bytes
+pub const MAX_METADATA_KEY_LEN: usize = 256;
+
+/// Maximum nesting depth of a metadata value
+pub const MAX_METADATA_DEPTH: usize = 32;
+
 /// A message sent to the bot
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[non_exhaustive]
 pub struct Message {
     /// Unique message ID
     pub id: Uuid,
@@ -28,8 +52,8 @@ pub struct Message {
     pub message_type: MessageType,
 
     /// Message content
-    #[validate(length(min = 1, max = 100_000))]
-    pub content: String,
+    #[validate(custom(function = "validate_content_length"))]
+    pub content: Content,
 
     /// Optional attachments
     pub attachments: Vec<Attachment>,
@@ -59,7 +83,7 @@ impl Message {
     /// assert_eq!(message.content, "Hello, bot!");
     /// ```
     #[must_use]
-    pub fn text(content: impl Into<String>) -> Self {
+    pub fn text(content: impl Into<Content>) -> Self {
         Self {
             id: Uuid::new_v4(),
             conversation_id: Uuid::new_v4().to_string(),
@@ -76,7 +100,7 @@ impl Message {
 
     /// Create a new message with a specific type
     #[must_use]
-    pub fn with_type(content: impl Into<String>, message_type: MessageType) -> Self {
+    pub fn with_type(content: impl Into<Content>, message_type: MessageType) -> Self {
         let mut message = Self::text(content);
         message.message_type = message_type;
         message
@@ -139,6 +163,26 @@ impl Message {
             ));
         }
 
+        if self.metadata.len() > MAX_METADATA_ENTRIES {
+            return Err(Error::InvalidInput(format!(
+                "Message metadata has {} entries (max {MAX_METADATA_ENTRIES})",
+                self.metadata.len()
+            )));
+        }
+
+        for (key, value) in &self.metadata {
+            if key.len() > MAX_METADATA_KEY_LEN {
+                return Err(Error::InvalidInput(format!(
+                    "Metadata key exceeds {MAX_METADATA_KEY_LEN} bytes"
+                )));
+            }
+            if crate::sanitize::json_depth(value) > MAX_METADATA_DEPTH {
+                return Err(Error::InvalidInput(format!(
+                    "Metadata value for {key:?} is nested deeper than {MAX_METADATA_DEPTH} levels"
+                )));
+            }
+        }
+
         Ok(())
     }
 
@@ -162,8 +206,10 @@ impl Message {
 }
 
 /// Type of message
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
+#[non_exhaustive]
 pub enum MessageType {
     /// Plain text message
     Text,
@@ -194,7 +240,9 @@ impl MessageType {
 }
 
 /// Message flags for special handling
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct MessageFlags {
     /// Message is urgent
     pub urgent: bool,
@@ -210,8 +258,188 @@ pub struct MessageFlags {
     pub no_log: bool,
 }
 
+fn validate_content_length(content: &Content) -> std::result::Result<(), ValidationError> {
+    let chars = content.chars().count();
+    if (1..=MAX_CONTENT_CHARS).contains(&chars) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("length"))
+    }
+}
+
+/// Shared, immutable message text
+///
+/// Cloning a `Content` bumps a reference count instead of copying the text,
+/// so large messages can move through pipeline stages and into context
+/// history without duplication. Mutation is copy-on-write: [`Content::update`]
+/// only allocates when the new text differs from the old.
+#[derive(Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Content(Arc<str>);
+
+impl Content {
+    /// Create content from anything string-like
+    #[must_use]
+    pub fn new(text: impl Into<Self>) -> Self {
+        text.into()
+    }
+
+    /// Borrow the text
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Replace the text
+    pub fn set(&mut self, text: impl Into<Self>) {
+        *self = text.into();
+    }
+
+    /// Clear the text
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Rewrite the text, keeping the shared buffer when nothing changed
+    ///
+    /// Returns `true` if the content was modified.
+    pub fn update<F>(&mut self, f: F) -> bool
+    where
+        F: FnOnce(&str) -> std::borrow::Cow<'_, str>,
+    {
+        let updated = match f(&self.0) {
+            std::borrow::Cow::Borrowed(_) => return false,
+            std::borrow::Cow::Owned(text) => text,
+        };
+        if *updated == *self.0 {
+            return false;
+        }
+        self.0 = updated.into();
+        true
+    }
+
+    /// Check whether two contents share the same buffer
+    #[must_use]
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Deref for Content {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Content {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Content {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for Content {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&*self.0, f)
+    }
+}
+
+impl From<String> for Content {
+    fn from(text: String) -> Self {
+        Self(text.into())
+    }
+}
+
+impl From<&String> for Content {
+    fn from(text: &String) -> Self {
+        Self(text.as_str().into())
+    }
+}
+
+impl From<&str> for Content {
+    fn from(text: &str) -> Self {
+        Self(text.into())
+    }
+}
+
+impl From<Arc<str>> for Content {
+    fn from(text: Arc<str>) -> Self {
+        Self(text)
+    }
+}
+
+impl From<Box<str>> for Content {
+    fn from(text: Box<str>) -> Self {
+        Self(text.into())
+    }
+}
+
+impl From<Content> for String {
+    fn from(content: Content) -> Self {
+        content.0.to_string()
+    }
+}
+
+impl PartialEq<str> for Content {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Content {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for Content {
+    fn eq(&self, other: &String) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl Serialize for Content {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Content {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Content {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        String::schema_name()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+}
+
 /// An attachment to a message
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct Attachment {
     /// Unique attachment ID
     pub id: Uuid,
@@ -227,6 +455,11 @@ pub struct Attachment {
     pub thumbnail_url: Option<String>,
     /// Attachment metadata
     pub metadata: HashMap<String, serde_json::Value>,
+    /// Inline payload, shared rather than copied between clones
+    ///
+    /// Not serialized; persisted forms refer to the payload through `url`.
+    #[serde(skip)]
+    pub data: Option<Bytes>,
 }
 
 impl Attachment {
@@ -246,9 +479,19 @@ impl Attachment {
             url: url.into(),
             thumbnail_url: None,
             metadata: HashMap::new(),
+            data: None,
         }
     }
 
+    /// Attach an inline payload, updating `size` to match
+    #[must_use]
+    pub fn with_data(mut self, data: impl Into<Bytes>) -> Self {
+        let data = data.into();
+        self.size = data.len();
+        self.data = Some(data);
+        self
+    }
+
     /// Check if this is an image attachment
     #[must_use]
     pub fn is_image(&self) -> bool {
@@ -269,7 +512,9 @@ impl Attachment {
 }
 
 /// A response from the bot
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct Response {
     /// Unique response ID
     pub id: Uuid,
@@ -327,6 +572,21 @@ impl Response {
         self
     }
 
+    /// Add costs incurred besides inference to the usage
+    ///
+    /// A response without usage gets usage with no tokens.
+    pub fn add_costs(&mut self, costs: &CostBreakdown) {
+        self.usage
+            .get_or_insert_with(|| TokenUsage::new(0, 0, ""))
+            .add_costs(costs);
+    }
+
+    /// What the response cost, by source
+    #[must_use]
+    pub fn cost(&self) -> Option<&CostBreakdown> {
+        self.usage.as_ref().map(|usage| &usage.cost)
+    }
+
     /// Add a suggestion
     #[must_use]
     pub fn with_suggestion(mut self, suggestion: Suggestion) -> Self {
@@ -341,6 +601,20 @@ impl Response {
         self
     }
 
+    /// Set the response type
+    #[must_use]
+    pub fn with_type(mut self, response_type: ResponseType) -> Self {
+        self.response_type = response_type;
+        self
+    }
+
+    /// Add a metadata entry
+    #[must_use]
+    pub fn with_metadata(mut self, key: impl Into<String>, value: serde_json::Value) -> Self {
+        self.metadata.insert(key.into(), value);
+        self
+    }
+
     /// Check if this response contains an error
     #[must_use]
     pub fn is_error(&self) -> bool {
@@ -352,11 +626,106 @@ impl Response {
     pub fn total_tokens(&self) -> usize {
         self.usage.as_ref().map_or(0, |u| u.total_tokens)
     }
+
+    /// Attach structured content for channels that can render it
+    ///
+    /// Channels without rich rendering fall back to [`Response::content`].
+    #[must_use]
+    pub fn with_embed(mut self, embed: Embed) -> Self {
+        self.response_type = ResponseType::Embed;
+        self.metadata.insert(
+            EMBED_METADATA_KEY.to_string(),
+            serde_json::to_value(embed).unwrap_or_default(),
+        );
+        self
+    }
+
+    /// Structured content attached with [`Response::with_embed`]
+    #[must_use]
+    pub fn embed(&self) -> Option<Embed> {
+        self.metadata
+            .get(EMBED_METADATA_KEY)
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+    }
+}
+
+/// Structured content rendered as a card by rich channels
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Embed {
+    /// Card title
+    pub title: String,
+    /// Body text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Link opened from the card
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Image shown on the card
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    /// Name/value pairs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<EmbedField>,
+}
+
+/// A name/value pair shown on an [`Embed`]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbedField {
+    /// Label
+    pub name: String,
+    /// Value
+    pub value: String,
+}
+
+impl Embed {
+    /// Create an embed with a title
+    #[must_use]
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Set the body text
+    #[must_use]
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Set the link
+    #[must_use]
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Set the image
+    #[must_use]
+    pub fn with_image(mut self, image_url: impl Into<String>) -> Self {
+        self.image_url = Some(image_url.into());
+        self
+    }
+
+    /// Add a name/value pair
+    #[must_use]
+    pub fn with_field(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.fields.push(EmbedField {
+            name: name.into(),
+            value: value.into(),
+        });
+        self
+    }
 }
 
 /// Type of response
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
+#[non_exhaustive]
 pub enum ResponseType {
     /// Plain text response
     Text,
@@ -375,7 +744,9 @@ pub enum ResponseType {
 }
 
 /// Response error information
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct ResponseError {
     /// Error code
     pub code: String,
@@ -385,6 +756,9 @@ pub struct ResponseError {
     pub retryable: bool,
     /// Optional retry after duration in seconds
     pub retry_after: Option<u64>,
+    /// What the user can do about the error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
 }
 
 impl ResponseError {
@@ -396,6 +770,7 @@ impl ResponseError {
             message: message.into(),
             retryable: false,
             retry_after: None,
+            hint: None,
         }
     }
 
@@ -412,10 +787,19 @@ impl ResponseError {
         self.retry_after = Some(seconds);
         self
     }
+
+    /// Set the remediation hint
+    #[must_use]
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
 }
 
 /// Response flags
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct ResponseFlags {
     /// Response was truncated
     pub truncated: bool,
@@ -430,7 +814,9 @@ pub struct ResponseFlags {
 }
 
 /// Token usage information
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct TokenUsage {
     /// Input tokens used
     pub input_tokens: usize,
@@ -438,10 +824,13 @@ pub struct TokenUsage {
     pub output_tokens: usize,
     /// Total tokens (input + output)
     pub total_tokens: usize,
-    /// Estimated cost in USD
+    /// Estimated cost in USD, the [`total`](CostBreakdown::total) of `cost`
     pub estimated_cost: f64,
     /// Model used
     pub model: String,
+    /// What the estimated cost is made of
+    #[serde(default)]
+    pub cost: CostBreakdown,
 }
 
 impl TokenUsage {
@@ -458,9 +847,24 @@ impl TokenUsage {
             total_tokens,
             estimated_cost,
             model: model_string,
+            cost: CostBreakdown::inference(estimated_cost),
         }
     }
 
+    /// Replace the estimated inference cost, e.g. with the provider's own figure
+    #[must_use]
+    pub fn with_inference_cost(mut self, cost: f64) -> Self {
+        self.cost.inference = cost;
+        self.estimated_cost = self.cost.total();
+        self
+    }
+
+    /// Add costs incurred besides inference
+    pub fn add_costs(&mut self, costs: &CostBreakdown) {
+        self.cost.merge(costs);
+        self.estimated_cost = self.cost.total();
+    }
+
     fn calculate_cost(input_tokens: usize, output_tokens: usize, model: &str) -> f64 {
         // Cost per 1K tokens (example rates)
         let (input_rate, output_rate) = match model {
@@ -475,8 +879,90 @@ impl TokenUsage {
     }
 }
 
+/// Cost of a single tool invocation
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCost {
+    /// Tool name
+    pub name: String,
+    /// Cost in USD
+    pub cost: f64,
+}
+
+/// What a request consumed, by source, in USD
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CostBreakdown {
+    /// Model inference
+    pub inference: f64,
+    /// Embedding calls
+    pub embeddings: f64,
+    /// Tool invocations, in call order
+    pub tools: Vec<ToolCost>,
+    /// Cost avoided by cache hits; not part of the total
+    pub cache_savings: f64,
+}
+
+impl CostBreakdown {
+    /// A breakdown with only inference cost
+    #[must_use]
+    pub fn inference(cost: f64) -> Self {
+        Self {
+            inference: cost,
+            ..Self::default()
+        }
+    }
+
+    /// Record the cost of embedding calls
+    pub fn add_embeddings(&mut self, cost: f64) {
+        self.embeddings += cost;
+    }
+
+    /// Record a tool invocation
+    pub fn add_tool(&mut self, name: impl Into<String>, cost: f64) {
+        self.tools.push(ToolCost {
+            name: name.into(),
+            cost,
+        });
+    }
+
+    /// Record what a cache hit saved
+    pub fn add_cache_savings(&mut self, saved: f64) {
+        self.cache_savings += saved;
+    }
+
+    /// Add every cost in `other`
+    pub fn merge(&mut self, other: &Self) {
+        self.inference += other.inference;
+        self.embeddings += other.embeddings;
+        self.tools.extend(other.tools.iter().cloned());
+        self.cache_savings += other.cache_savings;
+    }
+
+    /// Total spent on tools
+    #[must_use]
+    pub fn tools_total(&self) -> f64 {
+        self.tools.iter().map(|tool| tool.cost).sum()
+    }
+
+    /// Total spent: inference, embeddings, and tools
+    #[must_use]
+    pub fn total(&self) -> f64 {
+        self.inference + self.embeddings + self.tools_total()
+    }
+
+    /// Whether nothing was spent or saved
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0.0 && self.tools.is_empty() && self.cache_savings == 0.0
+    }
+}
+
 /// A suggestion for follow-up actions
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct Suggestion {
     /// Suggestion text
     pub text: String,
@@ -486,9 +972,30 @@ pub struct Suggestion {
     pub icon: Option<String>,
 }
 
+impl Suggestion {
+    /// Create a suggestion without an icon
+    #[must_use]
+    pub fn new(text: impl Into<String>, action: SuggestionAction) -> Self {
+        Self {
+            text: text.into(),
+            action,
+            icon: None,
+        }
+    }
+
+    /// Set the icon
+    #[must_use]
+    pub fn with_icon(mut self, icon: impl Into<String>) -> Self {
+        self.icon = Some(icon.into());
+        self
+    }
+}
+
 /// Action for a suggestion
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
+#[non_exhaustive]
 pub enum SuggestionAction {
     /// Send a message
     Message(String),
@@ -539,6 +1046,98 @@ mod tests {
         assert!(message.validate().is_err());
     }
 
+    #[test]
+    fn test_metadata_limits() {
+        let mut deep = serde_json::json!(0);
+        for _ in 0..=MAX_METADATA_DEPTH {
+            deep = serde_json::json!([deep]);
+        }
+        assert!(Message::text("hi")
+            .with_metadata("deep", deep)
+            .validate()
+            .is_err());
+
+        assert!(Message::text("hi")
+            .with_metadata("k".repeat(MAX_METADATA_KEY_LEN + 1), serde_json::json!(1))
+            .validate()
+            .is_err());
+
+        let mut message = Message::text("hi");
+        for i in 0..=MAX_METADATA_ENTRIES {
+            message.metadata.insert(i.to_string(), serde_json::json!(i));
+        }
+        assert!(message.validate().is_err());
+    }
+
+    #[test]
+    fn test_content_is_shared_and_copy_on_write() {
+        let message = Message::text("x".repeat(100_000));
+        let copy = message.clone();
+        assert!(message.content.ptr_eq(&copy.content));
+
+        let mut content = copy.content;
+        assert!(!content.update(|s| s.into()));
+        assert!(content.ptr_eq(&message.content));
+        assert!(!content.update(|s| std::borrow::Cow::Owned(s.to_string())));
+        assert!(content.ptr_eq(&message.content));
+
+        assert!(content.update(|s| std::borrow::Cow::Owned(s.replace('x', "y"))));
+        assert!(!content.ptr_eq(&message.content));
+        assert!(content.starts_with('y'));
+        assert_eq!(message.content.len(), 100_000);
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let message = Message::with_type("/help", MessageType::Command)
+            .with_conversation_id("conv-1")
+            .with_user_id("user-1")
+            .with_parent(Uuid::new_v4())
+            .with_attachment(Attachment::new("a.png", "image/png", 3, "s3://a.png"))
+            .with_metadata("channel", serde_json::json!("slack"));
+
+        let json = serde_json::to_string(&message).unwrap();
+        let back: Message = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(back.id, message.id);
+        assert_eq!(back.message_type, MessageType::Command);
+        assert_eq!(back.parent_id, message.parent_id);
+        assert_eq!(back.timestamp, message.timestamp);
+        assert_eq!(back.attachments[0].filename, "a.png");
+        assert_eq!(serde_json::to_string(&back).unwrap(), json);
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let response = Response::error(
+            "conv-1",
+            ResponseError::new("rate_limited", "Slow down").retry_after(5),
+        )
+        .with_usage(TokenUsage::new(10, 20, "anthropic.claude-haiku"))
+        .with_suggestion(Suggestion::new(
+            "Retry",
+            SuggestionAction::Command("retry".into()),
+        ));
+
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["response_type"], "error");
+        assert_eq!(json["suggestions"][0]["action"]["command"], "retry");
+
+        let back: Response = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(back.error.as_ref().unwrap().retry_after, Some(5));
+        assert_eq!(back.total_tokens(), 30);
+        assert_eq!(serde_json::to_value(&back).unwrap(), json);
+    }
+
+    #[test]
+    fn test_content_serializes_as_string() {
+        let message = Message::text("hi");
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["content"], "hi");
+        let back: Message = serde_json::from_value(json).unwrap();
+        assert_eq!(back.content, "hi");
+    }
+
     #[test]
     fn test_response_creation() {
         let response = Response::text("conv-123", "Hello, user!");
@@ -547,6 +1146,21 @@ mod tests {
         assert!(!response.is_error());
     }
 
+    #[test]
+    fn test_response_builder_methods() {
+        let response = Response::text("conv-123", "Pick one")
+            .with_type(ResponseType::Markdown)
+            .with_metadata("source", serde_json::json!("faq"))
+            .with_suggestion(
+                Suggestion::new("Docs", SuggestionAction::Url("https://example.com".into()))
+                    .with_icon("book"),
+            );
+
+        assert_eq!(response.response_type, ResponseType::Markdown);
+        assert_eq!(response.metadata["source"], "faq");
+        assert_eq!(response.suggestions[0].icon.as_deref(), Some("book"));
+    }
+
     #[test]
     fn test_error_response() {
         let error = ResponseError::new("E001", "Something went wrong")
@@ -570,6 +1184,32 @@ mod tests {
         assert!(usage.estimated_cost > 0.0);
     }
 
+    #[test]
+    fn test_cost_breakdown() {
+        let mut costs = CostBreakdown::default();
+        assert!(costs.is_empty());
+        costs.add_embeddings(0.25);
+        costs.add_tool("fx_convert", 0.5);
+        costs.add_tool("web_fetch", 0.25);
+        costs.add_cache_savings(2.0);
+        assert!((costs.total() - 1.0).abs() < f64::EPSILON);
+
+        let mut response = Response::text("conv", "Hi")
+            .with_usage(TokenUsage::new(10, 10, "model").with_inference_cost(1.0));
+        response.add_costs(&costs);
+        let usage = response.usage.as_ref().unwrap();
+        assert!((usage.estimated_cost - 2.0).abs() < f64::EPSILON);
+        let cost = response.cost().unwrap();
+        assert!((cost.inference - 1.0).abs() < f64::EPSILON);
+        assert_eq!(cost.tools.len(), 2);
+        assert!((cost.cache_savings - 2.0).abs() < f64::EPSILON);
+
+        let mut bare = Response::text("conv", "Cached");
+        bare.add_costs(&costs);
+        assert_eq!(bare.usage.as_ref().unwrap().total_tokens, 0);
+        assert!((bare.usage.unwrap().estimated_cost - 1.0).abs() < f64::EPSILON);
+    }
+
     #[test]
     fn test_attachment_types() {
         let image = Attachment::new(
@@ -616,6 +1256,19 @@ mod tests {
                 prop_assert_ne!(msg1.id, msg2.id);
             }
 
+            #[test]
+            fn test_validate_never_panics(
+                content in any::<String>(),
+                metadata in prop::collection::hash_map(any::<String>(), any::<String>(), 0..300)
+            ) {
+                let mut message = Message::text(content);
+                message.metadata = metadata
+                    .into_iter()
+                    .map(|(k, v)| (k, serde_json::Value::String(v)))
+                    .collect();
+                let _ = message.validate();
+            }
+
             #[test]
             fn test_token_cost_calculation(
                 input in 0usize..100_000,
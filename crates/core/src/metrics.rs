@@ -0,0 +1,608 @@
+//! Prometheus export of bot, pipeline, and Bedrock metrics
+//!
+//! [`MetricsExporter`] owns a Prometheus [`Registry`] and maps the existing
+//! metric structs onto it at scrape time, so nothing changes on the request
+//! path: [`BotMetrics`](crate::bot::BotMetrics) and every configuration
+//! version's [`PipelineMetrics`](crate::pipeline::PipelineMetrics) through
+//! [`register_bot`](MetricsExporter::register_bot), and, with the `bedrock`
+//! feature, a client's `BedrockMetrics` through
+//! [`register_bedrock`](MetricsExporter::register_bedrock). Totals become
+//! counters, in-flight values gauges, and latencies histograms.
+//!
+//! Mount [`router`](MetricsExporter::router) in an axum app to serve
+//! `GET /metrics` for scraping.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use axum::{http::header, routing::get, Router};
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::{self, MetricFamily, MetricType};
+use prometheus::{Encoder, Registry, TextEncoder};
+
+use crate::{bot::Bot, error::Error, slo::LatencyHistogram};
+
+/// Path [`MetricsExporter::router`] serves metrics on
+pub const METRICS_PATH: &str = "/metrics";
+
+/// Prefix of every exported metric name
+const NAMESPACE: &str = "universal_bot";
+
+/// Serves registered metrics in the Prometheus text format
+#[derive(Debug, Clone)]
+pub struct MetricsExporter {
+    registry: Registry,
+}
+
+impl Default for MetricsExporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetricsExporter {
+    /// Create an exporter with an empty registry
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_registry(Registry::new())
+    }
+
+    /// Export into an existing registry, e.g. one shared with other collectors
+    #[must_use]
+    pub const fn with_registry(registry: Registry) -> Self {
+        Self { registry }
+    }
+
+    /// The underlying registry
+    #[must_use]
+    pub const fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Export `bot`'s metrics and those of its configuration versions' pipelines
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a bot is already registered.
+    pub fn register_bot(&self, bot: &Bot) -> Result<()> {
+        self.register(Box::new(BotCollector::new(bot.clone())?))
+    }
+
+    /// Export the metrics of a Bedrock client
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a client is already registered.
+    #[cfg(feature = "bedrock")]
+    pub fn register_bedrock(
+        &self,
+        client: universal_bot_bedrock::UniversalBedrockClient,
+    ) -> Result<()> {
+        self.register(Box::new(BedrockCollector::new(client)?))
+    }
+
+    fn register(&self, collector: Box<dyn Collector>) -> Result<()> {
+        self.registry
+            .register(collector)
+            .map_err(|e| Error::Configuration(format!("Failed to register metrics: {e}")).into())
+    }
+
+    /// Every registered metric in the Prometheus text format
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metrics cannot be encoded.
+    pub fn render(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| Error::Internal(format!("Failed to encode metrics: {e}")))?;
+        Ok(String::from_utf8(buffer)?)
+    }
+
+    /// A router serving [`render`](Self::render) on [`METRICS_PATH`]
+    ///
+    /// Merge it into an application's router, e.g.
+    /// `app.merge(exporter.router())`.
+    pub fn router(&self) -> Router {
+        let exporter = self.clone();
+        Router::new().route(
+            METRICS_PATH,
+            get(move || {
+                let exporter = exporter.clone();
+                async move {
+                    match exporter.render() {
+                        Ok(body) => (
+                            axum::http::StatusCode::OK,
+                            [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+                            body,
+                        ),
+                        Err(e) => (
+                            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+                            format!("{e:#}"),
+                        ),
+                    }
+                }
+            }),
+        )
+    }
+}
+
+/// A metric family's description and type
+struct Family {
+    desc: Desc,
+    kind: MetricType,
+}
+
+impl Family {
+    fn new(name: &str, help: &str, labels: &[&str], kind: MetricType) -> Result<Self> {
+        let desc = Desc::new(
+            format!("{NAMESPACE}_{name}"),
+            help.to_string(),
+            labels.iter().map(ToString::to_string).collect(),
+            HashMap::new(),
+        )
+        .map_err(|e| Error::Configuration(format!("Invalid metric {name}: {e}")))?;
+        Ok(Self { desc, kind })
+    }
+
+    fn counter(name: &str, help: &str, labels: &[&str]) -> Result<Self> {
+        Self::new(name, help, labels, MetricType::COUNTER)
+    }
+
+    fn gauge(name: &str, help: &str, labels: &[&str]) -> Result<Self> {
+        Self::new(name, help, labels, MetricType::GAUGE)
+    }
+
+    fn histogram(name: &str, help: &str, labels: &[&str]) -> Result<Self> {
+        Self::new(name, help, labels, MetricType::HISTOGRAM)
+    }
+
+    fn collect(&self, metrics: Vec<proto::Metric>) -> MetricFamily {
+        let mut family = MetricFamily::default();
+        family.set_name(self.desc.fq_name.clone());
+        family.set_help(self.desc.help.clone());
+        family.set_field_type(self.kind);
+        family.set_metric(metrics.into());
+        family
+    }
+
+    /// One sample with `value`, as a counter or gauge according to the family
+    fn value(&self, value: f64, labels: &[(&str, &str)]) -> proto::Metric {
+        let mut metric = labeled(labels);
+        if self.kind == MetricType::GAUGE {
+            let mut gauge = proto::Gauge::default();
+            gauge.set_value(value);
+            metric.set_gauge(gauge);
+        } else {
+            let mut counter = proto::Counter::default();
+            counter.set_value(value);
+            metric.set_counter(counter);
+        }
+        metric
+    }
+}
+
+fn labeled(labels: &[(&str, &str)]) -> proto::Metric {
+    let mut metric = proto::Metric::default();
+    let pairs: Vec<proto::LabelPair> = labels
+        .iter()
+        .map(|(name, value)| {
+            let mut pair = proto::LabelPair::default();
+            pair.set_name((*name).to_string());
+            pair.set_value((*value).to_string());
+            pair
+        })
+        .collect();
+    metric.set_label(pairs.into());
+    metric
+}
+
+/// A latency histogram in seconds
+fn histogram(latencies: &LatencyHistogram, labels: &[(&str, &str)]) -> proto::Metric {
+    let mut cumulative = 0;
+    let buckets: Vec<proto::Bucket> = latencies
+        .bounds()
+        .iter()
+        .zip(latencies.counts())
+        .map(|(bound, count)| {
+            cumulative += count;
+            let mut bucket = proto::Bucket::default();
+            bucket.set_upper_bound(bound.as_secs_f64());
+            bucket.set_cumulative_count(cumulative);
+            bucket
+        })
+        .collect();
+
+    let mut histogram = proto::Histogram::default();
+    histogram.set_sample_count(latencies.count());
+    histogram.set_sample_sum(latencies.sum().as_secs_f64());
+    histogram.set_bucket(buckets.into());
+    let mut metric = labeled(labels);
+    metric.set_histogram(histogram);
+    metric
+}
+
+/// Maps [`BotMetrics`](crate::bot::BotMetrics) and pipeline metrics at scrape time
+struct BotCollector {
+    bot: Bot,
+    requests: Family,
+    successes: Family,
+    errors: Family,
+    tokens: Family,
+    cost: Family,
+    response_time: Family,
+    pipeline_requests: Family,
+    pipeline_time: Family,
+    dead_letters: Family,
+    dropped_dead_letters: Family,
+}
+
+impl BotCollector {
+    fn new(bot: Bot) -> Result<Self> {
+        Ok(Self {
+            bot,
+            requests: Family::counter("requests_total", "Messages received", &[])?,
+            successes: Family::counter("responses_total", "Messages answered", &[])?,
+            errors: Family::counter("errors_total", "Messages that failed", &[])?,
+            tokens: Family::counter("tokens_total", "Tokens reported by responses", &[])?,
+            cost: Family::counter(
+                "cost_usd_total",
+                "Estimated cost reported by responses, in USD",
+                &[],
+            )?,
+            response_time: Family::histogram(
+                "response_seconds",
+                "End-to-end time to answer a message",
+                &[],
+            )?,
+            pipeline_requests: Family::counter(
+                "pipeline_requests_total",
+                "Messages processed by a configuration version's pipeline",
+                &["config_version"],
+            )?,
+            pipeline_time: Family::histogram(
+                "pipeline_seconds",
+                "Time a configuration version's pipeline took to process a message",
+                &["config_version"],
+            )?,
+            dead_letters: Family::gauge(
+                "webhook_dead_letters",
+                "Webhook events waiting in the dead-letter queue",
+                &[],
+            )?,
+            dropped_dead_letters: Family::counter(
+                "webhook_dead_letters_dropped_total",
+                "Webhook dead letters dropped because the queue was full",
+                &[],
+            )?,
+        })
+    }
+}
+
+impl Collector for BotCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        [
+            &self.requests,
+            &self.successes,
+            &self.errors,
+            &self.tokens,
+            &self.cost,
+            &self.response_time,
+            &self.pipeline_requests,
+            &self.pipeline_time,
+            &self.dead_letters,
+            &self.dropped_dead_letters,
+        ]
+        .into_iter()
+        .map(|family| &family.desc)
+        .collect()
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn collect(&self) -> Vec<MetricFamily> {
+        let metrics = self.bot.metrics();
+        let versions = self.bot.config_versions().versions();
+        let ids: Vec<String> = versions.iter().map(|v| v.id().to_string()).collect();
+        let webhooks = self.bot.webhooks();
+
+        vec![
+            self.requests.collect(vec![self
+                .requests
+                .value(metrics.requests_total() as f64, &[])]),
+            self.successes.collect(vec![self
+                .successes
+                .value(metrics.success_total() as f64, &[])]),
+            self.errors
+                .collect(vec![self.errors.value(metrics.errors_total() as f64, &[])]),
+            self.tokens
+                .collect(vec![self.tokens.value(metrics.tokens_total() as f64, &[])]),
+            self.cost
+                .collect(vec![self.cost.value(metrics.cost_total(), &[])]),
+            self.response_time
+                .collect(vec![histogram(&metrics.response_time_histogram(), &[])]),
+            self.pipeline_requests.collect(
+                versions
+                    .iter()
+                    .zip(&ids)
+                    .map(|(version, id)| {
+                        self.pipeline_requests.value(
+                            version.pipeline().metrics().requests_total() as f64,
+                            &[("config_version", id.as_str())],
+                        )
+                    })
+                    .collect(),
+            ),
+            self.pipeline_time.collect(
+                versions
+                    .iter()
+                    .zip(&ids)
+                    .map(|(version, id)| {
+                        histogram(
+                            &version.pipeline().metrics().processing_time_histogram(),
+                            &[("config_version", id.as_str())],
+                        )
+                    })
+                    .collect(),
+            ),
+            self.dead_letters.collect(
+                webhooks
+                    .map(|w| self.dead_letters.value(w.dead_letter_count() as f64, &[]))
+                    .into_iter()
+                    .collect(),
+            ),
+            self.dropped_dead_letters.collect(
+                webhooks
+                    .map(|w| {
+                        self.dropped_dead_letters
+                            .value(w.dropped_dead_letters() as f64, &[])
+                    })
+                    .into_iter()
+                    .collect(),
+            ),
+        ]
+    }
+}
+
+/// Maps a Bedrock client's `BedrockMetrics` at scrape time
+#[cfg(feature = "bedrock")]
+struct BedrockCollector {
+    client: universal_bot_bedrock::UniversalBedrockClient,
+    requests: Family,
+    failures: Family,
+    active: Family,
+    latency: Family,
+    tokens: Family,
+    cost: Family,
+    errors: Family,
+    region_requests: Family,
+    failovers: Family,
+    throttled: Family,
+    rate_limited: Family,
+    throttle_wait: Family,
+    first_token_requests: Family,
+    first_token_time: Family,
+    first_token_improvement: Family,
+    batch_items: Family,
+}
+
+#[cfg(feature = "bedrock")]
+impl BedrockCollector {
+    fn new(client: universal_bot_bedrock::UniversalBedrockClient) -> Result<Self> {
+        Ok(Self {
+            client,
+            requests: Family::counter("bedrock_requests_total", "Bedrock requests", &["model"])?,
+            failures: Family::counter("bedrock_failures_total", "Failed Bedrock requests", &[])?,
+            active: Family::gauge("bedrock_active_requests", "Bedrock requests in flight", &[])?,
+            latency: Family::counter(
+                "bedrock_latency_seconds_total",
+                "Time spent on Bedrock requests",
+                &[],
+            )?,
+            tokens: Family::counter(
+                "bedrock_tokens_total",
+                "Tokens processed by Bedrock",
+                &["direction"],
+            )?,
+            cost: Family::counter(
+                "bedrock_cost_usd_total",
+                "Estimated Bedrock cost, in USD",
+                &[],
+            )?,
+            errors: Family::counter("bedrock_errors_total", "Bedrock errors", &["type"])?,
+            region_requests: Family::counter(
+                "bedrock_region_requests_total",
+                "Bedrock requests sent to each region",
+                &["region"],
+            )?,
+            failovers: Family::counter(
+                "bedrock_failovers_total",
+                "Traffic shifts between regions",
+                &[],
+            )?,
+            throttled: Family::counter(
+                "bedrock_throttled_total",
+                "Requests held back by the client-side rate limiter",
+                &["model"],
+            )?,
+            rate_limited: Family::counter(
+                "bedrock_rate_limited_total",
+                "Requests failed because the rate limiter had no room in time",
+                &[],
+            )?,
+            throttle_wait: Family::counter(
+                "bedrock_throttle_wait_seconds_total",
+                "Time requests were held back by the rate limiter",
+                &[],
+            )?,
+            first_token_requests: Family::counter(
+                "bedrock_first_token_requests_total",
+                "Streamed requests timed to their first token",
+                &["model", "prefix"],
+            )?,
+            first_token_time: Family::counter(
+                "bedrock_first_token_seconds_total",
+                "Time to first streamed token",
+                &["model", "prefix"],
+            )?,
+            first_token_improvement: Family::gauge(
+                "bedrock_first_token_improvement_seconds",
+                "Average first-token time saved by a warm prompt prefix",
+                &["model"],
+            )?,
+            batch_items: Family::counter(
+                "bedrock_batch_items_total",
+                "Batch generation items by outcome",
+                &["outcome"],
+            )?,
+        })
+    }
+}
+
+#[cfg(feature = "bedrock")]
+impl Collector for BedrockCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        [
+            &self.requests,
+            &self.failures,
+            &self.active,
+            &self.latency,
+            &self.tokens,
+            &self.cost,
+            &self.errors,
+            &self.region_requests,
+            &self.failovers,
+            &self.throttled,
+            &self.rate_limited,
+            &self.throttle_wait,
+            &self.first_token_requests,
+            &self.first_token_time,
+            &self.first_token_improvement,
+            &self.batch_items,
+        ]
+        .into_iter()
+        .map(|family| &family.desc)
+        .collect()
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn collect(&self) -> Vec<MetricFamily> {
+        let metrics = self.client.metrics();
+        let by_label = |family: &Family, label: &str, counts: &HashMap<String, u64>| {
+            family.collect(
+                counts
+                    .iter()
+                    .map(|(value, count)| family.value(*count as f64, &[(label, value)]))
+                    .collect(),
+            )
+        };
+        let single = |family: &Family, value: f64| family.collect(vec![family.value(value, &[])]);
+
+        vec![
+            by_label(&self.requests, "model", &metrics.requests_by_model),
+            single(&self.failures, metrics.failed_requests as f64),
+            single(&self.active, metrics.active_requests as f64),
+            single(&self.latency, metrics.total_latency_ms as f64 / 1000.0),
+            self.tokens.collect(vec![
+                self.tokens
+                    .value(metrics.total_input_tokens as f64, &[("direction", "input")]),
+                self.tokens.value(
+                    metrics.total_output_tokens as f64,
+                    &[("direction", "output")],
+                ),
+            ]),
+            single(&self.cost, metrics.total_cost),
+            by_label(&self.errors, "type", &metrics.errors_by_type),
+            by_label(&self.region_requests, "region", &metrics.requests_by_region),
+            single(&self.failovers, metrics.total_failovers as f64),
+            by_label(&self.throttled, "model", &metrics.throttled_by_model),
+            single(&self.rate_limited, metrics.rate_limited_requests as f64),
+            single(
+                &self.throttle_wait,
+                metrics.throttle_wait_ms as f64 / 1000.0,
+            ),
+            self.first_token_requests.collect(
+                metrics
+                    .first_token_by_model
+                    .iter()
+                    .flat_map(|(model, latency)| {
+                        [
+                            (model, "warm", latency.warm_requests),
+                            (model, "cold", latency.cold_requests),
+                        ]
+                    })
+                    .map(|(model, prefix, count)| {
+                        self.first_token_requests.value(
+                            count as f64,
+                            &[("model", model.as_str()), ("prefix", prefix)],
+                        )
+                    })
+                    .collect(),
+            ),
+            self.first_token_time.collect(
+                metrics
+                    .first_token_by_model
+                    .iter()
+                    .flat_map(|(model, latency)| {
+                        [
+                            (model, "warm", latency.warm_total_ms),
+                            (model, "cold", latency.cold_total_ms),
+                        ]
+                    })
+                    .map(|(model, prefix, ms)| {
+                        self.first_token_time.value(
+                            ms as f64 / 1000.0,
+                            &[("model", model.as_str()), ("prefix", prefix)],
+                        )
+                    })
+                    .collect(),
+            ),
+            self.first_token_improvement.collect(
+                metrics
+                    .first_token_by_model
+                    .iter()
+                    .filter_map(|(model, latency)| {
+                        let improvement = latency.improvement_ms()?;
+                        Some(
+                            self.first_token_improvement
+                                .value(improvement / 1000.0, &[("model", model.as_str())]),
+                        )
+                    })
+                    .collect(),
+            ),
+            self.batch_items.collect(vec![
+                self.batch_items.value(
+                    metrics.successful_batch_items as f64,
+                    &[("outcome", "succeeded")],
+                ),
+                self.batch_items
+                    .value(metrics.failed_batch_items as f64, &[("outcome", "failed")]),
+            ]),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BotConfig;
+    use crate::message::Message;
+
+    #[tokio::test]
+    async fn test_bot_metrics_render() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        bot.process(Message::text("Hello")).await.unwrap();
+
+        let exporter = MetricsExporter::new();
+        exporter.register_bot(&bot).unwrap();
+        assert!(exporter.register_bot(&bot).is_err());
+
+        let text = exporter.render().unwrap();
+        assert!(text.contains("# TYPE universal_bot_requests_total counter"));
+        assert!(text.contains("universal_bot_requests_total 1"));
+        assert!(text.contains("# TYPE universal_bot_response_seconds histogram"));
+        assert!(text.contains("universal_bot_response_seconds_count 1"));
+        assert!(text.contains("universal_bot_pipeline_requests_total{config_version=\"1\"} 1"));
+    }
+}
@@ -0,0 +1,335 @@
+//! Handling of user input longer than a message may be
+//!
+//! Messages over [`InputOverflowConfig::max_chars`] are handled according to
+//! the tenant's [`OverflowPolicy`] before they reach the pipeline: rejected
+//! by validation as before, truncated with a notice, split into several user
+//! turns, or cut with the remainder summarized into the context. What was
+//! done is recorded as an [`OverflowReport`] under
+//! [`INPUT_OVERFLOW_METADATA_KEY`] on both the message and the response.
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    completion::CompletionFn,
+    config::{InputOverflowConfig, OverflowPolicy},
+    context::{Context, ContextMessage},
+    degraded::TENANT_METADATA_KEY,
+    message::{Message, MAX_CONTENT_CHARS},
+};
+
+/// Message and response metadata key holding the [`OverflowReport`]
+pub const INPUT_OVERFLOW_METADATA_KEY: &str = "input_overflow";
+
+/// Characters kept free for the truncation notice or part header
+const NOTICE_RESERVE: usize = 96;
+
+/// What was done with an overlong message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverflowReport {
+    /// Policy configured for the tenant
+    pub requested: OverflowPolicy,
+    /// Policy actually applied; truncation stands in when summarizing is unavailable
+    pub applied: OverflowPolicy,
+    /// Length of the original input, in characters
+    pub original_chars: usize,
+    /// Characters of the original input in the answered message
+    pub kept_chars: usize,
+    /// Number of user turns the input became
+    pub parts: usize,
+}
+
+/// An overlong message after its policy was applied
+#[derive(Debug, Clone)]
+pub struct OverflowOutcome {
+    /// The message to answer
+    pub message: Message,
+    /// What was done, if the message was over the limit
+    pub report: Option<OverflowReport>,
+    preceding: Vec<Message>,
+    summary: Option<String>,
+    kept_chars: usize,
+}
+
+impl OverflowOutcome {
+    fn new(message: Message, kept_chars: usize) -> Self {
+        Self {
+            message,
+            report: None,
+            preceding: Vec::new(),
+            summary: None,
+            kept_chars,
+        }
+    }
+
+    /// Add the split-off parts and the summary of the overflow to `context`
+    pub fn record(&self, context: &mut Context) {
+        for part in &self.preceding {
+            context.add_message(part);
+        }
+        if let Some(summary) = &self.summary {
+            let note = ContextMessage::system(format!(
+                "Summary of the part of the user's next message that was cut for length: {}",
+                summary.trim()
+            ));
+            context.token_count += note.estimated_tokens();
+            context.history.push_back(note);
+        }
+    }
+}
+
+/// Applies [`InputOverflowConfig`] to incoming messages
+#[derive(Clone)]
+pub struct InputOverflow {
+    config: InputOverflowConfig,
+    summarizer: Option<CompletionFn>,
+}
+
+impl std::fmt::Debug for InputOverflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InputOverflow")
+            .field("config", &self.config)
+            .field("summarizer", &self.summarizer.is_some())
+            .finish()
+    }
+}
+
+impl InputOverflow {
+    /// Create the handler; [`OverflowPolicy::Summarize`] truncates until a summarizer is set
+    #[must_use]
+    pub fn new(config: InputOverflowConfig) -> Self {
+        Self {
+            config,
+            summarizer: None,
+        }
+    }
+
+    /// Summarize overflow with the given completion function
+    #[must_use]
+    pub fn with_summarizer(mut self, summarizer: CompletionFn) -> Self {
+        self.summarizer = Some(summarizer);
+        self
+    }
+
+    /// Longest input handled as is, in characters
+    #[must_use]
+    pub fn max_chars(&self) -> usize {
+        self.config
+            .max_chars
+            .clamp(NOTICE_RESERVE * 2, MAX_CONTENT_CHARS)
+    }
+
+    /// Apply the tenant's policy to `message` if it is over the limit
+    ///
+    /// A failed summary falls back to truncation rather than failing the message.
+    pub async fn apply(&self, message: Message) -> OverflowOutcome {
+        let original_chars = message.content.chars().count();
+        let max_chars = self.max_chars();
+        if original_chars <= max_chars {
+            return OverflowOutcome::new(message, original_chars);
+        }
+
+        let tenant = message
+            .metadata
+            .get(TENANT_METADATA_KEY)
+            .and_then(serde_json::Value::as_str);
+        let requested = self.config.policy_for(tenant);
+        let limit = max_chars - NOTICE_RESERVE;
+
+        let (mut outcome, applied) = match requested {
+            OverflowPolicy::Reject => return OverflowOutcome::new(message, original_chars),
+            OverflowPolicy::Truncate => (truncate(message, limit), OverflowPolicy::Truncate),
+            OverflowPolicy::Split => (split(message, limit), OverflowPolicy::Split),
+            OverflowPolicy::Summarize => self.summarize(message, limit).await,
+        };
+        let report = OverflowReport {
+            requested,
+            applied,
+            original_chars,
+            kept_chars: outcome.kept_chars,
+            parts: outcome.preceding.len() + 1,
+        };
+        outcome.message.metadata.insert(
+            INPUT_OVERFLOW_METADATA_KEY.to_string(),
+            serde_json::json!(report),
+        );
+        outcome.report = Some(report);
+        outcome
+    }
+
+    async fn summarize(
+        &self,
+        mut message: Message,
+        limit: usize,
+    ) -> (OverflowOutcome, OverflowPolicy) {
+        let Some(summarizer) = &self.summarizer else {
+            return (truncate(message, limit), OverflowPolicy::Truncate);
+        };
+        let (head, rest) = split_at_chars(&message.content, limit);
+        let prompt = format!(
+            "Summarize the following text in a few sentences, keeping names, figures, \
+             and any questions asked.\n\n{rest}"
+        );
+        match summarizer(prompt).await {
+            Ok(summary) => {
+                let kept_chars = head.chars().count();
+                let content = format!(
+                    "{head}\n\n[The rest of this message was summarized in the conversation \
+                     context]"
+                );
+                message.content.set(content);
+                let mut outcome = OverflowOutcome::new(message, kept_chars);
+                outcome.summary = Some(summary);
+                (outcome, OverflowPolicy::Summarize)
+            }
+            Err(e) => {
+                warn!("Summarizing overlong input failed, truncating instead: {e:#}");
+                (truncate(message, limit), OverflowPolicy::Truncate)
+            }
+        }
+    }
+}
+
+fn truncate(mut message: Message, limit: usize) -> OverflowOutcome {
+    let total = message.content.chars().count();
+    let (head, _) = split_at_chars(&message.content, limit);
+    let kept_chars = head.chars().count();
+    let content = format!(
+        "{head}\n\n[Message truncated: {} of {total} characters omitted]",
+        total - kept_chars
+    );
+    message.content.set(content);
+    OverflowOutcome::new(message, kept_chars)
+}
+
+fn split(mut message: Message, limit: usize) -> OverflowOutcome {
+    let mut parts = Vec::new();
+    let mut rest = message.content.as_str();
+    while !rest.is_empty() {
+        let (head, tail) = split_at_chars(rest, limit);
+        parts.push(head.to_string());
+        rest = tail;
+    }
+
+    let count = parts.len();
+    let last = parts.pop().unwrap_or_default();
+    let preceding = parts
+        .into_iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let mut turn = message.clone();
+            turn.id = Uuid::new_v4();
+            turn.content
+                .set(format!("[Part {} of {count}]\n{part}", i + 1));
+            turn
+        })
+        .collect();
+    let kept_chars = last.chars().count();
+    message
+        .content
+        .set(format!("[Part {count} of {count}]\n{last}"));
+    let mut outcome = OverflowOutcome::new(message, kept_chars);
+    outcome.preceding = preceding;
+    outcome
+}
+
+/// Split `text` after at most `limit` characters, preferring a whitespace
+/// boundary in the second half of the head
+fn split_at_chars(text: &str, limit: usize) -> (&str, &str) {
+    let Some((end, _)) = text.char_indices().nth(limit) else {
+        return (text, "");
+    };
+    let head = &text[..end];
+    let cut = head
+        .rfind(char::is_whitespace)
+        .filter(|i| *i >= end / 2)
+        .unwrap_or(end);
+    (text[..cut].trim_end(), text[cut..].trim_start())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn handler(policy: OverflowPolicy) -> InputOverflow {
+        InputOverflow::new(InputOverflowConfig {
+            max_chars: 500,
+            default_policy: policy,
+            tenant_policies: HashMap::from([("strict".to_string(), OverflowPolicy::Reject)]),
+        })
+    }
+
+    fn long_message() -> Message {
+        Message::text("word ".repeat(300))
+    }
+
+    #[tokio::test]
+    async fn test_short_and_rejected_input_is_untouched() {
+        let outcome = handler(OverflowPolicy::Truncate)
+            .apply(Message::text("hello"))
+            .await;
+        assert!(outcome.report.is_none());
+
+        let message = long_message().with_metadata(TENANT_METADATA_KEY, "strict".into());
+        let outcome = handler(OverflowPolicy::Truncate)
+            .apply(message.clone())
+            .await;
+        assert!(outcome.report.is_none());
+        assert_eq!(outcome.message.content, message.content);
+    }
+
+    #[tokio::test]
+    async fn test_truncate_appends_notice() {
+        let outcome = handler(OverflowPolicy::Truncate)
+            .apply(long_message())
+            .await;
+        let report = outcome.report.unwrap();
+        assert_eq!(report.applied, OverflowPolicy::Truncate);
+        assert_eq!(report.original_chars, 1500);
+        assert!(outcome.message.content.chars().count() <= 500);
+        assert!(outcome.message.content.ends_with("characters omitted]"));
+        assert!(outcome
+            .message
+            .metadata
+            .contains_key(INPUT_OVERFLOW_METADATA_KEY));
+    }
+
+    #[tokio::test]
+    async fn test_split_adds_preceding_turns_to_context() {
+        let outcome = handler(OverflowPolicy::Split).apply(long_message()).await;
+        let report = outcome.report.clone().unwrap();
+        assert_eq!(report.parts, 4);
+        assert!(outcome.message.content.starts_with("[Part 4 of 4]"));
+
+        let mut context = Context::new("test");
+        outcome.record(&mut context);
+        assert_eq!(context.history.len(), 3);
+        assert!(context.history[0].content.starts_with("[Part 1 of 4]"));
+    }
+
+    #[tokio::test]
+    async fn test_summarize_records_summary_or_falls_back() {
+        let fallback = handler(OverflowPolicy::Summarize)
+            .apply(long_message())
+            .await;
+        assert_eq!(fallback.report.unwrap().applied, OverflowPolicy::Truncate);
+
+        let summarizer: CompletionFn =
+            Arc::new(|_| Box::pin(async { Ok("The user repeated a word.".to_string()) }));
+        let outcome = handler(OverflowPolicy::Summarize)
+            .with_summarizer(summarizer)
+            .apply(long_message())
+            .await;
+        assert_eq!(
+            outcome.report.as_ref().unwrap().applied,
+            OverflowPolicy::Summarize
+        );
+
+        let mut context = Context::new("test");
+        outcome.record(&mut context);
+        assert!(context.history[0].content.contains("repeated a word"));
+    }
+}
@@ -10,22 +10,36 @@ use std::time::Duration;
 use anyhow::{Context as _, Result};
 use async_trait::async_trait;
 use parking_lot::RwLock;
-use tracing::{debug, instrument};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, info_span, instrument, warn, Instrument as _};
 
 use crate::{
-    config::{BotConfig, PipelineConfig},
+    config::{BotConfig, PipelineConfig, TraceSamplingConfig, UserErrorConfig},
     context::Context,
     error::Error,
-    message::{Message, Response},
+    message::{CostBreakdown, Message, Response},
+    prompt_registry::PROMPT_VERSION_METADATA_KEY,
+    prompt_template::PromptTemplate,
+    provider::{Provider, ProviderRequest, PROVIDER_METADATA_KEY},
+    provisioned::{ProvisionedThroughputManager, CAPACITY_METADATA_KEY},
+    retrieval::{self, RetrieveStage, Retriever},
+    selection::{ModelSelector, BUDGET_DOWNGRADE_METADATA_KEY},
+    slo::LatencyHistogram,
+    streaming::ChunkSink,
+    structured::{self, OUTPUT_SCHEMA_METADATA_KEY},
+    trace_context::TraceContext,
+    user_error::ErrorCatalog,
 };
 
 /// Message processing pipeline
 pub struct MessagePipeline {
-    #[allow(dead_code)]
     config: PipelineConfig,
+    model: String,
     stages: Vec<Box<dyn PipelineStage>>,
     middleware: Vec<Box<dyn PipelineMiddleware>>,
     metrics: Arc<PipelineMetrics>,
+    provisioned: Option<Arc<ProvisionedThroughputManager>>,
+    sampler: TraceSampler,
 }
 
 impl MessagePipeline {
@@ -36,13 +50,49 @@ impl MessagePipeline {
     /// Returns an error if pipeline initialization fails.
     #[instrument(skip(config))]
     pub async fn new(config: &BotConfig) -> Result<Self> {
+        Self::with_provider(config, None).await
+    }
+
+    /// Create a pipeline whose process stage generates replies with `provider`
+    ///
+    /// Without a provider the process stage answers with placeholder text.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if pipeline initialization fails.
+    pub async fn with_provider(
+        config: &BotConfig,
+        provider: Option<Arc<dyn Provider>>,
+    ) -> Result<Self> {
+        Self::with_components(config, provider, None).await
+    }
+
+    /// Create a pipeline with a provider and a [`Retriever`] for the `retrieve` stage
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if pipeline initialization fails, including when
+    /// the `retrieve` stage is enabled without a retriever.
+    #[instrument(skip(config, provider, retriever))]
+    pub async fn with_components(
+        config: &BotConfig,
+        provider: Option<Arc<dyn Provider>>,
+        retriever: Option<Arc<Retriever>>,
+    ) -> Result<Self> {
         debug!("Creating message pipeline");
 
         let mut stages: Vec<Box<dyn PipelineStage>> = Vec::new();
+        let provisioned = provisioned_throughput(config);
 
         // Add stages based on configuration
         for stage_name in &config.pipeline_config.enabled_stages {
-            let stage = Self::create_stage(stage_name, config)?;
+            let stage = Self::create_stage(
+                stage_name,
+                config,
+                provisioned.as_ref(),
+                provider.as_ref(),
+                retriever.as_ref(),
+            )?;
             stages.push(stage);
         }
 
@@ -57,9 +107,12 @@ impl MessagePipeline {
 
         Ok(Self {
             config: config.pipeline_config.clone(),
+            model: config.model.clone(),
             stages,
             middleware,
             metrics: Arc::new(PipelineMetrics::new()),
+            provisioned,
+            sampler: TraceSampler::new(config.pipeline_config.trace_sampling.clone()),
         })
     }
 
@@ -68,35 +121,94 @@ impl MessagePipeline {
     /// # Errors
     ///
     /// Returns an error if any stage in the pipeline fails
-    #[instrument(skip(self, message, context))]
     pub async fn process(
         &self,
-        mut message: Message,
+        message: Message,
         context: Arc<RwLock<Context>>,
     ) -> Result<Response> {
+        self.process_with(PipelineContext::new(message, context))
+            .await
+    }
+
+    /// Process a message through the pipeline, starting from a prepared context
+    ///
+    /// Lets the bot hand stages state that must not come from the message,
+    /// such as an assigned [`PipelineContext::prompt`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any stage in the pipeline fails
+    #[instrument(
+        skip(self, pipeline_ctx),
+        fields(message_id = %pipeline_ctx.message.id, trace_id = tracing::field::Empty)
+    )]
+    pub async fn process_with(&self, mut pipeline_ctx: PipelineContext) -> Result<Response> {
+        // Continue the caller's trace before any child spans start
+        if let Some(trace) = TraceContext::from_metadata(&pipeline_ctx.message.metadata) {
+            trace.attach(&tracing::Span::current());
+        }
         let start = std::time::Instant::now();
         self.metrics.increment_requests();
 
         // Apply middleware pre-processing
+        let mut message = pipeline_ctx.message;
         for mw in &self.middleware {
             message = mw.before_pipeline(message).await?;
         }
 
-        // Create pipeline context
-        let mut pipeline_ctx = PipelineContext {
-            message,
-            context,
-            metadata: HashMap::default(),
-        };
+        // Decide on verbose tracing before stages rewrite the message
+        let message_id = message.id;
+        let sampled = self.sampler.samples(&message);
+        let prompt = (sampled || self.config.trace_sampling.sample_errors)
+            .then(|| self.sampler.prompt_text(&message.content));
+        pipeline_ctx.message = message;
 
         // Process through stages
+        let mut stage_timings = Vec::with_capacity(self.stages.len());
         for stage in &self.stages {
             debug!("Processing stage: {}", stage.name());
-            pipeline_ctx = stage.process(pipeline_ctx).await?;
+            let stage_start = std::time::Instant::now();
+            let span = info_span!("pipeline.stage", stage = stage.name());
+            pipeline_ctx = match stage.process(pipeline_ctx).instrument(span).await {
+                Ok(ctx) => ctx,
+                Err(e) => {
+                    if let Some(prompt) = &prompt {
+                        warn!(
+                            target: TRACE_LOG_TARGET,
+                            message_id = %message_id,
+                            prompt = %prompt,
+                            stages = ?stage_timings,
+                            failed_stage = stage.name(),
+                            error = %e,
+                            "Pipeline request failed"
+                        );
+                    }
+                    return Err(e);
+                }
+            };
+            stage_timings.push(StageTrace {
+                name: stage.name().to_string(),
+                duration_ms: millis(stage_start.elapsed()),
+            });
         }
 
+        let conversation_id = pipeline_ctx.message.conversation_id.clone();
+        let mut trace = ExecutionTrace {
+            stages: stage_timings,
+            route: metadata_str(&pipeline_ctx.metadata, "route"),
+            model: metadata_str(&pipeline_ctx.metadata, "model")
+                .unwrap_or_else(|| self.model.clone()),
+            retries: pipeline_ctx
+                .metadata
+                .get("retries")
+                .and_then(serde_json::Value::as_u64)
+                .map_or(0, |n| u32::try_from(n).unwrap_or(u32::MAX)),
+            cache: metadata_str(&pipeline_ctx.metadata, "cache_status"),
+            duration_ms: 0,
+        };
+
         // Generate response
-        let mut response = self.generate_response(pipeline_ctx)?;
+        let mut response = Self::generate_response(pipeline_ctx);
 
         // Apply middleware post-processing
         for mw in self.middleware.iter().rev() {
@@ -107,6 +219,34 @@ impl MessagePipeline {
         let duration = start.elapsed();
         self.metrics.record_processing_time(duration);
 
+        trace.duration_ms = millis(duration);
+        if sampled {
+            info!(
+                target: TRACE_LOG_TARGET,
+                message_id = %message_id,
+                conversation_id = %conversation_id,
+                prompt = %prompt.as_deref().unwrap_or_default(),
+                trace = %serde_json::to_string(&trace)?,
+                "Verbose request trace"
+            );
+        } else {
+            info!(
+                target: TRACE_LOG_TARGET,
+                message_id = %message_id,
+                conversation_id = %conversation_id,
+                route = trace.route.as_deref().unwrap_or_default(),
+                model = %trace.model,
+                duration_ms = trace.duration_ms,
+                "Request summary"
+            );
+        }
+
+        if self.config.attach_trace || sampled {
+            response
+                .metadata
+                .insert(TRACE_METADATA_KEY.to_string(), serde_json::to_value(trace)?);
+        }
+
         debug!("Pipeline processed in {:?}", duration);
         Ok(response)
     }
@@ -121,42 +261,202 @@ impl MessagePipeline {
         self.middleware.push(middleware);
     }
 
+    /// Names of the stages, in the order messages pass through them
+    #[must_use]
+    pub fn stage_names(&self) -> Vec<String> {
+        self.stages
+            .iter()
+            .map(|stage| stage.name().to_string())
+            .collect()
+    }
+
     /// Get pipeline metrics
     #[must_use]
     pub fn metrics(&self) -> &PipelineMetrics {
         &self.metrics
     }
 
+    /// Provisioned throughput routing, if any deployments are configured
+    #[must_use]
+    pub fn provisioned_throughput(&self) -> Option<&Arc<ProvisionedThroughputManager>> {
+        self.provisioned.as_ref()
+    }
+
     // Private helper methods
 
-    fn create_stage(name: &str, config: &BotConfig) -> Result<Box<dyn PipelineStage>> {
+    fn create_stage(
+        name: &str,
+        config: &BotConfig,
+        provisioned: Option<&Arc<ProvisionedThroughputManager>>,
+        provider: Option<&Arc<dyn Provider>>,
+        retriever: Option<&Arc<Retriever>>,
+    ) -> Result<Box<dyn PipelineStage>> {
         match name {
             "sanitize" => Ok(Box::new(SanitizeStage::new())),
             "enrich" => Ok(Box::new(EnrichStage::new())),
-            "route" => Ok(Box::new(RouteStage::new())),
-            "process" => Ok(Box::new(ProcessStage::new(config.clone()))),
-            "format" => Ok(Box::new(FormatStage::new())),
+            "route" => Ok(Box::new(route_stage(config, provisioned.cloned()))),
+            "process" => Ok(Box::new(process_stage(config, provider.cloned()))),
+            "format" => Ok(Box::new(FormatStage::from_config(
+                &config.pipeline_config.user_errors,
+            ))),
+            "cite" => Ok(Box::new(crate::citation::CitationStage::new())),
+            "extract" => Ok(Box::new(crate::extraction::ExtractStage::default())),
+            "retrieve" => {
+                let retriever = retriever.ok_or_else(|| {
+                    Error::Configuration("The retrieve stage needs a retriever".to_string())
+                })?;
+                Ok(Box::new(RetrieveStage::new(
+                    retriever.clone(),
+                    config.pipeline_config.retrieval.clone(),
+                )))
+            }
             _ => Err(Error::Configuration(format!("Unknown pipeline stage: {name}")).into()),
         }
     }
 
-    #[allow(clippy::unused_self)]
-    fn generate_response(&self, ctx: PipelineContext) -> Result<Response> {
-        // Extract response from pipeline context
-        if let Some(response) = ctx.metadata.get("response") {
-            let response: Response = serde_json::from_value(response.clone())
-                .context("Failed to deserialize response")?;
-            Ok(response)
-        } else {
-            // Create default response if none was generated
-            Ok(Response::text(
+    fn generate_response(ctx: PipelineContext) -> Response {
+        // Create default response if no stage produced one
+        let mut response = ctx.response.unwrap_or_else(|| {
+            Response::text(
                 ctx.message.conversation_id,
                 "Message processed successfully",
-            ))
+            )
+        });
+        if !ctx.costs.is_empty() {
+            response.add_costs(&ctx.costs);
         }
+        if let Some(downgrade) = ctx.metadata.get(BUDGET_DOWNGRADE_METADATA_KEY) {
+            response
+                .metadata
+                .insert(BUDGET_DOWNGRADE_METADATA_KEY.to_string(), downgrade.clone());
+        }
+        response
     }
 }
 
+/// The provisioned throughput manager for `config`, if any deployments are configured
+fn provisioned_throughput(config: &BotConfig) -> Option<Arc<ProvisionedThroughputManager>> {
+    let manager = ProvisionedThroughputManager::new(config.provisioned_throughput.clone())
+        .with_output_reserve(config.max_tokens as u64);
+    manager.is_enabled().then(|| Arc::new(manager))
+}
+
+/// The routing stage for `config`
+fn route_stage(
+    config: &BotConfig,
+    provisioned: Option<Arc<ProvisionedThroughputManager>>,
+) -> RouteStage {
+    let stage = RouteStage::new().with_model_selection(ModelSelector::new(config));
+    match provisioned {
+        Some(manager) => stage.with_provisioned_throughput(manager),
+        None => stage,
+    }
+}
+
+/// The processing stage for `config`
+fn process_stage(config: &BotConfig, provider: Option<Arc<dyn Provider>>) -> ProcessStage {
+    let stage = ProcessStage::new(config.clone());
+    match provider {
+        Some(provider) => stage.with_provider(provider),
+        None => stage,
+    }
+}
+
+/// Response metadata key holding the [`ExecutionTrace`]
+///
+/// Only present when [`PipelineConfig::attach_trace`] is enabled or the
+/// request was sampled for a verbose trace.
+pub const TRACE_METADATA_KEY: &str = "universal_bot.trace";
+
+/// Log target for per-request summaries and verbose traces
+pub const TRACE_LOG_TARGET: &str = "universal_bot::trace";
+
+/// Chooses which requests get verbose traces
+///
+/// Decisions are keyed on the message ID, so a message is either always or
+/// never sampled, however many times it is replayed.
+#[derive(Debug, Clone, Default)]
+pub struct TraceSampler {
+    config: TraceSamplingConfig,
+}
+
+impl TraceSampler {
+    /// Sample with the given policy
+    #[must_use]
+    pub fn new(config: TraceSamplingConfig) -> Self {
+        Self { config }
+    }
+
+    /// Whether `message` gets a verbose trace
+    #[must_use]
+    pub fn samples(&self, message: &Message) -> bool {
+        const BUCKETS: u16 = 10_000;
+        let bucket = u16::try_from(message.id.as_u128() % u128::from(BUCKETS)).unwrap_or(0);
+        f64::from(bucket) < self.config.verbose_rate.clamp(0.0, 1.0) * f64::from(BUCKETS)
+    }
+
+    /// Prompt text as it may appear in a verbose trace
+    #[must_use]
+    pub fn prompt_text(&self, content: &str) -> String {
+        if self.config.redact_prompts {
+            crate::sanitize::redact_pii(content)
+        } else {
+            content.to_string()
+        }
+    }
+}
+
+/// Condensed record of how a response was produced
+///
+/// Stages can contribute to the trace by setting `retries` (a number) or
+/// `cache_status` (a string) in [`PipelineContext::metadata`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionTrace {
+    /// Stages run, in order
+    pub stages: Vec<StageTrace>,
+    /// Route chosen by the routing stage
+    pub route: Option<String>,
+    /// Model selected for generation
+    pub model: String,
+    /// Provider retries performed
+    pub retries: u32,
+    /// Cache outcome, e.g. `hit` or `miss`
+    pub cache: Option<String>,
+    /// Total pipeline time in milliseconds
+    pub duration_ms: u64,
+}
+
+impl ExecutionTrace {
+    /// Read the trace attached to a response, if any
+    #[must_use]
+    pub fn from_response(response: &Response) -> Option<Self> {
+        response
+            .metadata
+            .get(TRACE_METADATA_KEY)
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+    }
+}
+
+/// Timing of a single pipeline stage
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageTrace {
+    /// Stage name
+    pub name: String,
+    /// Time spent in the stage in milliseconds
+    pub duration_ms: u64,
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+fn metadata_str(metadata: &HashMap<String, serde_json::Value>, key: &str) -> Option<String> {
+    metadata
+        .get(key)
+        .and_then(serde_json::Value::as_str)
+        .map(str::to_string)
+}
+
 /// Pipeline processing context
 #[derive(Debug)]
 pub struct PipelineContext {
@@ -166,6 +466,49 @@ pub struct PipelineContext {
     pub context: Arc<RwLock<Context>>,
     /// Pipeline metadata
     pub metadata: std::collections::HashMap<String, serde_json::Value>,
+    /// Response produced by the processing stage, refined by later stages
+    pub response: Option<Response>,
+    /// Costs stages incurred besides inference, such as embeddings, tools,
+    /// and cache savings; added to the response's usage
+    pub costs: CostBreakdown,
+    /// Registry prompt version assigned by the bot, rendered as the system prompt
+    ///
+    /// Kept off the message so callers cannot replace the system prompt
+    /// through message metadata.
+    pub prompt: Option<Arc<PromptTemplate>>,
+    /// System prompt to use instead of the configured one, such as a
+    /// [`BranchVariant`](crate::branch::BranchVariant)'s
+    pub system_prompt: Option<String>,
+}
+
+impl PipelineContext {
+    /// Create a context for a message entering the pipeline
+    #[must_use]
+    pub fn new(message: Message, context: Arc<RwLock<Context>>) -> Self {
+        Self {
+            message,
+            context,
+            metadata: HashMap::new(),
+            response: None,
+            costs: CostBreakdown::default(),
+            prompt: None,
+            system_prompt: None,
+        }
+    }
+
+    /// Render `prompt` as the system prompt
+    #[must_use]
+    pub fn with_prompt(mut self, prompt: Option<Arc<PromptTemplate>>) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    /// Use `prompt` instead of the configured system prompt
+    #[must_use]
+    pub fn with_system_prompt(mut self, prompt: Option<String>) -> Self {
+        self.system_prompt = prompt;
+        self
+    }
 }
 
 /// Trait for pipeline stages
@@ -192,87 +535,222 @@ pub trait PipelineMiddleware: Send + Sync {
     }
 }
 
-/// Sanitization stage - cleans and validates input
-struct SanitizeStage;
+/// A pipeline stage with statically dispatched, unboxed processing
+///
+/// Unlike [`PipelineStage`], whose futures are boxed and called through a
+/// vtable, `StaticStage` is composed at compile time: tuples of stages are
+/// themselves stages that run each element in order. Use it with
+/// [`StaticPipeline`] for fixed pipelines; a `Vec<Box<dyn PipelineStage>>`
+/// is also a `StaticStage`, so plugin-injected stages can still be appended.
+pub trait StaticStage: Send + Sync {
+    /// Process the pipeline context
+    fn process(
+        &self,
+        ctx: PipelineContext,
+    ) -> impl std::future::Future<Output = Result<PipelineContext>> + Send;
+}
 
-impl SanitizeStage {
-    fn new() -> Self {
-        Self
-    }
+macro_rules! impl_static_stage_for_builtin {
+    ($($stage:ty),*) => {
+        $(
+            impl StaticStage for $stage {
+                async fn process(&self, ctx: PipelineContext) -> Result<PipelineContext> {
+                    self.apply(ctx)
+                }
+            }
+        )*
+    };
 }
 
-#[async_trait]
-impl PipelineStage for SanitizeStage {
-    fn name(&self) -> &str {
-        "sanitize"
+impl_static_stage_for_builtin!(SanitizeStage, EnrichStage, RouteStage, FormatStage);
+
+impl StaticStage for ProcessStage {
+    async fn process(&self, ctx: PipelineContext) -> Result<PipelineContext> {
+        self.generate(ctx).await
     }
+}
+
+macro_rules! impl_static_stage_for_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: StaticStage),+> StaticStage for ($($name,)+) {
+            #[allow(non_snake_case)]
+            async fn process(&self, ctx: PipelineContext) -> Result<PipelineContext> {
+                let ($($name,)+) = self;
+                $(let ctx = $name.process(ctx).await?;)+
+                Ok(ctx)
+            }
+        }
+    };
+}
+
+impl_static_stage_for_tuple!(A);
+impl_static_stage_for_tuple!(A, B);
+impl_static_stage_for_tuple!(A, B, C);
+impl_static_stage_for_tuple!(A, B, C, D);
+impl_static_stage_for_tuple!(A, B, C, D, E);
+impl_static_stage_for_tuple!(A, B, C, D, E, F);
+impl_static_stage_for_tuple!(A, B, C, D, E, F, G);
+impl_static_stage_for_tuple!(A, B, C, D, E, F, G, H);
 
+impl StaticStage for Vec<Box<dyn PipelineStage>> {
     async fn process(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
+        for stage in self {
+            debug!("Processing stage: {}", stage.name());
+            ctx = stage.process(ctx).await?;
+        }
+        Ok(ctx)
+    }
+}
+
+/// The stages of [`StaticPipeline::standard`]
+pub type StandardStages = (
+    SanitizeStage,
+    EnrichStage,
+    RouteStage,
+    ProcessStage,
+    FormatStage,
+);
+
+/// Pipeline whose stages are fixed at compile time
+///
+/// Runs the same stage contract as [`MessagePipeline`] without middleware or
+/// per-stage boxing. Prefer it for fixed pipelines on hot paths; keep
+/// [`MessagePipeline`] when stages are chosen at runtime.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::sync::Arc;
+/// # use parking_lot::RwLock;
+/// use universal_bot_core::pipeline::{SanitizeStage, StaticPipeline};
+/// use universal_bot_core::{Context, Message};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let pipeline = StaticPipeline::new((SanitizeStage::new(),));
+/// let context = Arc::new(RwLock::new(Context::new("conv")));
+/// let response = pipeline.process(Message::text("Hello"), context).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct StaticPipeline<S> {
+    stages: S,
+}
+
+impl<S: StaticStage> StaticPipeline<S> {
+    /// Create a pipeline from a stage or tuple of stages
+    #[must_use]
+    pub const fn new(stages: S) -> Self {
+        Self { stages }
+    }
+
+    /// Get the stages
+    #[must_use]
+    pub const fn stages(&self) -> &S {
+        &self.stages
+    }
+
+    /// Process a message through the stages
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any stage fails
+    pub async fn process(
+        &self,
+        message: Message,
+        context: Arc<RwLock<Context>>,
+    ) -> Result<Response> {
+        let ctx = self
+            .stages
+            .process(PipelineContext::new(message, context))
+            .await?;
+        Ok(MessagePipeline::generate_response(ctx))
+    }
+}
+
+impl StaticPipeline<StandardStages> {
+    /// The built-in sanitize, enrich, route, process, and format stages
+    ///
+    /// Without a provider the process stage answers with placeholder text;
+    /// use [`standard_with_provider`](Self::standard_with_provider) to
+    /// generate replies.
+    #[must_use]
+    pub fn standard(config: &BotConfig) -> Self {
+        Self::standard_with_provider(config, None)
+    }
+
+    /// The built-in stages, with the process stage generating replies with `provider`
+    #[must_use]
+    pub fn standard_with_provider(config: &BotConfig, provider: Option<Arc<dyn Provider>>) -> Self {
+        Self::new((
+            SanitizeStage::new(),
+            EnrichStage::new(),
+            route_stage(config, provisioned_throughput(config)),
+            process_stage(config, provider),
+            FormatStage::from_config(&config.pipeline_config.user_errors),
+        ))
+    }
+}
+
+/// Metadata keys the bot sets itself, which the sanitize stage leaves intact
+const INTERNAL_METADATA_KEYS: &[&str] = &[OUTPUT_SCHEMA_METADATA_KEY];
+
+/// Sanitization stage - cleans and validates input
+#[derive(Debug, Default)]
+pub struct SanitizeStage;
+
+impl SanitizeStage {
+    /// Create the stage
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Run the stage synchronously
+    pub fn apply(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
         // Sanitize message content
-        ctx.message.content = self.sanitize_content(&ctx.message.content);
+        ctx.message
+            .content
+            .update(|content| std::borrow::Cow::Owned(crate::sanitize::sanitize_content(content)));
 
         // Validate message
         ctx.message
             .validate()
             .context("Message validation failed")?;
 
-        // Remove sensitive data from metadata
-        self.sanitize_metadata(&mut ctx.message.metadata);
+        // Remove sensitive data from caller-supplied metadata
+        crate::sanitize::sanitize_metadata_except(
+            &mut ctx.message.metadata,
+            INTERNAL_METADATA_KEYS,
+        );
 
         Ok(ctx)
     }
 }
 
-impl SanitizeStage {
-    #[allow(clippy::unused_self)]
-    fn sanitize_content(&self, content: &str) -> String {
-        // Remove control characters
-        let sanitized = content
-            .chars()
-            .filter(|c| !c.is_control() || c.is_whitespace())
-            .collect::<String>();
-
-        // Trim excessive whitespace
-        sanitized
-            .lines()
-            .map(str::trim)
-            .filter(|line| !line.is_empty())
-            .collect::<Vec<_>>()
-            .join("\n")
+#[async_trait]
+impl PipelineStage for SanitizeStage {
+    fn name(&self) -> &str {
+        "sanitize"
     }
 
-    #[allow(clippy::unused_self)]
-    fn sanitize_metadata(
-        &self,
-        metadata: &mut std::collections::HashMap<String, serde_json::Value>,
-    ) {
-        // Remove potentially sensitive keys
-        const SENSITIVE_KEYS: &[&str] = &["password", "token", "secret", "api_key", "auth"];
-
-        metadata.retain(|key, _| {
-            !SENSITIVE_KEYS
-                .iter()
-                .any(|&sensitive| key.to_lowercase().contains(sensitive))
-        });
+    async fn process(&self, ctx: PipelineContext) -> Result<PipelineContext> {
+        self.apply(ctx)
     }
 }
 
 /// Enrichment stage - adds context and metadata
-struct EnrichStage;
+#[derive(Debug, Default)]
+pub struct EnrichStage;
 
 impl EnrichStage {
-    fn new() -> Self {
+    /// Create the stage
+    #[must_use]
+    pub fn new() -> Self {
         Self
     }
-}
-
-#[async_trait]
-impl PipelineStage for EnrichStage {
-    fn name(&self) -> &str {
-        "enrich"
-    }
 
-    async fn process(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
+    /// Run the stage synchronously
+    pub fn apply(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
         // Add timestamp if not present
         ctx.metadata.insert(
             "processed_at".to_string(),
@@ -303,6 +781,17 @@ impl PipelineStage for EnrichStage {
     }
 }
 
+#[async_trait]
+impl PipelineStage for EnrichStage {
+    fn name(&self) -> &str {
+        "enrich"
+    }
+
+    async fn process(&self, ctx: PipelineContext) -> Result<PipelineContext> {
+        self.apply(ctx)
+    }
+}
+
 impl EnrichStage {
     #[allow(clippy::unused_self)]
     fn detect_language(&self, _content: &str) -> &str {
@@ -312,21 +801,49 @@ impl EnrichStage {
 }
 
 /// Routing stage - determines processing path
-struct RouteStage;
+///
+/// With a [`ModelSelector`] the stage also resolves the model for the
+/// message into the `model` and `model_source` metadata, and answers the
+/// `/model` command. A model downgraded under budget pressure is recorded
+/// under [`BUDGET_DOWNGRADE_METADATA_KEY`] and copied to the response. With
+/// a [`ProvisionedThroughputManager`] the resolved model is then rewritten
+/// to provisioned capacity when it has headroom, and the capacity used is
+/// recorded under [`CAPACITY_METADATA_KEY`].
+#[derive(Debug, Default)]
+pub struct RouteStage {
+    selector: Option<ModelSelector>,
+    provisioned: Option<Arc<ProvisionedThroughputManager>>,
+}
 
 impl RouteStage {
-    fn new() -> Self {
-        Self
+    /// Create the stage
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            selector: None,
+            provisioned: None,
+        }
     }
-}
 
-#[async_trait]
-impl PipelineStage for RouteStage {
-    fn name(&self) -> &str {
-        "route"
+    /// Prefer provisioned capacity for the resolved model
+    #[must_use]
+    pub fn with_provisioned_throughput(
+        mut self,
+        manager: Arc<ProvisionedThroughputManager>,
+    ) -> Self {
+        self.provisioned = Some(manager);
+        self
     }
 
-    async fn process(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
+    /// Resolve models and handle `/model` with the given selector
+    #[must_use]
+    pub fn with_model_selection(mut self, selector: ModelSelector) -> Self {
+        self.selector = Some(selector);
+        self
+    }
+
+    /// Run the stage synchronously
+    pub fn apply(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
         use crate::message::MessageType;
 
         // Determine route based on message type
@@ -362,10 +879,69 @@ impl PipelineStage for RouteStage {
             _ => {}
         }
 
+        if let Some(selector) = &self.selector {
+            if route == "command"
+                && self.extract_command(&ctx.message.content).as_deref() == Some("model")
+            {
+                let reply = {
+                    let mut context = ctx.context.write();
+                    selector
+                        .handle_command(&ctx.message, &mut context)
+                        .unwrap_or_else(|e| format!("Cannot change model: {e}"))
+                };
+                ctx.metadata
+                    .insert("command_output".to_string(), serde_json::json!(reply));
+            }
+
+            let mut selection = selector.resolve(&ctx.message, &ctx.context.read());
+            if let Some(downgrade) = selector.downgrade(&selection, &ctx.message) {
+                selection.model.clone_from(&downgrade.to);
+                ctx.metadata.insert(
+                    BUDGET_DOWNGRADE_METADATA_KEY.to_string(),
+                    serde_json::to_value(downgrade)?,
+                );
+            }
+            ctx.metadata
+                .insert("model".to_string(), serde_json::json!(selection.model));
+            ctx.metadata.insert(
+                "model_source".to_string(),
+                serde_json::json!(selection.source),
+            );
+        }
+
+        if let Some(manager) = &self.provisioned {
+            let model = ctx
+                .metadata
+                .get("model")
+                .and_then(|m| m.as_str())
+                .map(str::to_string);
+            if let Some(model) = model {
+                let estimate = manager.estimate_tokens(&ctx.message.content);
+                let route = manager.route(&model, estimate);
+                ctx.metadata
+                    .insert("model".to_string(), serde_json::json!(route.model));
+                ctx.metadata.insert(
+                    CAPACITY_METADATA_KEY.to_string(),
+                    serde_json::json!(route.capacity),
+                );
+            }
+        }
+
         Ok(ctx)
     }
 }
 
+#[async_trait]
+impl PipelineStage for RouteStage {
+    fn name(&self) -> &str {
+        "route"
+    }
+
+    async fn process(&self, ctx: PipelineContext) -> Result<PipelineContext> {
+        self.apply(ctx)
+    }
+}
+
 impl RouteStage {
     #[allow(clippy::unused_self)]
     fn extract_command(&self, content: &str) -> Option<String> {
@@ -381,27 +957,140 @@ impl RouteStage {
 }
 
 /// Processing stage - main AI processing
-struct ProcessStage {
-    #[allow(dead_code)]
+///
+/// Messages on the default route are answered by the stage's [`Provider`]
+/// with the conversation history as context; the response carries the
+/// provider's token usage. Commands, system and error messages, media, and
+/// every message when no provider is set get placeholder text instead.
+pub struct ProcessStage {
     config: BotConfig,
+    provider: Option<Arc<dyn Provider>>,
 }
 
-impl ProcessStage {
-    fn new(config: BotConfig) -> Self {
-        Self { config }
+impl std::fmt::Debug for ProcessStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProcessStage")
+            .field("model", &self.config.model)
+            .field("provider", &self.provider.as_ref().map(|p| p.name()))
+            .finish_non_exhaustive()
     }
 }
 
-#[async_trait]
-impl PipelineStage for ProcessStage {
-    fn name(&self) -> &str {
-        "process"
+impl ProcessStage {
+    /// Create the stage for the given bot configuration
+    #[must_use]
+    pub fn new(config: BotConfig) -> Self {
+        Self {
+            config,
+            provider: None,
+        }
     }
 
-    async fn process(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
-        // This is where we would integrate with AI providers
-        // For now, create a simple response
+    /// Generate replies with `provider`
+    #[must_use]
+    pub fn with_provider(mut self, provider: Arc<dyn Provider>) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    /// Run the stage, calling the provider for messages on the default route
+    ///
+    /// The model is the one chosen by the route stage, or the configured
+    /// model. A provider named under [`PROVIDER_METADATA_KEY`] in the
+    /// pipeline or message metadata is passed on for a
+    /// [`ProviderRegistry`](crate::provider::ProviderRegistry) to honour.
+    /// Under [`Bot::process_stream`](crate::Bot::process_stream) the reply
+    /// is generated with [`Provider::generate_streaming`]. A schema under
+    /// [`OUTPUT_SCHEMA_METADATA_KEY`] in the message metadata is added to
+    /// the system prompt, which is [`PipelineContext::system_prompt`] if
+    /// set. With prompt templates enabled, the system prompt
+    /// is first rendered with the conversation's variables; an assigned
+    /// [`PipelineContext::prompt`] is rendered in its place, and its ID
+    /// stamped on the response under [`PROMPT_VERSION_METADATA_KEY`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the system prompt template does not render or
+    /// the provider fails.
+    pub async fn generate(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
+        let route = metadata_str(&ctx.metadata, "route");
+        let Some(provider) = self
+            .provider
+            .as_ref()
+            .filter(|_| route.as_deref().unwrap_or("default") == "default")
+        else {
+            return self.apply(ctx);
+        };
 
+        let model =
+            metadata_str(&ctx.metadata, "model").unwrap_or_else(|| self.config.model.clone());
+        let mut request =
+            ProviderRequest::new(&self.config, &model, &ctx.context.read(), &ctx.message);
+        if let Some(prompt) = &ctx.system_prompt {
+            request.system_prompt = Some(prompt.clone());
+        }
+        let templates = &self.config.pipeline_config.prompt_templates;
+        let mut prompt_version = None;
+        if let Some(template) = &ctx.prompt {
+            let rendered = template.render(&ctx.context.read().variables)?;
+            request.system_prompt = Some(rendered);
+            prompt_version = Some(template.id());
+        } else if let Some(source) = request
+            .system_prompt
+            .as_deref()
+            .filter(|_| templates.enabled)
+        {
+            let template = PromptTemplate {
+                partials: templates.partials.clone(),
+                ..PromptTemplate::new("system_prompt", source)
+            };
+            let rendered = template.render(&ctx.context.read().variables)?;
+            request.system_prompt = Some(rendered);
+        }
+        request.provider = metadata_str(&ctx.metadata, PROVIDER_METADATA_KEY)
+            .or_else(|| metadata_str(&ctx.message.metadata, PROVIDER_METADATA_KEY));
+        let documents = retrieval::retrieved_documents(&ctx.metadata);
+        if let Some(references) = retrieval::prompt_context(
+            &documents,
+            self.config.pipeline_config.retrieval.max_context_chars,
+        ) {
+            request.system_prompt = Some(match request.system_prompt.take() {
+                Some(prompt) => format!("{prompt}\n\n{references}"),
+                None => references,
+            });
+        }
+        if let Some(schema) = ctx.message.metadata.get(OUTPUT_SCHEMA_METADATA_KEY) {
+            let instruction = structured::schema_instruction(schema);
+            request.system_prompt = Some(match request.system_prompt.take() {
+                Some(prompt) => format!("{prompt}\n\n{instruction}"),
+                None => instruction,
+            });
+        }
+        let reply = match ChunkSink::current() {
+            Some(sink) => provider.generate_streaming(request, &sink).await,
+            None => provider.generate(request).await,
+        }
+        .with_context(|| format!("Provider {} failed to generate a reply", provider.name()))?;
+
+        let mut response = Response::text(ctx.message.conversation_id.clone(), reply.content)
+            .with_usage(reply.usage);
+        if let Some(reason) = reply.finish_reason {
+            response
+                .metadata
+                .insert("finish_reason".to_string(), serde_json::json!(reason));
+        }
+        if let Some(version) = prompt_version {
+            response.metadata.insert(
+                PROMPT_VERSION_METADATA_KEY.to_string(),
+                serde_json::json!(version),
+            );
+        }
+        ctx.response = Some(response);
+        Ok(ctx)
+    }
+
+    /// Run the stage synchronously with placeholder replies, never calling the provider
+    pub fn apply(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
         let route = ctx
             .metadata
             .get("route")
@@ -416,18 +1105,33 @@ impl PipelineStage for ProcessStage {
             _ => format!("Processing message: {}", ctx.message.content),
         };
 
-        let response = Response::text(ctx.message.conversation_id.clone(), response_content);
-
-        ctx.metadata
-            .insert("response".to_string(), serde_json::to_value(response)?);
+        ctx.response = Some(Response::text(
+            ctx.message.conversation_id.clone(),
+            response_content,
+        ));
 
         Ok(ctx)
     }
 }
 
+#[async_trait]
+impl PipelineStage for ProcessStage {
+    fn name(&self) -> &str {
+        "process"
+    }
+
+    async fn process(&self, ctx: PipelineContext) -> Result<PipelineContext> {
+        self.generate(ctx).await
+    }
+}
+
 impl ProcessStage {
     #[allow(clippy::unused_self)]
     fn process_command(&self, ctx: &PipelineContext) -> String {
+        if let Some(output) = metadata_str(&ctx.metadata, "command_output") {
+            return output;
+        }
+
         let command = ctx
             .metadata
             .get("command")
@@ -439,43 +1143,68 @@ impl ProcessStage {
 }
 
 /// Formatting stage - formats the response
-struct FormatStage;
+///
+/// Also turns errors into responses users can read, with messages from its
+/// [`ErrorCatalog`].
+#[derive(Debug, Default)]
+pub struct FormatStage {
+    errors: ErrorCatalog,
+}
 
 impl FormatStage {
-    fn new() -> Self {
-        Self
+    /// Create the stage
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
     }
-}
 
-#[async_trait]
-impl PipelineStage for FormatStage {
-    fn name(&self) -> &str {
-        "format"
+    /// Create the stage with configured error messages
+    #[must_use]
+    pub fn from_config(config: &UserErrorConfig) -> Self {
+        Self {
+            errors: ErrorCatalog::from_config(config),
+        }
     }
 
-    async fn process(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
-        if let Some(response_value) = ctx.metadata.get_mut("response") {
-            if let Ok(mut response) = serde_json::from_value::<Response>(response_value.clone()) {
-                // Apply formatting based on preferences
-                if let Some(format_pref) = ctx.message.metadata.get("format") {
-                    if let Some(format) = format_pref.as_str() {
-                        match format {
-                            "markdown" => {
-                                response.response_type = crate::message::ResponseType::Markdown;
-                            }
-                            "html" => {
-                                response.response_type = crate::message::ResponseType::Html;
-                                response.content = self.to_html(&response.content);
-                            }
-                            "json" => {
-                                response.response_type = crate::message::ResponseType::Json;
-                            }
-                            _ => {}
-                        }
+    /// A response to `message` explaining `error` in the user's language
+    ///
+    /// The response carries the catalog message and hint, never the error's
+    /// own text.
+    #[must_use]
+    pub fn error_response(
+        &self,
+        message: &Message,
+        error: &anyhow::Error,
+        locale: Option<&str>,
+    ) -> Response {
+        let shown = self.errors.describe(error, locale);
+        debug!("Answering {:?} error: {error:#}", shown.kind);
+        shown.into_response(message.conversation_id.clone())
+    }
+
+    /// Run the stage synchronously
+    pub fn apply(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
+        if let Some(response) = ctx.response.as_mut() {
+            // Apply formatting based on preferences
+            if let Some(format) = ctx
+                .message
+                .metadata
+                .get("format")
+                .and_then(serde_json::Value::as_str)
+            {
+                match format {
+                    "markdown" => {
+                        response.response_type = crate::message::ResponseType::Markdown;
+                    }
+                    "html" => {
+                        response.response_type = crate::message::ResponseType::Html;
+                        response.content = self.to_html(&response.content);
                     }
+                    "json" => {
+                        response.response_type = crate::message::ResponseType::Json;
+                    }
+                    _ => {}
                 }
-
-                *response_value = serde_json::to_value(response)?;
             }
         }
 
@@ -483,9 +1212,22 @@ impl PipelineStage for FormatStage {
     }
 }
 
+#[async_trait]
+impl PipelineStage for FormatStage {
+    fn name(&self) -> &str {
+        "format"
+    }
+
+    async fn process(&self, ctx: PipelineContext) -> Result<PipelineContext> {
+        self.apply(ctx)
+    }
+}
+
 impl FormatStage {
+    /// Render plain response text as escaped HTML
+    #[must_use]
     #[allow(clippy::unused_self)]
-    fn to_html(&self, content: &str) -> String {
+    pub fn to_html(&self, content: &str) -> String {
         // Simple HTML conversion
         format!(
             "<p>{}</p>",
@@ -580,6 +1322,7 @@ impl PipelineMiddleware for TimeoutMiddleware {
 pub struct PipelineMetrics {
     requests_total: Arc<RwLock<u64>>,
     processing_times: Arc<RwLock<Vec<Duration>>>,
+    processing_histogram: Arc<RwLock<LatencyHistogram>>,
 }
 
 impl PipelineMetrics {
@@ -587,6 +1330,7 @@ impl PipelineMetrics {
         Self {
             requests_total: Arc::new(RwLock::new(0)),
             processing_times: Arc::new(RwLock::new(Vec::new())),
+            processing_histogram: Arc::new(RwLock::new(LatencyHistogram::default())),
         }
     }
 
@@ -595,6 +1339,7 @@ impl PipelineMetrics {
     }
 
     fn record_processing_time(&self, duration: Duration) {
+        self.processing_histogram.write().record(duration);
         let mut times = self.processing_times.write();
         times.push(duration);
         if times.len() > 1000 {
@@ -608,6 +1353,12 @@ impl PipelineMetrics {
         *self.requests_total.read()
     }
 
+    /// Get the processing times since the pipeline was built, bucketed
+    #[must_use]
+    pub fn processing_time_histogram(&self) -> LatencyHistogram {
+        self.processing_histogram.read().clone()
+    }
+
     /// Get average processing time
     #[must_use]
     #[allow(clippy::cast_possible_truncation)]
@@ -633,19 +1384,460 @@ mod tests {
         assert!(pipeline.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_static_pipeline_matches_dynamic() {
+        let config = BotConfig::default();
+        let dynamic = MessagePipeline::new(&config).await.unwrap();
+        let fixed = StaticPipeline::standard(&config);
+
+        let message = Message::with_type("/help me", crate::message::MessageType::Command);
+        let context = Arc::new(RwLock::new(Context::new("conv")));
+
+        let expected = dynamic
+            .process(message.clone(), context.clone())
+            .await
+            .unwrap();
+        let actual = fixed.process(message, context).await.unwrap();
+        assert_eq!(actual.content, expected.content);
+        assert_eq!(actual.content, "Executing command: help");
+    }
+
+    #[tokio::test]
+    async fn test_static_pipeline_calls_provider() {
+        let config = BotConfig::default();
+        let dynamic = MessagePipeline::with_provider(&config, Some(Arc::new(EchoProvider)))
+            .await
+            .unwrap();
+        let fixed = StaticPipeline::standard_with_provider(&config, Some(Arc::new(EchoProvider)));
+
+        let context = Arc::new(RwLock::new(Context::new("conv")));
+        context.write().add_message(&Message::text("earlier"));
+        let expected = dynamic
+            .process(Message::text("now"), context.clone())
+            .await
+            .unwrap();
+        let actual = fixed.process(Message::text("now"), context).await.unwrap();
+        assert_eq!(actual.content, "2 turns, last: now");
+        assert_eq!(actual.content, expected.content);
+        assert_eq!(actual.usage.unwrap().total_tokens, 17);
+    }
+
+    #[tokio::test]
+    async fn test_static_pipeline_with_dynamic_tail() {
+        let tail: Vec<Box<dyn PipelineStage>> = vec![Box::new(FormatStage::new())];
+        let pipeline = StaticPipeline::new((ProcessStage::new(BotConfig::default()), tail));
+        let message = Message::text("hi").with_metadata("format", serde_json::json!("html"));
+        let context = Arc::new(RwLock::new(Context::new("conv")));
+
+        let response = pipeline.process(message, context).await.unwrap();
+        assert_eq!(response.response_type, crate::message::ResponseType::Html);
+    }
+
+    struct EchoProvider;
+
+    #[async_trait]
+    impl Provider for EchoProvider {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        async fn generate(
+            &self,
+            request: ProviderRequest,
+        ) -> Result<crate::provider::ProviderResponse> {
+            let last = request.messages.last().unwrap().content.clone();
+            Ok(crate::provider::ProviderResponse {
+                content: format!("{} turns, last: {last}", request.messages.len()),
+                usage: crate::message::TokenUsage::new(12, 5, request.model),
+                finish_reason: Some("end_turn".to_string()),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_stage_calls_provider() {
+        let config = BotConfig::default();
+        let pipeline = MessagePipeline::with_provider(&config, Some(Arc::new(EchoProvider)))
+            .await
+            .unwrap();
+        let context = Arc::new(RwLock::new(Context::new("conv")));
+        context.write().add_message(&Message::text("earlier"));
+
+        let response = pipeline
+            .process(Message::text("now"), context.clone())
+            .await
+            .unwrap();
+        assert_eq!(response.content, "2 turns, last: now");
+        let usage = response.usage.unwrap();
+        assert_eq!(usage.total_tokens, 17);
+        assert_eq!(usage.model, config.model);
+        assert_eq!(response.metadata["finish_reason"], "end_turn");
+
+        let command = Message::with_type("/help", crate::message::MessageType::Command);
+        let response = pipeline.process(command, context).await.unwrap();
+        assert_eq!(response.content, "Executing command: help");
+    }
+
+    struct SystemPromptProvider;
+
+    #[async_trait]
+    impl Provider for SystemPromptProvider {
+        fn name(&self) -> &str {
+            "system-prompt"
+        }
+
+        async fn generate(
+            &self,
+            request: ProviderRequest,
+        ) -> Result<crate::provider::ProviderResponse> {
+            Ok(crate::provider::ProviderResponse {
+                content: request.system_prompt.unwrap_or_default(),
+                usage: crate::message::TokenUsage::new(1, 1, request.model),
+                finish_reason: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_system_prompt_renders_as_template() {
+        let mut config = BotConfig {
+            system_prompt: Some("{{> persona}}{{#if plan}} Plan: {{plan}}.{{/if}}".to_string()),
+            ..BotConfig::default()
+        };
+        let templates = &mut config.pipeline_config.prompt_templates;
+        templates.enabled = true;
+        templates
+            .partials
+            .insert("persona".to_string(), "You are {{bot_name}}.".to_string());
+        let pipeline =
+            MessagePipeline::with_provider(&config, Some(Arc::new(SystemPromptProvider)))
+                .await
+                .unwrap();
+
+        let mut context = Context::new("conv");
+        context.set_variable("bot_name", serde_json::json!("Ferris"));
+        context.set_variable("plan", serde_json::json!("pro"));
+        let response = pipeline
+            .process(Message::text("hi"), Arc::new(RwLock::new(context)))
+            .await
+            .unwrap();
+        assert_eq!(response.content, "You are Ferris. Plan: pro.");
+
+        let unset = pipeline
+            .process(
+                Message::text("hi"),
+                Arc::new(RwLock::new(Context::new("c2"))),
+            )
+            .await;
+        assert!(unset.is_err());
+    }
+
+    struct KeywordEmbedder;
+
+    #[async_trait]
+    impl crate::vector::Embedder for KeywordEmbedder {
+        fn model(&self) -> &str {
+            "keywords"
+        }
+
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| vec![f32::from(u8::from(text.contains("refund"))), 1.0])
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_stage_adds_documents_to_prompt() {
+        use crate::vector::{HnswIndex, VectorRecord, VectorStore};
+
+        let mut config = BotConfig {
+            system_prompt: Some("Be brief.".to_string()),
+            ..BotConfig::default()
+        };
+        config
+            .pipeline_config
+            .enabled_stages
+            .insert(3, "retrieve".to_string());
+        assert!(MessagePipeline::new(&config).await.is_err());
+
+        let store = Arc::new(HnswIndex::default());
+        store
+            .upsert(vec![VectorRecord::new(
+                "policy",
+                vec![1.0, 1.0],
+                "Refunds take five days.",
+            )])
+            .await
+            .unwrap();
+        let retriever = Arc::new(Retriever::new(Arc::new(KeywordEmbedder), store));
+        let pipeline = MessagePipeline::with_components(
+            &config,
+            Some(Arc::new(SystemPromptProvider)),
+            Some(retriever),
+        )
+        .await
+        .unwrap();
+
+        let context = Arc::new(RwLock::new(Context::new("conv")));
+        let response = pipeline
+            .process(Message::text("Where is my refund?"), context)
+            .await
+            .unwrap();
+        assert!(response.content.starts_with("Be brief.\n\n"));
+        assert!(response.content.contains("[1] Refunds take five days."));
+    }
+
+    struct ToolStage;
+
+    #[async_trait]
+    impl PipelineStage for ToolStage {
+        fn name(&self) -> &str {
+            "tool"
+        }
+
+        async fn process(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
+            ctx.costs.add_embeddings(0.001);
+            ctx.costs.add_tool("web_fetch", 0.002);
+            Ok(ctx)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stage_costs_join_response_usage() {
+        let config = BotConfig::default();
+        let mut pipeline = MessagePipeline::with_provider(&config, Some(Arc::new(EchoProvider)))
+            .await
+            .unwrap();
+        pipeline.add_stage(Box::new(ToolStage));
+        let context = Arc::new(RwLock::new(Context::new("conv")));
+
+        let response = pipeline
+            .process(Message::text("now"), context)
+            .await
+            .unwrap();
+        let usage = response.usage.as_ref().unwrap();
+        let cost = response.cost().unwrap();
+        assert!(cost.inference > 0.0);
+        assert_eq!(cost.tools[0].name, "web_fetch");
+        assert!((usage.estimated_cost - (cost.inference + 0.003)).abs() < 1e-12);
+    }
+
+    #[tokio::test]
+    async fn test_format_stage_updates_typed_response() {
+        let config = BotConfig::default();
+        let pipeline = MessagePipeline::new(&config).await.unwrap();
+        let context = Arc::new(RwLock::new(Context::new("conv")));
+        let message = Message::text("a < b").with_metadata("format", serde_json::json!("html"));
+
+        let response = pipeline.process(message, context).await.unwrap();
+
+        assert_eq!(response.response_type, crate::message::ResponseType::Html);
+        assert!(response.content.contains("a &lt; b"));
+    }
+
+    #[tokio::test]
+    async fn test_execution_trace_is_opt_in() {
+        let context = Arc::new(RwLock::new(Context::new("conv")));
+        let mut config = BotConfig::default();
+
+        let pipeline = MessagePipeline::new(&config).await.unwrap();
+        let response = pipeline
+            .process(Message::text("hi"), context.clone())
+            .await
+            .unwrap();
+        assert!(!response.metadata.contains_key(TRACE_METADATA_KEY));
+
+        config.pipeline_config.attach_trace = true;
+        let pipeline = MessagePipeline::new(&config).await.unwrap();
+        let response = pipeline
+            .process(
+                Message::with_type("/help", crate::message::MessageType::Command),
+                context,
+            )
+            .await
+            .unwrap();
+
+        let trace = ExecutionTrace::from_response(&response).unwrap();
+        let stages: Vec<&str> = trace.stages.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(stages, ["sanitize", "enrich", "route", "process", "format"]);
+        assert_eq!(trace.route.as_deref(), Some("command"));
+        assert_eq!(trace.model, config.model);
+        assert_eq!(trace.retries, 0);
+        assert!(trace.cache.is_none());
+    }
+
     #[test]
     fn test_sanitize_stage() {
-        let stage = SanitizeStage::new();
         let content = "Hello\x00World\x01Test";
-        let sanitized = stage.sanitize_content(content);
+        let sanitized = crate::sanitize::sanitize_content(content);
         assert!(!sanitized.contains('\x00'));
         assert!(!sanitized.contains('\x01'));
     }
 
+    #[test]
+    fn test_sanitize_stage_keeps_internal_metadata() {
+        let schema = serde_json::json!({ "properties": { "author": {}, "token_count": {} } });
+        let message = Message::text("hi")
+            .with_metadata(OUTPUT_SCHEMA_METADATA_KEY, schema.clone())
+            .with_metadata("author", serde_json::json!("jane"))
+            .with_metadata("session_token", serde_json::json!("t"));
+        let context = Arc::new(RwLock::new(Context::new("conv")));
+
+        let ctx = SanitizeStage::new()
+            .apply(PipelineContext::new(message, context))
+            .unwrap();
+
+        assert_eq!(ctx.message.metadata[OUTPUT_SCHEMA_METADATA_KEY], schema);
+        assert_eq!(ctx.message.metadata["author"], "jane");
+        assert!(!ctx.message.metadata.contains_key("session_token"));
+    }
+
     #[test]
     fn test_route_stage_command_extraction() {
         let stage = RouteStage::new();
         assert_eq!(stage.extract_command("/help me"), Some("help".to_string()));
         assert_eq!(stage.extract_command("not a command"), None);
     }
+
+    #[tokio::test]
+    async fn test_model_command_pins_conversation() {
+        let mut config = BotConfig::default();
+        config
+            .model_selection
+            .authorized_users
+            .push("admin".to_string());
+        let pipeline = StaticPipeline::standard(&config);
+        let context = Arc::new(RwLock::new(Context::new("conv")));
+
+        let command = Message::with_type(
+            "/model anthropic.claude-haiku",
+            crate::message::MessageType::Command,
+        )
+        .with_user_id("admin");
+        let response = pipeline.process(command, context.clone()).await.unwrap();
+        assert_eq!(response.content, "Model pinned to anthropic.claude-haiku");
+
+        let stage = RouteStage::new().with_model_selection(ModelSelector::new(&config));
+        let ctx = stage
+            .apply(PipelineContext::new(Message::text("hi"), context))
+            .unwrap();
+        assert_eq!(ctx.metadata["model"], "anthropic.claude-haiku");
+        assert_eq!(ctx.metadata["model_source"], "conversation");
+    }
+
+    #[tokio::test]
+    async fn test_route_stage_downgrades_under_budget_pressure() {
+        let mut config = BotConfig::default();
+        config.budget.max_cost_usd = Some(100.0);
+        config.budget.downgrade = Some(crate::config::BudgetDowngradePolicy::new([
+            config.model.as_str(),
+            "anthropic.claude-sonnet-4",
+            "anthropic.claude-haiku",
+        ]));
+        let pipeline = StaticPipeline::standard(&config);
+        let context = Arc::new(RwLock::new(Context::new("conv")));
+
+        let message = Message::text("hi").with_metadata(
+            crate::selection::BUDGET_PRESSURE_METADATA_KEY,
+            serde_json::json!(0.5),
+        );
+        let response = pipeline.process(message, context.clone()).await.unwrap();
+        assert!(!response
+            .metadata
+            .contains_key(BUDGET_DOWNGRADE_METADATA_KEY));
+
+        let message = Message::text("hi").with_metadata(
+            crate::selection::BUDGET_PRESSURE_METADATA_KEY,
+            serde_json::json!(0.85),
+        );
+        let response = pipeline.process(message, context).await.unwrap();
+        let downgrade = &response.metadata[BUDGET_DOWNGRADE_METADATA_KEY];
+        assert_eq!(downgrade["from"], config.model.as_str());
+        assert_eq!(downgrade["to"], "anthropic.claude-sonnet-4");
+        assert_eq!(downgrade["reason"], "85% of the budget is spent");
+    }
+
+    #[tokio::test]
+    async fn test_route_stage_prefers_provisioned_capacity() {
+        let arn = "arn:aws:bedrock:us-east-1:123456789012:provisioned-model/abc123";
+        let mut config = BotConfig {
+            max_tokens: 600,
+            ..BotConfig::default()
+        };
+        config
+            .provisioned_throughput
+            .models
+            .push(crate::config::ProvisionedModelConfig {
+                arn: arn.to_string(),
+                base_model: config.model.clone(),
+                model_units: 1,
+                tokens_per_minute_per_unit: 1_000,
+                commitment_expires_at: None,
+            });
+        let pipeline = MessagePipeline::new(&config).await.unwrap();
+        let manager = pipeline.provisioned_throughput().unwrap().clone();
+        let stage = route_stage(&config, Some(manager.clone()));
+        let context = Arc::new(RwLock::new(Context::new("conv")));
+
+        let ctx = stage
+            .apply(PipelineContext::new(Message::text("hi"), context.clone()))
+            .unwrap();
+        assert_eq!(ctx.metadata["model"], arn);
+        assert_eq!(ctx.metadata[CAPACITY_METADATA_KEY], "provisioned");
+
+        let ctx = stage
+            .apply(PipelineContext::new(Message::text("hi"), context))
+            .unwrap();
+        assert_eq!(ctx.metadata["model"], config.model.as_str());
+        assert_eq!(ctx.metadata[CAPACITY_METADATA_KEY], "spillover");
+        assert_eq!(manager.report()[0].spillovers, 1);
+    }
+
+    #[tokio::test]
+    async fn test_sampled_requests_carry_a_trace() {
+        let context = Arc::new(RwLock::new(Context::new("conv")));
+        let mut config = BotConfig::default();
+        config.pipeline_config.trace_sampling.verbose_rate = 1.0;
+
+        let pipeline = MessagePipeline::new(&config).await.unwrap();
+        let response = pipeline
+            .process(Message::text("hi"), context)
+            .await
+            .unwrap();
+        assert!(ExecutionTrace::from_response(&response).is_some());
+    }
+
+    #[test]
+    fn test_trace_sampler() {
+        let message = Message::text("mail me at bob@example.com");
+        let never = TraceSampler::default();
+        let always = TraceSampler::new(TraceSamplingConfig {
+            verbose_rate: 1.0,
+            ..TraceSamplingConfig::default()
+        });
+        assert!(!never.samples(&message));
+        assert!(always.samples(&message));
+
+        let half = TraceSampler::new(TraceSamplingConfig {
+            verbose_rate: 0.5,
+            ..TraceSamplingConfig::default()
+        });
+        assert_eq!(half.samples(&message), half.samples(&message));
+        let sampled = (0..1_000)
+            .filter(|_| half.samples(&Message::text("hi")))
+            .count();
+        assert!((350..650).contains(&sampled));
+
+        assert_eq!(
+            always.prompt_text(&message.content),
+            "mail me at [redacted]"
+        );
+        let raw = TraceSampler::new(TraceSamplingConfig {
+            redact_prompts: false,
+            ..TraceSamplingConfig::default()
+        });
+        assert_eq!(raw.prompt_text(&message.content), &*message.content);
+    }
 }
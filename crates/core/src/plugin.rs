@@ -5,15 +5,18 @@
 
 use std::collections::HashMap;
 use std::fmt;
+use std::sync::Arc;
 
 use anyhow::Result;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, info, instrument, warn};
+use tracing::{debug, info, info_span, instrument, warn, Instrument as _};
 
 use crate::{
     error::Error,
+    json_schema,
     message::{Message, Response},
+    trace_context,
 };
 
 /// Plugin trait for extending bot functionality
@@ -51,6 +54,19 @@ pub trait Plugin: Send + Sync {
         true
     }
 
+    /// JSON Schema of the request data for `tool`, one of the plugin's
+    /// [`CapabilityType::ToolProvider`] capabilities, if it publishes one
+    fn tool_schema(&self, _tool: &str) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// JSON Schema that successful responses' data for `request_type` must
+    /// match, checked by registries with
+    /// [`strict responses`](PluginRegistry::with_strict_responses)
+    fn response_schema(&self, _request_type: &RequestType) -> Option<serde_json::Value> {
+        None
+    }
+
     /// Get plugin metadata
     fn metadata(&self) -> PluginMetadata {
         PluginMetadata {
@@ -157,6 +173,7 @@ impl Default for ResourceLimits {
 }
 
 /// Plugin request
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PluginRequest {
     /// Request ID
@@ -170,6 +187,7 @@ pub struct PluginRequest {
 }
 
 /// Request type
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum RequestType {
@@ -186,6 +204,7 @@ pub enum RequestType {
 }
 
 /// Plugin response
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PluginResponse {
     /// Response ID
@@ -242,10 +261,14 @@ pub struct PluginMetadata {
 }
 
 /// Plugin registry for managing plugins
+///
+/// Cloning is cheap: clones share the registered plugins.
+#[derive(Clone)]
 pub struct PluginRegistry {
-    plugins: HashMap<String, Box<dyn Plugin>>,
+    plugins: HashMap<String, Arc<dyn Plugin>>,
     hooks: HashMap<HookType, Vec<String>>,
     permissions: HashMap<String, Vec<Permission>>,
+    strict_responses: bool,
 }
 
 impl PluginRegistry {
@@ -256,9 +279,19 @@ impl PluginRegistry {
             plugins: HashMap::new(),
             hooks: HashMap::new(),
             permissions: HashMap::new(),
+            strict_responses: false,
         }
     }
 
+    /// Check successful responses against the plugin's
+    /// [`response_schema`](Plugin::response_schema), turning a mismatch into
+    /// [`Error::InvalidPluginResponse`]
+    #[must_use]
+    pub const fn with_strict_responses(mut self, strict: bool) -> Self {
+        self.strict_responses = strict;
+        self
+    }
+
     /// Register a plugin
     ///
     /// # Errors
@@ -283,18 +316,40 @@ impl PluginRegistry {
             self.register_hook(&name, &capability);
         }
 
-        self.plugins.insert(name.clone(), plugin);
+        self.plugins.insert(name.clone(), Arc::from(plugin));
         self.permissions.insert(name, vec![Permission::All]);
 
         Ok(())
     }
 
+    /// Re-initialize a registered plugin with `config`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no plugin named `name` is registered, a clone of
+    /// the registry still shares it, or it rejects the configuration.
+    #[instrument(skip(self, config))]
+    pub fn configure(&mut self, name: &str, config: PluginConfig) -> Result<()> {
+        let plugin = self
+            .plugins
+            .get_mut(name)
+            .ok_or_else(|| Error::NotFound(format!("Plugin '{name}' not found")))?;
+        let plugin = Arc::get_mut(plugin)
+            .ok_or_else(|| Error::Plugin(format!("Plugin '{name}' is in use")))?;
+        futures::executor::block_on(plugin.initialize(config))
+    }
+
     /// Unregister a plugin
+    ///
+    /// The plugin is shut down unless a clone of the registry still shares
+    /// it, in which case it is only removed from this registry.
     #[instrument(skip(self))]
     pub async fn unregister(&mut self, name: &str) -> Result<()> {
         if let Some(mut plugin) = self.plugins.remove(name) {
             info!("Unregistering plugin: {}", name);
-            plugin.shutdown().await?;
+            if let Some(plugin) = Arc::get_mut(&mut plugin) {
+                plugin.shutdown().await?;
+            }
 
             // Remove from hooks
             for hooks in self.hooks.values_mut() {
@@ -318,6 +373,16 @@ impl PluginRegistry {
         self.plugins.values().map(|p| p.metadata()).collect()
     }
 
+    /// All registered plugins, in no particular order
+    pub fn plugins(&self) -> impl Iterator<Item = &dyn Plugin> {
+        self.plugins.values().map(std::convert::AsRef::as_ref)
+    }
+
+    /// Permissions granted to a plugin; empty if it is not registered
+    pub fn permissions(&self, plugin_name: &str) -> &[Permission] {
+        self.permissions.get(plugin_name).map_or(&[], Vec::as_slice)
+    }
+
     /// Apply pre-processing plugins
     #[instrument(skip(self, message))]
     pub async fn apply_pre_processing(&self, mut message: Message) -> Result<Message> {
@@ -330,7 +395,7 @@ impl PluginRegistry {
                     metadata: HashMap::new(),
                 };
 
-                match plugin.process(request).await {
+                match call_plugin(plugin.as_ref(), request, self.strict_responses).await {
                     Ok(response) if response.success => {
                         if let Ok(processed) = serde_json::from_value(response.data) {
                             message = processed;
@@ -364,7 +429,7 @@ impl PluginRegistry {
                 metadata: HashMap::new(),
             };
 
-            match plugin.process(request).await {
+            match call_plugin(plugin.as_ref(), request, self.strict_responses).await {
                 Ok(plugin_response) if plugin_response.success => {
                     if let Ok(processed) = serde_json::from_value(plugin_response.data) {
                         response = processed;
@@ -386,6 +451,39 @@ impl PluginRegistry {
         Ok(response)
     }
 
+    /// Invoke a tool provided by a plugin
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if no registered plugin named
+    /// `plugin_name` provides tools, or the plugin's own error.
+    #[instrument(skip(self, data, metadata))]
+    pub async fn invoke_tool(
+        &self,
+        plugin_name: &str,
+        data: serde_json::Value,
+        metadata: HashMap<String, serde_json::Value>,
+    ) -> Result<PluginResponse> {
+        let plugin = self
+            .hooks
+            .get(&HookType::ToolProvider)
+            .filter(|names| names.iter().any(|name| name == plugin_name))
+            .and_then(|_| self.plugins.get(plugin_name))
+            .ok_or_else(|| Error::NotFound(format!("No tool plugin '{plugin_name}'")))?;
+
+        call_plugin(
+            plugin.as_ref(),
+            PluginRequest {
+                id: uuid::Uuid::new_v4().to_string(),
+                request_type: RequestType::InvokeTool,
+                data,
+                metadata,
+            },
+            self.strict_responses,
+        )
+        .await
+    }
+
     /// Check if a plugin has permission
     pub fn has_permission(&self, plugin_name: &str, permission: &Permission) -> bool {
         self.permissions
@@ -412,6 +510,42 @@ impl PluginRegistry {
     }
 }
 
+/// Send a request to a plugin in its own span, passing the trace context along
+///
+/// When `strict`, a successful response must match the plugin's schema for
+/// the request type.
+async fn call_plugin(
+    plugin: &dyn Plugin,
+    mut request: PluginRequest,
+    strict: bool,
+) -> Result<PluginResponse> {
+    trace_context::propagate(&mut request.metadata);
+    let schema = if strict {
+        plugin.response_schema(&request.request_type)
+    } else {
+        None
+    };
+    let span = info_span!("plugin.process", plugin = plugin.name(), request_id = %request.id);
+    let response = plugin.process(request).instrument(span).await?;
+
+    if let Some(schema) = schema.filter(|_| response.success) {
+        if let Err(violation) = json_schema::validate(&schema, &response.data) {
+            warn!("Plugin {} response rejected: {}", plugin.name(), violation);
+            return Err(Error::InvalidPluginResponse {
+                plugin: plugin.name().to_string(),
+                path: if violation.path.is_empty() {
+                    "/".to_string()
+                } else {
+                    violation.path
+                },
+                message: violation.message,
+            }
+            .into());
+        }
+    }
+    Ok(response)
+}
+
 impl Default for PluginRegistry {
     fn default() -> Self {
         Self::new()
@@ -565,6 +699,76 @@ mod tests {
         assert_eq!(echo_message.content, "Echo: Hello, world!");
     }
 
+    /// Tool plugin whose replies drop a field its schema requires
+    struct SloppyTool;
+
+    #[async_trait]
+    impl Plugin for SloppyTool {
+        fn name(&self) -> &str {
+            "sloppy"
+        }
+
+        fn version(&self) -> &str {
+            "1.0.0"
+        }
+
+        fn capabilities(&self) -> Vec<Capability> {
+            vec![Capability {
+                name: "lookup".to_string(),
+                description: "Looks things up".to_string(),
+                capability_type: CapabilityType::ToolProvider,
+                required_permissions: Vec::new(),
+            }]
+        }
+
+        fn response_schema(&self, request_type: &RequestType) -> Option<serde_json::Value> {
+            matches!(request_type, RequestType::InvokeTool).then(|| {
+                serde_json::json!({
+                    "type": "object",
+                    "required": ["result"],
+                    "properties": { "result": { "type": "string" } }
+                })
+            })
+        }
+
+        async fn process(&self, request: PluginRequest) -> Result<PluginResponse> {
+            Ok(PluginResponse::success(
+                request.id,
+                serde_json::json!({ "answer": 42 }),
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_strict_responses_reject_schema_violations() {
+        let mut lenient = PluginRegistry::new();
+        lenient.register(Box::new(SloppyTool)).unwrap();
+        let response = lenient
+            .invoke_tool("sloppy", serde_json::json!({}), HashMap::new())
+            .await
+            .unwrap();
+        assert_eq!(response.data["answer"], 42);
+
+        let mut strict = PluginRegistry::new().with_strict_responses(true);
+        strict.register(Box::new(SloppyTool)).unwrap();
+        let error = strict
+            .invoke_tool("sloppy", serde_json::json!({}), HashMap::new())
+            .await
+            .unwrap_err();
+        match error.downcast_ref::<Error>() {
+            Some(Error::InvalidPluginResponse {
+                plugin,
+                path,
+                message,
+            }) => {
+                assert_eq!(plugin, "sloppy");
+                assert_eq!(path, "/");
+                assert_eq!(message, "missing required field `result`");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+
     #[test]
     fn test_plugin_response() {
         let response = PluginResponse::success("test", serde_json::json!({"key": "value"}));
@@ -578,4 +782,27 @@ mod tests {
             Some("Something went wrong")
         );
     }
+
+    #[test]
+    fn test_plugin_wire_round_trip() {
+        let request = PluginRequest {
+            id: "req-1".to_string(),
+            request_type: RequestType::Custom("summarize".to_string()),
+            data: serde_json::json!({ "text": "hi" }),
+            metadata: HashMap::new(),
+        };
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json["request_type"],
+            serde_json::json!({ "custom": "summarize" })
+        );
+        let back: PluginRequest = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&back).unwrap(), json);
+
+        let response = PluginResponse::error("req-1", "boom");
+        let json = serde_json::to_value(&response).unwrap();
+        let back: PluginResponse = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(back.error.as_deref(), Some("boom"));
+        assert_eq!(serde_json::to_value(&back).unwrap(), json);
+    }
 }
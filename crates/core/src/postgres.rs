@@ -0,0 +1,420 @@
+//! `PostgreSQL` context storage
+//!
+//! [`PostgresContextStore`] backs [`StorageBackend::Postgres`](crate::config::StorageBackend::Postgres).
+//! Contexts are stored as JSONB, so they can be inspected and queried in
+//! place; payloads that were compressed or encrypted go to a `BYTEA` column
+//! instead. Expired rows are invisible to reads and removed by
+//! [`purge_expired`](PostgresContextStore::purge_expired).
+//!
+//! The schema is created and upgraded by [`migrate`](PostgresContextStore::migrate),
+//! which records applied steps in a `<table>_migrations` table and holds an
+//! advisory lock, so several bot instances can start against the same
+//! database at once.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::Row;
+use tracing::{debug, info};
+
+use crate::{
+    compression::PayloadCodec,
+    context::{Context, ContextPage, ContextStore},
+    encryption::ContextEncryption,
+    error::Error,
+};
+
+/// Table used unless [`with_table`](PostgresContextStore::with_table) says otherwise
+pub const DEFAULT_TABLE: &str = "bot_contexts";
+
+/// Schema steps, applied in order; `{table}` is replaced with the table name
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS {table} (
+        key TEXT PRIMARY KEY,
+        context JSONB,
+        payload BYTEA,
+        expires_at TIMESTAMPTZ NOT NULL,
+        updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        CHECK ((context IS NULL) <> (payload IS NULL))
+    )",
+    "CREATE INDEX IF NOT EXISTS {table}_expires_at_idx ON {table} (expires_at)",
+];
+
+/// Context store backed by a `PostgreSQL` table
+pub struct PostgresContextStore {
+    pool: PgPool,
+    table: String,
+    codec: PayloadCodec,
+    encryption: Option<ContextEncryption>,
+}
+
+impl std::fmt::Debug for PostgresContextStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PostgresContextStore")
+            .field("table", &self.table)
+            .field("encrypted", &self.encryption.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl PostgresContextStore {
+    /// Create a store using an existing pool
+    #[must_use]
+    pub fn new(pool: PgPool, codec: PayloadCodec, encryption: Option<ContextEncryption>) -> Self {
+        Self {
+            pool,
+            table: DEFAULT_TABLE.to_string(),
+            codec,
+            encryption,
+        }
+    }
+
+    /// Connect to the database at `url`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the connection fails.
+    pub async fn connect(
+        url: &str,
+        codec: PayloadCodec,
+        encryption: Option<ContextEncryption>,
+    ) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_secs(10))
+            .connect(url)
+            .await
+            .map_err(database_error)?;
+        Ok(Self::new(pool, codec, encryption))
+    }
+
+    /// Store contexts in `table` instead of [`DEFAULT_TABLE`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Configuration`] unless the name is a lowercase SQL
+    /// identifier.
+    pub fn with_table(mut self, table: impl Into<String>) -> Result<Self> {
+        let table = table.into();
+        if !is_table_name(&table) {
+            return Err(
+                Error::Configuration(format!("Invalid context table name: {table}")).into(),
+            );
+        }
+        self.table = table;
+        Ok(self)
+    }
+
+    /// The schema steps for `table`, in order
+    #[must_use]
+    #[allow(clippy::literal_string_with_formatting_args)]
+    pub fn migrations(table: &str) -> Vec<String> {
+        MIGRATIONS
+            .iter()
+            .map(|step| step.replace("{table}", table))
+            .collect()
+    }
+
+    /// Apply the schema steps not yet recorded as applied
+    ///
+    /// Returns how many steps were applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if a step fails; the steps of this call
+    /// are rolled back together.
+    pub async fn migrate(&self) -> Result<usize> {
+        let mut tx = self.pool.begin().await.map_err(database_error)?;
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind(&self.table)
+            .execute(&mut *tx)
+            .await
+            .map_err(database_error)?;
+        sqlx::query(&format!(
+            "CREATE TABLE IF NOT EXISTS {}_migrations (
+                version INTEGER PRIMARY KEY,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )",
+            self.table
+        ))
+        .execute(&mut *tx)
+        .await
+        .map_err(database_error)?;
+
+        let applied: i32 = sqlx::query(&format!(
+            "SELECT COALESCE(MAX(version), 0) FROM {}_migrations",
+            self.table
+        ))
+        .fetch_one(&mut *tx)
+        .await
+        .and_then(|row| row.try_get(0))
+        .map_err(database_error)?;
+
+        let pending: Vec<(i32, String)> = (1..)
+            .zip(Self::migrations(&self.table))
+            .filter(|(version, _)| *version > applied)
+            .collect();
+        for (version, step) in &pending {
+            debug!("Applying context schema step {} to {}", version, self.table);
+            sqlx::query(step)
+                .execute(&mut *tx)
+                .await
+                .map_err(database_error)?;
+            sqlx::query(&format!(
+                "INSERT INTO {}_migrations (version) VALUES ($1)",
+                self.table
+            ))
+            .bind(version)
+            .execute(&mut *tx)
+            .await
+            .map_err(database_error)?;
+        }
+        tx.commit().await.map_err(database_error)?;
+
+        if !pending.is_empty() {
+            info!(
+                "Applied {} context schema steps to {}",
+                pending.len(),
+                self.table
+            );
+        }
+        Ok(pending.len())
+    }
+
+    /// Delete expired contexts, returning how many were removed
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the delete fails.
+    pub async fn purge_expired(&self) -> Result<u64> {
+        let result = sqlx::query(&format!(
+            "DELETE FROM {} WHERE expires_at <= now()",
+            self.table
+        ))
+        .execute(&self.pool)
+        .await
+        .map_err(database_error)?;
+        Ok(result.rows_affected())
+    }
+
+    async fn decode(&self, key: &str, row: &sqlx::postgres::PgRow) -> Result<Context> {
+        let json: Option<String> = row.try_get("context").map_err(database_error)?;
+        if let Some(json) = json {
+            let context: Context =
+                serde_json::from_str(&json).map_err(|e| Error::Serialization(e.to_string()))?;
+            if let Some(encryption) = &self.encryption {
+                encryption.check_unsealed(key, &context)?;
+            }
+            return Ok(context);
+        }
+        let payload: Vec<u8> = row.try_get("payload").map_err(database_error)?;
+        match &self.encryption {
+            Some(encryption) => encryption.open_context(key, payload, &self.codec).await,
+            None => Ok(self.codec.decode(&payload)?),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ContextStore for PostgresContextStore {
+    async fn get(&self, key: &str) -> Result<Option<Context>> {
+        let row = sqlx::query(&format!(
+            "SELECT context::text AS context, payload FROM {} \
+             WHERE key = $1 AND expires_at > now()",
+            self.table
+        ))
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(database_error)?;
+        match row {
+            Some(row) => Ok(Some(self.decode(key, &row).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn set(&self, key: &str, context: Context, ttl: Duration) -> Result<()> {
+        let mut payload = self.codec.encode(&context)?;
+        if let Some(encryption) = &self.encryption {
+            payload = encryption.seal(context.tenant(), key, payload).await?;
+        }
+        // Plain JSON is stored as JSONB; compressed or encrypted payloads as bytes
+        let (json, payload) = PayloadCodec::plain_json(&payload)
+            .map_or((None, Some(payload.as_slice())), |json| (Some(json), None));
+
+        sqlx::query(&format!(
+            "INSERT INTO {} (key, context, payload, expires_at, updated_at) \
+             VALUES ($1, $2::jsonb, $3, now() + $4 * interval '1 second', now()) \
+             ON CONFLICT (key) DO UPDATE SET context = EXCLUDED.context, \
+             payload = EXCLUDED.payload, expires_at = EXCLUDED.expires_at, \
+             updated_at = EXCLUDED.updated_at",
+            self.table
+        ))
+        .bind(key)
+        .bind(json)
+        .bind(payload)
+        .bind(ttl.as_secs_f64())
+        .execute(&self.pool)
+        .await
+        .map_err(database_error)?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        sqlx::query(&format!("DELETE FROM {} WHERE key = $1", self.table))
+            .bind(key)
+            .execute(&self.pool)
+            .await
+            .map_err(database_error)?;
+        Ok(())
+    }
+
+    async fn list_keys(&self, pattern: &str) -> Result<Vec<String>> {
+        let rows = sqlx::query(&format!(
+            "SELECT key FROM {} WHERE key LIKE $1 ESCAPE '\\' AND expires_at > now()",
+            self.table
+        ))
+        .bind(like_pattern(pattern))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(database_error)?;
+        let keys = rows
+            .iter()
+            .map(|row| row.try_get("key"))
+            .collect::<std::result::Result<Vec<String>, _>>()
+            .map_err(database_error)?;
+        Ok(keys)
+    }
+
+    async fn list_page(
+        &self,
+        pattern: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<ContextPage> {
+        let rows = sqlx::query(&format!(
+            "SELECT key FROM {} WHERE key LIKE $1 ESCAPE '\\' AND expires_at > now() \
+             AND ($2::text IS NULL OR key > $2) ORDER BY key LIMIT $3",
+            self.table
+        ))
+        .bind(like_pattern(pattern))
+        .bind(cursor)
+        .bind(i64::try_from(limit.saturating_add(1)).unwrap_or(i64::MAX))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(database_error)?;
+        let keys = rows
+            .iter()
+            .map(|row| row.try_get("key"))
+            .collect::<std::result::Result<Vec<String>, _>>()
+            .map_err(database_error)?;
+        Ok(ContextPage::from_overfetch(keys, limit))
+    }
+
+    async fn get_many(&self, keys: &[&str]) -> Result<Vec<Option<Context>>> {
+        let rows = sqlx::query(&format!(
+            "SELECT key, context::text AS context, payload FROM {} \
+             WHERE key = ANY($1) AND expires_at > now()",
+            self.table
+        ))
+        .bind(keys)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(database_error)?;
+
+        let mut found = std::collections::HashMap::with_capacity(rows.len());
+        for row in &rows {
+            let key: String = row.try_get("key").map_err(database_error)?;
+            let context = self.decode(&key, row).await?;
+            found.insert(key, context);
+        }
+        Ok(keys.iter().map(|key| found.remove(*key)).collect())
+    }
+
+    async fn delete_many(&self, keys: &[&str]) -> Result<()> {
+        sqlx::query(&format!("DELETE FROM {} WHERE key = ANY($1)", self.table))
+            .bind(keys)
+            .execute(&self.pool)
+            .await
+            .map_err(database_error)?;
+        Ok(())
+    }
+
+    async fn touch_many(&self, keys: &[&str], ttl: Duration) -> Result<()> {
+        sqlx::query(&format!(
+            "UPDATE {} SET expires_at = now() + $2 * interval '1 second' \
+             WHERE key = ANY($1) AND expires_at > now()",
+            self.table
+        ))
+        .bind(keys)
+        .bind(ttl.as_secs_f64())
+        .execute(&self.pool)
+        .await
+        .map_err(database_error)?;
+        Ok(())
+    }
+
+    fn compression_stats(&self) -> Option<crate::compression::CompressionStats> {
+        Some(self.codec.stats())
+    }
+}
+
+/// Whether `name` is a lowercase identifier short enough to suffix
+fn is_table_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && name.len() <= 48
+}
+
+/// A `LIKE` pattern matching keys that contain `pattern`
+fn like_pattern(pattern: &str) -> String {
+    let mut like = String::with_capacity(pattern.len() + 2);
+    like.push('%');
+    for c in pattern.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            like.push('\\');
+        }
+        like.push(c);
+    }
+    like.push('%');
+    like
+}
+
+#[allow(clippy::needless_pass_by_value)] // Passed to `map_err`
+fn database_error(e: sqlx::Error) -> Error {
+    Error::Database(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_like_pattern_escapes_wildcards() {
+        assert_eq!(like_pattern(""), "%%");
+        assert_eq!(like_pattern("user_1"), "%user\\_1%");
+        assert_eq!(like_pattern("50%\\"), "%50\\%\\\\%");
+    }
+
+    #[test]
+    fn test_migrations_use_table_name() {
+        let steps = PostgresContextStore::migrations("tenant_contexts");
+        assert_eq!(steps.len(), MIGRATIONS.len());
+        assert!(steps[0].contains("CREATE TABLE IF NOT EXISTS tenant_contexts ("));
+        assert!(steps[1].contains("tenant_contexts_expires_at_idx"));
+        assert!(steps.iter().all(|step| !step.contains("{table}")));
+    }
+
+    #[test]
+    fn test_table_names_are_validated() {
+        assert!(is_table_name("bot_contexts"));
+        assert!(is_table_name("_contexts2"));
+        assert!(!is_table_name("contexts; DROP TABLE users"));
+        assert!(!is_table_name("Contexts"));
+        assert!(!is_table_name("2contexts"));
+        assert!(!is_table_name(""));
+    }
+}
@@ -0,0 +1,183 @@
+//! Startup self-test
+//!
+//! [`Bot::preflight`](crate::Bot::preflight) runs the checks in this module
+//! and collects their outcomes into a [`PreflightReport`], so deploy
+//! pipelines can refuse to roll out a bot that cannot serve traffic.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Outcome of a single preflight check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    /// The check succeeded
+    Pass,
+    /// The check could not confirm readiness, but the bot can still start
+    Warn,
+    /// The bot cannot serve requests
+    Fail,
+}
+
+/// Result of one preflight check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreflightCheck {
+    /// Check name, e.g. `config` or `store`
+    pub name: String,
+    /// Outcome
+    pub status: CheckStatus,
+    /// Human-readable detail
+    pub detail: String,
+    /// Time spent running the check
+    #[serde(with = "humantime_serde")]
+    pub duration: Duration,
+}
+
+/// Options controlling which preflight checks run
+#[derive(Debug, Clone, Default)]
+pub struct PreflightOptions {
+    /// Send one tiny message through the full pipeline
+    pub generate: bool,
+}
+
+/// Collected results of all preflight checks
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PreflightReport {
+    /// Individual checks, in the order they ran
+    pub checks: Vec<PreflightCheck>,
+}
+
+impl PreflightReport {
+    /// The worst status across all checks
+    #[must_use]
+    pub fn status(&self) -> CheckStatus {
+        self.checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(CheckStatus::Pass)
+    }
+
+    /// Whether no check failed
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.status() != CheckStatus::Fail
+    }
+
+    /// Look up a check by name
+    #[must_use]
+    pub fn check(&self, name: &str) -> Option<&PreflightCheck> {
+        self.checks.iter().find(|check| check.name == name)
+    }
+
+    pub(crate) fn record(&mut self, name: &str, started: Instant, outcome: (CheckStatus, String)) {
+        let (status, detail) = outcome;
+        self.checks.push(PreflightCheck {
+            name: name.to_string(),
+            status,
+            detail,
+            duration: started.elapsed(),
+        });
+    }
+}
+
+impl std::fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for check in &self.checks {
+            let label = match check.status {
+                CheckStatus::Pass => "PASS",
+                CheckStatus::Warn => "WARN",
+                CheckStatus::Fail => "FAIL",
+            };
+            writeln!(
+                f,
+                "[{label}] {:<12} {} ({:?})",
+                check.name, check.detail, check.duration
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Look for AWS credentials the SDK's default provider chain would pick up
+///
+/// Only the presence of a source is checked; credentials served by the
+/// instance metadata service cannot be detected without a network call, so
+/// their absence is a warning rather than a failure.
+pub(crate) fn resolve_credentials() -> (CheckStatus, String) {
+    const ENV_SOURCES: &[(&str, &str)] = &[
+        ("AWS_ACCESS_KEY_ID", "environment variables"),
+        ("AWS_PROFILE", "named profile"),
+        ("AWS_WEB_IDENTITY_TOKEN_FILE", "web identity token"),
+        (
+            "AWS_CONTAINER_CREDENTIALS_RELATIVE_URI",
+            "container credentials",
+        ),
+        (
+            "AWS_CONTAINER_CREDENTIALS_FULL_URI",
+            "container credentials",
+        ),
+    ];
+
+    if let Some((_, source)) = ENV_SOURCES
+        .iter()
+        .find(|(var, _)| std::env::var_os(var).is_some_and(|v| !v.is_empty()))
+    {
+        return (CheckStatus::Pass, format!("Found {source}"));
+    }
+
+    let shared = std::env::var_os("AWS_SHARED_CREDENTIALS_FILE")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".aws/credentials"))
+        });
+    if shared.as_ref().is_some_and(|path| path.is_file()) {
+        return (
+            CheckStatus::Pass,
+            "Found shared credentials file".to_string(),
+        );
+    }
+
+    (
+        CheckStatus::Warn,
+        "No credential source found; relying on instance metadata".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(name: &str, status: CheckStatus) -> PreflightCheck {
+        PreflightCheck {
+            name: name.to_string(),
+            status,
+            detail: String::new(),
+            duration: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_report_status_is_worst_check() {
+        let mut report = PreflightReport::default();
+        assert_eq!(report.status(), CheckStatus::Pass);
+
+        report.checks.push(check("config", CheckStatus::Pass));
+        report.checks.push(check("credentials", CheckStatus::Warn));
+        assert_eq!(report.status(), CheckStatus::Warn);
+        assert!(report.is_ok());
+
+        report.checks.push(check("store", CheckStatus::Fail));
+        assert_eq!(report.status(), CheckStatus::Fail);
+        assert!(!report.is_ok());
+        assert!(report.to_string().contains("[FAIL] store"));
+    }
+
+    #[test]
+    fn test_status_serialization() {
+        let json = serde_json::to_string(&check("model", CheckStatus::Warn)).unwrap();
+        assert!(json.contains(r#""status":"warn""#));
+    }
+}
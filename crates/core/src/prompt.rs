@@ -0,0 +1,134 @@
+//! Prompt interpolation
+//!
+//! A deliberately small `{{name}}` substitution used to build prompts from
+//! user-controlled values. Malformed templates are reported as errors and
+//! output size is capped, so hostile variables cannot blow up a prompt.
+
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+use crate::error::{Error, Result};
+
+/// Upper bound on interpolated output, in bytes
+pub const MAX_INTERPOLATED_LEN: usize = 1_000_000;
+
+/// Substitute `{{name}}` placeholders in `template` with values from `vars`
+///
+/// String values are inserted verbatim; other JSON values are inserted in
+/// their compact JSON form. Whitespace inside the braces is ignored.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] for unterminated placeholders, unknown
+/// variables, or output larger than [`MAX_INTERPOLATED_LEN`].
+///
+/// # Example
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use universal_bot_core::prompt::interpolate;
+///
+/// let vars = HashMap::from([("name".to_string(), serde_json::json!("Ada"))]);
+/// assert_eq!(interpolate("Hello, {{ name }}!", &vars)?, "Hello, Ada!");
+/// # Ok::<(), universal_bot_core::Error>(())
+/// ```
+pub fn interpolate<S: BuildHasher>(
+    template: &str,
+    vars: &HashMap<String, serde_json::Value, S>,
+) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after_open = &rest[start + 2..];
+        let end = after_open.find("}}").ok_or_else(|| {
+            Error::InvalidInput(format!(
+                "Unterminated placeholder at byte {}",
+                template.len() - rest.len() + start
+            ))
+        })?;
+
+        let name = after_open[..end].trim();
+        let value = vars
+            .get(name)
+            .ok_or_else(|| Error::InvalidInput(format!("Unknown template variable: {name}")))?;
+        match value {
+            serde_json::Value::String(s) => output.push_str(s),
+            other => output.push_str(&other.to_string()),
+        }
+
+        if output.len() > MAX_INTERPOLATED_LEN {
+            return Err(Error::InvalidInput(format!(
+                "Interpolated prompt exceeds {MAX_INTERPOLATED_LEN} bytes"
+            )));
+        }
+        rest = &after_open[end + 2..];
+    }
+
+    output.push_str(rest);
+    if output.len() > MAX_INTERPOLATED_LEN {
+        return Err(Error::InvalidInput(format!(
+            "Interpolated prompt exceeds {MAX_INTERPOLATED_LEN} bytes"
+        )));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn vars() -> HashMap<String, serde_json::Value> {
+        HashMap::from([
+            ("name".to_string(), json!("Ada")),
+            ("count".to_string(), json!(3)),
+            ("nested".to_string(), json!({ "a": [1] })),
+        ])
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let out = interpolate("{{name}} has {{ count }} items: {{nested}}", &vars()).unwrap();
+        assert_eq!(out, r#"Ada has 3 items: {"a":[1]}"#);
+        assert_eq!(
+            interpolate("no placeholders", &vars()).unwrap(),
+            "no placeholders"
+        );
+    }
+
+    #[test]
+    fn test_interpolate_rejects_malformed() {
+        assert!(interpolate("Hello {{name", &vars()).is_err());
+        assert!(interpolate("Hello {{missing}}", &vars()).is_err());
+        assert_eq!(interpolate("}} {", &vars()).unwrap(), "}} {");
+    }
+
+    #[test]
+    fn test_interpolate_caps_output() {
+        let big = HashMap::from([("x".to_string(), json!("a".repeat(MAX_INTERPOLATED_LEN)))]);
+        assert!(interpolate("{{x}}{{x}}", &big).is_err());
+    }
+
+    #[cfg(feature = "property-testing")]
+    mod property_tests {
+        use super::*;
+        use proptest::prelude::*;
+
+        proptest! {
+            #[test]
+            fn test_interpolate_never_panics(template in any::<String>(), value in any::<String>()) {
+                let vars = HashMap::from([("v".to_string(), json!(value))]);
+                let _ = interpolate(&template, &vars);
+            }
+
+            #[test]
+            fn test_interpolate_substitutes_verbatim(prefix in "[^{]*", value in any::<String>()) {
+                let vars = HashMap::from([("v".to_string(), json!(&value))]);
+                let out = interpolate(&format!("{prefix}{{{{v}}}}"), &vars).unwrap();
+                prop_assert_eq!(out, format!("{prefix}{value}"));
+            }
+        }
+    }
+}
@@ -0,0 +1,398 @@
+//! Versioned prompts and A/B experiments
+//!
+//! A [`PromptRegistry`] keeps every version of each named
+//! [`PromptTemplate`]. Without an experiment a prompt resolves to its latest
+//! version; with one, each conversation is assigned an arm by hashing its ID,
+//! so it sees the same version on every turn and across replicas. Arms are
+//! weighted, so an experiment can compare two versions or several.
+//!
+//! With [`PromptTemplateConfig::registry_prompt`](crate::config::PromptTemplateConfig::registry_prompt)
+//! set, the bot uses the assigned version as the system prompt, stamps the
+//! response with its ID under [`PROMPT_VERSION_METADATA_KEY`], and records
+//! request outcomes per version for [`PromptRegistry::metrics`].
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{error::Error, prompt_template::PromptTemplate};
+
+/// Response metadata key holding the `name@version` of the system prompt used
+pub const PROMPT_VERSION_METADATA_KEY: &str = "prompt_version";
+
+/// One version of a prompt in an experiment, with its share of conversations
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExperimentArm {
+    /// Prompt version
+    pub version: String,
+    /// Relative share of conversations assigned to this version
+    pub weight: u32,
+}
+
+impl ExperimentArm {
+    /// An arm serving `version` with `weight`
+    #[must_use]
+    pub fn new(version: impl Into<String>, weight: u32) -> Self {
+        Self {
+            version: version.into(),
+            weight,
+        }
+    }
+}
+
+/// Request outcomes under one prompt version, for comparison
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptMetrics {
+    /// Prompt name
+    pub prompt: String,
+    /// Prompt version
+    pub version: String,
+    /// Weight in the running experiment, if the version is in one
+    pub weight: Option<u32>,
+    /// Requests answered with this version
+    pub requests: u64,
+    /// Requests that failed or produced an error response
+    pub errors: u64,
+    /// Tokens used across all requests
+    pub tokens: u64,
+    /// Mean request latency
+    pub average_latency: Option<Duration>,
+}
+
+impl PromptMetrics {
+    /// Share of requests that failed
+    #[must_use]
+    pub fn error_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.requests as f64
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PromptCounters {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    tokens: AtomicU64,
+    latency_micros: AtomicU64,
+}
+
+#[derive(Debug)]
+struct PromptVersion {
+    template: Arc<PromptTemplate>,
+    counters: PromptCounters,
+}
+
+/// Every version of one prompt and its running experiment
+#[derive(Debug, Default)]
+struct PromptFamily {
+    versions: Vec<PromptVersion>,
+    arms: Vec<ExperimentArm>,
+}
+
+impl PromptFamily {
+    fn get(&self, version: &str) -> Option<&PromptVersion> {
+        self.versions.iter().find(|v| v.template.version == version)
+    }
+}
+
+/// Named, versioned prompts and the experiments comparing them
+#[derive(Debug, Default)]
+pub struct PromptRegistry {
+    prompts: RwLock<BTreeMap<String, PromptFamily>>,
+}
+
+impl PromptRegistry {
+    /// Create an empty registry
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a prompt version, which becomes the prompt's latest
+    ///
+    /// Versions are immutable: registering the same text again is a no-op.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the template does not parse, or
+    /// [`Error::Configuration`] if the version is registered with other text.
+    pub fn register(&self, template: PromptTemplate) -> Result<()> {
+        template.validate()?;
+        let mut prompts = self.prompts.write();
+        let family = prompts.entry(template.name.clone()).or_default();
+        if let Some(existing) = family.get(&template.version) {
+            if *existing.template == template {
+                return Ok(());
+            }
+            return Err(Error::Configuration(format!(
+                "Prompt {} is already registered with different text",
+                template.id()
+            ))
+            .into());
+        }
+        let id = template.id();
+        family.versions.push(PromptVersion {
+            template: Arc::new(template),
+            counters: PromptCounters::default(),
+        });
+        drop(prompts);
+        info!("Registered prompt {}", id);
+        Ok(())
+    }
+
+    /// A registered prompt version
+    #[must_use]
+    pub fn get(&self, name: &str, version: &str) -> Option<Arc<PromptTemplate>> {
+        self.prompts
+            .read()
+            .get(name)?
+            .get(version)
+            .map(|v| v.template.clone())
+    }
+
+    /// The most recently registered version of a prompt
+    #[must_use]
+    pub fn latest(&self, name: &str) -> Option<Arc<PromptTemplate>> {
+        self.prompts
+            .read()
+            .get(name)?
+            .versions
+            .last()
+            .map(|v| v.template.clone())
+    }
+
+    /// Versions of a prompt, oldest first
+    #[must_use]
+    pub fn versions(&self, name: &str) -> Vec<String> {
+        self.prompts
+            .read()
+            .get(name)
+            .map_or_else(Vec::new, |family| {
+                family
+                    .versions
+                    .iter()
+                    .map(|v| v.template.version.clone())
+                    .collect()
+            })
+    }
+
+    /// Split conversations between versions of a prompt by weight
+    ///
+    /// Replaces any running experiment on the prompt. Conversations keep
+    /// their arm as long as the arms and weights stay the same.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if an arm's version is not registered, or
+    /// [`Error::Validation`] if no arm has a positive weight.
+    pub fn start_experiment(&self, name: &str, arms: Vec<ExperimentArm>) -> Result<()> {
+        if arms.iter().all(|arm| arm.weight == 0) {
+            return Err(Error::Validation(format!(
+                "Experiment on prompt {name} needs an arm with a positive weight"
+            ))
+            .into());
+        }
+        let mut prompts = self.prompts.write();
+        let family = prompts
+            .get_mut(name)
+            .ok_or_else(|| Error::NotFound(format!("Prompt {name} not found")))?;
+        if let Some(missing) = arms.iter().find(|arm| family.get(&arm.version).is_none()) {
+            return Err(
+                Error::NotFound(format!("Prompt {name}@{} not found", missing.version)).into(),
+            );
+        }
+        family.arms = arms;
+        drop(prompts);
+        info!("Started experiment on prompt {}", name);
+        Ok(())
+    }
+
+    /// Stop splitting conversations, serving the latest version again
+    pub fn end_experiment(&self, name: &str) {
+        if let Some(family) = self.prompts.write().get_mut(name) {
+            family.arms.clear();
+        }
+    }
+
+    /// The arms of the experiment running on a prompt, if any
+    #[must_use]
+    pub fn experiment(&self, name: &str) -> Vec<ExperimentArm> {
+        self.prompts
+            .read()
+            .get(name)
+            .map_or_else(Vec::new, |family| family.arms.clone())
+    }
+
+    /// The version of a prompt to use for a conversation
+    ///
+    /// The experiment arm the conversation hashes into, or the latest version
+    /// when no experiment is running.
+    #[must_use]
+    pub fn assign(&self, name: &str, conversation_id: &str) -> Option<Arc<PromptTemplate>> {
+        let prompts = self.prompts.read();
+        let family = prompts.get(name)?;
+        let total: u64 = family.arms.iter().map(|arm| u64::from(arm.weight)).sum();
+        if total == 0 {
+            return family.versions.last().map(|v| v.template.clone());
+        }
+        let mut point = bucket(name, conversation_id) % total;
+        let arm = family.arms.iter().find(|arm| {
+            let weight = u64::from(arm.weight);
+            if point < weight {
+                return true;
+            }
+            point -= weight;
+            false
+        })?;
+        let template = family.get(&arm.version).map(|v| v.template.clone());
+        drop(prompts);
+        template
+    }
+
+    /// Record the outcome of a request answered with `template`
+    pub fn record(
+        &self,
+        template: &PromptTemplate,
+        latency: Duration,
+        failed: bool,
+        tokens: usize,
+    ) {
+        let prompts = self.prompts.read();
+        let Some(version) = prompts
+            .get(&template.name)
+            .and_then(|family| family.get(&template.version))
+        else {
+            return;
+        };
+        let counters = &version.counters;
+        counters.requests.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            counters.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        counters
+            .tokens
+            .fetch_add(u64::try_from(tokens).unwrap_or(u64::MAX), Ordering::Relaxed);
+        let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        counters.latency_micros.fetch_add(micros, Ordering::Relaxed);
+        drop(prompts);
+    }
+
+    /// Request outcomes for every prompt version, by name and then oldest first
+    #[must_use]
+    pub fn metrics(&self) -> Vec<PromptMetrics> {
+        let prompts = self.prompts.read();
+        prompts
+            .iter()
+            .flat_map(|(name, family)| {
+                family.versions.iter().map(move |v| {
+                    let counters = &v.counters;
+                    let requests = counters.requests.load(Ordering::Relaxed);
+                    let latency = counters.latency_micros.load(Ordering::Relaxed);
+                    PromptMetrics {
+                        prompt: name.clone(),
+                        version: v.template.version.clone(),
+                        weight: family
+                            .arms
+                            .iter()
+                            .find(|arm| arm.version == v.template.version)
+                            .map(|arm| arm.weight),
+                        requests,
+                        errors: counters.errors.load(Ordering::Relaxed),
+                        tokens: counters.tokens.load(Ordering::Relaxed),
+                        average_latency: (requests > 0)
+                            .then(|| Duration::from_micros(latency / requests)),
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+/// FNV-1a over the prompt name and conversation ID, stable across replicas
+fn bucket(name: &str, conversation_id: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in name.bytes().chain([0]).chain(conversation_id.bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> PromptRegistry {
+        let registry = PromptRegistry::new();
+        registry
+            .register(PromptTemplate::new("support", "Be helpful."))
+            .unwrap();
+        registry
+            .register(PromptTemplate::new("support", "Be brief.").with_version("2"))
+            .unwrap();
+        registry
+    }
+
+    #[test]
+    fn test_versions_are_immutable() {
+        let registry = registry();
+
+        assert_eq!(registry.latest("support").unwrap().version, "2");
+        assert!(registry
+            .register(PromptTemplate::new("support", "Be helpful."))
+            .is_ok());
+        assert!(registry
+            .register(PromptTemplate::new("support", "Be rude."))
+            .is_err());
+        assert_eq!(registry.versions("support"), ["1", "2"]);
+        assert!(registry
+            .start_experiment("support", vec![ExperimentArm::new("3", 1)])
+            .is_err());
+    }
+
+    #[test]
+    fn test_assignment_is_sticky_and_weighted() {
+        let registry = registry();
+        registry
+            .start_experiment(
+                "support",
+                vec![ExperimentArm::new("1", 3), ExperimentArm::new("2", 1)],
+            )
+            .unwrap();
+
+        let assigned: Vec<_> = (0..1000)
+            .map(|i| {
+                let conversation = format!("conv-{i}");
+                let version = registry.assign("support", &conversation).unwrap();
+                assert_eq!(registry.assign("support", &conversation).unwrap(), version);
+                version
+            })
+            .collect();
+        let control = assigned.iter().filter(|t| t.version == "1").count();
+        assert!(
+            (650..850).contains(&control),
+            "{control} of 1000 in control"
+        );
+
+        registry.record(&assigned[0], Duration::from_millis(20), false, 40);
+        let metrics = registry.metrics();
+        let recorded = metrics
+            .iter()
+            .find(|m| m.version == assigned[0].version)
+            .unwrap();
+        assert_eq!((recorded.requests, recorded.tokens), (1, 40));
+        assert!(metrics.iter().all(|m| m.weight.is_some()));
+
+        registry.end_experiment("support");
+        assert_eq!(registry.assign("support", "conv-0").unwrap().version, "2");
+    }
+}
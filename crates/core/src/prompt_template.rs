@@ -0,0 +1,193 @@
+//! Prompt templates
+//!
+//! A [`PromptTemplate`] is a named, versioned Handlebars prompt with
+//! `{{name}}` variables, `{{#if}}` and `{{#each}}` blocks, and
+//! `{{> partial}}` includes, so prompts can be reused and reviewed instead
+//! of formatted inline. Rendering is strict: a variable printed outside a
+//! condition must be set. Output is not HTML-escaped and is capped at
+//! [`MAX_INTERPOLATED_LEN`].
+//!
+//! With [`PromptTemplateConfig::enabled`](crate::config::PromptTemplateConfig::enabled),
+//! the process stage renders the system prompt as a template with the
+//! conversation's variables and the configured partials.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    prompt::MAX_INTERPOLATED_LEN,
+};
+
+/// A Handlebars prompt with the partials it includes
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    /// Template name
+    pub name: String,
+
+    /// Version, bumped whenever the text changes
+    #[serde(default = "default_version")]
+    pub version: String,
+
+    /// Handlebars source
+    pub source: String,
+
+    /// Partials the source can include with `{{> name}}`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub partials: BTreeMap<String, String>,
+}
+
+fn default_version() -> String {
+    "1".to_string()
+}
+
+impl PromptTemplate {
+    /// Create version 1 of a template
+    #[must_use]
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            version: default_version(),
+            source: source.into(),
+            partials: BTreeMap::new(),
+        }
+    }
+
+    /// Set the version
+    #[must_use]
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.version = version.into();
+        self
+    }
+
+    /// Add a partial, replacing any of the same name
+    #[must_use]
+    pub fn with_partial(mut self, name: impl Into<String>, source: impl Into<String>) -> Self {
+        self.partials.insert(name.into(), source.into());
+        self
+    }
+
+    /// `name@version`, for logs and metadata
+    #[must_use]
+    pub fn id(&self) -> String {
+        format!("{}@{}", self.name, self.version)
+    }
+
+    fn invalid(&self, error: impl std::fmt::Display) -> Error {
+        Error::InvalidInput(format!("Prompt template {}: {error}", self.id()))
+    }
+
+    fn registry(&self) -> Result<Handlebars<'static>> {
+        let mut registry = Handlebars::new();
+        registry.set_strict_mode(true);
+        registry.register_escape_fn(handlebars::no_escape);
+        for (name, source) in &self.partials {
+            registry
+                .register_partial(name, source)
+                .map_err(|e| self.invalid(e))?;
+        }
+        registry
+            .register_template_string(&self.name, &self.source)
+            .map_err(|e| self.invalid(e))?;
+        Ok(registry)
+    }
+
+    /// Check that the source and partials parse
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] naming the syntax error.
+    pub fn validate(&self) -> Result<()> {
+        self.registry().map(drop)
+    }
+
+    /// Render the template with `vars`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] for syntax errors, unset variables,
+    /// missing partials, or output larger than [`MAX_INTERPOLATED_LEN`].
+    pub fn render<S: BuildHasher>(
+        &self,
+        vars: &HashMap<String, serde_json::Value, S>,
+    ) -> Result<String> {
+        let output = self
+            .registry()?
+            .render(&self.name, vars)
+            .map_err(|e| self.invalid(e))?;
+        if output.len() > MAX_INTERPOLATED_LEN {
+            return Err(self.invalid(format!("output exceeds {MAX_INTERPOLATED_LEN} bytes")));
+        }
+        Ok(output)
+    }
+
+    /// A Bedrock generation config with the rendered template as system prompt
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from [`render`](Self::render).
+    #[cfg(feature = "bedrock")]
+    pub fn generation_config<S: BuildHasher>(
+        &self,
+        vars: &HashMap<String, serde_json::Value, S>,
+    ) -> Result<universal_bot_bedrock::GenerationConfig> {
+        Ok(universal_bot_bedrock::GenerationConfig {
+            system_prompt: Some(self.render(vars)?),
+            ..universal_bot_bedrock::GenerationConfig::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn support() -> PromptTemplate {
+        PromptTemplate::new(
+            "support",
+            "You help {{customer}} with {{product}}.{{#if vip}} They are a VIP.{{/if}}\n\
+             {{> tone}}{{#each rules}}\n- {{this}}{{/each}}",
+        )
+        .with_version("2")
+        .with_partial("tone", "Be {{tone}} & precise.")
+    }
+
+    #[test]
+    fn test_render_variables_conditionals_and_partials() {
+        let vars = HashMap::from([
+            ("customer".to_string(), json!("Ada <ada@example.com>")),
+            ("product".to_string(), json!("billing")),
+            ("tone".to_string(), json!("warm")),
+            ("vip".to_string(), json!(true)),
+            ("rules".to_string(), json!(["No refunds over $500"])),
+        ]);
+
+        assert_eq!(
+            support().render(&vars).unwrap(),
+            "You help Ada <ada@example.com> with billing. They are a VIP.\n\
+             Be warm & precise.\n- No refunds over $500"
+        );
+        assert_eq!(support().id(), "support@2");
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let unset = support()
+            .render(&HashMap::from([("customer".to_string(), json!("Ada"))]))
+            .unwrap_err();
+        assert!(unset.to_string().contains("support@2"));
+
+        assert!(PromptTemplate::new("broken", "{{#if x}}")
+            .validate()
+            .is_err());
+        assert!(PromptTemplate::new("partial", "{{> missing}}")
+            .render(&HashMap::new())
+            .is_err());
+    }
+}
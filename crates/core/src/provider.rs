@@ -0,0 +1,653 @@
+//! AI provider abstraction
+//!
+//! [`ProcessStage`](crate::pipeline::ProcessStage) turns a message and its
+//! conversation context into a [`ProviderRequest`] and hands it to a
+//! [`Provider`], which returns the model output and its real token usage.
+//! The `bedrock` feature adds `bedrock::BedrockProvider` for Amazon Bedrock,
+//! the `openai` feature [`OpenAiProvider`] for OpenAI-compatible HTTP APIs,
+//! and the `ollama` feature [`OllamaProvider`] for local Ollama models;
+//! other backends implement the trait directly.
+//!
+//! A [`ProviderRegistry`] holds several providers and is itself a provider:
+//! each request goes to the provider named under [`PROVIDER_METADATA_KEY`]
+//! in the pipeline or message metadata, else the provider routed for the
+//! model's prefix, else the default.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+    config::BotConfig,
+    context::{Context, MessageRole},
+    error::Error,
+    message::{Message, TokenUsage},
+    streaming::ChunkSink,
+    template,
+};
+
+/// Pipeline or message metadata key naming the provider for a request
+pub const PROVIDER_METADATA_KEY: &str = "provider";
+
+/// One turn of the conversation sent to the model
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderMessage {
+    /// Who produced the turn
+    pub role: MessageRole,
+    /// Turn text
+    pub content: String,
+}
+
+impl ProviderMessage {
+    /// A turn with the given role
+    #[must_use]
+    pub fn new(role: MessageRole, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+        }
+    }
+}
+
+/// Everything a provider needs to generate a reply
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProviderRequest {
+    /// Model to invoke
+    pub model: String,
+    /// Instructions sent ahead of the conversation
+    pub system_prompt: Option<String>,
+    /// Conversation turns in prompt order, ending with the new user message
+    pub messages: Vec<ProviderMessage>,
+    /// Sampling temperature
+    pub temperature: f32,
+    /// Maximum tokens to generate
+    pub max_tokens: usize,
+    /// Provider to use, overriding routing in a [`ProviderRegistry`]
+    #[serde(default)]
+    pub provider: Option<String>,
+}
+
+impl ProviderRequest {
+    /// Build the request for `message` in `context` under `config`
+    ///
+    /// History is taken in [`prompt order`](Context::prompt_history), so
+    /// system notes and pinned messages lead. A system prompt set by the
+    /// context's [template](crate::template) replaces the configured one.
+    #[must_use]
+    pub fn new(config: &BotConfig, model: &str, context: &Context, message: &Message) -> Self {
+        let mut messages: Vec<ProviderMessage> = context
+            .prompt_history()
+            .into_iter()
+            .map(|turn| ProviderMessage::new(turn.role, turn.content.as_str()))
+            .collect();
+        messages.push(ProviderMessage::new(
+            MessageRole::User,
+            message.content.as_str(),
+        ));
+
+        Self {
+            model: model.to_string(),
+            system_prompt: template::system_prompt_of(context)
+                .map(ToString::to_string)
+                .or_else(|| config.system_prompt.clone()),
+            messages,
+            temperature: config.temperature,
+            max_tokens: config.max_tokens,
+            provider: None,
+        }
+    }
+
+    /// Send the request to the named provider
+    #[must_use]
+    pub fn with_provider(mut self, provider: impl Into<String>) -> Self {
+        self.provider = Some(provider.into());
+        self
+    }
+
+    /// The turns with the system prompt, if any, as a leading system turn
+    ///
+    /// This is the shape chat-completion APIs expect.
+    #[must_use]
+    pub fn chat_messages(&self) -> Vec<ProviderMessage> {
+        self.system_prompt
+            .iter()
+            .map(|prompt| ProviderMessage::new(MessageRole::System, prompt.as_str()))
+            .chain(self.messages.iter().cloned())
+            .collect()
+    }
+}
+
+/// A provider's reply
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderResponse {
+    /// Generated text
+    pub content: String,
+    /// Tokens used and their cost, as reported by the provider
+    pub usage: TokenUsage,
+    /// Why generation stopped, e.g. `end_turn` or `max_tokens`
+    pub finish_reason: Option<String>,
+}
+
+/// A backend that generates replies
+#[async_trait]
+pub trait Provider: Send + Sync {
+    /// Provider name, for logs and traces
+    fn name(&self) -> &str;
+
+    /// Generate a reply
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the provider fails; errors the caller may retry
+    /// should be [`Error::Provider`](crate::error::Error::Provider),
+    /// [`Error::RateLimit`](crate::error::Error::RateLimit), or
+    /// [`Error::Timeout`](crate::error::Error::Timeout), so they count
+    /// towards the degraded-mode circuit breaker.
+    async fn generate(&self, request: ProviderRequest) -> Result<ProviderResponse>;
+
+    /// Generate a reply, sending its text to `sink` as it is produced
+    ///
+    /// The default sends the whole reply from [`generate`](Self::generate)
+    /// as one chunk; providers that can stream should override it.
+    ///
+    /// # Errors
+    ///
+    /// As for [`generate`](Self::generate).
+    async fn generate_streaming(
+        &self,
+        request: ProviderRequest,
+        sink: &ChunkSink,
+    ) -> Result<ProviderResponse> {
+        let response = self.generate(request).await?;
+        sink.send(response.content.clone()).await;
+        Ok(response)
+    }
+}
+
+/// A model prefix routed to a provider
+#[derive(Debug, Clone)]
+struct ModelRoute {
+    prefix: String,
+    provider: String,
+    strip_prefix: bool,
+}
+
+/// Several providers behind one, routing each request
+///
+/// The provider for a request is, in order: the one named in
+/// [`ProviderRequest::provider`], the one routed for the longest matching
+/// model prefix, and the default, which is the first registered unless set
+/// with [`default_provider`](Self::default_provider). Prefix routes can strip
+/// the prefix, so `ollama/llama3` reaches Ollama as `llama3`.
+#[derive(Default)]
+pub struct ProviderRegistry {
+    providers: HashMap<String, Arc<dyn Provider>>,
+    routes: Vec<ModelRoute>,
+    default: Option<String>,
+}
+
+impl std::fmt::Debug for ProviderRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProviderRegistry")
+            .field("providers", &self.names())
+            .field("routes", &self.routes)
+            .field("default", &self.default)
+            .finish()
+    }
+}
+
+impl ProviderRegistry {
+    /// Create an empty registry
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `provider` under `name`, replacing any provider of that name
+    #[must_use]
+    pub fn register(mut self, name: impl Into<String>, provider: Arc<dyn Provider>) -> Self {
+        let name = name.into();
+        self.default.get_or_insert_with(|| name.clone());
+        self.providers.insert(name, provider);
+        self
+    }
+
+    /// Send models starting with `prefix` to the provider named `provider`
+    #[must_use]
+    pub fn route(mut self, prefix: impl Into<String>, provider: impl Into<String>) -> Self {
+        self.routes.push(ModelRoute {
+            prefix: prefix.into(),
+            provider: provider.into(),
+            strip_prefix: false,
+        });
+        self
+    }
+
+    /// Like [`route`](Self::route), removing the prefix from the model name
+    #[must_use]
+    pub fn route_stripping(
+        mut self,
+        prefix: impl Into<String>,
+        provider: impl Into<String>,
+    ) -> Self {
+        self.routes.push(ModelRoute {
+            prefix: prefix.into(),
+            provider: provider.into(),
+            strip_prefix: true,
+        });
+        self
+    }
+
+    /// Use the provider named `name` for models no route matches
+    #[must_use]
+    pub fn default_provider(mut self, name: impl Into<String>) -> Self {
+        self.default = Some(name.into());
+        self
+    }
+
+    /// The provider registered under `name`
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn Provider>> {
+        self.providers.get(name)
+    }
+
+    /// Names of the registered providers, sorted
+    #[must_use]
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.providers.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// The provider for `request`, with the model rewritten for it
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Configuration`] if the provider the request names or
+    /// routes to is not registered, or no provider applies.
+    pub fn resolve(
+        &self,
+        mut request: ProviderRequest,
+    ) -> Result<(&Arc<dyn Provider>, ProviderRequest)> {
+        let name = if let Some(name) = request.provider.clone() {
+            name
+        } else if let Some(route) = self
+            .routes
+            .iter()
+            .filter(|route| request.model.starts_with(&route.prefix))
+            .max_by_key(|route| route.prefix.len())
+        {
+            if route.strip_prefix {
+                request.model = request.model[route.prefix.len()..].to_string();
+            }
+            route.provider.clone()
+        } else if let Some(name) = self.default.clone() {
+            name
+        } else {
+            return Err(
+                Error::Configuration(format!("No provider for model {}", request.model)).into(),
+            );
+        };
+
+        let provider = self
+            .providers
+            .get(&name)
+            .ok_or_else(|| Error::Configuration(format!("Unknown provider: {name}")))?;
+        Ok((provider, request))
+    }
+}
+
+#[async_trait]
+impl Provider for ProviderRegistry {
+    fn name(&self) -> &str {
+        "registry"
+    }
+
+    async fn generate(&self, request: ProviderRequest) -> Result<ProviderResponse> {
+        let (provider, request) = self.resolve(request)?;
+        debug!("Routing {} to provider {}", request.model, provider.name());
+        provider.generate(request).await
+    }
+
+    async fn generate_streaming(
+        &self,
+        request: ProviderRequest,
+        sink: &ChunkSink,
+    ) -> Result<ProviderResponse> {
+        let (provider, request) = self.resolve(request)?;
+        debug!("Routing {} to provider {}", request.model, provider.name());
+        provider.generate_streaming(request, sink).await
+    }
+}
+
+/// Map an HTTP failure onto the core error whose retry semantics match
+#[cfg(any(feature = "openai", feature = "ollama"))]
+fn http_error(provider: &str, error: &reqwest::Error) -> anyhow::Error {
+    let message = format!("{provider} request failed: {error}");
+    match error.status().map(|status| status.as_u16()) {
+        Some(429) => Error::RateLimit,
+        Some(401) => Error::Authentication(message),
+        Some(403) => Error::Authorization(message),
+        Some(400..=499) => Error::InvalidInput(message),
+        Some(_) => Error::Provider(message),
+        None => Error::Network(message),
+    }
+    .into()
+}
+
+/// Generates replies through an `OpenAI`-compatible chat completions API
+///
+/// Works with `OpenAI` itself and with gateways and servers exposing the same
+/// `/chat/completions` endpoint, such as `vLLM` or `LiteLLM`.
+#[cfg(feature = "openai")]
+pub struct OpenAiProvider {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+#[cfg(feature = "openai")]
+impl std::fmt::Debug for OpenAiProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenAiProvider")
+            .field("base_url", &self.base_url)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "openai")]
+impl OpenAiProvider {
+    /// Create a provider for the API at `base_url`, e.g. `https://api.openai.com/v1`
+    #[must_use]
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: None,
+        }
+    }
+
+    /// Authenticate with a bearer API key
+    #[must_use]
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+}
+
+#[cfg(feature = "openai")]
+#[async_trait]
+impl Provider for OpenAiProvider {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    async fn generate(&self, request: ProviderRequest) -> Result<ProviderResponse> {
+        #[derive(Deserialize)]
+        struct Completion {
+            choices: Vec<Choice>,
+            #[serde(default)]
+            usage: Option<Usage>,
+            #[serde(default)]
+            model: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct Choice {
+            message: ProviderMessage,
+            #[serde(default)]
+            finish_reason: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct Usage {
+            prompt_tokens: usize,
+            completion_tokens: usize,
+        }
+
+        let body = serde_json::json!({
+            "model": request.model,
+            "messages": request.chat_messages(),
+            "temperature": request.temperature,
+            "max_tokens": request.max_tokens,
+        });
+        let mut call = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&body);
+        if let Some(api_key) = &self.api_key {
+            call = call.bearer_auth(api_key);
+        }
+        let completion: Completion = call
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| http_error("OpenAI", &e))?
+            .json()
+            .await
+            .map_err(|e| Error::Provider(format!("Invalid OpenAI response: {e}")))?;
+
+        let Some(choice) = completion.choices.into_iter().next() else {
+            return Err(Error::Provider("OpenAI returned no choices".to_string()).into());
+        };
+        let model = completion.model.unwrap_or(request.model);
+        let usage = completion.usage.map_or_else(
+            || TokenUsage::new(0, 0, model.as_str()),
+            |usage| TokenUsage::new(usage.prompt_tokens, usage.completion_tokens, model.as_str()),
+        );
+        Ok(ProviderResponse {
+            content: choice.message.content,
+            usage,
+            finish_reason: choice.finish_reason,
+        })
+    }
+}
+
+/// Generates replies with models served by a local Ollama
+#[cfg(feature = "ollama")]
+pub struct OllamaProvider {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+#[cfg(feature = "ollama")]
+impl std::fmt::Debug for OllamaProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OllamaProvider")
+            .field("base_url", &self.base_url)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "ollama")]
+impl Default for OllamaProvider {
+    fn default() -> Self {
+        Self::new("http://localhost:11434")
+    }
+}
+
+#[cfg(feature = "ollama")]
+impl OllamaProvider {
+    /// Create a provider for the Ollama server at `base_url`
+    #[must_use]
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "ollama")]
+#[async_trait]
+impl Provider for OllamaProvider {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    async fn generate(&self, request: ProviderRequest) -> Result<ProviderResponse> {
+        #[derive(Deserialize)]
+        struct Chat {
+            message: ProviderMessage,
+            #[serde(default)]
+            done_reason: Option<String>,
+            #[serde(default)]
+            prompt_eval_count: usize,
+            #[serde(default)]
+            eval_count: usize,
+        }
+
+        let body = serde_json::json!({
+            "model": request.model,
+            "messages": request.chat_messages(),
+            "stream": false,
+            "options": {
+                "temperature": request.temperature,
+                "num_predict": request.max_tokens,
+            },
+        });
+        let chat: Chat = self
+            .client
+            .post(format!("{}/api/chat", self.base_url))
+            .json(&body)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| http_error("Ollama", &e))?
+            .json()
+            .await
+            .map_err(|e| Error::Provider(format!("Invalid Ollama response: {e}")))?;
+
+        // Local models cost nothing per token
+        let usage = TokenUsage::new(chat.prompt_eval_count, chat.eval_count, request.model)
+            .with_inference_cost(0.0);
+        Ok(ProviderResponse {
+            content: chat.message.content,
+            usage,
+            finish_reason: chat.done_reason,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Response;
+
+    struct Named(&'static str);
+
+    #[async_trait]
+    impl Provider for Named {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        async fn generate(&self, request: ProviderRequest) -> Result<ProviderResponse> {
+            Ok(ProviderResponse {
+                content: format!("{} answered with {}", self.0, request.model),
+                usage: TokenUsage::new(1, 1, request.model),
+                finish_reason: None,
+            })
+        }
+    }
+
+    fn request(model: &str) -> ProviderRequest {
+        ProviderRequest::new(
+            &BotConfig::default(),
+            model,
+            &Context::new("conv"),
+            &Message::text("Hi"),
+        )
+    }
+
+    #[test]
+    fn test_request_includes_history_in_prompt_order() {
+        let config = BotConfig::builder()
+            .model("anthropic.claude-sonnet-4")
+            .system_prompt("Be brief")
+            .build()
+            .unwrap();
+        let mut context = Context::new("conv");
+        context.add_message(&Message::text("Hi"));
+        context.add_response(&Response::text("conv", "Hello!"));
+
+        let request = ProviderRequest::new(
+            &config,
+            "anthropic.claude-haiku",
+            &context,
+            &Message::text("How are you?"),
+        );
+        assert_eq!(request.model, "anthropic.claude-haiku");
+        assert_eq!(request.system_prompt.as_deref(), Some("Be brief"));
+        assert_eq!(
+            request.messages,
+            vec![
+                ProviderMessage::new(MessageRole::User, "Hi"),
+                ProviderMessage::new(MessageRole::Assistant, "Hello!"),
+                ProviderMessage::new(MessageRole::User, "How are you?"),
+            ]
+        );
+
+        context.set_variable(template::SYSTEM_PROMPT_VARIABLE, "Be thorough".into());
+        let request = ProviderRequest::new(&config, "m", &context, &Message::text("Why?"));
+        assert_eq!(request.system_prompt.as_deref(), Some("Be thorough"));
+    }
+
+    #[tokio::test]
+    async fn test_registry_routes_by_prefix_and_metadata() {
+        let registry = ProviderRegistry::new()
+            .register("bedrock", Arc::new(Named("bedrock")))
+            .register("ollama", Arc::new(Named("ollama")))
+            .register("openai", Arc::new(Named("openai")))
+            .route("gpt-", "openai")
+            .route_stripping("ollama/", "ollama");
+        assert_eq!(registry.names(), ["bedrock", "ollama", "openai"]);
+
+        let registry = &registry;
+        let answer = |request| async move { registry.generate(request).await.unwrap().content };
+        assert_eq!(
+            answer(request("anthropic.claude-haiku")).await,
+            "bedrock answered with anthropic.claude-haiku"
+        );
+        assert_eq!(
+            answer(request("gpt-4o")).await,
+            "openai answered with gpt-4o"
+        );
+        assert_eq!(
+            answer(request("ollama/llama3")).await,
+            "ollama answered with llama3"
+        );
+        assert_eq!(
+            answer(request("gpt-4o").with_provider("ollama")).await,
+            "ollama answered with gpt-4o"
+        );
+
+        let unknown = registry
+            .generate(request("gpt-4o").with_provider("vertex"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            unknown.downcast_ref::<Error>(),
+            Some(Error::Configuration(_))
+        ));
+        assert!(ProviderRegistry::new()
+            .generate(request("gpt-4o"))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_chat_messages_lead_with_system_prompt() {
+        let mut request = request("gpt-4o");
+        request.system_prompt = Some("Be brief".to_string());
+        assert_eq!(
+            request.chat_messages(),
+            vec![
+                ProviderMessage::new(MessageRole::System, "Be brief"),
+                ProviderMessage::new(MessageRole::User, "Hi"),
+            ]
+        );
+    }
+}
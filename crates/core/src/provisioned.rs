@@ -0,0 +1,341 @@
+//! Provisioned throughput routing
+//!
+//! Bedrock provisioned throughput sells model units with a fixed tokens per
+//! minute capacity, billed whether or not it is used. The
+//! [`ProvisionedThroughputManager`] sends requests for a base model to a
+//! matching provisioned deployment while its capacity in the last minute
+//! stays below the spillover threshold, then spills over to the on-demand
+//! model. It also tracks how much of each commitment is actually used.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::config::{ProvisionedModelConfig, ProvisionedThroughputConfig};
+
+/// Pipeline metadata key recording which capacity served the message
+pub const CAPACITY_METADATA_KEY: &str = "model_capacity";
+
+/// Span over which per-minute capacity is measured
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Capacity a request was routed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capacity {
+    /// A provisioned deployment with headroom
+    Provisioned,
+    /// The on-demand model, because provisioned capacity was saturated or expired
+    Spillover,
+    /// The on-demand model, because no deployment serves it
+    OnDemand,
+}
+
+/// Where a request should be sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapacityRoute {
+    /// Model ID or ARN to invoke
+    pub model: String,
+    /// Capacity the model belongs to
+    pub capacity: Capacity,
+}
+
+/// Utilization of one provisioned commitment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommitmentUtilization {
+    /// Provisioned model ARN
+    pub arn: String,
+    /// On-demand model the deployment serves
+    pub base_model: String,
+    /// Purchased model units
+    pub model_units: u32,
+    /// Share of per-minute capacity used in the last minute
+    pub current_utilization: f64,
+    /// Share of capacity used since tracking started
+    pub average_utilization: f64,
+    /// Tokens served since tracking started
+    pub total_tokens: u64,
+    /// Requests served since tracking started
+    pub requests: u64,
+    /// Requests that spilled over to on-demand instead
+    pub spillovers: u64,
+    /// End of the commitment term
+    pub commitment_expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    window: VecDeque<(Instant, u64)>,
+    window_tokens: u64,
+    total_tokens: u64,
+    requests: u64,
+    spillovers: u64,
+}
+
+impl Usage {
+    fn prune(&mut self, now: Instant) {
+        while let Some(&(at, tokens)) = self.window.front() {
+            if now.saturating_duration_since(at) < WINDOW {
+                break;
+            }
+            self.window.pop_front();
+            self.window_tokens -= tokens;
+        }
+    }
+
+    fn add(&mut self, tokens: u64, now: Instant) {
+        self.window.push_back((now, tokens));
+        self.window_tokens += tokens;
+        self.total_tokens += tokens;
+    }
+}
+
+/// Routes between provisioned and on-demand capacity and tracks utilization
+#[derive(Debug)]
+pub struct ProvisionedThroughputManager {
+    config: ProvisionedThroughputConfig,
+    output_reserve: u64,
+    usage: Mutex<HashMap<String, Usage>>,
+    started: Instant,
+}
+
+impl ProvisionedThroughputManager {
+    /// Create the manager with no recorded usage
+    #[must_use]
+    pub fn new(config: ProvisionedThroughputConfig) -> Self {
+        Self {
+            config,
+            output_reserve: 0,
+            usage: Mutex::new(HashMap::new()),
+            started: Instant::now(),
+        }
+    }
+
+    /// Reserve `tokens` of output on top of each routed request's prompt estimate
+    #[must_use]
+    pub fn with_output_reserve(mut self, tokens: u64) -> Self {
+        self.output_reserve = tokens;
+        self
+    }
+
+    /// Whether any provisioned deployment is configured
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        !self.config.models.is_empty()
+    }
+
+    /// Tokens to reserve for a prompt, using about four characters per token
+    #[must_use]
+    pub fn estimate_tokens(&self, prompt: &str) -> u64 {
+        (prompt.chars().count() as u64).div_ceil(4) + self.output_reserve
+    }
+
+    /// Choose the capacity for a request and reserve its estimated tokens
+    ///
+    /// `model` may be a base model or a provisioned ARN. Requests for a
+    /// saturated or expired deployment are sent to its base model instead.
+    pub fn route(&self, model: &str, estimated_tokens: u64) -> CapacityRoute {
+        self.route_at(model, estimated_tokens, Instant::now(), Utc::now())
+    }
+
+    /// Record tokens served by a deployment beyond what [`route`](Self::route) reserved
+    pub fn record(&self, arn: &str, tokens: u64) {
+        let now = Instant::now();
+        let mut usage = self.usage.lock();
+        let entry = usage.entry(arn.to_string()).or_default();
+        entry.prune(now);
+        entry.add(tokens, now);
+        drop(usage);
+    }
+
+    /// Share of a deployment's per-minute capacity used in the last minute
+    #[must_use]
+    pub fn utilization(&self, arn: &str) -> Option<f64> {
+        let deployment = self.config.models.iter().find(|m| m.arn == arn)?;
+        let now = Instant::now();
+        let mut usage = self.usage.lock();
+        let entry = usage.entry(arn.to_string()).or_default();
+        entry.prune(now);
+        let window_tokens = entry.window_tokens;
+        drop(usage);
+        Some(ratio(window_tokens, deployment.tokens_per_minute()))
+    }
+
+    /// Utilization of every configured commitment
+    #[must_use]
+    pub fn report(&self) -> Vec<CommitmentUtilization> {
+        self.report_at(Instant::now())
+    }
+
+    fn route_at(
+        &self,
+        model: &str,
+        estimated_tokens: u64,
+        now: Instant,
+        today: DateTime<Utc>,
+    ) -> CapacityRoute {
+        let matching: Vec<&ProvisionedModelConfig> = self
+            .config
+            .models
+            .iter()
+            .filter(|m| m.base_model == model || m.arn == model)
+            .collect();
+        let Some(base_model) = matching.first().map(|m| m.base_model.clone()) else {
+            return CapacityRoute {
+                model: model.to_string(),
+                capacity: Capacity::OnDemand,
+            };
+        };
+
+        let mut usage = self.usage.lock();
+        let mut best: Option<(&ProvisionedModelConfig, f64)> = None;
+        for deployment in matching
+            .into_iter()
+            .filter(|m| m.commitment_expires_at.is_none_or(|end| end > today))
+        {
+            let entry = usage.entry(deployment.arn.clone()).or_default();
+            entry.prune(now);
+            let projected = ratio(
+                entry.window_tokens + estimated_tokens,
+                deployment.tokens_per_minute(),
+            );
+            if best.is_none_or(|(_, lowest)| projected < lowest) {
+                best = Some((deployment, projected));
+            }
+        }
+
+        match best {
+            Some((deployment, projected)) if projected <= self.config.spillover_threshold => {
+                let entry = usage.entry(deployment.arn.clone()).or_default();
+                entry.add(estimated_tokens, now);
+                entry.requests += 1;
+                drop(usage);
+                CapacityRoute {
+                    model: deployment.arn.clone(),
+                    capacity: Capacity::Provisioned,
+                }
+            }
+            best => {
+                if let Some((deployment, projected)) = best {
+                    debug!(
+                        arn = %deployment.arn,
+                        projected,
+                        "Provisioned capacity saturated, spilling over to on-demand"
+                    );
+                    usage.entry(deployment.arn.clone()).or_default().spillovers += 1;
+                }
+                drop(usage);
+                CapacityRoute {
+                    model: base_model,
+                    capacity: Capacity::Spillover,
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn report_at(&self, now: Instant) -> Vec<CommitmentUtilization> {
+        let elapsed_minutes = (now.saturating_duration_since(self.started).as_secs_f64()
+            / WINDOW.as_secs_f64())
+        .max(1.0);
+        let mut usage = self.usage.lock();
+        self.config
+            .models
+            .iter()
+            .map(|deployment| {
+                let entry = usage.entry(deployment.arn.clone()).or_default();
+                entry.prune(now);
+                let capacity = deployment.tokens_per_minute();
+                CommitmentUtilization {
+                    arn: deployment.arn.clone(),
+                    base_model: deployment.base_model.clone(),
+                    model_units: deployment.model_units,
+                    current_utilization: ratio(entry.window_tokens, capacity),
+                    average_utilization: if capacity == 0 {
+                        0.0
+                    } else {
+                        entry.total_tokens as f64 / (capacity as f64 * elapsed_minutes)
+                    },
+                    total_tokens: entry.total_tokens,
+                    requests: entry.requests,
+                    spillovers: entry.spillovers,
+                    commitment_expires_at: deployment.commitment_expires_at,
+                }
+            })
+            .collect()
+    }
+}
+
+/// `used / capacity`, treating zero capacity as saturated
+#[allow(clippy::cast_precision_loss)]
+fn ratio(used: u64, capacity: u64) -> f64 {
+    if capacity == 0 {
+        f64::INFINITY
+    } else {
+        used as f64 / capacity as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARN: &str = "arn:aws:bedrock:us-east-1:123456789012:provisioned-model/abc123";
+
+    fn manager(expires: Option<DateTime<Utc>>) -> ProvisionedThroughputManager {
+        ProvisionedThroughputManager::new(ProvisionedThroughputConfig {
+            models: vec![ProvisionedModelConfig {
+                arn: ARN.to_string(),
+                base_model: "anthropic.claude-haiku".to_string(),
+                model_units: 1,
+                tokens_per_minute_per_unit: 1_000,
+                commitment_expires_at: expires,
+            }],
+            spillover_threshold: 0.9,
+        })
+    }
+
+    #[test]
+    fn test_prefers_provisioned_then_spills_over() {
+        let manager = manager(None);
+        let now = Instant::now();
+
+        let route = manager.route_at("anthropic.claude-haiku", 500, now, Utc::now());
+        assert_eq!(route.model, ARN);
+        assert_eq!(route.capacity, Capacity::Provisioned);
+
+        let route = manager.route_at("anthropic.claude-haiku", 500, now, Utc::now());
+        assert_eq!(route.model, "anthropic.claude-haiku");
+        assert_eq!(route.capacity, Capacity::Spillover);
+
+        let route = manager.route_at(ARN, 400, now, Utc::now());
+        assert_eq!(route.capacity, Capacity::Provisioned);
+
+        let later = now + WINDOW;
+        let route = manager.route_at("anthropic.claude-haiku", 800, later, Utc::now());
+        assert_eq!(route.capacity, Capacity::Provisioned);
+
+        let report = manager.report_at(later);
+        assert_eq!(report[0].total_tokens, 1_700);
+        assert_eq!(report[0].requests, 3);
+        assert_eq!(report[0].spillovers, 1);
+        assert!((report[0].current_utilization - 0.8).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_expired_commitment_and_unknown_models() {
+        let expired = manager(Some(Utc::now() - chrono::Duration::days(1)));
+        let route = expired.route(ARN, 10);
+        assert_eq!(route.model, "anthropic.claude-haiku");
+        assert_eq!(route.capacity, Capacity::Spillover);
+
+        let route = expired.route("meta.llama3-8b-instruct", 10);
+        assert_eq!(route.model, "meta.llama3-8b-instruct");
+        assert_eq!(route.capacity, Capacity::OnDemand);
+        assert_eq!(expired.utilization("meta.llama3-8b-instruct"), None);
+    }
+}
@@ -0,0 +1,282 @@
+//! Retrieval-augmented generation
+//!
+//! The [`RetrieveStage`] embeds the user's message, searches a
+//! [`VectorStore`] for the closest documents, and records them in the
+//! pipeline metadata under [`RETRIEVED_DOCUMENTS_METADATA_KEY`]. The process
+//! stage then adds them to the system prompt as numbered references.
+//!
+//! Enable it by listing `retrieve` before `process` in
+//! [`PipelineConfig::enabled_stages`](crate::config::PipelineConfig::enabled_stages)
+//! and giving the pipeline a [`Retriever`]. Any store works, e.g. the
+//! in-memory [`HnswIndex`](crate::vector::HnswIndex) filled by the
+//! [ingestion pipeline](crate::ingest).
+
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+    config::RetrievalConfig,
+    error::Error,
+    pipeline::{PipelineContext, PipelineStage},
+    vector::{Embedder, MetadataFilter, VectorStore},
+};
+
+/// Pipeline metadata key listing the [`RetrievedDocument`]s for a message
+pub const RETRIEVED_DOCUMENTS_METADATA_KEY: &str = "retrieved_documents";
+
+/// A document retrieved for a message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetrievedDocument {
+    /// Record ID in the vector store
+    pub id: String,
+    /// Document text
+    pub content: String,
+    /// Similarity to the message (higher is more similar)
+    pub score: f32,
+    /// Record metadata, e.g. the source document
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, serde_json::Value>,
+}
+
+/// Embeds queries and searches a vector store with them
+pub struct Retriever {
+    embedder: Arc<dyn Embedder>,
+    store: Arc<dyn VectorStore>,
+    filter: Option<MetadataFilter>,
+}
+
+impl std::fmt::Debug for Retriever {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Retriever")
+            .field("model", &self.embedder.model())
+            .field("filter", &self.filter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Retriever {
+    /// Search `store` with queries embedded by `embedder`
+    ///
+    /// The embedder must be the one the store's records were embedded with.
+    #[must_use]
+    pub fn new(embedder: Arc<dyn Embedder>, store: Arc<dyn VectorStore>) -> Self {
+        Self {
+            embedder,
+            store,
+            filter: None,
+        }
+    }
+
+    /// Only retrieve records matching `filter`
+    #[must_use]
+    pub fn with_filter(mut self, filter: MetadataFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// The documents closest to `query` under `config`, most similar first
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query cannot be embedded or the store cannot
+    /// be searched.
+    pub async fn retrieve(
+        &self,
+        query: &str,
+        config: &RetrievalConfig,
+    ) -> Result<Vec<RetrievedDocument>> {
+        let mut vectors = self.embedder.embed(&[query.to_string()]).await?;
+        let Some(vector) = vectors.pop() else {
+            return Err(Error::Provider(format!(
+                "Embedder {} returned no vector for the query",
+                self.embedder.model()
+            ))
+            .into());
+        };
+
+        let hits = self
+            .store
+            .search(&vector, config.top_k, self.filter.as_ref())
+            .await?;
+        Ok(hits
+            .into_iter()
+            .filter(|hit| !config.min_score.is_some_and(|min| hit.score < min))
+            .map(|hit| RetrievedDocument {
+                id: hit.record.id,
+                content: hit.record.content,
+                score: hit.score,
+                metadata: hit.record.metadata,
+            })
+            .collect())
+    }
+}
+
+/// Retrieved documents as numbered references for the system prompt
+///
+/// Documents that would take the text past `max_chars` are left out.
+#[must_use]
+pub fn prompt_context(documents: &[RetrievedDocument], max_chars: usize) -> Option<String> {
+    let mut context = String::from("Use these documents to answer, citing them by number:");
+    let base = context.len();
+    for (n, document) in documents.iter().enumerate() {
+        let entry = format!("\n[{}] {}", n + 1, document.content.trim());
+        if context.len() - base + entry.len() > max_chars {
+            break;
+        }
+        context.push_str(&entry);
+    }
+    (context.len() > base).then_some(context)
+}
+
+/// The documents a [`RetrieveStage`] recorded in pipeline metadata
+#[must_use]
+pub fn retrieved_documents<S: BuildHasher>(
+    metadata: &HashMap<String, serde_json::Value, S>,
+) -> Vec<RetrievedDocument> {
+    metadata
+        .get(RETRIEVED_DOCUMENTS_METADATA_KEY)
+        .and_then(|value| serde_json::from_value(value.clone()).ok())
+        .unwrap_or_default()
+}
+
+/// Pipeline stage that retrieves documents for the message
+///
+/// Register it before `process` as the `retrieve` stage. Only messages on
+/// the default route are looked up. When retrieval fails the message is
+/// answered without documents.
+#[derive(Debug)]
+pub struct RetrieveStage {
+    retriever: Arc<Retriever>,
+    config: RetrievalConfig,
+}
+
+impl RetrieveStage {
+    /// Create the stage
+    #[must_use]
+    pub fn new(retriever: Arc<Retriever>, config: RetrievalConfig) -> Self {
+        Self { retriever, config }
+    }
+}
+
+#[async_trait]
+impl PipelineStage for RetrieveStage {
+    fn name(&self) -> &str {
+        "retrieve"
+    }
+
+    async fn process(&self, mut ctx: PipelineContext) -> Result<PipelineContext> {
+        let route = ctx
+            .metadata
+            .get("route")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or("default");
+        if route != "default" || ctx.message.content.trim().is_empty() {
+            return Ok(ctx);
+        }
+
+        match self
+            .retriever
+            .retrieve(&ctx.message.content, &self.config)
+            .await
+        {
+            Ok(documents) if documents.is_empty() => {}
+            Ok(documents) => {
+                debug!("Retrieved {} document(s)", documents.len());
+                ctx.metadata.insert(
+                    RETRIEVED_DOCUMENTS_METADATA_KEY.to_string(),
+                    serde_json::to_value(documents)?,
+                );
+            }
+            Err(e) => warn!("Retrieval failed, answering without documents: {:#}", e),
+        }
+        Ok(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Context;
+    use crate::message::Message;
+    use crate::vector::{HnswIndex, VectorRecord};
+    use parking_lot::RwLock;
+
+    /// Embeds text by counting the letters a, b, and c
+    struct LetterEmbedder;
+
+    #[async_trait]
+    impl Embedder for LetterEmbedder {
+        fn model(&self) -> &str {
+            "letters"
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    ['a', 'b', 'c']
+                        .iter()
+                        .map(|letter| text.matches(*letter).count() as f32)
+                        .collect()
+                })
+                .collect())
+        }
+    }
+
+    async fn retriever() -> Arc<Retriever> {
+        let store = Arc::new(HnswIndex::default());
+        store
+            .upsert(vec![
+                VectorRecord::new("a", vec![1.0, 0.0, 0.0], "all about a"),
+                VectorRecord::new("b", vec![0.0, 1.0, 0.0], "all about b"),
+                VectorRecord::new("c", vec![0.0, 0.0, 1.0], "all about c"),
+            ])
+            .await
+            .unwrap();
+        Arc::new(Retriever::new(Arc::new(LetterEmbedder), store))
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_respects_top_k_and_min_score() {
+        let retriever = retriever().await;
+        let config = RetrievalConfig {
+            top_k: 2,
+            min_score: Some(0.5),
+            ..RetrievalConfig::default()
+        };
+        let documents = retriever.retrieve("bbb", &config).await.unwrap();
+        let ids: Vec<&str> = documents.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, ["b"]);
+    }
+
+    #[tokio::test]
+    async fn test_stage_records_documents() {
+        let stage = RetrieveStage::new(retriever().await, RetrievalConfig::default());
+        let context = Arc::new(RwLock::new(Context::new("conv")));
+
+        let ctx = stage
+            .process(PipelineContext::new(Message::text("cc"), context.clone()))
+            .await
+            .unwrap();
+        let documents = retrieved_documents(&ctx.metadata);
+        assert_eq!(documents[0].id, "c");
+
+        let prompt = prompt_context(&documents, 1_000).unwrap();
+        assert!(prompt.contains("[1] all about c"));
+        assert!(prompt_context(&documents, 5).is_none());
+
+        let mut command = PipelineContext::new(Message::text("/help"), context);
+        command
+            .metadata
+            .insert("route".to_string(), serde_json::json!("command"));
+        let ctx = stage.process(command).await.unwrap();
+        assert!(retrieved_documents(&ctx.metadata).is_empty());
+    }
+}
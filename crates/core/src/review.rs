@@ -0,0 +1,497 @@
+//! Sampling conversations into a human review queue
+//!
+//! A [`ReviewSampler`] decides which conversations a person should read:
+//! a share of all conversations picked at random, plus every conversation
+//! with low user feedback or an escalation tag, according to its
+//! [`SamplingRule`]s. Sampled conversations become [`ReviewItem`]s in a
+//! [`ReviewQueue`] with their transcript already redacted. Reviewers take
+//! items from the queue and record a [`ReviewVerdict`];
+//! [`ReviewQueue::quality_metrics`] aggregates verdicts per day.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{
+    context::Context, curation::Exchange, feedback::FeedbackAnalyzer, sanitize::redact_pii,
+};
+
+/// Why a conversation was sampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SampleReason {
+    /// Picked at random
+    Random,
+    /// An exchange had low feedback or judge rating
+    LowFeedback,
+    /// The conversation carries an escalation tag
+    Escalated,
+}
+
+/// When to send a conversation for review
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SamplingRule {
+    /// Any conversation
+    All {
+        /// Share of conversations sampled, 0.0 to 1.0
+        rate: f64,
+    },
+    /// Conversations with an exchange scored at or below `max_score`
+    LowFeedback {
+        /// Highest feedback score or judge rating that counts as low
+        max_score: f32,
+        /// Share of matching conversations sampled
+        rate: f64,
+    },
+    /// Conversations tagged with any of `tags`
+    Escalated {
+        /// Context tags that mark an escalation
+        tags: Vec<String>,
+        /// Share of matching conversations sampled
+        rate: f64,
+    },
+}
+
+impl SamplingRule {
+    fn reason(&self) -> SampleReason {
+        match self {
+            Self::All { .. } => SampleReason::Random,
+            Self::LowFeedback { .. } => SampleReason::LowFeedback,
+            Self::Escalated { .. } => SampleReason::Escalated,
+        }
+    }
+
+    const fn rate(&self) -> f64 {
+        match self {
+            Self::All { rate } | Self::LowFeedback { rate, .. } | Self::Escalated { rate, .. } => {
+                *rate
+            }
+        }
+    }
+
+    fn matches(&self, context: &Context, exchanges: &[Exchange]) -> bool {
+        match self {
+            Self::All { .. } => true,
+            Self::LowFeedback { max_score, .. } => exchanges
+                .iter()
+                .filter_map(FeedbackAnalyzer::score)
+                .any(|score| score <= *max_score),
+            Self::Escalated { tags, .. } => context.metadata.tags.iter().any(|t| tags.contains(t)),
+        }
+    }
+}
+
+/// Configuration for review sampling
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReviewSamplingConfig {
+    /// Rules checked in order; the first that samples a conversation sets its reason
+    pub rules: Vec<SamplingRule>,
+    /// Mask personal data in transcripts before they are queued
+    pub redact: bool,
+}
+
+impl Default for ReviewSamplingConfig {
+    fn default() -> Self {
+        Self {
+            rules: vec![
+                SamplingRule::Escalated {
+                    tags: vec!["escalated".to_string()],
+                    rate: 1.0,
+                },
+                SamplingRule::LowFeedback {
+                    max_score: 0.4,
+                    rate: 1.0,
+                },
+                SamplingRule::All { rate: 0.02 },
+            ],
+            redact: true,
+        }
+    }
+}
+
+/// A reviewer's assessment of a conversation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReviewVerdict {
+    /// Who reviewed the conversation
+    pub reviewer: String,
+    /// Whether the bot's replies were acceptable
+    pub acceptable: bool,
+    /// Quality score, 0.0 (bad) to 1.0 (good)
+    pub score: f32,
+    /// Problem labels, e.g. `hallucination` or `tone`
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Free-form notes
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// When the verdict was recorded
+    pub reviewed_at: DateTime<Utc>,
+}
+
+impl ReviewVerdict {
+    /// Create a verdict recorded now
+    #[must_use]
+    pub fn new(reviewer: impl Into<String>, acceptable: bool, score: f32) -> Self {
+        Self {
+            reviewer: reviewer.into(),
+            acceptable,
+            score: score.clamp(0.0, 1.0),
+            labels: Vec::new(),
+            notes: None,
+            reviewed_at: Utc::now(),
+        }
+    }
+
+    /// Add a problem label
+    #[must_use]
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.labels.push(label.into());
+        self
+    }
+
+    /// Attach notes
+    #[must_use]
+    pub fn with_notes(mut self, notes: impl Into<String>) -> Self {
+        self.notes = Some(notes.into());
+        self
+    }
+}
+
+/// A conversation waiting for, or given, a review
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReviewItem {
+    /// Queue item ID
+    pub id: Uuid,
+    /// Conversation reviewed
+    pub conversation_id: String,
+    /// Why the conversation was sampled
+    pub reason: SampleReason,
+    /// Redacted transcript
+    pub transcript: Vec<Exchange>,
+    /// Lowest feedback score or judge rating in the conversation
+    pub lowest_score: Option<f32>,
+    /// When the conversation was queued
+    pub queued_at: DateTime<Utc>,
+    /// Reviewer's verdict, once recorded
+    pub verdict: Option<ReviewVerdict>,
+}
+
+/// Picks conversations for review with [`ReviewSamplingConfig`] rules
+#[derive(Debug, Clone)]
+pub struct ReviewSampler {
+    config: ReviewSamplingConfig,
+}
+
+impl ReviewSampler {
+    /// Sample with the given rules
+    #[must_use]
+    pub fn new(config: ReviewSamplingConfig) -> Self {
+        Self { config }
+    }
+
+    /// Why `context` should be reviewed, or `None` to skip it
+    ///
+    /// `exchanges` carry the conversation's feedback scores. Random draws
+    /// are derived from the conversation ID, so a conversation is sampled
+    /// the same way each time it is checked.
+    #[must_use]
+    pub fn sample(&self, context: &Context, exchanges: &[Exchange]) -> Option<SampleReason> {
+        let draw = draw(&context.id);
+        self.config
+            .rules
+            .iter()
+            .find(|rule| rule.matches(context, exchanges) && draw < rule.rate().clamp(0.0, 1.0))
+            .map(SamplingRule::reason)
+    }
+
+    /// Build a queue item for `context` if it is sampled
+    #[must_use]
+    pub fn review_item(&self, context: &Context, exchanges: &[Exchange]) -> Option<ReviewItem> {
+        let reason = self.sample(context, exchanges)?;
+        let transcript = exchanges.iter().map(|e| self.prepare(e)).collect();
+        Some(ReviewItem {
+            id: Uuid::new_v4(),
+            conversation_id: context.id.clone(),
+            reason,
+            transcript,
+            lowest_score: exchanges
+                .iter()
+                .filter_map(FeedbackAnalyzer::score)
+                .min_by(f32::total_cmp),
+            queued_at: Utc::now(),
+            verdict: None,
+        })
+    }
+
+    fn prepare(&self, exchange: &Exchange) -> Exchange {
+        if !self.config.redact {
+            return exchange.clone();
+        }
+        let mut exchange = exchange.clone();
+        exchange.prompt = redact_pii(&exchange.prompt);
+        exchange.completion = redact_pii(&exchange.completion);
+        exchange.system = exchange.system.as_deref().map(redact_pii);
+        exchange
+    }
+}
+
+/// Map a conversation ID to a stable value in `[0.0, 1.0)`
+fn draw(conversation_id: &str) -> f64 {
+    const BUCKETS: u32 = 10_000;
+    let digest = Sha256::digest(conversation_id.as_bytes());
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    let bucket = u32::try_from(u64::from_be_bytes(bytes) % u64::from(BUCKETS)).unwrap_or(0);
+    f64::from(bucket) / f64::from(BUCKETS)
+}
+
+/// Review quality for one day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QualityBucket {
+    /// Day the verdicts were recorded, UTC
+    pub date: NaiveDate,
+    /// Conversations reviewed
+    pub reviewed: usize,
+    /// Conversations judged acceptable
+    pub acceptable: usize,
+    /// Mean reviewer score
+    pub mean_score: f32,
+    /// Reviews per sampling reason
+    pub by_reason: BTreeMap<SampleReason, usize>,
+    /// Occurrences of each problem label
+    pub labels: BTreeMap<String, usize>,
+}
+
+impl QualityBucket {
+    /// Share of reviewed conversations judged acceptable
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn acceptance_rate(&self) -> f32 {
+        if self.reviewed == 0 {
+            0.0
+        } else {
+            self.acceptable as f32 / self.reviewed as f32
+        }
+    }
+}
+
+/// Sampled conversations awaiting and holding reviewer verdicts
+#[derive(Debug, Default)]
+pub struct ReviewQueue {
+    items: DashMap<Uuid, ReviewItem>,
+}
+
+impl ReviewQueue {
+    /// Create an empty queue
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an item, replacing any unreviewed item for the same conversation
+    pub fn enqueue(&self, item: ReviewItem) {
+        self.items.retain(|_, existing| {
+            existing.conversation_id != item.conversation_id || existing.verdict.is_some()
+        });
+        debug!(
+            conversation_id = %item.conversation_id,
+            reason = ?item.reason,
+            "Queued conversation for review"
+        );
+        self.items.insert(item.id, item);
+    }
+
+    /// Look up an item
+    #[must_use]
+    pub fn get(&self, id: Uuid) -> Option<ReviewItem> {
+        self.items.get(&id).map(|item| item.clone())
+    }
+
+    /// Items without a verdict, escalations first, then oldest first
+    #[must_use]
+    pub fn pending(&self, limit: usize) -> Vec<ReviewItem> {
+        let mut items: Vec<ReviewItem> = self
+            .items
+            .iter()
+            .filter(|item| item.verdict.is_none())
+            .map(|item| item.clone())
+            .collect();
+        items.sort_by(|a, b| {
+            b.reason
+                .cmp(&a.reason)
+                .then_with(|| a.queued_at.cmp(&b.queued_at))
+        });
+        items.truncate(limit);
+        items
+    }
+
+    /// Record a reviewer's verdict for an item
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no item with this ID or it already has a verdict.
+    pub fn record_verdict(&self, id: Uuid, verdict: ReviewVerdict) -> Result<()> {
+        let Some(mut item) = self.items.get_mut(&id) else {
+            bail!("No review item {id}");
+        };
+        if item.verdict.is_some() {
+            bail!("Review item {id} already has a verdict");
+        }
+        item.verdict = Some(verdict);
+        Ok(())
+    }
+
+    /// Number of items without a verdict
+    #[must_use]
+    pub fn pending_count(&self) -> usize {
+        self.items.iter().filter(|i| i.verdict.is_none()).count()
+    }
+
+    /// Verdicts recorded since `since`, aggregated per day, oldest first
+    #[must_use]
+    pub fn quality_metrics(&self, since: DateTime<Utc>) -> Vec<QualityBucket> {
+        let mut days: BTreeMap<NaiveDate, (QualityBucket, f32)> = BTreeMap::new();
+        for item in &self.items {
+            let Some(verdict) = item.verdict.as_ref().filter(|v| v.reviewed_at >= since) else {
+                continue;
+            };
+            let date = verdict.reviewed_at.date_naive();
+            let (bucket, total) = days.entry(date).or_insert_with(|| {
+                (
+                    QualityBucket {
+                        date,
+                        reviewed: 0,
+                        acceptable: 0,
+                        mean_score: 0.0,
+                        by_reason: BTreeMap::new(),
+                        labels: BTreeMap::new(),
+                    },
+                    0.0,
+                )
+            });
+            bucket.reviewed += 1;
+            bucket.acceptable += usize::from(verdict.acceptable);
+            *bucket.by_reason.entry(item.reason).or_default() += 1;
+            for label in &verdict.labels {
+                *bucket.labels.entry(label.clone()).or_default() += 1;
+            }
+            *total += verdict.score;
+        }
+        days.into_values()
+            .map(|(mut bucket, total)| {
+                #[allow(clippy::cast_precision_loss)]
+                let reviewed = bucket.reviewed as f32;
+                bucket.mean_score = total / reviewed;
+                bucket
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchange(prompt: &str, score: Option<f32>) -> Exchange {
+        Exchange {
+            conversation_id: "c1".to_string(),
+            response_id: None,
+            system: None,
+            prompt: prompt.to_string(),
+            completion: "Sure.".to_string(),
+            feedback_score: score,
+            judge_rating: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn sampler(rules: Vec<SamplingRule>) -> ReviewSampler {
+        ReviewSampler::new(ReviewSamplingConfig {
+            rules,
+            redact: true,
+        })
+    }
+
+    #[test]
+    fn test_rules_pick_low_feedback_and_escalated_conversations() {
+        let mut rules = ReviewSamplingConfig::default().rules;
+        rules.pop();
+        let sampler = sampler(rules);
+        let mut context = Context::new("c1");
+        let fine = [exchange("Hi", Some(0.9))];
+        let poor = [exchange("Hi", Some(0.9)), exchange("Why?", Some(0.1))];
+
+        assert_eq!(sampler.sample(&context, &fine), None);
+        assert_eq!(
+            sampler.sample(&context, &poor),
+            Some(SampleReason::LowFeedback)
+        );
+        context.metadata.tags.push("escalated".to_string());
+        assert_eq!(
+            sampler.sample(&context, &poor),
+            Some(SampleReason::Escalated)
+        );
+    }
+
+    #[test]
+    fn test_random_sampling_is_stable_and_near_rate() {
+        let sampler = sampler(vec![SamplingRule::All { rate: 0.25 }]);
+        let picked = (0..2_000)
+            .filter(|i| {
+                let context = Context::new(format!("conversation-{i}"));
+                sampler.sample(&context, &[]).is_some()
+            })
+            .count();
+        assert!((400..600).contains(&picked), "sampled {picked}");
+
+        let context = Context::new("conversation-7");
+        assert_eq!(sampler.sample(&context, &[]), sampler.sample(&context, &[]));
+    }
+
+    #[test]
+    fn test_queue_redacts_and_aggregates_verdicts() {
+        let sampler = sampler(vec![SamplingRule::All { rate: 1.0 }]);
+        let queue = ReviewQueue::new();
+        let context = Context::new("c1");
+        let item = sampler
+            .review_item(&context, &[exchange("Mail bob@example.com", Some(0.2))])
+            .unwrap();
+        assert_eq!(item.transcript[0].prompt, "Mail [redacted]");
+        assert_eq!(item.lowest_score, Some(0.2));
+        let id = item.id;
+        queue.enqueue(item);
+        queue.enqueue(sampler.review_item(&Context::new("c2"), &[]).unwrap());
+        assert_eq!(queue.pending_count(), 2);
+
+        queue
+            .record_verdict(id, ReviewVerdict::new("ana", false, 0.3).with_label("tone"))
+            .unwrap();
+        assert!(queue
+            .record_verdict(id, ReviewVerdict::new("ana", true, 1.0))
+            .is_err());
+        assert!(queue
+            .record_verdict(Uuid::new_v4(), ReviewVerdict::new("ana", true, 1.0))
+            .is_err());
+        let pending = queue.pending(10);
+        assert_eq!(pending.len(), 1);
+        queue
+            .record_verdict(pending[0].id, ReviewVerdict::new("ana", true, 0.9))
+            .unwrap();
+
+        let metrics = queue.quality_metrics(Utc::now() - chrono::Duration::days(1));
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].reviewed, 2);
+        assert!((metrics[0].acceptance_rate() - 0.5).abs() < f32::EPSILON);
+        assert!((metrics[0].mean_score - 0.6).abs() < 1e-6);
+        assert_eq!(metrics[0].labels.get("tone"), Some(&1));
+        assert_eq!(metrics[0].by_reason.get(&SampleReason::Random), Some(&2));
+    }
+}
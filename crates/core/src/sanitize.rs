@@ -0,0 +1,362 @@
+//! Input sanitization helpers
+//!
+//! These are the routines behind the pipeline's sanitize stage, exposed so
+//! adapters and the fuzz harness can exercise them directly. Every function
+//! here is total: any input, however adversarial, produces an output rather
+//! than a panic.
+
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+/// Metadata keys removed during sanitization
+///
+/// Matched case-insensitively as substrings, ignoring `_`, `-`, `.`, and
+/// spaces, so `accessToken`, `X-Api-Key`, and `clientsecret` all match.
+pub const SENSITIVE_KEYS: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "apikey",
+    "auth",
+    "credential",
+];
+
+/// Keys containing a [`SENSITIVE_KEYS`] entry that are known not to carry
+/// credentials, matched the same way but against the whole key
+pub const NON_SENSITIVE_KEYS: &[&str] = &["author", "authority", "maxtokens", "tokenizer"];
+
+/// Clean user-supplied message content
+///
+/// Removes control characters (other than whitespace) and bidirectional
+/// override/isolate characters, trims each line, and drops blank lines.
+#[must_use]
+pub fn sanitize_content(content: &str) -> String {
+    let sanitized = content
+        .chars()
+        .filter(|c| (!c.is_control() || c.is_whitespace()) && !is_bidi_control(*c))
+        .collect::<String>();
+
+    sanitized
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Remove sensitive keys from metadata, including inside nested objects
+///
+/// Nested values are walked iteratively so that deeply nested JSON cannot
+/// exhaust the stack.
+pub fn sanitize_metadata<S: BuildHasher>(metadata: &mut HashMap<String, serde_json::Value, S>) {
+    sanitize_metadata_except(metadata, &[]);
+}
+
+/// Remove sensitive keys from metadata, leaving the `internal` entries untouched
+///
+/// Used by the pipeline so values it set itself, such as an output schema
+/// whose properties may be named `token_count`, survive sanitization.
+pub fn sanitize_metadata_except<S: BuildHasher>(
+    metadata: &mut HashMap<String, serde_json::Value, S>,
+    internal: &[&str],
+) {
+    metadata.retain(|key, _| internal.contains(&key.as_str()) || !is_sensitive_key(key));
+
+    let mut stack: Vec<&mut serde_json::Value> = metadata
+        .iter_mut()
+        .filter(|(key, _)| !internal.contains(&key.as_str()))
+        .map(|(_, value)| value)
+        .collect();
+    while let Some(value) = stack.pop() {
+        match value {
+            serde_json::Value::Object(map) => {
+                map.retain(|key, _| !is_sensitive_key(key));
+                stack.extend(map.values_mut());
+            }
+            serde_json::Value::Array(items) => stack.extend(items.iter_mut()),
+            _ => {}
+        }
+    }
+}
+
+/// Check whether a metadata key looks like it carries a credential
+#[must_use]
+pub fn is_sensitive_key(key: &str) -> bool {
+    let key: String = key
+        .chars()
+        .filter(|c| !matches!(c, '_' | '-' | '.' | ' '))
+        .flat_map(char::to_lowercase)
+        .collect();
+    !NON_SENSITIVE_KEYS.contains(&key.as_str())
+        && SENSITIVE_KEYS
+            .iter()
+            .any(|sensitive| key.contains(sensitive))
+}
+
+/// Placeholder substituted for redacted personal data
+pub const REDACTED: &str = "[redacted]";
+
+/// Mask personal data in free text
+///
+/// Email addresses and digit runs long enough to be phone, account, or card
+/// numbers (seven or more digits, allowing spaces, dashes, dots, and
+/// parentheses between them) are replaced with [`REDACTED`].
+#[must_use]
+pub fn redact_pii(text: &str) -> String {
+    let emails_masked: String = text
+        .split_inclusive(char::is_whitespace)
+        .map(|piece| {
+            let word = piece.trim_matches(|c: char| {
+                c.is_whitespace()
+                    || matches!(c, '<' | '>' | '(' | ')' | ',' | ';' | ':' | '"' | '\'')
+            });
+            let word = word.trim_end_matches('.');
+            if is_email(word) {
+                piece.replacen(word, REDACTED, 1)
+            } else {
+                piece.to_string()
+            }
+        })
+        .collect();
+
+    let chars: Vec<char> = emails_masked.chars().collect();
+    let mut redacted = String::with_capacity(emails_masked.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if !chars[i].is_ascii_digit() {
+            redacted.push(chars[i]);
+            i += 1;
+            continue;
+        }
+
+        let mut digits = 0;
+        let mut end = i;
+        let mut j = i;
+        while j < chars.len()
+            && (chars[j].is_ascii_digit() || matches!(chars[j], ' ' | '-' | '.' | '(' | ')'))
+        {
+            if chars[j].is_ascii_digit() {
+                digits += 1;
+                end = j + 1;
+            }
+            j += 1;
+        }
+
+        if digits >= 7 {
+            redacted.push_str(REDACTED);
+        } else {
+            redacted.extend(&chars[i..end]);
+        }
+        i = end;
+    }
+    redacted
+}
+
+fn is_email(word: &str) -> bool {
+    let Some((local, domain)) = word.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+}
+
+/// Nesting depth of a JSON value, computed without recursion
+///
+/// Scalars have depth 0; each enclosing array or object adds one.
+#[must_use]
+pub fn json_depth(value: &serde_json::Value) -> usize {
+    let mut max_depth = 0;
+    let mut stack = vec![(value, 0usize)];
+    while let Some((value, depth)) = stack.pop() {
+        max_depth = max_depth.max(depth);
+        match value {
+            serde_json::Value::Object(map) => {
+                stack.extend(map.values().map(|v| (v, depth + 1)));
+                max_depth = max_depth.max(depth + 1);
+            }
+            serde_json::Value::Array(items) => {
+                stack.extend(items.iter().map(|v| (v, depth + 1)));
+                max_depth = max_depth.max(depth + 1);
+            }
+            _ => {}
+        }
+    }
+    max_depth
+}
+
+const fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sanitize_content() {
+        assert_eq!(sanitize_content("  hi\x07 \n\n there  "), "hi\nthere");
+        assert_eq!(sanitize_content("abc\u{202E}def\u{2066}"), "abcdef");
+        assert_eq!(sanitize_content("\u{0}\u{1}"), "");
+    }
+
+    #[test]
+    fn test_sanitize_metadata_nested() {
+        let mut metadata = HashMap::new();
+        metadata.insert("API_KEY".to_string(), json!("x"));
+        metadata.insert(
+            "profile".to_string(),
+            json!({ "name": "a", "auth": { "token": "y" }, "list": [{ "password": "z" }] }),
+        );
+
+        sanitize_metadata(&mut metadata);
+
+        assert!(!metadata.contains_key("API_KEY"));
+        assert_eq!(metadata["profile"], json!({ "name": "a", "list": [{}] }));
+    }
+
+    #[test]
+    fn test_is_sensitive_key() {
+        for key in [
+            "password",
+            "api_key",
+            "X-Api-Key",
+            "apiKey",
+            "access_token",
+            "refreshToken",
+            "client.secret",
+            "Authorization",
+            "accesstoken",
+            "clientsecret",
+            "userpassword",
+            "authtoken",
+        ] {
+            assert!(is_sensitive_key(key), "{key} should be sensitive");
+        }
+        for key in [
+            "author",
+            "authority",
+            "max_tokens",
+            "maxTokens",
+            "tokenizer",
+            "keyword",
+        ] {
+            assert!(!is_sensitive_key(key), "{key} should survive");
+        }
+    }
+
+    #[test]
+    fn test_sanitize_metadata_keeps_lookalike_keys() {
+        let mut metadata = HashMap::new();
+        metadata.insert("author".to_string(), json!("jane"));
+        metadata.insert("max_tokens".to_string(), json!(256));
+        metadata.insert(
+            "request".to_string(),
+            json!({ "authority": "ops", "session_token": "t" }),
+        );
+
+        sanitize_metadata(&mut metadata);
+
+        assert_eq!(metadata["author"], "jane");
+        assert_eq!(metadata["max_tokens"], 256);
+        assert_eq!(metadata["request"], json!({ "authority": "ops" }));
+    }
+
+    #[test]
+    fn test_sanitize_metadata_except_internal_keys() {
+        let mut metadata = HashMap::new();
+        metadata.insert(
+            "schema".to_string(),
+            json!({ "properties": { "token": {} } }),
+        );
+        metadata.insert("user".to_string(), json!({ "token": "t" }));
+
+        sanitize_metadata_except(&mut metadata, &["schema"]);
+
+        assert_eq!(metadata["schema"], json!({ "properties": { "token": {} } }));
+        assert_eq!(metadata["user"], json!({}));
+    }
+
+    #[test]
+    fn test_redact_pii() {
+        assert_eq!(
+            redact_pii("Mail <jane.doe@example.com>, or call +1 (555) 010-9999."),
+            "Mail <[redacted]>, or call +[redacted]."
+        );
+        assert_eq!(
+            redact_pii("Order 12345 shipped on 2024-01-02"),
+            "Order 12345 shipped on [redacted]"
+        );
+        assert_eq!(
+            redact_pii("user@localhost is fine"),
+            "user@localhost is fine"
+        );
+    }
+
+    #[test]
+    fn test_json_depth() {
+        assert_eq!(json_depth(&json!(1)), 0);
+        assert_eq!(json_depth(&json!([])), 1);
+        assert_eq!(json_depth(&json!({ "a": [[{ "b": 1 }]] })), 4);
+
+        let mut deep = json!(0);
+        for _ in 0..1_000 {
+            deep = json!([deep]);
+        }
+        assert_eq!(json_depth(&deep), 1_000);
+    }
+
+    #[cfg(feature = "property-testing")]
+    mod property_tests {
+        use super::*;
+        use proptest::prelude::*;
+
+        fn arb_json() -> impl Strategy<Value = serde_json::Value> {
+            let leaf = prop_oneof![
+                Just(serde_json::Value::Null),
+                any::<bool>().prop_map(serde_json::Value::Bool),
+                any::<i64>().prop_map(|n| json!(n)),
+                ".*".prop_map(serde_json::Value::String),
+            ];
+            leaf.prop_recursive(16, 256, 8, |inner| {
+                prop_oneof![
+                    prop::collection::vec(inner.clone(), 0..8).prop_map(serde_json::Value::Array),
+                    prop::collection::hash_map(".*", inner, 0..8).prop_map(|m| json!(m)),
+                ]
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn test_sanitize_content_is_idempotent(content in any::<String>()) {
+                let once = sanitize_content(&content);
+                prop_assert!(!once.chars().any(is_bidi_control));
+                prop_assert_eq!(sanitize_content(&once), once);
+            }
+
+            #[test]
+            fn test_sanitize_metadata_removes_all_sensitive_keys(
+                metadata in prop::collection::hash_map(".*", arb_json(), 0..32)
+            ) {
+                let mut metadata = metadata;
+                sanitize_metadata(&mut metadata);
+                let mut stack: Vec<&serde_json::Value> = metadata.values().collect();
+                prop_assert!(!metadata.keys().any(|k| is_sensitive_key(k)));
+                while let Some(value) = stack.pop() {
+                    match value {
+                        serde_json::Value::Object(map) => {
+                            prop_assert!(!map.keys().any(|k| is_sensitive_key(k)));
+                            stack.extend(map.values());
+                        }
+                        serde_json::Value::Array(items) => stack.extend(items),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! JSON Schema for the wire format
+//!
+//! Schemas are derived from the same types that serde uses, so they cannot
+//! drift from what the crate actually reads and writes. API layers can
+//! publish them, webhook consumers can validate payloads against them, and
+//! plugin authors can generate client types from them.
+//!
+//! Available with the `schema` feature.
+
+use std::collections::BTreeMap;
+
+use schemars::{schema::RootSchema, schema_for};
+
+use crate::{
+    config::BotConfig,
+    message::{Message, Response},
+    plugin::{PluginRequest, PluginResponse},
+};
+
+/// Schema for [`Message`]
+#[must_use]
+pub fn message() -> RootSchema {
+    schema_for!(Message)
+}
+
+/// Schema for [`Response`]
+#[must_use]
+pub fn response() -> RootSchema {
+    schema_for!(Response)
+}
+
+/// Schema for [`PluginRequest`]
+#[must_use]
+pub fn plugin_request() -> RootSchema {
+    schema_for!(PluginRequest)
+}
+
+/// Schema for [`PluginResponse`]
+#[must_use]
+pub fn plugin_response() -> RootSchema {
+    schema_for!(PluginResponse)
+}
+
+/// Schema for [`BotConfig`], as accepted from configuration files
+#[must_use]
+pub fn bot_config() -> RootSchema {
+    schema_for!(BotConfig)
+}
+
+/// All published schemas, keyed by type name
+#[must_use]
+pub fn all() -> BTreeMap<&'static str, RootSchema> {
+    BTreeMap::from([
+        ("Message", message()),
+        ("Response", response()),
+        ("PluginRequest", plugin_request()),
+        ("PluginResponse", plugin_response()),
+        ("BotConfig", bot_config()),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(schema: &RootSchema) -> Vec<&str> {
+        schema
+            .schema
+            .object
+            .as_ref()
+            .map(|object| object.properties.keys().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_message_schema_matches_wire_format() {
+        let schema = message();
+        let props = properties(&schema);
+        assert!(props.contains(&"content"));
+        assert!(props.contains(&"conversation_id"));
+
+        let json = serde_json::to_value(&schema).unwrap();
+        assert_eq!(json["properties"]["content"]["type"], "string");
+        assert!(json["definitions"]["Attachment"]["properties"]
+            .get("data")
+            .is_none());
+    }
+
+    #[test]
+    fn test_config_durations_are_strings() {
+        let json = serde_json::to_value(bot_config()).unwrap();
+        assert_eq!(json["properties"]["timeout"]["type"], "string");
+    }
+
+    #[test]
+    fn test_all_schemas_are_titled() {
+        for (name, schema) in all() {
+            let title = schema
+                .schema
+                .metadata
+                .as_ref()
+                .and_then(|m| m.title.as_deref());
+            assert_eq!(title, Some(name));
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Credential storage for integrations
+//!
+//! Plugins read API keys and OAuth tokens through a [`SecretsProvider`]
+//! instead of taking them as plain configuration, so deployments can back
+//! them with a vault or cloud secrets manager. Names are slash-separated
+//! paths such as `google/alice/refresh_token`.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use dashmap::DashMap;
+
+use crate::error::Error;
+
+/// Reads and writes named secrets
+#[async_trait]
+pub trait SecretsProvider: Send + Sync {
+    /// Fetch a secret
+    async fn get(&self, name: &str) -> Result<Option<String>>;
+
+    /// Store a secret, e.g. a rotated OAuth refresh token
+    async fn put(&self, name: &str, value: &str) -> Result<()>;
+}
+
+/// In-memory secrets, for tests and single-process deployments
+#[derive(Debug, Default)]
+pub struct MemorySecretsProvider {
+    secrets: DashMap<String, String>,
+}
+
+impl MemorySecretsProvider {
+    /// Create an empty store
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SecretsProvider for MemorySecretsProvider {
+    async fn get(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.secrets.get(name).map(|value| value.clone()))
+    }
+
+    async fn put(&self, name: &str, value: &str) -> Result<()> {
+        self.secrets.insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+/// Read-only secrets from environment variables
+///
+/// `google/client_secret` is read from `GOOGLE_CLIENT_SECRET`: the name is
+/// upper-cased and every character other than a letter or digit becomes
+/// `_`. An optional prefix is prepended.
+#[derive(Debug, Clone, Default)]
+pub struct EnvSecretsProvider {
+    prefix: String,
+}
+
+impl EnvSecretsProvider {
+    /// Read variables prefixed with `prefix`, e.g. `BOT_`
+    #[must_use]
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+
+    /// The environment variable a secret is read from
+    #[must_use]
+    pub fn variable(&self, name: &str) -> String {
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        format!("{}{name}", self.prefix)
+    }
+}
+
+#[async_trait]
+impl SecretsProvider for EnvSecretsProvider {
+    async fn get(&self, name: &str) -> Result<Option<String>> {
+        Ok(std::env::var(self.variable(name)).ok())
+    }
+
+    async fn put(&self, name: &str, _value: &str) -> Result<()> {
+        Err(Error::Configuration(format!(
+            "Cannot store {name}: environment secrets are read-only"
+        ))
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_providers() {
+        let memory = MemorySecretsProvider::new();
+        assert_eq!(memory.get("a/b").await.unwrap(), None);
+        memory.put("a/b", "s3cret").await.unwrap();
+        assert_eq!(memory.get("a/b").await.unwrap().as_deref(), Some("s3cret"));
+
+        let env = EnvSecretsProvider::new("BOT_");
+        assert_eq!(
+            env.variable("google/client-secret"),
+            "BOT_GOOGLE_CLIENT_SECRET"
+        );
+        assert!(env.put("x", "y").await.is_err());
+    }
+}
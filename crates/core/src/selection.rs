@@ -0,0 +1,396 @@
+//! Model selection and per-conversation pinning
+//!
+//! The model serving a message is resolved with the precedence
+//! request override > conversation pin > tenant default > global default.
+//! A request overrides the model with the [`MODEL_OVERRIDE_METADATA_KEY`]
+//! message metadata; a conversation pins one with the `/model` command,
+//! which stores a [`ModelPin`] in the context variables.
+//!
+//! Under budget pressure the resolved model may then be downgraded per
+//! [`BudgetConfig::downgrade`]: the bot stamps the share of the budget spent
+//! on each message under [`BUDGET_PRESSURE_METADATA_KEY`], and
+//! [`ModelSelector::downgrade`] picks the cheaper model, recorded in the
+//! response metadata under [`BUDGET_DOWNGRADE_METADATA_KEY`].
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+    config::{BotConfig, BudgetConfig, ModelSelectionConfig},
+    context::Context,
+    degraded::TENANT_METADATA_KEY,
+    error::Error,
+    message::Message,
+};
+
+/// Message metadata key carrying a per-request model override
+pub const MODEL_OVERRIDE_METADATA_KEY: &str = "model";
+
+/// Context variable holding the conversation's [`ModelPin`]
+pub const MODEL_PIN_VARIABLE: &str = "model_pin";
+
+/// Message metadata key carrying the share of the budget spent, set by the bot
+pub const BUDGET_PRESSURE_METADATA_KEY: &str = "budget_pressure";
+
+/// Response metadata key describing a [`BudgetDowngrade`]
+pub const BUDGET_DOWNGRADE_METADATA_KEY: &str = "budget_downgrade";
+
+/// Model and generation settings pinned to a conversation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelPin {
+    /// Pinned model
+    pub model: String,
+    /// Temperature override
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Maximum tokens override
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<usize>,
+    /// User who set the pin
+    pub pinned_by: String,
+}
+
+impl ModelPin {
+    /// Read the pin stored in a context, if any
+    #[must_use]
+    pub fn load(context: &Context) -> Option<Self> {
+        context
+            .get_variable(MODEL_PIN_VARIABLE)
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+    }
+
+    /// Store the pin in a context, replacing any previous one
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pin cannot be serialized.
+    pub fn store(&self, context: &mut Context) -> Result<()> {
+        context.set_variable(MODEL_PIN_VARIABLE, serde_json::to_value(self)?);
+        Ok(())
+    }
+
+    /// Remove the pin from a context; returns whether one was set
+    pub fn clear(context: &mut Context) -> bool {
+        context.variables.remove(MODEL_PIN_VARIABLE).is_some()
+    }
+}
+
+/// Where a selected model came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelSource {
+    /// Per-request override in the message metadata
+    Request,
+    /// Model pinned to the conversation
+    Conversation,
+    /// Tenant default
+    Tenant,
+    /// [`BotConfig::model`]
+    Global,
+}
+
+/// The model chosen for a message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelSelection {
+    /// Model to call
+    pub model: String,
+    /// Where the choice came from
+    pub source: ModelSource,
+    /// Temperature, when pinned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Maximum tokens, when pinned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<usize>,
+}
+
+impl ModelSelection {
+    fn new(model: impl Into<String>, source: ModelSource) -> Self {
+        Self {
+            model: model.into(),
+            source,
+            temperature: None,
+            max_tokens: None,
+        }
+    }
+}
+
+/// A model swapped for a cheaper one because the budget is running out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BudgetDowngrade {
+    /// Model that would have been used
+    pub from: String,
+    /// Model used instead
+    pub to: String,
+    /// Share of the budget spent
+    pub budget_used: f64,
+    /// Human-readable explanation
+    pub reason: String,
+}
+
+/// Resolves models and handles the `/model` command
+#[derive(Debug, Clone)]
+pub struct ModelSelector {
+    default_model: String,
+    config: ModelSelectionConfig,
+    budget: BudgetConfig,
+}
+
+impl ModelSelector {
+    /// Create a selector from the bot configuration
+    #[must_use]
+    pub fn new(config: &BotConfig) -> Self {
+        Self {
+            default_model: config.model.clone(),
+            config: config.model_selection.clone(),
+            budget: config.budget.clone(),
+        }
+    }
+
+    /// Choose the model for a message
+    ///
+    /// Overrides and pins naming a model outside
+    /// [`ModelSelectionConfig::allowed_models`] are ignored.
+    #[must_use]
+    pub fn resolve(&self, message: &Message, context: &Context) -> ModelSelection {
+        if let Some(model) = message
+            .metadata
+            .get(MODEL_OVERRIDE_METADATA_KEY)
+            .and_then(serde_json::Value::as_str)
+        {
+            if self.config.allows(model) {
+                return ModelSelection::new(model, ModelSource::Request);
+            }
+            warn!("Ignoring request override to disallowed model {}", model);
+        }
+
+        if let Some(pin) = ModelPin::load(context) {
+            if self.config.allows(&pin.model) {
+                return ModelSelection {
+                    model: pin.model,
+                    source: ModelSource::Conversation,
+                    temperature: pin.temperature,
+                    max_tokens: pin.max_tokens,
+                };
+            }
+            warn!("Ignoring pin to disallowed model {}", pin.model);
+        }
+
+        let tenant_model = message
+            .metadata
+            .get(TENANT_METADATA_KEY)
+            .and_then(serde_json::Value::as_str)
+            .and_then(|tenant| self.config.tenant_models.get(tenant));
+        tenant_model.map_or_else(
+            || ModelSelection::new(self.default_model.clone(), ModelSource::Global),
+            |model| ModelSelection::new(model.clone(), ModelSource::Tenant),
+        )
+    }
+
+    /// The cheaper model to use instead of `selection` under the message's budget pressure
+    ///
+    /// Returns `None` when no downgrade policy is configured, the message
+    /// carries no [`BUDGET_PRESSURE_METADATA_KEY`], or no threshold is crossed.
+    #[must_use]
+    pub fn downgrade(
+        &self,
+        selection: &ModelSelection,
+        message: &Message,
+    ) -> Option<BudgetDowngrade> {
+        let policy = self.budget.downgrade.as_ref()?;
+        let pressure = message
+            .metadata
+            .get(BUDGET_PRESSURE_METADATA_KEY)
+            .and_then(serde_json::Value::as_f64)?;
+        let to = policy.downgrade(&selection.model, pressure)?;
+        debug!(
+            "Downgrading {} to {} at {:.0}% of budget",
+            selection.model,
+            to,
+            pressure * 100.0
+        );
+        Some(BudgetDowngrade {
+            from: selection.model.clone(),
+            to: to.to_string(),
+            budget_used: pressure,
+            reason: format!("{:.0}% of the budget is spent", pressure * 100.0),
+        })
+    }
+
+    /// Run a `/model` command against the conversation and describe the result
+    ///
+    /// `/model` shows the current selection, `/model reset` removes the pin,
+    /// and `/model <id> [temperature=<t>] [max_tokens=<n>]` pins a model.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Authorization`] if the sender may not switch models,
+    /// or [`Error::InvalidInput`] for an unknown model or argument.
+    pub fn handle_command(&self, message: &Message, context: &mut Context) -> Result<String> {
+        let mut args = message.content.split_whitespace().skip(1);
+        let Some(model) = args.next() else {
+            let selection = self.resolve(message, context);
+            return Ok(format!(
+                "Current model: {} ({:?})",
+                selection.model, selection.source
+            ));
+        };
+
+        if !self.config.can_switch(&message.user_id) {
+            return Err(Error::Authorization(format!(
+                "User {} may not switch models",
+                message.user_id
+            ))
+            .into());
+        }
+
+        if model == "reset" {
+            return Ok(if ModelPin::clear(context) {
+                "Model pin removed".to_string()
+            } else {
+                "No model is pinned".to_string()
+            });
+        }
+
+        if !self.config.allows(model) {
+            return Err(Error::InvalidInput(format!("Model {model} is not available")).into());
+        }
+
+        let settings: HashMap<&str, &str> = args.filter_map(|arg| arg.split_once('=')).collect();
+        let mut pin = ModelPin {
+            model: model.to_string(),
+            temperature: None,
+            max_tokens: None,
+            pinned_by: message.user_id.clone(),
+        };
+        for (key, value) in settings {
+            let invalid = || Error::InvalidInput(format!("Invalid value for {key}: {value}"));
+            match key {
+                "temperature" => {
+                    let temperature: f32 = value.parse().map_err(|_| invalid())?;
+                    if !(0.0..=1.0).contains(&temperature) {
+                        return Err(invalid().into());
+                    }
+                    pin.temperature = Some(temperature);
+                }
+                "max_tokens" => {
+                    pin.max_tokens = Some(value.parse().map_err(|_| invalid())?);
+                }
+                _ => {
+                    return Err(Error::InvalidInput(format!("Unknown setting: {key}")).into());
+                }
+            }
+        }
+
+        pin.store(context)?;
+        debug!("Pinned conversation {} to {}", context.id, pin.model);
+        Ok(format!("Model pinned to {}", pin.model))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selector() -> ModelSelector {
+        let mut config = BotConfig::default();
+        config
+            .model_selection
+            .tenant_models
+            .insert("acme".to_string(), "anthropic.claude-haiku".to_string());
+        config
+            .model_selection
+            .authorized_users
+            .push("admin".to_string());
+        ModelSelector::new(&config)
+    }
+
+    fn command(user: &str, content: &str) -> Message {
+        Message::with_type(content, crate::message::MessageType::Command).with_user_id(user)
+    }
+
+    #[test]
+    fn test_precedence() {
+        let selector = selector();
+        let mut context = Context::new("conv");
+        let message = Message::text("hi").with_metadata(TENANT_METADATA_KEY, "acme".into());
+
+        let selection = selector.resolve(&Message::text("hi"), &context);
+        assert_eq!(selection.source, ModelSource::Global);
+        assert_eq!(selection.model, "anthropic.claude-opus-4-1");
+
+        let selection = selector.resolve(&message, &context);
+        assert_eq!(selection.source, ModelSource::Tenant);
+        assert_eq!(selection.model, "anthropic.claude-haiku");
+
+        selector
+            .handle_command(
+                &command("admin", "/model anthropic.claude-sonnet-4 temperature=0.3"),
+                &mut context,
+            )
+            .unwrap();
+        let selection = selector.resolve(&message, &context);
+        assert_eq!(selection.source, ModelSource::Conversation);
+        assert_eq!(selection.model, "anthropic.claude-sonnet-4");
+        assert_eq!(selection.temperature, Some(0.3));
+
+        let overridden = message.clone().with_metadata(
+            MODEL_OVERRIDE_METADATA_KEY,
+            "meta.llama3-8b-instruct".into(),
+        );
+        let selection = selector.resolve(&overridden, &context);
+        assert_eq!(selection.source, ModelSource::Request);
+        assert_eq!(selection.model, "meta.llama3-8b-instruct");
+
+        let disallowed = message.with_metadata(MODEL_OVERRIDE_METADATA_KEY, "made-up-model".into());
+        assert_eq!(
+            selector.resolve(&disallowed, &context).source,
+            ModelSource::Conversation
+        );
+    }
+
+    #[test]
+    fn test_model_command_requires_authorization() {
+        let selector = selector();
+        let mut context = Context::new("conv");
+
+        let err = selector
+            .handle_command(
+                &command("guest", "/model anthropic.claude-haiku"),
+                &mut context,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Authorization(_))
+        ));
+        assert!(ModelPin::load(&context).is_none());
+
+        let reply = selector
+            .handle_command(&command("guest", "/model"), &mut context)
+            .unwrap();
+        assert!(reply.contains("anthropic.claude-opus-4-1"));
+
+        assert!(selector
+            .handle_command(&command("admin", "/model unknown-model"), &mut context)
+            .is_err());
+        selector
+            .handle_command(
+                &command("admin", "/model anthropic.claude-haiku"),
+                &mut context,
+            )
+            .unwrap();
+        assert_eq!(
+            ModelPin::load(&context).unwrap().pinned_by,
+            "admin".to_string()
+        );
+        let reply = selector
+            .handle_command(&command("admin", "/model reset"), &mut context)
+            .unwrap();
+        assert_eq!(reply, "Model pin removed");
+        assert!(ModelPin::load(&context).is_none());
+    }
+}
@@ -0,0 +1,529 @@
+//! Time-boxed conversation sessions
+//!
+//! With [`SessionConfig::enabled`] set, a conversation is divided into
+//! sessions. A session ends after [`SessionConfig::idle_timeout`] without
+//! messages or once it has run for [`SessionConfig::max_duration`]. The next
+//! message then closes it: the [`SessionManager`] writes a wrap-up summary
+//! with action items, saves it as a [`SessionRecord`], and starts the new
+//! session from a fresh context seeded with that summary.
+//!
+//! Summaries are written by a completion function set with
+//! [`BotBuilder::session_summarizer`](crate::bot::BotBuilder::session_summarizer);
+//! without one, the record lists the user's requests instead.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::{
+    completion::CompletionFn,
+    config::SessionConfig,
+    context::{Context, ContextMessage, MessageRole},
+};
+
+/// Context variable holding the current [`Session`]
+pub const SESSION_VARIABLE: &str = "session";
+
+/// The session a context is in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    /// Session ID
+    pub id: Uuid,
+    /// When the session started
+    pub started_at: DateTime<Utc>,
+    /// Sessions the conversation had before this one
+    pub previous: u32,
+}
+
+impl Session {
+    /// The session stored in a context, if any
+    #[must_use]
+    pub fn load(context: &Context) -> Option<Self> {
+        context
+            .get_variable(SESSION_VARIABLE)
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+    }
+
+    /// The session a context is in, treating contexts without one as a
+    /// first session that started with the context
+    #[must_use]
+    pub fn current(context: &Context) -> Self {
+        Self::load(context).unwrap_or_else(|| Self {
+            id: Uuid::new_v4(),
+            started_at: context.metadata.created_at,
+            previous: 0,
+        })
+    }
+
+    fn store(&self, context: &mut Context) -> Result<()> {
+        context.set_variable(SESSION_VARIABLE, serde_json::to_value(self)?);
+        Ok(())
+    }
+}
+
+/// Why a session was closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEndReason {
+    /// No messages for the idle timeout
+    Inactivity,
+    /// The session ran for its maximum duration
+    Duration,
+}
+
+/// A closed session and its wrap-up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    /// Session ID
+    pub id: Uuid,
+    /// Conversation the session belonged to
+    pub conversation_id: String,
+    /// User the session was with
+    pub user_id: Option<String>,
+    /// When the session started
+    pub started_at: DateTime<Utc>,
+    /// Last activity in the session
+    pub ended_at: DateTime<Utc>,
+    /// Why the session was closed
+    pub reason: SessionEndReason,
+    /// Messages exchanged in the session
+    pub message_count: usize,
+    /// Wrap-up summary
+    pub summary: String,
+    /// Follow-ups agreed in the session
+    #[serde(default)]
+    pub action_items: Vec<String>,
+}
+
+impl SessionRecord {
+    /// The note a new session starts from
+    #[must_use]
+    pub fn seed_note(&self) -> String {
+        let mut note = format!("Summary of the previous session: {}", self.summary);
+        if !self.action_items.is_empty() {
+            note.push_str("\nOpen action items:");
+            for item in &self.action_items {
+                note.push_str("\n- ");
+                note.push_str(item);
+            }
+        }
+        note
+    }
+}
+
+/// Storage for closed sessions
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Save a closed session
+    async fn save(&self, record: SessionRecord) -> Result<()>;
+
+    /// Closed sessions of a conversation, oldest first
+    async fn list(&self, conversation_id: &str) -> Result<Vec<SessionRecord>>;
+}
+
+/// In-process session store
+#[derive(Debug, Default)]
+pub struct MemorySessionStore {
+    records: DashMap<String, Vec<SessionRecord>>,
+}
+
+impl MemorySessionStore {
+    /// Create an empty store
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemorySessionStore {
+    async fn save(&self, record: SessionRecord) -> Result<()> {
+        self.records
+            .entry(record.conversation_id.clone())
+            .or_default()
+            .push(record);
+        Ok(())
+    }
+
+    async fn list(&self, conversation_id: &str) -> Result<Vec<SessionRecord>> {
+        Ok(self
+            .records
+            .get(conversation_id)
+            .map(|records| records.clone())
+            .unwrap_or_default())
+    }
+}
+
+/// Closes sessions and starts new ones
+pub struct SessionManager {
+    config: SessionConfig,
+    store: Arc<dyn SessionStore>,
+    summarizer: Option<CompletionFn>,
+    /// Held while a conversation's session is wrapped up, so it closes once
+    closing: DashMap<String, Arc<tokio::sync::Mutex<()>>>,
+}
+
+impl std::fmt::Debug for SessionManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionManager")
+            .field("config", &self.config)
+            .field("summarizer", &self.summarizer.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl SessionManager {
+    /// Create a manager keeping records in memory
+    #[must_use]
+    pub fn new(config: SessionConfig) -> Self {
+        Self {
+            config,
+            store: Arc::new(MemorySessionStore::new()),
+            summarizer: None,
+            closing: DashMap::new(),
+        }
+    }
+
+    /// Keep records in `store`
+    #[must_use]
+    pub fn with_store(mut self, store: Arc<dyn SessionStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Write wrap-ups with the given completion function
+    #[must_use]
+    pub fn with_summarizer(mut self, summarizer: CompletionFn) -> Self {
+        self.summarizer = Some(summarizer);
+        self
+    }
+
+    /// Whether sessions are closed at all
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Where closed sessions are kept
+    #[must_use]
+    pub fn store(&self) -> &Arc<dyn SessionStore> {
+        &self.store
+    }
+
+    /// Whether the session in `context` is over at `now`, and why
+    ///
+    /// Sessions without messages never end.
+    #[must_use]
+    pub fn due(&self, context: &Context, now: DateTime<Utc>) -> Option<SessionEndReason> {
+        if !self.config.enabled || context.metadata.message_count == 0 {
+            return None;
+        }
+        let elapsed = |since: DateTime<Utc>| (now - since).to_std().unwrap_or(Duration::ZERO);
+
+        if elapsed(context.metadata.last_activity) >= self.config.idle_timeout {
+            return Some(SessionEndReason::Inactivity);
+        }
+        let started_at = Session::current(context).started_at;
+        self.config
+            .max_duration
+            .filter(|max| elapsed(started_at) >= *max)
+            .map(|_| SessionEndReason::Duration)
+    }
+
+    /// Close the session in a shared context if it is over
+    ///
+    /// One caller wraps up a conversation at a time; the others wait and find
+    /// the session already closed. Messages added to the context while the
+    /// wrap-up is written are kept in the new session. Returns the closed
+    /// session's record, if one was closed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the new session cannot be stored in the context.
+    pub async fn wrap_up(&self, context: &Arc<RwLock<Context>>) -> Result<Option<SessionRecord>> {
+        let id = {
+            let context = context.read();
+            if self.due(&context, Utc::now()).is_none() {
+                return Ok(None);
+            }
+            context.id.clone()
+        };
+
+        let lock = self.closing.entry(id.clone()).or_default().clone();
+        let _closing = lock.lock().await;
+        let closed = self.close_shared(context).await;
+        self.closing.remove(&id);
+        closed
+    }
+
+    async fn close_shared(&self, context: &Arc<RwLock<Context>>) -> Result<Option<SessionRecord>> {
+        let mut next = context.read().clone();
+        // Another caller may have closed it, or a new message revived it
+        let Some(reason) = self.due(&next, Utc::now()) else {
+            return Ok(None);
+        };
+        let seen = next.metadata.message_count;
+        let record = self.close(&mut next, reason).await?;
+
+        let mut current = context.write();
+        next.keep_messages_since(&current, seen);
+        *current = next;
+        drop(current);
+        Ok(Some(record))
+    }
+
+    /// Close the session in `context` and start a new one seeded with its wrap-up
+    ///
+    /// The history is replaced by a single system note carrying the summary
+    /// and open action items; variables are kept. A failed summary falls
+    /// back to listing the user's requests, and a record that cannot be
+    /// saved is logged rather than failing the message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the new session cannot be stored in the context.
+    pub async fn close(
+        &self,
+        context: &mut Context,
+        reason: SessionEndReason,
+    ) -> Result<SessionRecord> {
+        let session = Session::current(context);
+        let (summary, action_items) = match &self.summarizer {
+            Some(summarizer) => match summarizer(wrap_up_prompt(context)).await {
+                Ok(text) => parse_wrap_up(&text),
+                Err(e) => {
+                    warn!("Writing session wrap-up failed, listing requests instead: {e:#}");
+                    (fallback_summary(context), Vec::new())
+                }
+            },
+            None => (fallback_summary(context), Vec::new()),
+        };
+
+        let record = SessionRecord {
+            id: session.id,
+            conversation_id: context.id.clone(),
+            user_id: context.user.id.clone(),
+            started_at: session.started_at,
+            ended_at: context.metadata.last_activity,
+            reason,
+            message_count: context.metadata.message_count,
+            summary,
+            action_items,
+        };
+        debug!(
+            "Closing session {} of {} ({:?})",
+            record.id, record.conversation_id, reason
+        );
+        if let Err(e) = self.store.save(record.clone()).await {
+            warn!("Failed to save session {}: {:#}", record.id, e);
+        }
+
+        context.clear_history();
+        let note = ContextMessage::system(record.seed_note());
+        context.token_count = note.estimated_tokens();
+        context.history.push_back(note);
+        Session {
+            id: Uuid::new_v4(),
+            started_at: Utc::now(),
+            previous: session.previous + 1,
+        }
+        .store(context)?;
+        Ok(record)
+    }
+}
+
+/// Prompt asking for a wrap-up of the session in `context`
+fn wrap_up_prompt(context: &Context) -> String {
+    let mut prompt = String::from(
+        "Wrap up the following conversation. Reply with a line starting \"Summary:\" \
+         giving the outcome in a few sentences, then a line \"Action items:\" followed \
+         by one follow-up per line starting with \"- \", or \"- none\".\n\n",
+    );
+    for message in &context.history {
+        let role = match message.role {
+            MessageRole::System => "system",
+            MessageRole::User => "user",
+            MessageRole::Assistant => "assistant",
+        };
+        prompt.push_str(role);
+        prompt.push_str(": ");
+        prompt.push_str(&message.content);
+        prompt.push('\n');
+    }
+    prompt
+}
+
+/// Summary and action items from a wrap-up reply
+fn parse_wrap_up(text: &str) -> (String, Vec<String>) {
+    let mut summary = Vec::new();
+    let mut action_items = Vec::new();
+    let mut in_items = false;
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let lower = line.to_lowercase();
+        if lower.starts_with("action items") {
+            in_items = true;
+        } else if in_items {
+            let item = line.trim_start_matches(['-', '*', ' ']).trim();
+            if !item.is_empty() && !item.eq_ignore_ascii_case("none") {
+                action_items.push(item.to_string());
+            }
+        } else if lower.starts_with("summary:") {
+            summary.push(line["summary:".len()..].trim());
+        } else {
+            summary.push(line);
+        }
+    }
+    (summary.join(" "), action_items)
+}
+
+/// A summary listing the user's requests
+fn fallback_summary(context: &Context) -> String {
+    let requests: Vec<&str> = context
+        .history
+        .iter()
+        .filter(|message| message.role == MessageRole::User)
+        .map(|message| message.content.as_str())
+        .collect();
+    if requests.is_empty() {
+        "No requests were made.".to_string()
+    } else {
+        format!("The user asked: {}", requests.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{Message, Response};
+
+    fn conversation() -> Context {
+        let mut context = Context::new("conv");
+        context.add_message(&Message::text("Can you reset my password?"));
+        context.add_response(&Response::text("conv", "Done; check your email."));
+        context
+    }
+
+    fn manager(max_duration: Option<Duration>) -> SessionManager {
+        SessionManager::new(SessionConfig {
+            enabled: true,
+            idle_timeout: Duration::from_secs(600),
+            max_duration,
+        })
+    }
+
+    #[test]
+    fn test_sessions_end_on_inactivity_or_duration() {
+        let context = conversation();
+        let now = context.metadata.last_activity;
+        let manager = manager(Some(Duration::from_secs(3600)));
+
+        assert_eq!(manager.due(&context, now), None);
+        assert_eq!(
+            manager.due(&context, now + chrono::Duration::minutes(10)),
+            Some(SessionEndReason::Inactivity)
+        );
+
+        let mut busy = context.clone();
+        busy.metadata.created_at = now - chrono::Duration::hours(2);
+        assert_eq!(manager.due(&busy, now), Some(SessionEndReason::Duration));
+        assert_eq!(
+            manager.due(&Context::new("empty"), now + chrono::Duration::days(1)),
+            None
+        );
+        assert_eq!(
+            SessionManager::new(SessionConfig::default())
+                .due(&context, now + chrono::Duration::days(1)),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_close_records_wrap_up_and_seeds_next_session() {
+        let summarizer: CompletionFn = Arc::new(|_prompt| {
+            Box::pin(async {
+                Ok("Summary: Reset the user's password.\nAction items:\n- Confirm the email arrived"
+                    .to_string())
+            })
+        });
+        let manager = manager(None).with_summarizer(summarizer);
+        let mut context = conversation();
+
+        let record = manager
+            .close(&mut context, SessionEndReason::Inactivity)
+            .await
+            .unwrap();
+        assert_eq!(record.summary, "Reset the user's password.");
+        assert_eq!(record.action_items, ["Confirm the email arrived"]);
+        assert_eq!(record.message_count, 2);
+        assert_eq!(
+            manager.store().list("conv").await.unwrap(),
+            std::slice::from_ref(&record)
+        );
+
+        assert_eq!(context.history.len(), 1);
+        assert_eq!(context.history[0].role, MessageRole::System);
+        assert!(context.history[0]
+            .content
+            .contains("- Confirm the email arrived"));
+        let session = Session::load(&context).unwrap();
+        assert_eq!(session.previous, 1);
+        assert_ne!(session.id, record.id);
+        assert_eq!(
+            manager.due(&context, Utc::now() + chrono::Duration::days(1)),
+            None
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_wrap_ups_close_once_and_keep_new_messages() {
+        let summarizer: CompletionFn = Arc::new(|_prompt| {
+            Box::pin(async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok("Summary: Reset the user's password.\nAction items:\n- none".to_string())
+            })
+        });
+        let manager = manager(Some(Duration::from_secs(3600))).with_summarizer(summarizer);
+        let mut context = conversation();
+        context.metadata.created_at -= chrono::Duration::hours(2);
+        let context = Arc::new(RwLock::new(context));
+
+        let (first, second, ()) = tokio::join!(
+            manager.wrap_up(&context),
+            manager.wrap_up(&context),
+            async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                context.write().add_message(&Message::text("Thanks!"));
+            }
+        );
+        let closed = [first.unwrap(), second.unwrap()];
+        assert_eq!(closed.iter().flatten().count(), 1);
+        assert_eq!(manager.store().list("conv").await.unwrap().len(), 1);
+
+        let context = context.read().clone();
+        assert_eq!(context.history.len(), 2);
+        assert_eq!(context.history[0].role, MessageRole::System);
+        assert_eq!(context.history[1].content.as_str(), "Thanks!");
+        assert_eq!(context.metadata.message_count, 1);
+        assert_eq!(Session::load(&context).unwrap().previous, 1);
+    }
+
+    #[test]
+    fn test_fallback_summary_lists_requests() {
+        assert_eq!(
+            fallback_summary(&conversation()),
+            "The user asked: Can you reset my password?"
+        );
+        assert_eq!(
+            parse_wrap_up("Summary: Nothing.\nAction items:\n- none"),
+            ("Nothing.".to_string(), Vec::new())
+        );
+    }
+}
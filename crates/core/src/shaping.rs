@@ -0,0 +1,437 @@
+//! Outbound rate shaping for channel adapters
+//!
+//! Chat platforms meter bots differently: Slack allows about one message
+//! per second per channel, Telegram about thirty per second overall. A
+//! [`RateShape`] states an adapter's limits, per destination (room, channel,
+//! chat) and across all of them, and a [`RateShaper`] holds messages back
+//! until they fit, in the order they were queued. With a coalesce window,
+//! chunks queued or arriving shortly after the last delivery to a
+//! destination are merged into an edit of that message instead of being
+//! sent one by one.
+//!
+//! Channels take their shape from the `rate` entry of their
+//! [`ChannelSpec`](crate::botfile::ChannelSpec).
+
+use std::future::Future;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{error::Error, irc::FloodControl};
+
+/// A delivery limit of `messages` per `per`, sent back to back if unused
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rate {
+    /// Messages allowed in each period
+    pub messages: u32,
+    /// Length of the period
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub per: Duration,
+}
+
+impl Rate {
+    /// `messages` per second
+    #[must_use]
+    pub const fn per_second(messages: u32) -> Self {
+        Self {
+            messages,
+            per: Duration::from_secs(1),
+        }
+    }
+
+    fn flood_control(self) -> FloodControl {
+        let messages = self.messages.max(1);
+        FloodControl::new(messages, self.per / messages)
+    }
+}
+
+/// Outbound limits of one channel adapter
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateShape {
+    /// Limit for each destination, e.g. a Slack channel
+    pub per_destination: Option<Rate>,
+    /// Limit across all destinations, e.g. a Telegram bot
+    pub global: Option<Rate>,
+    /// Merge chunks delivered to a destination within this long of the last
+    /// delivery into an edit of it; zero sends every chunk separately
+    #[serde(with = "humantime_serde")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub coalesce_window: Duration,
+}
+
+/// What a [`RateShaper`] asks the adapter to do with queued chunks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delivery {
+    /// Send a new message
+    Send {
+        /// Message text
+        body: String,
+    },
+    /// Replace the text of a message sent earlier
+    Edit {
+        /// ID the adapter returned for the message
+        message_id: String,
+        /// The message's new, complete text
+        body: String,
+    },
+}
+
+struct Delivered {
+    message_id: String,
+    body: String,
+    at: Instant,
+}
+
+/// Chunks waiting for a delivery, numbered in the order they were queued
+#[derive(Default)]
+struct Pending {
+    text: String,
+    /// Number of the first chunk in `text`
+    first: u64,
+    /// Number the next chunk gets
+    next: u64,
+}
+
+impl Pending {
+    fn push(&mut self, chunk: &str) -> u64 {
+        self.text.push_str(chunk);
+        self.next += 1;
+        self.next - 1
+    }
+
+    fn take(&mut self) -> (Range<u64>, String) {
+        let chunks = self.first..self.next;
+        self.first = self.next;
+        (chunks, std::mem::take(&mut self.text))
+    }
+}
+
+/// Outcome of a delivery, kept for the callers whose chunks it carried
+struct Batch {
+    chunks: Range<u64>,
+    outcome: std::result::Result<String, String>,
+    /// Callers yet to read the outcome
+    waiting: u64,
+}
+
+struct LaneState {
+    flood: Option<FloodControl>,
+    last: Option<Delivered>,
+    batches: Vec<Batch>,
+}
+
+impl LaneState {
+    /// Outcome of the delivery that carried chunk `seq`
+    fn outcome(&mut self, seq: u64) -> Result<String> {
+        let Some(index) = self
+            .batches
+            .iter()
+            .position(|batch| batch.chunks.contains(&seq))
+        else {
+            return Err(dropped("its delivery was cancelled"));
+        };
+        let batch = &mut self.batches[index];
+        batch.waiting -= 1;
+        let outcome = batch.outcome.clone().map_err(|e| dropped(&e));
+        if batch.waiting == 0 {
+            self.batches.remove(index);
+        }
+        outcome
+    }
+}
+
+fn dropped(reason: &str) -> anyhow::Error {
+    Error::Network(format!("Queued chunk was dropped: {reason}")).into()
+}
+
+/// Queue for one destination
+struct Lane {
+    pending: Mutex<Pending>,
+    state: tokio::sync::Mutex<LaneState>,
+}
+
+/// Paces an adapter's outbound messages to its [`RateShape`]
+pub struct RateShaper {
+    shape: RateShape,
+    global: Option<Mutex<FloodControl>>,
+    lanes: DashMap<String, Arc<Lane>>,
+}
+
+impl std::fmt::Debug for RateShaper {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RateShaper")
+            .field("shape", &self.shape)
+            .field("destinations", &self.lanes.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl RateShaper {
+    /// Create a shaper with full buckets
+    #[must_use]
+    pub fn new(shape: RateShape) -> Self {
+        Self {
+            global: shape.global.map(|rate| Mutex::new(rate.flood_control())),
+            shape,
+            lanes: DashMap::new(),
+        }
+    }
+
+    /// The limits being applied
+    #[must_use]
+    pub const fn shape(&self) -> &RateShape {
+        &self.shape
+    }
+
+    fn lane(&self, destination: &str) -> Arc<Lane> {
+        self.lanes
+            .entry(destination.to_string())
+            .or_insert_with(|| {
+                Arc::new(Lane {
+                    pending: Mutex::new(Pending::default()),
+                    state: tokio::sync::Mutex::new(LaneState {
+                        flood: self.shape.per_destination.map(Rate::flood_control),
+                        last: None,
+                        batches: Vec::new(),
+                    }),
+                })
+            })
+            .clone()
+    }
+
+    async fn wait_for_slot(&self, state: &mut LaneState) {
+        let now = Instant::now();
+        let mut wait = state
+            .flood
+            .as_mut()
+            .map_or(Duration::ZERO, |flood| flood.reserve(now));
+        if let Some(global) = &self.global {
+            let global_wait = global.lock().reserve(now);
+            wait = wait.max(global_wait);
+        }
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Wait until a message may be sent to `destination`
+    ///
+    /// Callers are let through in the order they arrive.
+    pub async fn acquire(&self, destination: &str) {
+        let lane = self.lane(destination);
+        let mut state = lane.state.lock().await;
+        self.wait_for_slot(&mut state).await;
+    }
+
+    /// Queue `chunk` for `destination` and deliver it with `deliver`
+    ///
+    /// Chunks queued while waiting for a slot go out together, and within
+    /// the coalesce window they are appended to the last message with an
+    /// edit. Returns the ID of the message carrying the chunk.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from `deliver`; the chunks it carried are dropped,
+    /// and their callers get an error too.
+    pub async fn deliver<F, Fut>(
+        &self,
+        destination: &str,
+        chunk: &str,
+        deliver: F,
+    ) -> Result<String>
+    where
+        F: FnOnce(Delivery) -> Fut,
+        Fut: Future<Output = Result<String>>,
+    {
+        let lane = self.lane(destination);
+        let seq = lane.pending.lock().push(chunk);
+
+        let mut state = lane.state.lock().await;
+        if seq < lane.pending.lock().first {
+            // An earlier caller delivered this chunk along with its own
+            return state.outcome(seq);
+        }
+
+        self.wait_for_slot(&mut state).await;
+        let (chunks, text) = lane.pending.lock().take();
+        let window = self.shape.coalesce_window;
+        let delivery = match &state.last {
+            Some(last) if !window.is_zero() && last.at.elapsed() <= window => {
+                debug!("Coalescing {} bytes into {}", text.len(), last.message_id);
+                Delivery::Edit {
+                    message_id: last.message_id.clone(),
+                    body: last.body.clone() + &text,
+                }
+            }
+            _ => Delivery::Send { body: text },
+        };
+        let body = match &delivery {
+            Delivery::Send { body } | Delivery::Edit { body, .. } => body.clone(),
+        };
+
+        let outcome = deliver(delivery).await;
+        if let Ok(message_id) = &outcome {
+            state.last = Some(Delivered {
+                message_id: message_id.clone(),
+                body,
+                at: Instant::now(),
+            });
+        }
+        let waiting = chunks.end - chunks.start - 1;
+        if waiting > 0 {
+            state.batches.push(Batch {
+                chunks,
+                outcome: outcome
+                    .as_ref()
+                    .map(Clone::clone)
+                    .map_err(|e| format!("{e:#}")),
+                waiting,
+            });
+        }
+        drop(state);
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records deliveries, numbering sent messages
+    #[derive(Default)]
+    struct Outbox {
+        deliveries: Mutex<Vec<Delivery>>,
+    }
+
+    impl Outbox {
+        fn deliver(&self, delivery: Delivery) -> std::future::Ready<Result<String>> {
+            let mut deliveries = self.deliveries.lock();
+            deliveries.push(delivery.clone());
+            let sent = deliveries.len();
+            drop(deliveries);
+            std::future::ready(Ok(match delivery {
+                Delivery::Send { .. } => format!("m{sent}"),
+                Delivery::Edit { message_id, .. } => message_id,
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rapid_chunks_become_edits() {
+        let shaper = RateShaper::new(RateShape {
+            coalesce_window: Duration::from_secs(60),
+            ..RateShape::default()
+        });
+        let outbox = Outbox::default();
+
+        let first = shaper
+            .deliver("general", "Hel", |d| outbox.deliver(d))
+            .await
+            .unwrap();
+        let second = shaper
+            .deliver("general", "lo", |d| outbox.deliver(d))
+            .await
+            .unwrap();
+        shaper
+            .deliver("random", "Hi", |d| outbox.deliver(d))
+            .await
+            .unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(
+            *outbox.deliveries.lock(),
+            [
+                Delivery::Send {
+                    body: "Hel".to_string()
+                },
+                Delivery::Edit {
+                    message_id: "m1".to_string(),
+                    body: "Hello".to_string()
+                },
+                Delivery::Send {
+                    body: "Hi".to_string()
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queued_chunks_wait_and_go_out_together() {
+        let shaper = RateShaper::new(RateShape {
+            per_destination: Some(Rate {
+                messages: 1,
+                per: Duration::from_millis(100),
+            }),
+            ..RateShape::default()
+        });
+        let outbox = Outbox::default();
+        let started = Instant::now();
+
+        shaper
+            .deliver("general", "one", |d| outbox.deliver(d))
+            .await
+            .unwrap();
+        let (a, b) = tokio::join!(
+            shaper.deliver("general", "two ", |d| outbox.deliver(d)),
+            shaper.deliver("general", "three", |d| outbox.deliver(d)),
+        );
+
+        assert!(started.elapsed() >= Duration::from_millis(90));
+        assert_eq!(a.unwrap(), b.unwrap());
+        assert_eq!(
+            outbox.deliveries.lock()[1],
+            Delivery::Send {
+                body: "two three".to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_fails_every_chunk_it_carried() {
+        let shaper = RateShaper::new(RateShape {
+            per_destination: Some(Rate {
+                messages: 1,
+                per: Duration::from_millis(100),
+            }),
+            coalesce_window: Duration::from_secs(60),
+            ..RateShape::default()
+        });
+        let outbox = Outbox::default();
+
+        shaper
+            .deliver("general", "one", |d| outbox.deliver(d))
+            .await
+            .unwrap();
+        let (a, b) = tokio::join!(
+            shaper.deliver("general", " two", |_| std::future::ready(Err(
+                anyhow::anyhow!("connection reset")
+            ))),
+            shaper.deliver("general", " three", |d| outbox.deliver(d)),
+        );
+        assert!(a.is_err());
+        assert!(b.unwrap_err().to_string().contains("connection reset"));
+
+        let id = shaper
+            .deliver("general", " four", |d| outbox.deliver(d))
+            .await
+            .unwrap();
+        assert_eq!(id, "m1");
+        assert_eq!(
+            outbox.deliveries.lock()[1],
+            Delivery::Edit {
+                message_id: "m1".to_string(),
+                body: "one four".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,315 @@
+//! Load shedding under overload
+//!
+//! [`LoadShedder`] tracks how many messages are in flight and a smoothed
+//! wait for the context store, which is where requests queue for pooled
+//! connections. Saturation is the larger of the two relative to their
+//! [`LoadSheddingConfig`] limits. From a saturation of 1.0, background
+//! requests are rejected before any work is done; from
+//! `normal_saturation`, normal ones are as well. Interactive requests are
+//! never shed, so they keep the capacity that is left instead of timing out
+//! behind everything else.
+//!
+//! A request's [`RequestPriority`] comes from its [`PRIORITY_METADATA_KEY`]
+//! metadata, then its `urgent` flag, then its tenant's configured priority.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+    config::{LoadSheddingConfig, RequestPriority},
+    degraded::TENANT_METADATA_KEY,
+    message::{Message, Response, ResponseError},
+};
+
+/// Message metadata key setting the request's [`RequestPriority`]
+pub const PRIORITY_METADATA_KEY: &str = "priority";
+
+/// Response metadata key set on every shed response, holding the request's priority
+pub const SHED_METADATA_KEY: &str = "shed";
+
+/// Weight of a new sample in the smoothed wait
+const WAIT_SMOOTHING: f64 = 0.2;
+
+/// How long a wait sample counts once no newer one arrives
+///
+/// While everything but interactive traffic is shed, few requests reach the
+/// context store; stale samples must not keep the bot saturated.
+const WAIT_SAMPLE_TTL: Duration = Duration::from_secs(10);
+
+/// Why a request was rejected
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Overloaded {
+    /// Priority of the rejected request
+    pub priority: RequestPriority,
+    /// Saturation when it was rejected
+    pub saturation: f64,
+    /// Suggested wait before retrying
+    #[serde(with = "humantime_serde")]
+    pub retry_after: Duration,
+}
+
+impl Overloaded {
+    /// The retryable error response sent in place of a reply
+    #[must_use]
+    pub fn into_response(self, conversation_id: impl Into<String>) -> Response {
+        Response::error(
+            conversation_id,
+            ResponseError::new(
+                "overloaded",
+                "The service is overloaded, please retry later",
+            )
+            .retryable(true)
+            .retry_after(self.retry_after.as_secs().max(1)),
+        )
+        .with_metadata(SHED_METADATA_KEY, serde_json::json!(self.priority))
+    }
+}
+
+/// An admitted request; dropping it marks the request as finished
+#[derive(Debug)]
+pub struct LoadPermit<'a> {
+    shedder: &'a LoadShedder,
+}
+
+impl Drop for LoadPermit<'_> {
+    fn drop(&mut self) {
+        self.shedder.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct WaitSample {
+    smoothed: Duration,
+    at: Instant,
+}
+
+/// Admission control shared by a bot
+#[derive(Debug)]
+pub struct LoadShedder {
+    config: LoadSheddingConfig,
+    in_flight: AtomicUsize,
+    wait: Mutex<Option<WaitSample>>,
+    shed: AtomicU64,
+}
+
+impl LoadShedder {
+    /// Create a shedder with nothing in flight
+    #[must_use]
+    pub fn new(config: LoadSheddingConfig) -> Self {
+        Self {
+            config,
+            in_flight: AtomicUsize::new(0),
+            wait: Mutex::new(None),
+            shed: AtomicU64::new(0),
+        }
+    }
+
+    /// The priority of a message
+    #[must_use]
+    pub fn priority_of(&self, message: &Message) -> RequestPriority {
+        if let Some(priority) = message
+            .metadata
+            .get(PRIORITY_METADATA_KEY)
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+        {
+            return priority;
+        }
+        if message.flags.urgent {
+            return RequestPriority::Interactive;
+        }
+        let tenant = message
+            .metadata
+            .get(TENANT_METADATA_KEY)
+            .and_then(serde_json::Value::as_str);
+        self.config.priority_for(tenant)
+    }
+
+    /// Record how long a request waited for the context store
+    pub fn record_wait(&self, wait: Duration) {
+        let now = Instant::now();
+        let mut sample = self.wait.lock();
+        let smoothed = match *sample {
+            Some(previous) if now.duration_since(previous.at) < WAIT_SAMPLE_TTL => previous
+                .smoothed
+                .mul_f64(1.0 - WAIT_SMOOTHING)
+                .saturating_add(wait.mul_f64(WAIT_SMOOTHING)),
+            _ => wait,
+        };
+        *sample = Some(WaitSample { smoothed, at: now });
+    }
+
+    /// The smoothed context store wait, zero once samples have gone stale
+    #[must_use]
+    pub fn wait(&self) -> Duration {
+        let sample = *self.wait.lock();
+        sample
+            .filter(|sample| sample.at.elapsed() < WAIT_SAMPLE_TTL)
+            .map_or(Duration::ZERO, |sample| sample.smoothed)
+    }
+
+    /// Messages currently being processed
+    #[must_use]
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Requests rejected so far
+    #[must_use]
+    pub fn shed_total(&self) -> u64 {
+        self.shed.load(Ordering::Relaxed)
+    }
+
+    /// How loaded the bot is; 1.0 is at the configured limits
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn saturation(&self) -> f64 {
+        let depth = self.in_flight() as f64 / self.config.max_in_flight.max(1) as f64;
+        let wait = if self.config.max_wait.is_zero() {
+            0.0
+        } else {
+            self.wait().as_secs_f64() / self.config.max_wait.as_secs_f64()
+        };
+        depth.max(wait)
+    }
+
+    /// Admit a message, or reject it early if its priority is being shed
+    ///
+    /// Hold the permit until the message is processed. With shedding
+    /// disabled every message is admitted and still counted.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Overloaded`] if the message should be answered with
+    /// [`Overloaded::into_response`] instead of being processed.
+    pub fn admit(&self, message: &Message) -> Result<LoadPermit<'_>, Overloaded> {
+        if self.config.enabled {
+            let priority = self.priority_of(message);
+            let saturation = self.saturation();
+            let limit = match priority {
+                RequestPriority::Background => 1.0,
+                RequestPriority::Normal => self.config.normal_saturation,
+                RequestPriority::Interactive => f64::INFINITY,
+            };
+            if saturation >= limit {
+                self.shed.fetch_add(1, Ordering::Relaxed);
+                debug!(
+                    "Shedding {:?} request at saturation {:.2}",
+                    priority, saturation
+                );
+                return Err(Overloaded {
+                    priority,
+                    saturation,
+                    retry_after: self.config.retry_after,
+                });
+            }
+        }
+
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        Ok(LoadPermit { shedder: self })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn shedder() -> LoadShedder {
+        LoadShedder::new(LoadSheddingConfig {
+            enabled: true,
+            max_in_flight: 2,
+            max_wait: Duration::from_millis(100),
+            normal_saturation: 1.5,
+            retry_after: Duration::from_secs(3),
+            tenant_priorities: HashMap::from([("batch".to_string(), RequestPriority::Background)]),
+        })
+    }
+
+    fn with_priority(priority: &str) -> Message {
+        Message::text("hi").with_metadata(PRIORITY_METADATA_KEY, serde_json::json!(priority))
+    }
+
+    #[test]
+    fn test_priority_resolution() {
+        let shedder = shedder();
+        assert_eq!(
+            shedder.priority_of(&Message::text("hi")),
+            RequestPriority::Normal
+        );
+        assert_eq!(
+            shedder.priority_of(&with_priority("background")),
+            RequestPriority::Background
+        );
+
+        let mut urgent = Message::text("hi");
+        urgent.flags.urgent = true;
+        assert_eq!(shedder.priority_of(&urgent), RequestPriority::Interactive);
+
+        let batch = Message::text("hi").with_metadata(TENANT_METADATA_KEY, "batch".into());
+        assert_eq!(shedder.priority_of(&batch), RequestPriority::Background);
+        let pinned = batch.with_metadata(PRIORITY_METADATA_KEY, "interactive".into());
+        assert_eq!(shedder.priority_of(&pinned), RequestPriority::Interactive);
+    }
+
+    #[test]
+    fn test_queue_depth_sheds_lowest_priority_first() {
+        let shedder = shedder();
+        let first = shedder.admit(&Message::text("one")).unwrap();
+        let second = shedder.admit(&Message::text("two")).unwrap();
+        assert_eq!(shedder.in_flight(), 2);
+
+        let rejected = shedder.admit(&with_priority("background")).unwrap_err();
+        assert_eq!(rejected.priority, RequestPriority::Background);
+        assert!((rejected.saturation - 1.0).abs() < f64::EPSILON);
+
+        let third = shedder.admit(&Message::text("three")).unwrap();
+        assert!(shedder.admit(&Message::text("four")).is_err());
+        assert!(shedder.admit(&with_priority("interactive")).is_ok());
+        assert_eq!(shedder.shed_total(), 2);
+
+        drop((first, second, third));
+        assert_eq!(shedder.in_flight(), 0);
+        assert!(shedder.admit(&with_priority("background")).is_ok());
+    }
+
+    #[test]
+    fn test_wait_time_saturates() {
+        let shedder = shedder();
+        shedder.record_wait(Duration::from_millis(200));
+        assert!((shedder.saturation() - 2.0).abs() < 1e-9);
+        assert!(shedder.admit(&Message::text("hi")).is_err());
+
+        shedder.record_wait(Duration::ZERO);
+        assert_eq!(shedder.wait(), Duration::from_millis(160));
+    }
+
+    #[test]
+    fn test_disabled_admits_everything() {
+        let shedder = LoadShedder::new(LoadSheddingConfig {
+            max_in_flight: 1,
+            ..LoadSheddingConfig::default()
+        });
+        let _held = shedder.admit(&Message::text("one")).unwrap();
+        assert!(shedder.admit(&with_priority("background")).is_ok());
+        assert_eq!(shedder.shed_total(), 0);
+    }
+
+    #[test]
+    fn test_overloaded_response() {
+        let response = Overloaded {
+            priority: RequestPriority::Normal,
+            saturation: 1.7,
+            retry_after: Duration::from_secs(3),
+        }
+        .into_response("conv");
+        let error = response.error.as_ref().unwrap();
+        assert_eq!(error.code, "overloaded");
+        assert!(error.retryable);
+        assert_eq!(error.retry_after, Some(3));
+        assert_eq!(response.metadata[SHED_METADATA_KEY], "normal");
+    }
+}
@@ -0,0 +1,629 @@
+//! Latency service level objectives
+//!
+//! [`SloTracker`] records the end-to-end latency and outcome of every
+//! processed message into one-minute [`LatencyHistogram`] slots. Each
+//! [`LatencyObjective`] is evaluated over rolling windows of those slots:
+//! requests slower than its threshold, and optionally failed ones, are bad,
+//! and the burn rate is the bad fraction divided by the error budget
+//! `1 - target`. A burn rate of 1 spends the budget exactly over the
+//! objective's period.
+//!
+//! A [`BurnRateAlert`] fires when its long and short windows both burn
+//! faster than its limit, and resolves once they no longer do. Transitions
+//! are logged and passed to every registered [`SloAlertHook`], e.g. to page
+//! the on-call rotation before users notice.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::config::{AlertSeverity, BurnRateAlert, LatencyObjective, SloConfig};
+
+/// Default upper bounds of histogram buckets, in milliseconds
+const DEFAULT_BOUNDS_MS: [u64; 12] = [
+    50, 100, 250, 500, 1_000, 2_000, 3_000, 5_000, 10_000, 20_000, 30_000, 60_000,
+];
+
+/// Width of one histogram slot, in seconds
+const SLOT_SECONDS: i64 = 60;
+
+/// Request latencies counted into fixed buckets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    bounds: Arc<[Duration]>,
+    /// One count per bound, plus one for latencies above the last bound
+    counts: Vec<u64>,
+    /// Sum of the latencies counted
+    sum: Duration,
+}
+
+impl Default for LatencyHistogram {
+    /// An empty histogram with buckets from 50ms to one minute
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_BOUNDS_MS
+                .iter()
+                .map(|ms| Duration::from_millis(*ms))
+                .collect(),
+        )
+    }
+}
+
+impl LatencyHistogram {
+    /// An empty histogram whose buckets end at `bounds`
+    #[must_use]
+    pub fn new(mut bounds: Vec<Duration>) -> Self {
+        bounds.sort_unstable();
+        bounds.dedup();
+        Self {
+            counts: vec![0; bounds.len() + 1],
+            bounds: bounds.into(),
+            sum: Duration::ZERO,
+        }
+    }
+
+    fn empty_like(&self) -> Self {
+        Self {
+            bounds: Arc::clone(&self.bounds),
+            counts: vec![0; self.counts.len()],
+            sum: Duration::ZERO,
+        }
+    }
+
+    /// Count one request
+    pub fn record(&mut self, latency: Duration) {
+        let bucket = self.bounds.partition_point(|bound| *bound < latency);
+        self.counts[bucket] += 1;
+        self.sum += latency;
+    }
+
+    /// Add the counts of a histogram with the same buckets
+    pub fn merge(&mut self, other: &Self) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.sum += other.sum;
+    }
+
+    /// Requests counted
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Upper bounds of the buckets, shortest first
+    #[must_use]
+    pub fn bounds(&self) -> &[Duration] {
+        &self.bounds
+    }
+
+    /// Requests per bucket: one count per bound, then the count above the last bound
+    #[must_use]
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Total latency of the requests counted
+    #[must_use]
+    pub const fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    /// Requests in buckets ending at or below `threshold`
+    ///
+    /// Exact when `threshold` is a bucket bound; [`SloTracker`] adds every
+    /// objective's threshold to its bounds.
+    #[must_use]
+    pub fn count_within(&self, threshold: Duration) -> u64 {
+        let buckets = self.bounds.partition_point(|bound| *bound <= threshold);
+        self.counts[..buckets].iter().sum()
+    }
+
+    /// Upper bound of the bucket holding quantile `q`, e.g. 0.95 for p95
+    ///
+    /// Returns `None` when empty, and [`Duration::MAX`] when the quantile
+    /// falls above the largest bound.
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn percentile(&self, q: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, n) in self.counts.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Some(self.bounds.get(bucket).copied().unwrap_or(Duration::MAX));
+            }
+        }
+        Some(Duration::MAX)
+    }
+}
+
+/// One minute of recorded requests
+#[derive(Debug, Clone)]
+struct Slot {
+    minute: i64,
+    latencies: LatencyHistogram,
+    errors: u64,
+}
+
+/// Whether an alert started or stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    /// The budget is burning faster than the alert allows
+    Firing,
+    /// The burn rate dropped back below the limit
+    Resolved,
+}
+
+/// A burn-rate alert starting or stopping
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SloAlert {
+    /// Name of the objective
+    pub objective: String,
+    /// Urgency
+    pub severity: AlertSeverity,
+    /// Whether the alert started or stopped
+    pub state: AlertState,
+    /// Burn rate over the long window, if it had enough requests
+    pub long_burn_rate: Option<f64>,
+    /// Burn rate over the short window, if it had enough requests
+    pub short_burn_rate: Option<f64>,
+    /// The alert's limit
+    pub burn_rate_limit: f64,
+    /// Long window
+    #[serde(with = "humantime_serde")]
+    pub long_window: Duration,
+    /// Short window
+    #[serde(with = "humantime_serde")]
+    pub short_window: Duration,
+    /// Fraction of the period's error budget left; negative once overspent
+    pub budget_remaining: f64,
+    /// When the transition was detected
+    pub at: DateTime<Utc>,
+}
+
+/// Current standing of one objective over its period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SloStatus {
+    /// Name of the objective
+    pub objective: String,
+    /// Latency at the objective's target quantile, e.g. the p95
+    pub observed: Option<Duration>,
+    /// Requests counted
+    pub requests: u64,
+    /// Requests that missed the objective
+    pub bad_requests: u64,
+    /// Fraction of the error budget left; negative once overspent
+    pub budget_remaining: f64,
+    /// Severities of the alerts currently firing
+    pub firing: Vec<AlertSeverity>,
+}
+
+/// Receives SLO alerts as they fire and resolve
+#[async_trait]
+pub trait SloAlertHook: Send + Sync {
+    /// Handle a transition
+    async fn on_alert(&self, alert: &SloAlert) -> Result<()>;
+}
+
+/// Tracks request latency against [`SloConfig`] objectives
+pub struct SloTracker {
+    config: SloConfig,
+    empty: LatencyHistogram,
+    retention_minutes: i64,
+    slots: Mutex<VecDeque<Slot>>,
+    /// Firing alerts, by objective and alert index
+    firing: Mutex<HashSet<(usize, usize)>>,
+    hooks: RwLock<Vec<Arc<dyn SloAlertHook>>>,
+}
+
+impl std::fmt::Debug for SloTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SloTracker")
+            .field("config", &self.config)
+            .field("hooks", &self.hooks.read().len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl SloTracker {
+    /// Create a tracker for the configured objectives
+    #[must_use]
+    pub fn new(config: SloConfig) -> Self {
+        let mut bounds: Vec<Duration> = DEFAULT_BOUNDS_MS
+            .iter()
+            .map(|ms| Duration::from_millis(*ms))
+            .collect();
+        bounds.extend(config.objectives.iter().map(|o| o.threshold));
+
+        let retention = config
+            .objectives
+            .iter()
+            .flat_map(|o| std::iter::once(o.period).chain(o.alerts.iter().map(|a| a.long_window)))
+            .max()
+            .unwrap_or_default();
+
+        Self {
+            empty: LatencyHistogram::new(bounds),
+            retention_minutes: minutes(retention),
+            slots: Mutex::new(VecDeque::new()),
+            firing: Mutex::new(HashSet::new()),
+            hooks: RwLock::new(Vec::new()),
+            config,
+        }
+    }
+
+    /// Whether requests are being tracked
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled && !self.config.objectives.is_empty()
+    }
+
+    /// Pass alerts to `hook` as well
+    pub fn add_hook(&self, hook: Arc<dyn SloAlertHook>) {
+        self.hooks.write().push(hook);
+    }
+
+    /// Record a processed request
+    ///
+    /// Failed requests are kept apart from latencies, so they only count
+    /// against objectives with [`LatencyObjective::count_errors`] set.
+    pub fn record(&self, latency: Duration, failed: bool) {
+        if self.is_enabled() {
+            self.record_at(Utc::now(), latency, failed);
+        }
+    }
+
+    fn record_at(&self, at: DateTime<Utc>, latency: Duration, failed: bool) {
+        let minute = at.timestamp().div_euclid(SLOT_SECONDS);
+        let mut slots = self.slots.lock();
+        if slots.back().is_none_or(|slot| slot.minute < minute) {
+            slots.push_back(Slot {
+                minute,
+                latencies: self.empty.clone(),
+                errors: 0,
+            });
+        }
+        // Requests finishing out of order land in their own minute, if still kept
+        let Some(slot) = slots.iter_mut().rev().find(|slot| slot.minute == minute) else {
+            return;
+        };
+        if failed {
+            slot.errors += 1;
+        } else {
+            slot.latencies.record(latency);
+        }
+        while slots
+            .front()
+            .is_some_and(|slot| slot.minute <= minute - self.retention_minutes)
+        {
+            slots.pop_front();
+        }
+        drop(slots);
+    }
+
+    /// Latencies and failures in the `window` ending at `now`
+    fn window(&self, now: DateTime<Utc>, window: Duration) -> (LatencyHistogram, u64) {
+        let last = now.timestamp().div_euclid(SLOT_SECONDS);
+        let first = last - minutes(window) + 1;
+        let mut latencies = self.empty.empty_like();
+        let mut errors = 0;
+        for slot in self.slots.lock().iter().rev() {
+            if slot.minute < first {
+                break;
+            }
+            if slot.minute <= last {
+                latencies.merge(&slot.latencies);
+                errors += slot.errors;
+            }
+        }
+        (latencies, errors)
+    }
+
+    /// Standing of every objective over its period
+    #[must_use]
+    pub fn status(&self) -> Vec<SloStatus> {
+        self.status_at(Utc::now())
+    }
+
+    fn status_at(&self, now: DateTime<Utc>) -> Vec<SloStatus> {
+        let firing = self.firing.lock().clone();
+        self.config
+            .objectives
+            .iter()
+            .enumerate()
+            .map(|(i, objective)| {
+                let (latencies, errors) = self.window(now, objective.period);
+                let (requests, bad_requests) = outcomes(objective, &latencies, errors);
+                SloStatus {
+                    objective: objective.name.clone(),
+                    observed: latencies.percentile(objective.target),
+                    requests,
+                    bad_requests,
+                    budget_remaining: budget_remaining(objective, requests, bad_requests),
+                    firing: objective
+                        .alerts
+                        .iter()
+                        .enumerate()
+                        .filter(|(j, _)| firing.contains(&(i, *j)))
+                        .map(|(_, alert)| alert.severity)
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// Check burn rates, passing alerts that fired or resolved to the hooks
+    pub async fn evaluate(&self) -> Vec<SloAlert> {
+        let alerts = self.transitions_at(Utc::now());
+        if alerts.is_empty() {
+            return alerts;
+        }
+        let hooks = self.hooks.read().clone();
+        for alert in &alerts {
+            for hook in &hooks {
+                if let Err(e) = hook.on_alert(alert).await {
+                    warn!("SLO alert hook failed for {}: {}", alert.objective, e);
+                }
+            }
+        }
+        alerts
+    }
+
+    fn transitions_at(&self, now: DateTime<Utc>) -> Vec<SloAlert> {
+        let mut alerts = Vec::new();
+        let mut firing = self.firing.lock();
+        for (i, objective) in self.config.objectives.iter().enumerate() {
+            let (latencies, errors) = self.window(now, objective.period);
+            let (requests, bad_requests) = outcomes(objective, &latencies, errors);
+            let budget_remaining = budget_remaining(objective, requests, bad_requests);
+
+            for (j, alert) in objective.alerts.iter().enumerate() {
+                let long_burn_rate = self.burn_rate(now, objective, alert.long_window);
+                let short_burn_rate = self.burn_rate(now, objective, alert.short_window);
+                let exceeded = long_burn_rate.is_some_and(|rate| rate >= alert.burn_rate)
+                    && short_burn_rate.is_some_and(|rate| rate >= alert.burn_rate);
+                let state = match (exceeded, firing.contains(&(i, j))) {
+                    (true, false) => {
+                        firing.insert((i, j));
+                        AlertState::Firing
+                    }
+                    (false, true) => {
+                        firing.remove(&(i, j));
+                        AlertState::Resolved
+                    }
+                    _ => continue,
+                };
+                let alert = slo_alert(
+                    objective,
+                    alert,
+                    state,
+                    (long_burn_rate, short_burn_rate),
+                    budget_remaining,
+                    now,
+                );
+                match state {
+                    AlertState::Firing => warn!(
+                        "SLO {} burning error budget at {:.1}x over {:?} ({:?} alert)",
+                        alert.objective,
+                        alert.long_burn_rate.unwrap_or_default(),
+                        alert.long_window,
+                        alert.severity
+                    ),
+                    AlertState::Resolved => info!(
+                        "SLO {} burn rate back below {:.1}x over {:?}",
+                        alert.objective, alert.burn_rate_limit, alert.long_window
+                    ),
+                }
+                alerts.push(alert);
+            }
+        }
+        drop(firing);
+        alerts
+    }
+
+    fn burn_rate(
+        &self,
+        now: DateTime<Utc>,
+        objective: &LatencyObjective,
+        window: Duration,
+    ) -> Option<f64> {
+        let (latencies, errors) = self.window(now, window);
+        let (requests, bad_requests) = outcomes(objective, &latencies, errors);
+        if requests == 0 || requests < objective.min_requests {
+            return None;
+        }
+        Some(bad_fraction(requests, bad_requests) / (1.0 - objective.target))
+    }
+
+    /// Evaluate every [`SloConfig::evaluation_interval`] until the returned task is aborted
+    pub fn spawn_evaluator(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.config.evaluation_interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                self.evaluate().await;
+            }
+        })
+    }
+}
+
+fn slo_alert(
+    objective: &LatencyObjective,
+    alert: &BurnRateAlert,
+    state: AlertState,
+    (long_burn_rate, short_burn_rate): (Option<f64>, Option<f64>),
+    budget_remaining: f64,
+    at: DateTime<Utc>,
+) -> SloAlert {
+    SloAlert {
+        objective: objective.name.clone(),
+        severity: alert.severity,
+        state,
+        long_burn_rate,
+        short_burn_rate,
+        burn_rate_limit: alert.burn_rate,
+        long_window: alert.long_window,
+        short_window: alert.short_window,
+        budget_remaining,
+        at,
+    }
+}
+
+/// Requests counted by `objective` and how many of them were bad
+fn outcomes(objective: &LatencyObjective, latencies: &LatencyHistogram, errors: u64) -> (u64, u64) {
+    let slow = latencies.count() - latencies.count_within(objective.threshold);
+    if objective.count_errors {
+        (latencies.count() + errors, slow + errors)
+    } else {
+        (latencies.count(), slow)
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn bad_fraction(requests: u64, bad_requests: u64) -> f64 {
+    if requests == 0 {
+        return 0.0;
+    }
+    bad_requests as f64 / requests as f64
+}
+
+fn budget_remaining(objective: &LatencyObjective, requests: u64, bad_requests: u64) -> f64 {
+    1.0 - bad_fraction(requests, bad_requests) / (1.0 - objective.target)
+}
+
+/// Whole minutes covering `duration`, at least one
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn minutes(duration: Duration) -> i64 {
+    (duration.as_secs().div_ceil(SLOT_SECONDS as u64) as i64).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> SloTracker {
+        SloTracker::new(SloConfig {
+            enabled: true,
+            ..SloConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_histogram_percentiles() {
+        let mut histogram =
+            LatencyHistogram::new(vec![Duration::from_millis(100), Duration::from_secs(1)]);
+        assert_eq!(histogram.percentile(0.95), None);
+        for _ in 0..90 {
+            histogram.record(Duration::from_millis(80));
+        }
+        for _ in 0..10 {
+            histogram.record(Duration::from_millis(600));
+        }
+        assert_eq!(histogram.percentile(0.5), Some(Duration::from_millis(100)));
+        assert_eq!(histogram.percentile(0.95), Some(Duration::from_secs(1)));
+        assert_eq!(histogram.count_within(Duration::from_millis(100)), 90);
+
+        histogram.record(Duration::from_secs(5));
+        assert_eq!(histogram.percentile(1.0), Some(Duration::MAX));
+    }
+
+    #[test]
+    fn test_fast_burn_fires_and_resolves() {
+        let tracker = tracker();
+        let now = Utc::now();
+
+        // Half the requests in the last ten minutes were slow: a 10x burn
+        // rate, above the 6x and 1x limits but below the 14.4x one
+        for minute in (0..10).rev() {
+            let at = now - chrono::Duration::minutes(minute);
+            for _ in 0..10 {
+                tracker.record_at(at, Duration::from_millis(400), false);
+            }
+            for _ in 0..10 {
+                tracker.record_at(at, Duration::from_secs(4), false);
+            }
+        }
+        let alerts = tracker.transitions_at(now);
+        assert_eq!(alerts.len(), 2);
+        assert!(alerts.iter().all(|alert| alert.state == AlertState::Firing));
+        assert!((alerts[0].burn_rate_limit - 6.0).abs() < f64::EPSILON);
+        assert!((alerts[0].long_burn_rate.unwrap() - 10.0).abs() < 1e-9);
+        assert!(tracker.transitions_at(now).is_empty());
+
+        let status = &tracker.status_at(now)[0];
+        assert_eq!(status.requests, 200);
+        assert_eq!(status.bad_requests, 100);
+        assert_eq!(status.observed, Some(Duration::from_secs(5)));
+        assert_eq!(
+            status.firing,
+            vec![AlertSeverity::Page, AlertSeverity::Ticket]
+        );
+
+        // Once the page's short window is quiet it resolves; the ticket's
+        // six-hour window still sees the slow requests
+        let later = now + chrono::Duration::minutes(31);
+        let alerts = tracker.transitions_at(later);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].state, AlertState::Resolved);
+        assert_eq!(alerts[0].severity, AlertSeverity::Page);
+    }
+
+    #[test]
+    fn test_errors_count_only_when_configured() {
+        let mut config = SloConfig {
+            enabled: true,
+            ..SloConfig::default()
+        };
+        config.objectives[0].count_errors = false;
+        let tracker = SloTracker::new(config);
+        let now = Utc::now();
+        tracker.record_at(now, Duration::from_millis(100), false);
+        tracker.record_at(now, Duration::from_millis(100), true);
+
+        let status = &tracker.status_at(now)[0];
+        assert_eq!(status.requests, 1);
+        assert_eq!(status.bad_requests, 0);
+        assert!((status.budget_remaining - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[tokio::test]
+    async fn test_hooks_receive_alerts() {
+        struct Recorder(Mutex<Vec<SloAlert>>);
+
+        #[async_trait]
+        impl SloAlertHook for Recorder {
+            async fn on_alert(&self, alert: &SloAlert) -> Result<()> {
+                self.0.lock().push(alert.clone());
+                Ok(())
+            }
+        }
+
+        let tracker = tracker();
+        let recorder = Arc::new(Recorder(Mutex::new(Vec::new())));
+        tracker.add_hook(recorder.clone());
+        for _ in 0..20 {
+            tracker.record(Duration::from_secs(10), false);
+        }
+        let alerts = tracker.evaluate().await;
+        assert!(!alerts.is_empty());
+        assert_eq!(recorder.0.lock().len(), alerts.len());
+    }
+}
@@ -0,0 +1,520 @@
+//! `SQLite` context storage
+//!
+//! [`SqliteContextStore`] backs [`StorageBackend::Sqlite`](crate::config::StorageBackend::Sqlite)
+//! for single-node bots that keep context in a local file. The database runs
+//! in WAL mode, so reads are not blocked by writes, and with incremental
+//! auto-vacuum, so [`vacuum`](SqliteContextStore::vacuum) can return the
+//! space of expired rows to the file system.
+//!
+//! A file that fails its integrity check on open is moved aside as
+//! `<path>.corrupt-<timestamp>` and replaced with an empty database. Stored
+//! contexts are lost, but the bot starts instead of failing on every
+//! request.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use sqlx::sqlite::{
+    SqliteAutoVacuum, SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions,
+    SqliteSynchronous,
+};
+use sqlx::Row;
+use tracing::{debug, info, warn};
+
+use crate::{
+    compression::PayloadCodec,
+    context::{Context, ContextPage, ContextStore},
+    encryption::ContextEncryption,
+    error::Error,
+};
+
+/// How often [`ContextManager`](crate::context::ContextManager) vacuums the store
+pub const VACUUM_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Schema steps, applied in order and tracked in `PRAGMA user_version`
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS contexts (
+        key TEXT PRIMARY KEY NOT NULL,
+        payload BLOB NOT NULL,
+        expires_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    ) WITHOUT ROWID",
+    "CREATE INDEX IF NOT EXISTS contexts_expires_at_idx ON contexts (expires_at)",
+];
+
+/// `SQLite` primary result codes meaning the file is damaged or not a database
+const SQLITE_CORRUPT: i32 = 11;
+const SQLITE_NOTADB: i32 = 26;
+
+/// What a vacuum pass did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VacuumReport {
+    /// Expired contexts deleted
+    pub purged: u64,
+    /// Free pages returned to the file system
+    pub freed_pages: i64,
+}
+
+/// Context store backed by a local `SQLite` file
+pub struct SqliteContextStore {
+    pool: SqlitePool,
+    path: PathBuf,
+    codec: PayloadCodec,
+    encryption: Option<ContextEncryption>,
+}
+
+impl std::fmt::Debug for SqliteContextStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqliteContextStore")
+            .field("path", &self.path)
+            .field("encrypted", &self.encryption.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl SqliteContextStore {
+    /// Open or create the database at `path` and bring its schema up to date
+    ///
+    /// A corrupted file is moved aside and replaced with an empty database.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the file cannot be opened or created,
+    /// including after moving a corrupted one aside.
+    pub async fn open(
+        path: impl AsRef<Path>,
+        codec: PayloadCodec,
+        encryption: Option<ContextEncryption>,
+    ) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let pool = match Self::connect(&path).await {
+            Ok(pool) => pool,
+            Err(e) if is_corruption(&e) => {
+                let moved = quarantine(&path).await?;
+                warn!(
+                    "Context database {} is corrupted ({}); moved it to {} and starting empty",
+                    path.display(),
+                    e,
+                    moved.display()
+                );
+                Self::connect(&path).await.map_err(database_error)?
+            }
+            Err(e) => return Err(database_error(e).into()),
+        };
+        Ok(Self {
+            pool,
+            path,
+            codec,
+            encryption,
+        })
+    }
+
+    async fn connect(path: &Path) -> std::result::Result<SqlitePool, sqlx::Error> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .auto_vacuum(SqliteAutoVacuum::Incremental)
+            .busy_timeout(Duration::from_secs(5));
+        let pool = SqlitePoolOptions::new()
+            .max_connections(4)
+            .connect_with(options)
+            .await?;
+
+        let check: String = sqlx::query_scalar("PRAGMA quick_check")
+            .fetch_one(&pool)
+            .await?;
+        if check != "ok" {
+            pool.close().await;
+            return Err(sqlx::Error::Protocol(format!(
+                "integrity check failed: {check}"
+            )));
+        }
+
+        Self::migrate(&pool).await?;
+        Ok(pool)
+    }
+
+    async fn migrate(pool: &SqlitePool) -> std::result::Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        let applied: i64 = sqlx::query_scalar("PRAGMA user_version")
+            .fetch_one(&mut *tx)
+            .await?;
+        let pending: Vec<(i64, &str)> = (1..)
+            .zip(MIGRATIONS.iter().copied())
+            .filter(|(version, _)| *version > applied)
+            .collect();
+        for (version, step) in &pending {
+            debug!("Applying context schema step {}", version);
+            sqlx::query(step).execute(&mut *tx).await?;
+        }
+        if let Some((version, _)) = pending.last() {
+            // PRAGMA arguments cannot be bound
+            sqlx::query(&format!("PRAGMA user_version = {version}"))
+                .execute(&mut *tx)
+                .await?;
+            info!("Applied {} context schema steps", pending.len());
+        }
+        tx.commit().await
+    }
+
+    /// The database file
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Delete expired contexts, returning how many were removed
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the delete fails.
+    pub async fn purge_expired(&self) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM contexts WHERE expires_at <= ?")
+            .bind(now_millis())
+            .execute(&self.pool)
+            .await
+            .map_err(database_error)?;
+        Ok(result.rows_affected())
+    }
+
+    /// Purge expired contexts, release free pages, and truncate the WAL
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if a step fails.
+    pub async fn vacuum(&self) -> Result<VacuumReport> {
+        let purged = self.purge_expired().await?;
+        let mut conn = self.pool.acquire().await.map_err(database_error)?;
+        let freed_pages: i64 = sqlx::query_scalar("PRAGMA freelist_count")
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(database_error)?;
+        sqlx::query("PRAGMA incremental_vacuum")
+            .execute(&mut *conn)
+            .await
+            .map_err(database_error)?;
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&mut *conn)
+            .await
+            .map_err(database_error)?;
+        Ok(VacuumReport {
+            purged,
+            freed_pages,
+        })
+    }
+
+    /// Vacuum every `interval` until the store is dropped
+    pub fn spawn_vacuum(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let store: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(store) = store.upgrade() else {
+                    break;
+                };
+                match store.vacuum().await {
+                    Ok(report) => debug!("Vacuumed context database: {:?}", report),
+                    Err(e) => warn!("Failed to vacuum context database: {:#}", e),
+                }
+            }
+        })
+    }
+
+    async fn decode(&self, key: &str, payload: Vec<u8>) -> Result<Context> {
+        match &self.encryption {
+            Some(encryption) => encryption.open_context(key, payload, &self.codec).await,
+            None => Ok(self.codec.decode(&payload)?),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ContextStore for SqliteContextStore {
+    async fn get(&self, key: &str) -> Result<Option<Context>> {
+        let payload: Option<Vec<u8>> =
+            sqlx::query_scalar("SELECT payload FROM contexts WHERE key = ? AND expires_at > ?")
+                .bind(key)
+                .bind(now_millis())
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(database_error)?;
+        match payload {
+            Some(payload) => Ok(Some(self.decode(key, payload).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn set(&self, key: &str, context: Context, ttl: Duration) -> Result<()> {
+        let mut payload = self.codec.encode(&context)?;
+        if let Some(encryption) = &self.encryption {
+            payload = encryption.seal(context.tenant(), key, payload).await?;
+        }
+        let now = now_millis();
+        sqlx::query(
+            "INSERT INTO contexts (key, payload, expires_at, updated_at) VALUES (?, ?, ?, ?) \
+             ON CONFLICT (key) DO UPDATE SET payload = excluded.payload, \
+             expires_at = excluded.expires_at, updated_at = excluded.updated_at",
+        )
+        .bind(key)
+        .bind(payload)
+        .bind(expires_at(now, ttl))
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(database_error)?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        sqlx::query("DELETE FROM contexts WHERE key = ?")
+            .bind(key)
+            .execute(&self.pool)
+            .await
+            .map_err(database_error)?;
+        Ok(())
+    }
+
+    async fn list_keys(&self, pattern: &str) -> Result<Vec<String>> {
+        // instr() rather than LIKE, which ignores ASCII case in SQLite
+        let keys = sqlx::query_scalar(
+            "SELECT key FROM contexts WHERE instr(key, ?) > 0 AND expires_at > ?",
+        )
+        .bind(pattern)
+        .bind(now_millis())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(database_error)?;
+        Ok(keys)
+    }
+
+    async fn list_page(
+        &self,
+        pattern: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<ContextPage> {
+        let keys = sqlx::query_scalar(
+            "SELECT key FROM contexts WHERE instr(key, ?) > 0 AND expires_at > ? \
+             AND (? IS NULL OR key > ?) ORDER BY key LIMIT ?",
+        )
+        .bind(pattern)
+        .bind(now_millis())
+        .bind(cursor)
+        .bind(cursor)
+        .bind(i64::try_from(limit.saturating_add(1)).unwrap_or(i64::MAX))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(database_error)?;
+        Ok(ContextPage::from_overfetch(keys, limit))
+    }
+
+    async fn get_many(&self, keys: &[&str]) -> Result<Vec<Option<Context>>> {
+        let rows = sqlx::query(
+            "SELECT key, payload FROM contexts \
+             WHERE key IN (SELECT value FROM json_each(?)) AND expires_at > ?",
+        )
+        .bind(json_keys(keys)?)
+        .bind(now_millis())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(database_error)?;
+
+        let mut found = std::collections::HashMap::with_capacity(rows.len());
+        for row in rows {
+            let key: String = row.try_get("key").map_err(database_error)?;
+            let payload: Vec<u8> = row.try_get("payload").map_err(database_error)?;
+            let context = self.decode(&key, payload).await?;
+            found.insert(key, context);
+        }
+        Ok(keys.iter().map(|key| found.remove(*key)).collect())
+    }
+
+    async fn delete_many(&self, keys: &[&str]) -> Result<()> {
+        sqlx::query("DELETE FROM contexts WHERE key IN (SELECT value FROM json_each(?))")
+            .bind(json_keys(keys)?)
+            .execute(&self.pool)
+            .await
+            .map_err(database_error)?;
+        Ok(())
+    }
+
+    async fn touch_many(&self, keys: &[&str], ttl: Duration) -> Result<()> {
+        let now = now_millis();
+        sqlx::query(
+            "UPDATE contexts SET expires_at = ? \
+             WHERE key IN (SELECT value FROM json_each(?)) AND expires_at > ?",
+        )
+        .bind(expires_at(now, ttl))
+        .bind(json_keys(keys)?)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(database_error)?;
+        Ok(())
+    }
+
+    fn compression_stats(&self) -> Option<crate::compression::CompressionStats> {
+        Some(self.codec.stats())
+    }
+}
+
+fn now_millis() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+fn expires_at(now: i64, ttl: Duration) -> i64 {
+    now.saturating_add(i64::try_from(ttl.as_millis()).unwrap_or(i64::MAX))
+}
+
+/// Keys as a JSON array, for `json_each`
+fn json_keys(keys: &[&str]) -> Result<String> {
+    serde_json::to_string(keys).map_err(|e| Error::Serialization(e.to_string()).into())
+}
+
+/// Whether `e` means the database file is damaged
+fn is_corruption(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Database(db) => db
+            .code()
+            .and_then(|code| i32::from_str(&code).ok())
+            .is_some_and(|code| matches!(code & 0xff, SQLITE_CORRUPT | SQLITE_NOTADB)),
+        sqlx::Error::Protocol(message) => message.starts_with("integrity check failed"),
+        _ => false,
+    }
+}
+
+/// Move a damaged database and its WAL files aside, returning the new path
+async fn quarantine(path: &Path) -> Result<PathBuf> {
+    let moved = sibling(
+        path,
+        &format!(".corrupt-{}", Utc::now().format("%Y%m%dT%H%M%S")),
+    );
+    tokio::fs::rename(path, &moved).await.map_err(|e| {
+        Error::Database(format!(
+            "Failed to move corrupted database {}: {e}",
+            path.display()
+        ))
+    })?;
+    for suffix in ["-wal", "-shm"] {
+        let sidecar = sibling(path, suffix);
+        if tokio::fs::try_exists(&sidecar).await.unwrap_or(false) {
+            if let Err(e) = tokio::fs::rename(&sidecar, sibling(&moved, suffix)).await {
+                warn!("Failed to move {}: {}", sidecar.display(), e);
+            }
+        }
+    }
+    Ok(moved)
+}
+
+/// `path` with `suffix` appended to its file name
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[allow(clippy::needless_pass_by_value)] // Passed to `map_err`
+fn database_error(e: sqlx::Error) -> Error {
+    Error::Database(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CompressionConfig;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("contexts-{}.db", uuid::Uuid::new_v4()))
+    }
+
+    async fn store(path: &Path) -> SqliteContextStore {
+        SqliteContextStore::open(path, PayloadCodec::new(CompressionConfig::default()), None)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_round_trip_and_expiry() {
+        let path = temp_path();
+        let store = store(&path).await;
+        let hour = Duration::from_secs(3600);
+        store
+            .set("user:1", Context::new("user:1"), hour)
+            .await
+            .unwrap();
+        store
+            .set("User:2", Context::new("User:2"), hour)
+            .await
+            .unwrap();
+        store
+            .set("user:3", Context::new("user:3"), Duration::ZERO)
+            .await
+            .unwrap();
+
+        assert_eq!(store.get("user:1").await.unwrap().unwrap().id, "user:1");
+        assert!(store.get("user:3").await.unwrap().is_none());
+        assert_eq!(store.list_keys("user:").await.unwrap(), vec!["user:1"]);
+
+        let found = store.get_many(&["user:1", "missing"]).await.unwrap();
+        assert!(found[0].is_some() && found[1].is_none());
+
+        let page = store.list_page(":", None, 1).await.unwrap();
+        assert_eq!(page.keys, vec!["User:2"]);
+        let page = store
+            .list_page(":", page.next_cursor.as_deref(), 1)
+            .await
+            .unwrap();
+        assert_eq!(page.keys, vec!["user:1"]);
+        assert!(page.next_cursor.is_none());
+
+        let report = store.vacuum().await.unwrap();
+        assert_eq!(report.purged, 1);
+
+        store.delete_many(&["user:1", "User:2"]).await.unwrap();
+        assert!(store.list_keys("").await.unwrap().is_empty());
+        drop(store);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_contexts_survive_reopen() {
+        let path = temp_path();
+        let hour = Duration::from_secs(3600);
+        store(&path)
+            .await
+            .set("kept", Context::new("kept"), hour)
+            .await
+            .unwrap();
+        assert!(store(&path).await.get("kept").await.unwrap().is_some());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_file_is_moved_aside() {
+        let path = temp_path();
+        std::fs::write(&path, vec![0xAB; 8192]).unwrap();
+
+        let store = store(&path).await;
+        assert!(store.get("anything").await.unwrap().is_none());
+
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        let quarantined: Vec<_> = std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|file| file.starts_with(&format!("{name}.corrupt-")))
+            .collect();
+        assert_eq!(quarantined.len(), 1);
+        for file in quarantined {
+            let _ = std::fs::remove_file(path.with_file_name(file));
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+}
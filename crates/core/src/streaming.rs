@@ -0,0 +1,164 @@
+//! Streaming replies as they are generated
+//!
+//! [`Bot::process_stream`](crate::Bot::process_stream) runs a message
+//! through the same pipeline as [`Bot::process`](crate::Bot::process) and
+//! yields [`StreamChunk`]s: a [`Delta`](StreamChunk::Delta) for each piece
+//! of text the provider produces, then one [`Done`](StreamChunk::Done) with
+//! the final response or an [`Error`](StreamChunk::Error). Later pipeline
+//! stages and plugins may still rewrite the text, so clients should replace
+//! the streamed text with the final response's content.
+//!
+//! Providers receive a [`ChunkSink`] through
+//! [`Provider::generate_streaming`](crate::provider::Provider::generate_streaming).
+//! The sink holds at most [`STREAM_BUFFER`] undelivered chunks and then waits,
+//! so a slow reader slows generation rather than growing a queue. Dropping
+//! the stream cancels the request: generation stops and the exchange is not
+//! recorded in the conversation.
+
+use std::future::Future;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{error::Error, message::Response};
+
+/// Chunks a stream buffers before the provider has to wait for the reader
+pub const STREAM_BUFFER: usize = 32;
+
+tokio::task_local! {
+    static CURRENT_SINK: ChunkSink;
+}
+
+/// One event of a streamed reply
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamChunk {
+    /// More reply text
+    Delta {
+        /// Text to append
+        text: String,
+    },
+    /// The final response; always the last chunk of a successful stream
+    Done {
+        /// Response as returned by [`Bot::process`](crate::Bot::process)
+        response: Box<Response>,
+    },
+    /// Processing failed; always the last chunk of a failed stream
+    Error {
+        /// Error code, as in [`ErrorResponse`](crate::error::ErrorResponse)
+        code: String,
+        /// Error message
+        message: String,
+    },
+}
+
+impl StreamChunk {
+    /// The chunk ending a stream that failed with `error`
+    #[must_use]
+    pub fn error(error: &anyhow::Error) -> Self {
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<Error>())
+            .map_or_else(
+                || Self::Error {
+                    code: "E999".to_string(),
+                    message: format!("{error:#}"),
+                },
+                |cause| Self::Error {
+                    code: cause.error_code().to_string(),
+                    message: cause.to_string(),
+                },
+            )
+    }
+
+    /// Whether this chunk ends the stream
+    #[must_use]
+    pub const fn is_final(&self) -> bool {
+        !matches!(self, Self::Delta { .. })
+    }
+}
+
+/// Where a provider sends reply text while generating
+#[derive(Debug, Clone)]
+pub struct ChunkSink {
+    tx: mpsc::Sender<StreamChunk>,
+}
+
+impl ChunkSink {
+    /// Create a sink and the receiver its chunks arrive on
+    #[must_use]
+    pub fn channel() -> (Self, mpsc::Receiver<StreamChunk>) {
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        (Self { tx }, rx)
+    }
+
+    /// The sink of the stream being processed on this task, if any
+    #[must_use]
+    pub fn current() -> Option<Self> {
+        CURRENT_SINK.try_with(Clone::clone).ok()
+    }
+
+    /// Run `future` with this sink as the [`current`](Self::current) one
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CURRENT_SINK.scope(self, future).await
+    }
+
+    /// Send reply text, waiting while the buffer is full
+    ///
+    /// Text sent after the reader has gone is discarded; the request is
+    /// cancelled separately.
+    pub async fn send(&self, text: impl Into<String>) {
+        let text = text.into();
+        if !text.is_empty() {
+            let _ = self.tx.send(StreamChunk::Delta { text }).await;
+        }
+    }
+
+    /// Send the chunk ending the stream
+    pub async fn finish(&self, outcome: &anyhow::Result<Response>) {
+        let chunk = match outcome {
+            Ok(response) => StreamChunk::Done {
+                response: Box::new(response.clone()),
+            },
+            Err(e) => StreamChunk::error(e),
+        };
+        let _ = self.tx.send(chunk).await;
+    }
+
+    /// Whether the reader has gone
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// Wait until the reader has gone
+    pub async fn closed(&self) {
+        self.tx.closed().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sink_is_scoped_to_the_task() {
+        assert!(ChunkSink::current().is_none());
+        let (sink, mut rx) = ChunkSink::channel();
+        sink.clone()
+            .scope(async {
+                ChunkSink::current().unwrap().send("Hel").await;
+                ChunkSink::current().unwrap().send("").await;
+            })
+            .await;
+        sink.finish(&Err(Error::RateLimit.into())).await;
+
+        assert!(matches!(rx.recv().await, Some(StreamChunk::Delta { text }) if text == "Hel"));
+        let last = rx.recv().await.unwrap();
+        assert!(last.is_final());
+        assert!(matches!(last, StreamChunk::Error { code, .. } if code == "E009"));
+
+        drop(rx);
+        assert!(sink.is_closed());
+    }
+}
@@ -0,0 +1,121 @@
+//! Structured output
+//!
+//! A message carrying a JSON Schema under [`OUTPUT_SCHEMA_METADATA_KEY`]
+//! has the schema added to the system prompt by the process stage.
+//! With the `schema` feature,
+//! [`Bot::process_structured`](crate::Bot::process_structured) sets the
+//! schema from the target type, checks the reply against it, and sends a
+//! reply that does not parse or match back to the model with the error, up
+//! to a repair limit.
+
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+use crate::{
+    completion::extract_json,
+    json_schema::{self, SchemaViolation},
+    message::Response,
+};
+
+/// Message metadata key holding the JSON Schema the reply must match
+pub const OUTPUT_SCHEMA_METADATA_KEY: &str = "output_schema";
+
+/// A value parsed from a reply, with the reply it came from
+#[derive(Debug, Clone)]
+pub struct StructuredResponse<T> {
+    /// The parsed value
+    pub value: T,
+    /// The reply that parsed
+    pub response: Response,
+    /// Messages processed, including the first
+    pub attempts: usize,
+}
+
+/// Why a reply was not accepted
+#[derive(Debug, Error)]
+pub enum ReplyError {
+    /// The reply holds no JSON, or it does not deserialize as the target type
+    #[error("{0}")]
+    Parse(#[from] serde_json::Error),
+    /// The reply's JSON does not match the schema
+    #[error("{0}")]
+    Schema(SchemaViolation),
+}
+
+/// System prompt text asking for a reply matching `schema`
+#[must_use]
+pub fn schema_instruction(schema: &serde_json::Value) -> String {
+    format!(
+        "Reply with a single JSON value that matches this JSON Schema, and nothing else: \
+         no prose and no code fences.\n\n{schema}"
+    )
+}
+
+/// Follow-up message asking the model to fix a reply that failed with `error`
+#[must_use]
+pub fn repair_prompt(error: &ReplyError) -> String {
+    format!(
+        "That reply is not valid for the schema: {error}. \
+         Reply again with only the corrected JSON value."
+    )
+}
+
+/// Parse the JSON value in a reply, check it against `schema`, and
+/// deserialize it as `T`
+///
+/// Tolerates surrounding prose or code fences.
+///
+/// # Errors
+///
+/// Returns the parse error, the schema violation naming the offending path,
+/// or the deserialization error naming the offending field.
+pub fn parse_structured<T: DeserializeOwned>(
+    text: &str,
+    schema: &serde_json::Value,
+) -> Result<T, ReplyError> {
+    let value: serde_json::Value = serde_json::from_str(extract_json(text))?;
+    json_schema::validate(schema, &value).map_err(ReplyError::Schema)?;
+    Ok(serde_json::from_value(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, serde::Deserialize, PartialEq)]
+    struct Todo {
+        title: String,
+        done: bool,
+    }
+
+    fn schema() -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "title": { "type": "string", "minLength": 1 },
+                "done": { "type": "boolean" }
+            },
+            "required": ["title", "done"]
+        })
+    }
+
+    #[test]
+    fn test_parse_structured() {
+        let fenced = "```json\n{\"title\": \"Ship\", \"done\": true}\n```";
+        assert_eq!(
+            parse_structured::<Todo>(fenced, &schema()).unwrap(),
+            Todo {
+                title: "Ship".to_string(),
+                done: true,
+            }
+        );
+
+        let error =
+            parse_structured::<Todo>("{\"title\": \"\", \"done\": true}", &schema()).unwrap_err();
+        assert!(matches!(error, ReplyError::Schema(_)));
+        assert!(repair_prompt(&error).contains("/title"));
+
+        let error = parse_structured::<Todo>("not json", &schema()).unwrap_err();
+        assert!(matches!(error, ReplyError::Parse(_)));
+    }
+}
@@ -0,0 +1,747 @@
+//! Microsoft Teams channel adapter
+//!
+//! Speaks the Bot Framework REST protocol: inbound [`Activity`] payloads are
+//! converted to [`Message`]s, responses are sent back as activities, and
+//! [`Embed`]s are rendered as Adaptive Cards. A [`ConversationReference`] is
+//! stored for every conversation so scheduled jobs can later post into it
+//! with [`TeamsAdapter::send_proactive`]. Outbound activities can be paced
+//! per conversation with a [`RateShape`].
+//!
+//! Validating the Bot Framework JWT on inbound requests is the job of the
+//! HTTP layer that receives them, before activities reach the adapter.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+    bot::Bot,
+    degraded::TENANT_METADATA_KEY,
+    error::Error,
+    message::{
+        Attachment, Embed, Message, MessageType, Response, Suggestion, SuggestionAction,
+        CHANNEL_METADATA_KEY,
+    },
+    shaping::{RateShape, RateShaper},
+};
+
+/// Content type of Adaptive Card attachments
+pub const ADAPTIVE_CARD_CONTENT_TYPE: &str = "application/vnd.microsoft.card.adaptive";
+
+/// Message metadata key listing users mentioned in the message, other than the bot
+pub const MENTIONS_METADATA_KEY: &str = "mentions";
+
+/// Adaptive Card schema version emitted by [`render_adaptive_card`]
+const ADAPTIVE_CARD_VERSION: &str = "1.4";
+
+/// A user or bot in a conversation
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelAccount {
+    /// Channel-specific ID
+    pub id: String,
+    /// Display name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Azure AD object ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aad_object_id: Option<String>,
+}
+
+/// The conversation an activity belongs to
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationAccount {
+    /// Conversation ID
+    pub id: String,
+    /// `personal`, `groupChat`, or `channel`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversation_type: Option<String>,
+    /// Azure AD tenant
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+}
+
+/// An entity attached to an activity, such as a mention
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entity {
+    /// Entity type, e.g. `mention`
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Mentioned account, for mentions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mentioned: Option<ChannelAccount>,
+    /// Mention markup in the text, e.g. `<at>Ana</at>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+impl Entity {
+    /// A mention of `account`, matching `<at>name</at>` in the text
+    #[must_use]
+    pub fn mention(account: &ChannelAccount) -> Self {
+        let name = account.name.clone().unwrap_or_else(|| account.id.clone());
+        Self {
+            kind: "mention".to_string(),
+            mentioned: Some(account.clone()),
+            text: Some(format!("<at>{name}</at>")),
+        }
+    }
+}
+
+/// A file or card attached to an activity
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityAttachment {
+    /// MIME type or card content type
+    pub content_type: String,
+    /// Download URL, for files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_url: Option<String>,
+    /// Inline content, for cards
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<serde_json::Value>,
+    /// Filename
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// A Bot Framework activity
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Activity {
+    /// Activity type, e.g. `message` or `conversationUpdate`
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Activity ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// When the activity was sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Endpoint replies are sent to
+    #[serde(default)]
+    pub service_url: String,
+    /// Channel, `msteams` for Teams
+    #[serde(default)]
+    pub channel_id: String,
+    /// Sender
+    #[serde(default)]
+    pub from: ChannelAccount,
+    /// Conversation
+    #[serde(default)]
+    pub conversation: ConversationAccount,
+    /// Receiver
+    #[serde(default)]
+    pub recipient: ChannelAccount,
+    /// Message text, possibly containing `<at>` mention markup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// `plain`, `markdown`, or `xml`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_format: Option<String>,
+    /// Entities such as mentions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entities: Vec<Entity>,
+    /// Files and cards
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<ActivityAttachment>,
+    /// Activity this one replies to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to_id: Option<String>,
+}
+
+/// What is needed to post into a conversation without an inbound activity
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationReference {
+    /// Endpoint for the conversation
+    pub service_url: String,
+    /// Channel, `msteams` for Teams
+    pub channel_id: String,
+    /// Conversation
+    pub conversation: ConversationAccount,
+    /// The bot's account
+    pub bot: ChannelAccount,
+    /// The last user who wrote
+    pub user: ChannelAccount,
+}
+
+impl ConversationReference {
+    /// The reference for the conversation an inbound activity arrived in
+    #[must_use]
+    pub fn from_activity(activity: &Activity) -> Self {
+        Self {
+            service_url: activity.service_url.clone(),
+            channel_id: activity.channel_id.clone(),
+            conversation: activity.conversation.clone(),
+            bot: activity.recipient.clone(),
+            user: activity.from.clone(),
+        }
+    }
+}
+
+/// Storage for conversation references, keyed by bot conversation ID
+#[async_trait]
+pub trait ConversationReferenceStore: Send + Sync {
+    /// Insert or replace a reference
+    async fn save(&self, conversation_id: &str, reference: &ConversationReference) -> Result<()>;
+
+    /// Load the reference for a conversation
+    async fn load(&self, conversation_id: &str) -> Result<Option<ConversationReference>>;
+}
+
+/// In-memory conversation reference store
+#[derive(Debug, Default)]
+pub struct MemoryConversationReferenceStore {
+    references: DashMap<String, ConversationReference>,
+}
+
+impl MemoryConversationReferenceStore {
+    /// Create an empty store
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ConversationReferenceStore for MemoryConversationReferenceStore {
+    async fn save(&self, conversation_id: &str, reference: &ConversationReference) -> Result<()> {
+        self.references
+            .insert(conversation_id.to_string(), reference.clone());
+        Ok(())
+    }
+
+    async fn load(&self, conversation_id: &str) -> Result<Option<ConversationReference>> {
+        Ok(self
+            .references
+            .get(conversation_id)
+            .map(|entry| entry.clone()))
+    }
+}
+
+/// Posts activities to the Bot Framework connector
+#[async_trait]
+pub trait TeamsConnector: Send + Sync {
+    /// Send an activity into a conversation
+    async fn send(
+        &self,
+        service_url: &str,
+        conversation_id: &str,
+        activity: &Activity,
+    ) -> Result<()>;
+}
+
+/// Routes Teams activities through a [`Bot`]
+pub struct TeamsAdapter {
+    bot: Bot,
+    connector: Arc<dyn TeamsConnector>,
+    references: Arc<dyn ConversationReferenceStore>,
+    shaper: Option<RateShaper>,
+}
+
+impl TeamsAdapter {
+    /// Create an adapter with an in-memory reference store
+    #[must_use]
+    pub fn new(bot: Bot, connector: Arc<dyn TeamsConnector>) -> Self {
+        Self {
+            bot,
+            connector,
+            references: Arc::new(MemoryConversationReferenceStore::new()),
+            shaper: None,
+        }
+    }
+
+    /// Keep conversation references in the given store
+    #[must_use]
+    pub fn with_reference_store(mut self, references: Arc<dyn ConversationReferenceStore>) -> Self {
+        self.references = references;
+        self
+    }
+
+    /// Pace outbound activities to `shape`, queueing per conversation
+    ///
+    /// The connector cannot edit activities, so the coalesce window is unused.
+    #[must_use]
+    pub fn with_rate_shape(mut self, shape: RateShape) -> Self {
+        self.shaper = Some(RateShaper::new(shape));
+        self
+    }
+
+    /// Bot conversation ID for a Teams conversation
+    #[must_use]
+    pub fn conversation_id(conversation: &ConversationAccount) -> String {
+        format!("teams:{}", conversation.id)
+    }
+
+    /// Convert a `message` activity into a bot message
+    ///
+    /// The bot's own `<at>` mention is stripped from the text; other
+    /// mentions are listed under [`MENTIONS_METADATA_KEY`]. Returns `None`
+    /// for other activity types.
+    #[must_use]
+    pub fn to_message(activity: &Activity) -> Option<Message> {
+        if activity.kind != "message" {
+            return None;
+        }
+
+        let mut text = activity.text.clone().unwrap_or_default();
+        let mut mentions = Vec::new();
+        for entity in activity.entities.iter().filter(|e| e.kind == "mention") {
+            let Some(mentioned) = &entity.mentioned else {
+                continue;
+            };
+            if mentioned.id == activity.recipient.id {
+                if let Some(markup) = &entity.text {
+                    text = text.replace(markup, "");
+                }
+            } else {
+                mentions.push(serde_json::json!({
+                    "id": mentioned.id,
+                    "name": mentioned.name,
+                }));
+            }
+        }
+        let text = text.trim();
+
+        let message_type = if text.starts_with('/') {
+            MessageType::Command
+        } else {
+            MessageType::Text
+        };
+        let user_id = activity
+            .from
+            .aad_object_id
+            .clone()
+            .unwrap_or_else(|| activity.from.id.clone());
+        let mut message = Message::with_type(text, message_type)
+            .with_conversation_id(Self::conversation_id(&activity.conversation))
+            .with_user_id(user_id)
+            .with_metadata(CHANNEL_METADATA_KEY, serde_json::json!("teams"));
+        if let Some(tenant) = &activity.conversation.tenant_id {
+            message = message.with_metadata(TENANT_METADATA_KEY, serde_json::json!(tenant));
+        }
+        if !mentions.is_empty() {
+            message = message.with_metadata(MENTIONS_METADATA_KEY, serde_json::json!(mentions));
+        }
+
+        for file in &activity.attachments {
+            // Teams repeats the message body as an HTML attachment
+            let Some(url) = &file.content_url else {
+                continue;
+            };
+            message = message.with_attachment(Attachment::new(
+                file.name
+                    .clone()
+                    .unwrap_or_else(|| "attachment".to_string()),
+                file.content_type.clone(),
+                0,
+                url.clone(),
+            ));
+        }
+        Some(message)
+    }
+
+    /// Build the activity that carries a response into a conversation
+    ///
+    /// In channels and group chats the reply mentions `mention`, when given.
+    #[must_use]
+    pub fn to_activity(
+        reference: &ConversationReference,
+        response: &Response,
+        mention: Option<&ChannelAccount>,
+    ) -> Activity {
+        let mut text = response.content.clone();
+        let mut entities = Vec::new();
+        let is_personal = reference.conversation.conversation_type.as_deref() == Some("personal");
+        if let Some(user) = mention.filter(|_| !is_personal) {
+            let entity = Entity::mention(user);
+            text = format!("{} {text}", entity.text.as_deref().unwrap_or_default());
+            entities.push(entity);
+        }
+
+        let attachments = response
+            .embed()
+            .map(|embed| ActivityAttachment {
+                content_type: ADAPTIVE_CARD_CONTENT_TYPE.to_string(),
+                content_url: None,
+                content: Some(render_adaptive_card(&embed, &response.suggestions)),
+                name: None,
+            })
+            .into_iter()
+            .collect();
+
+        Activity {
+            kind: "message".to_string(),
+            service_url: reference.service_url.clone(),
+            channel_id: reference.channel_id.clone(),
+            from: reference.bot.clone(),
+            conversation: reference.conversation.clone(),
+            recipient: reference.user.clone(),
+            text: Some(text),
+            text_format: Some("markdown".to_string()),
+            entities,
+            attachments,
+            ..Activity::default()
+        }
+    }
+
+    /// Process an inbound activity and send the reply
+    ///
+    /// Returns the reply, or `None` for activities that are not messages.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the reference cannot be stored, processing
+    /// fails, or the reply cannot be sent.
+    pub async fn handle(&self, activity: &Activity) -> Result<Option<Activity>> {
+        let reference = ConversationReference::from_activity(activity);
+        let conversation_id = Self::conversation_id(&activity.conversation);
+        self.references.save(&conversation_id, &reference).await?;
+
+        let Some(message) = Self::to_message(activity) else {
+            debug!("Ignoring {} activity", activity.kind);
+            return Ok(None);
+        };
+
+        let response = self.bot.process(message).await?;
+        let mut reply = Self::to_activity(&reference, &response, Some(&activity.from));
+        reply.reply_to_id.clone_from(&activity.id);
+        self.send(&reference, &reply).await?;
+        Ok(Some(reply))
+    }
+
+    /// Post a response into a conversation the bot has seen before
+    ///
+    /// Used for messages that are not replies, such as scheduled messages
+    /// and completed background jobs.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if no reference is stored for the
+    /// conversation, or an error if sending fails.
+    pub async fn send_proactive(&self, conversation_id: &str, response: &Response) -> Result<()> {
+        let reference = self
+            .references
+            .load(conversation_id)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("No Teams reference for {conversation_id}")))?;
+        let activity = Self::to_activity(&reference, response, None);
+        self.send(&reference, &activity).await
+    }
+
+    async fn send(&self, reference: &ConversationReference, activity: &Activity) -> Result<()> {
+        if let Some(shaper) = &self.shaper {
+            shaper.acquire(&reference.conversation.id).await;
+        }
+        self.connector
+            .send(&reference.service_url, &reference.conversation.id, activity)
+            .await
+    }
+}
+
+/// Render an embed and its suggestions as an Adaptive Card
+#[must_use]
+pub fn render_adaptive_card(embed: &Embed, suggestions: &[Suggestion]) -> serde_json::Value {
+    let mut body = vec![serde_json::json!({
+        "type": "TextBlock",
+        "text": embed.title,
+        "weight": "Bolder",
+        "size": "Medium",
+        "wrap": true,
+    })];
+    if let Some(description) = &embed.description {
+        body.push(serde_json::json!({
+            "type": "TextBlock",
+            "text": description,
+            "wrap": true,
+        }));
+    }
+    if !embed.fields.is_empty() {
+        let facts: Vec<_> = embed
+            .fields
+            .iter()
+            .map(|field| serde_json::json!({ "title": field.name, "value": field.value }))
+            .collect();
+        body.push(serde_json::json!({ "type": "FactSet", "facts": facts }));
+    }
+    if let Some(image_url) = &embed.image_url {
+        body.push(serde_json::json!({ "type": "Image", "url": image_url }));
+    }
+
+    let mut actions = Vec::new();
+    if let Some(url) = &embed.url {
+        actions.push(serde_json::json!({ "type": "Action.OpenUrl", "title": "Open", "url": url }));
+    }
+    for suggestion in suggestions {
+        let action = match &suggestion.action {
+            SuggestionAction::Url(url) => {
+                serde_json::json!({ "type": "Action.OpenUrl", "title": suggestion.text, "url": url })
+            }
+            SuggestionAction::Message(text) | SuggestionAction::Command(text) => {
+                serde_json::json!({
+                    "type": "Action.Submit",
+                    "title": suggestion.text,
+                    "data": { "msteams": { "type": "imBack", "value": text } },
+                })
+            }
+            SuggestionAction::Custom(data) => serde_json::json!({
+                "type": "Action.Submit",
+                "title": suggestion.text,
+                "data": data,
+            }),
+        };
+        actions.push(action);
+    }
+
+    serde_json::json!({
+        "type": "AdaptiveCard",
+        "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+        "version": ADAPTIVE_CARD_VERSION,
+        "body": body,
+        "actions": actions,
+    })
+}
+
+/// Connector that calls the Bot Framework REST API
+///
+/// Authenticates with the app's client credentials and caches the token
+/// until shortly before it expires.
+#[cfg(feature = "teams")]
+pub struct HttpTeamsConnector {
+    client: reqwest::Client,
+    app_id: String,
+    app_password: String,
+    token: parking_lot::Mutex<Option<(String, std::time::Instant)>>,
+}
+
+#[cfg(feature = "teams")]
+impl HttpTeamsConnector {
+    const TOKEN_URL: &'static str =
+        "https://login.microsoftonline.com/botframework.com/oauth2/v2.0/token";
+    const SCOPE: &'static str = "https://api.botframework.com/.default";
+
+    /// Create a connector for a Bot Framework app registration
+    #[must_use]
+    pub fn new(app_id: impl Into<String>, app_password: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            app_id: app_id.into(),
+            app_password: app_password.into(),
+            token: parking_lot::Mutex::new(None),
+        }
+    }
+
+    async fn token(&self) -> Result<String> {
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            access_token: String,
+            expires_in: u64,
+        }
+
+        if let Some((token, expires)) = self.token.lock().clone() {
+            if std::time::Instant::now() < expires {
+                return Ok(token);
+            }
+        }
+
+        let response: TokenResponse = self
+            .client
+            .post(Self::TOKEN_URL)
+            .form(&[
+                ("grant_type", "client_credentials"),
+                ("client_id", self.app_id.as_str()),
+                ("client_secret", self.app_password.as_str()),
+                ("scope", Self::SCOPE),
+            ])
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| Error::Authentication(format!("Bot Framework token request failed: {e}")))?
+            .json()
+            .await
+            .map_err(|e| Error::Authentication(format!("Invalid token response: {e}")))?;
+
+        let lifetime = std::time::Duration::from_secs(response.expires_in.saturating_sub(60));
+        *self.token.lock() = Some((
+            response.access_token.clone(),
+            std::time::Instant::now() + lifetime,
+        ));
+        Ok(response.access_token)
+    }
+}
+
+#[cfg(feature = "teams")]
+#[async_trait]
+impl TeamsConnector for HttpTeamsConnector {
+    async fn send(
+        &self,
+        service_url: &str,
+        conversation_id: &str,
+        activity: &Activity,
+    ) -> Result<()> {
+        let token = self.token().await?;
+        let url = format!(
+            "{}/v3/conversations/{conversation_id}/activities",
+            service_url.trim_end_matches('/')
+        );
+        self.client
+            .post(url)
+            .bearer_auth(token)
+            .json(activity)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| Error::Network(format!("Bot Framework send failed: {e}")))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BotConfig;
+    use parking_lot::Mutex;
+
+    #[derive(Default)]
+    struct RecordingConnector {
+        sent: Mutex<Vec<(String, Activity)>>,
+    }
+
+    #[async_trait]
+    impl TeamsConnector for RecordingConnector {
+        async fn send(
+            &self,
+            _service_url: &str,
+            conversation_id: &str,
+            activity: &Activity,
+        ) -> Result<()> {
+            self.sent
+                .lock()
+                .push((conversation_id.to_string(), activity.clone()));
+            Ok(())
+        }
+    }
+
+    fn inbound() -> Activity {
+        serde_json::from_value(serde_json::json!({
+            "type": "message",
+            "id": "act-1",
+            "serviceUrl": "https://smba.trafficmanager.net/emea/",
+            "channelId": "msteams",
+            "from": { "id": "29:ana", "name": "Ana", "aadObjectId": "aad-ana" },
+            "conversation": {
+                "id": "19:general@thread.tacv2",
+                "conversationType": "channel",
+                "tenantId": "contoso",
+            },
+            "recipient": { "id": "28:bot", "name": "Helper" },
+            "text": "<at>Helper</at> ask <at>Ben</at> about the release",
+            "entities": [
+                { "type": "mention", "text": "<at>Helper</at>", "mentioned": { "id": "28:bot", "name": "Helper" } },
+                { "type": "mention", "text": "<at>Ben</at>", "mentioned": { "id": "29:ben", "name": "Ben" } },
+            ],
+            "attachments": [
+                { "contentType": "text/html", "content": "<p>...</p>" },
+                { "contentType": "application/pdf", "contentUrl": "https://files/notes.pdf", "name": "notes.pdf" },
+            ],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_activity_to_message() {
+        let message = TeamsAdapter::to_message(&inbound()).unwrap();
+        assert_eq!(message.content, "ask <at>Ben</at> about the release");
+        assert_eq!(message.conversation_id, "teams:19:general@thread.tacv2");
+        assert_eq!(message.user_id, "aad-ana");
+        assert_eq!(message.metadata[TENANT_METADATA_KEY], "contoso");
+        assert_eq!(message.metadata[MENTIONS_METADATA_KEY][0]["id"], "29:ben");
+        assert_eq!(message.attachments.len(), 1);
+        assert_eq!(message.attachments[0].filename, "notes.pdf");
+
+        let update = Activity {
+            kind: "conversationUpdate".to_string(),
+            ..inbound()
+        };
+        assert!(TeamsAdapter::to_message(&update).is_none());
+    }
+
+    #[test]
+    fn test_embed_renders_adaptive_card() {
+        let response = Response::text("conv", "Release 1.2 is ready")
+            .with_embed(
+                Embed::new("Release 1.2")
+                    .with_description("All checks passed")
+                    .with_field("Build", "#481")
+                    .with_url("https://ci/481"),
+            )
+            .with_suggestion(Suggestion::new(
+                "Deploy",
+                SuggestionAction::Command("/deploy 1.2".into()),
+            ));
+        let reference = ConversationReference::from_activity(&inbound());
+        let activity = TeamsAdapter::to_activity(&reference, &response, Some(&reference.user));
+
+        assert_eq!(
+            activity.text.as_deref(),
+            Some("<at>Ana</at> Release 1.2 is ready")
+        );
+        assert_eq!(
+            activity.entities[0].mentioned.as_ref().unwrap().id,
+            "29:ana"
+        );
+        let card = activity.attachments[0].content.as_ref().unwrap();
+        assert_eq!(
+            activity.attachments[0].content_type,
+            ADAPTIVE_CARD_CONTENT_TYPE
+        );
+        assert_eq!(card["body"][0]["text"], "Release 1.2");
+        assert_eq!(card["body"][2]["facts"][0]["value"], "#481");
+        assert_eq!(card["actions"][0]["type"], "Action.OpenUrl");
+        assert_eq!(
+            card["actions"][1]["data"]["msteams"]["value"],
+            "/deploy 1.2"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reply_and_proactive_message() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        let connector = Arc::new(RecordingConnector::default());
+        let adapter = TeamsAdapter::new(bot, connector.clone());
+
+        let reply = adapter.handle(&inbound()).await.unwrap().unwrap();
+        assert_eq!(reply.reply_to_id.as_deref(), Some("act-1"));
+        assert_eq!(reply.recipient.id, "29:ana");
+
+        adapter
+            .send_proactive(
+                "teams:19:general@thread.tacv2",
+                &Response::text("teams:19:general@thread.tacv2", "Daily digest"),
+            )
+            .await
+            .unwrap();
+        let sent = connector.sent.lock().clone();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[1].0, "19:general@thread.tacv2");
+        assert_eq!(sent[1].1.text.as_deref(), Some("Daily digest"));
+        assert!(sent[1].1.reply_to_id.is_none());
+
+        let missing = adapter
+            .send_proactive("teams:unknown", &Response::text("teams:unknown", "hi"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            missing.downcast_ref::<Error>(),
+            Some(Error::NotFound(_))
+        ));
+    }
+}
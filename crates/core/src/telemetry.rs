@@ -0,0 +1,334 @@
+//! Opt-in anonymized usage telemetry
+//!
+//! Telemetry is off unless [`TelemetryConfig::enabled`] is set, and starts
+//! off whenever the `DO_NOT_TRACK` environment variable is set to anything
+//! but `0`; [`Telemetry::set_enabled`] toggles it at runtime.
+//!
+//! Only counters are collected: how often each [`Feature`] is used and how
+//! often each [`Error::error_code`] occurs. Both are keyed by names fixed in
+//! this crate, so message content, user and conversation IDs, and
+//! configuration values cannot reach a report. Reports carry a random
+//! per-process session ID that is not derived from the host or user.
+//!
+//! [`HttpTelemetrySink`] is available with the `telemetry` feature.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::{config::TelemetryConfig, error::Error};
+
+/// Version of the [`TelemetryReport`] layout
+pub const TELEMETRY_SCHEMA_VERSION: u32 = 1;
+
+/// Built-in features whose usage is counted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// A message processed by the bot
+    Process,
+    /// A reply streamed by the bot
+    Stream,
+    /// A background job submitted
+    Job,
+    /// A plugin tool invoked
+    Tool,
+    /// A branch comparison run
+    BranchCompare,
+    /// A webhook registered
+    Webhook,
+    /// A preflight check run
+    Preflight,
+    /// A debug bundle generated
+    DebugBundle,
+}
+
+/// Aggregate usage statistics for one reporting period
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TelemetryReport {
+    /// Report layout version
+    pub schema_version: u32,
+    /// Random ID for this process, regenerated on every start
+    pub session_id: Uuid,
+    /// Crate version
+    pub version: String,
+    /// Operating system family, e.g. `linux`
+    pub os: String,
+    /// CPU architecture, e.g. `x86_64`
+    pub arch: String,
+    /// Start of the reporting period
+    pub period_start: DateTime<Utc>,
+    /// End of the reporting period
+    pub period_end: DateTime<Utc>,
+    /// Uses per feature
+    pub features: BTreeMap<Feature, u64>,
+    /// Occurrences per error code
+    pub errors: BTreeMap<String, u64>,
+}
+
+impl TelemetryReport {
+    /// Whether the report holds no usage
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty() && self.errors.is_empty()
+    }
+}
+
+/// Destination for telemetry reports
+#[async_trait]
+pub trait TelemetrySink: Send + Sync {
+    /// Deliver a report
+    async fn send(&self, report: &TelemetryReport) -> Result<()>;
+}
+
+/// Collects anonymized usage counters
+#[derive(Debug)]
+pub struct Telemetry {
+    enabled: AtomicBool,
+    session_id: Uuid,
+    period_start: Mutex<DateTime<Utc>>,
+    features: DashMap<Feature, u64>,
+    errors: DashMap<&'static str, u64>,
+}
+
+impl Telemetry {
+    /// Create the collector, enabled only if configured and not vetoed by `DO_NOT_TRACK`
+    #[must_use]
+    pub fn new(config: &TelemetryConfig) -> Self {
+        let do_not_track = std::env::var("DO_NOT_TRACK").is_ok_and(|v| !v.is_empty() && v != "0");
+        let enabled = config.enabled && !do_not_track;
+        if enabled {
+            info!("Anonymized usage telemetry is enabled");
+        }
+        Self {
+            enabled: AtomicBool::new(enabled),
+            session_id: Uuid::new_v4(),
+            period_start: Mutex::new(Utc::now()),
+            features: DashMap::new(),
+            errors: DashMap::new(),
+        }
+    }
+
+    /// A collector that records nothing until enabled
+    #[must_use]
+    pub fn disabled() -> Self {
+        Self::new(&TelemetryConfig::default())
+    }
+
+    /// Whether usage is being recorded
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Turn recording on or off; turning it off discards unsent counters
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.reset();
+        }
+    }
+
+    /// Count a use of `feature`
+    pub fn record_feature(&self, feature: Feature) {
+        if self.is_enabled() {
+            *self.features.entry(feature).or_insert(0) += 1;
+        }
+    }
+
+    /// Count an error by the code of the first [`Error`] in its chain
+    pub fn record_error(&self, error: &anyhow::Error) {
+        if !self.is_enabled() {
+            return;
+        }
+        let code = error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<Error>())
+            .map_or("other", Error::error_code);
+        *self.errors.entry(code).or_insert(0) += 1;
+    }
+
+    /// The counters recorded since the last report
+    #[must_use]
+    pub fn snapshot(&self) -> TelemetryReport {
+        TelemetryReport {
+            schema_version: TELEMETRY_SCHEMA_VERSION,
+            session_id: self.session_id,
+            version: crate::VERSION.to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            period_start: *self.period_start.lock(),
+            period_end: Utc::now(),
+            features: self
+                .features
+                .iter()
+                .map(|e| (*e.key(), *e.value()))
+                .collect(),
+            errors: self
+                .errors
+                .iter()
+                .map(|e| ((*e.key()).to_string(), *e.value()))
+                .collect(),
+        }
+    }
+
+    /// Take the current report and start a new period
+    ///
+    /// Returns `None` while disabled or when nothing was recorded.
+    #[must_use]
+    pub fn take_report(&self) -> Option<TelemetryReport> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let report = self.snapshot();
+        self.reset();
+        (!report.is_empty()).then_some(report)
+    }
+
+    /// Send the current report to `sink`
+    ///
+    /// Returns whether a report was sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sink fails; the report is dropped either way.
+    pub async fn flush(&self, sink: &dyn TelemetrySink) -> Result<bool> {
+        let Some(report) = self.take_report() else {
+            return Ok(false);
+        };
+        sink.send(&report).await?;
+        debug!("Sent telemetry report for session {}", report.session_id);
+        Ok(true)
+    }
+
+    /// Flush to `sink` every `interval` until the returned task is aborted
+    pub fn spawn_reporter(
+        self: Arc<Self>,
+        sink: Arc<dyn TelemetrySink>,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.flush(sink.as_ref()).await {
+                    warn!("Failed to send telemetry report: {}", e);
+                }
+            }
+        })
+    }
+
+    fn reset(&self) {
+        self.features.clear();
+        self.errors.clear();
+        *self.period_start.lock() = Utc::now();
+    }
+}
+
+/// Sink that POSTs reports as JSON
+#[cfg(feature = "telemetry")]
+pub struct HttpTelemetrySink {
+    client: reqwest::Client,
+    endpoint: String,
+}
+
+#[cfg(feature = "telemetry")]
+impl HttpTelemetrySink {
+    /// Create a sink posting to `endpoint`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Initialization`] if the HTTP client cannot be built.
+    pub fn new(endpoint: impl Into<String>) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("universal-bot/", env!("CARGO_PKG_VERSION")))
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| Error::Initialization(format!("Failed to build HTTP client: {e}")))?;
+        Ok(Self {
+            client,
+            endpoint: endpoint.into(),
+        })
+    }
+}
+
+#[cfg(feature = "telemetry")]
+#[async_trait]
+impl TelemetrySink for HttpTelemetrySink {
+    async fn send(&self, report: &TelemetryReport) -> Result<()> {
+        self.client
+            .post(&self.endpoint)
+            .json(report)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| Error::Network(format!("Telemetry upload failed: {e}")))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct RecordingSink(Mutex<Vec<TelemetryReport>>);
+
+    #[async_trait]
+    impl TelemetrySink for RecordingSink {
+        async fn send(&self, report: &TelemetryReport) -> Result<()> {
+            self.0.lock().push(report.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reports_only_counters_while_enabled() {
+        let telemetry = Telemetry::disabled();
+        let sink = RecordingSink(Mutex::new(Vec::new()));
+        telemetry.record_feature(Feature::Process);
+        assert!(!telemetry.flush(&sink).await.unwrap());
+
+        telemetry.set_enabled(true);
+        telemetry.record_feature(Feature::Process);
+        telemetry.record_feature(Feature::Process);
+        telemetry.record_feature(Feature::Tool);
+        telemetry.record_error(&anyhow::Error::from(Error::Validation(
+            "user@example.com is not allowed".into(),
+        )));
+        telemetry.record_error(&anyhow::anyhow!("secret message text"));
+        assert!(telemetry.flush(&sink).await.unwrap());
+        assert!(!telemetry.flush(&sink).await.unwrap());
+
+        let reports = sink.0.lock().clone();
+        let report = &reports[0];
+        assert_eq!(report.features[&Feature::Process], 2);
+        assert_eq!(report.features[&Feature::Tool], 1);
+        assert_eq!(report.errors["E002"], 1);
+        assert_eq!(report.errors["other"], 1);
+
+        let json = serde_json::to_string(report).unwrap();
+        assert!(!json.contains("example.com"));
+        assert!(!json.contains("secret"));
+    }
+
+    #[test]
+    fn test_disabling_discards_counters() {
+        let telemetry = Telemetry::disabled();
+        telemetry.set_enabled(true);
+        telemetry.record_feature(Feature::Job);
+        telemetry.set_enabled(false);
+        telemetry.set_enabled(true);
+        assert!(telemetry.take_report().is_none());
+    }
+}
@@ -0,0 +1,266 @@
+//! Conversation templates
+//!
+//! A [`ConversationTemplate`] is a starter kit for a guided experience: a
+//! system prompt, an opening greeting, prompts to suggest to the user,
+//! knowledge pinned to the top of the prompt, and the flows the
+//! conversation is bound to. Templates are configured in
+//! [`ContextConfig::templates`](crate::config::ContextConfig::templates) and
+//! instantiated with
+//! [`ContextManager::create_from_template`](crate::context::ContextManager::create_from_template).
+//!
+//! Everything besides the greeting and pinned knowledge is kept in context
+//! variables, so it survives persistence and is visible to plugins and
+//! channels.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    context::{Context, ContextMessage, MessageRole},
+    error::Error,
+    message::{Suggestion, SuggestionAction},
+};
+
+/// Context variable naming the template a conversation was created from
+pub const TEMPLATE_VARIABLE: &str = "template";
+
+/// Context variable holding a system prompt that replaces the configured one
+pub const SYSTEM_PROMPT_VARIABLE: &str = "system_prompt";
+
+/// Context variable holding the prompts to suggest to the user
+pub const SUGGESTED_PROMPTS_VARIABLE: &str = "suggested_prompts";
+
+/// Context variable holding the conversation's flow bindings
+pub const FLOWS_VARIABLE: &str = "flows";
+
+/// A predefined starting point for conversations
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConversationTemplate {
+    /// Human-readable name
+    pub name: String,
+
+    /// System prompt used instead of the configured
+    /// [`BotConfig::system_prompt`](crate::config::BotConfig::system_prompt)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+
+    /// Assistant message the conversation opens with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub greeting: Option<String>,
+
+    /// Prompts offered to the user before they type anything
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub suggested_prompts: Vec<String>,
+
+    /// Reference text pinned to the top of every prompt
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pinned_knowledge: Vec<String>,
+
+    /// Flows the conversation is bound to, by trigger name
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub flows: HashMap<String, String>,
+
+    /// Further variables set on the new context
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub variables: HashMap<String, serde_json::Value>,
+}
+
+impl ConversationTemplate {
+    /// Create an empty template
+    #[must_use]
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Set the system prompt
+    #[must_use]
+    pub fn with_system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.system_prompt = Some(prompt.into());
+        self
+    }
+
+    /// Set the greeting
+    #[must_use]
+    pub fn with_greeting(mut self, greeting: impl Into<String>) -> Self {
+        self.greeting = Some(greeting.into());
+        self
+    }
+
+    /// Add a suggested prompt
+    #[must_use]
+    pub fn with_suggested_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.suggested_prompts.push(prompt.into());
+        self
+    }
+
+    /// Add pinned knowledge
+    #[must_use]
+    pub fn with_pinned_knowledge(mut self, knowledge: impl Into<String>) -> Self {
+        self.pinned_knowledge.push(knowledge.into());
+        self
+    }
+
+    /// Bind `flow` to `trigger`
+    #[must_use]
+    pub fn with_flow(mut self, trigger: impl Into<String>, flow: impl Into<String>) -> Self {
+        self.flows.insert(trigger.into(), flow.into());
+        self
+    }
+
+    /// The suggested prompts as suggestions that send them as messages
+    #[must_use]
+    pub fn suggestions(&self) -> Vec<Suggestion> {
+        self.suggested_prompts
+            .iter()
+            .map(|prompt| Suggestion::new(prompt, SuggestionAction::Message(prompt.clone())))
+            .collect()
+    }
+
+    /// Set up `context` as a conversation started from the template `id`
+    ///
+    /// Pinned knowledge comes first in the history, then the greeting.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the pinned knowledge exceeds
+    /// `max_pinned_tokens`.
+    pub fn apply(&self, id: &str, context: &mut Context, max_pinned_tokens: usize) -> Result<()> {
+        let knowledge: Vec<ContextMessage> = self
+            .pinned_knowledge
+            .iter()
+            .map(|text| ContextMessage {
+                pinned: true,
+                ..ContextMessage::system(text.as_str())
+            })
+            .collect();
+        let knowledge_tokens: usize = knowledge.iter().map(ContextMessage::estimated_tokens).sum();
+        if context.pinned_tokens() + knowledge_tokens > max_pinned_tokens {
+            return Err(Error::InvalidInput(format!(
+                "Pinned knowledge of template {id} needs {knowledge_tokens} tokens, \
+                 over the {max_pinned_tokens} token budget"
+            ))
+            .into());
+        }
+        for message in knowledge {
+            context.token_count += message.estimated_tokens();
+            context.history.push_back(message);
+        }
+
+        if let Some(greeting) = &self.greeting {
+            let message = ContextMessage {
+                role: MessageRole::Assistant,
+                content: greeting.as_str().into(),
+                timestamp: Utc::now(),
+                message_id: Some(Uuid::new_v4()),
+                pinned: false,
+            };
+            context.token_count += message.estimated_tokens();
+            context.history.push_back(message);
+            context.metadata.message_count += 1;
+        }
+
+        context.set_variable(TEMPLATE_VARIABLE, id.into());
+        if let Some(prompt) = &self.system_prompt {
+            context.set_variable(SYSTEM_PROMPT_VARIABLE, prompt.as_str().into());
+        }
+        if !self.suggested_prompts.is_empty() {
+            context.set_variable(
+                SUGGESTED_PROMPTS_VARIABLE,
+                serde_json::to_value(&self.suggested_prompts)?,
+            );
+        }
+        if !self.flows.is_empty() {
+            context.set_variable(FLOWS_VARIABLE, serde_json::to_value(&self.flows)?);
+        }
+        for (key, value) in &self.variables {
+            context.set_variable(key.as_str(), value.clone());
+        }
+        Ok(())
+    }
+}
+
+/// The template a context was created from, if any
+#[must_use]
+pub fn template_of(context: &Context) -> Option<&str> {
+    context
+        .get_variable(TEMPLATE_VARIABLE)
+        .and_then(serde_json::Value::as_str)
+}
+
+/// The system prompt a context's template set, if any
+#[must_use]
+pub fn system_prompt_of(context: &Context) -> Option<&str> {
+    context
+        .get_variable(SYSTEM_PROMPT_VARIABLE)
+        .and_then(serde_json::Value::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn onboarding() -> ConversationTemplate {
+        ConversationTemplate::new("Onboarding")
+            .with_system_prompt("You help new customers set up their account.")
+            .with_greeting("Welcome! What would you like to set up first?")
+            .with_suggested_prompt("Connect my bank")
+            .with_suggested_prompt("Invite my team")
+            .with_pinned_knowledge("Accounts support up to 10 team members on the free plan.")
+            .with_flow("start", "onboarding-v2")
+    }
+
+    #[test]
+    fn test_apply_template() {
+        let mut context = Context::new("conv");
+        onboarding()
+            .apply("onboarding", &mut context, 1024)
+            .unwrap();
+
+        let roles: Vec<(MessageRole, bool)> = context
+            .history
+            .iter()
+            .map(|message| (message.role, message.pinned))
+            .collect();
+        assert_eq!(
+            roles,
+            [(MessageRole::System, true), (MessageRole::Assistant, false)]
+        );
+        assert_eq!(context.metadata.message_count, 1);
+        assert_eq!(template_of(&context), Some("onboarding"));
+        assert_eq!(
+            system_prompt_of(&context),
+            Some("You help new customers set up their account.")
+        );
+        assert_eq!(
+            context.get_variable(SUGGESTED_PROMPTS_VARIABLE),
+            Some(&serde_json::json!(["Connect my bank", "Invite my team"]))
+        );
+        assert_eq!(
+            context.get_variable(FLOWS_VARIABLE),
+            Some(&serde_json::json!({"start": "onboarding-v2"}))
+        );
+        assert_eq!(onboarding().suggestions().len(), 2);
+    }
+
+    #[test]
+    fn test_pinned_knowledge_respects_budget() {
+        let mut context = Context::new("conv");
+        let error = onboarding()
+            .apply("onboarding", &mut context, 4)
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::InvalidInput(_))
+        ));
+        assert!(context.history.is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+//! YAML document generation
+//!
+//! Where a conversation [`template`](crate::template) shapes a chat, a
+//! [`YamlTemplate`] describes a document for the model to write, in the
+//! PDMT style: a `{{name}}` prompt, a YAML skeleton the output must follow,
+//! and sampling at temperature 0 so the same inputs give the same document.
+//! [`TemplateGenerator`] renders the prompt, reads the YAML out of the reply,
+//! and deserializes and validates it. A reply that fails is sent back with
+//! the error and regenerated, up to a retry limit.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use validator::Validate;
+
+use crate::{
+    context::MessageRole,
+    error::Error,
+    message::TokenUsage,
+    prompt,
+    provider::{Provider, ProviderMessage, ProviderRequest},
+};
+
+/// Regenerations after a failed reply unless set with [`TemplateGenerator::with_max_retries`]
+pub const DEFAULT_TEMPLATE_RETRIES: usize = 2;
+
+/// Output limit unless set with [`TemplateGenerator::with_max_tokens`]
+pub const DEFAULT_TEMPLATE_MAX_TOKENS: usize = 2048;
+
+/// A YAML document for the model to write
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct YamlTemplate {
+    /// Template name, for logs
+    pub name: String,
+    /// What to write, with `{{name}}` placeholders
+    pub prompt: String,
+    /// Example YAML showing the structure the output must follow
+    pub schema: String,
+}
+
+impl YamlTemplate {
+    /// Create a template
+    #[must_use]
+    pub fn new(
+        name: impl Into<String>,
+        prompt: impl Into<String>,
+        schema: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            prompt: prompt.into(),
+            schema: schema.into(),
+        }
+    }
+
+    /// The full prompt for `variables`, ending with the required structure
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if a placeholder has no variable.
+    pub fn render(&self, variables: &HashMap<String, serde_json::Value>) -> Result<String> {
+        let task = prompt::interpolate(&self.prompt, variables)?;
+        Ok(format!(
+            "{task}\n\nReturn only valid YAML, with no explanations or code fences, \
+             following this structure exactly:\n\n{}",
+            self.schema.trim()
+        ))
+    }
+}
+
+/// A document parsed from the model's reply
+#[derive(Debug, Clone)]
+pub struct GeneratedTemplate<T> {
+    /// The parsed and validated document
+    pub value: T,
+    /// The YAML it was parsed from
+    pub yaml: String,
+    /// Replies generated, including the first
+    pub attempts: usize,
+    /// Tokens used across all attempts
+    pub usage: TokenUsage,
+}
+
+/// Generates documents from [`YamlTemplate`]s with a provider
+#[derive(Clone)]
+pub struct TemplateGenerator {
+    provider: Arc<dyn Provider>,
+    model: String,
+    max_tokens: usize,
+    max_retries: usize,
+}
+
+impl std::fmt::Debug for TemplateGenerator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TemplateGenerator")
+            .field("provider", &self.provider.name())
+            .field("model", &self.model)
+            .field("max_tokens", &self.max_tokens)
+            .field("max_retries", &self.max_retries)
+            .finish()
+    }
+}
+
+impl TemplateGenerator {
+    /// Generate with `model` through `provider`
+    #[must_use]
+    pub fn new(provider: Arc<dyn Provider>, model: impl Into<String>) -> Self {
+        Self {
+            provider,
+            model: model.into(),
+            max_tokens: DEFAULT_TEMPLATE_MAX_TOKENS,
+            max_retries: DEFAULT_TEMPLATE_RETRIES,
+        }
+    }
+
+    /// Generate at most `max_tokens` per reply
+    #[must_use]
+    pub const fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// Regenerate at most `max_retries` times after a failed reply
+    #[must_use]
+    pub const fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Generate `template` with `variables` and parse it as `T`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the prompt cannot be rendered or the provider
+    /// fails, or [`Error::Validation`] if no reply parses and validates.
+    pub async fn generate<T: DeserializeOwned + Validate>(
+        &self,
+        template: &YamlTemplate,
+        variables: &HashMap<String, serde_json::Value>,
+    ) -> Result<GeneratedTemplate<T>> {
+        let mut request = ProviderRequest {
+            model: self.model.clone(),
+            system_prompt: None,
+            messages: vec![ProviderMessage::new(
+                MessageRole::User,
+                template.render(variables)?,
+            )],
+            temperature: 0.0,
+            max_tokens: self.max_tokens,
+            provider: None,
+        };
+        let mut usage = TokenUsage::new(0, 0, &self.model);
+        let mut last_error = String::new();
+
+        for attempt in 1..=self.max_retries + 1 {
+            let reply = self
+                .provider
+                .generate(request.clone())
+                .await
+                .with_context(|| format!("Failed to generate template {}", template.name))?;
+            usage.input_tokens += reply.usage.input_tokens;
+            usage.output_tokens += reply.usage.output_tokens;
+            usage.total_tokens += reply.usage.total_tokens;
+            usage.add_costs(&reply.usage.cost);
+            let yaml = extract_yaml(&reply.content);
+            match parse::<T>(yaml) {
+                Ok(value) => {
+                    return Ok(GeneratedTemplate {
+                        value,
+                        yaml: yaml.to_string(),
+                        attempts: attempt,
+                        usage,
+                    })
+                }
+                Err(e) => {
+                    debug!("Template {} attempt {attempt} failed: {e}", template.name);
+                    request
+                        .messages
+                        .push(ProviderMessage::new(MessageRole::Assistant, reply.content));
+                    request.messages.push(ProviderMessage::new(
+                        MessageRole::User,
+                        format!(
+                            "That YAML is invalid: {e}. Return the corrected YAML only, \
+                             following the same structure."
+                        ),
+                    ));
+                    last_error = e;
+                }
+            }
+        }
+
+        Err(Error::Validation(format!(
+            "Template {} failed after {} attempts: {last_error}",
+            template.name,
+            self.max_retries + 1
+        ))
+        .into())
+    }
+}
+
+/// The YAML in a reply, without a surrounding code fence
+fn extract_yaml(text: &str) -> &str {
+    let text = text.trim();
+    let Some(start) = text.find("```") else {
+        return text;
+    };
+    let body = &text[start + 3..];
+    // Skip the fence's language tag, e.g. `yaml`
+    let body = body.find('\n').map_or(body, |newline| &body[newline + 1..]);
+    body.find("```").map_or(body, |end| &body[..end]).trim()
+}
+
+fn parse<T: DeserializeOwned + Validate>(yaml: &str) -> std::result::Result<T, String> {
+    let value: T = serde_yaml::from_str(yaml).map_err(|e| e.to_string())?;
+    value.validate().map_err(|e| e.to_string())?;
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::provider::ProviderResponse;
+
+    #[derive(Debug, Deserialize, Validate)]
+    struct TodoList {
+        #[validate(length(min = 1))]
+        todos: Vec<Todo>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Todo {
+        content: String,
+        estimated_hours: f32,
+    }
+
+    /// Replies with each of its answers in turn
+    struct Scripted {
+        replies: Vec<&'static str>,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Provider for Scripted {
+        fn name(&self) -> &str {
+            "scripted"
+        }
+
+        async fn generate(&self, request: ProviderRequest) -> Result<ProviderResponse> {
+            assert!(request.temperature.abs() < f32::EPSILON);
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(ProviderResponse {
+                content: self.replies[call.min(self.replies.len() - 1)].to_string(),
+                usage: TokenUsage::new(100, 50, request.model),
+                finish_reason: None,
+            })
+        }
+    }
+
+    fn template() -> YamlTemplate {
+        YamlTemplate::new(
+            "todos",
+            "Plan the project: {{ project }}",
+            "todos:\n  - content: \"Task\"\n    estimated_hours: 1.0\n",
+        )
+    }
+
+    fn variables() -> HashMap<String, serde_json::Value> {
+        HashMap::from([("project".to_string(), serde_json::json!("JWT auth API"))])
+    }
+
+    #[tokio::test]
+    async fn test_invalid_replies_are_regenerated() {
+        let provider = Arc::new(Scripted {
+            replies: vec![
+                "todos: []",
+                "```yaml\ntodos:\n  - content: Issue tokens\n    estimated_hours: 3\n```",
+            ],
+            calls: AtomicUsize::new(0),
+        });
+        let generator = TemplateGenerator::new(provider, "anthropic.claude-haiku");
+
+        let generated = generator
+            .generate::<TodoList>(&template(), &variables())
+            .await
+            .unwrap();
+
+        assert_eq!(generated.attempts, 2);
+        assert_eq!(generated.value.todos[0].content, "Issue tokens");
+        assert!((generated.value.todos[0].estimated_hours - 3.0).abs() < f32::EPSILON);
+        assert_eq!(generated.usage.total_tokens, 300);
+    }
+
+    #[tokio::test]
+    async fn test_generation_gives_up_after_retries() {
+        let provider = Arc::new(Scripted {
+            replies: vec!["Sure! Here is your plan."],
+            calls: AtomicUsize::new(0),
+        });
+        let generator =
+            TemplateGenerator::new(provider.clone(), "anthropic.claude-haiku").with_max_retries(1);
+
+        let error = generator
+            .generate::<TodoList>(&template(), &variables())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::Validation(_))
+        ));
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 2);
+        assert!(template().render(&HashMap::new()).is_err());
+    }
+}
@@ -0,0 +1,816 @@
+//! Ticket creation tools for Jira and ServiceNow
+//!
+//! [`TicketPlugin`] exposes `create_ticket` and `update_ticket` tools backed
+//! by a [`TicketBackend`]. Generic ticket fields are renamed to the
+//! backend's fields through [`TicketConfig::field_map`], and new tickets are
+//! filed in the project whose [`IntentRoute`] matches the request's intent.
+//!
+//! Creation goes through an [`ApprovalGate`]. Held requests are kept until
+//! [`TicketPlugin::approve`] or [`TicketPlugin::reject`] is called. Tickets
+//! created while serving a conversation are added to the next response for
+//! it under [`TICKETS_METADATA_KEY`] during plugin post-processing.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    approval::{ApprovalDecision, ApprovalGate, ApprovalRequest, AutoApprove},
+    error::Error,
+    message::Response,
+    plugin::{
+        Capability, CapabilityType, Permission, Plugin, PluginRequest, PluginResponse, RequestType,
+    },
+};
+
+/// Response metadata key listing tickets created or updated for the response
+pub const TICKETS_METADATA_KEY: &str = "tickets";
+
+/// Plugin request metadata key naming the conversation a tool call serves
+pub const CONVERSATION_METADATA_KEY: &str = "conversation_id";
+
+/// A ticket in the backend
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TicketRef {
+    /// Backend ID, e.g. a Jira issue ID or ServiceNow `sys_id`
+    pub id: String,
+    /// Human-facing key, e.g. `OPS-42` or `INC0010001`
+    pub key: String,
+    /// Link to the ticket
+    pub url: String,
+}
+
+/// Where a new ticket is filed
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TicketRoute {
+    /// Jira project key or ServiceNow assignment group
+    pub project: String,
+    /// Jira issue type or ServiceNow table; `None` uses the backend default
+    #[serde(default)]
+    pub issue_type: Option<String>,
+}
+
+/// Routes tickets for an intent to a project
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntentRoute {
+    /// Intent name, e.g. `access_request`
+    pub intent: String,
+    /// Words in the summary that imply the intent when none is given
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// Destination
+    #[serde(flatten)]
+    pub route: TicketRoute,
+}
+
+/// Routing and field mapping for a ticket backend
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TicketConfig {
+    /// Destination when no intent route matches
+    pub default_route: TicketRoute,
+    /// Intent routes, tried in order
+    #[serde(default)]
+    pub routes: Vec<IntentRoute>,
+    /// Generic field name to backend field name; unmapped fields pass through
+    #[serde(default)]
+    pub field_map: HashMap<String, String>,
+}
+
+impl TicketConfig {
+    /// Jira defaults, filing tasks in `project`
+    #[must_use]
+    pub fn jira(project: impl Into<String>) -> Self {
+        Self {
+            default_route: TicketRoute {
+                project: project.into(),
+                issue_type: Some("Task".to_string()),
+            },
+            routes: Vec::new(),
+            field_map: HashMap::new(),
+        }
+    }
+
+    /// ServiceNow defaults, filing incidents for `assignment_group`
+    #[must_use]
+    pub fn servicenow(assignment_group: impl Into<String>) -> Self {
+        Self {
+            default_route: TicketRoute {
+                project: assignment_group.into(),
+                issue_type: Some("incident".to_string()),
+            },
+            routes: Vec::new(),
+            field_map: HashMap::from([
+                ("summary".to_string(), "short_description".to_string()),
+                ("priority".to_string(), "urgency".to_string()),
+            ]),
+        }
+    }
+
+    /// Add an intent route
+    #[must_use]
+    pub fn with_route(mut self, route: IntentRoute) -> Self {
+        self.routes.push(route);
+        self
+    }
+
+    /// Map a generic field to a backend field
+    #[must_use]
+    pub fn with_field(mut self, generic: impl Into<String>, backend: impl Into<String>) -> Self {
+        self.field_map.insert(generic.into(), backend.into());
+        self
+    }
+
+    /// The destination for a ticket
+    ///
+    /// An explicit intent selects its route; otherwise the first route with
+    /// a keyword in the summary wins.
+    #[must_use]
+    pub fn route_for(&self, intent: Option<&str>, summary: &str) -> &TicketRoute {
+        let summary = summary.to_lowercase();
+        let matched = intent.map_or_else(
+            || {
+                self.routes.iter().find(|r| {
+                    r.keywords
+                        .iter()
+                        .any(|keyword| summary.contains(&keyword.to_lowercase()))
+                })
+            },
+            |intent| {
+                self.routes
+                    .iter()
+                    .find(|r| r.intent.eq_ignore_ascii_case(intent))
+            },
+        );
+        matched.map_or(&self.default_route, |r| &r.route)
+    }
+
+    /// Rename generic fields to backend fields
+    #[must_use]
+    pub fn map_fields(
+        &self,
+        fields: serde_json::Map<String, serde_json::Value>,
+    ) -> serde_json::Map<String, serde_json::Value> {
+        fields
+            .into_iter()
+            .map(|(name, value)| match self.field_map.get(&name) {
+                Some(mapped) => (mapped.clone(), value),
+                None => (name, value),
+            })
+            .collect()
+    }
+}
+
+/// Creates and updates tickets in an external system
+#[async_trait]
+pub trait TicketBackend: Send + Sync {
+    /// Backend name, used as the plugin name
+    fn name(&self) -> &str;
+
+    /// File a ticket
+    async fn create(
+        &self,
+        route: &TicketRoute,
+        fields: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<TicketRef>;
+
+    /// Change fields of an existing ticket, by key
+    async fn update(
+        &self,
+        key: &str,
+        fields: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<TicketRef>;
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "tool", rename_all = "snake_case")]
+enum ToolCall {
+    CreateTicket {
+        summary: String,
+        #[serde(default)]
+        description: Option<String>,
+        #[serde(default)]
+        intent: Option<String>,
+        #[serde(default)]
+        fields: serde_json::Map<String, serde_json::Value>,
+    },
+    UpdateTicket {
+        key: String,
+        fields: serde_json::Map<String, serde_json::Value>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct HeldTicket {
+    conversation_id: Option<String>,
+    route: TicketRoute,
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Tool plugin that files tickets through a [`TicketBackend`]
+///
+/// Invoked with [`RequestType::InvokeTool`] and data of the form
+/// `{"tool": "create_ticket", "summary": "...", "description": "...",
+/// "intent": "access_request", "fields": {"priority": "High"}}` or
+/// `{"tool": "update_ticket", "key": "OPS-42", "fields": {...}}`.
+pub struct TicketPlugin {
+    backend: Arc<dyn TicketBackend>,
+    config: TicketConfig,
+    gate: Arc<dyn ApprovalGate>,
+    held: DashMap<Uuid, HeldTicket>,
+    created: DashMap<String, Vec<TicketRef>>,
+}
+
+impl TicketPlugin {
+    /// Create a plugin that files tickets without approval
+    #[must_use]
+    pub fn new(backend: Arc<dyn TicketBackend>, config: TicketConfig) -> Self {
+        Self {
+            backend,
+            config,
+            gate: Arc::new(AutoApprove),
+            held: DashMap::new(),
+            created: DashMap::new(),
+        }
+    }
+
+    /// Ask `gate` before filing tickets
+    #[must_use]
+    pub fn with_approval_gate(mut self, gate: Arc<dyn ApprovalGate>) -> Self {
+        self.gate = gate;
+        self
+    }
+
+    /// File a held ticket
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if nothing is held under `approval_id`,
+    /// or an error if the backend fails, in which case the ticket stays held.
+    pub async fn approve(&self, approval_id: Uuid) -> Result<TicketRef> {
+        let held = self
+            .held
+            .get(&approval_id)
+            .map(|entry| entry.clone())
+            .ok_or_else(|| Error::NotFound(format!("No ticket awaiting approval {approval_id}")))?;
+        let ticket = self.file(&held).await?;
+        self.held.remove(&approval_id);
+        Ok(ticket)
+    }
+
+    /// Discard a held ticket, returning whether one was held
+    pub fn reject(&self, approval_id: Uuid) -> bool {
+        self.held.remove(&approval_id).is_some()
+    }
+
+    async fn file(&self, held: &HeldTicket) -> Result<TicketRef> {
+        let ticket = self
+            .backend
+            .create(&held.route, held.fields.clone())
+            .await?;
+        info!("Created {} ticket {}", self.backend.name(), ticket.key);
+        self.remember(held.conversation_id.as_deref(), &ticket);
+        Ok(ticket)
+    }
+
+    fn remember(&self, conversation_id: Option<&str>, ticket: &TicketRef) {
+        if let Some(conversation_id) = conversation_id {
+            self.created
+                .entry(conversation_id.to_string())
+                .or_default()
+                .push(ticket.clone());
+        }
+    }
+
+    async fn create_ticket(
+        &self,
+        conversation_id: Option<String>,
+        summary: String,
+        description: Option<String>,
+        intent: Option<&str>,
+        mut fields: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<serde_json::Value> {
+        let route = self.config.route_for(intent, &summary).clone();
+        fields.insert("summary".to_string(), summary.clone().into());
+        if let Some(description) = description {
+            fields.insert("description".to_string(), description.into());
+        }
+        let held = HeldTicket {
+            conversation_id,
+            route,
+            fields: self.config.map_fields(fields),
+        };
+
+        let mut request = ApprovalRequest::new(
+            format!("{}.create_ticket", self.backend.name()),
+            summary,
+            serde_json::json!({ "route": held.route, "fields": held.fields }),
+        );
+        if let Some(conversation_id) = &held.conversation_id {
+            request = request.with_conversation_id(conversation_id.clone());
+        }
+
+        match self.gate.review(&request).await? {
+            ApprovalDecision::Approved => {
+                let ticket = self.file(&held).await?;
+                Ok(serde_json::json!({ "status": "created", "ticket": ticket }))
+            }
+            ApprovalDecision::Denied { reason } => {
+                Ok(serde_json::json!({ "status": "denied", "reason": reason }))
+            }
+            ApprovalDecision::Pending => {
+                self.held.insert(request.id, held);
+                Ok(serde_json::json!({ "status": "pending_approval", "approval_id": request.id }))
+            }
+        }
+    }
+
+    /// Add tickets filed for the response's conversation to its metadata
+    fn attach_tickets(&self, mut response: Response) -> Response {
+        if let Some((_, tickets)) = self.created.remove(&response.conversation_id) {
+            let entry = response
+                .metadata
+                .entry(TICKETS_METADATA_KEY.to_string())
+                .or_insert_with(|| serde_json::json!([]));
+            if let Some(list) = entry.as_array_mut() {
+                list.extend(tickets.iter().filter_map(|t| serde_json::to_value(t).ok()));
+            }
+        }
+        response
+    }
+}
+
+#[async_trait]
+impl Plugin for TicketPlugin {
+    fn name(&self) -> &str {
+        self.backend.name()
+    }
+
+    fn version(&self) -> &str {
+        "1.0.0"
+    }
+
+    fn description(&self) -> &'static str {
+        "Creates and updates tickets"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        let tool = |name: &str, description: &str| Capability {
+            name: name.to_string(),
+            capability_type: CapabilityType::ToolProvider,
+            description: description.to_string(),
+            required_permissions: vec![Permission::NetworkAccess],
+        };
+        vec![
+            tool("create_ticket", "File a ticket, subject to approval"),
+            tool("update_ticket", "Change fields of an existing ticket"),
+        ]
+    }
+
+    async fn process(&self, request: PluginRequest) -> Result<PluginResponse> {
+        match &request.request_type {
+            RequestType::InvokeTool => {}
+            RequestType::Custom(kind) if kind == "post_process" => {
+                return Ok(match serde_json::from_value::<Response>(request.data) {
+                    Ok(response) => PluginResponse::success(
+                        request.id,
+                        serde_json::to_value(self.attach_tickets(response))?,
+                    ),
+                    Err(e) => PluginResponse::error(request.id, e),
+                });
+            }
+            _ => {
+                return Ok(PluginResponse::error(
+                    request.id,
+                    "Unsupported request type",
+                ))
+            }
+        }
+
+        let conversation_id = request
+            .metadata
+            .get(CONVERSATION_METADATA_KEY)
+            .and_then(|value| value.as_str())
+            .map(str::to_string);
+        let call = match serde_json::from_value::<ToolCall>(request.data) {
+            Ok(call) => call,
+            Err(e) => return Ok(PluginResponse::error(request.id, e)),
+        };
+
+        let result = match call {
+            ToolCall::CreateTicket {
+                summary,
+                description,
+                intent,
+                fields,
+            } => {
+                self.create_ticket(
+                    conversation_id,
+                    summary,
+                    description,
+                    intent.as_deref(),
+                    fields,
+                )
+                .await
+            }
+            ToolCall::UpdateTicket { key, fields } => self
+                .backend
+                .update(&key, self.config.map_fields(fields))
+                .await
+                .map(|ticket| {
+                    self.remember(conversation_id.as_deref(), &ticket);
+                    serde_json::json!({ "status": "updated", "ticket": ticket })
+                }),
+        };
+
+        Ok(match result {
+            Ok(data) => {
+                let mut response = PluginResponse::success(request.id, data);
+                if let Some(ticket) = response.data.get("ticket").cloned() {
+                    response.metadata.insert(
+                        TICKETS_METADATA_KEY.to_string(),
+                        serde_json::json!([ticket]),
+                    );
+                }
+                response
+            }
+            Err(e) => PluginResponse::error(request.id, e),
+        })
+    }
+}
+
+/// Jira Cloud or Data Center backend, using the v2 REST API
+#[cfg(feature = "tickets")]
+pub struct JiraBackend {
+    client: reqwest::Client,
+    base_url: String,
+    user: String,
+    api_token: String,
+}
+
+#[cfg(feature = "tickets")]
+impl JiraBackend {
+    /// Create a backend authenticating with an email and API token
+    #[must_use]
+    pub fn new(
+        base_url: impl Into<String>,
+        user: impl Into<String>,
+        api_token: impl Into<String>,
+    ) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            user: user.into(),
+            api_token: api_token.into(),
+        }
+    }
+
+    fn with_names(
+        mut fields: serde_json::Map<String, serde_json::Value>,
+    ) -> serde_json::Map<String, serde_json::Value> {
+        // Jira expects priorities as objects
+        if let Some(serde_json::Value::String(priority)) = fields.get("priority").cloned() {
+            fields.insert(
+                "priority".to_string(),
+                serde_json::json!({ "name": priority }),
+            );
+        }
+        fields
+    }
+}
+
+#[cfg(feature = "tickets")]
+#[async_trait]
+impl TicketBackend for JiraBackend {
+    fn name(&self) -> &str {
+        "jira"
+    }
+
+    async fn create(
+        &self,
+        route: &TicketRoute,
+        fields: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<TicketRef> {
+        #[derive(Deserialize)]
+        struct Created {
+            id: String,
+            key: String,
+        }
+
+        let mut fields = Self::with_names(fields);
+        fields.insert(
+            "project".to_string(),
+            serde_json::json!({ "key": route.project }),
+        );
+        fields.insert(
+            "issuetype".to_string(),
+            serde_json::json!({ "name": route.issue_type.as_deref().unwrap_or("Task") }),
+        );
+        let created: Created = self
+            .client
+            .post(format!("{}/rest/api/2/issue", self.base_url))
+            .basic_auth(&self.user, Some(&self.api_token))
+            .json(&serde_json::json!({ "fields": fields }))
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| Error::Network(format!("Jira create failed: {e}")))?
+            .json()
+            .await
+            .map_err(|e| Error::Network(format!("Invalid Jira response: {e}")))?;
+        Ok(TicketRef {
+            url: format!("{}/browse/{}", self.base_url, created.key),
+            id: created.id,
+            key: created.key,
+        })
+    }
+
+    async fn update(
+        &self,
+        key: &str,
+        fields: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<TicketRef> {
+        self.client
+            .put(format!("{}/rest/api/2/issue/{key}", self.base_url))
+            .basic_auth(&self.user, Some(&self.api_token))
+            .json(&serde_json::json!({ "fields": Self::with_names(fields) }))
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| Error::Network(format!("Jira update failed: {e}")))?;
+        Ok(TicketRef {
+            id: key.to_string(),
+            key: key.to_string(),
+            url: format!("{}/browse/{key}", self.base_url),
+        })
+    }
+}
+
+/// ServiceNow backend, using the Table API
+///
+/// The route's project is the assignment group and its issue type the
+/// table, `incident` by default. Updates look tickets up by number.
+#[cfg(feature = "tickets")]
+pub struct ServiceNowBackend {
+    client: reqwest::Client,
+    instance_url: String,
+    user: String,
+    password: String,
+    table: String,
+}
+
+#[cfg(feature = "tickets")]
+impl ServiceNowBackend {
+    /// Create a backend for `https://{instance}.service-now.com`
+    #[must_use]
+    pub fn new(
+        instance_url: impl Into<String>,
+        user: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            instance_url: instance_url.into().trim_end_matches('/').to_string(),
+            user: user.into(),
+            password: password.into(),
+            table: "incident".to_string(),
+        }
+    }
+
+    fn ticket(&self, table: &str, record: &serde_json::Value) -> Result<TicketRef> {
+        let (Some(sys_id), Some(number)) = (record["sys_id"].as_str(), record["number"].as_str())
+        else {
+            return Err(Error::Provider("ServiceNow returned no record".to_string()).into());
+        };
+        Ok(TicketRef {
+            id: sys_id.to_string(),
+            key: number.to_string(),
+            url: format!("{}/{table}.do?sys_id={sys_id}", self.instance_url),
+        })
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<serde_json::Value> {
+        Ok(request
+            .basic_auth(&self.user, Some(&self.password))
+            .header("Accept", "application/json")
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| Error::Network(format!("ServiceNow request failed: {e}")))?
+            .json()
+            .await
+            .map_err(|e| Error::Network(format!("Invalid ServiceNow response: {e}")))?)
+    }
+}
+
+#[cfg(feature = "tickets")]
+#[async_trait]
+impl TicketBackend for ServiceNowBackend {
+    fn name(&self) -> &str {
+        "servicenow"
+    }
+
+    async fn create(
+        &self,
+        route: &TicketRoute,
+        mut fields: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<TicketRef> {
+        let table = route.issue_type.as_deref().unwrap_or(&self.table);
+        fields.insert("assignment_group".to_string(), route.project.clone().into());
+        let body = self
+            .send(
+                self.client
+                    .post(format!("{}/api/now/table/{table}", self.instance_url))
+                    .json(&fields),
+            )
+            .await?;
+        self.ticket(table, &body["result"])
+    }
+
+    async fn update(
+        &self,
+        key: &str,
+        fields: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<TicketRef> {
+        let table = &self.table;
+        let found = self
+            .send(
+                self.client
+                    .get(format!("{}/api/now/table/{table}", self.instance_url))
+                    .query(&[
+                        ("sysparm_query", format!("number={key}")),
+                        ("sysparm_limit", "1".to_string()),
+                    ]),
+            )
+            .await?;
+        let ticket = self.ticket(table, &found["result"][0])?;
+        self.send(
+            self.client
+                .patch(format!(
+                    "{}/api/now/table/{table}/{}",
+                    self.instance_url, ticket.id
+                ))
+                .json(&fields),
+        )
+        .await?;
+        Ok(ticket)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approval::RequireApproval;
+    use parking_lot::Mutex;
+
+    #[derive(Default)]
+    struct RecordingBackend {
+        created: Mutex<Vec<(TicketRoute, serde_json::Map<String, serde_json::Value>)>>,
+    }
+
+    #[async_trait]
+    impl TicketBackend for RecordingBackend {
+        fn name(&self) -> &str {
+            "jira"
+        }
+
+        async fn create(
+            &self,
+            route: &TicketRoute,
+            fields: serde_json::Map<String, serde_json::Value>,
+        ) -> Result<TicketRef> {
+            let mut created = self.created.lock();
+            created.push((route.clone(), fields));
+            let key = format!("{}-{}", route.project, created.len());
+            Ok(TicketRef {
+                id: created.len().to_string(),
+                url: format!("https://jira/browse/{key}"),
+                key,
+            })
+        }
+
+        async fn update(
+            &self,
+            key: &str,
+            _fields: serde_json::Map<String, serde_json::Value>,
+        ) -> Result<TicketRef> {
+            Ok(TicketRef {
+                id: key.to_string(),
+                key: key.to_string(),
+                url: String::new(),
+            })
+        }
+    }
+
+    fn config() -> TicketConfig {
+        TicketConfig::jira("OPS")
+            .with_route(IntentRoute {
+                intent: "access_request".to_string(),
+                keywords: vec!["access".to_string(), "permission".to_string()],
+                route: TicketRoute {
+                    project: "IAM".to_string(),
+                    issue_type: Some("Access".to_string()),
+                },
+            })
+            .with_field("team", "customfield_10010")
+    }
+
+    fn invoke(data: serde_json::Value) -> PluginRequest {
+        PluginRequest {
+            id: "call-1".to_string(),
+            request_type: RequestType::InvokeTool,
+            data,
+            metadata: HashMap::from([(
+                CONVERSATION_METADATA_KEY.to_string(),
+                serde_json::json!("conv-1"),
+            )]),
+        }
+    }
+
+    #[test]
+    fn test_routing_and_field_mapping() {
+        let config = config();
+        assert_eq!(config.route_for(Some("ACCESS_REQUEST"), "x").project, "IAM");
+        assert_eq!(
+            config.route_for(None, "Need Permission for repo").project,
+            "IAM"
+        );
+        assert_eq!(config.route_for(None, "Printer on fire").project, "OPS");
+
+        let mut fields = serde_json::Map::new();
+        fields.insert("team".to_string(), "sre".into());
+        fields.insert("labels".to_string(), serde_json::json!(["bot"]));
+        let mapped = config.map_fields(fields);
+        assert_eq!(mapped["customfield_10010"], "sre");
+        assert!(mapped.contains_key("labels"));
+
+        let snow = TicketConfig::servicenow("Service Desk");
+        assert_eq!(snow.field_map["summary"], "short_description");
+    }
+
+    #[tokio::test]
+    async fn test_create_ticket_returns_id_in_metadata() {
+        let backend = Arc::new(RecordingBackend::default());
+        let plugin = TicketPlugin::new(backend.clone(), config());
+
+        let response = plugin
+            .process(invoke(serde_json::json!({
+                "tool": "create_ticket",
+                "summary": "Grant access to billing dashboard",
+                "fields": { "team": "finance" },
+            })))
+            .await
+            .unwrap();
+        assert!(response.success);
+        assert_eq!(response.data["status"], "created");
+        assert_eq!(response.metadata[TICKETS_METADATA_KEY][0]["key"], "IAM-1");
+        assert_eq!(backend.created.lock()[0].1["customfield_10010"], "finance");
+
+        let post = plugin
+            .process(PluginRequest {
+                id: "post".to_string(),
+                request_type: RequestType::Custom("post_process".to_string()),
+                data: serde_json::to_value(Response::text("conv-1", "Filed IAM-1")).unwrap(),
+                metadata: HashMap::new(),
+            })
+            .await
+            .unwrap();
+        let bot_response: Response = serde_json::from_value(post.data).unwrap();
+        assert_eq!(
+            bot_response.metadata[TICKETS_METADATA_KEY][0]["key"],
+            "IAM-1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_creation_waits_for_approval() {
+        let backend = Arc::new(RecordingBackend::default());
+        let plugin = TicketPlugin::new(backend.clone(), config())
+            .with_approval_gate(Arc::new(RequireApproval));
+
+        let response = plugin
+            .process(invoke(serde_json::json!({
+                "tool": "create_ticket",
+                "summary": "Disk full on db-3",
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.data["status"], "pending_approval");
+        assert!(backend.created.lock().is_empty());
+
+        let approval_id: Uuid =
+            serde_json::from_value(response.data["approval_id"].clone()).unwrap();
+        let ticket = plugin.approve(approval_id).await.unwrap();
+        assert_eq!(ticket.key, "OPS-1");
+        assert!(plugin.approve(approval_id).await.is_err());
+        assert!(!plugin.reject(approval_id));
+    }
+}
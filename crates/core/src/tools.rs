@@ -0,0 +1,872 @@
+//! Built-in deterministic tools
+//!
+//! Models are unreliable at exact arithmetic and unit conversion, so the bot
+//! registers [`CalculatorPlugin`] and [`UnitConverterPlugin`] by default.
+//! Both compute with arbitrary-precision decimals; currency conversion reads
+//! exchange rates from a pluggable [`FxRates`] source.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bigdecimal::{BigDecimal, One, ToPrimitive, Zero};
+use dashmap::DashMap;
+use serde::Deserialize;
+
+use crate::{
+    error::Error,
+    message::Message,
+    plugin::{Capability, CapabilityType, Plugin, PluginRequest, PluginResponse, RequestType},
+};
+
+/// Decimal places kept in results before trailing zeros are dropped
+const RESULT_SCALE: i64 = 30;
+
+/// Decimal places kept in currency conversions
+const CURRENCY_SCALE: i64 = 2;
+
+/// Largest number of digits an intermediate value may have
+const MAX_DIGITS: u64 = 2_000;
+
+/// Largest magnitude of an exponent
+const MAX_EXPONENT: i64 = 1_000;
+
+/// Longest expression accepted
+const MAX_EXPRESSION_LEN: usize = 1_000;
+
+/// Format a decimal rounded to `scale` places, without exponent notation or
+/// trailing zeros
+#[must_use]
+pub fn format_decimal(value: &BigDecimal, scale: i64) -> String {
+    let (mantissa, exponent) = value.round(scale).as_bigint_and_exponent();
+    let mantissa = mantissa.to_string();
+    let (sign, digits) = mantissa
+        .strip_prefix('-')
+        .map_or(("", mantissa.as_str()), |digits| ("-", digits));
+    let text = match usize::try_from(exponent) {
+        Ok(0) => digits.to_string(),
+        Ok(places) => {
+            let digits = format!("{digits:0>width$}", width = places + 1);
+            let (whole, fraction) = digits.split_at(digits.len() - places);
+            let fraction = fraction.trim_end_matches('0');
+            if fraction.is_empty() {
+                whole.to_string()
+            } else {
+                format!("{whole}.{fraction}")
+            }
+        }
+        Err(_) => {
+            let zeros = usize::try_from(exponent.unsigned_abs()).unwrap_or_default();
+            format!("{digits}{}", "0".repeat(zeros))
+        }
+    };
+    if text.chars().all(|c| c == '0') {
+        "0".to_string()
+    } else {
+        format!("{sign}{text}")
+    }
+}
+
+fn invalid(message: impl Into<String>) -> anyhow::Error {
+    Error::InvalidInput(message.into()).into()
+}
+
+fn parse_decimal(value: &str) -> Result<BigDecimal> {
+    BigDecimal::from_str(value.trim()).map_err(|_| invalid(format!("Invalid number: {value}")))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(BigDecimal),
+    Ident(String),
+    Op(char),
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&d) = chars.peek() {
+                if d.is_ascii_digit() || d == '.' {
+                    number.push(d);
+                    chars.next();
+                } else if d == '_' || d == ',' {
+                    // Digit group separators
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Number(parse_decimal(&number)?));
+        } else if c.is_ascii_alphabetic() {
+            let mut ident = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_alphanumeric()) {
+                ident.push(d.to_ascii_lowercase());
+                chars.next();
+            }
+            tokens.push(Token::Ident(ident));
+        } else if "+-*/%^()".contains(c) {
+            tokens.push(Token::Op(c));
+            chars.next();
+        } else if c == '×' || c == '÷' {
+            tokens.push(Token::Op(if c == '×' { '*' } else { '/' }));
+            chars.next();
+        } else {
+            return Err(invalid(format!("Unexpected character '{c}'")));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive-descent evaluator over decimal tokens
+struct Evaluator {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Evaluator {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn eat(&mut self, op: char) -> bool {
+        if self.peek() == Some(&Token::Op(op)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn checked(value: BigDecimal) -> Result<BigDecimal> {
+        if value.digits() > MAX_DIGITS {
+            return Err(invalid("Result is too large"));
+        }
+        Ok(value)
+    }
+
+    fn expression(&mut self) -> Result<BigDecimal> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value = Self::checked(value + self.term()?)?;
+            } else if self.eat('-') {
+                value = Self::checked(value - self.term()?)?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<BigDecimal> {
+        let mut value = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op @ ('*' | '/' | '%'))) => *op,
+                _ => return Ok(value),
+            };
+            self.position += 1;
+            let rhs = self.unary()?;
+            if op != '*' && rhs.is_zero() {
+                return Err(invalid("Division by zero"));
+            }
+            value = Self::checked(match op {
+                '*' => value * rhs,
+                '/' => value / rhs,
+                _ => value % rhs,
+            })?;
+        }
+    }
+
+    fn unary(&mut self) -> Result<BigDecimal> {
+        if self.eat('-') {
+            return Ok(-self.unary()?);
+        }
+        if self.eat('+') {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<BigDecimal> {
+        let base = self.primary()?;
+        if !self.eat('^') {
+            return Ok(base);
+        }
+        let exponent = self.unary()?;
+        if !exponent.is_integer() {
+            return Err(invalid("Exponents must be whole numbers"));
+        }
+        let exponent = exponent
+            .to_i64()
+            .filter(|e| e.abs() <= MAX_EXPONENT)
+            .ok_or_else(|| invalid(format!("Exponents are limited to ±{MAX_EXPONENT}")))?;
+        Self::pow(&base, exponent)
+    }
+
+    fn pow(base: &BigDecimal, exponent: i64) -> Result<BigDecimal> {
+        let mut result = BigDecimal::one();
+        let mut square = base.clone();
+        let mut remaining = exponent.unsigned_abs();
+        while remaining > 0 {
+            if remaining & 1 == 1 {
+                result = Self::checked(&result * &square)?;
+            }
+            remaining >>= 1;
+            if remaining > 0 {
+                square = Self::checked(&square * &square)?;
+            }
+        }
+        if exponent < 0 {
+            if result.is_zero() {
+                return Err(invalid("Division by zero"));
+            }
+            return Ok(BigDecimal::one() / result);
+        }
+        Ok(result)
+    }
+
+    fn primary(&mut self) -> Result<BigDecimal> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| invalid("Unexpected end of expression"))?;
+        self.position += 1;
+        match token {
+            Token::Number(value) => Ok(value),
+            Token::Op('(') => {
+                let value = self.expression()?;
+                if !self.eat(')') {
+                    return Err(invalid("Missing closing parenthesis"));
+                }
+                Ok(value)
+            }
+            Token::Ident(name) if name == "pi" => Ok(parse_decimal(
+                "3.14159265358979323846264338327950288419716939937510",
+            )?),
+            Token::Ident(name) => {
+                if !self.eat('(') {
+                    return Err(invalid(format!("Unknown name '{name}'")));
+                }
+                let argument = self.expression()?;
+                if !self.eat(')') {
+                    return Err(invalid("Missing closing parenthesis"));
+                }
+                match name.as_str() {
+                    "sqrt" => argument
+                        .sqrt()
+                        .ok_or_else(|| invalid("Square root of a negative number")),
+                    "abs" => Ok(argument.abs()),
+                    _ => Err(invalid(format!("Unknown function '{name}'"))),
+                }
+            }
+            Token::Op(op) => Err(invalid(format!("Unexpected '{op}'"))),
+        }
+    }
+}
+
+/// Evaluate an arithmetic expression exactly
+///
+/// Supports `+ - * / % ^`, parentheses, `sqrt()`, `abs()`, and `pi`.
+/// Division and square roots are computed to 100 significant digits.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] for malformed expressions, division by
+/// zero, or results that grow beyond a few thousand digits.
+pub fn evaluate(expression: &str) -> Result<BigDecimal> {
+    if expression.len() > MAX_EXPRESSION_LEN {
+        return Err(invalid("Expression is too long"));
+    }
+    let mut evaluator = Evaluator {
+        tokens: tokenize(expression)?,
+        position: 0,
+    };
+    let value = evaluator.expression()?;
+    evaluator.peek().map_or(Ok(value), |token| {
+        Err(invalid(format!("Unexpected {token:?}")))
+    })
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "tool", rename_all = "snake_case")]
+enum CalculatorCall {
+    Calculate { expression: String },
+}
+
+/// Tool plugin evaluating arithmetic with arbitrary precision
+///
+/// Invoked with [`RequestType::InvokeTool`] and data of the form
+/// `{"tool": "calculate", "expression": "(1.1 + 2.2) * 3"}`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CalculatorPlugin;
+
+impl CalculatorPlugin {
+    /// Create the plugin
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl Plugin for CalculatorPlugin {
+    fn name(&self) -> &str {
+        "calculator"
+    }
+
+    fn version(&self) -> &str {
+        "1.0.0"
+    }
+
+    fn description(&self) -> &'static str {
+        "Evaluates arithmetic exactly"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability {
+            name: "calculate".to_string(),
+            capability_type: CapabilityType::ToolProvider,
+            description: "Evaluate an arithmetic expression with + - * / % ^, sqrt, and abs"
+                .to_string(),
+            required_permissions: Vec::new(),
+        }]
+    }
+
+    fn tool_schema(&self, tool: &str) -> Option<serde_json::Value> {
+        (tool == "calculate").then(|| {
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "tool": { "const": "calculate" },
+                    "expression": {
+                        "type": "string",
+                        "description": "Arithmetic expression, e.g. (1.1 + 2.2) * 3",
+                    },
+                },
+                "required": ["tool", "expression"],
+            })
+        })
+    }
+
+    fn can_handle(&self, _message: &Message) -> bool {
+        false
+    }
+
+    async fn process(&self, request: PluginRequest) -> Result<PluginResponse> {
+        if !matches!(request.request_type, RequestType::InvokeTool) {
+            return Ok(PluginResponse::error(
+                request.id,
+                "Unsupported request type",
+            ));
+        }
+        let CalculatorCall::Calculate { expression } =
+            match serde_json::from_value::<CalculatorCall>(request.data) {
+                Ok(call) => call,
+                Err(e) => return Ok(PluginResponse::error(request.id, e)),
+            };
+
+        Ok(match evaluate(&expression) {
+            Ok(value) => PluginResponse::success(
+                request.id,
+                serde_json::json!({
+                    "expression": expression,
+                    "result": format_decimal(&value, RESULT_SCALE),
+                }),
+            ),
+            Err(e) => PluginResponse::error(request.id, e),
+        })
+    }
+}
+
+/// Exchange rates for currency conversion
+#[async_trait]
+pub trait FxRates: Send + Sync {
+    /// Units of `to` per unit of `from`, for ISO 4217 codes
+    async fn rate(&self, from: &str, to: &str) -> Result<BigDecimal>;
+}
+
+/// Fixed exchange rates relative to a base currency
+///
+/// Rates can be updated at runtime, e.g. by a job that polls a rates API.
+#[derive(Debug)]
+pub struct StaticFxRates {
+    base: String,
+    rates: DashMap<String, BigDecimal>,
+}
+
+impl StaticFxRates {
+    /// Create an empty table for `base`
+    #[must_use]
+    pub fn new(base: impl Into<String>) -> Self {
+        Self {
+            base: base.into().to_ascii_uppercase(),
+            rates: DashMap::new(),
+        }
+    }
+
+    /// Add a rate in units of `code` per unit of the base currency
+    #[must_use]
+    pub fn with_rate(self, code: &str, per_base: BigDecimal) -> Self {
+        self.set_rate(code, per_base);
+        self
+    }
+
+    /// Set a rate in units of `code` per unit of the base currency
+    pub fn set_rate(&self, code: &str, per_base: BigDecimal) {
+        self.rates.insert(code.to_ascii_uppercase(), per_base);
+    }
+
+    fn per_base(&self, code: &str) -> Result<BigDecimal> {
+        if code == self.base {
+            return Ok(BigDecimal::one());
+        }
+        self.rates
+            .get(code)
+            .map(|rate| rate.clone())
+            .filter(|rate| !rate.is_zero())
+            .ok_or_else(|| Error::NotFound(format!("No exchange rate for {code}")).into())
+    }
+}
+
+impl Default for StaticFxRates {
+    fn default() -> Self {
+        Self::new("USD")
+    }
+}
+
+#[async_trait]
+impl FxRates for StaticFxRates {
+    async fn rate(&self, from: &str, to: &str) -> Result<BigDecimal> {
+        Ok(self.per_base(to)? / self.per_base(from)?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    Length,
+    Mass,
+    Volume,
+    Time,
+    Data,
+    Speed,
+    Area,
+    Temperature,
+}
+
+/// A unit as `base = (value + offset) * numerator / denominator`
+struct Unit {
+    names: &'static [&'static str],
+    dimension: Dimension,
+    numerator: &'static str,
+    denominator: &'static str,
+    offset: &'static str,
+}
+
+const fn unit(
+    names: &'static [&'static str],
+    dimension: Dimension,
+    numerator: &'static str,
+) -> Unit {
+    Unit {
+        names,
+        dimension,
+        numerator,
+        denominator: "1",
+        offset: "0",
+    }
+}
+
+const UNITS: &[Unit] = &[
+    unit(&["m", "meter", "metre"], Dimension::Length, "1"),
+    unit(&["km", "kilometer", "kilometre"], Dimension::Length, "1000"),
+    unit(
+        &["cm", "centimeter", "centimetre"],
+        Dimension::Length,
+        "0.01",
+    ),
+    unit(
+        &["mm", "millimeter", "millimetre"],
+        Dimension::Length,
+        "0.001",
+    ),
+    unit(&["mi", "mile"], Dimension::Length, "1609.344"),
+    unit(&["yd", "yard"], Dimension::Length, "0.9144"),
+    unit(&["ft", "foot", "feet"], Dimension::Length, "0.3048"),
+    unit(&["in", "inch", "inches"], Dimension::Length, "0.0254"),
+    unit(&["nmi", "nautical mile"], Dimension::Length, "1852"),
+    unit(&["kg", "kilogram"], Dimension::Mass, "1"),
+    unit(&["g", "gram"], Dimension::Mass, "0.001"),
+    unit(&["mg", "milligram"], Dimension::Mass, "0.000001"),
+    unit(&["t", "tonne", "metric ton"], Dimension::Mass, "1000"),
+    unit(&["lb", "lbs", "pound"], Dimension::Mass, "0.45359237"),
+    unit(&["oz", "ounce"], Dimension::Mass, "0.028349523125"),
+    unit(&["st", "stone"], Dimension::Mass, "6.35029318"),
+    unit(&["l", "liter", "litre"], Dimension::Volume, "1"),
+    unit(
+        &["ml", "milliliter", "millilitre"],
+        Dimension::Volume,
+        "0.001",
+    ),
+    unit(
+        &["m3", "cubic meter", "cubic metre"],
+        Dimension::Volume,
+        "1000",
+    ),
+    unit(&["gal", "gallon"], Dimension::Volume, "3.785411784"),
+    unit(&["qt", "quart"], Dimension::Volume, "0.946352946"),
+    unit(&["pt", "pint"], Dimension::Volume, "0.473176473"),
+    unit(&["cup"], Dimension::Volume, "0.2365882365"),
+    unit(
+        &["floz", "fl oz", "fluid ounce"],
+        Dimension::Volume,
+        "0.0295735295625",
+    ),
+    unit(&["s", "sec", "second"], Dimension::Time, "1"),
+    unit(&["ms", "millisecond"], Dimension::Time, "0.001"),
+    unit(&["min", "minute"], Dimension::Time, "60"),
+    unit(&["h", "hr", "hour"], Dimension::Time, "3600"),
+    unit(&["d", "day"], Dimension::Time, "86400"),
+    unit(&["wk", "week"], Dimension::Time, "604800"),
+    unit(&["b", "byte"], Dimension::Data, "1"),
+    unit(&["kb", "kilobyte"], Dimension::Data, "1000"),
+    unit(&["mb", "megabyte"], Dimension::Data, "1000000"),
+    unit(&["gb", "gigabyte"], Dimension::Data, "1000000000"),
+    unit(&["tb", "terabyte"], Dimension::Data, "1000000000000"),
+    unit(&["kib", "kibibyte"], Dimension::Data, "1024"),
+    unit(&["mib", "mebibyte"], Dimension::Data, "1048576"),
+    unit(&["gib", "gibibyte"], Dimension::Data, "1073741824"),
+    unit(&["tib", "tebibyte"], Dimension::Data, "1099511627776"),
+    unit(&["m/s", "mps"], Dimension::Speed, "1"),
+    Unit {
+        names: &["km/h", "kph", "kmh"],
+        dimension: Dimension::Speed,
+        numerator: "1000",
+        denominator: "3600",
+        offset: "0",
+    },
+    Unit {
+        names: &["mph", "mi/h"],
+        dimension: Dimension::Speed,
+        numerator: "1609.344",
+        denominator: "3600",
+        offset: "0",
+    },
+    Unit {
+        names: &["kn", "knot", "kt"],
+        dimension: Dimension::Speed,
+        numerator: "1852",
+        denominator: "3600",
+        offset: "0",
+    },
+    unit(
+        &["m2", "sq m", "square meter", "square metre"],
+        Dimension::Area,
+        "1",
+    ),
+    unit(
+        &["km2", "sq km", "square kilometer"],
+        Dimension::Area,
+        "1000000",
+    ),
+    unit(&["ha", "hectare"], Dimension::Area, "10000"),
+    unit(&["acre", "ac"], Dimension::Area, "4046.8564224"),
+    unit(
+        &["ft2", "sq ft", "sqft", "square foot", "square feet"],
+        Dimension::Area,
+        "0.09290304",
+    ),
+    unit(
+        &["mi2", "sq mi", "square mile"],
+        Dimension::Area,
+        "2589988.110336",
+    ),
+    unit(&["k", "kelvin"], Dimension::Temperature, "1"),
+    Unit {
+        names: &["c", "°c", "celsius", "centigrade"],
+        dimension: Dimension::Temperature,
+        numerator: "1",
+        denominator: "1",
+        offset: "273.15",
+    },
+    Unit {
+        names: &["f", "°f", "fahrenheit"],
+        dimension: Dimension::Temperature,
+        numerator: "5",
+        denominator: "9",
+        offset: "459.67",
+    },
+];
+
+impl Unit {
+    fn find(name: &str) -> Option<&'static Self> {
+        let name = name.trim().to_lowercase();
+        let lookup = |name: &str| UNITS.iter().find(|unit| unit.names.contains(&name));
+        lookup(&name).or_else(|| {
+            // Plurals, e.g. "miles" or "degrees celsius"
+            let name = name.strip_prefix("degrees ").unwrap_or(&name);
+            lookup(name).or_else(|| {
+                name.strip_suffix('s')
+                    .filter(|n| n.len() > 1)
+                    .and_then(lookup)
+            })
+        })
+    }
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap_or_default()
+    }
+
+    fn to_base(&self, value: &BigDecimal) -> BigDecimal {
+        (value + Self::decimal(self.offset)) * Self::decimal(self.numerator)
+            / Self::decimal(self.denominator)
+    }
+
+    fn base_to_unit(&self, value: &BigDecimal) -> BigDecimal {
+        value * Self::decimal(self.denominator) / Self::decimal(self.numerator)
+            - Self::decimal(self.offset)
+    }
+}
+
+fn currency_code(name: &str) -> Option<String> {
+    let name = name.trim();
+    (name.len() == 3 && name.chars().all(|c| c.is_ascii_alphabetic()))
+        .then(|| name.to_ascii_uppercase())
+}
+
+/// Convert between units, or between currencies using `fx`
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] for unknown or incompatible units and
+/// [`Error::NotFound`] for currencies without a rate.
+pub async fn convert(value: &BigDecimal, from: &str, to: &str, fx: &dyn FxRates) -> Result<String> {
+    match (Unit::find(from), Unit::find(to)) {
+        (Some(from_unit), Some(to_unit)) => {
+            if from_unit.dimension != to_unit.dimension {
+                return Err(invalid(format!("Cannot convert {from} to {to}")));
+            }
+            let converted = to_unit.base_to_unit(&from_unit.to_base(value));
+            Ok(format_decimal(&converted, RESULT_SCALE))
+        }
+        _ => {
+            if let (Some(from), Some(to)) = (currency_code(from), currency_code(to)) {
+                let rate = fx.rate(&from, &to).await?;
+                Ok(format_decimal(&(value * rate), CURRENCY_SCALE))
+            } else {
+                let unknown = if Unit::find(from).is_none() { from } else { to };
+                Err(invalid(format!("Unknown unit '{unknown}'")))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "tool", rename_all = "snake_case")]
+enum ConverterCall {
+    Convert {
+        value: serde_json::Value,
+        from: String,
+        to: String,
+    },
+}
+
+/// Tool plugin converting units and currencies
+///
+/// Invoked with [`RequestType::InvokeTool`] and data of the form
+/// `{"tool": "convert", "value": "12.5", "from": "mi", "to": "km"}`.
+/// Three-letter codes that are not units, such as `EUR`, are converted
+/// as currencies.
+pub struct UnitConverterPlugin {
+    fx: Arc<dyn FxRates>,
+}
+
+impl UnitConverterPlugin {
+    /// Create the plugin with no exchange rates
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_fx_rates(Arc::new(StaticFxRates::default()))
+    }
+
+    /// Create the plugin with an exchange rate source
+    #[must_use]
+    pub fn with_fx_rates(fx: Arc<dyn FxRates>) -> Self {
+        Self { fx }
+    }
+}
+
+impl Default for UnitConverterPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Plugin for UnitConverterPlugin {
+    fn name(&self) -> &str {
+        "unit_converter"
+    }
+
+    fn version(&self) -> &str {
+        "1.0.0"
+    }
+
+    fn description(&self) -> &'static str {
+        "Converts units of measure and currencies"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability {
+            name: "convert".to_string(),
+            capability_type: CapabilityType::ToolProvider,
+            description: "Convert a value between units (length, mass, volume, time, data, \
+                          speed, area, temperature) or ISO 4217 currencies"
+                .to_string(),
+            required_permissions: Vec::new(),
+        }]
+    }
+
+    fn tool_schema(&self, tool: &str) -> Option<serde_json::Value> {
+        (tool == "convert").then(|| {
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "tool": { "const": "convert" },
+                    "value": {
+                        "type": ["string", "number"],
+                        "description": "Quantity to convert",
+                    },
+                    "from": { "type": "string", "description": "Source unit or currency code" },
+                    "to": { "type": "string", "description": "Target unit or currency code" },
+                },
+                "required": ["tool", "value", "from", "to"],
+            })
+        })
+    }
+
+    fn can_handle(&self, _message: &Message) -> bool {
+        false
+    }
+
+    async fn process(&self, request: PluginRequest) -> Result<PluginResponse> {
+        if !matches!(request.request_type, RequestType::InvokeTool) {
+            return Ok(PluginResponse::error(
+                request.id,
+                "Unsupported request type",
+            ));
+        }
+        let ConverterCall::Convert { value, from, to } =
+            match serde_json::from_value::<ConverterCall>(request.data) {
+                Ok(call) => call,
+                Err(e) => return Ok(PluginResponse::error(request.id, e)),
+            };
+        let value = match &value {
+            serde_json::Value::String(text) => parse_decimal(text),
+            other => parse_decimal(&other.to_string()),
+        };
+
+        let result = match value {
+            Ok(value) => convert(&value, &from, &to, self.fx.as_ref()).await,
+            Err(e) => Err(e),
+        };
+        Ok(match result {
+            Ok(result) => PluginResponse::success(
+                request.id,
+                serde_json::json!({ "from": from, "to": to, "result": result }),
+            ),
+            Err(e) => PluginResponse::error(request.id, e),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expression: &str) -> String {
+        format_decimal(&evaluate(expression).unwrap(), RESULT_SCALE)
+    }
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(eval("0.1 + 0.2"), "0.3");
+        assert_eq!(eval("2 + 3 * 4 ^ 2"), "50");
+        assert_eq!(eval("-2^2"), "-4");
+        assert_eq!(eval("2^-2"), "0.25");
+        assert_eq!(eval("(1,000 - 1) % 7"), "5");
+        assert_eq!(eval("1 / 3"), "0.333333333333333333333333333333");
+        assert_eq!(eval("2^100"), "1267650600228229401496703205376");
+        assert_eq!(eval("sqrt(2) * sqrt(2)"), "2");
+        assert_eq!(eval("abs(3 - 10) × 2"), "14");
+
+        assert!(evaluate("1 / 0").is_err());
+        assert!(evaluate("2 ^ 0.5").is_err());
+        assert!(evaluate("10 ^ 10000").is_err());
+        assert!(evaluate("(1 + 2").is_err());
+        assert!(evaluate("1 + ").is_err());
+        assert!(evaluate("foo(1)").is_err());
+    }
+
+    async fn units(value: &str, from: &str, to: &str) -> Result<String> {
+        convert(&decimal(value), from, to, &StaticFxRates::default()).await
+    }
+
+    #[tokio::test]
+    async fn test_convert_units() {
+        assert_eq!(units("26.2", "miles", "km").await.unwrap(), "42.1648128");
+        assert_eq!(units("100", "°C", "F").await.unwrap(), "212");
+        assert_eq!(units("-40", "fahrenheit", "celsius").await.unwrap(), "-40");
+        assert_eq!(units("1", "GiB", "MB").await.unwrap(), "1073.741824");
+        assert_eq!(units("60", "mph", "km/h").await.unwrap(), "96.56064");
+        assert!(units("1", "kg", "m").await.is_err());
+        assert!(units("1", "furlong", "m").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_convert_currency() {
+        let fx = StaticFxRates::new("usd")
+            .with_rate("EUR", decimal("0.8"))
+            .with_rate("JPY", decimal("150"));
+        assert_eq!(
+            convert(&decimal("10"), "usd", "EUR", &fx).await.unwrap(),
+            "8"
+        );
+        assert_eq!(
+            convert(&decimal("100"), "EUR", "JPY", &fx).await.unwrap(),
+            "18750"
+        );
+        assert!(convert(&decimal("1"), "USD", "GBP", &fx).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tools_registered_by_default() {
+        let bot = crate::Bot::new(crate::BotConfig::default()).await.unwrap();
+        let response = bot
+            .invoke_tool(
+                "calculator",
+                serde_json::json!({ "tool": "calculate", "expression": "12.5 * 8" }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.data["result"], "100");
+
+        let response = bot
+            .invoke_tool(
+                "unit_converter",
+                serde_json::json!({ "tool": "convert", "value": 5, "from": "kg", "to": "lb" }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.data["result"], "11.023113109243879036148690067251");
+    }
+}
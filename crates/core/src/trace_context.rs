@@ -0,0 +1,305 @@
+//! Distributed tracing across the pipeline, plugins, and providers
+//!
+//! A message can carry its caller's W3C trace context in
+//! [`Message::metadata`](crate::message::Message::metadata) under
+//! [`TRACEPARENT_METADATA_KEY`], optionally with [`TRACESTATE_METADATA_KEY`].
+//! [`MessagePipeline::process`](crate::pipeline::MessagePipeline::process)
+//! continues that trace: its span becomes a child of the caller's span, each
+//! stage and plugin call gets a child span, and the Bedrock client's request
+//! spans, which record the Bedrock request ID, nest under the stage that
+//! sent them. Plugin requests carry the current trace context in their
+//! metadata so out-of-process plugins can continue the trace.
+//!
+//! The spans are ordinary `tracing` spans. With the `otel` feature,
+//! [`init_with_otlp`](crate::init_with_otlp) exports them to an OTLP
+//! collector, as does [`init`](crate::init) when
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+use serde::{Deserialize, Serialize};
+use tracing::Span;
+
+/// Metadata key holding a W3C `traceparent` header value
+pub const TRACEPARENT_METADATA_KEY: &str = "traceparent";
+
+/// Metadata key holding a W3C `tracestate` header value
+pub const TRACESTATE_METADATA_KEY: &str = "tracestate";
+
+/// A remote span to continue, as carried by a W3C `traceparent` header
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceContext {
+    /// Trace ID, 32 lowercase hex digits
+    pub trace_id: String,
+    /// ID of the caller's span, 16 lowercase hex digits
+    pub parent_id: String,
+    /// Whether the caller sampled the trace
+    pub sampled: bool,
+    /// Vendor-specific `tracestate` entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_state: Option<String>,
+}
+
+impl TraceContext {
+    /// Parse a `traceparent` value such as
+    /// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`
+    ///
+    /// Returns `None` for malformed values and all-zero IDs.
+    #[must_use]
+    pub fn parse(traceparent: &str) -> Option<Self> {
+        let mut parts = traceparent.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let parent_id = parts.next()?;
+        let flags = parts.next()?;
+        // Version 00 has exactly four fields; later versions may append more
+        if version == "ff" || (version == "00" && parts.next().is_some()) {
+            return None;
+        }
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+        (is_hex_id(version, 2)
+            && is_hex_id(trace_id, 32)
+            && is_hex_id(parent_id, 16)
+            && trace_id.bytes().any(|b| b != b'0')
+            && parent_id.bytes().any(|b| b != b'0'))
+        .then(|| Self {
+            trace_id: trace_id.to_string(),
+            parent_id: parent_id.to_string(),
+            sampled: flags & 1 == 1,
+            trace_state: None,
+        })
+    }
+
+    /// The trace context in message or plugin request metadata, if any
+    #[must_use]
+    pub fn from_metadata<S: BuildHasher>(
+        metadata: &HashMap<String, serde_json::Value, S>,
+    ) -> Option<Self> {
+        let mut context = Self::parse(metadata.get(TRACEPARENT_METADATA_KEY)?.as_str()?)?;
+        context.trace_state = metadata
+            .get(TRACESTATE_METADATA_KEY)
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_string);
+        Some(context)
+    }
+
+    /// The context of the current span, if it is exported over OpenTelemetry
+    #[cfg(feature = "otel")]
+    #[must_use]
+    pub fn current() -> Option<Self> {
+        use opentelemetry::trace::TraceContextExt as _;
+        use tracing_opentelemetry::OpenTelemetrySpanExt as _;
+
+        let context = Span::current().context();
+        let span = context.span();
+        let span_context = span.span_context();
+        span_context.is_valid().then(|| Self {
+            trace_id: span_context.trace_id().to_string(),
+            parent_id: span_context.span_id().to_string(),
+            sampled: span_context.is_sampled(),
+            trace_state: Some(span_context.trace_state().header())
+                .filter(|state| !state.is_empty()),
+        })
+    }
+
+    /// The context of the current span; spans are only exported with the `otel` feature
+    #[cfg(not(feature = "otel"))]
+    #[must_use]
+    pub const fn current() -> Option<Self> {
+        None
+    }
+
+    /// The `traceparent` header value
+    #[must_use]
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{:02x}",
+            self.trace_id,
+            self.parent_id,
+            u8::from(self.sampled)
+        )
+    }
+
+    /// Write the context into message or plugin request metadata
+    pub fn inject<S: BuildHasher>(&self, metadata: &mut HashMap<String, serde_json::Value, S>) {
+        metadata.insert(
+            TRACEPARENT_METADATA_KEY.to_string(),
+            self.traceparent().into(),
+        );
+        if let Some(state) = &self.trace_state {
+            metadata.insert(TRACESTATE_METADATA_KEY.to_string(), state.clone().into());
+        }
+    }
+
+    /// Make `span` a child of the remote span
+    ///
+    /// Records the trace ID in the span's `trace_id` field, if it has one.
+    /// With the `otel` feature the span is also linked to the remote parent
+    /// in exported traces. Call this before `span` has children.
+    pub fn attach(&self, span: &Span) {
+        span.record("trace_id", self.trace_id.as_str());
+        #[cfg(feature = "otel")]
+        {
+            use opentelemetry::propagation::TextMapPropagator as _;
+            use opentelemetry_sdk::propagation::TraceContextPropagator;
+            use tracing_opentelemetry::OpenTelemetrySpanExt as _;
+
+            let mut carrier =
+                HashMap::from([(TRACEPARENT_METADATA_KEY.to_string(), self.traceparent())]);
+            if let Some(state) = &self.trace_state {
+                carrier.insert(TRACESTATE_METADATA_KEY.to_string(), state.clone());
+            }
+            span.set_parent(TraceContextPropagator::new().extract(&carrier));
+        }
+    }
+}
+
+fn is_hex_id(value: &str, len: usize) -> bool {
+    value.len() == len
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Pass the current trace context to a plugin or other downstream request
+///
+/// Leaves `metadata` unchanged when there is no exported current span.
+pub fn propagate<S: BuildHasher>(metadata: &mut HashMap<String, serde_json::Value, S>) {
+    if let Some(context) = TraceContext::current() {
+        context.inject(metadata);
+    }
+}
+
+/// OTLP span export settings
+#[cfg(feature = "otel")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OtlpConfig {
+    /// Collector gRPC endpoint
+    pub endpoint: String,
+
+    /// `service.name` reported with every span
+    pub service_name: String,
+
+    /// Share of new traces (0.0 to 1.0) exported; continued traces follow
+    /// their caller's sampling decision
+    pub sample_ratio: f64,
+
+    /// Export timeout
+    #[serde(with = "humantime_serde")]
+    pub timeout: std::time::Duration,
+}
+
+#[cfg(feature = "otel")]
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://localhost:4317".to_string(),
+            service_name: "universal-bot".to_string(),
+            sample_ratio: 1.0,
+            timeout: std::time::Duration::from_secs(10),
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+impl OtlpConfig {
+    /// Settings from the standard `OTEL_EXPORTER_OTLP_ENDPOINT` and
+    /// `OTEL_SERVICE_NAME` variables, or `None` if no endpoint is set
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        let endpoint = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .ok()
+            .filter(|endpoint| !endpoint.is_empty())?;
+        let mut config = Self {
+            endpoint,
+            ..Self::default()
+        };
+        if let Ok(name) = std::env::var("OTEL_SERVICE_NAME") {
+            config.service_name = name;
+        }
+        Some(config)
+    }
+
+    /// Install a batch OTLP exporter and return a layer feeding it
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the exporter cannot be built.
+    pub(crate) fn layer<S>(&self) -> crate::Result<impl tracing_subscriber::Layer<S>>
+    where
+        S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+    {
+        use opentelemetry_otlp::WithExportConfig as _;
+        use opentelemetry_sdk::trace::{self as sdktrace, Sampler};
+
+        opentelemetry::global::set_text_map_propagator(
+            opentelemetry_sdk::propagation::TraceContextPropagator::new(),
+        );
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(&self.endpoint)
+                    .with_timeout(self.timeout),
+            )
+            .with_trace_config(
+                sdktrace::config()
+                    .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                        self.sample_ratio.clamp(0.0, 1.0),
+                    ))))
+                    .with_resource(opentelemetry_sdk::Resource::new([
+                        opentelemetry::KeyValue::new("service.name", self.service_name.clone()),
+                    ])),
+            )
+            .install_batch(opentelemetry_sdk::runtime::Tokio)
+            .map_err(|e| crate::Error::Initialization(format!("OTLP exporter: {e}")))?;
+        Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_traceparent_round_trip() {
+        let context = TraceContext::parse(TRACEPARENT).unwrap();
+        assert_eq!(context.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(context.parent_id, "00f067aa0ba902b7");
+        assert!(context.sampled);
+        assert_eq!(context.traceparent(), TRACEPARENT);
+
+        let mut metadata = HashMap::new();
+        TraceContext {
+            trace_state: Some("vendor=1".to_string()),
+            ..context
+        }
+        .inject(&mut metadata);
+        let restored = TraceContext::from_metadata(&metadata).unwrap();
+        assert_eq!(restored.trace_state.as_deref(), Some("vendor=1"));
+        assert_eq!(restored.traceparent(), TRACEPARENT);
+    }
+
+    #[test]
+    fn test_invalid_traceparents_are_rejected() {
+        for value in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        ] {
+            assert_eq!(TraceContext::parse(value), None, "{value}");
+        }
+        let unsampled =
+            TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00");
+        assert!(!unsampled.unwrap().sampled);
+    }
+}
@@ -0,0 +1,262 @@
+//! Conversation transcripts
+//!
+//! A [`Transcript`] is a conversation in a portable form, for archiving,
+//! replaying, or moving between storage backends. As JSONL, its first line
+//! is a `conversation` record with the user, variables, metadata, and
+//! checkpoints, followed by one `turn` record per history entry, oldest
+//! first. Responses carry their token usage; usage of turns no longer in
+//! the history stays in the conversation record.
+//!
+//! [`ContextManager::export_transcript`](crate::context::ContextManager::export_transcript)
+//! and
+//! [`ContextManager::import_transcript`](crate::context::ContextManager::import_transcript)
+//! convert between stored contexts and transcripts.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    context::{Checkpoint, Context, ContextMessage, ContextMetadata, TurnUsage, UserContext},
+    error::Error,
+};
+
+/// Version of the transcript layout written by [`Transcript::from_context`]
+pub const TRANSCRIPT_FORMAT_VERSION: u32 = 1;
+
+/// Everything about a conversation except its history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptHeader {
+    /// Layout version, see [`TRANSCRIPT_FORMAT_VERSION`]
+    pub format: u32,
+    /// Conversation ID
+    pub id: String,
+    /// When the transcript was taken
+    pub exported_at: DateTime<Utc>,
+    /// User information
+    pub user: UserContext,
+    /// Session variables
+    pub variables: HashMap<String, serde_json::Value>,
+    /// Conversation metadata; its usage only lists turns not in the transcript
+    pub metadata: ContextMetadata,
+    /// Token count of the history
+    pub token_count: usize,
+    /// Snapshots available for rollback, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+/// A message or response in a transcript
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptTurn {
+    /// The history entry
+    #[serde(flatten)]
+    pub message: ContextMessage,
+    /// Tokens and cost of a response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TurnUsage>,
+}
+
+/// One JSONL line
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TranscriptRecord {
+    Conversation(Box<TranscriptHeader>),
+    Turn(TranscriptTurn),
+}
+
+/// A conversation's history with its usage and metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transcript {
+    /// The conversation
+    pub conversation: TranscriptHeader,
+    /// History, oldest first
+    pub turns: Vec<TranscriptTurn>,
+}
+
+impl Transcript {
+    /// Take a transcript of `context`
+    #[must_use]
+    pub fn from_context(context: &Context) -> Self {
+        let mut metadata = context.metadata.clone();
+        let mut usage: HashMap<_, _> = std::mem::take(&mut metadata.usage)
+            .into_iter()
+            .map(|turn| (turn.response_id, turn))
+            .collect();
+        let turns = context
+            .history
+            .iter()
+            .map(|message| TranscriptTurn {
+                message: message.clone(),
+                usage: message.message_id.and_then(|id| usage.remove(&id)),
+            })
+            .collect();
+        // Usage of trimmed turns, in the order it was recorded
+        metadata.usage = context
+            .metadata
+            .usage
+            .iter()
+            .filter(|turn| usage.contains_key(&turn.response_id))
+            .cloned()
+            .collect();
+
+        Self {
+            conversation: TranscriptHeader {
+                format: TRANSCRIPT_FORMAT_VERSION,
+                id: context.id.clone(),
+                exported_at: Utc::now(),
+                user: context.user.clone(),
+                variables: context.variables.clone(),
+                metadata,
+                token_count: context.token_count,
+                checkpoints: context.checkpoints.clone(),
+            },
+            turns,
+        }
+    }
+
+    /// The context the transcript was taken of
+    #[must_use]
+    pub fn into_context(self) -> Context {
+        let header = self.conversation;
+        let mut metadata = header.metadata;
+        let mut history = std::collections::VecDeque::with_capacity(self.turns.len());
+        for turn in self.turns {
+            metadata.usage.extend(turn.usage);
+            history.push_back(turn.message);
+        }
+        Context {
+            id: header.id,
+            history,
+            user: header.user,
+            variables: header.variables,
+            metadata,
+            token_count: header.token_count,
+            checkpoints: header.checkpoints,
+        }
+    }
+
+    /// Render the transcript as JSONL
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Serialization`] if a variable or metadata value
+    /// cannot be serialized.
+    pub fn to_jsonl(&self) -> Result<String> {
+        let mut jsonl = line(&TranscriptRecord::Conversation(Box::new(
+            self.conversation.clone(),
+        )))?;
+        for turn in &self.turns {
+            jsonl.push_str(&line(&TranscriptRecord::Turn(turn.clone()))?);
+        }
+        Ok(jsonl)
+    }
+
+    /// Parse a transcript written by [`to_jsonl`](Self::to_jsonl)
+    ///
+    /// Blank lines are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Serialization`] naming the first malformed line, or
+    /// if the transcript does not start with exactly one `conversation`
+    /// record of a known format.
+    pub fn from_jsonl(jsonl: &str) -> Result<Self> {
+        let mut conversation = None;
+        let mut turns = Vec::new();
+        for (index, text) in jsonl.lines().enumerate() {
+            if text.trim().is_empty() {
+                continue;
+            }
+            let malformed = |e: &dyn std::fmt::Display| {
+                Error::Serialization(format!("Transcript line {}: {e}", index + 1))
+            };
+            let record: TranscriptRecord = serde_json::from_str(text).map_err(|e| malformed(&e))?;
+            match (record, &conversation) {
+                (TranscriptRecord::Conversation(header), None) => {
+                    if header.format > TRANSCRIPT_FORMAT_VERSION {
+                        return Err(malformed(&format!(
+                            "format {} is newer than {TRANSCRIPT_FORMAT_VERSION}",
+                            header.format
+                        ))
+                        .into());
+                    }
+                    conversation = Some(*header);
+                }
+                (TranscriptRecord::Turn(turn), Some(_)) => turns.push(turn),
+                (TranscriptRecord::Conversation(_), Some(_)) => {
+                    return Err(malformed(&"second conversation record").into());
+                }
+                (TranscriptRecord::Turn(_), None) => {
+                    return Err(malformed(&"turn before the conversation record").into());
+                }
+            }
+        }
+        let conversation = conversation.ok_or_else(|| {
+            Error::Serialization("Transcript has no conversation record".to_string())
+        })?;
+        Ok(Self {
+            conversation,
+            turns,
+        })
+    }
+}
+
+fn line(record: &TranscriptRecord) -> Result<String> {
+    let json = serde_json::to_string(record).map_err(|e| Error::Serialization(e.to_string()))?;
+    Ok(json + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::MessageRole;
+    use crate::message::{Message, Response, TokenUsage};
+
+    #[test]
+    fn test_jsonl_round_trip() {
+        let mut context = Context::new("conv-1");
+        context.set_variable("plan", serde_json::json!("pro"));
+        let message = Message::text("What does pro include?");
+        context.add_message(&message);
+        context.add_response(
+            &Response::text("conv-1", "Everything.").with_usage(TokenUsage::new(
+                12,
+                3,
+                "anthropic.claude-haiku",
+            )),
+        );
+
+        let jsonl = Transcript::from_context(&context).to_jsonl().unwrap();
+        let lines: Vec<serde_json::Value> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["type"], "conversation");
+        assert_eq!(lines[1]["role"], "user");
+        assert_eq!(lines[2]["usage"]["input_tokens"], 12);
+
+        let restored = Transcript::from_jsonl(&jsonl).unwrap().into_context();
+        assert_eq!(restored.id, "conv-1");
+        assert_eq!(restored.history, context.history);
+        assert_eq!(restored.history[0].role, MessageRole::User);
+        assert_eq!(restored.metadata.usage, context.metadata.usage);
+        assert_eq!(restored.variables["plan"], "pro");
+    }
+
+    #[test]
+    fn test_malformed_jsonl_names_the_line() {
+        let jsonl = Transcript::from_context(&Context::new("conv-1"))
+            .to_jsonl()
+            .unwrap();
+
+        let error =
+            Transcript::from_jsonl(&format!("{jsonl}\n{{\"type\": \"turn\"}}\n")).unwrap_err();
+        assert!(error.to_string().contains("line 3"));
+        assert!(Transcript::from_jsonl("").is_err());
+        assert!(Transcript::from_jsonl(&jsonl.repeat(2)).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+//! Token usage over the course of a conversation
+//!
+//! Every response's usage is recorded in its context's
+//! [`ContextMetadata::usage`](crate::context::ContextMetadata::usage), which
+//! survives history trimming. [`UsageTimeline`] turns those records into a
+//! series with one point per turn and running totals, ready to chart, so
+//! admins can spot conversations whose cost keeps climbing.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::context::Context;
+
+/// Usage of one turn and of the conversation up to it
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsagePoint {
+    /// Turn number, starting at 1
+    pub turn: usize,
+    /// When the turn's response was produced
+    pub timestamp: DateTime<Utc>,
+    /// Model that produced the response
+    pub model: String,
+    /// Input tokens billed for the turn
+    pub input_tokens: usize,
+    /// Output tokens generated in the turn
+    pub output_tokens: usize,
+    /// Estimated cost of the turn in USD
+    pub cost_usd: f64,
+    /// Tokens used by this and all earlier turns
+    pub cumulative_tokens: usize,
+    /// Estimated cost of this and all earlier turns in USD
+    pub cumulative_cost_usd: f64,
+}
+
+impl UsagePoint {
+    /// Input and output tokens of the turn
+    #[must_use]
+    pub const fn total_tokens(&self) -> usize {
+        self.input_tokens + self.output_tokens
+    }
+}
+
+/// A conversation's token usage per turn
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageTimeline {
+    /// The conversation
+    pub conversation_id: String,
+    /// One point per turn, oldest first
+    pub points: Vec<UsagePoint>,
+}
+
+impl UsageTimeline {
+    /// The timeline of `context`'s recorded usage
+    #[must_use]
+    pub fn from_context(context: &Context) -> Self {
+        let mut cumulative_tokens = 0;
+        let mut cumulative_cost_usd = 0.0;
+        let points = context
+            .metadata
+            .usage
+            .iter()
+            .enumerate()
+            .map(|(i, usage)| {
+                cumulative_tokens += usage.input_tokens + usage.output_tokens;
+                cumulative_cost_usd += usage.cost_usd;
+                UsagePoint {
+                    turn: i + 1,
+                    timestamp: usage.timestamp,
+                    model: usage.model.clone(),
+                    input_tokens: usage.input_tokens,
+                    output_tokens: usage.output_tokens,
+                    cost_usd: usage.cost_usd,
+                    cumulative_tokens,
+                    cumulative_cost_usd,
+                }
+            })
+            .collect();
+        Self {
+            conversation_id: context.id.clone(),
+            points,
+        }
+    }
+
+    /// Tokens used by the whole conversation
+    #[must_use]
+    pub fn total_tokens(&self) -> usize {
+        self.points
+            .last()
+            .map_or(0, |point| point.cumulative_tokens)
+    }
+
+    /// Estimated cost of the whole conversation in USD
+    #[must_use]
+    pub fn total_cost_usd(&self) -> f64 {
+        self.points
+            .last()
+            .map_or(0.0, |point| point.cumulative_cost_usd)
+    }
+
+    /// The turn that used the most tokens
+    #[must_use]
+    pub fn peak(&self) -> Option<&UsagePoint> {
+        self.points.iter().max_by_key(|point| point.total_tokens())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{Response, TokenUsage};
+
+    #[test]
+    fn test_timeline_accumulates_turns_past_trimming() {
+        let mut context = Context::new("conv");
+        for (input, output) in [(100, 20), (400, 80), (250, 50)] {
+            let usage = TokenUsage::new(input, output, "anthropic.claude-haiku");
+            context.add_response(&Response::text("conv", "Reply").with_usage(usage));
+        }
+        context.trim_to_token_limit(0);
+
+        let timeline = UsageTimeline::from_context(&context);
+
+        let cumulative: Vec<_> = timeline
+            .points
+            .iter()
+            .map(|point| point.cumulative_tokens)
+            .collect();
+        assert_eq!(cumulative, [120, 600, 900]);
+        assert_eq!(timeline.total_tokens(), context.metadata.total_tokens);
+        assert!((timeline.total_cost_usd() - context.metadata.total_cost).abs() < 1e-12);
+        assert_eq!(timeline.peak().map(|point| point.turn), Some(2));
+    }
+}
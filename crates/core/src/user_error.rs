@@ -0,0 +1,377 @@
+//! User-facing error messages
+//!
+//! Internal errors name providers, hosts, and stack details users should not
+//! see. [`UserErrorKind::of`] sorts an error into a small set of kinds by its
+//! [`Error`] variant, or its Bedrock error category when the `bedrock`
+//! feature is enabled, and an [`ErrorCatalog`] turns the kind into a short
+//! message and a remediation hint in the user's language. The
+//! [`FormatStage`](crate::pipeline::FormatStage) uses the catalog for the
+//! error responses it builds.
+//!
+//! The catalog ships English, German, Spanish, and French text. Languages
+//! and kinds without an entry fall back to English; entries from
+//! [`UserErrorConfig::messages`] replace or add to the built-in ones.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::UserErrorConfig,
+    error::Error,
+    message::{Response, ResponseError},
+};
+
+/// Language used when the user's language has no catalog entry
+pub const FALLBACK_LANGUAGE: &str = "en";
+
+/// Placeholder in messages and hints replaced by the retry delay in seconds
+const SECONDS_PLACEHOLDER: &str = "{seconds}";
+
+/// What went wrong, as far as the user needs to know
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserErrorKind {
+    /// The message was malformed or could not be handled
+    InvalidRequest,
+    /// The message is over the model's input limit
+    TooLong,
+    /// A content filter blocked the request or the reply
+    ContentBlocked,
+    /// The user or bot is sending too many requests
+    RateLimited,
+    /// The spend budget is used up
+    BudgetExceeded,
+    /// The reply took too long
+    Timeout,
+    /// The model provider could not be reached
+    Unavailable,
+    /// The user may not use the bot
+    Unauthorized,
+    /// The conversation or resource does not exist
+    NotFound,
+    /// Anything else
+    Internal,
+}
+
+impl UserErrorKind {
+    /// Every kind, in declaration order
+    pub const ALL: [Self; 10] = [
+        Self::InvalidRequest,
+        Self::TooLong,
+        Self::ContentBlocked,
+        Self::RateLimited,
+        Self::BudgetExceeded,
+        Self::Timeout,
+        Self::Unavailable,
+        Self::Unauthorized,
+        Self::NotFound,
+        Self::Internal,
+    ];
+
+    /// Classify `error` by the first cause the catalog knows
+    #[must_use]
+    pub fn of(error: &anyhow::Error) -> Self {
+        error
+            .chain()
+            .find_map(|cause| {
+                #[cfg(feature = "bedrock")]
+                if let Some(error) = cause.downcast_ref::<universal_bot_bedrock::BedrockError>() {
+                    return Some(Self::of_bedrock(error));
+                }
+                cause.downcast_ref::<Error>().map(Self::of_error)
+            })
+            .unwrap_or(Self::Internal)
+    }
+
+    /// The kind of a core error
+    #[must_use]
+    pub const fn of_error(error: &Error) -> Self {
+        match error {
+            Error::InvalidInput(_) | Error::Validation(_) => Self::InvalidRequest,
+            Error::RateLimit => Self::RateLimited,
+            Error::BudgetExceeded { .. } => Self::BudgetExceeded,
+            Error::Timeout(_) => Self::Timeout,
+            Error::Provider(_) | Error::Network(_) | Error::Initialization(_) => Self::Unavailable,
+            Error::Authentication(_) | Error::Authorization(_) => Self::Unauthorized,
+            Error::NotFound(_) => Self::NotFound,
+            _ => Self::Internal,
+        }
+    }
+
+    /// The kind of a Bedrock error, by its category
+    #[cfg(feature = "bedrock")]
+    #[must_use]
+    pub fn of_bedrock(error: &universal_bot_bedrock::BedrockError) -> Self {
+        use universal_bot_bedrock::{BedrockError, ErrorCategory};
+
+        match error.category() {
+            ErrorCategory::Client => Self::InvalidRequest,
+            ErrorCategory::Content => Self::ContentBlocked,
+            ErrorCategory::RateLimit => Self::RateLimited,
+            ErrorCategory::Resource if matches!(error, BedrockError::TokenLimitExceeded(_)) => {
+                Self::TooLong
+            }
+            ErrorCategory::Network if matches!(error, BedrockError::Timeout(_)) => Self::Timeout,
+            ErrorCategory::Network | ErrorCategory::Server | ErrorCategory::Resource => {
+                Self::Unavailable
+            }
+            ErrorCategory::Authentication | ErrorCategory::Authorization => Self::Unauthorized,
+            _ => Self::Internal,
+        }
+    }
+
+    /// Code put in [`ResponseError::code`], e.g. `too_long`
+    #[must_use]
+    pub const fn code(self) -> &'static str {
+        match self {
+            Self::InvalidRequest => "invalid_request",
+            Self::TooLong => "too_long",
+            Self::ContentBlocked => "content_blocked",
+            Self::RateLimited => "rate_limited",
+            Self::BudgetExceeded => "budget_exceeded",
+            Self::Timeout => "timeout",
+            Self::Unavailable => "unavailable",
+            Self::Unauthorized => "unauthorized",
+            Self::NotFound => "not_found",
+            Self::Internal => "internal",
+        }
+    }
+
+    /// Whether sending the same message again may succeed
+    #[must_use]
+    pub const fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            Self::RateLimited | Self::Timeout | Self::Unavailable | Self::Internal
+        )
+    }
+
+    /// Whether the hint names a delay before retrying
+    const fn waits(self) -> bool {
+        matches!(self, Self::RateLimited | Self::Unavailable)
+    }
+}
+
+/// An error as shown to the user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserFacingError {
+    /// What went wrong
+    pub kind: UserErrorKind,
+    /// Short explanation in the user's language
+    pub message: String,
+    /// What the user can do about it
+    pub hint: Option<String>,
+    /// Seconds to wait before retrying, for rate limits and outages
+    pub retry_after: Option<u64>,
+}
+
+impl UserFacingError {
+    /// The message followed by the hint
+    #[must_use]
+    pub fn text(&self) -> String {
+        self.hint.as_ref().map_or_else(
+            || self.message.clone(),
+            |hint| format!("{} {hint}", self.message),
+        )
+    }
+
+    /// The error as carried by a response
+    #[must_use]
+    pub fn response_error(&self) -> ResponseError {
+        let mut error =
+            ResponseError::new(self.kind.code(), self.text()).retryable(self.kind.is_retryable());
+        if let Some(seconds) = self.retry_after {
+            error = error.retry_after(seconds);
+        }
+        if let Some(hint) = &self.hint {
+            error = error.with_hint(hint.clone());
+        }
+        error
+    }
+
+    /// An error response to the given conversation
+    #[must_use]
+    pub fn into_response(self, conversation_id: impl Into<String>) -> Response {
+        Response::error(conversation_id, self.response_error())
+    }
+}
+
+/// Localized messages and hints for each [`UserErrorKind`]
+#[derive(Debug, Clone)]
+pub struct ErrorCatalog {
+    entries: HashMap<(String, UserErrorKind), (String, Option<String>)>,
+    retry_after_seconds: u64,
+}
+
+impl Default for ErrorCatalog {
+    fn default() -> Self {
+        Self::from_config(&UserErrorConfig::default())
+    }
+}
+
+impl ErrorCatalog {
+    /// The built-in catalog with `config`'s delay and messages
+    #[must_use]
+    pub fn from_config(config: &UserErrorConfig) -> Self {
+        let mut catalog = Self {
+            entries: HashMap::new(),
+            retry_after_seconds: config.retry_after_seconds,
+        };
+        for (language, kind, message, hint) in BUILTIN_MESSAGES {
+            catalog = catalog.with_message(language, *kind, *message, Some(hint));
+        }
+        for entry in &config.messages {
+            catalog = catalog.with_message(
+                &entry.language,
+                entry.kind,
+                &entry.message,
+                entry.hint.as_deref(),
+            );
+        }
+        catalog
+    }
+
+    /// Use `message` and `hint` for `kind` in `language`, e.g. `de`
+    ///
+    /// Either may contain `{seconds}`, replaced by the retry delay.
+    #[must_use]
+    pub fn with_message(
+        mut self,
+        language: &str,
+        kind: UserErrorKind,
+        message: impl Into<String>,
+        hint: Option<&str>,
+    ) -> Self {
+        self.entries.insert(
+            (language.to_ascii_lowercase(), kind),
+            (message.into(), hint.map(str::to_string)),
+        );
+        self
+    }
+
+    /// How `error` is shown to a user with the given BCP 47 locale
+    #[must_use]
+    pub fn describe(&self, error: &anyhow::Error, locale: Option<&str>) -> UserFacingError {
+        self.message(UserErrorKind::of(error), locale)
+    }
+
+    /// The message for `kind` in the user's language, else English
+    #[must_use]
+    pub fn message(&self, kind: UserErrorKind, locale: Option<&str>) -> UserFacingError {
+        let language = locale
+            .and_then(|tag| tag.split(['-', '_']).next())
+            .map(str::to_ascii_lowercase);
+        let (message, hint) = language
+            .and_then(|language| self.entries.get(&(language, kind)))
+            .or_else(|| self.entries.get(&(FALLBACK_LANGUAGE.to_string(), kind)))
+            .cloned()
+            .unwrap_or_else(|| (kind.code().to_string(), None));
+        let seconds = self.retry_after_seconds.to_string();
+        let fill = |text: String| text.replace(SECONDS_PLACEHOLDER, &seconds);
+        UserFacingError {
+            kind,
+            message: fill(message),
+            hint: hint.map(fill),
+            retry_after: kind.waits().then_some(self.retry_after_seconds),
+        }
+    }
+}
+
+/// Language, kind, message, and hint
+type CatalogEntry = (&'static str, UserErrorKind, &'static str, &'static str);
+
+#[rustfmt::skip]
+const BUILTIN_MESSAGES: &[CatalogEntry] = &[
+    ("en", UserErrorKind::InvalidRequest, "I couldn't process that message.", "Try rephrasing it."),
+    ("en", UserErrorKind::TooLong, "That message is too long for me to read.", "Try a shorter message."),
+    ("en", UserErrorKind::ContentBlocked, "I can't help with that request.", "Try asking in a different way."),
+    ("en", UserErrorKind::RateLimited, "You're sending messages faster than I can answer.", "Try again in {seconds}s."),
+    ("en", UserErrorKind::BudgetExceeded, "The usage limit for this conversation has been reached.", "Contact your administrator to raise it."),
+    ("en", UserErrorKind::Timeout, "That took too long to answer.", "Try again, or ask a shorter question."),
+    ("en", UserErrorKind::Unavailable, "I'm temporarily unavailable.", "Try again in {seconds}s."),
+    ("en", UserErrorKind::Unauthorized, "You don't have access to this assistant.", "Sign in again or ask an administrator for access."),
+    ("en", UserErrorKind::NotFound, "I couldn't find that conversation.", "Start a new conversation."),
+    ("en", UserErrorKind::Internal, "Something went wrong on my side.", "Try again; if it keeps happening, contact support."),
+    ("de", UserErrorKind::InvalidRequest, "Diese Nachricht konnte ich nicht verarbeiten.", "Versuchen Sie es mit einer anderen Formulierung."),
+    ("de", UserErrorKind::TooLong, "Diese Nachricht ist zu lang für mich.", "Versuchen Sie es mit einer kürzeren Nachricht."),
+    ("de", UserErrorKind::ContentBlocked, "Bei dieser Anfrage kann ich nicht helfen.", "Versuchen Sie, anders zu fragen."),
+    ("de", UserErrorKind::RateLimited, "Sie senden Nachrichten schneller, als ich antworten kann.", "Versuchen Sie es in {seconds} s erneut."),
+    ("de", UserErrorKind::BudgetExceeded, "Das Nutzungslimit für diese Unterhaltung ist erreicht.", "Wenden Sie sich an Ihre Administration, um es zu erhöhen."),
+    ("de", UserErrorKind::Timeout, "Die Antwort hat zu lange gedauert.", "Versuchen Sie es erneut oder stellen Sie eine kürzere Frage."),
+    ("de", UserErrorKind::Unavailable, "Ich bin vorübergehend nicht erreichbar.", "Versuchen Sie es in {seconds} s erneut."),
+    ("de", UserErrorKind::Unauthorized, "Sie haben keinen Zugriff auf diesen Assistenten.", "Melden Sie sich erneut an oder bitten Sie um Zugriff."),
+    ("de", UserErrorKind::NotFound, "Diese Unterhaltung konnte ich nicht finden.", "Beginnen Sie eine neue Unterhaltung."),
+    ("de", UserErrorKind::Internal, "Bei mir ist etwas schiefgelaufen.", "Versuchen Sie es erneut; wenden Sie sich an den Support, falls es wieder passiert."),
+    ("es", UserErrorKind::InvalidRequest, "No pude procesar ese mensaje.", "Prueba a reformularlo."),
+    ("es", UserErrorKind::TooLong, "Ese mensaje es demasiado largo para mí.", "Prueba con un mensaje más corto."),
+    ("es", UserErrorKind::ContentBlocked, "No puedo ayudar con esa solicitud.", "Prueba a preguntar de otra forma."),
+    ("es", UserErrorKind::RateLimited, "Estás enviando mensajes más rápido de lo que puedo responder.", "Vuelve a intentarlo en {seconds} s."),
+    ("es", UserErrorKind::BudgetExceeded, "Se ha alcanzado el límite de uso de esta conversación.", "Contacta con tu administrador para ampliarlo."),
+    ("es", UserErrorKind::Timeout, "La respuesta tardó demasiado.", "Vuelve a intentarlo o haz una pregunta más corta."),
+    ("es", UserErrorKind::Unavailable, "No estoy disponible temporalmente.", "Vuelve a intentarlo en {seconds} s."),
+    ("es", UserErrorKind::Unauthorized, "No tienes acceso a este asistente.", "Inicia sesión de nuevo o pide acceso a un administrador."),
+    ("es", UserErrorKind::NotFound, "No encontré esa conversación.", "Empieza una conversación nueva."),
+    ("es", UserErrorKind::Internal, "Algo salió mal por mi parte.", "Vuelve a intentarlo; si sigue ocurriendo, contacta con soporte."),
+    ("fr", UserErrorKind::InvalidRequest, "Je n'ai pas pu traiter ce message.", "Essayez de le reformuler."),
+    ("fr", UserErrorKind::TooLong, "Ce message est trop long pour moi.", "Essayez un message plus court."),
+    ("fr", UserErrorKind::ContentBlocked, "Je ne peux pas répondre à cette demande.", "Essayez de la formuler autrement."),
+    ("fr", UserErrorKind::RateLimited, "Vous envoyez des messages plus vite que je ne peux répondre.", "Réessayez dans {seconds} s."),
+    ("fr", UserErrorKind::BudgetExceeded, "La limite d'utilisation de cette conversation est atteinte.", "Contactez votre administrateur pour l'augmenter."),
+    ("fr", UserErrorKind::Timeout, "La réponse a pris trop de temps.", "Réessayez ou posez une question plus courte."),
+    ("fr", UserErrorKind::Unavailable, "Je suis temporairement indisponible.", "Réessayez dans {seconds} s."),
+    ("fr", UserErrorKind::Unauthorized, "Vous n'avez pas accès à cet assistant.", "Reconnectez-vous ou demandez l'accès à un administrateur."),
+    ("fr", UserErrorKind::NotFound, "Je n'ai pas trouvé cette conversation.", "Commencez une nouvelle conversation."),
+    ("fr", UserErrorKind::Internal, "Un problème est survenu de mon côté.", "Réessayez ; si cela se reproduit, contactez le support."),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::UserErrorMessage;
+
+    #[test]
+    fn test_errors_are_classified_through_context() {
+        let error = anyhow::Error::from(Error::RateLimit).context("Processing stage failed");
+        assert_eq!(UserErrorKind::of(&error), UserErrorKind::RateLimited);
+        let error = anyhow::anyhow!("socket closed at 10.0.0.3");
+        assert_eq!(UserErrorKind::of(&error), UserErrorKind::Internal);
+
+        let shown = ErrorCatalog::default().describe(&Error::RateLimit.into(), Some("en-GB"));
+        assert_eq!(
+            shown.text(),
+            "You're sending messages faster than I can answer. Try again in 30s."
+        );
+        assert_eq!(shown.retry_after, Some(30));
+        let response = shown.response_error();
+        assert_eq!(response.code, "rate_limited");
+        assert!(response.retryable);
+    }
+
+    #[test]
+    fn test_messages_are_localized_with_fallback() {
+        let config = UserErrorConfig {
+            messages: vec![UserErrorMessage {
+                language: "nl".to_string(),
+                kind: UserErrorKind::TooLong,
+                message: "Dat bericht is te lang.".to_string(),
+                hint: None,
+            }],
+            ..UserErrorConfig::default()
+        };
+        let catalog = ErrorCatalog::from_config(&config);
+
+        let german = catalog.message(UserErrorKind::Timeout, Some("de-AT"));
+        assert_eq!(german.message, "Die Antwort hat zu lange gedauert.");
+        assert_eq!(
+            catalog.message(UserErrorKind::TooLong, Some("nl")).text(),
+            "Dat bericht is te lang."
+        );
+        let fallback = catalog.message(UserErrorKind::TooLong, Some("ja-JP"));
+        assert_eq!(fallback.hint.as_deref(), Some("Try a shorter message."));
+        for kind in UserErrorKind::ALL {
+            assert_ne!(catalog.message(kind, Some("fr")).message, kind.code());
+        }
+    }
+}
@@ -0,0 +1,885 @@
+//! Vector storage and approximate nearest neighbour search
+//!
+//! This module defines the [`VectorStore`] abstraction used for retrieval
+//! workloads, a backend-agnostic [`MetadataFilter`], and an in-memory HNSW
+//! index ([`HnswIndex`]) suitable for development and small deployments.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+
+use crate::error::Error;
+
+/// A vector with its source text and metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VectorRecord {
+    /// Unique record ID
+    pub id: String,
+    /// Embedding vector
+    pub vector: Vec<f32>,
+    /// Source text the vector was computed from
+    pub content: String,
+    /// Record metadata used for filtering
+    pub metadata: HashMap<String, serde_json::Value>,
+}
+
+impl VectorRecord {
+    /// Create a new record
+    #[must_use]
+    pub fn new(id: impl Into<String>, vector: Vec<f32>, content: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            vector,
+            content: content.into(),
+            metadata: HashMap::new(),
+        }
+    }
+
+    /// Add metadata
+    #[must_use]
+    pub fn with_metadata(mut self, key: impl Into<String>, value: serde_json::Value) -> Self {
+        self.metadata.insert(key.into(), value);
+        self
+    }
+}
+
+/// A search hit with its similarity score
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoredRecord {
+    /// The matching record
+    pub record: VectorRecord,
+    /// Similarity score (higher is more similar)
+    pub score: f32,
+}
+
+/// Metadata filter applied to search results
+///
+/// Filters are expressed independently of the backend so the same query can
+/// be sent to the in-memory index or a remote vector database.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataFilter {
+    /// Field equals the value
+    Eq(String, serde_json::Value),
+    /// Field does not equal the value
+    Ne(String, serde_json::Value),
+    /// Field equals one of the values
+    In(String, Vec<serde_json::Value>),
+    /// Field is present
+    Exists(String),
+    /// All filters match
+    And(Vec<Self>),
+    /// Any filter matches
+    Or(Vec<Self>),
+    /// Filter does not match
+    Not(Box<Self>),
+}
+
+impl MetadataFilter {
+    /// Create an equality filter
+    pub fn eq(key: impl Into<String>, value: serde_json::Value) -> Self {
+        Self::Eq(key.into(), value)
+    }
+
+    /// Create a membership filter
+    pub fn one_of(key: impl Into<String>, values: Vec<serde_json::Value>) -> Self {
+        Self::In(key.into(), values)
+    }
+
+    /// Check whether the metadata satisfies the filter
+    #[must_use]
+    pub fn matches(&self, metadata: &HashMap<String, serde_json::Value>) -> bool {
+        match self {
+            Self::Eq(key, value) => metadata.get(key) == Some(value),
+            Self::Ne(key, value) => metadata.get(key) != Some(value),
+            Self::In(key, values) => metadata.get(key).is_some_and(|v| values.contains(v)),
+            Self::Exists(key) => metadata.contains_key(key),
+            Self::And(filters) => filters.iter().all(|f| f.matches(metadata)),
+            Self::Or(filters) => filters.iter().any(|f| f.matches(metadata)),
+            Self::Not(filter) => !filter.matches(metadata),
+        }
+    }
+}
+
+/// Distance metric used to compare vectors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceMetric {
+    /// Cosine distance (1 - cosine similarity)
+    #[default]
+    Cosine,
+    /// Euclidean (L2) distance
+    Euclidean,
+    /// Negative dot product
+    DotProduct,
+}
+
+impl DistanceMetric {
+    /// Compute the distance between two vectors (lower is closer)
+    #[must_use]
+    pub fn distance(self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Self::Cosine => {
+                let (mut dot, mut norm_a, mut norm_b) = (0.0f32, 0.0f32, 0.0f32);
+                for (x, y) in a.iter().zip(b) {
+                    dot += x * y;
+                    norm_a += x * x;
+                    norm_b += y * y;
+                }
+                let denom = norm_a.sqrt() * norm_b.sqrt();
+                if denom == 0.0 {
+                    1.0
+                } else {
+                    1.0 - dot / denom
+                }
+            }
+            Self::Euclidean => a
+                .iter()
+                .zip(b)
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum::<f32>()
+                .sqrt(),
+            Self::DotProduct => -a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>(),
+        }
+    }
+
+    /// Convert a distance into a similarity score (higher is closer)
+    #[must_use]
+    pub fn score(self, distance: f32) -> f32 {
+        match self {
+            Self::Cosine => 1.0 - distance,
+            Self::Euclidean => 1.0 / (1.0 + distance),
+            Self::DotProduct => -distance,
+        }
+    }
+}
+
+/// Vector store trait for retrieval backends
+#[async_trait]
+pub trait VectorStore: Send + Sync {
+    /// Insert or replace records
+    async fn upsert(&self, records: Vec<VectorRecord>) -> Result<()>;
+
+    /// Search for the `top_k` nearest records, optionally filtered by metadata
+    async fn search(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        filter: Option<&MetadataFilter>,
+    ) -> Result<Vec<ScoredRecord>>;
+
+    /// Get a record by ID
+    async fn get(&self, id: &str) -> Result<Option<VectorRecord>>;
+
+    /// Delete records by ID, returning how many were removed
+    async fn delete(&self, ids: &[String]) -> Result<usize>;
+
+    /// Number of live records in the store
+    async fn len(&self) -> Result<usize>;
+
+    /// Check whether the store is empty
+    async fn is_empty(&self) -> Result<bool> {
+        Ok(self.len().await? == 0)
+    }
+}
+
+/// Embedding model trait used to vectorize text
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    /// Embedding model identifier
+    fn model(&self) -> &str;
+
+    /// Embed a batch of texts, returning one vector per input in order
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// Configuration for the HNSW index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HnswConfig {
+    /// Maximum neighbours per node on upper layers (layer 0 uses twice this)
+    pub m: usize,
+    /// Candidate list size during construction
+    pub ef_construction: usize,
+    /// Candidate list size during search
+    pub ef_search: usize,
+    /// Distance metric
+    pub metric: DistanceMetric,
+    /// Seed for level generation, making index layout reproducible
+    pub seed: u64,
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 200,
+            ef_search: 64,
+            metric: DistanceMetric::Cosine,
+            seed: 42,
+        }
+    }
+}
+
+/// In-memory HNSW (Hierarchical Navigable Small World) index
+///
+/// Deleted records are tombstoned so the graph stays navigable; they are
+/// excluded from results, and the graph is rebuilt from the live records
+/// once tombstones outnumber them, so repeated upserts do not grow it
+/// without bound.
+pub struct HnswIndex {
+    graph: RwLock<HnswGraph>,
+}
+
+impl HnswIndex {
+    /// Create an empty index
+    #[must_use]
+    pub fn new(config: HnswConfig) -> Self {
+        Self {
+            graph: RwLock::new(HnswGraph::new(config)),
+        }
+    }
+
+    /// Get the index configuration
+    #[must_use]
+    pub fn config(&self) -> HnswConfig {
+        self.graph.read().config.clone()
+    }
+
+    /// Persist the index to disk
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if serialization or the file write fails.
+    #[instrument(skip(self, path), fields(path = %path.as_ref().display()))]
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let snapshot = self.graph.read().snapshot()?;
+        let bytes = bincode::serialize(&snapshot)
+            .map_err(|e| Error::Serialization(format!("Failed to encode HNSW index: {e}")))?;
+        std::fs::write(path.as_ref(), bytes).context("Failed to write HNSW index file")?;
+        debug!("Saved HNSW index with {} records", snapshot.records.len());
+        Ok(())
+    }
+
+    /// Load an index previously written with [`HnswIndex::save`]
+    ///
+    /// The graph is rebuilt from the stored records using the stored
+    /// configuration, so the result is identical to the saved index.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or decoded.
+    #[instrument(skip(path), fields(path = %path.as_ref().display()))]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let bytes = std::fs::read(path.as_ref()).context("Failed to read HNSW index file")?;
+        let snapshot: HnswSnapshot = bincode::deserialize(&bytes)
+            .map_err(|e| Error::Serialization(format!("Failed to decode HNSW index: {e}")))?;
+
+        let mut graph = HnswGraph::new(snapshot.config);
+        for stored in snapshot.records {
+            graph.insert(stored.into_record()?)?;
+        }
+        debug!("Loaded HNSW index with {} records", graph.live);
+
+        Ok(Self {
+            graph: RwLock::new(graph),
+        })
+    }
+}
+
+impl Default for HnswIndex {
+    fn default() -> Self {
+        Self::new(HnswConfig::default())
+    }
+}
+
+#[async_trait]
+impl VectorStore for HnswIndex {
+    async fn upsert(&self, records: Vec<VectorRecord>) -> Result<()> {
+        let mut graph = self.graph.write();
+        records
+            .into_iter()
+            .try_for_each(|record| graph.insert(record))?;
+        graph.compact_if_needed()
+    }
+
+    async fn search(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        filter: Option<&MetadataFilter>,
+    ) -> Result<Vec<ScoredRecord>> {
+        self.graph.read().search(query, top_k, filter)
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<VectorRecord>> {
+        let graph = self.graph.read();
+        Ok(graph
+            .ids
+            .get(id)
+            .map(|&idx| graph.nodes[idx].record.clone()))
+    }
+
+    async fn delete(&self, ids: &[String]) -> Result<usize> {
+        let mut graph = self.graph.write();
+        let removed = ids.iter().filter(|id| graph.remove(id)).count();
+        graph.compact_if_needed()?;
+        drop(graph);
+        Ok(removed)
+    }
+
+    async fn len(&self) -> Result<usize> {
+        Ok(self.graph.read().live)
+    }
+}
+
+struct HnswNode {
+    record: VectorRecord,
+    /// Neighbour lists, one per layer the node participates in
+    neighbours: Vec<Vec<usize>>,
+    deleted: bool,
+}
+
+struct HnswGraph {
+    config: HnswConfig,
+    nodes: Vec<HnswNode>,
+    ids: HashMap<String, usize>,
+    entry_point: Option<usize>,
+    dimension: Option<usize>,
+    live: usize,
+    rng: fastrand::Rng,
+}
+
+impl HnswGraph {
+    fn new(config: HnswConfig) -> Self {
+        let rng = fastrand::Rng::with_seed(config.seed);
+        Self {
+            config,
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+            entry_point: None,
+            dimension: None,
+            live: 0,
+            rng,
+        }
+    }
+
+    fn distance(&self, query: &[f32], idx: usize) -> f32 {
+        self.config
+            .metric
+            .distance(query, &self.nodes[idx].record.vector)
+    }
+
+    fn max_neighbours(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.config.m * 2
+        } else {
+            self.config.m
+        }
+    }
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn random_level(&mut self) -> usize {
+        let ml = 1.0 / (self.config.m.max(2) as f64).ln();
+        let uniform = self.rng.f64().max(f64::MIN_POSITIVE);
+        (-uniform.ln() * ml).floor() as usize
+    }
+
+    fn check_dimension(&mut self, vector: &[f32]) -> Result<()> {
+        if vector.is_empty() {
+            return Err(Error::InvalidInput("Vector must not be empty".to_string()).into());
+        }
+        match self.dimension {
+            Some(dim) if dim != vector.len() => Err(Error::InvalidInput(format!(
+                "Vector dimension {} does not match index dimension {dim}",
+                vector.len()
+            ))
+            .into()),
+            Some(_) => Ok(()),
+            None => {
+                self.dimension = Some(vector.len());
+                Ok(())
+            }
+        }
+    }
+
+    fn insert(&mut self, record: VectorRecord) -> Result<()> {
+        self.check_dimension(&record.vector)?;
+        self.remove(&record.id);
+
+        let level = self.random_level();
+        let idx = self.nodes.len();
+        let query = record.vector.clone();
+        self.ids.insert(record.id.clone(), idx);
+        self.nodes.push(HnswNode {
+            record,
+            neighbours: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+        self.live += 1;
+
+        let Some(entry) = self.entry_point else {
+            self.entry_point = Some(idx);
+            return Ok(());
+        };
+
+        let top_level = self.nodes[entry].neighbours.len() - 1;
+        let mut current = entry;
+        for layer in (level + 1..=top_level).rev() {
+            current = self.greedy_closest(&query, current, layer);
+        }
+
+        let mut entry_points = vec![current];
+        for layer in (0..=level.min(top_level)).rev() {
+            let candidates =
+                self.search_layer(&query, &entry_points, self.config.ef_construction, layer);
+            let selected: Vec<usize> = candidates
+                .iter()
+                .take(self.max_neighbours(layer))
+                .map(|c| c.idx)
+                .collect();
+
+            for &neighbour in &selected {
+                self.nodes[neighbour].neighbours[layer].push(idx);
+                self.prune(neighbour, layer);
+            }
+            self.nodes[idx].neighbours[layer] = selected;
+            entry_points = candidates.into_iter().map(|c| c.idx).collect();
+        }
+
+        if level > top_level {
+            self.entry_point = Some(idx);
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, id: &str) -> bool {
+        match self.ids.remove(id) {
+            Some(idx) => {
+                self.nodes[idx].deleted = true;
+                self.live -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Rebuild the graph from the live records once tombstones outnumber them
+    fn compact_if_needed(&mut self) -> Result<()> {
+        let tombstones = self.nodes.len() - self.live;
+        if tombstones <= self.live {
+            return Ok(());
+        }
+        let mut graph = Self::new(self.config.clone());
+        graph.dimension = self.dimension;
+        for node in std::mem::take(&mut self.nodes) {
+            if !node.deleted {
+                graph.insert(node.record)?;
+            }
+        }
+        debug!("Compacted HNSW index, dropping {} tombstones", tombstones);
+        *self = graph;
+        Ok(())
+    }
+
+    fn prune(&mut self, idx: usize, layer: usize) {
+        let max = self.max_neighbours(layer);
+        if self.nodes[idx].neighbours[layer].len() <= max {
+            return;
+        }
+        let base = self.nodes[idx].record.vector.clone();
+        let mut scored: Vec<Candidate> = self.nodes[idx].neighbours[layer]
+            .iter()
+            .map(|&n| Candidate {
+                distance: self.distance(&base, n),
+                idx: n,
+            })
+            .collect();
+        scored.sort();
+        scored.truncate(max);
+        self.nodes[idx].neighbours[layer] = scored.into_iter().map(|c| c.idx).collect();
+    }
+
+    fn greedy_closest(&self, query: &[f32], start: usize, layer: usize) -> usize {
+        let mut current = start;
+        let mut best = self.distance(query, current);
+        loop {
+            let mut improved = false;
+            for &neighbour in &self.nodes[current].neighbours[layer] {
+                let distance = self.distance(query, neighbour);
+                if distance < best {
+                    best = distance;
+                    current = neighbour;
+                    improved = true;
+                }
+            }
+            if !improved {
+                return current;
+            }
+        }
+    }
+
+    /// Beam search on a single layer, returning candidates sorted by distance
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entry_points: &[usize],
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Candidate> {
+        let mut visited: HashSet<usize> = entry_points.iter().copied().collect();
+        let mut frontier: BinaryHeap<std::cmp::Reverse<Candidate>> = BinaryHeap::new();
+        let mut results: BinaryHeap<Candidate> = BinaryHeap::new();
+
+        for &idx in entry_points {
+            let candidate = Candidate {
+                distance: self.distance(query, idx),
+                idx,
+            };
+            frontier.push(std::cmp::Reverse(candidate));
+            results.push(candidate);
+        }
+
+        while let Some(std::cmp::Reverse(closest)) = frontier.pop() {
+            if results.len() >= ef
+                && results
+                    .peek()
+                    .is_some_and(|furthest| closest.distance > furthest.distance)
+            {
+                break;
+            }
+
+            for &neighbour in &self.nodes[closest.idx].neighbours[layer] {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let candidate = Candidate {
+                    distance: self.distance(query, neighbour),
+                    idx: neighbour,
+                };
+                let admit = results.len() < ef
+                    || results
+                        .peek()
+                        .is_some_and(|furthest| candidate.distance < furthest.distance);
+                if admit {
+                    frontier.push(std::cmp::Reverse(candidate));
+                    results.push(candidate);
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        results.into_sorted_vec()
+    }
+
+    fn search(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        filter: Option<&MetadataFilter>,
+    ) -> Result<Vec<ScoredRecord>> {
+        if let Some(dim) = self.dimension {
+            if dim != query.len() {
+                return Err(Error::InvalidInput(format!(
+                    "Query dimension {} does not match index dimension {dim}",
+                    query.len()
+                ))
+                .into());
+            }
+        }
+
+        let Some(entry) = self.entry_point else {
+            return Ok(Vec::new());
+        };
+        if top_k == 0 {
+            return Ok(Vec::new());
+        }
+
+        let accept = |idx: usize| {
+            let node = &self.nodes[idx];
+            !node.deleted && filter.is_none_or(|f| f.matches(&node.record.metadata))
+        };
+
+        let top_level = self.nodes[entry].neighbours.len() - 1;
+        let mut current = entry;
+        for layer in (1..=top_level).rev() {
+            current = self.greedy_closest(query, current, layer);
+        }
+
+        let ef = self.config.ef_search.max(top_k);
+        let mut hits: Vec<Candidate> = self
+            .search_layer(query, &[current], ef, 0)
+            .into_iter()
+            .filter(|c| accept(c.idx))
+            .take(top_k)
+            .collect();
+
+        // Restrictive filters or many tombstones can starve the beam search;
+        // fall back to an exact scan so callers always get the true top-k.
+        if hits.len() < top_k && hits.len() < self.live {
+            hits = (0..self.nodes.len())
+                .filter(|&idx| accept(idx))
+                .map(|idx| Candidate {
+                    distance: self.distance(query, idx),
+                    idx,
+                })
+                .collect();
+            hits.sort();
+            hits.truncate(top_k);
+        }
+
+        Ok(hits
+            .into_iter()
+            .map(|c| ScoredRecord {
+                record: self.nodes[c.idx].record.clone(),
+                score: self.config.metric.score(c.distance),
+            })
+            .collect())
+    }
+
+    fn snapshot(&self) -> Result<HnswSnapshot> {
+        let records = self
+            .nodes
+            .iter()
+            .filter(|node| !node.deleted)
+            .map(|node| StoredRecord::from_record(&node.record))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(HnswSnapshot {
+            config: self.config.clone(),
+            records,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    distance: f32,
+    idx: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.idx.cmp(&other.idx))
+    }
+}
+
+/// On-disk representation of the index
+///
+/// Metadata is stored as a JSON string because bincode cannot decode
+/// self-describing `serde_json::Value`s.
+#[derive(Serialize, Deserialize)]
+struct HnswSnapshot {
+    config: HnswConfig,
+    records: Vec<StoredRecord>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredRecord {
+    id: String,
+    vector: Vec<f32>,
+    content: String,
+    metadata: String,
+}
+
+impl StoredRecord {
+    fn from_record(record: &VectorRecord) -> Result<Self> {
+        Ok(Self {
+            id: record.id.clone(),
+            vector: record.vector.clone(),
+            content: record.content.clone(),
+            metadata: serde_json::to_string(&record.metadata)?,
+        })
+    }
+
+    fn into_record(self) -> Result<VectorRecord> {
+        Ok(VectorRecord {
+            id: self.id,
+            vector: self.vector,
+            content: self.content,
+            metadata: serde_json::from_str(&self.metadata)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, vector: Vec<f32>) -> VectorRecord {
+        VectorRecord::new(id, vector, format!("content {id}"))
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn grid(n: usize) -> Vec<VectorRecord> {
+        (0..n)
+            .map(|i| {
+                let angle = i as f32 * 0.01;
+                record(&format!("doc-{i}"), vec![angle.cos(), angle.sin(), 0.5])
+                    .with_metadata("parity", serde_json::json!(i % 2))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_distance_metrics() {
+        let a = [1.0, 0.0];
+        let b = [0.0, 1.0];
+        assert!(DistanceMetric::Cosine.distance(&a, &a).abs() < 1e-6);
+        assert!((DistanceMetric::Cosine.distance(&a, &b) - 1.0).abs() < 1e-6);
+        assert!((DistanceMetric::Euclidean.distance(&a, &b) - 2.0f32.sqrt()).abs() < 1e-6);
+        assert!((DistanceMetric::DotProduct.distance(&a, &a) + 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_metadata_filter() {
+        let mut metadata = HashMap::new();
+        metadata.insert("lang".to_string(), serde_json::json!("en"));
+        metadata.insert("year".to_string(), serde_json::json!(2024));
+
+        assert!(MetadataFilter::eq("lang", serde_json::json!("en")).matches(&metadata));
+        assert!(!MetadataFilter::eq("lang", serde_json::json!("de")).matches(&metadata));
+        assert!(MetadataFilter::one_of(
+            "year",
+            vec![serde_json::json!(2023), serde_json::json!(2024)]
+        )
+        .matches(&metadata));
+        assert!(MetadataFilter::And(vec![
+            MetadataFilter::Exists("lang".to_string()),
+            MetadataFilter::Not(Box::new(MetadataFilter::Exists("author".to_string()))),
+        ])
+        .matches(&metadata));
+    }
+
+    #[tokio::test]
+    async fn test_search_returns_nearest() {
+        let index = HnswIndex::default();
+        index.upsert(grid(200)).await.unwrap();
+        assert_eq!(index.len().await.unwrap(), 200);
+
+        let query = [0.0f32.cos(), 0.0f32.sin(), 0.5];
+        let hits = index.search(&query, 3, None).await.unwrap();
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[0].record.id, "doc-0");
+        assert!(hits[0].score >= hits[1].score);
+    }
+
+    #[tokio::test]
+    async fn test_search_with_filter() {
+        let index = HnswIndex::default();
+        index.upsert(grid(100)).await.unwrap();
+
+        let filter = MetadataFilter::eq("parity", serde_json::json!(1));
+        let hits = index
+            .search(&[1.0, 0.0, 0.5], 5, Some(&filter))
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 5);
+        assert!(hits
+            .iter()
+            .all(|h| h.record.metadata["parity"] == serde_json::json!(1)));
+        assert_eq!(hits[0].record.id, "doc-1");
+    }
+
+    #[tokio::test]
+    async fn test_upsert_and_delete() {
+        let index = HnswIndex::default();
+        index.upsert(grid(10)).await.unwrap();
+
+        index
+            .upsert(vec![record("doc-3", vec![0.0, 0.0, 1.0])])
+            .await
+            .unwrap();
+        assert_eq!(index.len().await.unwrap(), 10);
+        let updated = index.get("doc-3").await.unwrap().unwrap();
+        assert_eq!(updated.vector, vec![0.0, 0.0, 1.0]);
+
+        let removed = index
+            .delete(&["doc-3".to_string(), "missing".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(removed, 1);
+        assert!(index.get("doc-3").await.unwrap().is_none());
+
+        let hits = index.search(&[0.0, 0.0, 1.0], 10, None).await.unwrap();
+        assert_eq!(hits.len(), 9);
+        assert!(hits.iter().all(|h| h.record.id != "doc-3"));
+    }
+
+    #[tokio::test]
+    async fn test_repeated_upserts_stay_bounded() {
+        let index = HnswIndex::default();
+        index.upsert(grid(100)).await.unwrap();
+
+        for _ in 0..20 {
+            index.upsert(grid(100)).await.unwrap();
+            assert_eq!(index.len().await.unwrap(), 100);
+            assert!(index.graph.read().nodes.len() <= 200);
+        }
+        index
+            .delete(&(0..60).map(|i| format!("doc-{i}")).collect::<Vec<_>>())
+            .await
+            .unwrap();
+        assert_eq!(index.graph.read().nodes.len(), 40);
+
+        let hits = index.search(&[1.0, 0.0, 0.5], 5, None).await.unwrap();
+        assert_eq!(hits.len(), 5);
+        assert_eq!(hits[0].record.id, "doc-60");
+    }
+
+    #[tokio::test]
+    async fn test_dimension_mismatch() {
+        let index = HnswIndex::default();
+        index.upsert(grid(5)).await.unwrap();
+
+        assert!(index.upsert(vec![record("bad", vec![1.0])]).await.is_err());
+        assert!(index.search(&[1.0, 0.0], 1, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load() {
+        let index = HnswIndex::default();
+        index.upsert(grid(50)).await.unwrap();
+        index.delete(&["doc-0".to_string()]).await.unwrap();
+
+        let path = std::env::temp_dir().join(format!("hnsw-{}.bin", uuid::Uuid::new_v4()));
+        index.save(&path).unwrap();
+        let loaded = HnswIndex::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len().await.unwrap(), 49);
+        let query = [1.0, 0.0, 0.5];
+        let original = index.search(&query, 5, None).await.unwrap();
+        let restored = loaded.search(&query, 5, None).await.unwrap();
+        let ids =
+            |hits: &[ScoredRecord]| hits.iter().map(|h| h.record.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&original), ids(&restored));
+        assert_eq!(restored[0].record.metadata["parity"], serde_json::json!(1));
+    }
+}
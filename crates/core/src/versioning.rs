@@ -0,0 +1,389 @@
+//! Blue/green configuration versions
+//!
+//! Every effective configuration registered with a [`ConfigVersionRegistry`]
+//! gets a sequential version ID and its own ready-built pipeline, so
+//! switching between versions is a pointer swap rather than a rebuild.
+//! Responses are stamped with the version that produced them under
+//! [`CONFIG_VERSION_METADATA_KEY`], and request counts, errors, and latency
+//! are tracked per version so a regression can be spotted and rolled back.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    config::BotConfig, error::Error, pipeline::MessagePipeline, provider::Provider,
+    retrieval::Retriever,
+};
+
+/// Response metadata key holding the ID of the configuration version that produced it
+pub const CONFIG_VERSION_METADATA_KEY: &str = "config_version";
+
+/// Request outcomes under one configuration version
+#[derive(Debug, Default)]
+struct VersionCounters {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    latency_micros: AtomicU64,
+}
+
+/// Request outcomes under one configuration version, for comparison
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionMetrics {
+    /// Configuration version
+    pub version: u32,
+    /// Whether the version is serving traffic
+    pub active: bool,
+    /// Requests that reached the pipeline
+    pub requests: u64,
+    /// Requests that failed or produced an error response
+    pub errors: u64,
+    /// Mean pipeline latency
+    pub average_latency: Option<Duration>,
+}
+
+impl VersionMetrics {
+    /// Share of requests that failed
+    #[must_use]
+    pub fn error_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.requests as f64
+        }
+    }
+}
+
+/// One registered configuration and the pipeline built from it
+pub struct ConfigVersion {
+    id: u32,
+    hash: String,
+    created_at: DateTime<Utc>,
+    config: Arc<BotConfig>,
+    pipeline: Arc<MessagePipeline>,
+    counters: VersionCounters,
+}
+
+impl ConfigVersion {
+    /// Sequential version ID, starting at 1
+    #[must_use]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Hash of the full configuration, stable across builds
+    #[must_use]
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// When the version was registered
+    #[must_use]
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    /// The configuration
+    #[must_use]
+    pub fn config(&self) -> &Arc<BotConfig> {
+        &self.config
+    }
+
+    /// The pipeline built from the configuration
+    #[must_use]
+    pub fn pipeline(&self) -> &Arc<MessagePipeline> {
+        &self.pipeline
+    }
+
+    /// Record the outcome of a request served by this version
+    pub fn record(&self, latency: Duration, failed: bool) {
+        self.counters.requests.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.counters.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        self.counters
+            .latency_micros
+            .fetch_add(micros, Ordering::Relaxed);
+    }
+
+    fn metrics(&self, active: bool) -> VersionMetrics {
+        let requests = self.counters.requests.load(Ordering::Relaxed);
+        let latency = self.counters.latency_micros.load(Ordering::Relaxed);
+        VersionMetrics {
+            version: self.id,
+            active,
+            requests,
+            errors: self.counters.errors.load(Ordering::Relaxed),
+            average_latency: (requests > 0).then(|| Duration::from_micros(latency / requests)),
+        }
+    }
+}
+
+impl std::fmt::Debug for ConfigVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConfigVersion")
+            .field("id", &self.id)
+            .field("hash", &self.hash)
+            .field("created_at", &self.created_at)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct RegistryState {
+    versions: Vec<Arc<ConfigVersion>>,
+    active: Arc<ConfigVersion>,
+    previous: Option<u32>,
+}
+
+/// Registered configuration versions and the one serving traffic
+pub struct ConfigVersionRegistry {
+    state: RwLock<RegistryState>,
+    provider: Option<Arc<dyn Provider>>,
+    retriever: Option<Arc<Retriever>>,
+}
+
+impl std::fmt::Debug for ConfigVersionRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConfigVersionRegistry")
+            .field("state", &self.state)
+            .field("provider", &self.provider.as_ref().map(|p| p.name()))
+            .field("retriever", &self.retriever)
+            .finish()
+    }
+}
+
+impl ConfigVersionRegistry {
+    /// Create a registry whose first, active version is `config`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pipeline cannot be built.
+    pub async fn new(config: BotConfig) -> Result<Self> {
+        Self::with_provider(config, None).await
+    }
+
+    /// Create a registry whose pipelines all generate replies with `provider`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pipeline cannot be built.
+    pub async fn with_provider(
+        config: BotConfig,
+        provider: Option<Arc<dyn Provider>>,
+    ) -> Result<Self> {
+        Self::with_components(config, provider, None).await
+    }
+
+    /// Create a registry whose pipelines all use `provider` and `retriever`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pipeline cannot be built.
+    pub async fn with_components(
+        config: BotConfig,
+        provider: Option<Arc<dyn Provider>>,
+        retriever: Option<Arc<Retriever>>,
+    ) -> Result<Self> {
+        let initial =
+            Arc::new(build_version(1, config, provider.clone(), retriever.clone()).await?);
+        Ok(Self {
+            state: RwLock::new(RegistryState {
+                versions: vec![initial.clone()],
+                active: initial,
+                previous: None,
+            }),
+            provider,
+            retriever,
+        })
+    }
+
+    /// Register a configuration without activating it
+    ///
+    /// Registering a configuration identical to an existing version returns
+    /// that version's ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration is invalid or its pipeline
+    /// cannot be built.
+    pub async fn register(&self, config: BotConfig) -> Result<u32> {
+        config.validate()?;
+        let hash = config_hash(&config)?;
+        if let Some(existing) = self.state.read().versions.iter().find(|v| v.hash == hash) {
+            return Ok(existing.id);
+        }
+
+        let mut version =
+            build_version(0, config, self.provider.clone(), self.retriever.clone()).await?;
+        let mut state = self.state.write();
+        if let Some(existing) = state.versions.iter().find(|v| v.hash == version.hash) {
+            return Ok(existing.id);
+        }
+        version.id = state.versions.last().map_or(1, |last| last.id + 1);
+        let id = version.id;
+        state.versions.push(Arc::new(version));
+        drop(state);
+        info!("Registered configuration version {}", id);
+        Ok(id)
+    }
+
+    /// Serve traffic from version `id`, effective for the next request
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if no such version is registered.
+    pub fn activate(&self, id: u32) -> Result<Arc<ConfigVersion>> {
+        let mut state = self.state.write();
+        let version = state
+            .versions
+            .iter()
+            .find(|v| v.id == id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("Configuration version {id} not found")))?;
+        if state.active.id != id {
+            state.previous = Some(state.active.id);
+            state.active = version.clone();
+            drop(state);
+            info!("Activated configuration version {}", id);
+        }
+        Ok(version)
+    }
+
+    /// Return to the version that was active before the last activation
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if no other version has been active.
+    pub fn rollback(&self) -> Result<Arc<ConfigVersion>> {
+        let previous = self.state.read().previous.ok_or_else(|| {
+            Error::NotFound("No previous configuration version to roll back to".into())
+        })?;
+        self.activate(previous)
+    }
+
+    /// The version serving traffic
+    #[must_use]
+    pub fn active(&self) -> Arc<ConfigVersion> {
+        self.state.read().active.clone()
+    }
+
+    /// A registered version
+    #[must_use]
+    pub fn get(&self, id: u32) -> Option<Arc<ConfigVersion>> {
+        self.state
+            .read()
+            .versions
+            .iter()
+            .find(|v| v.id == id)
+            .cloned()
+    }
+
+    /// All registered versions, oldest first
+    #[must_use]
+    pub fn versions(&self) -> Vec<Arc<ConfigVersion>> {
+        self.state.read().versions.clone()
+    }
+
+    /// Request outcomes for every version, oldest first
+    #[must_use]
+    pub fn metrics(&self) -> Vec<VersionMetrics> {
+        let state = self.state.read();
+        state
+            .versions
+            .iter()
+            .map(|v| v.metrics(v.id == state.active.id))
+            .collect()
+    }
+}
+
+async fn build_version(
+    id: u32,
+    config: BotConfig,
+    provider: Option<Arc<dyn Provider>>,
+    retriever: Option<Arc<Retriever>>,
+) -> Result<ConfigVersion> {
+    let hash = config_hash(&config)?;
+    let pipeline = MessagePipeline::with_components(&config, provider, retriever).await?;
+    Ok(ConfigVersion {
+        id,
+        hash,
+        created_at: Utc::now(),
+        config: Arc::new(config),
+        pipeline: Arc::new(pipeline),
+        counters: VersionCounters::default(),
+    })
+}
+
+/// FNV-1a over the configuration's JSON form, whose object keys are sorted
+fn config_hash(config: &BotConfig) -> Result<String> {
+    let json = serde_json::to_value(config)
+        .map_err(|e| Error::Serialization(e.to_string()))?
+        .to_string();
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in json.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    Ok(format!("{hash:016x}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_register_activate_and_rollback() {
+        let registry = ConfigVersionRegistry::new(BotConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(registry.active().id(), 1);
+
+        let candidate = BotConfig {
+            system_prompt: Some("Answer in one sentence.".into()),
+            ..BotConfig::default()
+        };
+        let id = registry.register(candidate.clone()).await.unwrap();
+        assert_eq!(id, 2);
+        assert_eq!(registry.register(candidate).await.unwrap(), 2);
+        assert_eq!(registry.register(BotConfig::default()).await.unwrap(), 1);
+        assert_eq!(registry.active().id(), 1);
+
+        registry.activate(2).unwrap();
+        assert_eq!(registry.active().id(), 2);
+        registry.rollback().unwrap();
+        assert_eq!(registry.active().id(), 1);
+        assert!(registry.activate(7).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_metrics_are_kept_per_version() {
+        let registry = ConfigVersionRegistry::new(BotConfig::default())
+            .await
+            .unwrap();
+        let candidate = BotConfig {
+            temperature: 0.2,
+            ..BotConfig::default()
+        };
+        registry.register(candidate).await.unwrap();
+
+        registry.active().record(Duration::from_millis(10), false);
+        registry.activate(2).unwrap();
+        registry.active().record(Duration::from_millis(30), true);
+        registry.active().record(Duration::from_millis(10), false);
+
+        let metrics = registry.metrics();
+        assert_eq!(metrics[0].requests, 1);
+        assert!(!metrics[0].active);
+        assert_eq!(metrics[1].errors, 1);
+        assert!((metrics[1].error_rate() - 0.5).abs() < f64::EPSILON);
+        assert_eq!(metrics[1].average_latency, Some(Duration::from_millis(20)));
+    }
+}
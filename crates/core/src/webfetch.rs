@@ -0,0 +1,799 @@
+//! Web page fetching tool
+//!
+//! [`WebFetchPlugin`] gives the model a `fetch_url` tool. Pages are fetched
+//! through a [`PageFetcher`] only when the site's `robots.txt` allows it,
+//! bodies are capped at [`WebFetchConfig::max_bytes`], and HTML is reduced
+//! to readable text with [`HtmlExtractor`]. Long pages can be summarized by
+//! a cheap model before being returned. Every result carries the source URL
+//! and title so answers can cite it.
+//!
+//! URLs on loopback, private, link-local, and other reserved addresses are
+//! refused, as for webhook callbacks (see [`egress`](crate::egress)), unless
+//! [`WebFetchConfig::allow_private_targets`] is set. Redirects are followed
+//! by the plugin rather than the fetcher, so every hop is checked against
+//! that policy and its own origin's `robots.txt`.
+//!
+//! [`HttpPageFetcher`] is available with the `web-fetch` feature.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+    completion::CompletionFn,
+    egress,
+    error::Error,
+    ingest::{decode_entities, HtmlExtractor},
+    plugin::{
+        Capability, CapabilityType, Permission, Plugin, PluginRequest, PluginResponse, RequestType,
+    },
+};
+
+/// A fetched response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchedPage {
+    /// HTTP status code
+    pub status: u16,
+    /// `Content-Type` header
+    pub content_type: Option<String>,
+    /// Body, at most the requested number of bytes
+    pub body: Vec<u8>,
+    /// Whether the body was cut off at the size limit
+    pub truncated: bool,
+    /// `Location` header of a redirect, which fetchers must not follow
+    pub location: Option<String>,
+}
+
+/// Performs HTTP GET requests for the plugin
+#[async_trait]
+pub trait PageFetcher: Send + Sync {
+    /// Fetch a URL, reading at most `max_bytes` of the body
+    ///
+    /// Non-success statuses are returned, not treated as errors. Redirects
+    /// are returned with their [`FetchedPage::location`] rather than
+    /// followed, so the plugin can check each hop.
+    async fn get(&self, url: &str, max_bytes: usize) -> Result<FetchedPage>;
+}
+
+/// Fetch limits and identity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebFetchConfig {
+    /// User agent sent with requests and matched against `robots.txt`
+    pub user_agent: String,
+    /// Largest body read, in bytes
+    pub max_bytes: usize,
+    /// Longest text returned to the model, in characters
+    pub max_chars: usize,
+    /// Fetch pages on loopback, private, and reserved addresses, e.g. for
+    /// local development
+    #[serde(default)]
+    pub allow_private_targets: bool,
+}
+
+impl Default for WebFetchConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: "universal-bot".to_string(),
+            max_bytes: 2 * 1024 * 1024,
+            max_chars: 20_000,
+            allow_private_targets: false,
+        }
+    }
+}
+
+/// Redirects followed for one fetch before giving up
+pub const MAX_REDIRECTS: usize = 5;
+
+/// Split an `http(s)` URL into its origin and path (including the query)
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] for other schemes or a missing host.
+pub fn split_url(url: &str) -> Result<(&str, &str)> {
+    let url = url.split('#').next().unwrap_or(url);
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .ok_or_else(|| {
+            Error::InvalidInput(format!("Only http and https URLs can be fetched: {url}"))
+        })?;
+    let host_end = rest.find(['/', '?']).unwrap_or(rest.len());
+    if host_end == 0 {
+        return Err(Error::InvalidInput(format!("URL has no host: {url}")).into());
+    }
+    let (origin, path) = url.split_at(url.len() - rest.len() + host_end);
+    Ok((origin, if path.is_empty() { "/" } else { path }))
+}
+
+/// Rules from a `robots.txt` file that apply to one user agent
+///
+/// Follows RFC 9309: the group naming the agent is used if there is one,
+/// otherwise the `*` group; the longest matching rule wins, with `Allow`
+/// winning ties; `*` and a trailing `$` are supported in paths.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RobotsRules {
+    rules: Vec<(bool, String)>,
+}
+
+impl RobotsRules {
+    /// Rules allowing everything
+    #[must_use]
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// Rules denying everything
+    #[must_use]
+    pub fn deny_all() -> Self {
+        Self {
+            rules: vec![(false, "/".to_string())],
+        }
+    }
+
+    /// Parse the rules for `user_agent`
+    #[must_use]
+    pub fn parse(robots_txt: &str, user_agent: &str) -> Self {
+        let agent = user_agent
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let mut specific = Vec::new();
+        let mut wildcard = Vec::new();
+        let mut matched_specific = false;
+        let mut group: (bool, bool) = (false, false);
+        let mut in_agents = false;
+
+        for line in robots_txt.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        group = (false, false);
+                        in_agents = true;
+                    }
+                    let name = value.to_ascii_lowercase();
+                    if name == "*" {
+                        group.1 = true;
+                    } else if name == agent {
+                        group.0 = true;
+                        matched_specific = true;
+                    }
+                }
+                directive @ ("allow" | "disallow") => {
+                    in_agents = false;
+                    if value.is_empty() {
+                        continue;
+                    }
+                    let rule = (directive == "allow", value.to_string());
+                    if group.0 {
+                        specific.push(rule.clone());
+                    }
+                    if group.1 {
+                        wildcard.push(rule);
+                    }
+                }
+                _ => in_agents = false,
+            }
+        }
+
+        Self {
+            rules: if matched_specific { specific } else { wildcard },
+        }
+    }
+
+    /// Whether a path (with query) may be fetched
+    #[must_use]
+    pub fn allows(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| Self::matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .is_none_or(|(allow, _)| *allow)
+    }
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        let (pattern, anchored) = pattern
+            .strip_suffix('$')
+            .map_or((pattern, false), |p| (p, true));
+        let mut parts = pattern.split('*');
+        let Some(first) = parts.next() else {
+            return true;
+        };
+        let Some(mut rest) = path.strip_prefix(first) else {
+            return false;
+        };
+        let parts: Vec<&str> = parts.collect();
+        for (i, part) in parts.iter().enumerate() {
+            if anchored && i == parts.len() - 1 {
+                return rest.ends_with(part);
+            }
+            match rest.find(part) {
+                Some(at) => rest = &rest[at + part.len()..],
+                None => return false,
+            }
+        }
+        !anchored || rest.is_empty()
+    }
+}
+
+/// Extract the `<title>` of an HTML page
+#[must_use]
+pub fn html_title(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let title = decode_entities(html[start..end].trim());
+    (!title.is_empty()).then_some(title)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "tool", rename_all = "snake_case")]
+enum ToolCall {
+    FetchUrl {
+        url: String,
+        #[serde(default)]
+        summarize: bool,
+    },
+}
+
+/// Page content returned to the model
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FetchResult {
+    /// Source URL, for citation
+    pub url: String,
+    /// Page title
+    pub title: Option<String>,
+    /// Readable text or its summary
+    pub content: String,
+    /// Whether `content` is a summary
+    pub summarized: bool,
+    /// Whether the page was cut off at the size limits
+    pub truncated: bool,
+}
+
+/// Tool plugin fetching web pages for the model
+///
+/// Invoked with [`RequestType::InvokeTool`] and data of the form
+/// `{"tool": "fetch_url", "url": "https://example.com/", "summarize": true}`.
+/// Requires [`Permission::NetworkAccess`], so registries that do not grant
+/// network access to plugins never reach the fetcher.
+pub struct WebFetchPlugin {
+    fetcher: Arc<dyn PageFetcher>,
+    config: WebFetchConfig,
+    summarizer: Option<CompletionFn>,
+    robots: DashMap<String, RobotsRules>,
+}
+
+impl WebFetchPlugin {
+    /// Create the plugin with default limits
+    #[must_use]
+    pub fn new(fetcher: Arc<dyn PageFetcher>) -> Self {
+        Self {
+            fetcher,
+            config: WebFetchConfig::default(),
+            summarizer: None,
+            robots: DashMap::new(),
+        }
+    }
+
+    /// Override fetch limits and user agent
+    #[must_use]
+    pub fn with_config(mut self, config: WebFetchConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Summarize pages with a completion function when asked to
+    #[must_use]
+    pub fn with_summarizer(mut self, summarizer: CompletionFn) -> Self {
+        self.summarizer = Some(summarizer);
+        self
+    }
+
+    async fn robots_for(&self, origin: &str) -> RobotsRules {
+        if let Some(rules) = self.robots.get(origin) {
+            return rules.clone();
+        }
+        let url = format!("{origin}/robots.txt");
+        // Boxed because following the robots.txt redirects recurses into `follow`
+        let rules = match Box::pin(self.follow(&url, 512 * 1024, false)).await {
+            Ok((_, page)) if (200..300).contains(&page.status) => RobotsRules::parse(
+                &String::from_utf8_lossy(&page.body),
+                &self.config.user_agent,
+            ),
+            // RFC 9309: a missing robots.txt allows everything, an
+            // unreachable one disallows everything
+            Ok((_, page)) if (400..500).contains(&page.status) => RobotsRules::allow_all(),
+            Ok((_, page)) => {
+                debug!("{} returned {}, treating as disallowed", url, page.status);
+                RobotsRules::deny_all()
+            }
+            Err(e) => {
+                debug!("Failed to fetch {}: {}", url, e);
+                return RobotsRules::deny_all();
+            }
+        };
+        self.robots.insert(origin.to_string(), rules.clone());
+        rules
+    }
+
+    /// Parse a URL and apply the private-target policy to it
+    fn check_target(&self, url: &str) -> Result<url::Url> {
+        if self.config.allow_private_targets {
+            egress::parse_http_url(url)
+        } else {
+            egress::check_url(url)
+        }
+    }
+
+    /// GET `url`, following up to [`MAX_REDIRECTS`] redirects
+    ///
+    /// Every hop is checked against the private-target policy and, when
+    /// `check_robots` is set, against its origin's `robots.txt`. Returns
+    /// the final URL with its page.
+    async fn follow(
+        &self,
+        url: &str,
+        max_bytes: usize,
+        check_robots: bool,
+    ) -> Result<(String, FetchedPage)> {
+        let mut target = self.check_target(url)?;
+        for _ in 0..=MAX_REDIRECTS {
+            if check_robots {
+                let (origin, path) = split_url(target.as_str())?;
+                if !self.robots_for(origin).await.allows(path) {
+                    return Err(Error::Authorization(format!(
+                        "robots.txt disallows fetching {target}"
+                    ))
+                    .into());
+                }
+            }
+
+            let page = self.fetcher.get(target.as_str(), max_bytes).await?;
+            match page.location.as_deref() {
+                Some(location) if (300..400).contains(&page.status) => {
+                    let next = target.join(location).map_err(|e| {
+                        Error::Network(format!("{target} redirected to invalid {location}: {e}"))
+                    })?;
+                    target = self.check_target(next.as_str())?;
+                }
+                _ => return Ok((target.into(), page)),
+            }
+        }
+        Err(Error::Network(format!("Too many redirects fetching {url}")).into())
+    }
+
+    /// Fetch a page and reduce it to text
+    ///
+    /// The result carries the URL the page was finally read from.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] for non-HTTP URLs and private or
+    /// reserved targets, [`Error::Authorization`] when `robots.txt`
+    /// disallows the page or a redirect hop, [`Error::Network`] for failed
+    /// requests, and [`Error::Validation`] for content that is not text.
+    pub async fn fetch(&self, url: &str, summarize: bool) -> Result<FetchResult> {
+        let (url, page) = self.follow(url, self.config.max_bytes, true).await?;
+        let url = url.as_str();
+        if !(200..300).contains(&page.status) {
+            return Err(Error::Network(format!("{url} returned HTTP {}", page.status)).into());
+        }
+        let content_type = page.content_type.as_deref().unwrap_or("text/html");
+        let body = String::from_utf8_lossy(&page.body);
+        let (title, text) = if content_type.starts_with("text/html")
+            || content_type.starts_with("application/xhtml")
+        {
+            (html_title(&body), HtmlExtractor::extract_html(&body))
+        } else if content_type.starts_with("text/") || content_type.contains("json") {
+            (None, body.into_owned())
+        } else {
+            return Err(Error::Validation(format!(
+                "Cannot read {content_type} content from {url}"
+            ))
+            .into());
+        };
+
+        let mut truncated = page.truncated;
+        let mut content: String = text.chars().take(self.config.max_chars).collect();
+        truncated |= content.len() < text.len();
+
+        let summarized = match (&self.summarizer, summarize) {
+            (Some(summarizer), true) => {
+                content = summarizer(Self::summary_prompt(url, title.as_deref(), &content)).await?;
+                true
+            }
+            _ => false,
+        };
+
+        Ok(FetchResult {
+            url: url.to_string(),
+            title,
+            content,
+            summarized,
+            truncated,
+        })
+    }
+
+    fn summary_prompt(url: &str, title: Option<&str>, text: &str) -> String {
+        format!(
+            "Summarize the key facts of this web page in a few short paragraphs. \
+             Keep names, numbers, and dates exact.\n\nURL: {url}\nTitle: {}\n\n{text}",
+            title.unwrap_or("(none)")
+        )
+    }
+}
+
+#[async_trait]
+impl Plugin for WebFetchPlugin {
+    fn name(&self) -> &str {
+        "web_fetch"
+    }
+
+    fn version(&self) -> &str {
+        "1.0.0"
+    }
+
+    fn description(&self) -> &'static str {
+        "Fetches web pages as readable text for citation"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability {
+            name: "fetch_url".to_string(),
+            capability_type: CapabilityType::ToolProvider,
+            description: "Fetch a web page as text, optionally summarized".to_string(),
+            required_permissions: vec![Permission::NetworkAccess],
+        }]
+    }
+
+    async fn process(&self, request: PluginRequest) -> Result<PluginResponse> {
+        if !matches!(request.request_type, RequestType::InvokeTool) {
+            return Ok(PluginResponse::error(
+                request.id,
+                "Unsupported request type",
+            ));
+        }
+        let ToolCall::FetchUrl { url, summarize } =
+            match serde_json::from_value::<ToolCall>(request.data) {
+                Ok(call) => call,
+                Err(e) => return Ok(PluginResponse::error(request.id, e)),
+            };
+
+        Ok(match self.fetch(&url, summarize).await {
+            Ok(result) => PluginResponse::success(request.id, serde_json::to_value(result)?),
+            Err(e) => PluginResponse::error(request.id, e),
+        })
+    }
+}
+
+/// [`PageFetcher`] over `reqwest`, stopping reads at the size limit
+///
+/// Only public addresses are connected to, and redirects are returned to
+/// the plugin rather than followed.
+#[cfg(feature = "web-fetch")]
+pub struct HttpPageFetcher {
+    client: reqwest::Client,
+    allow_private_targets: bool,
+}
+
+#[cfg(feature = "web-fetch")]
+impl HttpPageFetcher {
+    /// Create a fetcher identifying itself as `user_agent`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Initialization`] if the HTTP client cannot be built.
+    pub fn new(user_agent: &str) -> Result<Self> {
+        Self::build(egress::public_client_builder(), user_agent, false)
+    }
+
+    /// Create a fetcher that may also reach private and reserved addresses,
+    /// to pair with [`WebFetchConfig::allow_private_targets`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Initialization`] if the HTTP client cannot be built.
+    pub fn with_private_targets(user_agent: &str) -> Result<Self> {
+        Self::build(reqwest::Client::builder(), user_agent, true)
+    }
+
+    fn build(
+        builder: reqwest::ClientBuilder,
+        user_agent: &str,
+        allow_private_targets: bool,
+    ) -> Result<Self> {
+        let client = builder
+            .user_agent(user_agent)
+            .timeout(std::time::Duration::from_secs(20))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| Error::Initialization(format!("Failed to build HTTP client: {e}")))?;
+        Ok(Self {
+            client,
+            allow_private_targets,
+        })
+    }
+}
+
+#[cfg(feature = "web-fetch")]
+#[async_trait]
+impl PageFetcher for HttpPageFetcher {
+    async fn get(&self, url: &str, max_bytes: usize) -> Result<FetchedPage> {
+        // IP-literal hosts skip the resolver, so they are checked here
+        let url = if self.allow_private_targets {
+            egress::parse_http_url(url)?
+        } else {
+            egress::check_url(url)?
+        };
+        let mut response = self
+            .client
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(|e| Error::Network(format!("Failed to fetch {url}: {e}")))?;
+        let status = response.status().as_u16();
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let content_type = header(reqwest::header::CONTENT_TYPE);
+        let location = header(reqwest::header::LOCATION);
+
+        let mut body = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| Error::Network(format!("Failed to read {url}: {e}")))?
+        {
+            let room = max_bytes - body.len();
+            if chunk.len() > room {
+                body.extend_from_slice(&chunk[..room]);
+                truncated = true;
+                break;
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(FetchedPage {
+            status,
+            content_type,
+            body,
+            truncated,
+            location,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Serves canned pages; a 3xx page's body is its `Location`
+    struct FakeWeb {
+        pages: HashMap<&'static str, (u16, &'static str, &'static str)>,
+    }
+
+    #[async_trait]
+    impl PageFetcher for FakeWeb {
+        async fn get(&self, url: &str, max_bytes: usize) -> Result<FetchedPage> {
+            let (status, content_type, body) =
+                self.pages
+                    .get(url)
+                    .copied()
+                    .unwrap_or((404, "text/plain", ""));
+            Ok(FetchedPage {
+                status,
+                content_type: Some(content_type.to_string()),
+                body: body.as_bytes()[..body.len().min(max_bytes)].to_vec(),
+                truncated: body.len() > max_bytes,
+                location: (300..400).contains(&status).then(|| body.to_string()),
+            })
+        }
+    }
+
+    fn plugin() -> WebFetchPlugin {
+        let pages = HashMap::from([
+            (
+                "https://example.com/robots.txt",
+                (
+                    200,
+                    "text/plain",
+                    "User-agent: *\nDisallow: /private\n\nUser-agent: other\nDisallow: /",
+                ),
+            ),
+            (
+                "https://example.com/post",
+                (
+                    200,
+                    "text/html; charset=utf-8",
+                    "<html><head><title>Hello &amp; welcome</title></head>\
+                     <body><nav>Menu</nav><p>First paragraph.</p><p>Second.</p></body></html>",
+                ),
+            ),
+            ("https://example.com/logo.png", (200, "image/png", "PNG")),
+            (
+                "https://example.com/moved",
+                (301, "text/plain", "https://mirror.example/private/post"),
+            ),
+            (
+                "https://example.com/internal",
+                (
+                    302,
+                    "text/plain",
+                    "http://169.254.169.254/latest/meta-data/",
+                ),
+            ),
+            (
+                "https://mirror.example/robots.txt",
+                (200, "text/plain", "User-agent: *\nDisallow: /private"),
+            ),
+            (
+                "https://mirror.example/private/post",
+                (200, "text/plain", "Mirrored"),
+            ),
+            ("https://example.com/old", (308, "text/plain", "/post")),
+            (
+                "http://169.254.169.254/latest/meta-data/",
+                (200, "text/plain", "iam/credentials"),
+            ),
+        ]);
+        WebFetchPlugin::new(Arc::new(FakeWeb { pages }))
+    }
+
+    #[test]
+    fn test_split_url() {
+        assert_eq!(
+            split_url("https://example.com/a?b=1#c").unwrap(),
+            ("https://example.com", "/a?b=1")
+        );
+        assert_eq!(
+            split_url("http://example.com").unwrap(),
+            ("http://example.com", "/")
+        );
+        assert!(split_url("file:///etc/passwd").is_err());
+        assert!(split_url("https:///x").is_err());
+    }
+
+    #[test]
+    fn test_robots_rules() {
+        let robots = "User-agent: universal-bot\nAllow: /docs/public\nDisallow: /docs\n\
+                      Disallow: /*.pdf$\n\nUser-agent: *\nDisallow: /";
+        let rules = RobotsRules::parse(robots, "universal-bot/1.0");
+        assert!(rules.allows("/"));
+        assert!(rules.allows("/docs/public/intro"));
+        assert!(!rules.allows("/docs/internal"));
+        assert!(!rules.allows("/files/report.pdf"));
+        assert!(rules.allows("/files/report.pdf?download=1"));
+
+        let others = RobotsRules::parse(robots, "crawler");
+        assert!(!others.allows("/"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_readable_text() {
+        let result = plugin()
+            .fetch("https://example.com/post", false)
+            .await
+            .unwrap();
+        assert_eq!(result.title.as_deref(), Some("Hello & welcome"));
+        assert_eq!(result.content, "First paragraph.\nSecond.");
+        assert!(!result.truncated && !result.summarized);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_respects_robots_and_content_type() {
+        let plugin = plugin();
+        assert!(plugin
+            .fetch("https://example.com/private/x", false)
+            .await
+            .is_err());
+        assert!(plugin
+            .fetch("https://example.com/logo.png", false)
+            .await
+            .is_err());
+        // No robots.txt means everything is allowed
+        let missing = plugin
+            .fetch("https://other.example/", false)
+            .await
+            .unwrap_err();
+        assert!(missing.to_string().contains("404"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_refuses_private_targets() {
+        let plugin = plugin();
+        let metadata = plugin
+            .fetch("http://169.254.169.254/latest/meta-data/", false)
+            .await
+            .unwrap_err();
+        assert!(metadata.to_string().contains("private or reserved"));
+        assert!(plugin
+            .fetch("http://localhost:8080/admin", false)
+            .await
+            .is_err());
+
+        // A public page cannot redirect the fetch to an internal address
+        let redirected = plugin
+            .fetch("https://example.com/internal", false)
+            .await
+            .unwrap_err();
+        assert!(redirected.to_string().contains("private or reserved"));
+
+        let local = self::plugin().with_config(WebFetchConfig {
+            allow_private_targets: true,
+            ..WebFetchConfig::default()
+        });
+        let result = local
+            .fetch("http://169.254.169.254/latest/meta-data/", false)
+            .await
+            .unwrap();
+        assert_eq!(result.content, "iam/credentials");
+    }
+
+    #[tokio::test]
+    async fn test_redirects_are_checked_against_each_origin() {
+        let plugin = plugin();
+        // Allowed on example.com, but disallowed by the mirror's robots.txt
+        let cross_origin = plugin
+            .fetch("https://example.com/moved", false)
+            .await
+            .unwrap_err();
+        assert!(cross_origin.to_string().contains("robots.txt"));
+
+        let same_origin = plugin
+            .fetch("https://example.com/old", false)
+            .await
+            .unwrap();
+        assert_eq!(same_origin.url, "https://example.com/post");
+        assert_eq!(same_origin.content, "First paragraph.\nSecond.");
+    }
+
+    #[tokio::test]
+    async fn test_tool_summarizes_and_truncates() {
+        let plugin = plugin()
+            .with_config(WebFetchConfig {
+                max_chars: 5,
+                ..WebFetchConfig::default()
+            })
+            .with_summarizer(Arc::new(|prompt: String| {
+                Box::pin(async move { Ok(format!("summary of {} chars", prompt.len())) })
+            }));
+        let response = plugin
+            .process(PluginRequest {
+                id: "call".to_string(),
+                request_type: RequestType::InvokeTool,
+                data: serde_json::json!({
+                    "tool": "fetch_url",
+                    "url": "https://example.com/post",
+                    "summarize": true,
+                }),
+                metadata: HashMap::new(),
+            })
+            .await
+            .unwrap();
+        assert!(response.success);
+        assert_eq!(response.data["url"], "https://example.com/post");
+        assert_eq!(response.data["summarized"], true);
+        assert_eq!(response.data["truncated"], true);
+        assert!(response.data["content"]
+            .as_str()
+            .unwrap()
+            .starts_with("summary of"));
+    }
+}
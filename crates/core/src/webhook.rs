@@ -0,0 +1,821 @@
+//! Per-conversation outbound webhooks
+//!
+//! API clients register a callback URL for a conversation. When something
+//! happens outside the request/response cycle — a scheduled message fires,
+//! a background job completes, a human handoff resolves, a conversation is
+//! about to expire — the bot POSTs a signed JSON [`WebhookEvent`] to that
+//! URL. Failed deliveries are retried with exponential backoff and
+//! dead-lettered once attempts run out.
+//!
+//! # Signatures
+//!
+//! Every delivery carries a `X-UniversalBot-Signature` header of the form
+//! `t=<unix seconds>,v1=<hex HMAC-SHA256>`, computed over `"{t}.{body}"`
+//! with the registration's secret. Receivers should check it with
+//! [`verify_signature`] and reject stale timestamps to prevent replays.
+//!
+//! # Callback targets
+//!
+//! Callbacks on loopback, private, link-local, and other reserved addresses,
+//! including cloud metadata endpoints, are rejected at registration unless
+//! [`WebhookConfig::allow_private_targets`] is set. [`HttpTransport`] checks
+//! again at connect time, so hostnames that resolve to internal addresses
+//! are not delivered to either.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use hmac::{Hmac, Mac};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::{debug, instrument, warn};
+use uuid::Uuid;
+
+use crate::egress;
+use crate::error::Error;
+
+/// Header carrying the payload signature
+pub const SIGNATURE_HEADER: &str = "X-UniversalBot-Signature";
+/// Header carrying the event kind
+pub const EVENT_HEADER: &str = "X-UniversalBot-Event";
+/// Header carrying the event ID, stable across retries
+pub const DELIVERY_HEADER: &str = "X-UniversalBot-Delivery";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Kind of asynchronous event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    /// A scheduled message was sent
+    ScheduledMessage,
+    /// A background job finished
+    JobCompleted,
+    /// A human handoff was resolved
+    HandoffResolved,
+    /// A conversation's context is about to expire
+    ConversationExpiring,
+}
+
+impl WebhookEventKind {
+    /// Wire name, as sent in [`EVENT_HEADER`]
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::ScheduledMessage => "scheduled_message",
+            Self::JobCompleted => "job_completed",
+            Self::HandoffResolved => "handoff_resolved",
+            Self::ConversationExpiring => "conversation_expiring",
+        }
+    }
+}
+
+/// An event delivered to a conversation's callback
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    /// Event ID, used by receivers to deduplicate retries
+    pub id: Uuid,
+    /// Conversation the event belongs to
+    pub conversation_id: String,
+    /// What happened
+    pub kind: WebhookEventKind,
+    /// Event-specific payload
+    pub data: serde_json::Value,
+    /// When the event occurred
+    pub timestamp: DateTime<Utc>,
+}
+
+impl WebhookEvent {
+    /// Create an event with a fresh ID
+    #[must_use]
+    pub fn new(
+        conversation_id: impl Into<String>,
+        kind: WebhookEventKind,
+        data: serde_json::Value,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            conversation_id: conversation_id.into(),
+            kind,
+            data,
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+/// A callback registered for a conversation
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WebhookRegistration {
+    /// Callback URL
+    pub url: String,
+    /// Shared secret used to sign payloads
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// Events to deliver; all events when empty
+    #[serde(default)]
+    pub events: Vec<WebhookEventKind>,
+    /// When the callback was registered
+    pub created_at: DateTime<Utc>,
+}
+
+impl std::fmt::Debug for WebhookRegistration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookRegistration")
+            .field("url", &self.url)
+            .field("secret", &"<redacted>")
+            .field("events", &self.events)
+            .field("created_at", &self.created_at)
+            .finish()
+    }
+}
+
+impl WebhookRegistration {
+    /// Register for all events
+    #[must_use]
+    pub fn new(url: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            secret: secret.into(),
+            events: Vec::new(),
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Restrict delivery to the given events
+    #[must_use]
+    pub fn with_events(mut self, events: impl IntoIterator<Item = WebhookEventKind>) -> Self {
+        self.events = events.into_iter().collect();
+        self
+    }
+
+    fn wants(&self, kind: WebhookEventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+}
+
+/// Retry settings for deliveries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// Delivery attempts before dead-lettering
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled after each failure
+    #[serde(with = "humantime_serde")]
+    pub initial_backoff: Duration,
+    /// Upper bound on the delay between retries
+    #[serde(with = "humantime_serde")]
+    pub max_backoff: Duration,
+    /// Dead letters kept at most; the oldest is dropped to make room
+    #[serde(default = "default_max_dead_letters")]
+    pub max_dead_letters: usize,
+    /// Accept callbacks on loopback, private, and reserved addresses, e.g.
+    /// for local development
+    #[serde(default)]
+    pub allow_private_targets: bool,
+}
+
+const fn default_max_dead_letters() -> usize {
+    1000
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            max_dead_letters: default_max_dead_letters(),
+            allow_private_targets: false,
+        }
+    }
+}
+
+/// Sends a signed payload to a callback URL
+#[async_trait]
+pub trait WebhookTransport: Send + Sync {
+    /// POST `body` to `url` and return the HTTP status code
+    async fn post(&self, url: &str, headers: &[(&str, String)], body: &[u8]) -> Result<u16>;
+}
+
+/// A delivery that exhausted its retries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// The undelivered event
+    pub event: WebhookEvent,
+    /// Callback URL the event was meant for
+    pub url: String,
+    /// Attempts made
+    pub attempts: u32,
+    /// Error from the final attempt
+    pub last_error: String,
+    /// When the event was dead-lettered
+    pub failed_at: DateTime<Utc>,
+}
+
+/// Result of dispatching an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+    /// The callback accepted the event
+    Delivered {
+        /// Attempts taken, including the successful one
+        attempts: u32,
+    },
+    /// The conversation has no callback for this event
+    NoCallback,
+    /// Every attempt failed and the event was dead-lettered
+    DeadLettered,
+}
+
+/// Registry of conversation callbacks and delivery engine
+pub struct WebhookManager {
+    config: WebhookConfig,
+    transport: Arc<dyn WebhookTransport>,
+    registrations: DashMap<String, WebhookRegistration>,
+    dead_letters: RwLock<VecDeque<DeadLetter>>,
+    dropped_dead_letters: AtomicU64,
+}
+
+impl WebhookManager {
+    /// Create a manager that delivers through `transport`
+    #[must_use]
+    pub fn new(transport: Arc<dyn WebhookTransport>, config: WebhookConfig) -> Self {
+        Self {
+            config,
+            transport,
+            registrations: DashMap::new(),
+            dead_letters: RwLock::new(VecDeque::new()),
+            dropped_dead_letters: AtomicU64::new(0),
+        }
+    }
+
+    /// Register or replace the callback for a conversation
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URL is not `http` or `https`, targets a
+    /// private or reserved address that is not allowed, or the secret is
+    /// empty.
+    pub fn register(
+        &self,
+        conversation_id: impl Into<String>,
+        registration: WebhookRegistration,
+    ) -> Result<()> {
+        if self.config.allow_private_targets {
+            egress::parse_http_url(&registration.url)?;
+        } else {
+            egress::check_url(&registration.url)?;
+        }
+        if registration.secret.is_empty() {
+            return Err(Error::InvalidInput("Webhook secret must not be empty".into()).into());
+        }
+
+        self.registrations
+            .insert(conversation_id.into(), registration);
+        Ok(())
+    }
+
+    /// Remove a conversation's callback, returning it if there was one
+    pub fn unregister(&self, conversation_id: &str) -> Option<WebhookRegistration> {
+        self.registrations
+            .remove(conversation_id)
+            .map(|(_, registration)| registration)
+    }
+
+    /// The callback registered for a conversation
+    #[must_use]
+    pub fn registration(&self, conversation_id: &str) -> Option<WebhookRegistration> {
+        self.registrations
+            .get(conversation_id)
+            .map(|entry| entry.clone())
+    }
+
+    /// Deliver an event to its conversation's callback, retrying on failure
+    ///
+    /// Network errors and non-2xx responses are retried with exponential
+    /// backoff; after [`WebhookConfig::max_attempts`] the event is moved to
+    /// the dead-letter queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error only if the event cannot be serialized.
+    #[instrument(skip(self, event), fields(event_id = %event.id, kind = event.kind.as_str()))]
+    pub async fn dispatch(&self, event: WebhookEvent) -> Result<DeliveryOutcome> {
+        let Some(registration) = self
+            .registration(&event.conversation_id)
+            .filter(|registration| registration.wants(event.kind))
+        else {
+            return Ok(DeliveryOutcome::NoCallback);
+        };
+
+        let body = serde_json::to_vec(&event).map_err(|e| Error::Serialization(e.to_string()))?;
+        let mut backoff = self.config.initial_backoff;
+        let mut last_error = String::new();
+        let max_attempts = self.config.max_attempts.max(1);
+
+        for attempt in 1..=max_attempts {
+            let signature = sign(&registration.secret, Utc::now().timestamp(), &body);
+            let headers = [
+                ("Content-Type", "application/json".to_string()),
+                (SIGNATURE_HEADER, signature),
+                (EVENT_HEADER, event.kind.as_str().to_string()),
+                (DELIVERY_HEADER, event.id.to_string()),
+            ];
+
+            match self
+                .transport
+                .post(&registration.url, &headers, &body)
+                .await
+            {
+                Ok(status) if (200..300).contains(&status) => {
+                    debug!("Delivered webhook on attempt {}", attempt);
+                    return Ok(DeliveryOutcome::Delivered { attempts: attempt });
+                }
+                Ok(status) => last_error = format!("Callback returned HTTP {status}"),
+                Err(e) => last_error = e.to_string(),
+            }
+
+            warn!("Webhook attempt {} failed: {}", attempt, last_error);
+            if attempt < max_attempts {
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(self.config.max_backoff);
+            }
+        }
+
+        let mut dead_letters = self.dead_letters.write();
+        while !dead_letters.is_empty() && dead_letters.len() >= self.config.max_dead_letters {
+            dead_letters.pop_front();
+            self.dropped_dead_letters.fetch_add(1, Ordering::Relaxed);
+        }
+        if self.config.max_dead_letters > 0 {
+            dead_letters.push_back(DeadLetter {
+                event,
+                url: registration.url,
+                attempts: max_attempts,
+                last_error,
+                failed_at: Utc::now(),
+            });
+        } else {
+            self.dropped_dead_letters.fetch_add(1, Ordering::Relaxed);
+        }
+        drop(dead_letters);
+        Ok(DeliveryOutcome::DeadLettered)
+    }
+
+    /// Events that could not be delivered, oldest first
+    #[must_use]
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.dead_letters.read().iter().cloned().collect()
+    }
+
+    /// Remove and return all dead-lettered events, e.g. to redeliver them
+    pub fn drain_dead_letters(&self) -> Vec<DeadLetter> {
+        self.dead_letters.write().drain(..).collect()
+    }
+
+    /// Number of events waiting in the dead-letter queue
+    #[must_use]
+    pub fn dead_letter_count(&self) -> usize {
+        self.dead_letters.read().len()
+    }
+
+    /// Dead letters dropped because the queue was full
+    #[must_use]
+    pub fn dropped_dead_letters(&self) -> u64 {
+        self.dropped_dead_letters.load(Ordering::Relaxed)
+    }
+}
+
+/// Compute the signature header value for a payload
+#[must_use]
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .unwrap_or_else(|_| unreachable!("HMAC accepts keys of any length"));
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    let digest = mac.finalize().into_bytes();
+    let hex = digest.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{b:02x}");
+        hex
+    });
+    format!("t={timestamp},v1={hex}")
+}
+
+/// Check a signature header against a payload
+///
+/// Rejects malformed headers, signatures from a different secret, and
+/// timestamps more than `tolerance` away from now.
+#[must_use]
+pub fn verify_signature(secret: &str, header: &str, body: &[u8], tolerance: Duration) -> bool {
+    let mut timestamp = None;
+    let mut signature = None;
+    for part in header.split(',') {
+        match part.split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signature = decode_hex(value),
+            _ => {}
+        }
+    }
+    let (Some(timestamp), Some(signature)) = (timestamp, signature) else {
+        return false;
+    };
+
+    let age = Utc::now().timestamp().abs_diff(timestamp);
+    if age > tolerance.as_secs() {
+        return false;
+    }
+
+    let Ok(mut mac) = HmacSha256::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Check a `sha256=<hex HMAC-SHA256 of the body>` signature header
+///
+/// This is the `X-Hub-Signature-256` scheme used by inbound webhooks from
+/// GitHub and Meta, keyed with the sender's shared secret.
+#[must_use]
+pub fn verify_sha256_header(secret: &str, header: &str, body: &[u8]) -> bool {
+    let Some(signature) = header.strip_prefix("sha256=").and_then(decode_hex) else {
+        return false;
+    };
+    let Ok(mut mac) = HmacSha256::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+pub(crate) fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Transport that POSTs with `reqwest`
+///
+/// Only public addresses are connected to, and redirects are not followed,
+/// so a callback cannot bounce deliveries to internal targets.
+#[cfg(feature = "webhooks")]
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    client: reqwest::Client,
+    allow_private_targets: bool,
+}
+
+#[cfg(feature = "webhooks")]
+impl HttpTransport {
+    /// Create a transport with the given per-request timeout
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HTTP client cannot be built.
+    pub fn new(timeout: Duration) -> Result<Self> {
+        Self::build(egress::public_client_builder(), timeout, false)
+    }
+
+    /// Create a transport that may also deliver to private and reserved
+    /// addresses, to pair with [`WebhookConfig::allow_private_targets`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HTTP client cannot be built.
+    pub fn with_private_targets(timeout: Duration) -> Result<Self> {
+        Self::build(reqwest::Client::builder(), timeout, true)
+    }
+
+    fn build(
+        builder: reqwest::ClientBuilder,
+        timeout: Duration,
+        allow_private_targets: bool,
+    ) -> Result<Self> {
+        let client = builder
+            .timeout(timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| Error::Network(e.to_string()))?;
+        Ok(Self {
+            client,
+            allow_private_targets,
+        })
+    }
+}
+
+#[cfg(feature = "webhooks")]
+#[async_trait]
+impl WebhookTransport for HttpTransport {
+    async fn post(&self, url: &str, headers: &[(&str, String)], body: &[u8]) -> Result<u16> {
+        // IP-literal hosts skip the resolver, so they are checked here
+        let url = if self.allow_private_targets {
+            egress::parse_http_url(url)?
+        } else {
+            egress::check_url(url)?
+        };
+        let mut request = self.client.post(url).body(body.to_vec());
+        for (name, value) in headers {
+            request = request.header(*name, value);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| Error::Network(e.to_string()))?;
+        Ok(response.status().as_u16())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+
+    /// Fails the first `failures` requests, then accepts
+    struct FlakyTransport {
+        failures: u32,
+        calls: AtomicU32,
+        last_headers: RwLock<Vec<(String, String)>>,
+    }
+
+    impl FlakyTransport {
+        fn new(failures: u32) -> Arc<Self> {
+            Arc::new(Self {
+                failures,
+                calls: AtomicU32::new(0),
+                last_headers: RwLock::new(Vec::new()),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl WebhookTransport for FlakyTransport {
+        async fn post(&self, _url: &str, headers: &[(&str, String)], _body: &[u8]) -> Result<u16> {
+            *self.last_headers.write() = headers
+                .iter()
+                .map(|(k, v)| ((*k).to_string(), v.clone()))
+                .collect();
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            if call < self.failures {
+                Ok(503)
+            } else {
+                Ok(204)
+            }
+        }
+    }
+
+    fn manager(transport: Arc<FlakyTransport>, max_attempts: u32) -> WebhookManager {
+        WebhookManager::new(
+            transport,
+            WebhookConfig {
+                max_attempts,
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(2),
+                ..WebhookConfig::default()
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_delivery_retries_then_succeeds() {
+        let transport = FlakyTransport::new(2);
+        let webhooks = manager(transport.clone(), 5);
+        webhooks
+            .register(
+                "conv",
+                WebhookRegistration::new("https://example.com/hook", "s3cret"),
+            )
+            .unwrap();
+
+        let event = WebhookEvent::new(
+            "conv",
+            WebhookEventKind::JobCompleted,
+            serde_json::json!({}),
+        );
+        let outcome = webhooks.dispatch(event.clone()).await.unwrap();
+        assert_eq!(outcome, DeliveryOutcome::Delivered { attempts: 3 });
+
+        let headers = transport.last_headers.read().clone();
+        let delivery = headers.iter().find(|(k, _)| k == DELIVERY_HEADER).unwrap();
+        assert_eq!(delivery.1, event.id.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_retries_dead_letter() {
+        let webhooks = manager(FlakyTransport::new(u32::MAX), 3);
+        webhooks
+            .register(
+                "conv",
+                WebhookRegistration::new("https://example.com/hook", "s3cret"),
+            )
+            .unwrap();
+
+        let event = WebhookEvent::new(
+            "conv",
+            WebhookEventKind::HandoffResolved,
+            serde_json::json!({}),
+        );
+        assert_eq!(
+            webhooks.dispatch(event).await.unwrap(),
+            DeliveryOutcome::DeadLettered
+        );
+
+        let dead = webhooks.drain_dead_letters();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].attempts, 3);
+        assert!(dead[0].last_error.contains("503"));
+        assert!(webhooks.dead_letters().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_event_filter_and_missing_callback() {
+        let transport = FlakyTransport::new(0);
+        let webhooks = manager(transport.clone(), 1);
+        webhooks
+            .register(
+                "conv",
+                WebhookRegistration::new("https://example.com/hook", "s3cret")
+                    .with_events([WebhookEventKind::JobCompleted]),
+            )
+            .unwrap();
+
+        let scheduled = WebhookEvent::new(
+            "conv",
+            WebhookEventKind::ScheduledMessage,
+            serde_json::json!({}),
+        );
+        assert_eq!(
+            webhooks.dispatch(scheduled).await.unwrap(),
+            DeliveryOutcome::NoCallback
+        );
+        let other = WebhookEvent::new(
+            "other",
+            WebhookEventKind::JobCompleted,
+            serde_json::json!({}),
+        );
+        assert_eq!(
+            webhooks.dispatch(other).await.unwrap(),
+            DeliveryOutcome::NoCallback
+        );
+        assert_eq!(transport.calls.load(Ordering::SeqCst), 0);
+
+        assert!(webhooks.unregister("conv").is_some());
+        assert!(webhooks.registration("conv").is_none());
+    }
+
+    #[test]
+    fn test_register_rejects_bad_callbacks() {
+        let webhooks = manager(FlakyTransport::new(0), 1);
+        assert!(webhooks
+            .register(
+                "conv",
+                WebhookRegistration::new("ftp://example.com", "s3cret")
+            )
+            .is_err());
+        assert!(webhooks
+            .register("conv", WebhookRegistration::new("https://example.com", ""))
+            .is_err());
+    }
+
+    #[test]
+    fn test_register_rejects_private_targets() {
+        let webhooks = manager(FlakyTransport::new(0), 1);
+        for url in [
+            "http://localhost:8080/hook",
+            "http://127.0.0.1/hook",
+            "https://10.1.2.3/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://metadata.google.internal/computeMetadata",
+            "http://user@192.168.0.1:80/hook",
+            "http://[::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+            "http://0.0.0.0/hook",
+            "http://127.0.0.1\\@evil.com/",
+            "http://2130706433/",
+            "http://0x7f.1/",
+            "http://127.1/",
+        ] {
+            let registration = WebhookRegistration::new(url, "s3cret");
+            assert!(webhooks.register("conv", registration).is_err(), "{url}");
+        }
+        for url in [
+            "https://hooks.example.com/cb",
+            "https://93.184.216.34:8443/cb",
+            "https://[2606:2800:220:1::]/cb",
+        ] {
+            let registration = WebhookRegistration::new(url, "s3cret");
+            assert!(webhooks.register("conv", registration).is_ok(), "{url}");
+        }
+
+        let local = WebhookManager::new(
+            FlakyTransport::new(0),
+            WebhookConfig {
+                allow_private_targets: true,
+                ..WebhookConfig::default()
+            },
+        );
+        local
+            .register(
+                "conv",
+                WebhookRegistration::new("http://localhost:8080/hook", "s3cret"),
+            )
+            .unwrap();
+    }
+
+    #[cfg(feature = "webhooks")]
+    #[tokio::test]
+    async fn test_http_transport_refuses_private_addresses() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                let _ = socket
+                    .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                    .await;
+            }
+        });
+
+        let transport = HttpTransport::new(Duration::from_secs(5)).unwrap();
+        for url in [
+            format!("http://localhost:{port}/hook"),
+            format!("http://127.0.0.1:{port}/hook"),
+            format!("http://127.1:{port}/hook"),
+        ] {
+            assert!(transport.post(&url, &[], b"{}").await.is_err(), "{url}");
+        }
+
+        let local = HttpTransport::with_private_targets(Duration::from_secs(5)).unwrap();
+        let status = local
+            .post(&format!("http://localhost:{port}/hook"), &[], b"{}")
+            .await
+            .unwrap();
+        assert_eq!(status, 204);
+    }
+
+    #[tokio::test]
+    async fn test_dead_letters_are_capped() {
+        let webhooks = WebhookManager::new(
+            FlakyTransport::new(u32::MAX),
+            WebhookConfig {
+                max_attempts: 1,
+                max_dead_letters: 2,
+                ..WebhookConfig::default()
+            },
+        );
+        webhooks
+            .register(
+                "conv",
+                WebhookRegistration::new("https://example.com/hook", "s3cret"),
+            )
+            .unwrap();
+
+        let mut ids = Vec::new();
+        for _ in 0..5 {
+            let event = WebhookEvent::new(
+                "conv",
+                WebhookEventKind::JobCompleted,
+                serde_json::json!({}),
+            );
+            ids.push(event.id);
+            webhooks.dispatch(event).await.unwrap();
+        }
+
+        let kept: Vec<_> = webhooks.dead_letters().iter().map(|d| d.event.id).collect();
+        assert_eq!(kept, ids[3..]);
+        assert_eq!(webhooks.dead_letter_count(), 2);
+        assert_eq!(webhooks.dropped_dead_letters(), 3);
+    }
+
+    #[test]
+    fn test_signature_round_trip() {
+        let body = br#"{"hello":"world"}"#;
+        let header = sign("s3cret", Utc::now().timestamp(), body);
+        let tolerance = Duration::from_secs(300);
+
+        assert!(verify_signature("s3cret", &header, body, tolerance));
+        assert!(!verify_signature("other", &header, body, tolerance));
+        assert!(!verify_signature("s3cret", &header, b"tampered", tolerance));
+        assert!(!verify_signature("s3cret", "t=1,v1=zz", body, tolerance));
+
+        let stale = sign("s3cret", Utc::now().timestamp() - 3600, body);
+        assert!(!verify_signature("s3cret", &stale, body, tolerance));
+    }
+}
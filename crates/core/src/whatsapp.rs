@@ -0,0 +1,947 @@
+//! WhatsApp Business channel adapter
+//!
+//! Receives WhatsApp Cloud API webhooks and answers through the Graph API.
+//! Inbound media is downloaded into message [`Attachment`]s and outbound
+//! attachments are uploaded before sending.
+//!
+//! WhatsApp only allows free-form messages within 24 hours of the user's
+//! last message. [`SessionWindows`] tracks that window per user, and
+//! [`WhatsAppAdapter::send_proactive`] falls back to an approved
+//! [`TemplateMessage`] once it has closed. Sends are limited per business
+//! phone number by a [`NumberRateLimiter`], and can be paced per recipient
+//! with a [`RateShape`].
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+    bot::Bot,
+    error::Error,
+    message::{Attachment, Message, MessageType, Response, CHANNEL_METADATA_KEY},
+    shaping::{RateShape, RateShaper},
+    webhook::verify_sha256_header,
+};
+
+/// Message metadata key holding the sender's WhatsApp profile name
+pub const PROFILE_NAME_METADATA_KEY: &str = "whatsapp_profile_name";
+
+/// Hours after the user's last message during which free-form replies are allowed
+pub const CUSTOMER_SERVICE_WINDOW_HOURS: i64 = 24;
+
+/// Default sends per second per business number, the Cloud API's base throughput
+pub const DEFAULT_MESSAGES_PER_SECOND: usize = 80;
+
+/// Answer a webhook subscription request
+///
+/// Returns the challenge to echo back when `mode` is `subscribe` and the
+/// token matches the one configured for the app, or `None` to reject it.
+#[must_use]
+pub fn verify_subscription<'a>(
+    verify_token: &str,
+    mode: &str,
+    token: &str,
+    challenge: &'a str,
+) -> Option<&'a str> {
+    (mode == "subscribe" && token == verify_token).then_some(challenge)
+}
+
+/// Check the `X-Hub-Signature-256` header of a webhook delivery
+///
+/// The header is `sha256=<hex HMAC-SHA256 of the body>` keyed with the app
+/// secret.
+#[must_use]
+pub fn verify_payload(app_secret: &str, header: &str, body: &[u8]) -> bool {
+    verify_sha256_header(app_secret, header, body)
+}
+
+/// A webhook delivery
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    /// Always `whatsapp_business_account`
+    #[serde(default)]
+    pub object: String,
+    /// Business accounts with changes
+    #[serde(default)]
+    pub entry: Vec<WebhookEntry>,
+}
+
+impl WebhookPayload {
+    /// Inbound messages with the business number that received them
+    pub fn messages(&self) -> impl Iterator<Item = (&ChangeValue, &InboundMessage)> {
+        self.entry
+            .iter()
+            .flat_map(|entry| &entry.changes)
+            .flat_map(|change| {
+                change
+                    .value
+                    .messages
+                    .iter()
+                    .map(move |m| (&change.value, m))
+            })
+    }
+}
+
+/// Changes for one business account
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebhookEntry {
+    /// Business account ID
+    #[serde(default)]
+    pub id: String,
+    /// Changed fields
+    #[serde(default)]
+    pub changes: Vec<WebhookChange>,
+}
+
+/// One changed field
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebhookChange {
+    /// Field name, `messages` for message events
+    #[serde(default)]
+    pub field: String,
+    /// Change contents
+    #[serde(default)]
+    pub value: ChangeValue,
+}
+
+/// Contents of a `messages` change
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChangeValue {
+    /// Business number that received the messages
+    #[serde(default)]
+    pub metadata: NumberMetadata,
+    /// Senders' profiles
+    #[serde(default)]
+    pub contacts: Vec<Contact>,
+    /// Inbound messages; delivery statuses are not modelled
+    #[serde(default)]
+    pub messages: Vec<InboundMessage>,
+}
+
+impl ChangeValue {
+    fn profile_name(&self, wa_id: &str) -> Option<&str> {
+        self.contacts
+            .iter()
+            .find(|contact| contact.wa_id == wa_id)
+            .map(|contact| contact.profile.name.as_str())
+    }
+}
+
+/// Business phone number identifiers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NumberMetadata {
+    /// Number as displayed to users
+    #[serde(default)]
+    pub display_phone_number: String,
+    /// Graph API ID used to send from this number
+    #[serde(default)]
+    pub phone_number_id: String,
+}
+
+/// A sender
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Contact {
+    /// WhatsApp ID, the sender's phone number
+    #[serde(default)]
+    pub wa_id: String,
+    /// Profile
+    #[serde(default)]
+    pub profile: Profile,
+}
+
+/// A sender's profile
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// Display name
+    #[serde(default)]
+    pub name: String,
+}
+
+/// An inbound message
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InboundMessage {
+    /// Message ID (`wamid.…`)
+    pub id: String,
+    /// Sender's WhatsApp ID
+    pub from: String,
+    /// Unix timestamp in seconds, as a string
+    #[serde(default)]
+    pub timestamp: String,
+    /// `text`, `image`, `document`, `audio`, `video`, ...
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Body of text messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<TextBody>,
+    /// Image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<InboundMedia>,
+    /// Document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document: Option<InboundMedia>,
+    /// Audio or voice note
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<InboundMedia>,
+    /// Video
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video: Option<InboundMedia>,
+}
+
+impl InboundMessage {
+    /// When the message was sent, falling back to now if the timestamp is invalid
+    #[must_use]
+    pub fn sent_at(&self) -> DateTime<Utc> {
+        self.timestamp
+            .parse::<i64>()
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs, 0))
+            .unwrap_or_else(Utc::now)
+    }
+
+    /// The attached media, if any
+    #[must_use]
+    pub fn media(&self) -> Option<&InboundMedia> {
+        self.image
+            .as_ref()
+            .or(self.document.as_ref())
+            .or(self.audio.as_ref())
+            .or(self.video.as_ref())
+    }
+}
+
+/// Text of a message
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TextBody {
+    /// Text
+    pub body: String,
+}
+
+/// Media reference in an inbound message
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InboundMedia {
+    /// Media ID, resolved through [`WhatsAppApi::download_media`]
+    pub id: String,
+    /// MIME type
+    #[serde(default)]
+    pub mime_type: String,
+    /// Caption typed with the media
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+    /// Filename, for documents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+}
+
+/// An approved message template, used outside the customer service window
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateMessage {
+    /// Template name
+    pub name: String,
+    /// Language code, e.g. `en_US`
+    pub language: String,
+    /// Values for the body placeholders, in order
+    pub parameters: Vec<String>,
+}
+
+impl TemplateMessage {
+    /// Create a template message without parameters
+    #[must_use]
+    pub fn new(name: impl Into<String>, language: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            language: language.into(),
+            parameters: Vec::new(),
+        }
+    }
+
+    /// Add a body parameter
+    #[must_use]
+    pub fn with_parameter(mut self, value: impl Into<String>) -> Self {
+        self.parameters.push(value.into());
+        self
+    }
+}
+
+/// What an outbound message carries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutboundContent {
+    /// Free-form text, only allowed inside the customer service window
+    Text(String),
+    /// An approved template
+    Template(TemplateMessage),
+    /// Previously uploaded media
+    Media {
+        /// `image`, `document`, `audio`, or `video`
+        kind: &'static str,
+        /// Media ID from [`WhatsAppApi::upload_media`]
+        media_id: String,
+        /// Caption
+        caption: Option<String>,
+        /// Filename, shown for documents
+        filename: Option<String>,
+    },
+}
+
+/// A message to one user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboundMessage {
+    /// Recipient's WhatsApp ID
+    pub to: String,
+    /// Content
+    pub content: OutboundContent,
+}
+
+impl OutboundMessage {
+    /// Graph API request body
+    #[must_use]
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut body = serde_json::json!({
+            "messaging_product": "whatsapp",
+            "recipient_type": "individual",
+            "to": self.to,
+        });
+        match &self.content {
+            OutboundContent::Text(text) => {
+                body["type"] = "text".into();
+                body["text"] = serde_json::json!({ "body": text });
+            }
+            OutboundContent::Template(template) => {
+                let parameters: Vec<_> = template
+                    .parameters
+                    .iter()
+                    .map(|value| serde_json::json!({ "type": "text", "text": value }))
+                    .collect();
+                body["type"] = "template".into();
+                body["template"] = serde_json::json!({
+                    "name": template.name,
+                    "language": { "code": template.language },
+                    "components": [{ "type": "body", "parameters": parameters }],
+                });
+            }
+            OutboundContent::Media {
+                kind,
+                media_id,
+                caption,
+                filename,
+            } => {
+                let mut media = serde_json::json!({ "id": media_id });
+                if let Some(caption) = caption {
+                    media["caption"] = caption.as_str().into();
+                }
+                if let Some(filename) = filename.as_ref().filter(|_| *kind == "document") {
+                    media["filename"] = filename.as_str().into();
+                }
+                body["type"] = (*kind).into();
+                body[*kind] = media;
+            }
+        }
+        body
+    }
+}
+
+/// WhatsApp media type for a MIME type
+#[must_use]
+pub fn media_kind(mime_type: &str) -> &'static str {
+    match mime_type.split('/').next() {
+        Some("image") => "image",
+        Some("audio") => "audio",
+        Some("video") => "video",
+        _ => "document",
+    }
+}
+
+/// Graph API operations used by the adapter
+#[async_trait]
+pub trait WhatsAppApi: Send + Sync {
+    /// Send a message from a business number, returning the message ID
+    async fn send(&self, phone_number_id: &str, message: &OutboundMessage) -> Result<String>;
+
+    /// Download media, returning its bytes and MIME type
+    async fn download_media(&self, media_id: &str) -> Result<(Bytes, String)>;
+
+    /// Upload an attachment's inline data, returning the media ID
+    async fn upload_media(&self, phone_number_id: &str, attachment: &Attachment) -> Result<String>;
+}
+
+/// When each user last wrote, for the customer service window
+#[derive(Debug, Default)]
+pub struct SessionWindows {
+    last_inbound: DashMap<String, DateTime<Utc>>,
+}
+
+impl SessionWindows {
+    /// Create an empty tracker
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a message from a user
+    pub fn record(&self, wa_id: &str, at: DateTime<Utc>) {
+        let mut last = self.last_inbound.entry(wa_id.to_string()).or_insert(at);
+        if *last < at {
+            *last = at;
+        }
+    }
+
+    /// Whether free-form messages to the user are allowed at `now`
+    #[must_use]
+    pub fn is_open(&self, wa_id: &str, now: DateTime<Utc>) -> bool {
+        self.last_inbound.get(wa_id).is_some_and(|last| {
+            now - *last < chrono::Duration::hours(CUSTOMER_SERVICE_WINDOW_HOURS)
+        })
+    }
+}
+
+/// Sliding-window send limit per business phone number
+#[derive(Debug)]
+pub struct NumberRateLimiter {
+    max_messages: usize,
+    window: Duration,
+    sent: DashMap<String, VecDeque<Instant>>,
+}
+
+impl NumberRateLimiter {
+    /// Allow `max_messages` per `window` for each number
+    #[must_use]
+    pub fn new(max_messages: usize, window: Duration) -> Self {
+        Self {
+            max_messages,
+            window,
+            sent: DashMap::new(),
+        }
+    }
+
+    /// Take a send slot for a number
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::RateLimit`] if the number has used its allowance for
+    /// the current window.
+    pub fn acquire(&self, phone_number_id: &str) -> Result<()> {
+        let now = Instant::now();
+        let mut sent = self.sent.entry(phone_number_id.to_string()).or_default();
+        while sent
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= self.window)
+        {
+            sent.pop_front();
+        }
+        if sent.len() >= self.max_messages {
+            return Err(Error::RateLimit.into());
+        }
+        sent.push_back(now);
+        drop(sent);
+        Ok(())
+    }
+}
+
+impl Default for NumberRateLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_MESSAGES_PER_SECOND, Duration::from_secs(1))
+    }
+}
+
+/// Routes WhatsApp messages through a [`Bot`]
+pub struct WhatsAppAdapter {
+    bot: Bot,
+    api: Arc<dyn WhatsAppApi>,
+    windows: SessionWindows,
+    limiter: NumberRateLimiter,
+    shaper: Option<RateShaper>,
+}
+
+impl WhatsAppAdapter {
+    /// Create an adapter with the default rate limit
+    #[must_use]
+    pub fn new(bot: Bot, api: Arc<dyn WhatsAppApi>) -> Self {
+        Self {
+            bot,
+            api,
+            windows: SessionWindows::new(),
+            limiter: NumberRateLimiter::default(),
+            shaper: None,
+        }
+    }
+
+    /// Replace the per-number rate limit
+    #[must_use]
+    pub fn with_rate_limit(mut self, max_messages: usize, window: Duration) -> Self {
+        self.limiter = NumberRateLimiter::new(max_messages, window);
+        self
+    }
+
+    /// Pace sends to `shape`, queueing per recipient
+    ///
+    /// Sent messages cannot be edited, so the coalesce window is unused.
+    #[must_use]
+    pub fn with_rate_shape(mut self, shape: RateShape) -> Self {
+        self.shaper = Some(RateShaper::new(shape));
+        self
+    }
+
+    /// Customer service windows
+    #[must_use]
+    pub fn windows(&self) -> &SessionWindows {
+        &self.windows
+    }
+
+    /// Bot conversation ID for a user talking to a business number
+    #[must_use]
+    pub fn conversation_id(phone_number_id: &str, wa_id: &str) -> String {
+        format!("whatsapp:{phone_number_id}:{wa_id}")
+    }
+
+    /// Convert an inbound message into a bot message, downloading its media
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if attached media cannot be downloaded.
+    pub async fn to_message(
+        &self,
+        value: &ChangeValue,
+        inbound: &InboundMessage,
+    ) -> Result<Message> {
+        let media = inbound.media();
+        let text = inbound
+            .text
+            .as_ref()
+            .map(|text| text.body.clone())
+            .or_else(|| media.and_then(|media| media.caption.clone()))
+            .unwrap_or_default();
+        let message_type = if text.starts_with('/') {
+            MessageType::Command
+        } else {
+            MessageType::Text
+        };
+
+        let mut message = Message::with_type(text, message_type)
+            .with_conversation_id(Self::conversation_id(
+                &value.metadata.phone_number_id,
+                &inbound.from,
+            ))
+            .with_user_id(inbound.from.clone())
+            .with_metadata(CHANNEL_METADATA_KEY, serde_json::json!("whatsapp"));
+        if let Some(name) = value.profile_name(&inbound.from) {
+            message = message.with_metadata(PROFILE_NAME_METADATA_KEY, serde_json::json!(name));
+        }
+
+        if let Some(media) = media {
+            let (data, mime_type) = self.api.download_media(&media.id).await?;
+            let filename = media
+                .filename
+                .clone()
+                .unwrap_or_else(|| format!("{}.{}", media.id, inbound.kind));
+            message = message.with_attachment(
+                Attachment::new(
+                    filename,
+                    mime_type,
+                    data.len(),
+                    format!("whatsapp://media/{}", media.id),
+                )
+                .with_data(data),
+            );
+        }
+        Ok(message)
+    }
+
+    /// Process a webhook delivery, replying to each message
+    ///
+    /// Messages that fail are logged and skipped, since the webhook must be
+    /// acknowledged regardless. Returns the IDs of the replies sent.
+    pub async fn handle(&self, payload: &WebhookPayload) -> Vec<String> {
+        let mut sent = Vec::new();
+        for (value, inbound) in payload.messages() {
+            self.windows.record(&inbound.from, inbound.sent_at());
+            match self.reply(value, inbound).await {
+                Ok(id) => sent.push(id),
+                Err(e) => warn!("Failed to answer WhatsApp message {}: {:#}", inbound.id, e),
+            }
+        }
+        sent
+    }
+
+    async fn reply(&self, value: &ChangeValue, inbound: &InboundMessage) -> Result<String> {
+        let message = self.to_message(value, inbound).await?;
+        let response = self.bot.process(message).await?;
+        self.send(
+            &value.metadata.phone_number_id,
+            OutboundMessage {
+                to: inbound.from.clone(),
+                content: OutboundContent::Text(response.content),
+            },
+        )
+        .await
+    }
+
+    /// Send a response that is not a reply, such as a scheduled message
+    ///
+    /// Inside the customer service window the response is sent as text.
+    /// Outside it, `template` is sent instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Validation`] if the window has closed and no
+    /// template is given, [`Error::RateLimit`] if the number is over its
+    /// limit, or an error if sending fails.
+    pub async fn send_proactive(
+        &self,
+        phone_number_id: &str,
+        wa_id: &str,
+        response: &Response,
+        template: Option<&TemplateMessage>,
+    ) -> Result<String> {
+        let content = if self.windows.is_open(wa_id, Utc::now()) {
+            OutboundContent::Text(response.content.clone())
+        } else if let Some(template) = template {
+            debug!(
+                "Window closed for {}, sending template {}",
+                wa_id, template.name
+            );
+            OutboundContent::Template(template.clone())
+        } else {
+            return Err(Error::Validation(format!(
+                "Outside the {CUSTOMER_SERVICE_WINDOW_HOURS}h window for {wa_id}; a template is required"
+            ))
+            .into());
+        };
+        self.send(
+            phone_number_id,
+            OutboundMessage {
+                to: wa_id.to_string(),
+                content,
+            },
+        )
+        .await
+    }
+
+    /// Upload an attachment's inline data and send it
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the attachment has no inline
+    /// data, [`Error::RateLimit`] if the number is over its limit, or an
+    /// error if uploading or sending fails.
+    pub async fn send_attachment(
+        &self,
+        phone_number_id: &str,
+        wa_id: &str,
+        attachment: &Attachment,
+        caption: Option<String>,
+    ) -> Result<String> {
+        if attachment.data.is_none() {
+            return Err(Error::InvalidInput(format!(
+                "Attachment {} has no inline data to upload",
+                attachment.filename
+            ))
+            .into());
+        }
+        let media_id = self.api.upload_media(phone_number_id, attachment).await?;
+        self.send(
+            phone_number_id,
+            OutboundMessage {
+                to: wa_id.to_string(),
+                content: OutboundContent::Media {
+                    kind: media_kind(&attachment.mime_type),
+                    media_id,
+                    caption,
+                    filename: Some(attachment.filename.clone()),
+                },
+            },
+        )
+        .await
+    }
+
+    async fn send(&self, phone_number_id: &str, message: OutboundMessage) -> Result<String> {
+        if let Some(shaper) = &self.shaper {
+            shaper.acquire(&message.to).await;
+        }
+        self.limiter.acquire(phone_number_id)?;
+        self.api.send(phone_number_id, &message).await
+    }
+}
+
+/// Graph API client authenticated with a system user access token
+#[cfg(feature = "whatsapp")]
+#[derive(Debug, Clone)]
+pub struct GraphApi {
+    client: reqwest::Client,
+    access_token: String,
+    base_url: String,
+}
+
+#[cfg(feature = "whatsapp")]
+impl GraphApi {
+    /// Default Graph API endpoint, including the API version
+    pub const DEFAULT_BASE_URL: &'static str = "https://graph.facebook.com/v19.0";
+
+    /// Create a client for the default endpoint
+    #[must_use]
+    pub fn new(access_token: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            access_token: access_token.into(),
+            base_url: Self::DEFAULT_BASE_URL.to_string(),
+        }
+    }
+
+    /// Use a different endpoint or API version
+    #[must_use]
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    async fn json(&self, request: reqwest::RequestBuilder) -> Result<serde_json::Value> {
+        Ok(request
+            .bearer_auth(&self.access_token)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| Error::Network(format!("Graph API request failed: {e}")))?
+            .json()
+            .await
+            .map_err(|e| Error::Network(format!("Invalid Graph API response: {e}")))?)
+    }
+}
+
+#[cfg(feature = "whatsapp")]
+#[async_trait]
+impl WhatsAppApi for GraphApi {
+    async fn send(&self, phone_number_id: &str, message: &OutboundMessage) -> Result<String> {
+        let url = format!("{}/{phone_number_id}/messages", self.base_url);
+        let body = self
+            .json(self.client.post(url).json(&message.to_json()))
+            .await?;
+        body["messages"][0]["id"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| Error::Provider("Graph API returned no message ID".to_string()).into())
+    }
+
+    async fn download_media(&self, media_id: &str) -> Result<(Bytes, String)> {
+        let info = self
+            .json(self.client.get(format!("{}/{media_id}", self.base_url)))
+            .await?;
+        let (Some(url), Some(mime_type)) = (info["url"].as_str(), info["mime_type"].as_str())
+        else {
+            return Err(Error::Provider(format!("No download URL for media {media_id}")).into());
+        };
+        let data = self
+            .client
+            .get(url)
+            .bearer_auth(&self.access_token)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| Error::Network(format!("Media download failed: {e}")))?
+            .bytes()
+            .await
+            .map_err(|e| Error::Network(format!("Media download failed: {e}")))?;
+        Ok((data, mime_type.to_string()))
+    }
+
+    async fn upload_media(&self, phone_number_id: &str, attachment: &Attachment) -> Result<String> {
+        let data = attachment.data.clone().unwrap_or_default();
+        let part = reqwest::multipart::Part::bytes(data.to_vec())
+            .file_name(attachment.filename.clone())
+            .mime_str(&attachment.mime_type)
+            .map_err(|e| Error::InvalidInput(format!("Invalid MIME type: {e}")))?;
+        let form = reqwest::multipart::Form::new()
+            .text("messaging_product", "whatsapp")
+            .text("type", attachment.mime_type.clone())
+            .part("file", part);
+        let url = format!("{}/{phone_number_id}/media", self.base_url);
+        let body = self.json(self.client.post(url).multipart(form)).await?;
+        body["id"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| Error::Provider("Graph API returned no media ID".to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write as _;
+
+    use super::*;
+    use crate::config::BotConfig;
+    use hmac::{Hmac, Mac};
+    use parking_lot::Mutex;
+    use sha2::Sha256;
+
+    #[derive(Default)]
+    struct RecordingApi {
+        sent: Mutex<Vec<(String, OutboundMessage)>>,
+    }
+
+    #[async_trait]
+    impl WhatsAppApi for RecordingApi {
+        async fn send(&self, phone_number_id: &str, message: &OutboundMessage) -> Result<String> {
+            let mut sent = self.sent.lock();
+            sent.push((phone_number_id.to_string(), message.clone()));
+            let id = format!("wamid.{}", sent.len());
+            drop(sent);
+            Ok(id)
+        }
+
+        async fn download_media(&self, _media_id: &str) -> Result<(Bytes, String)> {
+            Ok((
+                Bytes::from_static(b"%PDF-1.4"),
+                "application/pdf".to_string(),
+            ))
+        }
+
+        async fn upload_media(
+            &self,
+            _phone_number_id: &str,
+            _attachment: &Attachment,
+        ) -> Result<String> {
+            Ok("media-1".to_string())
+        }
+    }
+
+    fn payload(message: &serde_json::Value) -> WebhookPayload {
+        serde_json::from_value(serde_json::json!({
+            "object": "whatsapp_business_account",
+            "entry": [{
+                "id": "waba-1",
+                "changes": [{
+                    "field": "messages",
+                    "value": {
+                        "metadata": { "display_phone_number": "15550001111", "phone_number_id": "pn-1" },
+                        "contacts": [{ "wa_id": "447700900123", "profile": { "name": "Ana" } }],
+                        "messages": [message],
+                    },
+                }],
+            }],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_webhook_verification() {
+        assert_eq!(
+            verify_subscription("tok", "subscribe", "tok", "1158201444"),
+            Some("1158201444")
+        );
+        assert_eq!(verify_subscription("tok", "subscribe", "bad", "1"), None);
+
+        let body = br#"{"object":"whatsapp_business_account"}"#;
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"app-secret").unwrap();
+        mac.update(body);
+        let hex = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .fold(String::new(), |mut hex, b| {
+                let _ = write!(hex, "{b:02x}");
+                hex
+            });
+        assert!(verify_payload("app-secret", &format!("sha256={hex}"), body));
+        assert!(!verify_payload("other", &format!("sha256={hex}"), body));
+        assert!(!verify_payload("app-secret", &hex, body));
+    }
+
+    #[test]
+    fn test_template_json() {
+        let message = OutboundMessage {
+            to: "447700900123".to_string(),
+            content: OutboundContent::Template(
+                TemplateMessage::new("order_update", "en_US").with_parameter("#1042"),
+            ),
+        };
+        let json = message.to_json();
+        assert_eq!(json["type"], "template");
+        assert_eq!(json["template"]["language"]["code"], "en_US");
+        assert_eq!(
+            json["template"]["components"][0]["parameters"][0]["text"],
+            "#1042"
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_is_per_number() {
+        let limiter = NumberRateLimiter::new(2, Duration::from_secs(60));
+        assert!(limiter.acquire("pn-1").is_ok());
+        assert!(limiter.acquire("pn-1").is_ok());
+        let err = limiter.acquire("pn-1").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::RateLimit)
+        ));
+        assert!(limiter.acquire("pn-2").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_inbound_media_becomes_attachment() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        let adapter = WhatsAppAdapter::new(bot, Arc::new(RecordingApi::default()));
+        let payload = payload(&serde_json::json!({
+            "id": "wamid.in1",
+            "from": "447700900123",
+            "timestamp": "1700000000",
+            "type": "document",
+            "document": { "id": "m-9", "mime_type": "application/pdf", "filename": "invoice.pdf", "caption": "Is this right?" },
+        }));
+        let (value, inbound) = payload.messages().next().unwrap();
+
+        let message = adapter.to_message(value, inbound).await.unwrap();
+        assert_eq!(message.content, "Is this right?");
+        assert_eq!(message.conversation_id, "whatsapp:pn-1:447700900123");
+        assert_eq!(message.metadata[PROFILE_NAME_METADATA_KEY], "Ana");
+        assert_eq!(message.attachments[0].filename, "invoice.pdf");
+        assert_eq!(message.attachments[0].size, 8);
+    }
+
+    #[tokio::test]
+    async fn test_reply_and_window_fallback() {
+        let bot = Bot::new(BotConfig::default()).await.unwrap();
+        let api = Arc::new(RecordingApi::default());
+        let adapter = WhatsAppAdapter::new(bot, api.clone());
+        let payload = payload(&serde_json::json!({
+            "id": "wamid.in1",
+            "from": "447700900123",
+            "timestamp": Utc::now().timestamp().to_string(),
+            "type": "text",
+            "text": { "body": "hello" },
+        }));
+
+        let sent = adapter.handle(&payload).await;
+        assert_eq!(sent, vec!["wamid.1".to_string()]);
+
+        let digest = Response::text("whatsapp:pn-1:447700900123", "Your daily digest");
+        let template = TemplateMessage::new("daily_digest", "en");
+        adapter
+            .send_proactive("pn-1", "447700900123", &digest, Some(&template))
+            .await
+            .unwrap();
+        adapter
+            .send_proactive("pn-1", "447700900999", &digest, Some(&template))
+            .await
+            .unwrap();
+        let err = adapter
+            .send_proactive("pn-1", "447700900999", &digest, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Validation(_))
+        ));
+
+        let sent = api.sent.lock().clone();
+        assert_eq!(
+            sent[1].1.content,
+            OutboundContent::Text("Your daily digest".to_string())
+        );
+        assert_eq!(sent[2].1.content, OutboundContent::Template(template));
+    }
+}
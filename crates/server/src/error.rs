@@ -0,0 +1,98 @@
+//! Mapping bot errors onto HTTP responses
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::{debug, error};
+use universal_bot_core::{error::ErrorResponse, Error};
+
+/// An error returned from an API handler
+///
+/// The first [`Error`] in the chain decides the status code and error code.
+/// Other errors become a 500 whose message is logged but not returned.
+#[derive(Debug)]
+pub struct ApiError {
+    error: anyhow::Error,
+    request_id: Option<String>,
+}
+
+impl ApiError {
+    /// Report `request_id` with the error, for correlating with logs
+    #[must_use]
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    /// The HTTP status and body this error is returned as
+    #[must_use]
+    pub fn to_error_response(&self) -> (StatusCode, ErrorResponse) {
+        let (status, response) = self
+            .error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<Error>())
+            .map_or_else(
+                || {
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        ErrorResponse::new("E999", "Internal server error"),
+                    )
+                },
+                |cause| {
+                    (
+                        StatusCode::from_u16(cause.http_status_code())
+                            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                        ErrorResponse::new(cause.error_code(), cause.to_string()),
+                    )
+                },
+            );
+        match &self.request_id {
+            Some(id) => (status, response.with_request_id(id.clone())),
+            None => (status, response),
+        }
+    }
+}
+
+impl<E: Into<anyhow::Error>> From<E> for ApiError {
+    fn from(error: E) -> Self {
+        Self {
+            error: error.into(),
+            request_id: None,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, body) = self.to_error_response();
+        if status.is_server_error() {
+            error!("Request failed with {}: {:#}", status, self.error);
+        } else {
+            debug!("Request rejected with {}: {:#}", status, self.error);
+        }
+        (status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bot_errors_keep_their_status() {
+        let error = ApiError::from(
+            anyhow::Error::from(Error::NotFound("Context c1".to_string())).context("export"),
+        )
+        .with_request_id("r1");
+        let (status, body) = error.to_error_response();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body.code, "E012");
+        assert_eq!(body.request_id.as_deref(), Some("r1"));
+
+        let (status, body) = ApiError::from(anyhow::anyhow!("disk on fire")).to_error_response();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body.message, "Internal server error");
+    }
+}
@@ -0,0 +1,155 @@
+//! HTTP server for Universal Bot
+//!
+//! This crate exposes a [`Bot`] as a REST API:
+//!
+//! - `POST /v1/messages` processes a message and returns the bot's response.
+//!   Both message routes reject metadata outside [`CLIENT_METADATA_KEYS`].
+//! - `POST /v1/messages/stream` processes a message and streams the reply as
+//!   server-sent events: `delta` events with text as it is generated, then a
+//!   `done` event with the response or an `error` event. Idle streams send a
+//!   heartbeat comment, and closing the connection cancels processing.
+//! - `GET /v1/conversations/{id}` returns a conversation's context
+//! - `GET /v1/conversations/{id}/usage` returns its tokens and cost per turn
+//! - `GET /healthz` reports liveness and whether the bot is degraded
+//! - `GET /metrics` serves Prometheus metrics
+//!
+//! With [`router_with_verifier`], message posts and conversation reads must
+//! be signed as described in [`inbound`](universal_bot_core::inbound), under
+//! the channel named by the [`CHANNEL_HEADER`]; unsigned or replayed requests
+//! are rejected with a 401. Reads have no body, so they are signed over the
+//! request path and query instead.
+//!
+//! Failures are returned as an [`ErrorResponse`](universal_bot_core::error::ErrorResponse)
+//! body with the status from
+//! [`Error::http_status_code`](universal_bot_core::Error::http_status_code).
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use universal_bot_core::{Bot, BotConfig};
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let bot = Bot::new(BotConfig::default()).await?;
+//! universal_bot_server::serve(bot, "0.0.0.0:8080".parse()?).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+#![deny(missing_docs, rust_2018_idioms, clippy::all)]
+#![warn(clippy::pedantic, clippy::nursery)]
+#![allow(
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::unnecessary_literal_bound
+)]
+
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::{Context as _, Result};
+use axum::{
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use tracing::info;
+use universal_bot_core::{inbound::InboundVerifier, metrics::MetricsExporter, Bot};
+
+pub use error::ApiError;
+pub use routes::{Health, MessageRequest, CLIENT_METADATA_KEYS};
+
+mod error;
+mod routes;
+
+/// Path messages are posted to
+pub const MESSAGES_PATH: &str = "/v1/messages";
+
+/// Path messages are posted to for a streamed reply
+pub const STREAM_PATH: &str = "/v1/messages/stream";
+
+/// Path conversations are read from, with the conversation ID appended
+pub const CONVERSATIONS_PATH: &str = "/v1/conversations";
+
+/// Path of the health check
+pub const HEALTH_PATH: &str = "/healthz";
+
+/// Header naming the channel a signed message post comes from
+pub const CHANNEL_HEADER: &str = "X-UniversalBot-Channel";
+
+/// The API routes for `bot`, with metrics registered on a new exporter
+///
+/// # Errors
+///
+/// Returns an error if the bot's metrics cannot be registered.
+pub fn router(bot: Bot) -> Result<Router> {
+    let exporter = MetricsExporter::new();
+    exporter.register_bot(&bot)?;
+    Ok(router_with_metrics(bot, &exporter))
+}
+
+/// The API routes for `bot`, serving metrics from `exporter`
+///
+/// Nothing is registered on `exporter`; use this to export further
+/// collectors, such as a Bedrock client's metrics, next to the bot's.
+pub fn router_with_metrics(bot: Bot, exporter: &MetricsExporter) -> Router {
+    routes(bot, exporter, None)
+}
+
+/// The API routes for `bot`, accepting only message posts and conversation
+/// reads that pass `verifier`
+///
+/// Health and metrics are not signed.
+pub fn router_with_verifier(
+    bot: Bot,
+    exporter: &MetricsExporter,
+    verifier: Arc<InboundVerifier>,
+) -> Router {
+    routes(bot, exporter, Some(verifier))
+}
+
+fn routes(bot: Bot, exporter: &MetricsExporter, verifier: Option<Arc<InboundVerifier>>) -> Router {
+    let mut guarded = Router::new()
+        .route(MESSAGES_PATH, post(routes::post_message))
+        .route(STREAM_PATH, post(routes::stream_message))
+        .route(
+            &format!("{CONVERSATIONS_PATH}/:id"),
+            get(routes::get_conversation),
+        )
+        .route(
+            &format!("{CONVERSATIONS_PATH}/:id/usage"),
+            get(routes::get_usage),
+        );
+    if let Some(verifier) = verifier {
+        guarded = guarded.route_layer(middleware::from_fn_with_state(
+            verifier,
+            routes::verify_inbound,
+        ));
+    }
+    guarded
+        .route(HEALTH_PATH, get(routes::health))
+        .with_state(bot)
+        .merge(exporter.router())
+}
+
+/// Serve `bot` on `addr` until Ctrl+C, then finish in-flight requests
+///
+/// # Errors
+///
+/// Returns an error if metrics cannot be registered, `addr` cannot be
+/// bound, or the server fails.
+pub async fn serve(bot: Bot, addr: SocketAddr) -> Result<()> {
+    let app = router(bot)?;
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind {addr}"))?;
+    info!("Serving on {}", listener.local_addr()?);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            // If the signal handler cannot be installed, run until killed
+            if tokio::signal::ctrl_c().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+            info!("Shutting down");
+        })
+        .await
+        .context("Server failed")
+}